@@ -0,0 +1,52 @@
+//! The daily challenge panel in the lobby, for starting today's challenge and seeing how everyone did on it.
+//! The server keeps the leaderboard, and gets asked for it whenever the panel opens.
+
+use common::{daily::{DailyResult, Day}, message::Request};
+use format_xml::xml;
+use web_sys::{Element, Event, WebSocket};
+
+use crate::{add_event_listener, document, notes::PlayerNotes, processor::send_request, render};
+
+fn panel() -> Element {
+    document().get_element_by_id("daily_panel").expect("Missing daily challenge panel")
+}
+
+/// Asks the server for the leaderboard every time the panel opens, since it changes whenever someone finishes,
+/// and starts the challenge when the button gets clicked
+pub fn init(ws: &WebSocket) {
+    let cws = ws.clone();
+    add_event_listener(&panel(), "toggle", move |_: Event| {
+        if panel().has_attribute("open") {
+            send_request(&Request::GetDaily, &cws);
+        }
+    });
+    let cws = ws.clone();
+    add_event_listener(&document().get_element_by_id("play_daily").unwrap(), "click", move |_: Event| {
+        send_request(&Request::PlayDaily, &cws);
+    });
+}
+
+fn render_result(rank: usize, result: &DailyResult, notes: &PlayerNotes) -> String {
+    let outcome = if result.won() { "Won" } else { "Lost" };
+    xml! {
+        <li class="history-entry">
+            <div>{rank}". "{render::render_username(result.username(), notes)}</div>
+            <div class="history-details">{outcome}", "{result.distance()}" tiles travelled"</div>
+        </li>
+    }.to_string()
+}
+
+/// Shows the leaderboard the server sent, best first
+pub fn show(day: Day, results: &[DailyResult]) {
+    document().get_element_by_id("daily_day").expect("Missing daily challenge date")
+        .set_text_content(Some(&day.to_string()));
+    let list = document().get_element_by_id("daily_list").expect("Missing daily challenge leaderboard");
+    if results.is_empty() {
+        list.set_inner_html(&xml!(<li class="history-entry">"Nobody finished today's challenge yet"</li>).to_string());
+    } else {
+        let notes = PlayerNotes::load();
+        list.set_inner_html(&results.iter().enumerate()
+            .map(|(index, result)| render_result(index + 1, result, &notes))
+            .collect::<String>());
+    }
+}
@@ -4,12 +4,14 @@ use std::collections::{HashSet};
 use std::rc::Rc;
 
 use std::{cell::Cell};
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 
+use common::CONNECTION_TIMEOUT;
 use common::game::GameId;
-use common::{GameInstance};
+use common::{LobbyGame};
 
-use common::math::{Pt2, pt2};
+use common::math::{Pt2, Vec2, pt2};
+use common::nalgebra::vector;
 
 use common::{board::{BasePort}};
 use common::board::{BaseTLoc};
@@ -18,24 +20,29 @@ use getset::{CopyGetters, Getters, MutGetters};
 use itertools::{Itertools};
 use specs::prelude::*;
 use wasm_bindgen::{JsCast, prelude::Closure};
-use web_sys::{Element, KeyboardEvent, MouseEvent, SvgGraphicsElement};
+use web_sys::{Element, HtmlElement, KeyboardEvent, MouseEvent, MouseEventInit, SvgGraphicsElement, TouchEvent, WheelEvent};
 
 
-use crate::render::{BaseTileExt, SvgMatrixExt, self};
+use crate::render::{BaseTileExt, Rect, SvgMatrixExt, self};
 use crate::{document};
 
-/// Labels a game in the lobby with a GameInstance
+/// Labels a game in the lobby with how the lobby shows it
 #[derive(Clone, Debug)]
-pub struct GameInstanceLabel(pub GameInstance);
+pub struct LobbyGameLabel(pub LobbyGame);
 
-impl Component for GameInstanceLabel {
+impl Component for LobbyGameLabel {
     type Storage = DenseVecStorage<Self>;
 }
 
-/// Transformation component. Sets transform of other objects
+/// Transformation component. Sets transform of other objects.
+/// Scale gets applied first, then rotation, then translation.
 #[derive(Clone, Debug)]
 pub struct Transform {
     pub position: Pt2,
+    /// Clockwise rotation in degrees around the object's origin
+    pub rotation: f64,
+    /// Uniform scale around the object's origin
+    pub scale: f64,
 }
 
 impl Component for Transform {
@@ -43,8 +50,22 @@ impl Component for Transform {
 }
 
 impl Transform {
+    /// A transform that only moves the object
     pub fn new(position: Pt2) -> Self {
-        Self { position }
+        Self { position, rotation: 0.0, scale: 1.0 }
+    }
+
+    pub fn with_rotation(self, rotation: f64) -> Self {
+        Self { rotation, ..self }
+    }
+
+    pub fn with_scale(self, scale: f64) -> Self {
+        Self { scale, ..self }
+    }
+
+    /// The value of an SVG `transform` attribute that applies this transform
+    pub fn svg_transform(&self) -> String {
+        format!("translate({}, {}) rotate({}) scale({})", self.position.x, self.position.y, self.rotation, self.scale)
     }
 }
 
@@ -78,7 +99,7 @@ impl<'a> System<'a> for TransformSystem {
 
         for (transform, model, _) in (&transforms, &models, &self.changed).join() {
             let svg = document().get_element_by_id(&model.id).unwrap();
-            svg.set_attribute("transform", &format!("translate({}, {})", transform.position.x, transform.position.y))
+            svg.set_attribute("transform", &transform.svg_transform())
                 .expect("Cannot change transform");
         }
     }
@@ -113,17 +134,23 @@ impl Component for TileLabel {
 #[derive(Clone, Debug, Getters, MutGetters, CopyGetters)]
 pub struct TileSelect {
     /// Whether this entity is a selected tile
+    #[getset(get_copy = "pub")]
     selected: bool,
     #[getset(get = "pub")]
     kind: BaseKind,
     #[getset(get_copy = "pub", get_mut = "pub")]
     index: u32,
+    #[getset(get = "pub")]
     action: BaseGAct,
+    /// The group action the tile is currently rendered with
+    rendered_action: BaseGAct,
+    /// Whether the selection style needs to be updated
+    selection_changed: bool,
 }
 
 impl TileSelect {
     pub fn new(kind: BaseKind, index: u32, action: BaseGAct) -> Self {
-        Self { selected: false, kind, index, action }
+        Self { selected: false, kind, index, rendered_action: action.clone(), action, selection_changed: false }
     }
 }
 
@@ -131,6 +158,48 @@ impl Component for TileSelect {
     type Storage = DenseVecStorage<Self>;
 }
 
+/// What a highlight tells the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HighlightKind {
+    /// The held tile can be placed here
+    Legal,
+    /// The held tile can be placed here, but it would kill the user
+    Lethal,
+    /// Where the last hint suggested placing the held tile
+    Hint,
+}
+
+impl HighlightKind {
+    fn class(self) -> &'static str {
+        match self {
+            Self::Legal => "highlight-legal",
+            Self::Lethal => "highlight-lethal",
+            Self::Hint => "highlight-hint",
+        }
+    }
+}
+
+/// Colors a model to tell the user something about it.
+/// Rendering is left to `HighlightRenderSystem`.
+#[derive(Clone, Copy, Debug, Default, CopyGetters)]
+pub struct Highlight {
+    /// None if the model isn't highlighted
+    #[getset(get_copy = "pub")]
+    kind: Option<HighlightKind>,
+    /// The highlight the model is currently rendered with
+    rendered_kind: Option<HighlightKind>,
+}
+
+impl Highlight {
+    pub fn set_kind(&mut self, kind: Option<HighlightKind>) {
+        self.kind = kind;
+    }
+}
+
+impl Component for Highlight {
+    type Storage = DenseVecStorage<Self>;
+}
+
 /// Rendering component
 #[derive(Debug)]
 pub struct Model {
@@ -147,8 +216,10 @@ impl Component for Model {
 impl Model {
     pub const ORDER_BOARD: i32 = 0;
     pub const ORDER_TILE: i32 = 1;
-    pub const ORDER_PLAYER_TOKEN: i32 = 2;
-    pub const ORDER_TILE_HOVER: i32 = 3;
+    pub const ORDER_TRAIL: i32 = 2;
+    pub const ORDER_PLAYER_TOKEN: i32 = 3;
+    pub const ORDER_TILE_HOVER: i32 = 4;
+    pub const ORDER_TOKEN_PREVIEW: i32 = 5;
 
     /// Adds an element to a parent node, taking a counter that is used for the id and increments.
     /// Also takes a rendering order.
@@ -159,6 +230,11 @@ impl Model {
         parent.append_child(elem).expect("Failed to add element");
         Model { id: elem.id(), order, order_changed: true }
     }
+
+    /// The corresponding element, if it's still in the document
+    pub fn element(&self) -> Option<Element> {
+        document().get_element_by_id(&self.id)
+    }
 }
 
 impl Drop for Model {
@@ -170,42 +246,506 @@ impl Drop for Model {
     }
 }
 
-/// Mouse input tracker for the SVG region where the board shows
+/// How far the mouse wheel has to scroll to rotate the held tile once, in pixels.
+/// Wheels that scroll by lines or pages rotate it once per event instead.
+pub const WHEEL_STEP: f64 = 100.0;
+
+/// How far the mouse has to move with the button held to pan the board instead of clicking, in pixels
+pub const DRAG_THRESHOLD: f64 = 5.0;
+
+/// A mouse button being held down over the board
+#[derive(Clone, Copy, Debug, Default)]
+struct Drag {
+    /// Where the button went down, in client space. None if it isn't down.
+    start: Option<Pt2>,
+    /// Whether the mouse moved far enough to pan instead of click
+    dragging: bool,
+    /// How far the mouse moved while dragging that hasn't been applied, in client space
+    delta: Vec2,
+}
+
+/// How long a finger has to stay still on the board to rotate the held tile, in milliseconds
+pub const TOUCH_HOLD_TIME: f64 = 500.0;
+
+/// Fingers on the board.
+/// One finger positions the held tile, and holding it still or adding a second finger rotates the tile.
+#[derive(Clone, Copy, Debug, Default)]
+struct Touch {
+    /// When the first finger went down, in milliseconds. None if no finger is down.
+    start_time: Option<f64>,
+    /// Where the first finger went down, in client space
+    start: Pt2,
+    /// Whether the first finger moved far enough to position the held tile instead of tapping
+    moved: bool,
+    /// Whether the touch rotated the held tile
+    rotated: bool,
+}
+
+impl Touch {
+    /// Fingers went down, making `fingers` in total. Returns whether to rotate the held tile.
+    fn start(&mut self, position: Pt2, fingers: u32, now: f64) -> bool {
+        if self.start_time.is_none() {
+            *self = Self { start_time: Some(now), start: position, ..Self::default() };
+        }
+        // Once per touch, however many fingers get added
+        let rotate = fingers >= 2 && !self.rotated;
+        self.rotated |= rotate;
+        rotate
+    }
+
+    /// The first finger moved to `position`
+    fn move_to(&mut self, position: Pt2) {
+        self.moved |= (position - self.start).norm() > DRAG_THRESHOLD;
+    }
+
+    /// Returns whether to rotate the held tile because the first finger stayed still long enough
+    fn check_hold(&mut self, now: f64) -> bool {
+        let held = self.start_time.map_or(false, |start| now - start >= TOUCH_HOLD_TIME);
+        let rotate = held && !self.moved && !self.rotated;
+        self.rotated |= rotate;
+        rotate
+    }
+
+    /// Fingers went up, leaving `fingers`. Returns whether the touch was a tap.
+    fn end(&mut self, fingers: u32) -> bool {
+        if fingers == 0 {
+            self.start_time = None;
+        }
+        !self.moved && !self.rotated
+    }
+}
+
+/// Dispatches a bubbling mouse event named `name` to an element
+fn dispatch_mouse_event(elem: &Element, name: &str) {
+    let mut init = MouseEventInit::new();
+    init.bubbles(true);
+    let event = MouseEvent::new_with_mouse_event_init_dict(name, &init).expect("Cannot create mouse event");
+    elem.dispatch_event(&event).expect("Cannot dispatch mouse event");
+}
+
+/// Converts a position from client space to the space of `elem`.
+/// Goes through the element's current transform, so it respects the camera.
+/// No element means the input is simulated, so the position is already in board space.
+fn client_to_board(elem: Option<&SvgGraphicsElement>, position: Pt2) -> Pt2 {
+    match elem {
+        Some(elem) => elem.get_screen_ctm()
+            .expect("Missing SVG matrix")
+            .inverse().expect("Cannot inverse SVG matrix")
+            .transform(position),
+        None => position,
+    }
+}
+
+/// The token or tile following the mouse.
+/// Its element gets moved as soon as the mouse moves instead of once the systems run,
+/// so dragging feels immediate even at low frame rates. Only the element moves early;
+/// the systems still decide where the token or tile is.
+#[derive(Clone, Debug)]
+struct Follower {
+    /// Id of the element
+    id: String,
+    /// The transform the systems last gave it. Only the position follows the mouse.
+    transform: Transform,
+    /// Whether it's snapped to a slot, where it stays instead of following the mouse
+    snapped: bool,
+}
+
+impl Follower {
+    /// Moves the element to the mouse, at `position` in client space
+    fn follow(&self, elem: Option<&SvgGraphicsElement>, position: Pt2) {
+        if self.snapped {
+            return;
+        }
+        let transform = Transform { position: client_to_board(elem, position), ..self.transform.clone() };
+        // It might have been placed and deleted since
+        if let Some(svg) = document().get_element_by_id(&self.id) {
+            svg.set_attribute("transform", &transform.svg_transform()).expect("Cannot change transform");
+        }
+    }
+}
+
+/// Mouse and touch input tracker for the SVG region where the board shows
 #[derive(Debug)]
 pub struct BoardInput {
     /// Position of the mouse, in board space
     position: Pt2,
+    /// Position of the mouse, in client space. A finger on the board counts as the mouse.
     position_raw: Rc<Cell<Pt2>>,
-    callback: Closure<dyn FnMut(MouseEvent)>,
+    touch_raw: Rc<Cell<Touch>>,
+    /// Element under the first finger, which counts as moused over
+    touch_over: Rc<RefCell<Option<Element>>>,
+    /// How far the board got dragged this frame, in board space
+    drag: Vec2,
+    drag_raw: Rc<Cell<Drag>>,
+    /// Number of steps the wheel scrolled this frame, positive for down
+    wheel_steps: i32,
+    /// How far the wheel scrolled that hasn't made a step yet, in pixels
+    wheel_raw: Rc<Cell<f64>>,
+    /// Number of steps the wheel scrolled this frame while Ctrl was held, positive for down
+    zoom_steps: f64,
+    /// How far the wheel scrolled while Ctrl was held that hasn't been applied, in steps
+    zoom_raw: Rc<Cell<f64>>,
+    /// None if nothing is following the mouse
+    follower: Rc<RefCell<Option<Follower>>>,
+    /// Element whose coordinates are board space. None if the input is simulated instead,
+    /// in which case client space is board space.
+    elem: Option<SvgGraphicsElement>,
+    /// Mouse listeners. Empty if the input is simulated instead.
+    listeners: Vec<Closure<dyn FnMut(MouseEvent)>>,
+    /// None if the input is simulated instead
+    wheel_callback: Option<Closure<dyn FnMut(WheelEvent)>>,
+    /// Touch listeners. Empty if the input is simulated instead.
+    touch_listeners: Vec<Closure<dyn FnMut(TouchEvent)>>,
 }
 
 impl BoardInput {
+    /// Constructs a `BoardInput` that doesn't listen to any element.
+    /// Its input has to be simulated.
+    pub fn detached() -> Self {
+        Self {
+            position: Pt2::origin(),
+            position_raw: Rc::new(Cell::new(Pt2::origin())),
+            touch_raw: Rc::new(Cell::new(Touch::default())),
+            touch_over: Rc::new(RefCell::new(None)),
+            drag: Vec2::zeros(),
+            drag_raw: Rc::new(Cell::new(Drag::default())),
+            wheel_steps: 0,
+            wheel_raw: Rc::new(Cell::new(0.0)),
+            zoom_steps: 0.0,
+            zoom_raw: Rc::new(Cell::new(0.0)),
+            follower: Rc::new(RefCell::new(None)),
+            elem: None,
+            listeners: vec![],
+            wheel_callback: None,
+            touch_listeners: vec![],
+        }
+    }
+
     /// Constructs a `BoardInput` that gets mouse events from a specific SVG graphics element
     pub fn new(elem: &SvgGraphicsElement) -> Self {
-        let position_raw = Rc::new(Cell::new(Pt2::origin()));
-        let position_clone = Rc::clone(&position_raw);
-        
+        let mut input = Self::detached();
+        input.elem = Some(elem.clone());
+
+        let position_clone = Rc::clone(&input.position_raw);
+        let drag_clone = Rc::clone(&input.drag_raw);
+        let follower_clone = Rc::clone(&input.follower);
         let elem_clone = elem.clone();
         let mousemove_listener = Closure::wrap(Box::new(move |e: MouseEvent| {
-            let position = elem_clone.get_screen_ctm()
-                .expect("Missing SVG matrix")
-                .inverse().expect("Cannot inverse SVG matrix")
-                .transform(pt2(e.x() as f64, e.y() as f64));
+            let position = pt2(e.x() as f64, e.y() as f64);
+            let mut drag = drag_clone.get();
+            if let Some(start) = drag.start {
+                if drag.dragging {
+                    drag.delta += position - position_clone.get();
+                } else if (position - start).norm() > DRAG_THRESHOLD {
+                    drag.dragging = true;
+                    drag.delta += position - start;
+                }
+                drag_clone.set(drag);
+            }
             position_clone.set(position);
+            if let Some(follower) = follower_clone.borrow().as_ref() {
+                follower.follow(Some(&elem_clone), position);
+            }
         }) as Box<dyn FnMut(MouseEvent)>);
-        elem.add_event_listener_with_callback("mousemove", mousemove_listener.as_ref().unchecked_ref())
+
+        let drag_clone = Rc::clone(&input.drag_raw);
+        let mousedown_listener = Closure::wrap(Box::new(move |e: MouseEvent| {
+            if e.button() == 0 {
+                drag_clone.set(Drag { start: Some(pt2(e.x() as f64, e.y() as f64)), dragging: false, ..drag_clone.get() });
+            }
+        }) as Box<dyn FnMut(MouseEvent)>);
+
+        // Releasing the button outside the element still has to end the drag
+        let drag_clone = Rc::clone(&input.drag_raw);
+        let mouseup_listener = Closure::wrap(Box::new(move |_e: MouseEvent| {
+            drag_clone.set(Drag { start: None, ..drag_clone.get() });
+        }) as Box<dyn FnMut(MouseEvent)>);
+
+        // Letting go after a drag isn't a click on whatever is under the mouse.
+        // This runs in the capture phase, before the colliders inside see the click.
+        let drag_clone = Rc::clone(&input.drag_raw);
+        let click_listener = Closure::wrap(Box::new(move |e: MouseEvent| {
+            if drag_clone.get().dragging {
+                e.stop_propagation();
+            }
+        }) as Box<dyn FnMut(MouseEvent)>);
+
+        for (event, listener) in [("mousemove", &mousemove_listener), ("mousedown", &mousedown_listener),
+            ("mouseleave", &mouseup_listener)]
+        {
+            elem.add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())
+                .expect("Failed to add input callback");
+        }
+        document().add_event_listener_with_callback("mouseup", mouseup_listener.as_ref().unchecked_ref())
+            .expect("Failed to add input callback");
+        elem.add_event_listener_with_callback_and_bool("click", click_listener.as_ref().unchecked_ref(), true)
             .expect("Failed to add input callback");
 
-        Self {
-            position: Pt2::origin(),
-            position_raw,
-            callback: mousemove_listener,
+        let wheel_clone = Rc::clone(&input.wheel_raw);
+        let zoom_clone = Rc::clone(&input.zoom_raw);
+        let wheel_listener = Closure::wrap(Box::new(move |e: WheelEvent| {
+            // The wheel rotates the held tile, or zooms with Ctrl held, instead of scrolling or zooming the page
+            e.prevent_default();
+            let delta = if e.delta_mode() == WheelEvent::DOM_DELTA_PIXEL {
+                e.delta_y()
+            } else {
+                e.delta_y().signum() * WHEEL_STEP
+            };
+            if e.ctrl_key() {
+                zoom_clone.set(zoom_clone.get() + delta / WHEEL_STEP);
+            } else {
+                wheel_clone.set(wheel_clone.get() + delta);
+            }
+        }) as Box<dyn FnMut(WheelEvent)>);
+        elem.add_event_listener_with_callback("wheel", wheel_listener.as_ref().unchecked_ref())
+            .expect("Failed to add input callback");
+
+        let position_clone = Rc::clone(&input.position_raw);
+        let touch_clone = Rc::clone(&input.touch_raw);
+        let over_clone = Rc::clone(&input.touch_over);
+        let wheel_clone = Rc::clone(&input.wheel_raw);
+        let touchstart_listener = Closure::wrap(Box::new(move |e: TouchEvent| {
+            if let Some(finger) = e.touches().get(0) {
+                let position = pt2(finger.client_x() as f64, finger.client_y() as f64);
+                position_clone.set(position);
+                let mut touch = touch_clone.get();
+                if touch.start(position, e.touches().length(), js_sys::Date::now()) {
+                    wheel_clone.set(wheel_clone.get() + WHEEL_STEP);
+                }
+                touch_clone.set(touch);
+                *over_clone.borrow_mut() = document().element_from_point(position.x as f32, position.y as f32);
+            }
+        }) as Box<dyn FnMut(TouchEvent)>);
+
+        let position_clone = Rc::clone(&input.position_raw);
+        let touch_clone = Rc::clone(&input.touch_raw);
+        let over_clone = Rc::clone(&input.touch_over);
+        let follower_clone = Rc::clone(&input.follower);
+        let elem_clone = elem.clone();
+        let touchmove_listener = Closure::wrap(Box::new(move |e: TouchEvent| {
+            // Moving a finger positions the held tile instead of scrolling the page
+            e.prevent_default();
+            if let Some(finger) = e.touches().get(0) {
+                let position = pt2(finger.client_x() as f64, finger.client_y() as f64);
+                position_clone.set(position);
+                let mut touch = touch_clone.get();
+                touch.move_to(position);
+                touch_clone.set(touch);
+                if let Some(follower) = follower_clone.borrow().as_ref() {
+                    follower.follow(Some(&elem_clone), position);
+                }
+
+                // Touch events stay with the element the finger went down on,
+                // so colliders find out what's under the finger by getting moused over
+                let over = document().element_from_point(position.x as f32, position.y as f32);
+                let mut over_prev = over_clone.borrow_mut();
+                if *over_prev != over {
+                    if let Some(prev) = over_prev.as_ref() {
+                        dispatch_mouse_event(prev, "mouseout");
+                    }
+                    if let Some(over) = &over {
+                        dispatch_mouse_event(over, "mouseover");
+                    }
+                    *over_prev = over;
+                }
+            }
+        }) as Box<dyn FnMut(TouchEvent)>);
+
+        let touch_clone = Rc::clone(&input.touch_raw);
+        let over_clone = Rc::clone(&input.touch_over);
+        let touchend_listener = Closure::wrap(Box::new(move |e: TouchEvent| {
+            let mut touch = touch_clone.get();
+            // Only taps click what's under the finger
+            if !touch.end(e.touches().length()) {
+                e.prevent_default();
+            }
+            touch_clone.set(touch);
+            if e.touches().length() == 0 {
+                over_clone.borrow_mut().take();
+            }
+        }) as Box<dyn FnMut(TouchEvent)>);
+
+        for (event, listener) in [("touchstart", &touchstart_listener), ("touchmove", &touchmove_listener),
+            ("touchend", &touchend_listener), ("touchcancel", &touchend_listener)]
+        {
+            elem.add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())
+                .expect("Failed to add input callback");
+        }
+
+        input.listeners = vec![mousemove_listener, mousedown_listener, mouseup_listener, click_listener];
+        input.wheel_callback = Some(wheel_listener);
+        input.touch_listeners = vec![touchstart_listener, touchmove_listener, touchend_listener];
+        input
+    }
+
+    /// Simulates the mouse moving to a position in board space
+    pub fn simulate_move(&self, position: Pt2) {
+        self.position_raw.set(position);
+        if let Some(follower) = self.follower.borrow().as_ref() {
+            follower.follow(None, position);
         }
     }
 
+    /// Simulates the wheel scrolling by some pixels, positive for down
+    pub fn simulate_wheel(&self, delta: f64) {
+        self.wheel_raw.set(self.wheel_raw.get() + delta);
+    }
+
+    /// Simulates dragging the board by some distance in board space
+    pub fn simulate_drag(&self, delta: Vec2) {
+        let drag = self.drag_raw.get();
+        self.drag_raw.set(Drag { delta: drag.delta + delta, ..drag });
+    }
+
+    /// Simulates fingers going down at a position in board space at time `now` in milliseconds,
+    /// making `fingers` in total
+    pub fn simulate_touch_start(&self, position: Pt2, fingers: u32, now: f64) {
+        self.position_raw.set(position);
+        let mut touch = self.touch_raw.get();
+        if touch.start(position, fingers, now) {
+            self.wheel_raw.set(self.wheel_raw.get() + WHEEL_STEP);
+        }
+        self.touch_raw.set(touch);
+    }
+
+    /// Simulates the first finger moving to a position in board space
+    pub fn simulate_touch_move(&self, position: Pt2) {
+        self.position_raw.set(position);
+        let mut touch = self.touch_raw.get();
+        touch.move_to(position);
+        self.touch_raw.set(touch);
+    }
+
+    /// Simulates fingers going up, leaving `fingers`. Returns whether the touch was a tap.
+    pub fn simulate_touch_end(&self, fingers: u32) -> bool {
+        let mut touch = self.touch_raw.get();
+        let tap = touch.end(fingers);
+        self.touch_raw.set(touch);
+        tap
+    }
+
+    /// Simulates the wheel scrolling by some steps with Ctrl held, positive for down
+    pub fn simulate_zoom(&self, steps: f64) {
+        self.zoom_raw.set(self.zoom_raw.get() + steps);
+    }
+
+    /// Converts a position from client space to board space.
+    /// Goes through the element's current transform, so it respects the camera.
+    fn to_board(&self, position: Pt2) -> Pt2 {
+        client_to_board(self.elem.as_ref(), position)
+    }
+
+    /// Has the element of `model` follow the mouse between frames, with the rest of `transform` kept.
+    /// Snapped elements stay put until the next frame.
+    fn set_follower(&self, model: &Model, transform: &Transform, snapped: bool) {
+        *self.follower.borrow_mut() = Some(Follower { id: model.id.clone(), transform: transform.clone(), snapped });
+    }
+
     fn position(&self) -> Pt2 {
         self.position
     }
+
+    /// Number of steps the wheel scrolled this frame, positive for down
+    pub fn wheel_steps(&self) -> i32 {
+        self.wheel_steps
+    }
+
+    /// Number of steps the wheel scrolled this frame while Ctrl was held, positive for down
+    pub fn zoom_steps(&self) -> f64 {
+        self.zoom_steps
+    }
+
+    /// How far the board got dragged this frame, in board space
+    pub fn drag(&self) -> Vec2 {
+        self.drag
+    }
+}
+
+/// The part of the board in view, which can be zoomed and panned.
+/// Never shows anything outside its home, the view when zoomed all the way out.
+#[derive(Clone, Debug)]
+pub struct Camera {
+    home: Rect,
+    /// Center of the view, in board space
+    center: Pt2,
+    /// How many times bigger things look than at home
+    zoom: f64,
+    /// Whether the view changed since it was last shown
+    changed: bool,
+}
+
+impl Camera {
+    /// Most the view can be zoomed in
+    pub const MAX_ZOOM: f64 = 8.0;
+    /// How much one wheel step zooms in or out
+    pub const ZOOM_PER_STEP: f64 = 1.25;
+
+    /// Constructs a camera zoomed all the way out
+    pub fn new(home: Rect) -> Self {
+        let mut camera = Self { home, center: Pt2::origin(), zoom: 1.0, changed: true };
+        camera.zoom_out();
+        camera
+    }
+
+    /// Changes the home and zooms all the way out to it
+    pub fn set_home(&mut self, home: Rect) {
+        self.home = home;
+        self.zoom_out();
+    }
+
+    /// Zooms all the way out, showing all of home
+    pub fn zoom_out(&mut self) {
+        self.center = pt2(
+            self.home.left() as f64 + self.home.width() as f64 / 2.0,
+            self.home.top() as f64 + self.home.height() as f64 / 2.0,
+        );
+        self.zoom = 1.0;
+        self.changed = true;
+    }
+
+    /// The part of the board in view
+    pub fn view(&self) -> Rect {
+        let size = self.view_size();
+        Rect::from_ltwh(
+            (self.center.x - size.x / 2.0) as f32,
+            (self.center.y - size.y / 2.0) as f32,
+            size.x as f32,
+            size.y as f32,
+        )
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    /// Zooms in by `factor`, or out if it's less than 1, keeping `point` in the same place on screen
+    pub fn zoom_at(&mut self, point: Pt2, factor: f64) {
+        let zoom = (self.zoom * factor).clamp(1.0, Self::MAX_ZOOM);
+        self.center = point + (self.center - point) * (self.zoom / zoom);
+        self.zoom = zoom;
+        self.keep_in_home();
+    }
+
+    /// Drags the board by `delta` in board space, so the view moves the other way
+    pub fn pan(&mut self, delta: Vec2) {
+        self.center -= delta;
+        self.keep_in_home();
+    }
+
+    fn view_size(&self) -> Vec2 {
+        vector![self.home.width() as f64, self.home.height() as f64] / self.zoom
+    }
+
+    /// Moves the view back inside home if it went outside
+    fn keep_in_home(&mut self) {
+        let half = self.view_size() / 2.0;
+        let min = pt2(self.home.left() as f64, self.home.top() as f64) + half;
+        let max = pt2(self.home.left() as f64, self.home.top() as f64)
+            + vector![self.home.width() as f64, self.home.height() as f64] - half;
+        // Not clamp, which panics if rounding puts min just past max at zoom 1
+        self.center = pt2(self.center.x.max(min.x).min(max.x), self.center.y.max(min.y).min(max.y));
+        self.changed = true;
+    }
 }
 
 /// Keyboard input for the game
@@ -214,14 +754,26 @@ pub struct KeyboardInput {
     keys_down_raw: Rc<RefCell<HashSet<String>>>,
     keys_down: HashSet<String>,
     keys_pressed: HashSet<String>,
-    keydown_listener: Closure<dyn FnMut(KeyboardEvent)>,
-    keyup_listener: Closure<dyn FnMut(KeyboardEvent)>,
+    /// Keydown and keyup listeners. Empty if the input is simulated instead.
+    listeners: Vec<Closure<dyn FnMut(KeyboardEvent)>>,
 }
 
 impl KeyboardInput {
+    /// Constructs a `KeyboardInput` that doesn't listen to any element.
+    /// Its input has to be simulated.
+    pub fn detached() -> Self {
+        Self {
+            keys_down_raw: Rc::new(RefCell::new(HashSet::new())),
+            keys_down: HashSet::new(),
+            keys_pressed: HashSet::new(),
+            listeners: vec![],
+        }
+    }
+
     /// Constructs a `KeyboardInput` that gets keyboard events from a specific element.
     pub fn new(elem: &Element) -> Self {
-        let keys_down_raw = Rc::new(RefCell::new(HashSet::new()));
+        let mut input = Self::detached();
+        let keys_down_raw = Rc::clone(&input.keys_down_raw);
         let keys_clone = Rc::clone(&keys_down_raw);
 
         let keydown_listener = Closure::wrap(Box::new(move |e: KeyboardEvent| {
@@ -237,19 +789,29 @@ impl KeyboardInput {
         elem.add_event_listener_with_callback("keyup", keyup_listener.as_ref().unchecked_ref())
             .expect("Failed to add input callback");
 
-        Self {
-            keys_down_raw,
-            keys_down: HashSet::new(),
-            keys_pressed: HashSet::new(),
-            keydown_listener,
-            keyup_listener
-        }
+        input.listeners = vec![keydown_listener, keyup_listener];
+        input
+    }
+
+    /// Simulates a key going down, by key code
+    pub fn simulate_key_down(&self, code: &str) {
+        self.keys_down_raw.borrow_mut().insert(code.to_owned());
+    }
+
+    /// Simulates a key going up, by key code
+    pub fn simulate_key_up(&self, code: &str) {
+        self.keys_down_raw.borrow_mut().remove(code);
     }
 
     /// Whether a key is pressed
     pub fn pressed(&self, key: &str) -> bool {
         self.keys_pressed.contains(key)
     }
+
+    /// Whether a key is held down
+    pub fn down(&self, key: &str) -> bool {
+        self.keys_down.contains(key)
+    }
 }
 
 /// Updates keys_down and keys_pressed
@@ -278,14 +840,18 @@ impl Component for KeyLabel {
 /// Group action performed by a button press
 #[derive(Clone, Copy, Debug)]
 pub enum ButtonAction {
-    Rotation{ num_times: i32 }
+    Rotation{ num_times: i32 },
+    /// Flips the tile over, for tiles that can be flipped
+    Reflection,
 }
 
 impl ButtonAction {
-    /// Generate the corresponding group action
-    pub fn group_action(&self, tile: &BaseTile) -> BaseGAct {
+    /// Generate the corresponding group action.
+    /// None if it doesn't apply to the tile.
+    pub fn group_action(&self, tile: &BaseTile) -> Option<BaseGAct> {
         match self {
-            Self::Rotation{ num_times } => tile.rotation_action(*num_times)
+            Self::Rotation{ num_times } => Some(tile.rotation_action(*num_times)),
+            Self::Reflection => tile.reflection_action(),
         }
     }
 }
@@ -301,9 +867,10 @@ pub struct Collider {
     clicked: bool,
     hovered_raw: Rc<Cell<bool>>,
     clicked_raw: Rc<Cell<bool>>,
-    mouseover_listener: Closure<dyn FnMut(MouseEvent)>,
-    mouseout_listener: Closure<dyn FnMut(MouseEvent)>,
-    click_listener: Closure<dyn FnMut(MouseEvent)>,
+    /// Mouseover, mouseout, and click listeners. Empty if the input is simulated instead.
+    listeners: Vec<Closure<dyn FnMut(MouseEvent)>>,
+    /// Touchstart listener. Empty if the input is simulated instead.
+    touch_listeners: Vec<Closure<dyn FnMut(TouchEvent)>>,
 }
 
 impl Component for Collider {
@@ -314,10 +881,24 @@ impl Collider {
     pub const ORDER_START_PORT: i32 = -(i32::MIN / 2) + 1;
     pub const ORDER_TILE_LOC: i32 = -(i32::MIN / 2);
 
+    /// Constructs a collider that doesn't listen to any element.
+    /// Its input has to be simulated.
+    pub fn detached() -> Self {
+        Collider {
+            hovered: false,
+            clicked: false,
+            hovered_raw: Rc::new(Cell::new(false)),
+            clicked_raw: Rc::new(Cell::new(false)),
+            listeners: vec![],
+            touch_listeners: vec![],
+        }
+    }
+
     /// Constructs a collider.
     /// Takes an element to insert callbacks into
     pub fn new(elem: &Element) -> Self {
-        let hovered_raw = Rc::new(Cell::new(false));
+        let mut collider = Self::detached();
+        let hovered_raw = Rc::clone(&collider.hovered_raw);
         let hovered_clone = Rc::clone(&hovered_raw);
         let mouseover_listener = Closure::wrap(Box::new(move |_e: MouseEvent| {
             hovered_clone.set(true);
@@ -331,9 +912,16 @@ impl Collider {
             .expect("Failed to add collider callback");
         elem.add_event_listener_with_callback("mouseout", mouseout_listener.as_ref().unchecked_ref())
             .expect("Failed to add collider callback");
+        // A finger going down counts as mousing over right away, instead of once it goes up.
+        // Fingers moving onto or off of the element get turned into mouse events by BoardInput.
+        let hovered_clone = Rc::clone(&hovered_raw);
+        let touchstart_listener = Closure::wrap(Box::new(move |_e: TouchEvent| {
+            hovered_clone.set(true);
+        }) as Box<dyn FnMut(TouchEvent)>);
+        elem.add_event_listener_with_callback("touchstart", touchstart_listener.as_ref().unchecked_ref())
+            .expect("Failed to add collider callback");
 
-        let clicked_raw = Rc::new(Cell::new(false));
-        let clicked_clone = Rc::clone(&clicked_raw);
+        let clicked_clone = Rc::clone(&collider.clicked_raw);
         let click_listener = Closure::wrap(Box::new(move |_e: MouseEvent| {
             clicked_clone.set(true);
         }) as Box<dyn FnMut(MouseEvent)>);
@@ -341,15 +929,19 @@ impl Collider {
         elem.add_event_listener_with_callback("click", click_listener.as_ref().unchecked_ref())
             .expect("Failed to add collider callback");
 
-        Collider {
-            hovered: false,
-            clicked: false,
-            hovered_raw,
-            clicked_raw,
-            mouseover_listener,
-            mouseout_listener,
-            click_listener,
-        }
+        collider.listeners = vec![mouseover_listener, mouseout_listener, click_listener];
+        collider.touch_listeners = vec![touchstart_listener];
+        collider
+    }
+
+    /// Simulates the mouse moving onto or off of the collider
+    pub fn simulate_hover(&self, hovered: bool) {
+        self.hovered_raw.set(hovered);
+    }
+
+    /// Simulates a click on the collider
+    pub fn simulate_click(&self) {
+        self.clicked_raw.set(true);
     }
 
     /// Whether the collider is being hovered over
@@ -379,7 +971,51 @@ impl<'a> System<'a> for ColliderInputSystem {
         }
 
         let mut input = input.expect("Missing BoardInput");
-        input.position = input.position_raw.get();
+        let mut touch = input.touch_raw.get();
+        if touch.check_hold(js_sys::Date::now()) {
+            input.wheel_raw.set(input.wheel_raw.get() + WHEEL_STEP);
+        }
+        input.touch_raw.set(touch);
+        // Converted now instead of when the mouse moved, since the camera could have moved since
+        input.position = input.to_board(input.position_raw.get());
+        let drag = input.drag_raw.get();
+        input.drag = input.to_board(Pt2::from(drag.delta)) - input.to_board(Pt2::origin());
+        input.drag_raw.set(Drag { delta: Vec2::zeros(), ..drag });
+        input.zoom_steps = input.zoom_raw.get();
+        input.zoom_raw.set(0.0);
+        // Leftover scrolling carries over to the next frame
+        let steps = (input.wheel_raw.get() / WHEEL_STEP).trunc();
+        input.wheel_steps = steps as i32;
+        input.wheel_raw.set(input.wheel_raw.get() - steps * WHEEL_STEP);
+    }
+}
+
+/// Zooms the board view with Ctrl and the wheel, pans it by dragging, and shows the part in view
+pub struct CameraSystem;
+
+impl<'a> System<'a> for CameraSystem {
+    type SystemData = (Option<Read<'a, BoardInput>>, Option<Write<'a, Camera>>);
+
+    fn run(&mut self, (input, camera): Self::SystemData) {
+        let input = input.expect("Missing BoardInput");
+        let mut camera = camera.expect("Missing Camera");
+
+        if input.zoom_steps() != 0.0 {
+            // Scrolling down zooms out, like in map apps
+            camera.zoom_at(input.position(), Camera::ZOOM_PER_STEP.powf(-input.zoom_steps()));
+        }
+        if input.drag() != Vec2::zeros() {
+            camera.pan(input.drag());
+        }
+
+        if camera.changed {
+            camera.changed = false;
+            // Missing in tests
+            if let Some(svg_root) = document().get_element_by_id("svg_root") {
+                svg_root.set_attribute("viewBox", &camera.view().to_viewbox_value())
+                    .expect("Failed to set viewBox");
+            }
+        }
     }
 }
 
@@ -455,7 +1091,9 @@ pub struct PlaceTokenSystemData<'a> {
     colliders: ReadStorage<'a, Collider>,
     ports: ReadStorage<'a, PortLabel>,
     transforms: WriteStorage<'a, Transform>,
+    models: ReadStorage<'a, Model>,
     input: Option<Read<'a, BoardInput>>,
+    focus: Read<'a, KeyboardFocus>,
 }
 
 impl<'a> System<'a> for PlaceTokenSystem {
@@ -468,13 +1106,17 @@ impl<'a> System<'a> for PlaceTokenSystem {
             .flat_map(|(_, collider, transform)| {
                 collider.hovered().then(|| transform.position)
             })
-            .next();
-
-        for (_, transform) in (&data.tokens, &mut data.transforms).join() {
-            transform.position = if let Some(position) = position {
-                position
-            } else {
-                data.input.as_ref().expect("Missing BoardInput").position()
+            .next()
+            .or_else(|| data.focus.slot
+                .filter(|slot| data.token_slots.contains(*slot))
+                .and_then(|slot| data.transforms.get(slot))
+                .map(|transform| transform.position));
+
+        let input = data.input.as_ref().expect("Missing BoardInput");
+        for (_, transform, model) in (&data.tokens, &mut data.transforms, data.models.maybe()).join() {
+            transform.position = position.unwrap_or_else(|| input.position());
+            if let Some(model) = model {
+                input.set_follower(model, transform, position.is_some());
             }
         }
 
@@ -521,7 +1163,9 @@ pub struct PlaceTileSystemData<'a> {
     colliders: ReadStorage<'a, Collider>,
     locs: ReadStorage<'a, TLocLabel>,
     transforms: WriteStorage<'a, Transform>,
+    models: ReadStorage<'a, Model>,
     input: Option<Read<'a, BoardInput>>,
+    focus: Read<'a, KeyboardFocus>,
 }
 
 impl<'a> System<'a> for PlaceTileSystem {
@@ -534,13 +1178,17 @@ impl<'a> System<'a> for PlaceTileSystem {
             .flat_map(|(_, collider, transform)| {
                 collider.hovered().then(|| transform.position)
             })
-            .next();
-
-        for (_, transform) in (&data.tiles, &mut data.transforms).join() {
-            transform.position = if let Some(position) = position {
-                position
-            } else {
-                data.input.as_ref().expect("Missing BoardInput").position()
+            .next()
+            .or_else(|| data.focus.slot
+                .filter(|slot| data.tile_slots.contains(*slot))
+                .and_then(|slot| data.transforms.get(slot))
+                .map(|transform| transform.position));
+
+        let input = data.input.as_ref().expect("Missing BoardInput");
+        for (_, transform, model) in (&data.tiles, &mut data.transforms, data.models.maybe()).join() {
+            transform.position = position.unwrap_or_else(|| input.position());
+            if let Some(model) = model {
+                input.set_follower(model, transform, position.is_some());
             }
         }
 
@@ -553,9 +1201,117 @@ impl<'a> System<'a> for PlaceTileSystem {
     }
 }
 
+/// The slot chosen with the keyboard, for placing the token or a tile without a mouse
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KeyboardFocus {
+    pub slot: Option<Entity>,
+    /// Where the mouse was last frame, in board space
+    mouse: Pt2,
+}
+
+/// Keys that move the keyboard focus, and the direction they move it in
+const FOCUS_KEYS: [(&str, [f64; 2]); 4] = [
+    ("ArrowUp", [0.0, -1.0]),
+    ("ArrowDown", [0.0, 1.0]),
+    ("ArrowLeft", [-1.0, 0.0]),
+    ("ArrowRight", [1.0, 0.0]),
+];
+
+/// Finds the slot to move the focus to from `from` in `direction`.
+/// Slots straight in that direction get preferred over closer ones off to the side.
+fn next_slot(slots: &[(Entity, Pt2)], from: Pt2, direction: Vec2) -> Option<Entity> {
+    slots.iter()
+        .filter_map(|(slot, position)| {
+            let offset = position - from;
+            let along = offset.dot(&direction);
+            (along > 1e-6).then(|| (*slot, along + 2.0 * (offset - direction * along).norm()))
+        })
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).expect("Slot positions should be finite"))
+        .map(|(slot, _)| slot)
+}
+
+/// Moves the keyboard focus between the slots the token or held tile can go in with the arrow keys,
+/// and places it in the focused slot with Enter.
+/// The focus goes away when the mouse moves, so the mouse and the keyboard don't fight over the preview.
+pub struct KeyboardPlaceSystem;
+
+#[derive(SystemData)]
+pub struct KeyboardPlaceSystemData<'a> {
+    entities: Entities<'a>,
+    run_place_token: Read<'a, RunPlaceTokenSystem>,
+    run_place_tile: Read<'a, RunPlaceTileSystem>,
+    focus: Write<'a, KeyboardFocus>,
+    placed_port: Write<'a, PlacedPort>,
+    placed_loc: Write<'a, PlacedTLoc>,
+    tokens: ReadStorage<'a, TokenToPlace>,
+    tiles: ReadStorage<'a, TileToPlace>,
+    token_slots: ReadStorage<'a, TokenSlot>,
+    tile_slots: ReadStorage<'a, TileSlot>,
+    ports: ReadStorage<'a, PortLabel>,
+    locs: ReadStorage<'a, TLocLabel>,
+    transforms: ReadStorage<'a, Transform>,
+    keyboard_input: Option<Read<'a, KeyboardInput>>,
+    board_input: Option<Read<'a, BoardInput>>,
+}
+
+impl<'a> System<'a> for KeyboardPlaceSystem {
+    type SystemData = KeyboardPlaceSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let keyboard_input = data.keyboard_input.expect("Missing KeyboardInput");
+        let mouse = data.board_input.expect("Missing BoardInput").position();
+        if mouse != data.focus.mouse {
+            data.focus.slot = None;
+        }
+        data.focus.mouse = mouse;
+
+        let placing_token = data.run_place_token.0 && data.tokens.join().next().is_some();
+        let placing_tile = data.run_place_tile.0 && data.tiles.join().next().is_some();
+        let slots = if placing_token {
+            (&data.entities, &data.token_slots, &data.transforms).join()
+                .map(|(slot, _, transform)| (slot, transform.position))
+                .collect_vec()
+        } else if placing_tile {
+            (&data.entities, &data.tile_slots, &data.transforms).join()
+                .map(|(slot, _, transform)| (slot, transform.position))
+                .collect_vec()
+        } else {
+            vec![]
+        };
+
+        // Slots come and go as the game goes on
+        let focused = data.focus.slot.and_then(|focus| slots.iter().find(|(slot, _)| *slot == focus));
+        data.focus.slot = focused.map(|(slot, _)| *slot);
+
+        if let Some((_, direction)) = FOCUS_KEYS.iter().find(|(key, _)| keyboard_input.pressed(key)) {
+            let direction = Vec2::from(*direction);
+            data.focus.slot = match focused {
+                Some((slot, position)) => next_slot(&slots, *position, direction).or(Some(*slot)),
+                // Start from wherever the mouse left off
+                None => slots.iter()
+                    .min_by(|(_, a), (_, b)| (a - mouse).norm().partial_cmp(&(b - mouse).norm())
+                        .expect("Slot positions should be finite"))
+                    .map(|(slot, _)| *slot),
+            };
+        }
+
+        if keyboard_input.pressed("Enter") || keyboard_input.pressed("NumpadEnter") {
+            if let Some(slot) = data.focus.slot {
+                if placing_token {
+                    data.placed_port.0 = data.ports.get(slot).map(|port| port.0.clone());
+                } else {
+                    data.placed_loc.0 = data.locs.get(slot).map(|loc| loc.0.clone());
+                }
+            }
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct RunSelectTileSystem(pub bool);
 
+/// Selects tiles from the hand and transforms the selected tile.
+/// Rendering is left to `TileSelectRenderSystem`.
 pub struct SelectTileSystem;
 
 /// The tile that's currently selected, paired with its index and group action
@@ -570,7 +1326,7 @@ pub struct SelectTileSystemData<'a> {
     run: Read<'a, RunSelectTileSystem>,
     selected_tile: Write<'a, SelectedTile>,
     keyboard_input: Option<Read<'a, KeyboardInput>>,
-    models: ReadStorage<'a, Model>,
+    board_input: Option<Read<'a, BoardInput>>,
     colliders: ReadStorage<'a, Collider>,
     tiles: ReadStorage<'a, TileLabel>,
     tile_selects: WriteStorage<'a, TileSelect>,
@@ -587,26 +1343,25 @@ impl<'a> System<'a> for SelectTileSystem {
         // Edit group action if necessary
         let selected_tile = &mut *data.selected_tile;
         let keyboard_input = data.keyboard_input.expect("Missing KeyboardInput");
+        let wheel_steps = data.board_input.expect("Missing BoardInput").wheel_steps();
         if let (Some(action), Some(tile)) = (&mut selected_tile.1, &selected_tile.2) {
             for (collider, button_action, key) in (&data.colliders, &data.button_actions, &data.key_labels).join() {
                 if collider.clicked() || keyboard_input.pressed(&key.0) {
-                    *action = action.compose(&button_action.group_action(tile));
+                    if let Some(button_action) = button_action.group_action(tile) {
+                        *action = action.compose(&button_action);
+                    }
                 }
             }
+            // Scrolling down turns the tile clockwise, like the clockwise button
+            if wheel_steps != 0 {
+                *action = action.compose(&tile.rotation_action(wheel_steps));
+            }
         }
 
-        for (model, tile_select, tile) in (&data.models, &mut data.tile_selects, &data.tiles).join() {
-            let elem = document().get_element_by_id(&model.id).expect("Missing model element");
-
-            // Replace rendered tile if necessary
+        for tile_select in (&mut data.tile_selects).join() {
             if tile_select.selected {
-                if let Some(action) = data.selected_tile.1.clone() {
-                    if action != tile_select.action {
-                        let old = elem.first_child().expect("Expected a tile svg");
-                        let new = render::parse_svg(&tile.0.apply_action(&action).render());
-                        elem.replace_child(&new, &old).expect("Failed to replace tile svg");
-                        tile_select.action = action;
-                    }
+                if let Some(action) = &data.selected_tile.1 {
+                    tile_select.action = action.clone();
                 }
             }
         }
@@ -620,10 +1375,12 @@ impl<'a> System<'a> for SelectTileSystem {
 
         for (collider, tile, tile_select) in (&data.colliders, &data.tiles, &mut data.tile_selects).join() {
             if found_selected {
+                tile_select.selection_changed |= tile_select.selected;
                 tile_select.selected = false;
                 continue;
             }
 
+            tile_select.selection_changed |= tile_select.selected != collider.clicked();
             tile_select.selected = collider.clicked();
             if collider.clicked() {
                 found_selected = true;
@@ -632,14 +1389,249 @@ impl<'a> System<'a> for SelectTileSystem {
                 data.selected_tile.2 = Some(tile.0.clone());
             }
         }
+    }
+}
+
+/// Selects hand tile `index` of kind `kind`, transformed by `action`, the same as if the user had picked it up and turned it.
+/// Opens the tile's hand tab too. Returns whether the tile was in the hand.
+pub fn select_hand_tile(world: &World, kind: &BaseKind, index: u32, action: &BaseGAct) -> bool {
+    let tiles = world.read_component::<TileLabel>();
+    let mut tile_selects = world.write_component::<TileSelect>();
+    let mut selected_tile = world.write_resource::<SelectedTile>();
+    let mut found = false;
+
+    for (tile, tile_select) in (&tiles, &mut tile_selects).join() {
+        let selected = !found && tile_select.kind() == kind && tile_select.index == index;
+        tile_select.selection_changed |= tile_select.selected != selected;
+        tile_select.selected = selected;
+        if selected {
+            found = true;
+            tile_select.action = action.clone();
+            *selected_tile = SelectedTile(index, Some(action.clone()), Some(tile.0.clone()));
+        }
+    }
+
+    if found {
+        world.write_resource::<OpenHandTab>().open(kind.clone());
+    }
+    found
+}
+
+/// A tab in the hand for the tiles of one kind
+#[derive(Clone, Debug)]
+pub struct HandTab(pub BaseKind);
+
+impl Component for HandTab {
+    type Storage = HashMapStorage<Self>;
+}
+
+/// The kind of tile whose tab in the hand is open.
+/// Only the hand tiles of that kind show.
+#[derive(Clone, Debug, Default)]
+pub struct OpenHandTab {
+    kind: Option<BaseKind>,
+    /// Whether which tiles show needs to be updated
+    changed: bool,
+}
+
+impl OpenHandTab {
+    pub fn kind(&self) -> Option<&BaseKind> {
+        self.kind.as_ref()
+    }
+
+    /// Makes the tiles that show get updated, for when the hand changed
+    pub fn refresh(&mut self) {
+        self.changed = true;
+    }
+
+    /// Opens the tab for tiles of kind `kind`
+    pub fn open(&mut self, kind: BaseKind) {
+        if self.kind.as_ref() != Some(&kind) {
+            self.kind = Some(kind);
+            self.changed = true;
+        }
+    }
+}
+
+/// Opens hand tabs when they get clicked, or with the number keys in tab order
+pub struct HandTabSystem;
+
+impl<'a> System<'a> for HandTabSystem {
+    type SystemData = (
+        Write<'a, OpenHandTab>,
+        Option<Read<'a, KeyboardInput>>,
+        ReadStorage<'a, Collider>,
+        ReadStorage<'a, HandTab>,
+    );
+
+    fn run(&mut self, (mut open_tab, keyboard_input, colliders, tabs): Self::SystemData) {
+        let keyboard_input = keyboard_input.expect("Missing KeyboardInput");
+        let kinds = (&tabs).join().map(|tab| &tab.0).sorted().collect_vec();
+
+        let clicked = (&colliders, &tabs).join().find(|(collider, _)| collider.clicked()).map(|(_, tab)| &tab.0);
+        let pressed = (1..=9).zip(&kinds)
+            .find(|(digit, _)| keyboard_input.pressed(&format!("Digit{}", digit)))
+            .map(|(_, kind)| *kind);
+        // The tab that was open could have run out of tiles
+        let kind = clicked.or(pressed)
+            .or_else(|| open_tab.kind.as_ref().filter(|kind| kinds.contains(kind)))
+            .or_else(|| kinds.first().copied())
+            .cloned();
+
+        if kind != open_tab.kind {
+            open_tab.kind = kind;
+            open_tab.changed = true;
+        }
+    }
+}
+
+/// Shows the hand tiles of the kind whose tab is open, and marks that tab as open
+pub struct HandTabRenderSystem;
+
+impl<'a> System<'a> for HandTabRenderSystem {
+    type SystemData = (
+        Write<'a, OpenHandTab>,
+        ReadStorage<'a, Model>,
+        ReadStorage<'a, TileSelect>,
+        ReadStorage<'a, HandTab>,
+    );
+
+    fn run(&mut self, (mut open_tab, models, tile_selects, tabs): Self::SystemData) {
+        if !open_tab.changed { return; }
+        open_tab.changed = false;
+
+        for (model, tile_select) in (&models, &tile_selects).join() {
+            let elem = document().get_element_by_id(&model.id).expect("Missing model element");
+            elem.toggle_attribute_with_force("hidden", Some(tile_select.kind()) != open_tab.kind())
+                .expect("Cannot set hand tile visibility");
+        }
+        for (model, tab) in (&models, &tabs).join() {
+            let elem = document().get_element_by_id(&model.id).expect("Missing model element");
+            elem.toggle_attribute_with_force("open", Some(&tab.0) == open_tab.kind())
+                .expect("Cannot set hand tab style");
+        }
+    }
+}
+
+/// Shows which tile is selected, and how it's transformed
+pub struct TileSelectRenderSystem;
+
+impl<'a> System<'a> for TileSelectRenderSystem {
+    type SystemData = (ReadStorage<'a, Model>, ReadStorage<'a, TileLabel>, WriteStorage<'a, TileSelect>);
 
-        // Update selection visualization
-        for (model, tile_select, _tile) in (&data.models, &mut data.tile_selects, &data.tiles).join() {
+    fn run(&mut self, (models, tiles, mut tile_selects): Self::SystemData) {
+        for (model, tile_select, tile) in (&models, &mut tile_selects, &tiles).join() {
             let elem = document().get_element_by_id(&model.id).expect("Missing model element");
-            elem.set_attribute(
-                "class", 
-                if tile_select.selected { "bottom-tile tile-selected" } else { "bottom-tile tile-unselected" }
-            ).expect("Cannot set tile select style");
+
+            // Replace rendered tile if necessary
+            if tile_select.action != tile_select.rendered_action {
+                let old = elem.first_child().expect("Expected a tile svg");
+                let new = render::parse_svg(&tile.0.apply_action(&tile_select.action).render());
+                elem.replace_child(&new, &old).expect("Failed to replace tile svg");
+                tile_select.rendered_action = tile_select.action.clone();
+            }
+
+            if tile_select.selection_changed {
+                elem.set_attribute(
+                    "class", 
+                    if tile_select.selected { "bottom-tile tile-selected" } else { "bottom-tile tile-unselected" }
+                ).expect("Cannot set tile select style");
+                tile_select.selection_changed = false;
+            }
+        }
+    }
+}
+
+/// Renders highlights by setting the class of the highlighted models
+pub struct HighlightRenderSystem;
+
+impl<'a> System<'a> for HighlightRenderSystem {
+    type SystemData = (ReadStorage<'a, Model>, WriteStorage<'a, Highlight>);
+
+    fn run(&mut self, (models, mut highlights): Self::SystemData) {
+        for (model, highlight) in (&models, &mut highlights).join() {
+            if highlight.kind != highlight.rendered_kind {
+                let elem = document().get_element_by_id(&model.id).expect("Missing model element");
+                match highlight.kind {
+                    Some(kind) => elem.set_attribute("class", kind.class()).expect("Cannot set highlight style"),
+                    None => elem.remove_attribute("class").expect("Cannot remove highlight style"),
+                }
+                highlight.rendered_kind = highlight.kind;
+            }
+        }
+    }
+}
+
+/// How long the turn player's token takes to pulse once, in milliseconds
+pub const PULSE_PERIOD: f64 = 2000.0;
+/// How much pulsing things fade at the dimmest point of a pulse
+const PULSE_DEPTH: f64 = 0.35;
+
+/// The time ambient animations are at, and whether they play at all
+#[derive(Clone, Copy, Debug)]
+pub struct AnimationClock {
+    /// In milliseconds
+    pub now: f64,
+    /// Off for users who'd rather nothing move on its own
+    pub enabled: bool,
+}
+
+impl Default for AnimationClock {
+    fn default() -> Self {
+        Self { now: 0.0, enabled: true }
+    }
+}
+
+impl AnimationClock {
+    /// How far into its pulse something pulsing is, from 0 at rest to 1 at its dimmest.
+    /// Always 0 while animations are off.
+    pub fn pulse(&self) -> f64 {
+        if !self.enabled {
+            return 0.0;
+        }
+        (1.0 - (self.now * std::f64::consts::TAU / PULSE_PERIOD).cos()) / 2.0
+    }
+}
+
+/// Marks the token of the player whose turn it is, which pulses gently
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Pulse;
+
+impl Component for Pulse {
+    type Storage = NullStorage<Self>;
+}
+
+/// Plays the ambient animations: the turn player's token pulses, and draw piles running low shimmer
+#[derive(Default)]
+pub struct AmbientAnimationSystem {
+    /// Entities that pulsed last frame
+    pulsed: BitSet,
+}
+
+impl<'a> System<'a> for AmbientAnimationSystem {
+    type SystemData = (Entities<'a>, Read<'a, AnimationClock>, ReadStorage<'a, Model>, ReadStorage<'a, Pulse>);
+
+    fn run(&mut self, (entities, clock, models, pulses): Self::SystemData) {
+        let pulse = clock.pulse();
+
+        // Tokens that stopped pulsing go back to normal
+        for (model, _, _) in (&models, !&pulses, &self.pulsed).join() {
+            if let Some(elem) = model.element() {
+                elem.remove_attribute("opacity").expect("Cannot remove pulse");
+            }
+        }
+        self.pulsed.clear();
+        for (entity, model, _) in (&entities, &models, &pulses).join() {
+            if let Some(elem) = model.element() {
+                elem.set_attribute("opacity", &(1.0 - PULSE_DEPTH * pulse).to_string()).expect("Cannot set pulse");
+            }
+            self.pulsed.add(entity.id());
+        }
+
+        // Draw piles get rendered again every move, so they shimmer by a variable their style uses
+        if let Some(state_list) = document().get_element_by_id("state_list") {
+            state_list.dyn_into::<HtmlElement>().expect("State list isn't an HTML element")
+                .style().set_property("--shimmer", &pulse.to_string()).expect("Cannot set shimmer");
         }
     }
 }
@@ -658,7 +1650,7 @@ pub struct SelectGameSystemData<'a> {
     run: Read<'a, RunSelectGameSystem>,
     selected_game: Write<'a, SelectedGame>,
     colliders: ReadStorage<'a, Collider>,
-    games: ReadStorage<'a, GameInstanceLabel>,
+    games: ReadStorage<'a, LobbyGameLabel>,
 }
 
 impl<'a> System<'a> for SelectGameSystem {
@@ -674,4 +1666,705 @@ impl<'a> System<'a> for SelectGameSystem {
             }
         }
     }
-}
\ No newline at end of file
+}
+/// Whether the debug overlay is shown over the board
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShowDebugOverlay(pub bool);
+
+/// Toggles the debug overlay when Alt+Shift+D is pressed
+pub struct ToggleDebugOverlaySystem;
+
+impl<'a> System<'a> for ToggleDebugOverlaySystem {
+    type SystemData = (Option<Read<'a, KeyboardInput>>, Write<'a, ShowDebugOverlay>);
+
+    fn run(&mut self, (keyboard_input, mut show): Self::SystemData) {
+        let keyboard_input = keyboard_input.expect("Missing KeyboardInput");
+        let alt = keyboard_input.down("AltLeft") || keyboard_input.down("AltRight");
+        let shift = keyboard_input.down("ShiftLeft") || keyboard_input.down("ShiftRight");
+        if alt && shift && keyboard_input.pressed("KeyD") {
+            show.0 = !show.0;
+        }
+    }
+}
+
+/// Draws collider outlines, entity ids, render orders, and port and tile location labels over the board.
+/// The overlay is redrawn every frame while it's shown, so it should run after everything else renders.
+pub struct DebugOverlaySystem;
+
+impl DebugOverlaySystem {
+    const OVERLAY_ID: &'static str = "debug_overlay";
+}
+
+impl<'a> System<'a> for DebugOverlaySystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, ShowDebugOverlay>,
+        ReadStorage<'a, Model>,
+        ReadStorage<'a, Collider>,
+        ReadStorage<'a, PortLabel>,
+        ReadStorage<'a, TLocLabel>,
+    );
+
+    fn run(&mut self, (entities, show, models, colliders, ports, locs): Self::SystemData) {
+        if let Some(overlay) = document().get_element_by_id(Self::OVERLAY_ID) {
+            overlay.remove();
+        }
+        if !show.0 { return; }
+
+        let root: SvgGraphicsElement = document().get_element_by_id("svg_root").expect("Missing main panel svg")
+            .dyn_into().expect("Not an <svg> element");
+        let to_root = root.get_screen_ctm()
+            .expect("Missing SVG matrix")
+            .inverse().expect("Cannot inverse SVG matrix");
+
+        let items = (&entities, &models).join()
+            .flat_map(|(entity, model)| {
+                // Only things on the board
+                let elem: SvgGraphicsElement = document().get_element_by_id(&model.id)?.dyn_into().ok()?;
+                if !root.contains(Some(elem.as_ref())) {
+                    return None;
+                }
+
+                let bbox = elem.get_b_box().ok()?;
+                let to_screen = elem.get_screen_ctm()?;
+                let corner = |x: f32, y: f32| to_root.transform(to_screen.transform(pt2(x as f64, y as f64)));
+                let min = corner(bbox.x(), bbox.y());
+                let max = corner(bbox.x() + bbox.width(), bbox.y() + bbox.height());
+
+                let mut label = format!("#{} z{}", entity.id(), model.order);
+                if let Some(port) = ports.get(entity) {
+                    label += &format!(" p{}", render::debug_port_label(&port.0));
+                }
+                if let Some(loc) = locs.get(entity) {
+                    label += &format!(" l{}", render::debug_tloc_label(&loc.0));
+                }
+
+                Some(render::DebugOverlayItem {
+                    rect: render::Rect::from_ltrb(min.x as f32, min.y as f32, max.x as f32, max.y as f32),
+                    collider: colliders.contains(entity),
+                    label,
+                })
+            })
+            .collect_vec();
+
+        let overlay = render::render_debug_overlay(&items);
+        overlay.set_id(Self::OVERLAY_ID);
+        root.append_child(&overlay).expect("Failed to add debug overlay");
+    }
+}
+
+/// How the connection to the server is doing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// Waiting for the connection to open
+    Connecting,
+    Connected,
+    /// The server hasn't said anything in a while
+    Unresponsive,
+    Closed,
+}
+
+impl Display for ConnectionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connecting => write!(f, "connecting"),
+            Self::Connected => write!(f, "connected"),
+            Self::Unresponsive => write!(f, "unresponsive"),
+            Self::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+/// Tracks the health of the connection to the server
+#[derive(Clone, Debug, CopyGetters)]
+pub struct ConnectionHealth {
+    #[getset(get_copy = "pub")]
+    status: ConnectionStatus,
+    /// When the server was last heard from, in milliseconds
+    last_heard: f64,
+}
+
+impl Default for ConnectionHealth {
+    fn default() -> Self {
+        Self { status: ConnectionStatus::Connecting, last_heard: 0.0 }
+    }
+}
+
+impl ConnectionHealth {
+    /// Records that the server said something at time `now`, in milliseconds
+    pub fn heard(&mut self, now: f64) {
+        if self.status != ConnectionStatus::Closed {
+            self.status = ConnectionStatus::Connected;
+            self.last_heard = now;
+        }
+    }
+
+    /// Records that the connection closed. It doesn't come back after this.
+    pub fn closed(&mut self) {
+        self.status = ConnectionStatus::Closed;
+    }
+
+    /// Marks the connection as unresponsive if the server has been quiet for too long
+    pub fn check(&mut self, now: f64) {
+        if self.status == ConnectionStatus::Connected && now - self.last_heard > CONNECTION_TIMEOUT.as_secs_f64() * 1000.0 {
+            self.status = ConnectionStatus::Unresponsive;
+        }
+    }
+}
+
+/// Shows the connection status whenever it changes
+#[derive(Debug, Default)]
+pub struct ConnectionStatusSystem {
+    shown: Option<ConnectionStatus>,
+}
+
+impl<'a> System<'a> for ConnectionStatusSystem {
+    type SystemData = Read<'a, ConnectionHealth>;
+
+    fn run(&mut self, health: Self::SystemData) {
+        if self.shown != Some(health.status()) {
+            render::set_connection_status(health.status());
+            self.shown = Some(health.status());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{WrapBase, math::{Pt2u, Vec2u}, tile::RegularTile};
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    use super::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    /// A world with everything the input systems need, with simulated input
+    fn test_world() -> World {
+        let mut world = World::new();
+        world.register::<Model>();
+        world.register::<Collider>();
+        world.register::<TokenSlot>();
+        world.register::<TokenToPlace>();
+        world.register::<TileSlot>();
+        world.register::<TileToPlace>();
+        world.register::<Transform>();
+        world.register::<PortLabel>();
+        world.register::<TLocLabel>();
+        world.register::<TileLabel>();
+        world.register::<TileSelect>();
+        world.register::<ButtonAction>();
+        world.register::<KeyLabel>();
+        world.insert(BoardInput::detached());
+        world.insert(Camera::new(Rect::from_ltwh(-0.5, -0.5, 7.0, 7.0)));
+        world.insert(KeyboardInput::detached());
+        world.insert(RunPlaceTokenSystem(true));
+        world.insert(RunSelectTileSystem(true));
+        world.insert(RunPlaceTileSystem(true));
+        world.insert(PlacedPort(None));
+        world.insert(SelectedTile(0, None, None));
+        world.insert(PlacedTLoc(None));
+        world.insert(KeyboardFocus::default());
+        world.register::<HandTab>();
+        world.insert(OpenHandTab::default());
+        world
+    }
+
+    /// Runs the input systems for one frame
+    fn run_frame(world: &mut World) {
+        ColliderInputSystem.run_now(world);
+        KeyboardInputSystem.run_now(world);
+        CameraSystem.run_now(world);
+        KeyboardPlaceSystem.run_now(world);
+        PlaceTokenSystem.run_now(world);
+        PlaceTileSystem.run_now(world);
+        SelectTileSystem.run_now(world);
+        HandTabSystem.run_now(world);
+        world.maintain();
+    }
+
+    fn click(world: &World, entity: Entity) {
+        world.read_storage::<Collider>().get(entity).expect("Missing collider").simulate_click();
+    }
+
+    fn hover(world: &World, entity: Entity, hovered: bool) {
+        world.read_storage::<Collider>().get(entity).expect("Missing collider").simulate_hover(hovered);
+    }
+
+    fn selected(world: &World, entities: &[Entity]) -> Vec<bool> {
+        let tile_selects = world.read_storage::<TileSelect>();
+        entities.iter().map(|e| tile_selects.get(*e).expect("Missing tile select").selected()).collect()
+    }
+
+    fn test_tile() -> BaseTile {
+        RegularTile::<4>::new(vec![2, 3, 0, 1, 7, 6, 5, 4]).wrap_base()
+    }
+
+    /// Creates tiles in a hand and returns their entities
+    fn create_hand(world: &mut World, tiles: &[BaseTile]) -> Vec<Entity> {
+        tiles.iter().enumerate().map(|(index, tile)| world.create_entity()
+            .with(Collider::detached())
+            .with(TileLabel(tile.clone()))
+            .with(TileSelect::new(tile.kind(), index as u32, tile.identity_action()))
+            .build()
+        ).collect()
+    }
+
+    /// Creates a rotate clockwise button
+    fn create_rotate_button(world: &mut World) -> Entity {
+        world.create_entity()
+            .with(Collider::detached())
+            .with(ButtonAction::Rotation{ num_times: 1 })
+            .with(KeyLabel("KeyR".to_owned()))
+            .build()
+    }
+
+    #[wasm_bindgen_test]
+    fn test_transform_svg() {
+        let transform = Transform::new(Pt2::new(1.5, 2.0));
+        assert_eq!(transform.svg_transform(), "translate(1.5, 2) rotate(0) scale(1)");
+        assert_eq!(transform.with_rotation(90.0).with_scale(0.5).svg_transform(), "translate(1.5, 2) rotate(90) scale(0.5)");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_select_tile() {
+        let mut world = test_world();
+        let tiles = [test_tile(), test_tile().rotate(1)];
+        let hand = create_hand(&mut world, &tiles);
+
+        run_frame(&mut world);
+        assert_eq!(selected(&world, &hand), vec![false, false]);
+        assert_eq!(world.fetch::<SelectedTile>().2, None);
+
+        click(&world, hand[1]);
+        run_frame(&mut world);
+        assert_eq!(selected(&world, &hand), vec![false, true]);
+        let selected_tile = world.fetch::<SelectedTile>().clone();
+        assert_eq!(selected_tile.0, 1);
+        assert_eq!(selected_tile.1, Some(tiles[1].identity_action()));
+        assert_eq!(selected_tile.2, Some(tiles[1].clone()));
+
+        // Clicks only last a frame
+        run_frame(&mut world);
+        assert_eq!(selected(&world, &hand), vec![false, true]);
+
+        click(&world, hand[0]);
+        run_frame(&mut world);
+        assert_eq!(selected(&world, &hand), vec![true, false]);
+        assert_eq!(world.fetch::<SelectedTile>().0, 0);
+
+        // Nothing happens while the system is off
+        world.insert(RunSelectTileSystem(false));
+        click(&world, hand[1]);
+        run_frame(&mut world);
+        assert_eq!(selected(&world, &hand), vec![true, false]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_rotate_selected_tile() {
+        let mut world = test_world();
+        let tile = test_tile();
+        let hand = create_hand(&mut world, &[tile.clone()]);
+        let button = create_rotate_button(&mut world);
+
+        // Nothing to rotate yet
+        click(&world, button);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, None);
+
+        click(&world, hand[0]);
+        run_frame(&mut world);
+        click(&world, button);
+        run_frame(&mut world);
+        click(&world, button);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, Some(tile.rotation_action(2)));
+
+        // Holding a key down only counts once
+        world.fetch::<KeyboardInput>().simulate_key_down("KeyR");
+        run_frame(&mut world);
+        run_frame(&mut world);
+        world.fetch::<KeyboardInput>().simulate_key_up("KeyR");
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, Some(tile.rotation_action(3)));
+
+        // Wraps around
+        world.fetch::<KeyboardInput>().simulate_key_down("KeyR");
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, Some(tile.identity_action()));
+        assert_eq!(world.read_storage::<TileSelect>().get(hand[0]).unwrap().action(), &tile.identity_action());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_reflect_unreflectable_tile() {
+        let mut world = test_world();
+        let tile = test_tile();
+        let hand = create_hand(&mut world, &[tile.clone()]);
+        let button = world.create_entity()
+            .with(Collider::detached())
+            .with(ButtonAction::Reflection)
+            .with(KeyLabel("KeyF".to_owned()))
+            .build();
+
+        click(&world, hand[0]);
+        run_frame(&mut world);
+        click(&world, button);
+        world.fetch::<KeyboardInput>().simulate_key_down("KeyF");
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, Some(tile.identity_action()));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wheel_rotates_selected_tile() {
+        let mut world = test_world();
+        let tile = test_tile();
+        let hand = create_hand(&mut world, &[tile.clone()]);
+
+        // Nothing to rotate yet, and the scrolling doesn't carry over
+        world.fetch::<BoardInput>().simulate_wheel(WHEEL_STEP);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, None);
+
+        click(&world, hand[0]);
+        run_frame(&mut world);
+        world.fetch::<BoardInput>().simulate_wheel(2.0 * WHEEL_STEP);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, Some(tile.rotation_action(2)));
+
+        // Small scrolls add up
+        world.fetch::<BoardInput>().simulate_wheel(-WHEEL_STEP / 2.0);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, Some(tile.rotation_action(2)));
+        world.fetch::<BoardInput>().simulate_wheel(-WHEEL_STEP / 2.0);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, Some(tile.rotation_action(1)));
+        assert_eq!(world.read_storage::<TileSelect>().get(hand[0]).unwrap().action(), &tile.rotation_action(1));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_hand_tabs() {
+        let mut world = test_world();
+        let tile = test_tile();
+        let hand = create_hand(&mut world, &[tile.clone(), tile.clone()]);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<OpenHandTab>().kind(), None);
+
+        // The only tab opens by itself
+        let tab = world.create_entity()
+            .with(Collider::detached())
+            .with(HandTab(tile.kind()))
+            .build();
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<OpenHandTab>().kind(), Some(&tile.kind()));
+
+        // Switching tabs keeps the selection
+        click(&world, hand[1]);
+        run_frame(&mut world);
+        world.fetch::<KeyboardInput>().simulate_key_down("Digit1");
+        click(&world, tab);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<OpenHandTab>().kind(), Some(&tile.kind()));
+        assert_eq!(selected(&world, &hand), vec![false, true]);
+
+        world.delete_entity(tab).unwrap();
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<OpenHandTab>().kind(), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_touch_rotates_selected_tile() {
+        let mut world = test_world();
+        let tile = test_tile();
+        let hand = create_hand(&mut world, &[tile.clone()]);
+        click(&world, hand[0]);
+        run_frame(&mut world);
+
+        // Holding a finger still rotates once, however long it's held
+        let long_ago = js_sys::Date::now() - TOUCH_HOLD_TIME;
+        world.fetch::<BoardInput>().simulate_touch_start(pt2(1.0, 1.0), 1, long_ago);
+        run_frame(&mut world);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, Some(tile.rotation_action(1)));
+        assert!(!world.fetch::<BoardInput>().simulate_touch_end(0), "Rotating isn't a tap");
+
+        // So does a second finger
+        world.fetch::<BoardInput>().simulate_touch_start(pt2(1.0, 1.0), 1, js_sys::Date::now());
+        world.fetch::<BoardInput>().simulate_touch_start(pt2(1.0, 1.0), 2, js_sys::Date::now());
+        world.fetch::<BoardInput>().simulate_touch_start(pt2(1.0, 1.0), 3, js_sys::Date::now());
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, Some(tile.rotation_action(2)));
+        world.fetch::<BoardInput>().simulate_touch_end(1);
+        world.fetch::<BoardInput>().simulate_touch_end(0);
+
+        // Moving the finger positions the tile instead
+        world.fetch::<BoardInput>().simulate_touch_start(pt2(1.0, 1.0), 1, long_ago);
+        world.fetch::<BoardInput>().simulate_touch_move(pt2(3.0, 2.0));
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<SelectedTile>().1, Some(tile.rotation_action(2)));
+        assert_eq!(world.fetch::<BoardInput>().position(), pt2(3.0, 2.0));
+        assert!(!world.fetch::<BoardInput>().simulate_touch_end(0), "Moving isn't a tap");
+
+        world.fetch::<BoardInput>().simulate_touch_start(pt2(1.0, 1.0), 1, js_sys::Date::now());
+        assert!(world.fetch::<BoardInput>().simulate_touch_end(0));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_camera_zoom_and_pan() {
+        let mut world = test_world();
+        // Already zoomed all the way out
+        world.fetch::<BoardInput>().simulate_zoom(3.0);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<Camera>().zoom(), 1.0);
+
+        // The point under the mouse stays put
+        world.fetch::<BoardInput>().simulate_move(pt2(1.0, 2.0));
+        world.fetch::<BoardInput>().simulate_zoom(-2.0);
+        run_frame(&mut world);
+        assert!((world.fetch::<Camera>().zoom() - Camera::ZOOM_PER_STEP.powi(2)).abs() < 1e-9);
+        let view = world.fetch::<Camera>().view();
+        assert!(((1.0 - view.left()) / view.width() - 1.5 / 7.0).abs() < 1e-4);
+        assert!(((2.0 - view.top()) / view.height() - 2.5 / 7.0).abs() < 1e-4);
+
+        // Dragging the board right shows more of its left side
+        world.fetch::<BoardInput>().simulate_drag(vector![0.5, 0.0]);
+        run_frame(&mut world);
+        let panned = world.fetch::<Camera>().view();
+        assert!((panned.left() - (view.left() - 0.5)).abs() < 1e-4);
+        assert!((panned.top() - view.top()).abs() < 1e-4);
+
+        // But never past the board's edge
+        world.fetch::<BoardInput>().simulate_drag(vector![100.0, -100.0]);
+        run_frame(&mut world);
+        let panned = world.fetch::<Camera>().view();
+        assert!((panned.left() - -0.5).abs() < 1e-4);
+        assert!((panned.top() + panned.height() - 6.5).abs() < 1e-4);
+
+        world.fetch_mut::<Camera>().zoom_out();
+        assert_eq!(world.fetch::<Camera>().view().to_viewbox_value(), "-0.5 -0.5 7 7");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_place_token() {
+        let mut world = test_world();
+        let ports = [
+            BasePort::Pt2uVec2u((Pt2u::new(0, 0), Vec2u::new(0, 1))),
+            BasePort::Pt2uVec2u((Pt2u::new(1, 0), Vec2u::new(0, 1))),
+        ];
+        let slots = ports.iter().enumerate().map(|(i, port)| world.create_entity()
+            .with(Collider::detached())
+            .with(TokenSlot)
+            .with(PortLabel(port.clone()))
+            .with(Transform::new(pt2(i as f64, 0.0)))
+            .build()
+        ).collect_vec();
+        let token = world.create_entity()
+            .with(TokenToPlace)
+            .with(Transform::new(Pt2::origin()))
+            .build();
+        let token_position = |world: &World| world.read_storage::<Transform>().get(token).unwrap().position;
+
+        // The token follows the mouse
+        world.fetch::<BoardInput>().simulate_move(pt2(2.5, 3.5));
+        run_frame(&mut world);
+        assert_eq!(token_position(&world), pt2(2.5, 3.5));
+
+        // and snaps to hovered slots
+        hover(&world, slots[1], true);
+        run_frame(&mut world);
+        assert_eq!(token_position(&world), pt2(1.0, 0.0));
+        assert_eq!(world.fetch::<PlacedPort>().0, None);
+
+        click(&world, slots[1]);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<PlacedPort>().0, Some(ports[1].clone()));
+
+        hover(&world, slots[1], false);
+        run_frame(&mut world);
+        assert_eq!(token_position(&world), pt2(2.5, 3.5));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_follower_moves_before_frame() {
+        let mut world = test_world();
+        let slot = world.create_entity()
+            .with(Collider::detached())
+            .with(TokenSlot)
+            .with(PortLabel(BasePort::Pt2uVec2u((Pt2u::new(0, 0), Vec2u::new(0, 1)))))
+            .with(Transform::new(pt2(1.0, 0.0)))
+            .build();
+        let elem = render::parse_svg(r#"<g xmlns="http://www.w3.org/2000/svg"/>"#);
+        let parent = document().document_element().expect("Missing root element");
+        // Far from the ids anything else uses, other tests included
+        let mut id_counter = (1 << 40) + 100;
+        world.create_entity()
+            .with(Model::new(&elem, 0, &parent, &mut id_counter))
+            .with(TokenToPlace)
+            .with(Transform::new(Pt2::origin()).with_scale(0.5))
+            .build();
+        let element_transform = || elem.get_attribute("transform");
+
+        // Once the systems know what's following the mouse, it moves right away, keeping its scale
+        run_frame(&mut world);
+        world.fetch::<BoardInput>().simulate_move(pt2(2.5, 3.5));
+        assert_eq!(element_transform().as_deref(), Some("translate(2.5, 3.5) rotate(0) scale(0.5)"));
+
+        // but stays put while snapped to a slot
+        hover(&world, slot, true);
+        run_frame(&mut world);
+        elem.remove_attribute("transform").unwrap();
+        world.fetch::<BoardInput>().simulate_move(pt2(4.0, 4.0));
+        assert_eq!(element_transform(), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_keyboard_place_token() {
+        let mut world = test_world();
+        let ports = [
+            BasePort::Pt2uVec2u((Pt2u::new(0, 0), Vec2u::new(0, 1))),
+            BasePort::Pt2uVec2u((Pt2u::new(1, 0), Vec2u::new(0, 1))),
+            BasePort::Pt2uVec2u((Pt2u::new(2, 0), Vec2u::new(0, 1))),
+            BasePort::Pt2uVec2u((Pt2u::new(2, 1), Vec2u::new(0, 1))),
+        ];
+        let positions = [pt2(0.0, 0.0), pt2(1.0, 0.0), pt2(2.0, 0.0), pt2(1.0, 1.0)];
+        let slots = ports.iter().zip(positions).map(|(port, position)| world.create_entity()
+            .with(Collider::detached())
+            .with(TokenSlot)
+            .with(PortLabel(port.clone()))
+            .with(Transform::new(position))
+            .build()
+        ).collect_vec();
+        let token = world.create_entity()
+            .with(TokenToPlace)
+            .with(Transform::new(Pt2::origin()))
+            .build();
+        let token_position = |world: &World| world.read_storage::<Transform>().get(token).unwrap().position;
+        let press = |world: &mut World, key: &str| {
+            world.fetch::<KeyboardInput>().simulate_key_down(key);
+            run_frame(world);
+            world.fetch::<KeyboardInput>().simulate_key_up(key);
+            run_frame(world);
+        };
+
+        // The focus starts at the slot closest to the mouse
+        world.fetch::<BoardInput>().simulate_move(pt2(1.1, 0.2));
+        run_frame(&mut world);
+        press(&mut world, "ArrowLeft");
+        assert_eq!(world.fetch::<KeyboardFocus>().slot, Some(slots[1]));
+        assert_eq!(token_position(&world), pt2(1.0, 0.0));
+
+        press(&mut world, "ArrowLeft");
+        assert_eq!(world.fetch::<KeyboardFocus>().slot, Some(slots[0]));
+        // Nothing further left
+        press(&mut world, "ArrowLeft");
+        assert_eq!(world.fetch::<KeyboardFocus>().slot, Some(slots[0]));
+        press(&mut world, "ArrowDown");
+        assert_eq!(world.fetch::<KeyboardFocus>().slot, Some(slots[3]));
+        // Straight up wins over the slots off to the side
+        press(&mut world, "ArrowUp");
+        assert_eq!(world.fetch::<KeyboardFocus>().slot, Some(slots[1]));
+        assert_eq!(world.fetch::<PlacedPort>().0, None);
+
+        press(&mut world, "Enter");
+        assert_eq!(world.fetch::<PlacedPort>().0, Some(ports[1].clone()));
+
+        // Moving the mouse takes over again
+        world.fetch::<BoardInput>().simulate_move(pt2(2.5, 3.5));
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<KeyboardFocus>().slot, None);
+        assert_eq!(token_position(&world), pt2(2.5, 3.5));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_place_tile() {
+        let mut world = test_world();
+        let locs = [BaseTLoc::Pt2u(Pt2u::new(0, 0)), BaseTLoc::Pt2u(Pt2u::new(1, 0))];
+        let slots = locs.iter().enumerate().map(|(i, loc)| world.create_entity()
+            .with(Collider::detached())
+            .with(TileSlot)
+            .with(TLocLabel(loc.clone()))
+            .with(Transform::new(pt2(i as f64, 0.0)))
+            .build()
+        ).collect_vec();
+        let tile = world.create_entity()
+            .with(TileToPlace)
+            .with(Transform::new(Pt2::origin()))
+            .build();
+        let tile_position = |world: &World| world.read_storage::<Transform>().get(tile).unwrap().position;
+
+        hover(&world, slots[0], true);
+        run_frame(&mut world);
+        assert_eq!(tile_position(&world), pt2(0.0, 0.0));
+
+        // Nothing gets placed while the system is off
+        world.insert(RunPlaceTileSystem(false));
+        click(&world, slots[0]);
+        run_frame(&mut world);
+        assert_eq!(world.fetch::<PlacedTLoc>().0, None);
+
+        world.insert(RunPlaceTileSystem(true));
+        hover(&world, slots[0], false);
+        hover(&world, slots[1], true);
+        click(&world, slots[1]);
+        run_frame(&mut world);
+        assert_eq!(tile_position(&world), pt2(1.0, 0.0));
+        assert_eq!(world.fetch::<PlacedTLoc>().0, Some(locs[1].clone()));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_highlight_render() {
+        let mut world = World::new();
+        world.register::<Model>();
+        world.register::<Highlight>();
+        let elem = render::parse_svg(r#"<g xmlns="http://www.w3.org/2000/svg"/>"#);
+        let parent = document().document_element().expect("Missing root element");
+        // Far from the ids anything else uses
+        let mut id_counter = 1 << 40;
+        let entity = world.create_entity()
+            .with(Model::new(&elem, 0, &parent, &mut id_counter))
+            .with(Highlight::default())
+            .build();
+        let set_kind = |world: &World, kind| world.write_storage::<Highlight>().get_mut(entity).unwrap().set_kind(kind);
+
+        set_kind(&world, Some(HighlightKind::Lethal));
+        HighlightRenderSystem.run_now(&world);
+        assert_eq!(elem.get_attribute("class").as_deref(), Some("highlight-lethal"));
+
+        set_kind(&world, Some(HighlightKind::Legal));
+        HighlightRenderSystem.run_now(&world);
+        assert_eq!(elem.get_attribute("class").as_deref(), Some("highlight-legal"));
+
+        set_kind(&world, None);
+        HighlightRenderSystem.run_now(&world);
+        assert_eq!(elem.get_attribute("class"), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_ambient_animation() {
+        let mut world = World::new();
+        world.register::<Model>();
+        world.register::<Pulse>();
+        world.insert(AnimationClock { now: PULSE_PERIOD / 2.0, enabled: true });
+        let elem = render::parse_svg(r#"<g xmlns="http://www.w3.org/2000/svg"/>"#);
+        let parent = document().document_element().expect("Missing root element");
+        // Far from the ids anything else uses, other tests included
+        let mut id_counter = (1 << 40) + 200;
+        let entity = world.create_entity()
+            .with(Model::new(&elem, 0, &parent, &mut id_counter))
+            .with(Pulse)
+            .build();
+        let mut system = AmbientAnimationSystem::default();
+
+        // Halfway through a pulse is the dimmest
+        system.run_now(&world);
+        assert_eq!(elem.get_attribute("opacity").and_then(|opacity| opacity.parse::<f64>().ok()), Some(1.0 - PULSE_DEPTH));
+
+        // Nothing moves with animations off
+        world.write_resource::<AnimationClock>().enabled = false;
+        system.run_now(&world);
+        assert_eq!(elem.get_attribute("opacity").as_deref(), Some("1"));
+
+        // and tokens that stop pulsing go back to normal
+        world.write_storage::<Pulse>().remove(entity);
+        system.run_now(&world);
+        assert_eq!(elem.get_attribute("opacity"), None);
+    }
+}
@@ -0,0 +1,143 @@
+//! Downloading the board as a standalone SVG file, and finished games as archives that can be opened again later.
+//! The page's stylesheet doesn't come along with the SVG, so the styles that end up applying to each element,
+//! theme variables included, get inlined into the copy that gets downloaded.
+//! Archives, and puzzles, get opened from files picked in the lobby.
+
+use std::{rc::Rc, sync::{Arc, Mutex}};
+
+use common::{archive::GameArchive, puzzle::Puzzle};
+use itertools::Itertools;
+use wasm_bindgen::{JsCast, JsValue, prelude::Closure};
+use web_sys::{Blob, BlobPropertyBag, Element, Event, FileReader, HtmlAnchorElement, HtmlInputElement, ProgressEvent, Url, XmlSerializer};
+
+use crate::{SVG_NS, add_event_listener, console_log, document, game::GameWorld, i18n, render, window};
+
+/// Style properties that decide how the board looks
+const INLINED_PROPERTIES: &[&str] = &[
+    "fill", "fill-opacity", "fill-rule",
+    "stroke", "stroke-width", "stroke-opacity", "stroke-linecap", "stroke-linejoin", "stroke-dasharray",
+    "opacity", "visibility", "display",
+    "font-family", "font-size", "font-weight", "text-anchor", "dominant-baseline",
+];
+
+/// Sets an element copy's style to the computed style of the original.
+/// Style the copy already had inline comes last so it still wins.
+fn inline_style(original: &Element, copy: &Element) {
+    let computed = match window().get_computed_style(original) {
+        Ok(Some(computed)) => computed,
+        _ => return,
+    };
+    let style = INLINED_PROPERTIES.iter()
+        .filter_map(|property| {
+            let value = computed.get_property_value(property).ok()?;
+            (!value.is_empty()).then(|| format!("{}: {};", property, value))
+        })
+        .chain(copy.get_attribute("style"))
+        .join(" ");
+    copy.set_attribute("style", &style).expect("Can't set style");
+}
+
+/// Serializes an SVG element with the computed styles of it and everything in it inlined
+pub fn styled_svg(svg: &Element) -> String {
+    let copy = svg.clone_node_with_deep(true).expect("Can't clone SVG")
+        .dyn_into::<Element>().expect("Clone of an element isn't an element");
+    inline_style(svg, &copy);
+
+    // Cloning keeps the tree the same, so elements line up in document order
+    let originals = svg.query_selector_all("*").expect("Invalid selector");
+    let copies = copy.query_selector_all("*").expect("Invalid selector");
+    for i in 0..originals.length() {
+        if let (Some(original), Some(copy)) = (originals.get(i), copies.get(i)) {
+            if let (Ok(original), Ok(copy)) = (original.dyn_into::<Element>(), copy.dyn_into::<Element>()) {
+                inline_style(&original, &copy);
+            }
+        }
+    }
+
+    copy.set_attribute("xmlns", SVG_NS).expect("Can't set namespace");
+    XmlSerializer::new().expect("Can't create serializer")
+        .serialize_to_string(&copy).expect("Can't serialize SVG")
+}
+
+/// Makes a URL for a file with some contents and MIME type. It has to be revoked once it isn't needed.
+fn object_url(contents: &str, mime_type: &str) -> String {
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let mut options = BlobPropertyBag::new();
+    options.type_(mime_type);
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &options).expect("Can't create blob");
+    Url::create_object_url_with_blob(&blob).expect("Can't create URL for blob")
+}
+
+/// Downloads the SVG element with id `id`, styled like it is on screen, as a file named `filename`
+pub fn download_svg(id: &str, filename: &str) {
+    let svg = document().get_element_by_id(id).unwrap();
+    let url = object_url(&styled_svg(&svg), "image/svg+xml");
+
+    let link = document().create_element("a").unwrap()
+        .dyn_into::<HtmlAnchorElement>().expect("Link isn't an anchor element");
+    link.set_href(&url);
+    link.set_download(filename);
+    link.click();
+    Url::revoke_object_url(&url).expect("Can't revoke URL for blob");
+}
+
+/// Shows a link for downloading the archive of the game that just ended.
+/// Hides it if there's no archive.
+pub fn set_archive_link(archive: Option<&GameArchive>) {
+    let link = document().get_element_by_id("archive_link").unwrap();
+    // The URL of the last archive keeps it in memory until it gets revoked
+    if let Some(url) = link.get_attribute("href") {
+        Url::revoke_object_url(&url).ok();
+    }
+    match archive {
+        Some(archive) => link.set_attribute("href", &object_url(&archive.to_json(), "application/json")).unwrap(),
+        None => link.remove_attribute("href").unwrap(),
+    }
+}
+
+fn file_input(id: &str) -> HtmlInputElement {
+    document().get_element_by_id(id).expect("Missing file input")
+        .dyn_into().expect("File input isn't an input element")
+}
+
+/// Calls `on_load` with the text of every file picked with the file input with id `id`
+fn on_file_picked(id: &'static str, on_load: impl Fn(String) + 'static) {
+    let on_load = Rc::new(on_load);
+    add_event_listener(&file_input(id), "change", move |_: Event| {
+        let file = match file_input(id).files().and_then(|files| files.get(0)) {
+            Some(file) => file,
+            None => return,
+        };
+        let reader = FileReader::new().expect("Can't create file reader");
+        let creader = reader.clone();
+        let con_load = Rc::clone(&on_load);
+        let on_read = Closure::once_into_js(move |_: ProgressEvent| {
+            con_load(creader.result().ok().and_then(|result| result.as_string()).unwrap_or_default());
+        });
+        reader.set_onload(Some(on_read.unchecked_ref()));
+        reader.read_as_text(&file).ok();
+        // So picking the same file again opens it again
+        file_input(id).set_value("");
+    });
+}
+
+/// Adds the listeners for opening game archives, which get watched like replays, and puzzles, which get played offline
+pub fn init_import(game_world: &Arc<Mutex<GameWorld>>) {
+    let cgw = Arc::clone(game_world);
+    on_file_picked("import_archive", move |json| match GameArchive::from_json(&json) {
+        Ok(archive) => cgw.lock().unwrap().watch_archive(archive),
+        Err(err) => {
+            console_log!("Can't open archive: {}", err);
+            render::set_notice(i18n::INVALID_ARCHIVE);
+        }
+    });
+
+    let cgw = Arc::clone(game_world);
+    on_file_picked("import_puzzle", move |json| match Puzzle::from_json(&json) {
+        Ok(puzzle) => cgw.lock().unwrap().play_puzzle(puzzle),
+        Err(err) => {
+            console_log!("Can't open puzzle: {}", err);
+            render::set_notice(i18n::INVALID_PUZZLE);
+        }
+    });
+}
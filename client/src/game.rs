@@ -1,12 +1,13 @@
 
 
-use common::{message::{Request, Response}};
-use specs::{Builder, Dispatcher, DispatcherBuilder, Entity, World, WorldExt};
+use common::{archive::GameArchive, message::{Request, Response}, options::{Emote, GamePreset}, puzzle::Puzzle, replay::Replay};
+use specs::{Builder, Dispatcher, DispatcherBuilder, Entity, Join, World, WorldExt};
+use strum::IntoEnumIterator;
 use wasm_bindgen::JsCast;
 use web_sys::{Element, SvgElement};
 
 
-use crate::{document, ecs::{BoardInput, ButtonAction, Collider, ColliderInputSystem, KeyLabel, KeyboardInput, KeyboardInputSystem, Model, PlaceTileSystem, PlaceTokenSystem, PlacedPort, PlacedTLoc, PortLabel, RunPlaceTileSystem, RunPlaceTokenSystem, RunSelectTileSystem, SelectTileSystem, SelectedTile, SvgOrderSystem, TLocLabel, TileLabel, TileSelect, TileSlot, TileToPlace, TokenSlot, TokenToPlace, Transform, TransformSystem, GameInstanceLabel, RunSelectGameSystem, SelectGameSystem, SelectedGame}};
+use crate::{daily, document, history, render::{self, Rect, TokenPalette}, settings::{Keybindings, Settings}, ecs::{BoardInput, ButtonAction, Camera, CameraSystem, HandTab, HandTabRenderSystem, HandTabSystem, OpenHandTab, KeyboardFocus, KeyboardPlaceSystem, Collider, ColliderInputSystem, KeyLabel, KeyboardInput, KeyboardInputSystem, Model, PlaceTileSystem, PlaceTokenSystem, PlacedPort, PlacedTLoc, PortLabel, RunPlaceTileSystem, RunPlaceTokenSystem, RunSelectTileSystem, SelectTileSystem, SelectedTile, SvgOrderSystem, TLocLabel, TileLabel, TileSelect, TileSlot, TileToPlace, TokenSlot, TokenToPlace, Transform, TransformSystem, LobbyGameLabel, RunSelectGameSystem, SelectGameSystem, SelectedGame, ConnectionHealth, ConnectionStatusSystem, TileSelectRenderSystem, ShowDebugOverlay, ToggleDebugOverlaySystem, DebugOverlaySystem, Highlight, HighlightRenderSystem, AnimationClock, AmbientAnimationSystem, Pulse}};
 
 mod app;
 use app::{AppStateT};
@@ -18,14 +19,38 @@ pub struct GameWorld {
     world: World,
     id_counter: u64,
     start_game_entity: Entity,
+    add_bot_entity: Entity,
+    shuffle_seats_entity: Entity,
+    set_color_entity: Entity,
+    /// A button for each emote
+    emote_entities: Vec<(Emote, Entity)>,
+    copy_invite_entity: Entity,
     leave_game_entity: Entity,
+    exchange_hand_entity: Entity,
+    scout_entity: Entity,
+    hint_entity: Entity,
+    request_undo_entity: Entity,
+    undo_accept_entity: Entity,
+    undo_decline_entity: Entity,
+    pause_entity: Entity,
+    play_offline_entity: Entity,
+    play_hotseat_entity: Entity,
+    pass_device_entity: Entity,
+    play_tutorial_entity: Entity,
+    tutorial_next_entity: Entity,
+    analyze_entity: Entity,
+    rematch_entity: Entity,
+    analysis_back_entity: Entity,
+    analysis_forward_entity: Entity,
+    analysis_main_line_entity: Entity,
     dispatcher: Dispatcher<'static, 'static>,
     render_dispatcher: Dispatcher<'static, 'static>,
 }
 
 impl GameWorld {
-    /// Constructs a game world
-    pub fn new() -> Self {
+    /// Constructs a game world, waiting for the server to accept `username`.
+    /// Game `join` gets joined right after, if the user followed an invitation to it.
+    pub fn new(username: String, join: Option<GameId>) -> Self {
         let mut world = World::new();
         world.register::<Model>();
         world.register::<Collider>();
@@ -40,9 +65,14 @@ impl GameWorld {
         world.register::<TileSelect>();
         world.register::<ButtonAction>();
         world.register::<KeyLabel>();
-        world.register::<GameInstanceLabel>();
+        world.register::<LobbyGameLabel>();
+        world.register::<Highlight>();
+        world.register::<Pulse>();
+        world.register::<HandTab>();
         world.insert(BoardInput::new(&document().get_element_by_id("svg_root").expect("Missing main panel svg")
             .dyn_into().expect("Not an <svg> element")));
+        // Same as the viewBox in index.html
+        world.insert(Camera::new(Rect::from_ltwh(-0.5, -0.5, 7.0, 7.0)));
         world.insert(KeyboardInput::new(&document().document_element().expect("Missing root element. What?!")));
         world.insert(RunPlaceTokenSystem(true));
         world.insert(RunSelectTileSystem(true));
@@ -51,48 +81,180 @@ impl GameWorld {
         world.insert(PlacedPort(None));
         world.insert(SelectedTile(0, None, None));
         world.insert(PlacedTLoc(None));
+        world.insert(KeyboardFocus::default());
+        world.insert(OpenHandTab::default());
         world.insert(SelectedGame(None));
+        world.insert(ConnectionHealth::default());
+        world.insert(ShowDebugOverlay(false));
+        let settings = Settings::load();
+        world.insert(settings.token_palette);
+        world.insert(AnimationClock { now: js_sys::Date::now(), enabled: !settings.reduce_motion });
 
-        world.create_entity()
-            .with(Collider::new(&document().get_element_by_id("rotate_ccw").expect("Missing rotate ccw button")))
-            .with(ButtonAction::Rotation{ num_times: -1 })
-            .with(KeyLabel("KeyE".to_owned()))
-            .build();
-
-        world.create_entity()
-            .with(Collider::new(&document().get_element_by_id("rotate_cw").expect("Missing rotate cw button")))
-            .with(ButtonAction::Rotation{ num_times: 1 })
-            .with(KeyLabel("KeyR".to_owned()))
-            .build();
+        for (id, action) in [
+            ("rotate_ccw", ButtonAction::Rotation{ num_times: -1 }),
+            ("rotate_cw", ButtonAction::Rotation{ num_times: 1 }),
+            ("reflect", ButtonAction::Reflection),
+        ] {
+            let key = settings.keybindings.key(&action).to_owned();
+            world.create_entity()
+                .with(Collider::new(&document().get_element_by_id(id).expect("Missing tile button")))
+                .with(action)
+                .with(KeyLabel(key))
+                .build();
+        }
+        render::set_button_keys(&settings.keybindings);
 
         let dispatcher = DispatcherBuilder::new()
             .with(ColliderInputSystem, "collider_input", &[])
             .with(KeyboardInputSystem, "keyboard_input", &[])
-            .with(PlaceTokenSystem, "place_token", &["collider_input", "keyboard_input"])
-            .with(PlaceTileSystem, "place_tile", &["collider_input", "keyboard_input"])
+            .with(CameraSystem, "camera", &["collider_input"])
+            .with(KeyboardPlaceSystem, "keyboard_place", &["collider_input", "keyboard_input"])
+            .with(PlaceTokenSystem, "place_token", &["collider_input", "keyboard_input", "keyboard_place"])
+            .with(PlaceTileSystem, "place_tile", &["collider_input", "keyboard_input", "keyboard_place"])
             .with(SelectTileSystem, "select_tile", &["collider_input", "keyboard_input"])
             .with(SelectGameSystem, "select_game", &["collider_input", "keyboard_input"])
+            .with(ToggleDebugOverlaySystem, "toggle_debug_overlay", &["keyboard_input"])
+            .with(HandTabSystem, "hand_tab", &["collider_input", "keyboard_input"])
             .build();
 
         let render_dispatcher = DispatcherBuilder::new()
             .with(SvgOrderSystem, "svg_order", &[])
+            .with(TileSelectRenderSystem, "tile_select_render", &[])
+            .with(HandTabRenderSystem, "hand_tab_render", &[])
+            .with(HighlightRenderSystem, "highlight_render", &[])
+            .with(AmbientAnimationSystem::default(), "ambient_animation", &[])
             .with(TransformSystem::new(&world), "transform", &[])
+            .with(ConnectionStatusSystem::default(), "connection_status", &[])
+            .with(DebugOverlaySystem, "debug_overlay", &["svg_order", "transform", "tile_select_render"])
             .build();
 
         let start_game_entity = world.create_entity()
             .with(Collider::new(&document().get_element_by_id("start_game").unwrap()))
             .build();
 
+        let add_bot_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("add_bot").unwrap()))
+            .build();
+
+        let shuffle_seats_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("shuffle_seats").unwrap()))
+            .build();
+
+        let set_color_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("set_color").unwrap()))
+            .build();
+
+        render::render_emote_buttons();
+        let emote_entities = Emote::iter()
+            .map(|emote| (emote, world.create_entity()
+                .with(Collider::new(&document().get_element_by_id(&format!("emote_{}", emote)).unwrap()))
+                .build()))
+            .collect();
+
+        let copy_invite_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("copy_invite").unwrap()))
+            .build();
+
         let leave_game_entity = world.create_entity()
             .with(Collider::new(&document().get_element_by_id("leave_game").unwrap()))
             .build();
 
+        let exchange_hand_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("exchange_hand").unwrap()))
+            .build();
+
+        let scout_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("scout_pile").unwrap()))
+            .build();
+
+        let hint_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("hint").unwrap()))
+            .build();
+
+        let request_undo_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("request_undo").unwrap()))
+            .build();
+
+        let undo_accept_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("undo_accept").unwrap()))
+            .build();
+
+        let undo_decline_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("undo_decline").unwrap()))
+            .build();
+
+        let pause_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("pause_game").unwrap()))
+            .build();
+
+        let play_offline_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("play_offline").unwrap()))
+            .build();
+
+        let play_hotseat_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("play_hotseat").unwrap()))
+            .build();
+
+        let pass_device_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("pass_device_ready").unwrap()))
+            .build();
+
+        let play_tutorial_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("play_tutorial").unwrap()))
+            .build();
+
+        let tutorial_next_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("tutorial_next").unwrap()))
+            .build();
+
+        let analyze_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("analyze_game").unwrap()))
+            .build();
+
+        let rematch_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("rematch").unwrap()))
+            .build();
+
+        let analysis_back_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("analysis_back").unwrap()))
+            .build();
+
+        let analysis_forward_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("analysis_forward").unwrap()))
+            .build();
+
+        let analysis_main_line_entity = world.create_entity()
+            .with(Collider::new(&document().get_element_by_id("analysis_main_line").unwrap()))
+            .build();
+
         Self {
-            state: Some(app::EnterUsername::default().into()),
+            state: Some(app::EnterUsername::new(username, join).into()),
             world,
             id_counter: 0,
             start_game_entity,
+            add_bot_entity,
+            shuffle_seats_entity,
+            set_color_entity,
+            emote_entities,
+            copy_invite_entity,
             leave_game_entity,
+            exchange_hand_entity,
+            scout_entity,
+            hint_entity,
+            request_undo_entity,
+            undo_accept_entity,
+            undo_decline_entity,
+            pause_entity,
+            play_offline_entity,
+            play_hotseat_entity,
+            pass_device_entity,
+            play_tutorial_entity,
+            tutorial_next_entity,
+            analyze_entity,
+            rematch_entity,
+            analysis_back_entity,
+            analysis_forward_entity,
+            analysis_main_line_entity,
             dispatcher,
             render_dispatcher,
         }
@@ -126,12 +288,98 @@ impl GameWorld {
             .expect("State is missing")
             .update(self, &mut requests));
 
+        self.world.write_resource::<AnimationClock>().now = js_sys::Date::now();
         self.render_dispatcher.dispatch(&self.world);
 
         requests
     }
 
+    /// Records that the connection to the server opened
+    pub fn connection_opened(&mut self) {
+        self.world.write_resource::<ConnectionHealth>().heard(js_sys::Date::now());
+    }
+
+    /// Records that the connection to the server closed
+    pub fn connection_closed(&mut self) {
+        self.world.write_resource::<ConnectionHealth>().closed();
+    }
+
+    /// Marks the connection as unresponsive if the server has been quiet for too long
+    pub fn check_connection(&mut self) {
+        self.world.write_resource::<ConnectionHealth>().check(js_sys::Date::now());
+    }
+
+    /// Switches to another token palette, drawing the tokens on screen again
+    pub fn set_token_palette(&mut self, palette: TokenPalette) {
+        self.world.insert(palette);
+        let mut state = self.state.take().expect("State is missing");
+        state.redraw_tokens(self);
+        self.state = Some(state);
+    }
+
+    /// Turns the ambient animations on or off
+    pub fn set_animations(&mut self, enabled: bool) {
+        self.world.write_resource::<AnimationClock>().enabled = enabled;
+    }
+
+    /// Switches the keys for the tile buttons
+    pub fn set_keybindings(&mut self, keybindings: &Keybindings) {
+        let actions = self.world.read_component::<ButtonAction>();
+        let mut keys = self.world.write_component::<KeyLabel>();
+        for (action, key) in (&actions, &mut keys).join() {
+            key.0 = keybindings.key(action).to_owned();
+        }
+        render::set_button_keys(keybindings);
+    }
+
+    /// Starts watching a replay, unless the user is busy with a game
+    pub fn watch_replay(&mut self, replay: Replay) {
+        // Replays don't keep the options, and spectators can't use any of them anyway
+        let state = self.state.take().expect("State is missing");
+        self.state = Some(app::ReplayViewer::start(state, replay, GamePreset::Casual.options(), self));
+    }
+
+    /// Jumps the replay being watched to right after entry `entry` of the move list.
+    /// Does nothing if the user isn't watching a replay.
+    pub fn jump_replay(&mut self, entry: usize) {
+        let mut state = self.state.take().expect("State is missing");
+        if let app::AppState::ReplayViewer(viewer) = &mut state {
+            viewer.jump(entry, self);
+        }
+        self.state = Some(state);
+    }
+
+    /// Starts solving a puzzle, unless the user is busy with a game
+    pub fn play_puzzle(&mut self, puzzle: Puzzle) {
+        let state = self.state.take().expect("State is missing");
+        self.state = Some(app::LocalGame::start_puzzle(state, puzzle, self));
+    }
+
+    /// Starts watching the replay in a game archive, unless the user is busy with a game
+    pub fn watch_archive(&mut self, archive: GameArchive) {
+        let state = self.state.take().expect("State is missing");
+        self.state = Some(app::ReplayViewer::start(state, archive.replay().clone(), archive.options().clone(), self));
+    }
+
     pub fn handle_response(&mut self, response: Response) -> Vec<Request> {
+        self.world.write_resource::<ConnectionHealth>().heard(js_sys::Date::now());
+        match response {
+            Response::Pong => return vec![],
+            Response::Replay{ replay: Some(replay), .. } => {
+                self.watch_replay(replay);
+                return vec![];
+            }
+            Response::History{ games } => {
+                history::show(&games);
+                return vec![];
+            }
+            Response::Daily{ day, results } => {
+                daily::show(day, &results);
+                return vec![];
+            }
+            _ => {}
+        }
+
         let mut requests = vec![];
 
         self.state = Some(self.state.take()
@@ -1,13 +1,23 @@
-use common::{board::{BasePort, BaseTLoc}, game_state::BaseGameState, message::{Request, Response}, player_state::{Looker}, tile::{BaseGAct, BaseKind, BaseTile}, game::GameId, GameInstance, math::Pt2};
+use std::{collections::{BTreeMap, VecDeque}, time::Duration};
+
+use common::{board::{BasePort, BaseTLoc}, clock::Clocks, game_state::{BaseGameState, BaseTileMove, BaseTurnEvent, WinCondition}, message::{Request, Response, SkipReason}, player_state::{Looker}, tile::{BaseGAct, BaseKind, BaseTile}, game::{GameId, MatchScore}, GameInstance, LobbyGame, math::Pt2};
 use format_xml::{spaced, xml};
 use itertools::{Itertools, chain};
 use specs::prelude::*;
 use enum_dispatch::enum_dispatch;
 use common::game::BaseGame;
+use common::config::NumPlayers;
+use common::options::{Avatar, BotDifficulty, Emote, GameOptions, GamePreset, TokenColor};
+use common::archive::GameArchive;
+use common::puzzle::{self, Puzzle, PuzzleStatus};
+use common::notation;
+use common::replay::{Replay, ReplayLink, ReplayMove};
+use wasm_bindgen::JsCast;
+use web_sys::HtmlSelectElement;
 
 
 
-use crate::{SVG_NS, document, ecs::{Model, TileSelect, Transform, Collider, TokenSlot, PortLabel, TokenToPlace, RunSelectGameSystem, SelectedGame}, render::{self, BaseBoardExt, BaseTileExt, TOKEN_RADIUS, BaseGameExt, ScreenState}, window};
+use crate::{SVG_NS, document, export, i18n, move_list, notes::PlayerNotes, prompt_username, settings, stats::GameStats, tile_count, tutorial::{self, Wait}, ecs::{Camera, Model, OpenHandTab, TileSelect, Transform, Collider, TokenSlot, PortLabel, TokenToPlace, RunSelectGameSystem, SelectedGame, SelectedTile, Pulse}, render::{self, BaseBoardExt, BaseTileExt, TOKEN_RADIUS, BaseGameExt, ScreenState, TokenPalette}};
 
 use super::GameWorld;
 use gameplay::GameplayStateT;
@@ -15,13 +25,102 @@ use gameplay::GameplayStateT;
 /// Initial state. Must enter a username.
 #[derive(Debug, Default)]
 pub struct EnterUsername {
-    usernames: Vec<String>,
+    /// Username waiting for the server to accept it
+    username: String,
+    /// Game to join once in the lobby, from an invite link
+    join: Option<GameId>,
 }
 
 /// User is in the lobby
 #[derive(Debug)]
 pub struct Lobby {
-    game_entities: Vec<(GameId, Entity)>,
+    game_entities: BTreeMap<GameId, Entity>,
+    /// Latest version of each game that changed since the last frame.
+    /// Games get rendered again once per frame, however many changes came in for them.
+    changed_games: BTreeMap<GameId, LobbyGame>,
+}
+
+/// User is playing a game without the server, against AI opponents or with other people on the same device,
+/// or solving a puzzle against AI opponents.
+/// The game runs on the same states as online games, with requests answered locally instead of by the server.
+/// When someone else at the device has to move, the device gets passed to them with the hands hidden in the meantime.
+#[derive(Debug)]
+pub struct LocalGame {
+    game: BaseGame,
+    /// The full state, looked at by the server
+    state: BaseGameState,
+    player_usernames: Vec<String>,
+    /// Options of the preset picked in the lobby, or the ones the puzzle or tutorial is played with
+    options: GameOptions,
+    /// Whether each player is an AI opponent instead of someone at the device
+    ai_players: Vec<bool>,
+    /// Who's at the device
+    looker: Looker,
+    /// The game as whoever's at the device sees it, like in an online game.
+    /// None while the device is being passed.
+    client_game: Option<Game>,
+    /// The lobby to go back to, kept up to date in the meantime
+    lobby: Lobby,
+    /// When the AI opponents get to act next, in milliseconds since the epoch
+    next_ai_action: f64,
+    /// The puzzle being solved, if any, and the number of tiles the user placed in it so far
+    puzzle: Option<(Puzzle, u32)>,
+    /// Moves written down before the device was last passed, so whoever gets it next sees them too
+    moves: Vec<String>,
+}
+
+/// User is going through the tutorial: a local game against an AI opponent, with a hint for each step
+/// and steps that wait for the user to do what the hint says
+#[derive(Debug)]
+pub struct Tutorial {
+    local_game: LocalGame,
+    /// Index of the step in `tutorial::STEPS` the user is on
+    step: usize,
+    /// Number of tiles the user placed so far
+    user_tiles: u32,
+    /// Number of tiles on the board and whose turn it was as of the last frame, to tell who placed new tiles
+    last_seen: (usize, u32),
+    /// Number of tiles the user placed and the action of the tile they held when the step started,
+    /// to tell when they place or rotate a tile
+    step_start: (u32, Option<BaseGAct>),
+}
+
+/// User is watching the replay of a finished game, one move at a time.
+/// The moves get shown the same way a spectator would see them online.
+#[derive(Debug)]
+pub struct ReplayViewer {
+    replay: Replay,
+    /// The replay's moves in move notation, paired with their index in the replay
+    notated: Vec<(usize, String)>,
+    /// The full state after the moves played so far
+    state: BaseGameState,
+    /// Number of moves played so far
+    num_played: usize,
+    /// The game as a spectator sees it
+    client_game: Game,
+    /// The lobby to go back to, kept up to date in the meantime
+    lobby: Lobby,
+    /// When the next move gets played, in milliseconds since the epoch
+    next_move: f64,
+}
+
+/// User is looking back over a finished game. They can step through its moves, and from any point
+/// place tiles for whoever's turn it is to see how the game would have gone instead.
+/// Everything happens on a copy of the game the server never hears about.
+#[derive(Debug)]
+pub struct Analysis {
+    replay: Replay,
+    options: GameOptions,
+    /// Number of the game's moves played so far
+    num_played: usize,
+    /// Tile placements tried out after those moves, in order
+    tried: Vec<BaseTileMove>,
+    /// The full state after the moves played and tried out so far
+    state: BaseGameState,
+    /// The game as whoever's turn it is sees it, so the user can place a tile for them
+    client_game: Game,
+    /// The lobby to go back to, kept up to date in the meantime
+    lobby: Lobby,
 }
 
 /// User is waiting to join a game.
@@ -29,7 +128,7 @@ pub struct Lobby {
 #[derive(Debug)]
 pub struct WaitJoinGame {
     id: GameId,
-    game_entities: Vec<(GameId, Entity)>,
+    game_entities: BTreeMap<GameId, Entity>,
 }
 
 /// User is in a game that hasn't started yet
@@ -38,6 +137,14 @@ pub struct StatelessGame {
     id: GameId,
     game: BaseGame,
     player_usernames: Vec<String>,
+    spectator_usernames: Vec<String>,
+    options: GameOptions,
+    /// None unless the game is a match of several rounds
+    match_score: Option<MatchScore>,
+    /// The token color each player picked. None for players who didn't pick one.
+    colors: Vec<Option<TokenColor>>,
+    /// Each player's avatar. None for players who didn't pick one.
+    avatars: Vec<Option<Avatar>>,
     board_entity: Entity,
 }
 
@@ -48,14 +155,53 @@ pub struct Game {
     pub(crate) game: BaseGame,
     pub(crate) state: BaseGameState,
     pub(crate) player_usernames: Vec<String>,
+    pub(crate) spectator_usernames: Vec<String>,
+    pub(crate) options: GameOptions,
+    /// None unless the game is a match of several rounds
+    pub(crate) match_score: Option<MatchScore>,
+    /// The token color each player picked. None for players who didn't pick one.
+    pub(crate) colors: Vec<Option<TokenColor>>,
+    /// Each player's avatar. None for players who didn't pick one.
+    pub(crate) avatars: Vec<Option<Avatar>>,
+    /// Whether each player is connected to the server
+    pub(crate) player_connected: Vec<bool>,
+    /// Whether each player has picked what to do this round, in games where everyone picks at once
+    pub(crate) committed: Vec<bool>,
+    /// How each player has been doing, since the user started watching
+    pub(crate) stats: GameStats,
     pub(crate) board_entity: Entity,
     /// An token entity for each player.
     /// None if the player didn't place their token yet
     pub(crate) token_entities: Vec<Option<Entity>>,
     /// Entites for tiles in the player's hand 
     pub(crate) tile_hand_entities: Vec<Entity>,
+    /// A hand tab entity for each kind of tile in the player's hand, in order of kind
+    pub(crate) hand_tab_entities: Vec<Entity>,
     /// Tiles on the board
     pub(crate) board_tile_entities: Vec<Entity>,
+    /// A trail entity for each player, showing where their token has been.
+    /// None if the token hasn't moved yet
+    pub(crate) trail_entities: Vec<Option<Entity>>,
+    /// See-through tokens showing where players would end up if the held tile got placed
+    pub(crate) preview_token_entities: Vec<Entity>,
+    /// How many of the user's moves got rejected since one last went through
+    pub(crate) rejections_in_a_row: u32,
+    /// The game's replay once it's over and the server saved it, for analyzing the game
+    pub(crate) replay: Option<Replay>,
+    /// Every move written down so far this round, in move notation
+    pub(crate) moves: Vec<String>,
+    /// The player who placed the last tile, for asking to take it back.
+    /// None if nothing got placed since the user got the game.
+    pub(crate) last_tile_placer: Option<u32>,
+    /// The player asking to take back the last tile placement, while everyone else decides
+    pub(crate) undo_requester: Option<u32>,
+    /// Each player's clock as the server last sent it, and when that was in milliseconds.
+    /// None if the game has no clocks or they haven't started yet.
+    pub(crate) clocks: Option<(Clocks, f64)>,
+    /// Whether the game is paused, so nobody can move
+    pub(crate) paused: bool,
+    /// The emote each player last sent and when it arrived in milliseconds, while it's still showing
+    pub(crate) emotes: Vec<Option<(Emote, f64)>>,
     /// None if this is being edited
     pub(crate) gameplay_state: Option<gameplay::State>,
 }
@@ -67,24 +213,43 @@ pub trait AppStateT {
     fn handle_response(self, world: &mut GameWorld, response: Response, requests: &mut Vec<Request>) -> AppState;
 }
 
+impl EnterUsername {
+    /// Waits for the server to accept `username`, then joins game `join` if there is one
+    pub fn new(username: String, join: Option<GameId>) -> Self {
+        Self { username, join }
+    }
+}
+
 impl AppStateT for EnterUsername {
-    fn update(self, _world: &mut GameWorld, _requests: &mut Vec<Request>) -> AppState {
-        self.into()
+    fn update(self, world: &mut GameWorld, _requests: &mut Vec<Request>) -> AppState {
+        // The server might not be around at all, so the lobby gets filled in if it ever shows up
+        match LocalGame::start_if_clicked(Lobby{ game_entities: BTreeMap::new(), changed_games: BTreeMap::new() }, world) {
+            Ok(state) => state,
+            Err(_) => self.into(),
+        }
     }
 
     fn handle_response(self, world: &mut GameWorld, response: Response, requests: &mut Vec<Request>) -> AppState {
         match response {
             Response::JoinedLobby{ games } => {
-                Lobby::new(games, world).into()
+                // Next time, the user joins with the same username without being asked
+                settings::save_username(self.username);
+                let lobby = Lobby::new(games, world);
+                // Invited users go straight to the game
+                if let Some(id) = self.join {
+                    world.world.get_mut::<RunSelectGameSystem>().unwrap().0 = false;
+                    requests.push(Request::JoinGame{ id });
+                    WaitJoinGame{ id, game_entities: lobby.game_entities }.into()
+                } else {
+                    lobby.into()
+                }
             }
 
-            Response::RejectedUsername => {
-                let username = window().prompt_with_message("Enter a username. The one you entered is already taken.")
-                    .unwrap_or(None)
-                    .unwrap_or_else(|| "Guest".to_owned());
+            Response::RejectedUsername{ reason } => {
+                let username = prompt_username(&format!("{} Enter a username.", i18n::reject_reason(reason)));
                 render::set_username(&username);
-                requests.push(Request::SetUsername{ username });
-                self.into()
+                requests.push(Request::SetUsername{ username: username.clone() });
+                Self::new(username, self.join).into()
             }
 
             _ => self.into()
@@ -93,47 +258,93 @@ impl AppStateT for EnterUsername {
 }
 
 impl AppStateT for Lobby {
-    fn update(self, world: &mut GameWorld, requests: &mut Vec<Request>) -> AppState {
+    fn update(mut self, world: &mut GameWorld, requests: &mut Vec<Request>) -> AppState {
+        self.show_changed_games(world);
+        let lobby = match LocalGame::start_if_clicked(self, world) {
+            Ok(state) => {
+                world.world.get_mut::<RunSelectGameSystem>().unwrap().0 = false;
+                return state;
+            }
+            Err(lobby) => lobby,
+        };
+
         world.world.get_mut::<RunSelectGameSystem>().unwrap().0 = true;
         if let Some(id) = world.world.get_mut::<SelectedGame>().unwrap().0.take() {
             world.world.get_mut::<RunSelectGameSystem>().unwrap().0 = false;
             requests.push(Request::JoinGame{ id });
-            WaitJoinGame{ id, game_entities: self.game_entities }.into()
+            WaitJoinGame{ id, game_entities: lobby.game_entities }.into()
         } else {
-            self.into()
+            lobby.into()
         }
     }
 
     fn handle_response(mut self, world: &mut GameWorld, response: Response, _requests: &mut Vec<Request>) -> AppState {
         match response {
             Response::ChangedGame { game } => {
-                match self.game_entities.binary_search_by_key(&game.id(), |(id, _)| *id) {
-                    Ok(pos) => {
-                        world.world.delete_entity(self.game_entities[pos].1).ok();
-                        self.game_entities[pos].1 = render::game_entity(game, &mut world.world, &mut world.id_counter);
-                    }
+                self.changed_games.insert(game.id(), game);
+                self.into()
+            }
 
-                    Err(pos) => {
-                        let id = game.id();
-                        let entity = render::game_entity(game, &mut world.world, &mut world.id_counter);
-                        self.game_entities.insert(pos, (id, entity));
-                    }
+            Response::RemovedGame{ id } => {
+                self.changed_games.remove(&id);
+                if let Some(entity) = self.game_entities.remove(&id) {
+                    world.world.delete_entity(entity).ok();
                 }
                 self.into()
             }
 
+            Response::JoinedLobby{ games } => {
+                // Only happens if the user got to the lobby some other way first, like by playing offline
+                self.game_entities.values().for_each(|entity| {
+                    world.world.delete_entity(*entity).ok();
+                });
+                self.changed_games.clear();
+                self.game_entities = games.into_iter().map(|game| (
+                    game.id(), render::game_entity(game, &mut world.world, &mut world.id_counter)
+                )).collect();
+                self.into()
+            }
+
+            // The server puts the user in the daily challenge as soon as they ask for it
+            Response::JoinedGame{ game } => {
+                self.game_entities.into_values().for_each(|entity| {
+                    world.world.delete_entity(entity).ok();
+                });
+                Game::app_state(game, world)
+            }
+
             _ => self.into()
         }
     }
 }
 
 impl Lobby {
-    fn new(games: Vec<GameInstance>, world: &mut GameWorld) -> Self {
+    fn new(games: Vec<LobbyGame>, world: &mut GameWorld) -> Self {
         render::set_screen_state(ScreenState::Lobby);
         Self {
             game_entities: games.into_iter().map(|game| (
                 game.id(), render::game_entity(game, &mut world.world, &mut world.id_counter)
-            )).collect()
+            )).collect(),
+            changed_games: BTreeMap::new(),
+        }
+    }
+
+    /// Renders the games that changed since the last frame again
+    fn show_changed_games(&mut self, world: &mut GameWorld) {
+        for (id, game) in std::mem::take(&mut self.changed_games) {
+            match self.game_entities.get_mut(&id) {
+                Some(entity) => {
+                    if let Err(game) = render::update_game_entity(*entity, game, &mut world.world) {
+                        world.world.delete_entity(*entity).ok();
+                        *entity = render::game_entity(game, &mut world.world, &mut world.id_counter);
+                    }
+                }
+
+                None => {
+                    let entity = render::game_entity(game, &mut world.world, &mut world.id_counter);
+                    self.game_entities.insert(id, entity);
+                }
+            }
         }
     }
 }
@@ -143,20 +354,20 @@ impl AppStateT for WaitJoinGame {
         self.into()
     }
 
-    fn handle_response(mut self, world: &mut GameWorld, response: Response, _requests: &mut Vec<Request>) -> AppState {
+    fn handle_response(self, world: &mut GameWorld, response: Response, _requests: &mut Vec<Request>) -> AppState {
         match response {
             Response::JoinedGame { game } => {
                 if self.id == game.id() {
-                    self.game_entities.drain(..).for_each(|(_, entity)| {
+                    self.game_entities.into_values().for_each(|entity| {
                         world.world.delete_entity(entity).ok();
                     });
                     Game::app_state(game, world)
                 } else { self.into() }
             }
 
-            Response::Rejected{ id } => {
+            Response::Rejected{ id, .. } => {
                 if self.id == id {
-                    Lobby{ game_entities: self.game_entities }.into()
+                    Lobby{ game_entities: self.game_entities, changed_games: BTreeMap::new() }.into()
                 } else { self.into() }
             }
 
@@ -169,21 +380,53 @@ impl AppStateT for StatelessGame {
     fn update(self, world: &mut GameWorld, requests: &mut Vec<Request>) -> AppState {
         if world.world.read_component::<Collider>().get(world.start_game_entity).unwrap().clicked() {
             requests.push(Request::StartGame{ id: self.id });
+        } else if world.world.read_component::<Collider>().get(world.add_bot_entity).unwrap().clicked() {
+            let difficulty = document().get_element_by_id("bot_difficulty").unwrap()
+                .dyn_into::<HtmlSelectElement>().expect("Bot difficulty dropdown isn't a select element")
+                .value().parse().unwrap_or(BotDifficulty::Medium);
+            requests.push(Request::AddBot{ id: self.id, difficulty });
+        } else if world.world.read_component::<Collider>().get(world.shuffle_seats_entity).unwrap().clicked() {
+            requests.push(Request::ReorderPlayers{ id: self.id, order: self.shuffled_seats() });
+        } else if world.world.read_component::<Collider>().get(world.set_color_entity).unwrap().clicked() {
+            let color = document().get_element_by_id("token_color").unwrap()
+                .dyn_into::<HtmlSelectElement>().expect("Token color dropdown isn't a select element")
+                .value().parse();
+            if let Ok(color) = color {
+                requests.push(Request::SetColor{ id: self.id, color });
+            }
+        } else if world.world.read_component::<Collider>().get(world.copy_invite_entity).unwrap().clicked() {
+            render::copy_invite_link(self.id);
         } else if world.world.read_component::<Collider>().get(world.leave_game_entity).unwrap().clicked() {
-            requests.push(Request::JoinLobby);
+            requests.push(Request::LeaveGame{ id: self.id });
         }
         self.into()
     }
 
     fn handle_response(mut self, world: &mut GameWorld, response: Response, _requests: &mut Vec<Request>) ->AppState {
         match response {
-            Response::ChangedPlayers{ id, names } => {
+            Response::ChangedPlayers{ id, names, avatars } => {
                 if id == self.id {
-                    let names_str = names.iter()
-                        .map(|name| html_escape::encode_text(name))
+                    let notes = PlayerNotes::load();
+                    let names_str = names.iter().zip(&avatars)
+                        .map(|(name, avatar)| render::render_username_with_avatar(name, *avatar, &notes))
                         .join("<br>");
                     document().get_element_by_id("usernames").unwrap().set_inner_html(&names_str);
                     self.player_usernames = names;
+                    self.avatars = avatars;
+                }
+                self.into()
+            }
+
+            Response::ChangedSpectators{ id, names } => {
+                if id == self.id {
+                    self.spectator_usernames = names;
+                }
+                self.into()
+            }
+
+            Response::ChangedColors{ id, colors } => {
+                if id == self.id {
+                    self.colors = colors;
                 }
                 self.into()
             }
@@ -195,6 +438,9 @@ impl AppStateT for StatelessGame {
 
             Response::StartedGame{ id, state } => {
                 if id == self.id {
+                    if self.options.rounds() > 1 {
+                        self.match_score = Some(MatchScore::new(self.options.rounds(), state.num_players()));
+                    }
                     self.with_state(state, world).into()
                 } else {
                     self.into()
@@ -207,19 +453,34 @@ impl AppStateT for StatelessGame {
 }
 
 impl StatelessGame {
-    fn new(id: GameId, game: BaseGame, players: Vec<String>, world: &mut GameWorld) -> Self {
+    fn new(
+        id: GameId, game: BaseGame, players: Vec<String>, spectators: Vec<String>, options: GameOptions, match_score: Option<MatchScore>,
+        world: &mut GameWorld
+    ) -> Self {
         render::set_screen_state(ScreenState::StatelessGame);
         let board_svg = render::parse_svg(&game.board().render());
         let board_entity = world.world.create_entity()
             .with(Model::new(&board_svg, Model::ORDER_BOARD, &GameWorld::svg_root(), &mut world.id_counter))
             .build();
+        // Each game starts with the whole board in view, whatever its size
+        world.world.write_resource::<Camera>().set_home(game.board().bounds());
+
+        Self { id, game, player_usernames: players, spectator_usernames: spectators, options, match_score, colors: vec![], avatars: vec![], board_entity }
+    }
 
-        Self { id, game, player_usernames: players, board_entity }
+    /// A random order for the players to sit in
+    fn shuffled_seats(&self) -> Vec<u32> {
+        let mut order = (0..self.player_usernames.len() as u32).collect_vec();
+        for i in (1..order.len()).rev() {
+            let j = (js_sys::Math::random() * (i + 1) as f64) as usize;
+            order.swap(i, j);
+        }
+        order
     }
 
     fn with_state(self, state: BaseGameState, world: &mut GameWorld) -> Game {
         render::set_screen_state(ScreenState::Game);
-        let StatelessGame{ id, game, player_usernames, board_entity } = self;
+        let StatelessGame{ id, game, player_usernames, spectator_usernames, options, match_score, colors, avatars, board_entity } = self;
 
         let (tile_hand_entities, gameplay_state) = if let Looker::Player(player) = state.looker() {
             let tile_hand_entities = state.player_state(player)
@@ -235,9 +496,13 @@ impl StatelessGame {
                     &mut world.id_counter,
                 ))
                 .collect_vec();
+            let tiles = state.player_state(player).map_or(vec![], |state| state.tiles_vec());
+            render::set_reflection_available(tiles.iter()
+                .flat_map(|(_, tiles)| tiles)
+                .any(|tile| tile.reflection_action().is_some()));
                 
-            if state.all_players_placed() {
-                // Rejoined game
+            if state.all_players_placed() || state.player_state(player).is_none() {
+                // Rejoined game, or left it earlier and can only watch now
                 (tile_hand_entities, gameplay::WaitTurn.into())
             } else if state.board_state().player_port(player).is_some() {
                 // Rejoined game, already placed port
@@ -260,10 +525,13 @@ impl StatelessGame {
                             .build()
                     })
                     .collect_vec();
+                let palette = *world.world.read_resource::<TokenPalette>();
                 let token_entity = world.world.create_entity()
                     .with(Transform::new(Pt2::origin()))
                     .with(Model::new(
-                        &render::parse_svg(&render::render_token(player, state.num_players(), &mut world.id_counter)),
+                        &render::parse_svg(&render::render_token(
+                            player, state.num_players(), palette, colors.get(player as usize).copied().flatten(), &mut world.id_counter
+                        )),
                         Model::ORDER_PLAYER_TOKEN, 
                         &GameWorld::svg_root(), &mut world.id_counter
                     ))
@@ -274,6 +542,7 @@ impl StatelessGame {
             }
 
         } else {
+            render::set_reflection_available(false);
             (vec![], gameplay::WaitTurn.into())
         };
 
@@ -288,16 +557,39 @@ impl StatelessGame {
             game,
             state,
             player_usernames,
+            spectator_usernames,
+            options,
+            match_score,
+            colors,
+            avatars,
+            player_connected: vec![true; num_players as usize],
+            committed: vec![false; num_players as usize],
+            stats: GameStats::new(num_players),
             board_entity,
             token_entities: vec![None; num_players as usize],
             tile_hand_entities, 
+            hand_tab_entities: vec![],
             board_tile_entities: vec![],
+            trail_entities: vec![None; num_players as usize],
+            preview_token_entities: vec![],
+            rejections_in_a_row: 0,
+            replay: None,
+            moves: vec![],
+            last_tile_placer: None,
+            undo_requester: None,
+            clocks: None,
+            paused: false,
+            emotes: vec![None; num_players as usize],
             gameplay_state: Some(gameplay_state),
         };
 
         game_state.display_state(world);
+        game_state.update_hand_tabs(world);
+        game_state.show_pause();
+        render::set_emotes_available(game_state.on_server() && game_state.state.is_player());
 
-        // For spectators: add ports and tiles that have already been placed
+        // For spectators and rejoins: add ports and tiles that have already been placed,
+        // including tiles that were on the board from the start
         for (player, port) in ports.into_iter().enumerate() {
             if let Some(port) = port {
                 game_state.move_token(world, player as u32, &port);
@@ -317,31 +609,215 @@ impl AppStateT for Game {
             .expect("Missing gameplay state")
             .update(&mut self, world, requests));
         if world.world.read_component::<Collider>().get(world.leave_game_entity).unwrap().clicked() {
-            requests.push(Request::JoinLobby);
+            requests.push(Request::LeaveGame{ id: self.id });
+        }
+
+        // The analysis happens away from the server, so the user leaves the game for it
+        if self.replay.is_some() && world.world.read_component::<Collider>().get(world.analyze_entity).unwrap().clicked() {
+            requests.push(Request::LeaveGame{ id: self.id });
+            let replay = self.replay.take().expect("Replay was just checked");
+            let gameplay_state = self.gameplay_state.take().expect("Missing gameplay state");
+            gameplay::delete_entities(gameplay_state, &mut self, world);
+            self.delete_entities(world);
+            return Analysis::new(replay, self.options, world).into();
+        }
+
+        let clicked = |entity| world.world.read_component::<Collider>().get(entity).unwrap().clicked();
+        if self.can_request_undo() && clicked(world.request_undo_entity) {
+            requests.push(Request::RequestUndo{ id: self.id });
+        }
+        if self.undo_requester.is_some() {
+            let (accept, decline) = (clicked(world.undo_accept_entity), clicked(world.undo_decline_entity));
+            if accept || decline {
+                requests.push(Request::RespondUndo{ id: self.id, accept });
+                self.undo_requester = None;
+                self.show_undo();
+            }
+        }
+        if self.can_rematch() && clicked(world.rematch_entity) {
+            requests.push(Request::Rematch{ id: self.id });
         }
+        if self.can_vote_pause() && clicked(world.pause_entity) {
+            if self.paused {
+                requests.push(Request::ResumeGame{ id: self.id });
+                render::set_notice(i18n::RESUME_ASKED);
+            } else {
+                requests.push(Request::PauseGame{ id: self.id });
+                render::set_notice(i18n::PAUSE_ASKED);
+            }
+        }
+        for (emote, entity) in &world.emote_entities {
+            if clicked(*entity) {
+                requests.push(Request::Emote{ id: self.id, emote: *emote });
+            }
+        }
+        self.expire_emotes(world);
+        self.tick_clock();
         self.into()
     }
 
     fn handle_response(mut self, world: &mut GameWorld, response: Response, requests: &mut Vec<Request>) -> AppState {
         if let Response::JoinedLobby{ games } = response {
-            let to_delete = chain!(
-                [self.board_entity],
-                self.token_entities.drain(..).flatten(),
-                self.tile_hand_entities.drain(..),
-                self.board_tile_entities.drain(..),
-            ).collect_vec();
-
-            world.world.delete_entities(&to_delete).ok();
+            self.delete_entities(world);
             return Lobby::new(games, world).into();
         }
 
+        // The game as the server has it, in answer to a resync, or the rematch the user asked for
+        if let Response::JoinedGame{ game } = response {
+            let gameplay_state = self.gameplay_state.take().expect("Missing gameplay state");
+            gameplay::delete_entities(gameplay_state, &mut self, world);
+            self.delete_entities(world);
+            if game.id() == self.id {
+                render::set_notice(i18n::RESYNCED);
+            }
+            return Self::app_state(game, world);
+        }
+
+        // The next round of the match, on a fresh board
+        if let Response::StartedRound{ id, state, score } = response {
+            if id != self.id {
+                return self.into();
+            }
+            let gameplay_state = self.gameplay_state.take().expect("Missing gameplay state");
+            gameplay::delete_entities(gameplay_state, &mut self, world);
+            self.delete_entities(world);
+            render::set_notice(&i18n::round_started(score.round(), score.rounds()));
+            let game = GameInstance::new(
+                self.id, self.game, Some(state), self.player_usernames, self.spectator_usernames, self.options, Some(score), vec![], self.colors, self.avatars
+            );
+            return Self::app_state(game, world);
+        }
+
+        // Everyone let the last tile placement be taken back, so the board goes back to how it was before
+        if let Response::UndidTurn{ game } = response {
+            if game.id() != self.id {
+                return self.into();
+            }
+            let gameplay_state = self.gameplay_state.take().expect("Missing gameplay state");
+            gameplay::delete_entities(gameplay_state, &mut self, world);
+            self.delete_entities(world);
+            render::set_notice(i18n::UNDID_TURN);
+            return Self::app_state(game, world);
+        }
+        self.count_rejections(&response, requests);
+
+        // Any move makes a pending request to take back a tile placement stale
+        if matches!(&response,
+            Response::PlacedTile{ id, .. } | Response::ExchangedHand{ id, .. } | Response::SkippedTurn{ id, .. } |
+            Response::LeftGame{ id, .. } if *id == self.id)
+        {
+            self.undo_requester = None;
+        }
+
         match &response {
             Response::PlacedToken{ id, player, port } => if *id == self.id {
+                self.note_move(ReplayMove::PlaceToken{ player: *player, port: port.clone() }, None);
                 self.set_token_position(world, *player, port)
             },
 
-            Response::PlacedTile{ id, player, kind, index, action, loc } => if *id == self.id {
-                self.take_turn_placing_tile(world, *player, kind, *index, action, loc)
+            Response::PlacedTile{ id, player, kind, index, action, loc, tile, drawn } => if *id == self.id {
+                self.note_move(
+                    ReplayMove::PlaceTile{ player: *player, kind: kind.clone(), index: *index, action: action.clone(), loc: loc.clone() },
+                    Some(tile),
+                );
+                self.committed[*player as usize] = false;
+                self.last_tile_placer = Some(*player);
+                self.state.reveal_tiles(&[(*player, *index, tile.clone())]);
+                self.take_turn_placing_tile(world, kind, *index, action, loc, drawn)
+            }
+
+            Response::ExchangedHand{ id, player, drawn } => if *id == self.id {
+                self.note_move(ReplayMove::ExchangeHand{ player: *player }, None);
+                self.exchange_hand(world, *player, drawn)
+            }
+
+            // Only the scout gets this, and the tiles show up on top of the draw piles
+            Response::Scouted{ id, tiles } => if *id == self.id {
+                self.state.scout(self.state.player_expect());
+                self.state.reveal_top_tiles(tiles);
+                self.display_state(world);
+            }
+
+            Response::LeftGame{ id, player, drawn } => if *id == self.id {
+                self.note_move(ReplayMove::Leave{ player: *player }, None);
+                self.committed[*player as usize] = false;
+                self.remove_player(world, *player, drawn)
+            }
+
+            Response::ChangedSpectators{ id, names } => if *id == self.id {
+                self.spectator_usernames = names.clone();
+                self.display_state(world);
+            }
+
+            // Players can change their avatar during the game
+            Response::ChangedPlayers{ id, avatars, .. } => if *id == self.id {
+                self.avatars = avatars.clone();
+                self.display_state(world);
+            }
+
+            Response::PlayerConnection{ id, player, connected } => if *id == self.id {
+                self.player_connected[*player as usize] = *connected;
+                self.display_state(world);
+            }
+
+            Response::SkippedTurn{ id, player, reason } => if *id == self.id {
+                self.note_move(ReplayMove::SkipTurn{ player: *player, reason: *reason }, None);
+                self.committed[*player as usize] = false;
+                self.state.skip_turn();
+                self.display_state(world);
+                render::set_notice(&i18n::skipped_turn(&self.player_usernames[*player as usize], *reason));
+            }
+
+            Response::CommittedTile{ id, player } => if *id == self.id {
+                self.committed[*player as usize] = true;
+                self.display_state(world);
+                if Looker::Player(*player) == self.state.looker() {
+                    render::set_notice(i18n::WAITING_FOR_PICKS);
+                }
+            }
+
+            Response::RejectedAttempt{ id, player, reason } => if *id == self.id {
+                let username = player.map(|player| self.player_usernames[player as usize].as_str());
+                render::add_rejection_feed_entry(&i18n::rejected_attempt(username, *reason));
+            }
+
+            Response::ReplaySaved{ id, replay_id, replay } => if *id == self.id {
+                render::set_replay_link(Some(&ReplayLink::fragment(replay, *replay_id)));
+                export::set_archive_link(Some(&GameArchive::new(self.options.clone(), replay.clone())));
+                render::set_analysis_available(true);
+                self.replay = Some(replay.clone());
+                render::set_rematch_available(self.can_rematch());
+            }
+
+            Response::UndoRequested{ id, player } => if *id == self.id {
+                self.undo_requester = Some(*player);
+                if Looker::Player(*player) == self.state.looker() {
+                    render::set_notice(i18n::UNDO_ASKED);
+                }
+            }
+
+            Response::Clocks{ id, clocks } => if *id == self.id {
+                self.clocks = Some((clocks.clone(), js_sys::Date::now()));
+                self.display_state(world);
+            }
+
+            Response::Emoted{ id, player, emote } => if *id == self.id {
+                self.emotes[*player as usize] = Some((*emote, js_sys::Date::now()));
+                self.display_state(world);
+            }
+
+            Response::GamePaused{ id } => if *id == self.id {
+                self.paused = true;
+            }
+
+            Response::GameResumed{ id } => if *id == self.id {
+                self.paused = false;
+                render::set_notice(i18n::GAME_RESUMED);
+            }
+
+            Response::UndoDeclined{ id, player } => if *id == self.id {
+                self.undo_requester = None;
+                render::set_notice(&i18n::undo_declined(&self.player_usernames[*player as usize]));
             }
 
             _ => {}
@@ -351,6 +827,8 @@ impl AppStateT for Game {
         self.gameplay_state = Some(self.gameplay_state.take()
             .expect("Missing gameplay state")
             .handle_response(&mut self, world, response, requests));
+        self.show_undo();
+        self.show_pause();
         self.into()
     }
 }
@@ -358,15 +836,173 @@ impl AppStateT for Game {
 impl Game {
     /// Returns either an `StatelessGame` or a `Game` depending on whether the game has started.
     fn app_state(game: GameInstance, world: &mut GameWorld) -> AppState {
-        let (id, game, state, players) = game.into_fields();
-        let stateless = StatelessGame::new(id, game, players, world);
+        let (id, game, state, players, spectators, options, match_score, moves, colors, avatars) = game.into_fields();
+        let mut stateless = StatelessGame::new(id, game, players, spectators, options, match_score, world);
+        stateless.colors = colors;
+        stateless.avatars = avatars;
         if let Some(state) = state {
-            stateless.with_state(state, world).into()
+            let mut game = stateless.with_state(state, world);
+            game.moves = moves;
+            game.show_moves();
+            game.into()
         } else {
             stateless.into()
         }
     }
 
+    /// Keeps track of the user's moves getting rejected.
+    /// The client only sends moves it thinks are legal, so too many rejections in a row
+    /// mean it lost track of the game, and it asks the server for the game again.
+    fn count_rejections(&mut self, response: &Response, requests: &mut Vec<Request>) {
+        let (game_id, looker) = (self.id, self.state.looker());
+        let own = |id: &GameId, player: &u32| *id == game_id && Looker::Player(*player) == looker;
+        match response {
+            Response::Rejected{ id, .. } if *id == self.id => {
+                self.rejections_in_a_row += 1;
+                if self.rejections_in_a_row >= RESYNC_REJECTIONS {
+                    self.rejections_in_a_row = 0;
+                    requests.push(Request::Resync{ id: self.id });
+                }
+            }
+
+            Response::PlacedToken{ id, player, .. } |
+            Response::PlacedTile{ id, player, .. } |
+            Response::ExchangedHand{ id, player, .. } |
+            Response::CommittedTile{ id, player } if own(id, player) => self.rejections_in_a_row = 0,
+
+            Response::Scouted{ id, .. } if *id == self.id => self.rejections_in_a_row = 0,
+
+            _ => {}
+        }
+    }
+
+    /// Deletes the board and everything on it, for leaving the game
+    fn delete_entities(&mut self, world: &mut GameWorld) {
+        let to_delete = chain!(
+            [self.board_entity],
+            self.token_entities.drain(..).flatten(),
+            self.tile_hand_entities.drain(..),
+            self.hand_tab_entities.drain(..),
+            self.board_tile_entities.drain(..),
+            self.trail_entities.drain(..).flatten(),
+            self.preview_token_entities.drain(..),
+        ).collect_vec();
+
+        world.world.delete_entities(&to_delete).ok();
+        render::set_replay_link(None);
+        export::set_archive_link(None);
+        render::set_analysis_available(false);
+        render::set_rematch_available(false);
+        move_list::clear();
+        tile_count::clear();
+        render::set_hand_tabs_shown(false);
+        render::clear_rejection_feed();
+        render::set_hand_exchange_available(false);
+        render::set_scout_available(false);
+        render::set_undo_available(false);
+        render::set_undo_prompt(None);
+        render::set_pause_available(false, false);
+        render::set_paused(false);
+        render::set_emotes_available(false);
+    }
+
+    /// The token color `player` picked, if they did
+    fn color(&self, player: u32) -> Option<TokenColor> {
+        self.colors.get(player as usize).copied().flatten()
+    }
+
+    /// Whether the server hosts the game, as opposed to it being played or watched on the device
+    fn on_server(&self) -> bool {
+        ![LOCAL_GAME_ID, REPLAY_GAME_ID, ANALYSIS_GAME_ID].contains(&self.id)
+    }
+
+    /// Whether the user can ask for a rematch. They have to have played in the game, which has to be over and saved.
+    fn can_rematch(&self) -> bool {
+        self.on_server() && self.state.is_player() && self.replay.is_some()
+    }
+
+    /// Whether the user can ask to take back the last tile placement.
+    /// They have to have placed it, in a game where players take turns, and nobody can have moved since.
+    fn can_request_undo(&self) -> bool {
+        self.on_server() && !self.options.simultaneous() && self.undo_requester.is_none() &&
+            !self.state.game_over() && self.state.can_undo() &&
+            self.last_tile_placer.map_or(false, |player| Looker::Player(player) == self.state.looker())
+    }
+
+    /// Shows the button for asking to take back a tile placement if the user can,
+    /// and the prompt to answer someone else asking if there's one
+    fn show_undo(&self) {
+        render::set_undo_available(self.can_request_undo());
+        let prompt = self.undo_requester
+            .filter(|requester| self.state.is_player() && Looker::Player(*requester) != self.state.looker())
+            .map(|requester| i18n::undo_requested(&self.player_usernames[requester as usize]));
+        render::set_undo_prompt(prompt.as_deref());
+    }
+
+    /// How much time `player` has left on their clock. None if the game has no clocks or they haven't started yet.
+    fn clock_remaining(&self, player: u32) -> Option<Duration> {
+        self.clocks.as_ref().map(|(clocks, received)| {
+            let elapsed = Duration::from_secs_f64(((js_sys::Date::now() - received) / 1000.0).max(0.0));
+            clocks.remaining_after(player, elapsed)
+        })
+    }
+
+    /// Counts down the running clock in the state panel, if any
+    fn tick_clock(&self) {
+        if let Some(player) = self.clocks.as_ref().and_then(|(clocks, _)| clocks.running()) {
+            render::set_clock(player, self.clock_remaining(player).expect("Clocks were just checked"));
+        }
+    }
+
+    /// Takes down emotes that have been showing long enough
+    fn expire_emotes(&mut self, world: &mut GameWorld) {
+        let now = js_sys::Date::now();
+        let mut expired = false;
+        for emote in &mut self.emotes {
+            if emote.map_or(false, |(_, received)| now - received >= EMOTE_SHOWN_MS) {
+                *emote = None;
+                expired = true;
+            }
+        }
+        if expired {
+            self.display_state(world);
+        }
+    }
+
+    /// Whether the user can ask to pause the game, or to resume it if it's paused
+    fn can_vote_pause(&self) -> bool {
+        self.on_server() && self.state.is_player() && !self.state.game_over()
+    }
+
+    /// Shows the button for asking to pause or resume the game if the user can, and whether the game is paused
+    fn show_pause(&self) {
+        render::set_pause_available(self.can_vote_pause(), self.paused);
+        render::set_paused(self.paused);
+    }
+
+    /// Writes a move down in the move list. `tile` is the tile placed, for tile placements.
+    fn note_move(&mut self, mv: ReplayMove, tile: Option<&BaseTile>) {
+        if let Some(notation) = notation::notate(&self.game, &mv, tile) {
+            self.moves.push(notation);
+            self.show_moves();
+        }
+    }
+
+    /// Shows the moves written down so far in the move list, with the latest one marked
+    fn show_moves(&self) {
+        move_list::show(&self.moves, self.moves.len().checked_sub(1), false);
+    }
+
+    /// Whether the user can scout the draw piles this turn
+    pub fn can_scout(&self) -> bool {
+        self.options.scout() && self.state.can_scout(self.state.player_expect())
+    }
+
+    /// Whether the user can exchange their hand instead of placing a tile this turn
+    pub fn can_exchange_hand(&self) -> bool {
+        self.options.hand_exchange() && self.state.can_exchange_hand(self.state.player_expect())
+    }
+
     /// Moves a player token to some location.
     /// This does not care about `self.gameplay_state` and can be called with it being `None`.
     pub fn move_token(&mut self, world: &mut GameWorld, player: u32, port: &BasePort) {
@@ -378,15 +1014,96 @@ impl Game {
                 .expect("Expected token to exist since its ID is stored")
                 .position = position;
         } else {
+            let palette = *world.world.read_resource::<TokenPalette>();
             self.token_entities[player as usize] = Some(world.world.create_entity()
                 .with(Transform::new(position))
                 .with(Model::new(
-                    &render::parse_svg(&render::render_token(player, self.state.num_players(), &mut world.id_counter)),
+                    &render::parse_svg(&render::render_token(player, self.state.num_players(), palette, self.color(player), &mut world.id_counter)),
                     Model::ORDER_PLAYER_TOKEN, 
                     &GameWorld::svg_root(), &mut world.id_counter
                 ))
                 .build());
         }
+        self.draw_trail(world, player);
+    }
+
+    /// Draws the tokens, their trails, and the state panel again, after the token palette changed
+    pub fn redraw_tokens(&mut self, world: &mut GameWorld) {
+        let num_players = self.state.num_players();
+        if let Some(gameplay::GameplayState::PlaceToken(place)) = &self.gameplay_state {
+            let palette = *world.world.read_resource::<TokenPalette>();
+            let player = self.state.player_expect();
+            let svg = render::render_token(player, num_players, palette, self.color(player), &mut world.id_counter);
+            let model = Model::new(&render::parse_svg(&svg), Model::ORDER_PLAYER_TOKEN, &GameWorld::svg_root(), &mut world.id_counter);
+            world.world.write_component::<Model>().insert(place.token_entity, model).ok();
+        }
+
+        for player in 0..num_players {
+            if let Some(token) = self.token_entities[player as usize].take() {
+                world.world.delete_entity(token).expect("Entity deleted too early");
+                if let Some(port) = self.state.board_state().player_port(player) {
+                    self.move_token(world, player, &port);
+                }
+            }
+        }
+        self.display_state(world);
+    }
+
+    /// Redraws the trail of where a player's token has been, from the state.
+    /// This does not care about `self.gameplay_state` and can be called with it being `None`.
+    fn draw_trail(&mut self, world: &mut GameWorld, player: u32) {
+        if let Some(entity) = self.trail_entities[player as usize].take() {
+            world.world.delete_entity(entity).expect("Entity deleted too early");
+        }
+
+        let lines = self.game.board().trail_positions(&self.state.board_state().player_trail(player));
+        if !lines.is_empty() {
+            let palette = *world.world.read_resource::<TokenPalette>();
+            let svg = render::render_trail(player, self.state.num_players(), palette, self.color(player), &lines);
+            self.trail_entities[player as usize] = Some(world.world.create_entity()
+                .with(Model::new(&render::parse_svg(&svg), Model::ORDER_TRAIL, &GameWorld::svg_root(), &mut world.id_counter))
+                .build());
+        }
+    }
+
+    /// Shows where each token would end up if `tile` got placed at `loc`, replacing the previous preview.
+    /// Only tokens that would move get shown, and the ones that would die are highlighted.
+    /// Clears the preview if `placement` is None.
+    /// This does not care about `self.gameplay_state` and can be called with it being `None`.
+    pub fn preview_path(&mut self, world: &mut GameWorld, placement: Option<(&BaseTile, &BaseTLoc)>) {
+        world.world.delete_entities(&self.preview_token_entities).ok();
+        self.preview_token_entities.clear();
+
+        if let Some((tile, loc)) = placement {
+            let board = self.game.board();
+            let board_state = self.state.board_state();
+            let (preview, deaths) = board_state.preview_tile(&board, tile, loc);
+            let num_players = self.state.num_players();
+            let looker = self.state.player_expect();
+            let palette = *world.world.read_resource::<TokenPalette>();
+
+            for player in 0..num_players {
+                let dies = deaths.iter().any(|death| death.player() == player);
+                // Tokens that die on boards where dead tokens leave have no port, so show where their trail ends
+                let port = match preview.player_port(player) {
+                    Some(port) if board_state.player_port(player).as_ref() != Some(&port) => port,
+                    None if dies => match preview.player_trail(player).last() {
+                        Some(port) => port.clone(),
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                let svg = render::render_token_preview(player, num_players, palette, self.color(player), player == looker, dies, &mut world.id_counter);
+                self.preview_token_entities.push(world.world.create_entity()
+                    .with(Transform::new(board.port_position(&port)))
+                    .with(Model::new(
+                        &render::parse_svg(&svg),
+                        Model::ORDER_TOKEN_PREVIEW,
+                        &GameWorld::svg_root(), &mut world.id_counter
+                    ))
+                    .build());
+            }
+        }
     }
 
     /// Set the position of some player's token, editing the state.
@@ -409,14 +1126,33 @@ impl Game {
         self.board_tile_entities.push(board_tile_entity);
     }
 
-    pub fn take_turn_placing_tile(&mut self, world: &mut GameWorld, _player: u32, kind: &BaseKind, index: u32, action: &BaseGAct, loc: &BaseTLoc) {
+    /// Has the current player place a tile, then fills in the tiles drawn afterward,
+    /// given in (player, index, tile) format.
+    pub fn take_turn_placing_tile(&mut self, world: &mut GameWorld, kind: &BaseKind, index: u32, action: &BaseGAct, loc: &BaseTLoc,
+        drawn: &[(u32, u32, BaseTile)]
+    ) {
         let delta = self.state.take_turn_placing_tile(&self.game, kind, index, action, loc);
+        self.state.reveal_tiles(drawn);
+        self.stats.record_turn(&delta);
         self.display_state(world);
 
-        self.place_tile(world, &delta.tile_placed().1, loc);
-
-        for (player, port) in delta.player_ports().iter().enumerate() {
-            self.set_token_position(world, player as u32, port);
+        for event in delta.events() {
+            match event {
+                BaseTurnEvent::TilePlaced{ tile, loc, .. } => self.place_tile(world, tile, loc),
+                // Tokens that died coming off the board stay off it
+                BaseTurnEvent::PlayerMoved{ player, path } if self.state.board_state().player_port(*player).is_some() =>
+                    if let Some(port) = path.last() {
+                        self.set_token_position(world, *player, port);
+                    },
+                // The token left the board, but its trail still shows how it died
+                BaseTurnEvent::PlayerDied{ kill } if self.state.board_state().player_port(kill.victim()).is_none() => {
+                    if let Some(token) = self.token_entities[kill.victim() as usize].take() {
+                        world.world.delete_entity(token).expect("Entity deleted too early");
+                    }
+                    self.draw_trail(world, kill.victim());
+                }
+                _ => {}
+            }
         }
 
         if let Looker::Player(looker) = self.state.looker() {
@@ -451,33 +1187,113 @@ impl Game {
                 }
             }
 
-            // Add new tiles
-            for (player, index, tile) in delta.drawn_tiles() {
-                if *player == looker {
-                    let entity = tile.create_hand_entity(
-                        *index, 
-                        &tile.identity_action(),
-                        &mut world.world, 
-                        &mut world.id_counter
-                    );
-                    self.tile_hand_entities.push(entity);
-                }
+            self.add_drawn_tiles(world, looker, drawn);
+        }
+    }
+
+    /// Has the current player exchange their hand for the tiles in `drawn`, given in (player, index, tile) format
+    pub fn exchange_hand(&mut self, world: &mut GameWorld, player: u32, drawn: &[(u32, u32, BaseTile)]) {
+        self.state.take_turn_exchanging_hand();
+        self.state.reveal_tiles(drawn);
+        self.display_state(world);
+
+        if self.state.looker() == Looker::Player(player) {
+            world.world.delete_entities(&self.tile_hand_entities).expect("Entities deleted too early");
+            self.tile_hand_entities.clear();
+            self.add_drawn_tiles(world, player, drawn);
+        }
+    }
+
+    /// Adds hand entities for the tiles that `looker` drew, given in (player, index, tile) format.
+    fn add_drawn_tiles(&mut self, world: &mut GameWorld, looker: u32, drawn_tiles: &[(u32, u32, BaseTile)]) {
+        for (player, index, tile) in drawn_tiles {
+            if *player == looker {
+                let entity = tile.create_hand_entity(
+                    *index, 
+                    &tile.identity_action(),
+                    &mut world.world, 
+                    &mut world.id_counter
+                );
+                self.tile_hand_entities.push(entity);
             }
         }
+        self.update_hand_tabs(world);
+    }
+
+    /// Makes a hand tab for each kind of tile in the hand, with how many tiles of that kind there are
+    fn update_hand_tabs(&mut self, world: &mut GameWorld) {
+        world.world.delete_entities(&self.hand_tab_entities).expect("Entities deleted too early");
+        let counts = {
+            let storage = world.world.read_component::<TileSelect>();
+            self.tile_hand_entities.iter()
+                .map(|entity| storage.get(*entity).expect("Hand tile is missing TileSelect").kind().clone())
+                .counts()
+        };
+        self.hand_tab_entities = counts.into_iter()
+            .sorted()
+            .map(|(kind, count)| render::create_hand_tab_entity(&kind, count, &mut world.world, &mut world.id_counter))
+            .collect();
+        render::set_hand_tabs_shown(self.hand_tab_entities.len() > 1);
+        world.world.write_resource::<OpenHandTab>().refresh();
     }
 
-    fn display_player_state(&mut self, world: &mut GameWorld, player: u32, html_string: &mut String) {
-        let token = render::render_token(player, self.state.num_players(), &mut world.id_counter);
+    /// Removes a player who left the game, killing their token.
+    /// Their tiles got redistributed as `drawn`, given in (player, index, tile) format.
+    pub fn remove_player(&mut self, world: &mut GameWorld, player: u32, drawn: &[(u32, u32, BaseTile)]) {
+        self.state.remove_player(&self.game, player);
+        self.state.reveal_tiles(drawn);
+
+        if let Some(token) = self.token_entities[player as usize].take() {
+            world.world.delete_entity(token).expect("Entity deleted too early");
+        }
+
+        if let Looker::Player(looker) = self.state.looker() {
+            if looker == player {
+                world.world.delete_entities(&self.tile_hand_entities).expect("Entities deleted too early");
+                self.tile_hand_entities.clear();
+            }
+
+            self.add_drawn_tiles(world, looker, drawn);
+        }
+
+        self.display_state(world);
+    }
+
+    fn display_player_state(&mut self, world: &mut GameWorld, player: u32, notes: &PlayerNotes, html_string: &mut String) {
+        let palette = *world.world.read_resource::<TokenPalette>();
+        let token = render::render_token(player, self.state.num_players(), palette, self.color(player), &mut world.id_counter);
         let tile_svgs = self.state.player_state(player)
             .map(|state| state.tiles_vec())
             .into_iter()
             .flat_map(|tiles| tiles.into_iter().flat_map(|(_, tiles)| tiles))
-            .map(|tile| render::wrap_svg(&tile.render(), "state-tile"))
+            .map(|tile| render::wrap_svg(&tile.render(), "state-tile", tile.bounds()))
             .collect::<String>();
 
         let dead = self.state.player_state(player).is_none();
         let won = self.state.won(player);
-        let turn = self.state.turn_player() == player;
+        // When everyone picks at once, nobody in particular has the turn
+        let turn = self.state.turn_player() == player && !self.options.simultaneous();
+        let committed = self.committed[player as usize];
+        let connected = self.player_connected[player as usize];
+        let stats = self.stats.player(player);
+        // Scores only matter when they decide who wins
+        let score = if self.state.win_condition() == WinCondition::Scoring {
+            xml!(<div class="state-score">{self.state.scores()[player as usize]}" pts"</div>).to_string()
+        } else {
+            String::new()
+        };
+        // The round being played counts once it's over
+        let round_wins = self.match_score.as_ref().map_or(String::new(), |score| {
+            let wins = score.round_wins()[player as usize] + won as u32;
+            xml!(<div class="state-score">{wins}" won"</div>).to_string()
+        });
+        // Ticks down between updates from the server
+        let clock = self.clock_remaining(player).map_or(String::new(), |remaining| {
+            xml!(<div class="state-clock" id=("clock_"{player})>{render::format_clock(remaining)}</div>).to_string()
+        });
+        let emote = self.emotes[player as usize].map_or(String::new(), |(emote, _)| {
+            xml!(<div class="emote-bubble">{render::emote_emoji(emote)}</div>).to_string()
+        });
         let state_string = xml! {
             <div class="state">
                 <div class="state-top">
@@ -486,12 +1302,23 @@ impl Game {
                         width="20" height="20">{token}</svg>
                     </div>
                     <div class=("state-username"{if dead {"-dead"} else {""}})>{
-                        html_escape::encode_text(&self.player_usernames[player as usize])
+                        render::render_username_with_avatar(&self.player_usernames[player as usize], self.avatars.get(player as usize).copied().flatten(), notes)
                     }</div>
                     if (won) { <div class="state-winner">"WIN"</div> }
                     if (turn && !self.state.game_over()) { <div class="state-winner">"TURN"</div> }
+                    if (committed) { <div class="state-winner">"READY"</div> }
+                    if (!connected && !dead) { <div class="state-winner">"OFFLINE"</div> }
+                    {score}
+                    {round_wins}
+                    {clock}
+                    {emote}
                 </div>
                 <div class="state-tiles">{tile_svgs}</div>
+                <div class="state-stats">
+                    <div>"Tiles: "{stats.tiles_placed()}</div>
+                    <div>"Distance: "{stats.distance()}</div>
+                    <div>"Kills: "{stats.kills()}</div>
+                </div>
                 <div class="state-separator"></div>
             </div>
         }.to_string();
@@ -501,11 +1328,19 @@ impl Game {
     /// Displays the state of the game in the state panel.
     pub fn display_state(&mut self, world: &mut GameWorld) {
         let state_panel = document().get_element_by_id("state_panel").expect("Missing state panel");
+        let state_list = document().get_element_by_id("state_list").expect("Missing state list");
 
         let mut html_string = String::new();
+        let notes = PlayerNotes::load();
+
+        if let Some(score) = &self.match_score {
+            html_string += &xml! {
+                <div class="state-round">"Round "{score.round()}" of "{score.rounds()}</div>
+            }.to_string();
+        }
 
         for player in 0..self.state.num_players() {
-            self.display_player_state(world, player, &mut html_string);
+            self.display_player_state(world, player, &notes, &mut html_string);
         }
 
         let draw_pile_svgs = self.state.num_tiles_left_by_kind().into_iter()
@@ -514,9 +1349,10 @@ impl Game {
                 let representative = self.state.top_tile_left_of_kind(&kind)
                     .expect("Must have at least 1 tile in the pile");
 
-                let tile_svg = render::wrap_svg(&representative.render(), "state-draw-tile");
+                let tile_svg = render::wrap_svg(&representative.render(), "state-draw-tile", representative.bounds());
+                let class = if num_tiles <= LOW_DRAW_PILE_TILES { "state-draw-pile state-draw-pile-low" } else { "state-draw-pile" };
                 xml!(
-                    <div class="state-draw-pile">
+                    <div class={class}>
                         {tile_svg}
                         <div class="state-draw-count">{num_tiles}</div>
                     </div>
@@ -528,8 +1364,747 @@ impl Game {
             <div class="state-draw-piles">{draw_pile_svgs}</div>
         }.to_string();
 
-        state_panel.set_inner_html(&html_string);
+        if !self.spectator_usernames.is_empty() {
+            let names = self.spectator_usernames.iter()
+                .take(MAX_SHOWN_SPECTATORS)
+                .map(|name| render::render_username(name, &notes))
+                .join(", ");
+            let more = if self.spectator_usernames.len() > MAX_SHOWN_SPECTATORS { ", …" } else { "" };
+            html_string += &xml! {
+                <div class="state-spectators">"Watching: "{names}{more}" ("{self.spectator_usernames.len()}")"</div>
+            }.to_string();
+        }
+
+        state_list.set_inner_html(&html_string);
+        tile_count::show(&self.game, &self.state);
         state_panel.remove_attribute("style").expect("Failed to show state panel"); // remove the hiding attribute
+
+        // The turn player's token pulses, or when everyone picks at once, the tokens of everyone still picking
+        let mut pulses = world.world.write_component::<Pulse>();
+        pulses.clear();
+        if self.state.all_players_placed() && !self.state.game_over() {
+            let pulsing = if self.options.simultaneous() {
+                (0..self.state.num_players())
+                    .filter(|player| self.state.player_state(*player).is_some() && !self.committed[*player as usize])
+                    .collect_vec()
+            } else {
+                vec![self.state.turn_player()]
+            };
+            for player in pulsing {
+                if let Some(entity) = self.token_entities[player as usize] {
+                    pulses.insert(entity, Pulse).expect("Token entity was deleted");
+                }
+            }
+        }
+    }
+}
+
+/// Number of AI opponents in an offline game
+const OFFLINE_NUM_OPPONENTS: u32 = 3;
+/// How long AI opponents wait before each action, in milliseconds, so the user can follow along
+const LOCAL_AI_DELAY_MS: f64 = 500.0;
+/// The id of local games. The server never hears about them, so any id works.
+const LOCAL_GAME_ID: GameId = GameId(u64::MAX);
+/// How long each move of a replay stays on screen before the next one, in milliseconds
+const REPLAY_MOVE_DELAY_MS: f64 = 700.0;
+/// The id of games watched as replays. Like local games, the server never hears about them.
+const REPLAY_GAME_ID: GameId = GameId(u64::MAX - 1);
+/// The id of games being analyzed. Like local games, the server never hears about them.
+const ANALYSIS_GAME_ID: GameId = GameId(u64::MAX - 2);
+/// Draw piles with this many tiles or fewer left shimmer to say they're running low
+const LOW_DRAW_PILE_TILES: u32 = 5;
+/// Most spectators named in the state panel. The rest only count towards the total.
+const MAX_SHOWN_SPECTATORS: usize = 5;
+/// How many of the user's moves in a row can get rejected before the client asks for the game again
+const RESYNC_REJECTIONS: u32 = 3;
+/// How long an emote shows next to whoever sent it
+const EMOTE_SHOWN_MS: f64 = 4000.0;
+
+/// Picks a tile placement for an AI opponent, preferring ones that don't kill it.
+/// None if it has no tiles to place.
+fn choose_ai_move(game: &BaseGame, state: &BaseGameState, player: u32) -> Option<BaseTileMove> {
+    let moves = state.legal_moves(game, player);
+    let survives = |(kind, index, action, loc): &&BaseTileMove| !state.clone()
+        .take_turn_placing_tile(game, kind, *index, action, loc)
+        .dead_players().contains(&player);
+    moves.iter().find(survives).or_else(|| moves.first()).cloned()
+}
+
+/// Picks a free start port for an AI opponent, spread out by player index
+fn choose_ai_start_port(game: &BaseGame, state: &BaseGameState, player: u32) -> Option<BasePort> {
+    let free = game.start_ports().into_iter()
+        .filter(|port| state.clone().can_place_player(game, port))
+        .collect_vec();
+    free.get(player as usize * free.len() / state.num_players() as usize).cloned()
+}
+
+impl AppStateT for LocalGame {
+    fn update(mut self, world: &mut GameWorld, _requests: &mut Vec<Request>) -> AppState {
+        let mut local_requests = vec![];
+        if let Some(client_game) = self.client_game.take() {
+            self.client_game = Some(match client_game.update(world, &mut local_requests) {
+                AppState::Game(game) => game,
+                _ => unreachable!("Games only change state when the server says so"),
+            });
+        } else if world.world.read_component::<Collider>().get(world.pass_device_entity).unwrap().clicked() {
+            self.show(world, &mut local_requests);
+        }
+
+        let now = js_sys::Date::now();
+        if now >= self.next_ai_action {
+            self.next_ai_action = now + LOCAL_AI_DELAY_MS;
+            for response in self.ai_action() {
+                self.receive(world, response, &mut local_requests);
+            }
+            self.update_looker(world, &mut local_requests);
+        }
+
+        self.answer(world, local_requests)
+    }
+
+    fn handle_response(mut self, world: &mut GameWorld, response: Response, requests: &mut Vec<Request>) -> AppState {
+        // The server doesn't know about this game, but the lobby should be up to date when the user goes back to it
+        self.lobby = match self.lobby.handle_response(world, response, requests) {
+            AppState::Lobby(lobby) => lobby,
+            _ => unreachable!("The lobby only changes state when the user does something"),
+        };
+        self.into()
+    }
+}
+
+impl LocalGame {
+    /// Starts a local game, or the tutorial, if the user clicked one of the buttons for it in the lobby.
+    /// Gives the lobby back otherwise.
+    fn start_if_clicked(lobby: Lobby, world: &mut GameWorld) -> Result<AppState, Lobby> {
+        let clicked = |entity| world.world.read_component::<Collider>().get(entity).unwrap().clicked();
+        let (offline, hotseat, tutorial) =
+            (clicked(world.play_offline_entity), clicked(world.play_hotseat_entity), clicked(world.play_tutorial_entity));
+        let preset = || document().get_element_by_id("preset").unwrap()
+            .dyn_into::<HtmlSelectElement>().expect("Preset dropdown isn't a select element")
+            .value().parse().unwrap_or(GamePreset::Standard);
+
+        if offline {
+            let usernames = chain!(["You".to_owned()], (1..=OFFLINE_NUM_OPPONENTS).map(|i| format!("AI {}", i))).collect();
+            let ai_players = (0..=OFFLINE_NUM_OPPONENTS).map(|player| player != 0).collect();
+            Ok(Self::new(usernames, ai_players, preset().options(), lobby, world).into())
+        } else if hotseat {
+            let num_players = document().get_element_by_id("hotseat_players").unwrap()
+                .dyn_into::<HtmlSelectElement>().expect("Hotseat players dropdown isn't a select element")
+                .value().parse().ok().and_then(|num| NumPlayers::new(num).ok()).unwrap_or_default().get() as usize;
+            let usernames = (1..=num_players).map(|i| format!("Player {}", i)).collect();
+            Ok(Self::new(usernames, vec![false; num_players], preset().options(), lobby, world).into())
+        } else if tutorial {
+            Ok(Tutorial::new(lobby, world).into())
+        } else {
+            Err(lobby)
+        }
+    }
+
+    /// Sets up a local game played with some options
+    fn new(player_usernames: Vec<String>, ai_players: Vec<bool>, options: GameOptions, lobby: Lobby, world: &mut GameWorld) -> Self {
+        let game = options.game();
+        let state = game.new_state(player_usernames.len() as u32);
+
+        let mut local_game = Self {
+            game,
+            state,
+            player_usernames,
+            options,
+            ai_players,
+            looker: Looker::Spectator,
+            client_game: None,
+            lobby,
+            next_ai_action: 0.0,
+            puzzle: None,
+            moves: vec![],
+        };
+        local_game.update_looker(world, &mut vec![]);
+        local_game
+    }
+
+    /// Starts solving a puzzle if the user is in the lobby or hasn't gotten there yet.
+    /// Gives the state back unchanged if they're busy with a game.
+    pub fn start_puzzle(state: AppState, puzzle: Puzzle, world: &mut GameWorld) -> AppState {
+        let lobby = match state {
+            AppState::EnterUsername(_) => Lobby{ game_entities: BTreeMap::new(), changed_games: BTreeMap::new() },
+            AppState::Lobby(lobby) => lobby,
+            state => return state,
+        };
+        world.world.get_mut::<RunSelectGameSystem>().unwrap().0 = false;
+        render::set_notice(&i18n::puzzle_goal(&puzzle));
+
+        let player = puzzle.player();
+        let mut local_game = Self {
+            game: puzzle.game().clone(),
+            state: puzzle.state().clone(),
+            player_usernames: puzzle.usernames().clone(),
+            options: puzzle.options().clone(),
+            ai_players: (0..puzzle.state().num_players()).map(|other| other != player).collect(),
+            // The user gets to look at the puzzle even if it starts on someone else's turn
+            looker: Looker::Player(player),
+            client_game: None,
+            lobby,
+            next_ai_action: 0.0,
+            puzzle: Some((puzzle, 0)),
+            moves: vec![],
+        };
+        local_game.show(world, &mut vec![]);
+        local_game.into()
+    }
+
+    /// Whether the puzzle being solved got solved or failed. False if there isn't one.
+    fn puzzle_over(&self) -> bool {
+        self.puzzle.as_ref().map_or(false, |(puzzle, turns_taken)| puzzle.status(&self.state, *turns_taken) != PuzzleStatus::Unsolved)
+    }
+
+    /// The person at the device who has to move next.
+    /// None if nobody there has to, because it's up to the AI opponents or the game is over.
+    fn next_human(&self) -> Option<u32> {
+        let human = |player: &u32| !self.ai_players[*player as usize] && self.state.player_state(*player).is_some();
+        if !self.state.all_players_placed() {
+            (0..self.state.num_players()).filter(human)
+                .find(|player| self.state.board_state().player_port(*player).is_none())
+        } else if !self.state.game_over() {
+            Some(self.state.turn_player()).filter(human)
+        } else {
+            None
+        }
+    }
+
+    /// Gives the device to whoever has to move next if it's someone else,
+    /// hiding the game until they're ready if there's more than one person playing on it
+    fn update_looker(&mut self, world: &mut GameWorld, requests: &mut Vec<Request>) {
+        let next = match self.next_human() {
+            Some(player) if Looker::Player(player) != self.looker => player,
+            _ => return,
+        };
+
+        self.looker = Looker::Player(next);
+        if let Some(mut client_game) = self.client_game.take() {
+            self.moves = std::mem::take(&mut client_game.moves);
+            client_game.delete_entities(world);
+        }
+        if self.ai_players.iter().filter(|ai| !**ai).count() > 1 {
+            render::set_pass_device_username(&self.player_usernames[next as usize]);
+            render::set_screen_state(ScreenState::PassDevice);
+        } else {
+            self.show(world, requests);
+        }
+    }
+
+    /// Shows the game to whoever's at the device
+    fn show(&mut self, world: &mut GameWorld, requests: &mut Vec<Request>) {
+        let mut client_game = StatelessGame::new(LOCAL_GAME_ID, self.game.clone(), self.player_usernames.clone(), vec![], self.options.clone(), None, world)
+            .with_state(self.state.visible_state(self.looker).redacted(), world);
+        client_game.moves = self.moves.clone();
+        client_game.show_moves();
+        self.client_game = Some(client_game);
+        if let Some(response) = self.your_turn() {
+            self.receive(world, response, requests);
+        }
+    }
+
+    /// Has the side of whoever's at the device handle a response as if the server sent it.
+    /// Dropped while the device is being passed.
+    fn receive(&mut self, world: &mut GameWorld, response: Response, requests: &mut Vec<Request>) {
+        if let Some(client_game) = self.client_game.take() {
+            self.client_game = Some(match client_game.handle_response(world, response, requests) {
+                AppState::Game(game) => game,
+                _ => unreachable!("Local games never send the user to the lobby"),
+            });
+        }
+    }
+
+    /// Answers requests from the side of whoever's at the device the way the server would,
+    /// including requests caused by the answers, then passes the device on if needed.
+    /// Goes back to the lobby if the user left.
+    fn answer(mut self, world: &mut GameWorld, requests: Vec<Request>) -> AppState {
+        let mut requests = VecDeque::from(requests);
+        while let Some(request) = requests.pop_front() {
+            let responses = match request {
+                Request::PlaceToken{ player, port, .. } if Looker::Player(player) == self.looker =>
+                    self.place_token(player, port),
+                Request::PlaceTile{ player, kind, index, action, loc, .. } if Looker::Player(player) == self.looker =>
+                    self.place_tile(player, (kind, index, action, loc)),
+                Request::LeaveGame{ .. } => return self.leave(world),
+                _ => vec![],
+            };
+
+            let mut new_requests = vec![];
+            for response in responses {
+                self.receive(world, response, &mut new_requests);
+            }
+            self.update_looker(world, &mut new_requests);
+            requests.extend(new_requests);
+        }
+        self.into()
+    }
+
+    /// Throws the game away and goes back to the lobby
+    fn leave(mut self, world: &mut GameWorld) -> AppState {
+        if let Some(mut client_game) = self.client_game.take() {
+            client_game.delete_entities(world);
+        }
+        render::set_screen_state(ScreenState::Lobby);
+        self.lobby.into()
+    }
+
+    /// Skips the turns of players who can't move, the way the server would,
+    /// then tells whoever's at the device it's their turn if it is
+    fn next_turn(&mut self) -> Vec<Response> {
+        let mut responses = vec![];
+        while self.state.all_players_placed() && !self.state.game_over() && self.state.turn_player_stuck(&self.game) {
+            let player = self.state.turn_player();
+            self.state.skip_turn();
+            responses.push(Response::SkippedTurn{ id: LOCAL_GAME_ID, player, reason: SkipReason::NoLegalMove });
+        }
+        responses.extend(self.your_turn());
+        responses
+    }
+
+    /// Tells whoever's at the device it's their turn if it is
+    fn your_turn(&self) -> Option<Response> {
+        (self.state.all_players_placed() && !self.state.game_over() && !self.puzzle_over() &&
+            Looker::Player(self.state.turn_player()) == self.looker)
+            .then_some(Response::YourTurn{ id: LOCAL_GAME_ID })
+    }
+
+    /// Places a player's token if it can go there.
+    /// Returns the responses the server would send whoever's at the device.
+    fn place_token(&mut self, player: u32, port: BasePort) -> Vec<Response> {
+        let id = LOCAL_GAME_ID;
+        if let Err(reason) = self.state.check_place_player(&self.game, player, &port) {
+            return vec![Response::Rejected{ id, reason }];
+        }
+
+        self.state.place_player(player, &port);
+        let all_placed = self.state.all_players_placed();
+        chain!(
+            [Response::PlacedToken{ id, player, port }],
+            all_placed.then_some(Response::AllPlacedTokens{ id }),
+            self.next_turn(),
+        ).collect()
+    }
+
+    /// Has a player place a tile if it's their turn and the placement is legal.
+    /// Returns the responses the server would send whoever's at the device.
+    fn place_tile(&mut self, player: u32, (kind, index, action, loc): BaseTileMove) -> Vec<Response> {
+        let id = LOCAL_GAME_ID;
+        if let Err(reason) = self.state.check_place_tile(&self.game, player, &kind, index, &action, &loc) {
+            return vec![Response::Rejected{ id, reason }];
+        }
+
+        let tile = self.state.hand_tile(player, &kind, index).expect("Tile was checked to exist");
+        let result = self.state.take_turn_placing_tile(&self.game, &kind, index, &action, &loc);
+        if let Some((puzzle, turns_taken)) = &mut self.puzzle {
+            if player == puzzle.player() {
+                *turns_taken += 1;
+            }
+            match puzzle.status(&self.state, *turns_taken) {
+                PuzzleStatus::Solved => render::set_notice(i18n::PUZZLE_SOLVED),
+                PuzzleStatus::Failed => render::set_notice(i18n::PUZZLE_FAILED),
+                PuzzleStatus::Unsolved => {}
+            }
+        }
+        // Whoever's at the device only gets to see the tiles they drew
+        let drawn = result.drawn_tiles().iter().map(|(player, index, tile)| {
+            let tile = if Looker::Player(*player) == self.looker { tile.clone().with_visible(true) } else { tile.redacted() };
+            (*player, *index, tile)
+        }).collect();
+
+        chain!(
+            [Response::PlacedTile{ id, player, kind, index, action, loc, tile, drawn }],
+            self.next_turn(),
+        ).collect()
+    }
+
+    /// Has the next AI opponent that has something to do do it.
+    /// Returns the responses the server would send whoever's at the device.
+    fn ai_action(&mut self) -> Vec<Response> {
+        // Once a puzzle is over, everyone stops where they are
+        if self.puzzle_over() {
+            return vec![];
+        }
+        let unplaced = (0..self.state.num_players()).find(|player| self.ai_players[*player as usize] &&
+            self.state.player_state(*player).is_some() &&
+            self.state.board_state().player_port(*player).is_none());
+        let turn_player = self.state.turn_player();
+
+        if let Some(player) = unplaced {
+            choose_ai_start_port(&self.game, &self.state, player)
+                .map_or(vec![], |port| self.place_token(player, port))
+        } else if self.state.all_players_placed() && !self.state.game_over() && self.ai_players[turn_player as usize] {
+            // Opponents in puzzles move the way they do wherever the puzzle gets played
+            let mv = if self.puzzle.is_some() {
+                puzzle::opponent_move(&self.game, &self.state, turn_player)
+            } else {
+                choose_ai_move(&self.game, &self.state, turn_player)
+            };
+            match mv {
+                Some(mv) => self.place_tile(turn_player, mv),
+                None => {
+                    self.state.skip_turn();
+                    chain!(
+                        [Response::SkippedTurn{ id: LOCAL_GAME_ID, player: turn_player, reason: SkipReason::NoLegalMove }],
+                        self.next_turn(),
+                    ).collect()
+                }
+            }
+        } else {
+            vec![]
+        }
+    }
+}
+
+impl AppStateT for ReplayViewer {
+    fn update(mut self, world: &mut GameWorld, _requests: &mut Vec<Request>) -> AppState {
+        let mut local_requests = vec![];
+        self.client_game = match self.client_game.update(world, &mut local_requests) {
+            AppState::Game(game) => game,
+            _ => unreachable!("Games only change state when the server says so"),
+        };
+        // Spectators can only ask to leave
+        if local_requests.iter().any(|req| matches!(req, Request::LeaveGame{ .. })) {
+            self.client_game.delete_entities(world);
+            render::set_screen_state(ScreenState::Lobby);
+            return self.lobby.into();
+        }
+
+        let now = js_sys::Date::now();
+        if now >= self.next_move {
+            self.next_move = now + REPLAY_MOVE_DELAY_MS;
+            let responses = self.play_next_move();
+            if !responses.is_empty() {
+                for response in responses {
+                    self.client_game = match self.client_game.handle_response(world, response, &mut vec![]) {
+                        AppState::Game(game) => game,
+                        _ => unreachable!("Replays never send the user to the lobby"),
+                    };
+                }
+                self.show_moves();
+            }
+        }
+        self.into()
+    }
+
+    fn handle_response(mut self, world: &mut GameWorld, response: Response, requests: &mut Vec<Request>) -> AppState {
+        // The server doesn't know about the replay, but the lobby should be up to date when the user goes back to it
+        self.lobby = match self.lobby.handle_response(world, response, requests) {
+            AppState::Lobby(lobby) => lobby,
+            _ => unreachable!("The lobby only changes state when the user does something"),
+        };
+        self.into()
+    }
+}
+
+impl ReplayViewer {
+    /// Starts watching a replay if the user is in the lobby or hasn't gotten there yet.
+    /// Gives the state back unchanged if they're busy with a game.
+    pub fn start(state: AppState, replay: Replay, options: GameOptions, world: &mut GameWorld) -> AppState {
+        let lobby = match state {
+            AppState::EnterUsername(_) => Lobby{ game_entities: BTreeMap::new(), changed_games: BTreeMap::new() },
+            AppState::Lobby(lobby) => lobby,
+            state => return state,
+        };
+        world.world.get_mut::<RunSelectGameSystem>().unwrap().0 = false;
+
+        let state = replay.initial_state().clone();
+        let client_game = StatelessGame::new(REPLAY_GAME_ID, replay.game().clone(), replay.usernames().clone(), vec![], options, None, world)
+            .with_state(state.visible_state(Looker::Spectator), world);
+        let notated = notation::notate_replay(&replay);
+        let viewer = Self { replay, notated, state, num_played: 0, client_game, lobby, next_move: 0.0 };
+        viewer.show_moves();
+        viewer.into()
+    }
+
+    /// Shows every move of the replay in the move list, with the last one played marked
+    fn show_moves(&self) {
+        let moves = self.notated.iter().map(|(_, mv)| mv.clone()).collect_vec();
+        let current = self.notated.iter().rposition(|(i, _)| *i < self.num_played);
+        move_list::show(&moves, current, true);
+    }
+
+    /// Jumps to right after entry `entry` of the move list, and keeps playing from there
+    pub fn jump(&mut self, entry: usize, world: &mut GameWorld) {
+        let num_played = match self.notated.get(entry) {
+            Some((i, _)) => i + 1,
+            None => return,
+        };
+        let gameplay_state = self.client_game.gameplay_state.take().expect("Missing gameplay state");
+        gameplay::delete_entities(gameplay_state, &mut self.client_game, world);
+        self.client_game.delete_entities(world);
+
+        self.state = self.replay.initial_state().clone();
+        for mv in &self.replay.moves()[..num_played] {
+            mv.apply(self.replay.game(), &mut self.state);
+        }
+        self.num_played = num_played;
+        let options = self.client_game.options.clone();
+        self.client_game = StatelessGame::new(REPLAY_GAME_ID, self.replay.game().clone(), self.replay.usernames().clone(), vec![], options, None, world)
+            .with_state(self.state.visible_state(Looker::Spectator), world);
+        self.next_move = js_sys::Date::now() + REPLAY_MOVE_DELAY_MS;
+        self.show_moves();
+    }
+
+    /// Plays the next move, if there's one left.
+    /// Returns the responses the server would have sent a spectator.
+    fn play_next_move(&mut self) -> Vec<Response> {
+        let id = REPLAY_GAME_ID;
+        let mv = match self.replay.moves().get(self.num_played) {
+            Some(mv) => mv.clone(),
+            None => return vec![],
+        };
+        self.num_played += 1;
+
+        let was_all_placed = self.state.all_players_placed();
+        let tile = match &mv {
+            ReplayMove::PlaceTile{ player, kind, index, .. } => self.state.hand_tile(*player, kind, *index),
+            _ => None,
+        };
+        // Spectators get to see every tile drawn
+        let drawn = mv.apply(self.replay.game(), &mut self.state).into_iter()
+            .map(|(player, index, tile)| (player, index, tile.with_visible(true)))
+            .collect();
+        let newly_all_placed = self.state.all_players_placed() && !was_all_placed;
+
+        let response = match mv {
+            ReplayMove::PlaceToken{ player, port } => Some(Response::PlacedToken{ id, player, port }),
+            ReplayMove::PlaceTile{ player, kind, index, action, loc } => Some(Response::PlacedTile{
+                id, player, kind, index, action, loc, drawn,
+                tile: tile.expect("Replay placed a tile that wasn't in the hand"),
+            }),
+            ReplayMove::ExchangeHand{ player } => Some(Response::ExchangedHand{ id, player, drawn }),
+            // Spectators never find out about scouting
+            ReplayMove::Scout{ .. } => None,
+            ReplayMove::SkipTurn{ player, reason } => Some(Response::SkippedTurn{ id, player, reason }),
+            ReplayMove::Leave{ player } => Some(Response::LeftGame{ id, player, drawn }),
+        };
+        chain!(response, newly_all_placed.then_some(Response::AllPlacedTokens{ id })).collect()
+    }
+}
+
+impl AppStateT for Analysis {
+    fn update(mut self, world: &mut GameWorld, _requests: &mut Vec<Request>) -> AppState {
+        let mut local_requests = vec![];
+        self.client_game = match self.client_game.update(world, &mut local_requests) {
+            AppState::Game(game) => game,
+            _ => unreachable!("Games only change state when the server says so"),
+        };
+
+        let mut changed = false;
+        for request in local_requests {
+            match request {
+                Request::LeaveGame{ .. } => return self.leave(world),
+                Request::PlaceTile{ player, kind, index, action, loc, .. } |
+                Request::CommitTile{ player, kind, index, action, loc, .. } =>
+                    match self.state.check_place_tile(self.replay.game(), player, &kind, index, &action, &loc) {
+                        Ok(()) => {
+                            self.tried.push((kind, index, action, loc));
+                            changed = true;
+                        }
+                        Err(reason) => {
+                            let response = Response::Rejected{ id: ANALYSIS_GAME_ID, reason };
+                            self.client_game = match self.client_game.handle_response(world, response, &mut vec![]) {
+                                AppState::Game(game) => game,
+                                _ => unreachable!("Analyses never send the user to the lobby"),
+                            };
+                        }
+                    },
+                _ => {}
+            }
+        }
+
+        let clicked = |entity| world.world.read_component::<Collider>().get(entity).unwrap().clicked();
+        let (back, forward, main_line) =
+            (clicked(world.analysis_back_entity), clicked(world.analysis_forward_entity), clicked(world.analysis_main_line_entity));
+        // Going back undoes the placements tried out before any of the game's own moves
+        if back && (!self.tried.is_empty() || self.num_played > 0) {
+            if self.tried.pop().is_none() {
+                self.num_played -= 1;
+            }
+            changed = true;
+        } else if forward && self.tried.is_empty() && self.num_played < self.replay.moves().len() {
+            self.num_played += 1;
+            changed = true;
+        } else if main_line && !self.tried.is_empty() {
+            self.tried.clear();
+            changed = true;
+        }
+
+        if changed {
+            self.show(world);
+        }
+        self.into()
+    }
+
+    fn handle_response(mut self, world: &mut GameWorld, response: Response, requests: &mut Vec<Request>) -> AppState {
+        // The server doesn't know about the analysis, but the lobby should be up to date when the user goes back to it
+        self.lobby = match self.lobby.handle_response(world, response, requests) {
+            AppState::Lobby(lobby) => lobby,
+            _ => unreachable!("The lobby only changes state when the user does something"),
+        };
+        self.into()
+    }
+}
+
+impl Analysis {
+    /// Starts analyzing a finished game from its last move.
+    /// The lobby gets filled in once the server says the user left the game.
+    fn new(replay: Replay, options: GameOptions, world: &mut GameWorld) -> Self {
+        let num_played = replay.moves().len();
+        let state = replay.final_state();
+        let moves = notation::notate_replay(&replay).into_iter().map(|(_, mv)| mv).collect();
+        let client_game = Self::turn_view(&replay, &options, &state, moves, world);
+        let lobby = Lobby{ game_entities: BTreeMap::new(), changed_games: BTreeMap::new() };
+        let analysis = Self { replay, options, num_played, tried: vec![], state, client_game, lobby };
+        analysis.show_position();
+        analysis
+    }
+
+    /// The full state after the moves played and tried out so far, and those moves in move notation
+    fn position(&self) -> (BaseGameState, Vec<String>) {
+        let game = self.replay.game();
+        let mut state = self.replay.initial_state().clone();
+        for mv in &self.replay.moves()[..self.num_played] {
+            mv.apply(game, &mut state);
+        }
+        let mut moves = notation::notate_replay(&self.replay).into_iter()
+            .filter(|(i, _)| *i < self.num_played)
+            .map(|(_, mv)| mv)
+            .collect_vec();
+
+        for (kind, index, action, loc) in &self.tried {
+            let player = state.turn_player();
+            let tile = state.hand_tile(player, kind, *index);
+            let mv = ReplayMove::PlaceTile{ player, kind: kind.clone(), index: *index, action: action.clone(), loc: loc.clone() };
+            moves.extend(notation::notate(game, &mv, tile.as_ref()));
+            state.take_turn_placing_tile(game, kind, *index, action, loc);
+            // Players who can't move get skipped, the way the server would
+            while state.all_players_placed() && !state.game_over() && state.turn_player_stuck(game) {
+                let mv = ReplayMove::SkipTurn{ player: state.turn_player(), reason: SkipReason::NoLegalMove };
+                moves.extend(notation::notate(game, &mv, None));
+                state.skip_turn();
+            }
+        }
+        (state, moves)
+    }
+
+    /// The game as whoever's turn it is sees it after `moves`, ready for the user to place a tile for them.
+    /// Seen as a spectator before every token is placed and once the game is over.
+    fn turn_view(replay: &Replay, options: &GameOptions, state: &BaseGameState, moves: Vec<String>, world: &mut GameWorld) -> Game {
+        let playing = state.all_players_placed() && !state.game_over();
+        let looker = if playing { Looker::Player(state.turn_player()) } else { Looker::Spectator };
+        let mut client_game = StatelessGame::new(ANALYSIS_GAME_ID, replay.game().clone(), replay.usernames().clone(), vec![], options.clone(), None, world)
+            .with_state(state.visible_state(looker).redacted(), world);
+        client_game.moves = moves;
+        client_game.show_moves();
+        if !playing {
+            return client_game;
+        }
+        match client_game.handle_response(world, Response::YourTurn{ id: ANALYSIS_GAME_ID }, &mut vec![]) {
+            AppState::Game(game) => game,
+            _ => unreachable!("Analyses never send the user to the lobby"),
+        }
+    }
+
+    /// Shows the game at the position the analysis is at now
+    fn show(&mut self, world: &mut GameWorld) {
+        let gameplay_state = self.client_game.gameplay_state.take().expect("Missing gameplay state");
+        gameplay::delete_entities(gameplay_state, &mut self.client_game, world);
+        self.client_game.delete_entities(world);
+        let (state, moves) = self.position();
+        self.state = state;
+        self.client_game = Self::turn_view(&self.replay, &self.options, &self.state, moves, world);
+        self.show_position();
+    }
+
+    fn show_position(&self) {
+        let position = i18n::analysis_position(self.num_played, self.replay.moves().len(), self.tried.len());
+        render::set_analysis_position(Some(&position));
+    }
+
+    /// Stops analyzing and goes back to the lobby
+    fn leave(mut self, world: &mut GameWorld) -> AppState {
+        let gameplay_state = self.client_game.gameplay_state.take().expect("Missing gameplay state");
+        gameplay::delete_entities(gameplay_state, &mut self.client_game, world);
+        self.client_game.delete_entities(world);
+        render::set_analysis_position(None);
+        render::set_screen_state(ScreenState::Lobby);
+        self.lobby.into()
+    }
+}
+
+/// The user's player in the tutorial
+const TUTORIAL_PLAYER: u32 = 0;
+
+impl AppStateT for Tutorial {
+    fn update(mut self, world: &mut GameWorld, requests: &mut Vec<Request>) -> AppState {
+        self.local_game = match self.local_game.update(world, requests) {
+            AppState::LocalGame(local_game) => local_game,
+            // The user left partway through
+            state => {
+                tutorial::hide();
+                return state;
+            }
+        };
+
+        let state = &self.local_game.state;
+        let (num_tiles, turn_player) = (state.board_state().tiles_vec().len(), state.turn_player());
+        if num_tiles > self.last_seen.0 && self.last_seen.1 == TUTORIAL_PLAYER {
+            self.user_tiles += 1;
+        }
+        self.last_seen = (num_tiles, turn_player);
+
+        // Once the user is out, all that's left is the last step
+        let last = tutorial::STEPS.len() - 1;
+        let user_out = state.player_state(TUTORIAL_PLAYER).is_none() || state.game_over();
+        if user_out && self.step < last {
+            self.go_to_step(last, world);
+        } else if self.step_done(world) {
+            if self.step == last {
+                tutorial::hide();
+                return self.local_game.leave(world);
+            }
+            self.go_to_step(self.step + 1, world);
+        }
+        self.into()
+    }
+
+    fn handle_response(mut self, world: &mut GameWorld, response: Response, requests: &mut Vec<Request>) -> AppState {
+        self.local_game = match self.local_game.handle_response(world, response, requests) {
+            AppState::LocalGame(local_game) => local_game,
+            _ => unreachable!("Local games only change state when the user does something"),
+        };
+        self.into()
+    }
+}
+
+impl Tutorial {
+    /// Starts the tutorial on a standard board
+    fn new(lobby: Lobby, world: &mut GameWorld) -> Self {
+        let usernames = vec!["You".to_owned(), "AI".to_owned()];
+        let local_game = LocalGame::new(usernames, vec![false, true], GamePreset::Standard.options(), lobby, world);
+        let mut tutorial = Self { local_game, step: 0, user_tiles: 0, last_seen: (0, 0), step_start: (0, None) };
+        tutorial.go_to_step(0, world);
+        tutorial
+    }
+
+    fn go_to_step(&mut self, step: usize, world: &GameWorld) {
+        self.step = step;
+        self.step_start = (self.user_tiles, world.world.fetch::<SelectedTile>().1.clone());
+        tutorial::show_step(&tutorial::STEPS[step]);
+    }
+
+    /// Whether the user did what the step they're on waits for
+    fn step_done(&self, world: &GameWorld) -> bool {
+        let selected = world.world.fetch::<SelectedTile>();
+        match tutorial::STEPS[self.step].wait {
+            Wait::Next => world.world.read_component::<Collider>().get(world.tutorial_next_entity).unwrap().clicked(),
+            Wait::PlaceToken => self.local_game.state.board_state().player_port(TUTORIAL_PLAYER).is_some(),
+            Wait::SelectTile => selected.2.is_some(),
+            Wait::RotateTile => selected.2.is_some() && selected.1 != self.step_start.1,
+            Wait::PlaceTile => self.user_tiles > self.step_start.0,
+        }
     }
 }
 
@@ -543,16 +2118,41 @@ pub enum AppState {
     WaitJoinGame,
     StatelessGame,
     Game,
+    LocalGame,
+    ReplayViewer,
+    Tutorial,
+    Analysis,
 }
 
 pub type State = AppState;
 
+impl AppState {
+    /// Draws the tokens of the game on screen again, after the token palette changed
+    pub fn redraw_tokens(&mut self, world: &mut GameWorld) {
+        match self {
+            Self::Game(game) => game.redraw_tokens(world),
+            Self::LocalGame(local_game) => if let Some(game) = &mut local_game.client_game {
+                game.redraw_tokens(world);
+            }
+            Self::ReplayViewer(viewer) => viewer.client_game.redraw_tokens(world),
+            Self::Analysis(analysis) => analysis.client_game.redraw_tokens(world),
+            Self::Tutorial(tutorial) => if let Some(game) = &mut tutorial.local_game.client_game {
+                game.redraw_tokens(world);
+            }
+            _ => {}
+        }
+    }
+}
+
 pub mod gameplay {
     use specs::{Entity, WorldExt};
     use enum_dispatch::enum_dispatch;
-    use common::{math::Pt2, message::{Request, Response}, tile::BaseGAct};
+    use common::{board::BaseTLoc, math::Pt2, message::{Request, Response}, tile::{BaseGAct, BaseTile}};
+
+    use crate::{hint, ecs::{self, Collider, Highlight, HighlightKind, PlacedPort, PlacedTLoc, RunPlaceTileSystem, RunPlaceTokenSystem, SelectedTile, TLocLabel, TileLabel, Transform}, game::{GameWorld, app}, render::{self, BaseBoardExt, BaseTileExt}};
 
-    use crate::{ecs::{PlacedPort, PlacedTLoc, RunPlaceTileSystem, RunPlaceTokenSystem, SelectedTile, TileLabel, Transform}, game::{GameWorld, app}, render::{BaseBoardExt, BaseTileExt}};
+    /// How long the location a hint suggests stays highlighted, in milliseconds
+    const HINT_DURATION_MS: f64 = 3000.0;
 
     #[derive(Debug)]
     pub struct PlaceToken {
@@ -580,6 +2180,10 @@ pub mod gameplay {
         pub(crate) tile_entity: Option<Entity>,
         pub(crate) tile_index: u32,
         pub(crate) tile_action: Option<BaseGAct>,
+        /// Transformed tile and location the path preview is showing, if any
+        pub(crate) preview: Option<(BaseTile, BaseTLoc)>,
+        /// Location the last hint suggested, and when its highlight goes away in milliseconds since the epoch
+        pub(crate) hint: Option<(BaseTLoc, f64)>,
     }
 
     /// Waiting for the server to check the validity of the tile placement
@@ -632,7 +2236,7 @@ pub mod gameplay {
                     self.into()
                 },
 
-                Response::Rejected { id } => if id == app.id {
+                Response::Rejected{ id, .. } => if id == app.id {
                     PlaceToken { start_ports: self.start_ports, token_entity: self.token_entity }.into()
                 } else {
                     self.into()
@@ -673,12 +2277,17 @@ pub mod gameplay {
                     let locs = app.game.board().port_locs(&port).into_iter().map(|loc| {
                         app.game.board().create_loc_collider_entity(&loc, &mut world.world, &mut world.id_counter)
                     }).collect();
+                    render::set_hand_exchange_available(app.can_exchange_hand());
+                    render::set_scout_available(app.can_scout());
+                    render::set_hint_available(true);
 
                     PlaceTile {
                         locs,
                         tile_entity: None,
                         tile_index: 0,
                         tile_action: None,
+                        preview: None,
+                        hint: None,
                     }.into()
                 } else { self.into() }
             } else {
@@ -687,8 +2296,84 @@ pub mod gameplay {
         }
     }
 
+    /// Removes the tile being placed and the locations it could go, for when the user's turn is over
+    fn stop_placing_tile(app: &mut app::Game, world: &mut GameWorld, locs: &[Entity], tile_entity: Option<Entity>) {
+        if let Some(entity) = tile_entity {
+            world.world.delete_entity(entity).expect("Entity was deleted too early");
+        }
+        world.world.delete_entities(locs).expect("Entity was deleted too early");
+        world.world.get_mut::<SelectedTile>().expect("Missing SelectedTile").2 = None;
+        world.world.get_mut::<RunPlaceTileSystem>().expect("Missing RunPlaceTileSystem").0 = false;
+        app.preview_path(world, None);
+        render::set_hand_exchange_available(false);
+        render::set_scout_available(false);
+        render::set_hint_available(false);
+    }
+
+    /// Deletes the entities a gameplay state keeps, for throwing the game away
+    pub(crate) fn delete_entities(state: GameplayState, app: &mut app::Game, world: &mut GameWorld) {
+        match state {
+            GameplayState::PlaceToken(PlaceToken{ start_ports, token_entity }) |
+            GameplayState::WaitPlaceTokenCheck(WaitPlaceTokenCheck{ start_ports, token_entity }) => {
+                world.world.delete_entity(token_entity).ok();
+                world.world.delete_entities(&start_ports).ok();
+                world.world.get_mut::<RunPlaceTokenSystem>().expect("Missing RunPlaceTokenSystem").0 = false;
+            }
+            GameplayState::PlaceTile(PlaceTile{ locs, tile_entity, .. }) |
+            GameplayState::WaitPlaceTileCheck(WaitPlaceTileCheck{ locs, tile_entity, .. }) =>
+                stop_placing_tile(app, world, &locs, tile_entity),
+            GameplayState::WaitPlaceTokens(_) | GameplayState::WaitTurn(_) => {}
+        }
+    }
+
+    impl PlaceTile {
+        /// Colors the locations the held tile can be placed at, by whether placing it there kills the user
+        fn highlight_locs(&self, app: &app::Game, world: &mut GameWorld) {
+            let tile = self.tile_entity.map(|entity| world.world.read_component::<TileLabel>().get(entity)
+                .expect("Tile is missing label").0.clone());
+            let player = app.state.player_expect();
+            let board = app.game.board();
+            let board_state = app.state.board_state();
+            let loc_labels = world.world.read_component::<TLocLabel>();
+            let mut highlights = world.world.write_component::<Highlight>();
+
+            for entity in &self.locs {
+                let loc = &loc_labels.get(*entity).expect("Tile location is missing label").0;
+                let hinted = self.hint.as_ref().map_or(false, |(hint_loc, _)| hint_loc == loc);
+                let kind = tile.as_ref().zip(self.tile_action.as_ref())
+                    .filter(|(tile, action)| app.state.can_place_tile(&app.game, player, &tile.kind(), self.tile_index, action, loc))
+                    .map(|(tile, action)| {
+                        if hinted {
+                            return HighlightKind::Hint;
+                        }
+                        let (_, deaths) = board_state.preview_tile(&board, &tile.apply_action(action), loc);
+                        if deaths.iter().any(|death| death.player() == player) {
+                            HighlightKind::Lethal
+                        } else {
+                            HighlightKind::Legal
+                        }
+                    });
+                highlights.get_mut(*entity).expect("Tile location is missing highlight").set_kind(kind);
+            }
+        }
+    }
+
     impl GameplayStateT for PlaceTile {
         fn update(mut self, app: &mut app::Game, world: &mut GameWorld, requests: &mut Vec<Request>) -> GameplayState {
+            // Hint, which picks up the suggested tile so the selection below takes care of holding it
+            if world.world.read_component::<Collider>().get(world.hint_entity).unwrap().clicked() {
+                let player = app.state.player_expect();
+                if let Some((kind, index, action, loc)) = hint::suggest_move(&app.game, &app.state, player) {
+                    ecs::select_hand_tile(&world.world, &kind, index, &action);
+                    self.hint = Some((loc, js_sys::Date::now() + HINT_DURATION_MS));
+                    self.highlight_locs(app, world);
+                }
+            }
+            if self.hint.as_ref().map_or(false, |(_, until)| js_sys::Date::now() >= *until) {
+                self.hint = None;
+                self.highlight_locs(app, world);
+            }
+
             // Tile selection
             {
                 let selected_tile = world.world.fetch::<SelectedTile>();
@@ -721,9 +2406,52 @@ pub mod gameplay {
                             &mut world.id_counter,
                         ));
                     }
+                    self.highlight_locs(app, world);
                 }
             }
 
+            // Path preview
+            let preview = {
+                let colliders = world.world.read_component::<Collider>();
+                let loc_labels = world.world.read_component::<TLocLabel>();
+                let tile_labels = world.world.read_component::<TileLabel>();
+                let hovered_loc = self.locs.iter()
+                    .find(|entity| colliders.get(**entity).map_or(false, Collider::hovered))
+                    .map(|entity| loc_labels.get(*entity).expect("Tile location is missing label").0.clone());
+                let tile = self.tile_entity.map(|entity| tile_labels.get(entity).expect("Tile is missing label").0
+                    .apply_action(self.tile_action.as_ref().expect("Group action should exist")));
+                tile.zip(hovered_loc)
+            };
+            if preview != self.preview {
+                app.preview_path(world, preview.as_ref().map(|(tile, loc)| (tile, loc)));
+                self.preview = preview;
+            }
+
+            // Scouting, which doesn't end the turn
+            if app.can_scout() && world.world.read_component::<Collider>().get(world.scout_entity).unwrap().clicked() {
+                requests.push(Request::Scout{ id: app.id, player: app.state.player_expect() });
+                render::set_scout_available(false);
+            }
+
+            // Hand exchange
+            if app.can_exchange_hand() &&
+                world.world.read_component::<Collider>().get(world.exchange_hand_entity).unwrap().clicked()
+            {
+                requests.push(Request::ExchangeHand{ id: app.id, player: app.state.player_expect() });
+                // Suspend while waiting for the check
+                world.world.get_mut::<RunPlaceTileSystem>().expect("Missing RunPlaceTileSystem").0 = false;
+                app.preview_path(world, None);
+                render::set_hand_exchange_available(false);
+                render::set_hint_available(false);
+
+                return WaitPlaceTileCheck {
+                    locs: self.locs,
+                    tile_entity: self.tile_entity,
+                    tile_index: self.tile_index,
+                    tile_action: self.tile_action,
+                }.into();
+            }
+
             // Tile placement
             world.world.get_mut::<RunPlaceTileSystem>().expect("Missing RunPlaceTileSystem").0 = true;
             if let (Some(loc), Some(tile_entity)) = (
@@ -734,14 +2462,16 @@ pub mod gameplay {
                 world.world.get_mut::<RunPlaceTileSystem>().expect("Missing RunPlaceTileSystem").0 = false;
                 let kind = world.world.read_component::<TileLabel>().get(tile_entity)
                     .expect("Tile is missing label").0.kind();
-                requests.push(Request::PlaceTile {
-                    id: app.id,
-                    player: app.state.player_expect(),
-                    kind,
-                    index: self.tile_index,
-                    action: self.tile_action.clone().expect("Group action should exist"),
-                    loc
+                let (id, player, index) = (app.id, app.state.player_expect(), self.tile_index);
+                let action = self.tile_action.clone().expect("Group action should exist");
+                // When everyone picks at once, the tile only gets placed once everyone has picked
+                requests.push(if app.options.simultaneous() {
+                    Request::CommitTile{ id, player, kind, index, action, loc }
+                } else {
+                    Request::PlaceTile{ id, player, kind, index, action, loc }
                 });
+                app.preview_path(world, None);
+                render::set_hint_available(false);
 
                 WaitPlaceTileCheck {
                     locs: self.locs,
@@ -754,8 +2484,17 @@ pub mod gameplay {
             }
         }
 
-        fn handle_response(self, _app: &mut app::Game, _world: &mut GameWorld, _response: Response, _requests: &mut Vec<Request>) -> GameplayState {
-            self.into()
+        fn handle_response(self, app: &mut app::Game, world: &mut GameWorld, response: Response, _requests: &mut Vec<Request>) -> GameplayState {
+            match response {
+                // A move can be picked for the user when they run out of time
+                Response::SkippedTurn{ id, player, .. } |
+                Response::CommittedTile{ id, player } if id == app.id && player == app.state.player_expect() => {
+                    stop_placing_tile(app, world, &self.locs, self.tile_entity);
+                    WaitTurn.into()
+                }
+
+                _ => self.into()
+            }
         }
     }
 
@@ -766,21 +2505,34 @@ pub mod gameplay {
 
         fn handle_response(self, app: &mut app::Game, world: &mut GameWorld, response: Response, _requests: &mut Vec<Request>) -> GameplayState {
             match response {
-                Response::PlacedTile{ id, player, .. } => if id == app.id && player == app.state.player_expect() {
-                    self.tile_entity.map(|e| world.world.delete_entity(e).expect("Entity was deleted too early"));
-                    world.world.delete_entities(&self.locs).expect("Entity was deleted too early");
-                    world.world.get_mut::<SelectedTile>().expect("Missing SelectedTile").2 = None;
+                Response::PlacedTile{ id, player, .. } | Response::ExchangedHand{ id, player, .. } |
+                Response::CommittedTile{ id, player } =>
+                    if id == app.id && player == app.state.player_expect() {
+                        stop_placing_tile(app, world, &self.locs, self.tile_entity);
+                        WaitTurn.into()
+                    } else {
+                        self.into()
+                    },
+
+                // The turn ran out before the placement got checked
+                Response::SkippedTurn{ id, player, .. } => if id == app.id && player == app.state.player_expect() {
+                    stop_placing_tile(app, world, &self.locs, self.tile_entity);
                     WaitTurn.into()
                 } else {
                     self.into()
                 },
 
-                Response::Rejected{ id } => if id == app.id {
+                Response::Rejected{ id, .. } => if id == app.id {
+                    render::set_hand_exchange_available(app.can_exchange_hand());
+                    render::set_scout_available(app.can_scout());
+                    render::set_hint_available(true);
                     PlaceTile {
                         locs: self.locs,
                         tile_entity: self.tile_entity,
                         tile_index: self.tile_index,
                         tile_action: self.tile_action,
+                        preview: None,
+                        hint: None,
                     }.into()
                 } else { self.into() },
 
@@ -0,0 +1,50 @@
+//! Suggesting a tile placement when the user asks for a hint.
+//! Every legal placement gets tried out on the user's copy of the game, so only what the user can see goes into it.
+//! Placements are ranked by whether the user survives them, then by how many opponents they kill,
+//! then by how many of the user's visible tiles could still be placed safely afterwards.
+
+use common::{game::BaseGame, game_state::{BaseGameState, BaseTileMove, BaseTurnEvents}};
+
+/// The state after `player` makes a tile placement, and what happened because of it.
+/// In games where everyone picks at once, it doesn't have to be their turn.
+fn simulate_move(game: &BaseGame, state: &BaseGameState, player: u32, (kind, index, action, loc): &BaseTileMove)
+    -> (BaseGameState, BaseTurnEvents)
+{
+    let mut state = state.clone();
+    for _ in 0..state.num_players() {
+        if state.turn_player() == player {
+            break;
+        }
+        state.skip_turn();
+    }
+    let result = state.take_turn_placing_tile(game, kind, *index, action, loc);
+    (state, result)
+}
+
+/// How good a placement is for `player`. Higher is better.
+/// In order: whether they survive it, opponents it kills, safe placements left afterwards, and tiles moved through.
+fn score(game: &BaseGame, state: &BaseGameState, player: u32, mv: &BaseTileMove) -> (bool, usize, usize, u32) {
+    let (after, result) = simulate_move(game, state, player, mv);
+    let survives = !result.dead_players().contains(&player);
+    let kills = result.dead_players().iter().filter(|dead| **dead != player).count();
+
+    // Tiles drawn from the pile are hidden, so they can't be planned around
+    let safe_follow_ups = if survives {
+        after.legal_moves(game, player).iter()
+            .filter(|(kind, index, _, _)| after.hand_tile(player, kind, *index).map_or(false, |tile| tile.visible()))
+            .filter(|follow_up| !simulate_move(game, &after, player, follow_up).1.dead_players().contains(&player))
+            .count()
+    } else {
+        0
+    };
+    let distance = result.distance_moved(player);
+    (survives, kills, safe_follow_ups, distance)
+}
+
+/// The placement to suggest to `player`. None if they have no legal placement.
+pub fn suggest_move(game: &BaseGame, state: &BaseGameState, player: u32) -> Option<BaseTileMove> {
+    state.legal_moves(game, player).into_iter()
+        .map(|mv| (score(game, state, player, &mv), mv))
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, mv)| mv)
+}
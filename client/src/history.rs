@@ -0,0 +1,65 @@
+//! The history tab in the lobby, listing the results of the games the user finished most recently.
+//! The server keeps the results, and gets asked for them whenever the tab opens.
+
+use std::time::Duration;
+
+use common::{history::GameSummary, message::Request, replay::ReplayLink};
+use format_xml::xml;
+use itertools::Itertools;
+use web_sys::{Element, Event, WebSocket};
+
+use crate::{add_event_listener, document, notes::PlayerNotes, processor::send_request, render};
+
+fn panel() -> Element {
+    document().get_element_by_id("history_panel").expect("Missing history panel")
+}
+
+/// Asks the server for the user's history every time the tab opens, since it changes whenever a game ends
+pub fn init(ws: &WebSocket) {
+    let ws = ws.clone();
+    add_event_listener(&panel(), "toggle", move |_: Event| {
+        if panel().has_attribute("open") {
+            send_request(&Request::GetHistory, &ws);
+        }
+    });
+}
+
+/// Like `5m 12s`, or `12s` for games shorter than a minute
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m {}s", secs / 60, secs % 60)
+    }
+}
+
+fn render_summary(game: &GameSummary, notes: &PlayerNotes) -> String {
+    let players = game.players().iter().map(|name| render::render_username(name, notes)).join(", ");
+    let winners = if game.winners().is_empty() {
+        "No winner".to_owned()
+    } else {
+        format!("Won by {}", game.winners().iter().map(|name| render::render_username(name, notes)).join(", "))
+    };
+    let href = format!("#{}", ReplayLink::hosted_fragment(game.replay_id()));
+    xml! {
+        <li class="history-entry">
+            <div>{players}</div>
+            <div>{winners}</div>
+            <div class="history-details">{game.num_moves()}" moves, "{format_duration(game.duration())}" "
+                <a href={href} target="_blank">"Replay"</a>
+            </div>
+        </li>
+    }.to_string()
+}
+
+/// Shows the results the server sent, newest first
+pub fn show(games: &[GameSummary]) {
+    let list = document().get_element_by_id("history_list").expect("Missing history list");
+    if games.is_empty() {
+        list.set_inner_html(&xml!(<li class="history-entry">"No finished games yet"</li>).to_string());
+    } else {
+        let notes = PlayerNotes::load();
+        list.set_inner_html(&games.iter().map(|game| render_summary(game, &notes)).collect::<String>());
+    }
+}
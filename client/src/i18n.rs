@@ -0,0 +1,147 @@
+//! Wording of everything the server reports as a code.
+//! The server never sends text of its own besides usernames, so translating the client means translating this.
+
+use common::{message::{RejectReason, SkipReason}, puzzle::{Puzzle, PuzzleGoal}, validation::{MAX_USERNAME_LEN, UsernameProblem}};
+
+/// Says that a linked replay isn't kept by the server
+pub const MISSING_REPLAY: &str = "That replay isn't kept anymore.";
+
+/// Says that an opened file isn't a game archive, or is one from a newer version
+pub const INVALID_ARCHIVE: &str = "That file isn't a game archive this version can open.";
+
+/// Says that an opened file isn't a puzzle, or is one from a newer version or that can't be played
+pub const INVALID_PUZZLE: &str = "That file isn't a puzzle this version can open.";
+
+/// Says that the user reached the puzzle's goal
+pub const PUZZLE_SOLVED: &str = "Puzzle solved!";
+
+/// Says that the user can't reach the puzzle's goal anymore
+pub const PUZZLE_FAILED: &str = "Puzzle failed. Leave and open it again to retry.";
+
+/// Says that a link inviting others to the game was copied
+pub const INVITE_COPIED: &str = "Invite link copied. Anyone who opens it joins this game.";
+
+/// Says that the game got out of sync with the server and was fetched again
+pub const RESYNCED: &str = "The game was out of sync with the server and has been refreshed.";
+
+/// Says that the user picked a tile, and it gets placed once everyone else has picked too
+pub const WAITING_FOR_PICKS: &str = "Tile picked. It gets placed once everyone has picked.";
+
+/// Says that the user asked to take back their tile placement, and has to wait for everyone else
+pub const UNDO_ASKED: &str = "Asked everyone else to let you take back your tile.";
+
+/// Says that everyone let the last tile placement be taken back
+pub const UNDID_TURN: &str = "The last tile placement was taken back.";
+
+/// Labels the button for asking to pause the game
+pub const PAUSE_BUTTON: &str = "Pause";
+
+/// Labels the button for asking to resume the game
+pub const RESUME_BUTTON: &str = "Resume";
+
+/// Says that the user asked to pause the game, and what it takes
+pub const PAUSE_ASKED: &str = "Asked to pause. The game pauses once the host or most players ask.";
+
+/// Says that the user asked to resume the game, and what it takes
+pub const RESUME_ASKED: &str = "Asked to resume. The game resumes once the host or most players ask.";
+
+/// Says that the game got resumed
+pub const GAME_RESUMED: &str = "The game resumed.";
+
+/// Says why the server rejected a request
+pub fn reject_reason(reason: RejectReason) -> String {
+    match reason {
+        RejectReason::UsernameTaken => "That username is already taken.".to_owned(),
+        RejectReason::InvalidUsername{ problem } => username_problem(problem),
+        RejectReason::NoSuchGame => "That game doesn't exist anymore.".to_owned(),
+        RejectReason::NotAPlayer => "Only players of the game can do that, and only for themselves.".to_owned(),
+        RejectReason::AlreadyStarted => "The game has already started.".to_owned(),
+        RejectReason::NotStarted => "The game hasn't started yet.".to_owned(),
+        RejectReason::Eliminated => "You're out of the game.".to_owned(),
+        RejectReason::TokenAlreadyPlaced => "You already placed your token.".to_owned(),
+        RejectReason::TokensNotPlaced => "Everyone has to place their token first.".to_owned(),
+        RejectReason::NotYourTurn{ turn_player } => format!("It's player {}'s turn.", turn_player + 1),
+        RejectReason::GameOver => "The game is over.".to_owned(),
+        RejectReason::IllegalMove => "That move isn't allowed.".to_owned(),
+        RejectReason::TooManyGames => "The server can't host any more games right now.".to_owned(),
+        RejectReason::AlreadyPlayedDaily => "You already played today's challenge. Come back tomorrow for a new one.".to_owned(),
+        RejectReason::Paused => "The game is paused.".to_owned(),
+        RejectReason::RematchClosed => "It's too late to join that rematch.".to_owned(),
+        RejectReason::ColorTaken => "Someone else already picked that color.".to_owned(),
+    }
+}
+
+/// Says what's wrong with a username
+pub fn username_problem(problem: UsernameProblem) -> String {
+    match problem {
+        UsernameProblem::Empty => "Usernames can't be blank.".to_owned(),
+        UsernameProblem::TooLong => format!("Usernames can be at most {} characters long.", MAX_USERNAME_LEN),
+        UsernameProblem::InvalidCharacter => "Usernames can only have letters, digits, spaces, and - _ . '".to_owned(),
+        UsernameProblem::Reserved => "That username is reserved.".to_owned(),
+        UsernameProblem::Filtered => "That username isn't allowed on this server.".to_owned(),
+    }
+}
+
+/// Says that someone tried a move that got rejected, and what kind of move it was.
+/// `username` is None if they aren't playing in the game.
+pub fn rejected_attempt(username: Option<&str>, reason: RejectReason) -> String {
+    let attempt = match reason {
+        RejectReason::NotAPlayer => "a move for someone else",
+        RejectReason::NotYourTurn{ .. } => "a move out of turn",
+        RejectReason::IllegalMove => "an illegal move",
+        RejectReason::TokenAlreadyPlaced => "moving their token again",
+        RejectReason::TokensNotPlaced => "placing a tile before every token was placed",
+        RejectReason::Eliminated => "a move after being eliminated",
+        RejectReason::GameOver => "a move after the game ended",
+        RejectReason::Paused => "a move while the game was paused",
+        RejectReason::UsernameTaken | RejectReason::InvalidUsername{ .. } | RejectReason::NoSuchGame | RejectReason::AlreadyStarted | RejectReason::NotStarted |
+        RejectReason::TooManyGames | RejectReason::AlreadyPlayedDaily | RejectReason::RematchClosed |
+        RejectReason::ColorTaken =>
+            "a move that isn't possible right now",
+    };
+    format!("{} tried {}.", username.unwrap_or("Someone who isn't playing"), attempt)
+}
+
+/// Asks the user whether `username` can take back the last tile they placed
+pub fn undo_requested(username: &str) -> String {
+    format!("{} asks to take back their last tile.", username)
+}
+
+/// Says that `username` didn't let the last tile placement be taken back
+pub fn undo_declined(username: &str) -> String {
+    format!("{} turned down taking back the last tile.", username)
+}
+
+/// Says that the next round of a match started
+pub fn round_started(round: u32, rounds: u32) -> String {
+    format!("Round {} of {} started on a fresh board.", round, rounds)
+}
+
+/// Says what the user has to do to solve a puzzle
+pub fn puzzle_goal(puzzle: &Puzzle) -> String {
+    let goal = match puzzle.goal() {
+        PuzzleGoal::Survive{ turns: 1 } => "survive your next turn".to_owned(),
+        PuzzleGoal::Survive{ turns } => format!("survive your next {} turns", turns),
+        PuzzleGoal::Eliminate{ player } => format!("eliminate {} without dying", puzzle.usernames()[player as usize]),
+    };
+    format!("{}: {}.", puzzle.name(), goal)
+}
+
+/// Says how far into a finished game the analysis is, and how many tile placements were tried out from there
+pub fn analysis_position(num_played: usize, num_moves: usize, num_tried: usize) -> String {
+    match num_tried {
+        0 => format!("Move {} of {}", num_played, num_moves),
+        1 => format!("Move {} of {}, then 1 placement tried out", num_played, num_moves),
+        _ => format!("Move {} of {}, then {} placements tried out", num_played, num_moves, num_tried),
+    }
+}
+
+/// Says that `username`'s turn got skipped, and why
+pub fn skipped_turn(username: &str, reason: SkipReason) -> String {
+    match reason {
+        SkipReason::Disconnected => format!("{}'s turn was skipped since they haven't come back.", username),
+        SkipReason::OutOfTime => format!("{} ran out of time, so their turn was skipped.", username),
+        SkipReason::NoLegalMove => format!("{} has no tile they can place, so their turn was skipped.", username),
+        SkipReason::MoveBlocked => format!("{}'s pick couldn't be placed anymore, so their turn was skipped.", username),
+    }
+}
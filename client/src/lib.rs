@@ -2,10 +2,30 @@ pub mod processor;
 pub mod render;
 pub mod game;
 pub mod ecs;
+pub mod export;
+pub mod history;
+pub mod daily;
+pub mod i18n;
+pub mod notes;
+pub mod notify;
+pub mod settings;
+pub mod stats;
+pub mod tutorial;
+pub mod hint;
+pub mod move_list;
+pub mod tile_count;
+pub mod templates;
 
 
+use common::clock::TimeControl;
+use common::config::{MatchRounds, NumPlayers, SpectatorDelay};
+use common::game::GameId;
 use common::message::Request;
 use common::message::Response;
+use common::options::{BotDifficulty, GamePreset, RejectionFeed, TokenColor};
+use common::replay::ReplayLink;
+use common::validation::validate_username;
+use strum::IntoEnumIterator;
 use wasm_bindgen::convert::FromWasmAbi;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
@@ -13,12 +33,16 @@ use web_sys::Document;
 use web_sys::Element;
 use web_sys::ErrorEvent;
 use web_sys::Event;
+use web_sys::HtmlInputElement;
+use web_sys::Storage;
+use web_sys::UrlSearchParams;
 
 use web_sys::Window;
 use web_sys::{BinaryType, MessageEvent, WebSocket};
 
 use std::cell::RefCell;
 
+use std::iter;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -26,6 +50,9 @@ use std::sync::Mutex;
 use crate::game::GameWorld;
 use crate::processor::process_response;
 use crate::processor::send_request;
+use crate::notify::TurnAlert;
+use crate::settings::Settings;
+use crate::templates::GameTemplate;
 
 /// The SVG namespace
 pub const SVG_NS: &str = "http://www.w3.org/2000/svg";
@@ -59,6 +86,11 @@ pub fn document() -> Document {
     window().document().expect("Cannot get document")
 }
 
+/// The browser's local storage, if it allows it
+pub fn local_storage() -> Option<Storage> {
+    window().local_storage().ok().flatten()
+}
+
 /// Adds an event listener to an element.
 /// WARNING: This leaks the callback.
 fn add_event_listener<E: 'static + FromWasmAbi>(element: &Element, event_name: &str, callback: impl FnMut(E) + 'static) {
@@ -67,25 +99,110 @@ fn add_event_listener<E: 'static + FromWasmAbi>(element: &Element, event_name: &
     closure.forget()
 }
 
+/// Asks the user for a username, with `message` as the question, until they give one the rules allow.
+/// Returns the username trimmed the way the server would.
+pub fn prompt_username(message: &str) -> String {
+    let mut message = message.to_owned();
+    loop {
+        let username = window().prompt_with_message(&message)
+            .unwrap_or(None)
+            .unwrap_or_else(|| "Guest".to_owned());
+        match validate_username(&username) {
+            Ok(username) => return username,
+            Err(problem) => message = format!("{} Enter a username.", i18n::username_problem(problem)),
+        }
+    }
+}
+
+/// The WebSocket URL of the server to connect to, so one build works wherever it's hosted.
+/// `?server=host:port` in the page's URL picks the server, or `?server=wss://host/path` for the whole URL.
+/// Otherwise it's the host the page came from, on the port the server listens on.
+/// Servers that serve the page too only take games on `/ws`, so that's the path connected to.
+/// Pages served over HTTPS connect over `wss://`.
+/// Path of the server's WebSocket
+const WS_PATH: &str = "/ws";
+
+fn server_url() -> String {
+    let location = window().location();
+    let scheme = if location.protocol().ok().as_deref() == Some("https:") { "wss" } else { "ws" };
+    let server = location.search().ok()
+        .and_then(|search| UrlSearchParams::new_with_str(&search).ok())
+        .and_then(|params| params.get("server"))
+        .filter(|server| !server.is_empty());
+
+    match server {
+        Some(server) if server.contains("://") => server,
+        Some(server) => format!("{}://{}{}", scheme, server, WS_PATH),
+        None => {
+            let (default_host, port) = common::HOST_ADDRESS.rsplit_once(':').expect("Host address has no port");
+            // Pages opened as files have no host
+            let host = location.hostname().ok().filter(|host| !host.is_empty())
+                .unwrap_or_else(|| default_host.to_owned());
+            format!("{}://{}:{}{}", scheme, host, port, WS_PATH)
+        }
+    }
+}
+
 fn request_animation_frame(callback: &Closure<dyn FnMut()>) {
     window().request_animation_frame(callback.as_ref().unchecked_ref()).expect("Cannot request animation frame");
 }
 
 fn run() -> Result<(), JsValue> {
-    let ws = WebSocket::new(&format!("ws://{}/", common::HOST_ADDRESS))?;
+    let ws = WebSocket::new(&server_url())?;
     ws.set_binary_type(BinaryType::Arraybuffer);
-    let game_world = Arc::new(Mutex::new(GameWorld::new()));
 
-    let username = window().prompt_with_message("Enter a username")
-        .unwrap_or(None)
-        .unwrap_or_else(|| "Guest".to_owned());
+    // A saved username gets used without asking, unless the server rejects it
+    let username = Settings::load().username
+        .and_then(|username| validate_username(&username).ok())
+        .unwrap_or_else(|| prompt_username("Enter a username"));
     render::set_username(&username);
-    send_request(&Request::SetUsername{ username }, &ws);
+    send_request(&Request::SetUsername{ username: username.clone() }, &ws);
+    if let Some(avatar) = Settings::load().avatar {
+        send_request(&Request::SetAvatar{ avatar: Some(avatar) }, &ws);
+    }
+    // Invite links say which game to join once the username is accepted
+    let join = GameId::parse_join_fragment(&window().location().hash().unwrap_or_default());
+    let game_world = Arc::new(Mutex::new(GameWorld::new(username, join)));
+
+    // Replays in the link get watched right away, and ones kept by the server get asked for
+    match ReplayLink::parse(&window().location().hash().unwrap_or_default()) {
+        Some(ReplayLink::Inline(replay)) => game_world.lock().unwrap().watch_replay(replay),
+        Some(ReplayLink::Hosted(replay_id)) => send_request(&Request::GetReplay{ replay_id }, &ws),
+        None => {}
+    }
 
+    render::set_choices("preset", GamePreset::iter());
+    render::set_choices("bot_difficulty", BotDifficulty::iter());
+    render::set_choices("token_color", TokenColor::iter());
+    render::set_choices("rejection_feed", RejectionFeed::iter());
+    render::set_choices("rounds", MatchRounds::all());
+    let clock_choices = iter::once(templates::NO_CLOCK.to_owned()).chain(TimeControl::all().map(|control| control.to_string()));
+    render::set_choices("time_control", clock_choices);
+    let delay_choices = iter::once(templates::NO_DELAY.to_owned()).chain(SpectatorDelay::all().map(|delay| delay.to_string()));
+    render::set_choices("spectator_delay", delay_choices);
+    // Even the smallest board has room for 8 players
+    render::set_choices("hotseat_players", NumPlayers::all());
     let cws = ws.clone();
     add_event_listener(&document().get_element_by_id("create").unwrap(), "click", move |_: Event| {
-        send_request(&Request::CreateGame, &cws);
+        send_request(&GameTemplate::from_form(String::new()).request(), &cws);
+    });
+    templates::init();
+    add_event_listener(&document().get_element_by_id("show_trails").unwrap(), "change", |_: Event| {
+        let checked = document().get_element_by_id("show_trails").unwrap()
+            .dyn_into::<HtmlInputElement>().expect("Show trails checkbox isn't an input element")
+            .checked();
+        render::set_trails_shown(checked);
     });
+    add_event_listener(&document().get_element_by_id("export_svg").unwrap(), "click", |_: Event| {
+        export::download_svg("svg_root", "tsurust-board.svg");
+    });
+    export::init_import(&game_world);
+    move_list::init(&game_world);
+    notes::init();
+    history::init(&ws);
+    daily::init(&ws);
+    settings::init(&game_world, &ws);
+    let turn_alert = TurnAlert::init()?;
     
     let cws = ws.clone();
     let cgw = Arc::clone(&game_world);
@@ -94,6 +211,9 @@ fn run() -> Result<(), JsValue> {
             let array = js_sys::Uint8Array::new(&msg);
             let msg = bincode::deserialize::<Response>(&array.to_vec()).unwrap();
             console_log!("received response: {:?}", msg);
+            if let Response::YourTurn{ .. } = msg {
+                turn_alert.your_turn();
+            }
             
             for req in process_response(msg, &mut cgw.lock().unwrap()) {
                 send_request(&req, &cws);
@@ -109,13 +229,36 @@ fn run() -> Result<(), JsValue> {
     ws.set_onerror(Some(on_error.as_ref().unchecked_ref()));
     on_error.forget();
 
-    let _cws = ws.clone();
+    let cgw = Arc::clone(&game_world);
     let on_open = Closure::wrap(Box::new(move |_| {
-
+        cgw.lock().unwrap().connection_opened();
     }) as Box<dyn FnMut(JsValue)>);
     ws.set_onopen(Some(on_open.as_ref().unchecked_ref()));
     on_open.forget();
 
+    let cgw = Arc::clone(&game_world);
+    let on_close = Closure::wrap(Box::new(move |_| {
+        console_log!("connection closed");
+        cgw.lock().unwrap().connection_closed();
+    }) as Box<dyn FnMut(JsValue)>);
+    ws.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    on_close.forget();
+
+    // Browsers don't let us send ping frames, so ping with a request instead
+    let cws = ws.clone();
+    let cgw = Arc::clone(&game_world);
+    let on_heartbeat = Closure::wrap(Box::new(move || {
+        if cws.ready_state() == WebSocket::OPEN {
+            send_request(&Request::Ping, &cws);
+        }
+        cgw.lock().unwrap().check_connection();
+    }) as Box<dyn FnMut()>);
+    window().set_interval_with_callback_and_timeout_and_arguments_0(
+        on_heartbeat.as_ref().unchecked_ref(),
+        common::HEARTBEAT_PERIOD.as_millis() as i32,
+    )?;
+    on_heartbeat.forget();
+
     let on_frame = Rc::new(RefCell::new(None));
     let on_frame_clone = Rc::clone(&on_frame);
     let cgw = Arc::clone(&game_world);
@@ -133,7 +276,8 @@ fn run() -> Result<(), JsValue> {
 }
 
 // This is like the `main` function, except for JavaScript.
-#[wasm_bindgen(start)]
+// Tests don't have the page to run on
+#[cfg_attr(not(test), wasm_bindgen(start))]
 pub fn main_js() -> Result<(), JsValue> {
     // This provides better error messages in debug mode.
     // It's disabled in release mode so it doesn't bloat up the file size.
@@ -0,0 +1,50 @@
+//! The move list in the state panel: every move of the game in move notation, with the current one marked.
+//! In replays, clicking a move jumps the replay to right after it.
+
+use std::sync::{Arc, Mutex};
+
+use format_xml::xml;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, Event};
+
+use crate::{add_event_listener, document, game::GameWorld};
+
+fn list() -> Element {
+    document().get_element_by_id("move_list").expect("Missing move list")
+}
+
+/// Shows the moves with move `current` marked, scrolled so it's in view.
+/// Clicking a move only does something if `jumpable` is set.
+pub fn show(moves: &[String], current: Option<usize>, jumpable: bool) {
+    let list = list();
+    list.set_inner_html(&moves.iter().enumerate().map(|(i, mv)| {
+        let class = if Some(i) == current { "move-entry move-current" } else { "move-entry" };
+        xml!(<li class={class} data-move={i}>{mv}</li>).to_string()
+    }).collect::<String>());
+    list.toggle_attribute_with_force("jumpable", jumpable).unwrap();
+
+    if let Ok(Some(entry)) = list.query_selector(".move-current") {
+        entry.scroll_into_view_with_bool(false);
+    }
+}
+
+/// Empties the list, for leaving the game
+pub fn clear() {
+    show(&[], None, false);
+}
+
+/// Adds the listener for jumping replays to a move.
+/// The list gets rendered again after every move, so clicks on moves are caught by the list.
+pub fn init(game_world: &Arc<Mutex<GameWorld>>) {
+    let cgw = Arc::clone(game_world);
+    add_event_listener(&list(), "click", move |e: Event| {
+        let entry = e.target()
+            .and_then(|target| target.dyn_into::<Element>().ok())
+            .and_then(|target| target.closest(".move-entry").ok().flatten())
+            .and_then(|target| target.get_attribute("data-move"))
+            .and_then(|entry| entry.parse().ok());
+        if let Some(entry) = entry.filter(|_| list().has_attribute("jumpable")) {
+            cgw.lock().unwrap().jump_replay(entry);
+        }
+    });
+}
@@ -0,0 +1,141 @@
+//! Private notes players keep about other players, like how they tend to play.
+//! They get saved in the browser so they last between visits, and never get sent to the server.
+//! Usernames show their note on hover, and clicking one in the state panel edits it.
+
+use std::collections::HashMap;
+
+use wasm_bindgen::JsCast;
+use web_sys::{Element, Event, HtmlTextAreaElement};
+
+use crate::{add_event_listener, document, local_storage};
+
+/// Key the notes are saved under in local storage
+const STORAGE_KEY: &str = "tsurust-player-notes";
+
+/// Notes about players, by username
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlayerNotes(HashMap<String, String>);
+
+impl PlayerNotes {
+    /// Loads the saved notes.
+    /// Missing or unreadable notes, or a browser that doesn't allow storage, count as no notes.
+    pub fn load() -> Self {
+        local_storage()
+            .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten())
+            .map_or_else(Self::default, |json| Self::from_json(&json))
+    }
+
+    /// Saves the notes, if the browser allows it
+    pub fn save(&self) {
+        if let Some(storage) = local_storage() {
+            storage.set_item(STORAGE_KEY, &self.to_json()).ok();
+        }
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(&self.0).expect("Serialization went wrong")
+    }
+
+    fn from_json(json: &str) -> Self {
+        Self(serde_json::from_str(json).unwrap_or_default())
+    }
+
+    /// The note about a player, if there is one
+    pub fn get(&self, username: &str) -> Option<&str> {
+        self.0.get(username).map(String::as_str)
+    }
+
+    /// Sets the note about a player. A blank note removes it.
+    pub fn set(&mut self, username: &str, note: &str) {
+        let note = note.trim();
+        if note.is_empty() {
+            self.0.remove(username);
+        } else {
+            self.0.insert(username.to_owned(), note.to_owned());
+        }
+    }
+}
+
+fn popup() -> Element {
+    document().get_element_by_id("note_popup").expect("Missing note popup")
+}
+
+fn text_area() -> HtmlTextAreaElement {
+    document().get_element_by_id("note_text").expect("Missing note text")
+        .dyn_into().expect("Note text isn't a textarea element")
+}
+
+/// Opens the popup for editing the note about a player
+pub fn open_editor(username: &str) {
+    let popup = popup();
+    popup.set_attribute("data-username", username).expect("Cannot set note username");
+    document().get_element_by_id("note_username").expect("Missing note username")
+        .set_text_content(Some(username));
+    text_area().set_value(PlayerNotes::load().get(username).unwrap_or(""));
+    popup.toggle_attribute_with_force("hidden", false).unwrap();
+    text_area().focus().ok();
+}
+
+/// Saves the note being edited, and shows it on every rendering of the player's username
+fn save_editor() {
+    let popup = popup();
+    if let Some(username) = popup.get_attribute("data-username") {
+        let mut notes = PlayerNotes::load();
+        notes.set(&username, &text_area().value());
+        notes.save();
+
+        let targets = document().get_elements_by_class_name("note-target");
+        for target in (0..targets.length()).filter_map(|i| targets.item(i)) {
+            if target.get_attribute("data-username").as_deref() == Some(&username) {
+                target.set_attribute("title", notes.get(&username).unwrap_or("")).expect("Cannot set note");
+            }
+        }
+    }
+    close_editor();
+}
+
+fn close_editor() {
+    popup().toggle_attribute_with_force("hidden", true).unwrap();
+}
+
+/// Adds the listeners for editing notes.
+/// Usernames in the state panel get rendered again every turn, so clicks on them are caught by the panel.
+pub fn init() {
+    add_event_listener(&document().get_element_by_id("state_panel").unwrap(), "click", |e: Event| {
+        let username = e.target()
+            .and_then(|target| target.dyn_into::<Element>().ok())
+            .and_then(|target| target.closest(".note-target").ok().flatten())
+            .and_then(|target| target.get_attribute("data-username"));
+        if let Some(username) = username {
+            open_editor(&username);
+        }
+    });
+    add_event_listener(&document().get_element_by_id("note_save").unwrap(), "click", |_: Event| save_editor());
+    add_event_listener(&document().get_element_by_id("note_cancel").unwrap(), "click", |_: Event| close_editor());
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    use super::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_player_notes() {
+        let mut notes = PlayerNotes::default();
+        notes.set("alice", "  Always goes for the edge\n");
+        notes.set("bob", "Slow");
+        assert_eq!(notes.get("alice"), Some("Always goes for the edge"));
+        assert_eq!(notes.get("carol"), None);
+
+        // Blank notes remove the note
+        notes.set("bob", " ");
+        assert_eq!(notes.get("bob"), None);
+
+        assert_eq!(PlayerNotes::from_json(&notes.to_json()), notes);
+        // Notes that can't be read are lost instead of breaking the page
+        assert_eq!(PlayerNotes::from_json("{\"alice\": 3"), PlayerNotes::default());
+    }
+}
@@ -0,0 +1,72 @@
+//! Alerts for when it becomes the user's turn while they're looking at another tab.
+//! The browser shows a notification if the user allowed it, and the tab's title flashes until they come back.
+//! Alerts are off until the user turns them on in the settings, since asking for permission out of nowhere is rude.
+
+use std::{cell::Cell, rc::Rc};
+
+use wasm_bindgen::{JsCast, JsValue, prelude::Closure};
+use web_sys::{Notification, NotificationOptions, NotificationPermission};
+
+use crate::{document, settings::Settings, window};
+
+/// Title the tab flashes to
+const ALERT_TITLE: &str = "Your turn!";
+/// How long the title stays the same while flashing, in milliseconds
+const FLASH_PERIOD_MS: i32 = 1000;
+
+/// Asks for permission to show notifications, if the user hasn't been asked yet
+pub fn request_permission() {
+    if Notification::permission() == NotificationPermission::Default {
+        // If it gets denied, the title still flashes
+        Notification::request_permission().ok();
+    }
+}
+
+/// Alerts the user when it's their turn
+#[derive(Clone, Debug, Default)]
+pub struct TurnAlert {
+    /// Whether the title is flashing
+    flashing: Rc<Cell<bool>>,
+}
+
+impl TurnAlert {
+    /// Starts the timer that flashes the title
+    pub fn init() -> Result<Self, JsValue> {
+        let alert = Self::default();
+        let flashing = Rc::clone(&alert.flashing);
+        let title = document().title();
+        let on_flash = Closure::wrap(Box::new(move || {
+            if !flashing.get() {
+                return;
+            }
+            if document().hidden() {
+                let next = if document().title() == ALERT_TITLE { title.as_str() } else { ALERT_TITLE };
+                document().set_title(next);
+            } else {
+                // The user came back
+                flashing.set(false);
+                document().set_title(&title);
+            }
+        }) as Box<dyn FnMut()>);
+        window().set_interval_with_callback_and_timeout_and_arguments_0(
+            on_flash.as_ref().unchecked_ref(),
+            FLASH_PERIOD_MS,
+        )?;
+        on_flash.forget();
+        Ok(alert)
+    }
+
+    /// Alerts the user that it's their turn, if alerts are on and they're looking at another tab
+    pub fn your_turn(&self) {
+        if !Settings::load().turn_alerts || !document().hidden() {
+            return;
+        }
+        self.flashing.set(true);
+        document().set_title(ALERT_TITLE);
+        if Notification::permission() == NotificationPermission::Granted {
+            let mut options = NotificationOptions::new();
+            options.body("It's your turn to place a tile.");
+            Notification::new_with_options(ALERT_TITLE, &options).ok();
+        }
+    }
+}
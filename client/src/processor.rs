@@ -2,12 +2,20 @@ use common::message::{Request, Response};
 
 use web_sys::WebSocket;
 
-use crate::{console_log, game::GameWorld};
+use crate::{console_log, game::GameWorld, i18n, render};
 
 /// Processes a response and makes a nonnegative number of requests
 pub fn process_response(resp: Response, game_world: &mut GameWorld) -> Vec<Request> {
     let _doc = web_sys::window().unwrap().document().unwrap();
 
+    // Rejections stay on screen until something else happens
+    match &resp {
+        Response::Rejected{ reason, .. } | Response::RejectedGame{ reason } => render::set_notice(&i18n::reject_reason(*reason)),
+        Response::Replay{ replay: None, .. } => render::set_notice(i18n::MISSING_REPLAY),
+        Response::Pong | Response::ServerInfo{ .. } | Response::ChangedSpectators{ .. } | Response::History{ .. } |
+        Response::Daily{ .. } => {}
+        _ => render::set_notice(""),
+    }
     game_world.handle_response(resp)
 }
 
@@ -1,26 +1,35 @@
-use std::f64::consts::TAU;
+use std::f64::consts::{FRAC_1_SQRT_2, SQRT_2, TAU};
 
 
 
 use std::fmt::{Debug, Display};
+use std::time::Duration;
 use std::hash::Hash;
-use common::{for_each_tile, nalgebra, nalgebra as na, GameInstance};
+use common::{for_each_tile, nalgebra, nalgebra as na, GameStatus, LobbyGame};
+use common::game_state::WinCondition;
+use common::options::{Avatar, Emote, GameOptions, TokenColor};
 
 use common::math::{Pt2, Vec3f, Vec3u, pt2};
-use common::nalgebra::vector;
-use common::{board::{BaseBoard, BasePort, Board, RectangleBoard}, for_each_board, for_each_game, game::{BaseGame, Game, PathGame}, math::Vec2, tile::{RegularTile, Tile}};
+use common::nalgebra::{point, vector};
+use common::{board::{BaseBoard, BasePort, Board, RectangleBoard, SquareOctagonBoard, TorusBoard}, for_each_board, for_each_game, game::{BaseGame, Game, GameId, PathGame}, math::Vec2, tile::{RegularTile, Tile}};
 use common::board::{BaseTLoc, Port, TLoc};
-use common::tile::{BaseGAct, BaseTile, Kind};
-use format_xml::{xml, spaced};
+use common::tile::{BaseGAct, BaseKind, BaseTile, Kind, SquareOctagonKind, SquareOctagonTile};
+use format_xml::xml;
+use getset::CopyGetters;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, EnumString};
 
 use itertools::{Itertools, chain, iproduct, izip};
 use specs::prelude::*;
 use wasm_bindgen::{JsCast};
-use web_sys::{DomParser, Element, SupportedType, SvgElement, SvgMatrix};
+use web_sys::{DomParser, Element, HtmlSelectElement, SupportedType, SvgElement, SvgMatrix};
 
-use crate::ecs::{Collider, Model, TLocLabel, TileSlot, Transform, TileLabel, TileSelect, TileToPlace, GameInstanceLabel};
+use crate::ecs::{Collider, HandTab, Highlight, Model, TLocLabel, TileSlot, Transform, TileLabel, TileSelect, TileToPlace, LobbyGameLabel, ConnectionStatus};
 use crate::game::GameWorld;
-use crate::{SVG_NS, document};
+use crate::notes::PlayerNotes;
+use crate::{SVG_NS, document, i18n, window};
+use crate::settings::Keybindings;
 
 //fn create_svg_element<S: JsCast>(name: &str) -> S {
 //    web_sys::window().unwrap().document().unwrap().create_element_ns(Some("http://www.w3.org/2000/svg"), name)
@@ -47,7 +56,9 @@ pub fn parse_svg(svg_str: &str) -> SvgElement {
 pub enum ScreenState {
     Lobby,
     StatelessGame,
-    Game
+    Game,
+    /// Between turns of a game played on one device, while it gets passed to the next player
+    PassDevice,
 }
 
 impl Display for ScreenState {
@@ -56,6 +67,7 @@ impl Display for ScreenState {
             Self::Lobby => write!(f, "lobby"),
             Self::StatelessGame => write!(f, "stateless-game"),
             Self::Game => write!(f, "game"),
+            Self::PassDevice => write!(f, "pass-device"),
         }
     }
 }
@@ -64,14 +76,232 @@ pub fn set_screen_state(state: ScreenState) {
     document().get_element_by_id("screen").unwrap().set_attribute("state", &state.to_string()).unwrap();
 }
 
+/// Shows how the connection to the server is doing
+pub fn set_connection_status(status: ConnectionStatus) {
+    let text = match status {
+        ConnectionStatus::Connecting => "Connecting...",
+        ConnectionStatus::Connected => "Connected",
+        ConnectionStatus::Unresponsive => "Server not responding",
+        ConnectionStatus::Closed => "Disconnected",
+    };
+    let element = document().get_element_by_id("connection_status").unwrap();
+    element.set_attribute("status", &status.to_string()).unwrap();
+    element.set_inner_html(text);
+}
+
+/// Shows a short message to the user, or hides it if `text` is empty
+pub fn set_notice(text: &str) {
+    document().get_element_by_id("notice").unwrap().set_inner_html(&html_escape::encode_text(text));
+}
+
+/// Shows the flip button only if the tiles in the game can be flipped
+pub fn set_reflection_available(available: bool) {
+    document().get_element_by_id("reflect").unwrap()
+        .toggle_attribute_with_force("hidden", !available).unwrap();
+}
+
+/// Shows the hand exchange button only if the user can exchange their hand right now
+pub fn set_hand_exchange_available(available: bool) {
+    document().get_element_by_id("exchange_hand").unwrap()
+        .toggle_attribute_with_force("hidden", !available).unwrap();
+}
+
+/// Shows the scouting button only if the user can scout the draw pile right now
+pub fn set_scout_available(available: bool) {
+    document().get_element_by_id("scout_pile").unwrap()
+        .toggle_attribute_with_force("hidden", !available).unwrap();
+}
+
+/// Shows the hint button only if the user is placing a tile
+pub fn set_hint_available(available: bool) {
+    document().get_element_by_id("hint").unwrap()
+        .toggle_attribute_with_force("hidden", !available).unwrap();
+}
+
+/// Shows the button for asking to take back a tile placement only if the user can
+pub fn set_undo_available(available: bool) {
+    document().get_element_by_id("request_undo").unwrap()
+        .toggle_attribute_with_force("hidden", !available).unwrap();
+}
+
+/// Shows the prompt to accept or decline someone taking back their tile placement, asking `text`.
+/// Hides it if `text` is None.
+pub fn set_undo_prompt(text: Option<&str>) {
+    document().get_element_by_id("undo_text").unwrap().set_text_content(text);
+    document().get_element_by_id("undo_prompt").unwrap()
+        .toggle_attribute_with_force("hidden", text.is_none()).unwrap();
+}
+
+/// Shows the button for asking to pause the game, or to resume it if it's `paused`, only if `available`
+pub fn set_pause_available(available: bool, paused: bool) {
+    let button = document().get_element_by_id("pause_game").unwrap();
+    button.toggle_attribute_with_force("hidden", !available).unwrap();
+    button.set_attribute("value", if paused { i18n::RESUME_BUTTON } else { i18n::PAUSE_BUTTON }).unwrap();
+}
+
+/// Covers the board to show that the game is paused, or uncovers it
+pub fn set_paused(paused: bool) {
+    document().get_element_by_id("pause_overlay").unwrap()
+        .toggle_attribute_with_force("hidden", !paused).unwrap();
+}
+
+/// Shows the hand tabs only if there's more than one kind of tile to choose between
+pub fn set_hand_tabs_shown(shown: bool) {
+    document().get_element_by_id("hand_tabs").unwrap()
+        .toggle_attribute_with_force("hidden", !shown).unwrap();
+}
+
+/// Most rejected moves the feed shows at once. Older ones make way for new ones.
+const MAX_REJECTION_FEED_ENTRIES: u32 = 5;
+
+/// Adds a line to the feed of rejected moves in the game
+pub fn add_rejection_feed_entry(text: &str) {
+    let feed = document().get_element_by_id("rejected_moves").unwrap();
+    feed.insert_adjacent_html("beforeend", &xml!(<li>{html_escape::encode_text(text)}</li>).to_string())
+        .expect("Cannot add to rejection feed");
+    while feed.child_element_count() > MAX_REJECTION_FEED_ENTRIES {
+        feed.first_element_child().expect("Feed has entries").remove();
+    }
+}
+
+/// Empties the feed of rejected moves
+pub fn clear_rejection_feed() {
+    document().get_element_by_id("rejected_moves").unwrap().set_inner_html("");
+}
+
+/// Shows or hides the trails of where each token has been
+pub fn set_trails_shown(shown: bool) {
+    document().get_element_by_id("svg_root").unwrap()
+        .toggle_attribute_with_force("show-trails", shown).unwrap();
+}
+
+/// Copies a link that invites others to game `id` to the clipboard.
+/// The link is the page's own, so it leads to the same server.
+pub fn copy_invite_link(id: GameId) {
+    let href = window().location().href().unwrap_or_default();
+    let page = href.split_once('#').map_or(href.as_str(), |(page, _)| page);
+    let link = format!("{}#{}", page, id.join_fragment());
+    // The promise only fails if the browser doesn't allow it, and there's nothing more to do then
+    let _ = window().navigator().clipboard().write_text(&link);
+    set_notice(i18n::INVITE_COPIED);
+}
+
+/// Shows a link to the replay of the game that just ended, with `fragment` as its fragment.
+/// Hides it if there's no fragment.
+pub fn set_replay_link(fragment: Option<&str>) {
+    let link = document().get_element_by_id("replay_link").unwrap();
+    match fragment {
+        Some(fragment) => link.set_attribute("href", &format!("#{}", fragment)).unwrap(),
+        None => link.remove_attribute("href").unwrap(),
+    }
+}
+
+/// Shows the button for analyzing the game only if it's over and its replay got saved
+pub fn set_analysis_available(available: bool) {
+    document().get_element_by_id("analyze_game").unwrap()
+        .toggle_attribute_with_force("hidden", !available).unwrap();
+}
+
+/// Shows the button for asking for a rematch only if the user can
+pub fn set_rematch_available(available: bool) {
+    document().get_element_by_id("rematch").unwrap()
+        .toggle_attribute_with_force("hidden", !available).unwrap();
+}
+
+/// Shows the analysis controls with where in the game the analysis is, or hides them if None
+pub fn set_analysis_position(position: Option<&str>) {
+    let panel = document().get_element_by_id("analysis_panel").unwrap();
+    panel.toggle_attribute_with_force("hidden", position.is_none()).unwrap();
+    document().get_element_by_id("analysis_position").unwrap().set_text_content(position);
+}
+
+/// Shows the bound keys on the tile buttons
+pub fn set_button_keys(keybindings: &Keybindings) {
+    for (id, text, key) in [
+        ("rotate_ccw", "Rotate Counterclockwise", &keybindings.rotate_ccw),
+        ("rotate_cw", "Rotate Clockwise", &keybindings.rotate_cw),
+        ("reflect", "Flip", &keybindings.reflect),
+    ] {
+        document().get_element_by_id(id).unwrap()
+            .set_attribute("value", &format!("{} ({})", text, key_name(key))).unwrap();
+    }
+}
+
+/// Short name of a key code, like "e" for "KeyE"
+fn key_name(code: &str) -> String {
+    code.strip_prefix("Key").or_else(|| code.strip_prefix("Digit")).unwrap_or(code).to_lowercase()
+}
+
 pub fn set_username(username: &str) {
     let escaped = html_escape::encode_text(username);
     document().get_element_by_id("username_1").unwrap().set_inner_html(&escaped);
     document().get_element_by_id("username_2").unwrap().set_inner_html(&escaped);
 }
 
+/// Shows who the device should get passed to
+pub fn set_pass_device_username(username: &str) {
+    let escaped = html_escape::encode_text(username);
+    document().get_element_by_id("pass_device_username").unwrap().set_inner_html(&escaped);
+}
+
+/// Fills the dropdown with id `id` with choices, using their names as values.
+/// The first one is selected.
+pub fn set_choices<T: Display>(id: &str, choices: impl IntoIterator<Item = T>) {
+    let options = choices.into_iter()
+        .map(|choice| xml!(<option value={choice}>{choice}</option>).to_string())
+        .join("");
+    document().get_element_by_id(id).unwrap().set_inner_html(&options);
+}
+
+/// Selects the choice `choice` in the dropdown with id `id`
+pub fn set_choice<T: Display>(id: &str, choice: T) {
+    document().get_element_by_id(id).unwrap()
+        .dyn_into::<HtmlSelectElement>().expect("Not a select element")
+        .set_value(&choice.to_string());
+}
+
+/// Writes how much time is left on a clock, like `4:05`.
+/// Partial seconds count as whole ones, so a clock only reads `0:00` once it ran out.
+pub fn format_clock(remaining: Duration) -> String {
+    let secs = remaining.as_secs() + (remaining.subsec_nanos() > 0) as u64;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// Shows how much time `player` has left on their clock in the state panel
+pub fn set_clock(player: u32, remaining: Duration) {
+    if let Some(element) = document().get_element_by_id(&format!("clock_{}", player)) {
+        let text = format_clock(remaining);
+        // Only touch the page when the clock shows a different second
+        if element.text_content().as_deref() != Some(text.as_str()) {
+            element.set_text_content(Some(&text));
+        }
+    }
+}
+
+/// Describes a game's options in a few words
+fn options_summary(options: &GameOptions) -> String {
+    let time_limit = options.turn_time_limit()
+        .map_or("no time limit".to_owned(), |limit| format!("{}s turns", limit.as_secs()));
+    let auto_move = if options.auto_move() { ", auto-move" } else { "" };
+    let clock = options.time_control().map_or(String::new(), |control| format!(", {} clock", control));
+    let open_hands = if options.open_hands() { ", open hands" } else { "" };
+    let hand_exchange = if options.hand_exchange() { ", hand exchange" } else { "" };
+    let scout = if options.scout() { ", scouting" } else { "" };
+    let scoring = if options.win_condition() == WinCondition::Scoring { ", scoring" } else { "" };
+    let rounds = if options.rounds() > 1 { format!(", best of {}", options.rounds()) } else { String::new() };
+    let simultaneous = if options.simultaneous() { ", simultaneous" } else { "" };
+    let spectator_delay = options.spectator_delay()
+        .map_or(String::new(), |delay| format!(", spectators {}s behind", delay.as_secs()));
+    format!(
+        "{}x{}, {}{}{}{}{}{}{}{}{}{}",
+        options.board_width(), options.board_height(), time_limit, auto_move, clock, open_hands, hand_exchange, scout, scoring, rounds,
+        simultaneous, spectator_delay,
+    )
+}
+
 /// A rectangle.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, CopyGetters)]
+#[getset(get_copy = "pub")]
 pub struct Rect {
     left: f32,
     top: f32,
@@ -96,39 +326,147 @@ impl Rect {
     }
 }
 
-/// Renders a game instance as the html string for a selectable game in the lobby
-pub fn render_game_instance(game: &GameInstance) -> String {
-    let title = format!("{}. Normal", game.id().0);
-    let board = game.game().board();
+/// Renders a username as an html string that shows the user's note about the player on hover
+pub fn render_username(username: &str, notes: &PlayerNotes) -> String {
+    let note = notes.get(username).unwrap_or("");
+    xml!(
+        <span class="note-target" data-username={html_escape::encode_double_quoted_attribute(username)}
+            title={html_escape::encode_double_quoted_attribute(note)}>{html_escape::encode_text(username)}</span>
+    ).to_string()
+}
+
+/// The emoji an avatar is drawn as
+fn avatar_emoji(avatar: Avatar) -> &'static str {
+    match avatar {
+        Avatar::Cat => "🐱",
+        Avatar::Dog => "🐶",
+        Avatar::Fox => "🦊",
+        Avatar::Owl => "🦉",
+        Avatar::Frog => "🐸",
+        Avatar::Octopus => "🐙",
+        Avatar::Robot => "🤖",
+        Avatar::Dragon => "🐉",
+    }
+}
+
+/// Renders a username as `render_username` does, with its avatar in front if it has one
+pub fn render_username_with_avatar(username: &str, avatar: Option<Avatar>, notes: &PlayerNotes) -> String {
+    let avatar = avatar.map_or(String::new(), |avatar| xml!(<span class="avatar">{avatar_emoji(avatar)}</span>).to_string());
+    format!("{}{}", avatar, render_username(username, notes))
+}
+
+/// The emoji an emote is drawn as
+pub fn emote_emoji(emote: Emote) -> &'static str {
+    match emote {
+        Emote::ThumbsUp => "👍",
+        Emote::Laugh => "😂",
+        Emote::Wow => "😮",
+        Emote::Sad => "😢",
+        Emote::Angry => "😠",
+        Emote::GoodGame => "🤝",
+    }
+}
+
+/// Fills the bar of emote buttons, one per emote, with ids like `emote_Wow`
+pub fn render_emote_buttons() {
+    let buttons = Emote::iter()
+        .map(|emote| xml!(<input type="button" id=("emote_"{emote}) value={emote_emoji(emote)} title={emote}/>).to_string())
+        .join("");
+    document().get_element_by_id("emotes").unwrap().set_inner_html(&buttons);
+}
+
+/// Shows the bar of emote buttons only if the user can send emotes
+pub fn set_emotes_available(available: bool) {
+    document().get_element_by_id("emotes").unwrap()
+        .toggle_attribute_with_force("hidden", !available).unwrap();
+}
+
+fn game_title(game: &LobbyGame) -> String {
+    format!("{}. {}", game.id(), options_summary(game.options()))
+}
+
+fn game_status(game: &LobbyGame) -> &'static str {
+    match game.status() {
+        GameStatus::NotStarted => "Game Not Started",
+        GameStatus::Started => "Game Started",
+        GameStatus::Over => "Game Over",
+    }
+}
+
+fn game_players(game: &LobbyGame) -> String {
+    let notes = PlayerNotes::load();
+    let players = game.players().iter().zip(game.avatars())
+        .map(|(player, avatar)| render_username_with_avatar(player, *avatar, &notes))
+        .join("; ");
+    format!("Players: {}", players)
+}
+
+/// The board of a game in the lobby, as its options say
+fn lobby_board(game: &LobbyGame) -> BaseBoard {
+    game.options().game().board()
+}
+
+/// Renders a game as the html string for a selectable game in the lobby
+pub fn render_lobby_game(game: &LobbyGame) -> String {
+    let board = lobby_board(game);
     let board_svg = board.render();
     let board_bb = board.bounding_box();
-    let status = if let Some(state) = game.state() {
-        if state.game_over() { "Game Over" } else { "Game Started" }
-    } else { "Game Not Started" };
-    let players = game.players().iter().map(|player| html_escape::encode_text(player)).join("; ");
 
     xml!(
         <div class="game-box">
-            <div class="title">{ title }</div>
+            <div class="title">{ game_title(game) }</div>
             <svg xmlns={SVG_NS} class="board" viewBox={board_bb.to_viewbox_value()}>{ board_svg }</svg>
-            <div class="status">{ status }</div>
-            <div class="players">"Players: "{ players }</div>
+            <div class="status">{ game_status(game) }</div>
+            <div class="players">{ game_players(game) }</div>
         </div>
     ).to_string()
 }
 
-/// Creates a entity corresponding to a game instance.
-pub fn game_entity(game: GameInstance, world: &mut World, id_counter: &mut u64) -> Entity {
-    let elem = parse_elem(&render_game_instance(&game));
+/// Creates a entity corresponding to a game in the lobby.
+pub fn game_entity(game: LobbyGame, world: &mut World, id_counter: &mut u64) -> Entity {
+    let elem = parse_elem(&render_lobby_game(&game));
+    // Ids are random, so this doesn't say anything about the game, but it keeps each game in the same place when it's rendered again
+    let order = (game.id().0 >> 32) as i32;
     world.create_entity()
         .with(Model::new(
-            &elem, -(game.id().0 as i32), &GameWorld::game_panel(), id_counter
+            &elem, order, &GameWorld::game_panel(), id_counter
         ))
         .with(Collider::new(&elem))
-        .with(GameInstanceLabel(game))
+        .with(LobbyGameLabel(game))
         .build()
 }
 
+/// Shows the new version of a game on its entity, rewriting only the parts that changed.
+/// The element stays, so hovering it doesn't flicker.
+/// Gives the game back if its title or board changed, since then the entity has to be created again.
+pub fn update_game_entity(entity: Entity, game: LobbyGame, world: &mut World) -> Result<(), LobbyGame> {
+    let mut labels = world.write_component::<LobbyGameLabel>();
+    let label = match labels.get_mut(entity) {
+        Some(label) => label,
+        None => return Err(game),
+    };
+    if game_title(&label.0) != game_title(&game) || lobby_board(&label.0).render() != lobby_board(&game).render() {
+        return Err(game);
+    }
+    let elem = match world.read_component::<Model>().get(entity).and_then(Model::element) {
+        Some(elem) => elem,
+        None => return Err(game),
+    };
+
+    for (class, old, new) in [
+        ("status", game_status(&label.0).to_owned(), game_status(&game).to_owned()),
+        ("players", game_players(&label.0), game_players(&game)),
+    ] {
+        if old != new {
+            if let Some(part) = elem.get_elements_by_class_name(class).item(0) {
+                part.set_inner_html(&new);
+            }
+        }
+    }
+    label.0 = game;
+    Ok(())
+}
+
 pub trait SvgMatrixExt {
     /// Transforms a position with this matrix
     fn transform(&self, position: Pt2) -> Pt2;
@@ -150,6 +488,10 @@ pub trait BoardExt: Board {
     /// Gets the bounding box of the board in SVG space
     fn bounding_box(&self) -> Rect;
 
+    /// Gets the region of SVG space the main view shows when zoomed out:
+    /// the board with enough room around it for tokens on its edge
+    fn bounds(&self) -> Rect;
+
     /// Render the tile to an SVG string. Returns a string instead of SvgElement for ease of use with `xml!`
     fn render(&self) -> String;
 
@@ -157,6 +499,13 @@ pub trait BoardExt: Board {
 
     fn loc_position(&self, loc: &Self::TLoc) -> Pt2;
 
+    /// The lines to draw for a trail through `trail`, in order.
+    /// Usually there's just one, but boards can break it up where the trail jumps from one part of the board to another.
+    fn trail_positions(&self, trail: &[Self::Port]) -> Vec<Vec<Pt2>> {
+        let points = trail.iter().map(|port| self.port_position(port)).collect_vec();
+        if points.len() > 1 { vec![points] } else { vec![] }
+    }
+
     /// Render the collider for a specific tile location.
     fn render_collider(&self, loc: &Self::TLoc) -> SvgElement;
 
@@ -169,11 +518,18 @@ impl BoardExt for RectangleBoard {
         Rect::from_ltrb(-0.1, -0.1, self.width() as f32 + 0.1, self.height() as f32 + 0.1)
     }
 
+    fn bounds(&self) -> Rect {
+        Rect::from_ltrb(-0.5, -0.5, self.width() as f32 + 0.5, self.height() as f32 + 0.5)
+    }
+
     fn render(&self) -> String {
         format!(r##"<g xmlns="{}" class="rectangular-board">"##, SVG_NS) +
             &chain!(
-                iproduct!(0..self.height(), 0..self.width()).map(|(y, x)|
-                    xml!(<rect x={x} y={y} width="1" height="1"/>).to_string()),
+                iproduct!(0..self.height(), 0..self.width()).map(|(y, x)| if self.is_blocked(&point![x, y]) {
+                    xml!(<rect x={x} y={y} width="1" height="1" class="rectangular-board-blocked"/>).to_string()
+                } else {
+                    xml!(<rect x={x} y={y} width="1" height="1"/>).to_string()
+                }),
                 self.boundary_ports().into_iter().map(|(min, d)| {
                     let v = self.port_position(&(min, d));
                     let dx = if d.x == 0 { 0.1 } else { 0.0 };
@@ -210,6 +566,197 @@ impl BoardExt for RectangleBoard {
             .with(Transform::new(self.loc_position(loc)))
             .with(TLocLabel(loc.wrap_base()))
             .with(TileSlot)
+            .with(Highlight::default())
+            .build()
+    }
+}
+
+impl TorusBoard {
+    /// The board with its edges cut apart, which it's drawn as
+    fn flattened(&self) -> RectangleBoard {
+        RectangleBoard::new(self.width(), self.height(), self.ports_per_edge())
+    }
+
+    /// Where a port is drawn next to the tile at `loc`, which it may be on the opposite edge of the board from
+    fn port_position_near(&self, port: &<Self as Board>::Port, loc: &<Self as Board>::TLoc) -> Pt2 {
+        let mut position = self.port_position(port);
+        if position.x < loc.x as f64 {
+            position.x += self.width() as f64;
+        }
+        if position.y < loc.y as f64 {
+            position.y += self.height() as f64;
+        }
+        position
+    }
+}
+
+/// Renders an arrow head centered at `center` pointing along `dir`, which should have length 1
+fn render_wrap_arrow(center: Pt2, dir: Vec2) -> String {
+    let side = vector![-dir.y, dir.x] * 0.15;
+    let (back, tip) = (center - dir * 0.08, center + dir * 0.08);
+    let points = [back + side, tip, back - side].iter().map(|p| format!("{},{}", p.x, p.y)).join(" ");
+    xml!(<polyline points={points} class="torus-board-wrap"/>).to_string()
+}
+
+impl BoardExt for TorusBoard {
+    fn bounding_box(&self) -> Rect {
+        // Leaves room for the arrows that show how the edges are joined
+        Rect::from_ltrb(-0.4, -0.4, self.width() as f32 + 0.4, self.height() as f32 + 0.4)
+    }
+
+    fn bounds(&self) -> Rect {
+        self.flattened().bounds()
+    }
+
+    fn render(&self) -> String {
+        let (width, height) = (self.width() as f64, self.height() as f64);
+        // Matching arrows on opposite edges show which edges are joined: one arrow across, two arrows down
+        let arrows = [
+            (pt2(-0.25, height / 2.0), vector![1.0, 0.0]),
+            (pt2(width + 0.25, height / 2.0), vector![1.0, 0.0]),
+            (pt2(width / 2.0, -0.3), vector![0.0, 1.0]),
+            (pt2(width / 2.0, -0.18), vector![0.0, 1.0]),
+            (pt2(width / 2.0, height + 0.18), vector![0.0, 1.0]),
+            (pt2(width / 2.0, height + 0.3), vector![0.0, 1.0]),
+        ];
+
+        format!(r##"<g xmlns="{}" class="rectangular-board">"##, SVG_NS) +
+            &chain!(
+                iproduct!(0..self.height(), 0..self.width()).map(|(y, x)|
+                    xml!(<rect x={x} y={y} width="1" height="1"/>).to_string()),
+                self.boundary_ports().into_iter().map(|(min, d)| {
+                    let v = self.port_position(&(min, d));
+                    let dx = if d.x == 0 { 0.1 } else { 0.0 };
+                    let dy = if d.y == 0 { 0.1 } else { 0.0 };
+                    xml!(<line x1={v.x - dx} x2={v.x + dx} y1={v.y - dy} y2={v.y + dy} class="rectangular-board-notch"/>).to_string()
+                }),
+                arrows.into_iter().map(|(center, dir)| render_wrap_arrow(center, dir))
+            )
+                .join("") +
+            r##"</g>"##
+    }
+
+    fn port_position(&self, port: &<Self as Board>::Port) -> Pt2 {
+        self.flattened().port_position(port)
+    }
+
+    fn loc_position(&self, loc: &Self::TLoc) -> Pt2 {
+        self.flattened().loc_position(loc)
+    }
+
+    /// Trails break where they cross an edge, and pick up again on the opposite one
+    fn trail_positions(&self, trail: &[<Self as Board>::Port]) -> Vec<Vec<Pt2>> {
+        let mut lines: Vec<Vec<Pt2>> = vec![];
+        for (from, to) in trail.iter().tuple_windows() {
+            let loc = self.port_locs(from).into_iter()
+                .find(|loc| self.port_locs(to).contains(loc))
+                .expect("Trail steps should go through a tile");
+            let (from, to) = (self.port_position_near(from, &loc), self.port_position_near(to, &loc));
+            match lines.last_mut() {
+                Some(line) if line.last() == Some(&from) => line.push(to),
+                _ => lines.push(vec![from, to]),
+            }
+        }
+        lines
+    }
+
+    fn render_collider(&self, loc: &Self::TLoc) -> SvgElement {
+        self.flattened().render_collider(loc)
+    }
+
+    fn create_loc_collider_entity(&self, loc: &Self::TLoc, world: &mut World, id_counter: &mut u64) -> Entity {
+        self.flattened().create_loc_collider_entity(loc, world, id_counter)
+    }
+}
+
+/// Width of an octagon with unit sides, which is also the distance between neighboring octagons
+const OCTAGON_WIDTH: f64 = 1.0 + SQRT_2;
+
+/// Rotates a vector clockwise by 45 degrees
+fn rotate_eighth_turn(vec: Vec2) -> Vec2 {
+    vector![vec.x - vec.y, vec.x + vec.y] * FRAC_1_SQRT_2
+}
+
+impl SquareOctagonBoard {
+    /// The corners of the tile at `loc`, relative to its center, in the same order as its edges.
+    /// Squares stand on their corners so their edges line up with the octagons' slanted ones.
+    fn loc_polygon(&self, loc: &<Self as Board>::TLoc) -> Vec<Vec2> {
+        match self.kind_at(loc) {
+            SquareOctagonKind::Octagon => regular_polygon_points(8),
+            SquareOctagonKind::Square => regular_polygon_points(4).into_iter().map(rotate_eighth_turn).collect_vec(),
+        }
+    }
+
+    /// Where a port is drawn, and which way the edge it's on runs
+    fn port_position_and_direction(&self, port: &<Self as Board>::Port) -> (Pt2, Vec2) {
+        let loc = self.port_locs(port)[0];
+        let index = self.loc_ports(&loc).into_iter().position(|p| &p == port).expect("Port should be around its tile");
+        let polygon = self.loc_polygon(&loc);
+        let ports_per_edge = self.ports_per_edge() as usize;
+        let (edge, i) = (index / ports_per_edge, index % ports_per_edge + 1);
+        let (p0, p1) = (polygon[edge], polygon[(edge + 1) % polygon.len()]);
+        let position = self.loc_position(&loc) + p0 + (p1 - p0) * i as f64 / (ports_per_edge + 1) as f64;
+        (position, (p1 - p0).normalize())
+    }
+}
+
+impl BoardExt for SquareOctagonBoard {
+    fn bounding_box(&self) -> Rect {
+        let (width, height) = (self.width() as f64 * OCTAGON_WIDTH, self.height() as f64 * OCTAGON_WIDTH);
+        Rect::from_ltrb(-0.1, -0.1, width as f32 + 0.1, height as f32 + 0.1)
+    }
+
+    fn bounds(&self) -> Rect {
+        let (width, height) = (self.width() as f64 * OCTAGON_WIDTH, self.height() as f64 * OCTAGON_WIDTH);
+        Rect::from_ltrb(-0.5, -0.5, width as f32 + 0.5, height as f32 + 0.5)
+    }
+
+    fn render(&self) -> String {
+        format!(r##"<g xmlns="{}" class="rectangular-board">"##, SVG_NS) +
+            &chain!(
+                self.all_locs().into_iter().map(|loc| {
+                    let center = self.loc_position(&loc);
+                    let points = self.loc_polygon(&loc).into_iter().map(|p| format!("{},{}", center.x + p.x, center.y + p.y)).join(" ");
+                    xml!(<polygon points={points}/>).to_string()
+                }),
+                self.boundary_ports().into_iter().map(|port| {
+                    let (v, dir) = self.port_position_and_direction(&port);
+                    let notch = vector![-dir.y, dir.x] * 0.1;
+                    xml!(<line x1={v.x - notch.x} x2={v.x + notch.x} y1={v.y - notch.y} y2={v.y + notch.y} class="rectangular-board-notch"/>).to_string()
+                })
+            )
+                .join("") +
+            r##"</g>"##
+    }
+
+    fn port_position(&self, port: &<Self as Board>::Port) -> Pt2 {
+        self.port_position_and_direction(port).0
+    }
+
+    /// Octagons are at even coordinates and squares at odd ones, so each step in a coordinate is half an octagon
+    fn loc_position(&self, loc: &Self::TLoc) -> Pt2 {
+        (loc.cast::<f64>() + vector![1.0, 1.0]) * (OCTAGON_WIDTH / 2.0)
+    }
+
+    fn render_collider(&self, loc: &Self::TLoc) -> SvgElement {
+        let points = self.loc_polygon(loc).into_iter().map(|p| format!("{},{}", p.x, p.y)).join(" ");
+        let svg_str = xml! {
+            <g xmlns={SVG_NS} fill="transparent">
+                <polygon points={points}/>
+            </g>
+        }.to_string();
+        parse_svg(&svg_str)
+    }
+
+    fn create_loc_collider_entity(&self, loc: &Self::TLoc, world: &mut World, id_counter: &mut u64) -> Entity {
+        let svg = self.render_collider(loc);
+        world.create_entity()
+            .with(Model::new(&svg, Collider::ORDER_TILE_LOC, &GameWorld::svg_root(), id_counter))
+            .with(Collider::new(&svg))
+            .with(Transform::new(self.loc_position(loc)))
+            .with(TLocLabel(loc.wrap_base()))
+            .with(TileSlot)
+            .with(Highlight::default())
             .build()
     }
 }
@@ -219,12 +766,17 @@ impl BoardExt for RectangleBoard {
 pub trait BaseBoardExt {
     fn bounding_box(&self) -> Rect;
 
+    fn bounds(&self) -> Rect;
+
     fn render(&self) -> String;
     
     fn port_position(&self, port: &BasePort) -> Pt2;
 
     fn loc_position(&self, loc: &BaseTLoc) -> Pt2;
 
+    /// The lines to draw for a trail through `trail`, in order
+    fn trail_positions(&self, trail: &[BasePort]) -> Vec<Vec<Pt2>>;
+
     /// Creates an entity (mainly for collision detection) at a specific tile location.
     fn create_loc_collider_entity(&self, loc: &BaseTLoc, world: &mut World, id_counter: &mut u64) -> Entity;
 }
@@ -239,6 +791,12 @@ for_each_board! {
             }
         }
 
+        fn bounds(&self) -> Rect {
+            match self {
+                $($($p)*::$x(b) => b.bounds()),*
+            }
+        }
+
         fn render(&self) -> String {
             match self {
                 $($($p)*::$x(b) => b.render()),*
@@ -257,6 +815,14 @@ for_each_board! {
             }
         }
 
+        fn trail_positions(&self, trail: &[BasePort]) -> Vec<Vec<Pt2>> {
+            match self {
+                $($($p)*::$x(b) => b.trail_positions(&trail.iter()
+                    .map(|port| <$t as Board>::Port::unwrap_base_ref(port).clone())
+                    .collect_vec())),*
+            }
+        }
+
         fn create_loc_collider_entity(&self, loc: &BaseTLoc, world: &mut World, id_counter: &mut u64) -> Entity {
             match self {
                 $($($p)*::$x(b) => b.create_loc_collider_entity(
@@ -292,10 +858,20 @@ fn regular_polygon_svg_str(n: u32) -> String {
 /// Extension trait for Tile, mainly for rendering since
 /// the server should know nothing about rendering
 pub trait TileExt: Tile {
+    /// Gets the bounding box of the tile in SVG space
+    fn bounds(&self) -> Rect;
+
     fn render(&self) -> String;
 }
 
 impl<const EDGES: u32> TileExt for RegularTile<EDGES> {
+    fn bounds(&self) -> Rect {
+        let points = regular_polygon_points(EDGES);
+        let (left, right) = points.iter().map(|p| p.x).minmax().into_option().expect("Polygon has no points");
+        let (top, bottom) = points.iter().map(|p| p.y).minmax().into_option().expect("Polygon has no points");
+        Rect::from_ltrb(left as f32, top as f32, right as f32, bottom as f32)
+    }
+
     fn render(&self) -> String {
         if self.visible() {
             let connections = (0..self.num_ports()).map(|i| self.output(i)).collect_vec();
@@ -340,9 +916,28 @@ impl<const EDGES: u32> TileExt for RegularTile<EDGES> {
     }
 }
 
+/// Squares are drawn standing on their corners, the way they sit between octagons on the board
+impl TileExt for SquareOctagonTile {
+    fn bounds(&self) -> Rect {
+        match self {
+            Self::Octagon(tile) => tile.bounds(),
+            Self::Square(_) => Rect::from_ltrb(-FRAC_1_SQRT_2 as f32, -FRAC_1_SQRT_2 as f32, FRAC_1_SQRT_2 as f32, FRAC_1_SQRT_2 as f32),
+        }
+    }
+
+    fn render(&self) -> String {
+        match self {
+            Self::Octagon(tile) => tile.render(),
+            Self::Square(tile) => xml!(<g xmlns={SVG_NS} transform="rotate(45)">{tile.render()}</g>).to_string(),
+        }
+    }
+}
+
 /// Extension trait for BaseTile, mainly for rendering since
 /// the server should know nothing about rendering
 pub trait BaseTileExt {
+    fn bounds(&self) -> Rect;
+
     fn render(&self) -> String;
 
     fn create_hand_entity(&self, index: u32, action: &BaseGAct, world: &mut World, id_counter: &mut u64) -> Entity;
@@ -358,6 +953,10 @@ for_each_tile! {
     p::x, t => 
 
     impl BaseTileExt for BaseTile {
+        fn bounds(&self) -> Rect {
+            match self { $($($p)*::$x(b) => b.bounds()),* }
+        }
+
         fn render(&self) -> String {
             match self { $($($p)*::$x(b) => b.render()),* }
         }
@@ -365,7 +964,7 @@ for_each_tile! {
         fn create_hand_entity(&self, index: u32, action: &BaseGAct, world: &mut World, id_counter: &mut u64) -> Entity {
             match self { $($($p)*::$x(b) => {
                 let svg = self.apply_action(action).render();
-                let wrapper = parse_svg(&wrap_svg(&svg, ""));
+                let wrapper = parse_svg(&wrap_svg(&svg, "", self.bounds()));
                 wrapper.set_attribute("class", "bottom-tile tile-unselected").expect("Cannot set tile select class");
                 world.create_entity()
                     .with(TileLabel(self.clone()))
@@ -448,6 +1047,28 @@ for_each_game! {
     }
 }
 
+/// Name of a kind of tile, for its hand tab
+pub fn kind_name(kind: &BaseKind) -> &'static str {
+    match kind {
+        BaseKind::Unit(()) => "Tiles",
+        BaseKind::SquareOctagon(SquareOctagonKind::Octagon) => "Octagons",
+        BaseKind::SquareOctagon(SquareOctagonKind::Square) => "Squares",
+    }
+}
+
+/// Creates an entity for the hand tab of a kind of tile, which shows how many tiles of that kind are in the hand
+pub fn create_hand_tab_entity(kind: &BaseKind, count: usize, world: &mut World, id_counter: &mut u64) -> Entity {
+    let elem = document().create_element("div").expect("Cannot create hand tab");
+    elem.set_attribute("class", "hand-tab").expect("Cannot set hand tab class");
+    elem.set_text_content(Some(&format!("{} ({})", kind_name(kind), count)));
+    let parent = document().get_element_by_id("hand_tabs").expect("Missing hand tabs");
+    world.create_entity()
+        .with(Model::new(&elem, 0, &parent, id_counter))
+        .with(Collider::new(&elem))
+        .with(HandTab(kind.clone()))
+        .build()
+}
+
 /// Renders a port collider, used for detecting whether the mouse is hovering over a port
 pub fn render_port_collider() -> SvgElement {
     let svg_str = xml! {
@@ -470,31 +1091,223 @@ fn hsv_to_rgb(mut h: f32, s: f32, v: f32) -> Vec3f {
 
 pub const TOKEN_RADIUS: f64 = 0.1;
 
-/// Renders a player token, given the player index and the number of players.
-pub fn render_token(index: u32, num_players: u32, id_counter: &mut u64) -> String {
-    let color = hsv_to_rgb(index as f32 / num_players as f32, 1.0, 1.0);
-    let darker = color * 3.0 / 4.0;
+/// The Okabe-Ito colors, which stay apart for the common kinds of color blindness
+const COLORBLIND_COLORS: [[u32; 3]; 8] = [
+    [0xe6, 0x9f, 0x00],
+    [0x56, 0xb4, 0xe9],
+    [0x00, 0x9e, 0x73],
+    [0xf0, 0xe4, 0x42],
+    [0x00, 0x72, 0xb2],
+    [0xd5, 0x5e, 0x00],
+    [0xcc, 0x79, 0xa7],
+    [0x00, 0x00, 0x00],
+];
+
+/// How players' tokens get told apart
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Display, EnumIter, EnumString, Serialize, Deserialize)]
+pub enum TokenPalette {
+    /// Hues spread evenly around the color wheel
+    #[default]
+    Hues,
+    /// Colors safe for color blindness, with a pattern on each token so they don't need color at all
+    Colorblind,
+}
+
+impl TokenPalette {
+    /// The color of a player, given the player index, the number of players, and the color they picked if they did
+    pub fn color(self, index: u32, num_players: u32, chosen: Option<TokenColor>) -> Vec3f {
+        match (self, chosen) {
+            (_, Some(chosen)) => token_color_rgb(chosen),
+            (Self::Hues, None) => hsv_to_rgb(index as f32 / num_players as f32, 1.0, 1.0),
+            (Self::Colorblind, None) => Vec3f::from(COLORBLIND_COLORS[index as usize % COLORBLIND_COLORS.len()].map(|c| c as f32 / 255.0)),
+        }
+    }
+
+    /// The pattern on a player's token, if the palette uses them
+    pub fn pattern(self, index: u32) -> Option<TokenPattern> {
+        match self {
+            Self::Hues => None,
+            Self::Colorblind => Some(TOKEN_PATTERNS[index as usize % TOKEN_PATTERNS.len()]),
+        }
+    }
+}
+
+/// The color of a token color a player can pick
+fn token_color_rgb(color: TokenColor) -> Vec3f {
+    let rgb: [u32; 3] = match color {
+        TokenColor::Red => [0xe6, 0x19, 0x4b],
+        TokenColor::Orange => [0xf5, 0x82, 0x31],
+        TokenColor::Yellow => [0xff, 0xe1, 0x19],
+        TokenColor::Green => [0x3c, 0xb4, 0x4b],
+        TokenColor::Teal => [0x46, 0xf0, 0xf0],
+        TokenColor::Blue => [0x43, 0x63, 0xd8],
+        TokenColor::Purple => [0x91, 0x1e, 0xb4],
+        TokenColor::Pink => [0xf0, 0x32, 0xe6],
+    };
+    Vec3f::from(rgb.map(|c| c as f32 / 255.0))
+}
+
+/// Pattern drawn over a token
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenPattern {
+    Rings,
+    Stripes,
+    Dots,
+}
+
+/// Patterns in the order players get them.
+/// The number of them doesn't divide the number of colorblind colors, so no two of the first 24 players look alike.
+const TOKEN_PATTERNS: [TokenPattern; 3] = [TokenPattern::Rings, TokenPattern::Stripes, TokenPattern::Dots];
+
+/// Formats a color as a hex code
+fn color_hex(color: Vec3f) -> String {
     let color: Vec3u = na::try_convert(color * 255.0).expect("Color conversion failed");
-    let darker: Vec3u = na::try_convert(darker * 255.0).expect("Color conversion failed");
+    format!("#{:02x}{:02x}{:02x}", color.x, color.y, color.z)
+}
+
+/// Renders a pattern over a token of color `color`, in black or white, whichever stands out more
+fn render_token_pattern(pattern: TokenPattern, color: Vec3f, id: u64) -> String {
+    let luminance = 0.299 * color.x + 0.587 * color.y + 0.114 * color.z;
+    let ink = if luminance > 0.5 { "#000000" } else { "#ffffff" };
+    let stroke_width = TOKEN_RADIUS * 0.15;
+    match pattern {
+        TokenPattern::Rings => xml!(
+            <g fill="none" stroke={ink} stroke-width={stroke_width}>
+                <circle r={TOKEN_RADIUS * 0.7}/>
+                <circle r={TOKEN_RADIUS * 0.3}/>
+            </g>
+        ).to_string(),
+
+        TokenPattern::Stripes => {
+            let spacing = TOKEN_RADIUS * 0.5;
+            xml!(
+                <g>
+                    <defs>
+                        <pattern id=("p"{id}) patternUnits="userSpaceOnUse" width={spacing} height={spacing}
+                            patternTransform="rotate(45)">
+                            <rect width={spacing / 2.0} height={spacing} fill={ink}/>
+                        </pattern>
+                    </defs>
+                    <circle r={TOKEN_RADIUS * 0.85} fill=("url('#p"{id}"')")/>
+                </g>
+            ).to_string()
+        }
+
+        TokenPattern::Dots => {
+            let dots = (0..6)
+                .map(|i| {
+                    let angle = TAU * i as f64 / 6.0;
+                    let center = vector![angle.cos(), angle.sin()] * TOKEN_RADIUS * 0.55;
+                    xml!(<circle cx={center.x} cy={center.y} r={TOKEN_RADIUS * 0.14}/>).to_string()
+                })
+                .join("");
+            xml!(
+                <g fill={ink}>
+                    <circle r={TOKEN_RADIUS * 0.14}/>
+                    {dots}
+                </g>
+            ).to_string()
+        }
+    }
+}
+
+/// Renders a player token, given the player index, the number of players, and the color they picked if they did.
+pub fn render_token(index: u32, num_players: u32, palette: TokenPalette, chosen: Option<TokenColor>, id_counter: &mut u64) -> String {
+    let color = palette.color(index, num_players, chosen);
+    let darker = color * 3.0 / 4.0;
     let id = {*id_counter += 1; *id_counter - 1};
+    let pattern = palette.pattern(index)
+        .map(|pattern| render_token_pattern(pattern, color, id))
+        .unwrap_or_default();
     let result = xml!(
         <g xmlns={SVG_NS} transform="translate(0, 0)">
             <defs>
                 <radialGradient id=("g"{id})>
-                    <stop offset="0%" stop-color=("#"{color.x;02x}{color.y;02x}{color.z;02x})/>
-                    <stop offset="100%" stop-color=("#"{darker.x;02x}{darker.y;02x}{darker.z;02x})/>
+                    <stop offset="0%" stop-color={color_hex(color)}/>
+                    <stop offset="100%" stop-color={color_hex(darker)}/>
                 </radialGradient>
             </defs>
             <circle r={TOKEN_RADIUS} fill=("url('#g"{id}"')")/>
+            {pattern}
         </g>
     ).to_string();
     result
 }
 
+/// Renders the path a player's token took through each line of `lines`, in the player's color.
+/// Trails only show when toggled on.
+pub fn render_trail(index: u32, num_players: u32, palette: TokenPalette, chosen: Option<TokenColor>, lines: &[Vec<Pt2>]) -> String {
+    let color: Vec3u = na::try_convert(palette.color(index, num_players, chosen) * 255.0).expect("Color conversion failed");
+    let polylines = lines.iter().map(|points| {
+        let points = points.iter().map(|point| format!("{},{}", point.x, point.y)).join(" ");
+        xml!(
+            <polyline class="trail" points={points} stroke=("#"{color.x;02x}{color.y;02x}{color.z;02x})/>
+        ).to_string()
+    }).join("");
+    xml!(
+        <g xmlns={SVG_NS} pointer-events="none">{polylines}</g>
+    ).to_string()
+}
+
+/// Renders a see-through player token for previewing where it would end up.
+/// The user's own token is less see-through, and tokens that would die are outlined in red.
+pub fn render_token_preview(
+    index: u32, num_players: u32, palette: TokenPalette, chosen: Option<TokenColor>, own: bool, dies: bool, id_counter: &mut u64
+) -> String {
+    let class = chain!(
+        ["token-preview"],
+        own.then_some("token-preview-own"),
+        dies.then_some("token-preview-dead"),
+    ).join(" ");
+    xml!(
+        <g xmlns={SVG_NS} class={class} pointer-events="none">{render_token(index, num_players, palette, chosen, id_counter)}</g>
+    ).to_string()
+}
+
+/// Something labeled in the debug overlay
+#[derive(Clone, Debug)]
+pub struct DebugOverlayItem {
+    /// Bounding box, in board space
+    pub rect: Rect,
+    /// Whether it has a collider
+    pub collider: bool,
+    pub label: String,
+}
+
+/// Short label for a port in the debug overlay
+pub fn debug_port_label(port: &BasePort) -> String {
+    match port {
+        BasePort::Pt2uVec2u((loc, dir)) => format!("({},{})+({},{})", loc.x, loc.y, dir.x, dir.y),
+    }
+}
+
+/// Short label for a tile location in the debug overlay
+pub fn debug_tloc_label(loc: &BaseTLoc) -> String {
+    match loc {
+        BaseTLoc::Pt2u(loc) => format!("({},{})", loc.x, loc.y),
+    }
+}
+
+/// Renders the debug overlay. It ignores the mouse so it doesn't get in the way of colliders.
+pub fn render_debug_overlay(items: &[DebugOverlayItem]) -> SvgElement {
+    let items_svg = items.iter()
+        .map(|item| xml!(
+            <g class={if item.collider { "debug-item debug-collider" } else { "debug-item" }}>
+                <rect x={item.rect.left} y={item.rect.top} width={item.rect.width} height={item.rect.height}/>
+                <text x={item.rect.left} y={item.rect.top}>{item.label}</text>
+            </g>
+        ).to_string())
+        .join("");
+    let svg_str = xml!(
+        <g xmlns={SVG_NS} class="debug-overlay" pointer-events="none">{items_svg}</g>
+    ).to_string();
+    parse_svg(&svg_str)
+}
+
 /// Wraps the SVG in an `<svg>` element of a specific class.
-/// TODO: The viewport is set so the svg fits snugly inside.
-pub fn wrap_svg(svg: &str, class: &str) -> String {
+/// The viewport is set to `bounds`, so an svg with those bounds fits snugly inside.
+pub fn wrap_svg(svg: &str, class: &str, bounds: Rect) -> String {
     xml!(
-        <svg xmlns={SVG_NS} class={class} viewBox={spaced!(-0.5, -0.5, 1, 1)}>{svg}</svg>
+        <svg xmlns={SVG_NS} class={class} viewBox={bounds.to_viewbox_value()}>{svg}</svg>
     ).to_string()
 }
\ No newline at end of file
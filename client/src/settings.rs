@@ -0,0 +1,217 @@
+//! Settings the user picks, saved in the browser so they survive reloads.
+//! They get loaded at startup, so a saved username skips the prompt for one.
+
+use std::{iter, sync::{Arc, Mutex}};
+
+use common::{message::Request, options::Avatar};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use wasm_bindgen::JsCast;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement, KeyboardEvent, WebSocket};
+
+use crate::{add_event_listener, document, ecs::ButtonAction, game::GameWorld, local_storage, notify, processor::send_request, render::{self, TokenPalette}, templates::GameTemplate};
+
+/// The avatar choice for not having one
+const NO_AVATAR: &str = "None";
+
+/// Key the settings are saved under in local storage
+const STORAGE_KEY: &str = "tsurust-settings";
+
+/// Keys for the tile buttons, as key codes so they stay put on other keyboard layouts
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keybindings {
+    pub rotate_ccw: String,
+    pub rotate_cw: String,
+    pub reflect: String,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self {
+            rotate_ccw: "KeyE".to_owned(),
+            rotate_cw: "KeyR".to_owned(),
+            reflect: "KeyF".to_owned(),
+        }
+    }
+}
+
+impl Keybindings {
+    /// The key that does a button's action
+    pub fn key(&self, action: &ButtonAction) -> &str {
+        match action {
+            ButtonAction::Rotation{ num_times } if *num_times < 0 => &self.rotate_ccw,
+            ButtonAction::Rotation{ .. } => &self.rotate_cw,
+            ButtonAction::Reflection => &self.reflect,
+        }
+    }
+
+    /// The key bound to the setting with id `id` in the settings panel
+    fn key_mut(&mut self, id: &str) -> &mut String {
+        match id {
+            "key_rotate_ccw" => &mut self.rotate_ccw,
+            "key_rotate_cw" => &mut self.rotate_cw,
+            _ => &mut self.reflect,
+        }
+    }
+}
+
+/// Every setting. Ones missing from what was saved get their default.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Username to join with instead of asking for one
+    pub username: Option<String>,
+    /// Picture shown next to the username
+    pub avatar: Option<Avatar>,
+    /// Whether to alert the user when it's their turn while they're looking at another tab
+    pub turn_alerts: bool,
+    /// Whether to stop the board's ambient animations
+    pub reduce_motion: bool,
+    pub token_palette: TokenPalette,
+    pub keybindings: Keybindings,
+    /// Saved choices for the game creation form, sorted by name
+    pub templates: Vec<GameTemplate>,
+}
+
+impl Settings {
+    /// Loads the saved settings.
+    /// Missing or unreadable settings, or a browser that doesn't allow storage, count as the defaults.
+    pub fn load() -> Self {
+        local_storage()
+            .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten())
+            .map_or_else(Self::default, |json| Self::from_json(&json))
+    }
+
+    /// Saves the settings, if the browser allows it
+    pub fn save(&self) {
+        if let Some(storage) = local_storage() {
+            storage.set_item(STORAGE_KEY, &self.to_json()).ok();
+        }
+    }
+
+    /// Changes the saved settings
+    pub fn update(change: impl FnOnce(&mut Self)) {
+        let mut settings = Self::load();
+        change(&mut settings);
+        settings.save();
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Serialization went wrong")
+    }
+
+    fn from_json(json: &str) -> Self {
+        serde_json::from_str(json).unwrap_or_default()
+    }
+}
+
+fn input(id: &str) -> HtmlInputElement {
+    document().get_element_by_id(id).expect("Missing settings input")
+        .dyn_into().expect("Setting isn't an input element")
+}
+
+/// Saves the username to join with, and shows it in the settings panel
+pub fn save_username(username: String) {
+    input("settings_username").set_value(&username);
+    Settings::update(|settings| settings.username = Some(username));
+}
+
+/// Ids of the keybinding inputs in the settings panel
+const KEY_INPUT_IDS: [&str; 3] = ["key_rotate_ccw", "key_rotate_cw", "key_reflect"];
+
+/// Shows the saved settings in the settings panel and adds the listeners that change them.
+/// Changes that other players see get sent through `ws`.
+pub fn init(game_world: &Arc<Mutex<GameWorld>>, ws: &WebSocket) {
+    let mut settings = Settings::load();
+
+    input("settings_username").set_value(settings.username.as_deref().unwrap_or(""));
+    add_event_listener(&input("settings_username"), "change", |_: Event| {
+        let username = input("settings_username").value().trim().to_owned();
+        Settings::update(|settings| settings.username = (!username.is_empty()).then_some(username));
+    });
+
+    render::set_choices("avatar", iter::once(NO_AVATAR.to_owned()).chain(Avatar::iter().map(|avatar| avatar.to_string())));
+    render::set_choice("avatar", settings.avatar.map_or(NO_AVATAR.to_owned(), |avatar| avatar.to_string()));
+    let cws = ws.clone();
+    add_event_listener(&document().get_element_by_id("avatar").unwrap(), "change", move |_: Event| {
+        let avatar = document().get_element_by_id("avatar").unwrap()
+            .dyn_into::<HtmlSelectElement>().expect("Avatar dropdown isn't a select element")
+            .value().parse().ok();
+        Settings::update(|settings| settings.avatar = avatar);
+        send_request(&Request::SetAvatar{ avatar }, &cws);
+    });
+
+    input("turn_alerts").set_checked(settings.turn_alerts);
+    add_event_listener(&input("turn_alerts"), "change", |_: Event| {
+        let checked = input("turn_alerts").checked();
+        Settings::update(|settings| settings.turn_alerts = checked);
+        if checked {
+            notify::request_permission();
+        }
+    });
+
+    input("reduce_motion").set_checked(settings.reduce_motion);
+    let cgw = Arc::clone(game_world);
+    add_event_listener(&input("reduce_motion"), "change", move |_: Event| {
+        let checked = input("reduce_motion").checked();
+        Settings::update(|settings| settings.reduce_motion = checked);
+        cgw.lock().unwrap().set_animations(!checked);
+    });
+
+    render::set_choices("token_palette", TokenPalette::iter());
+    render::set_choice("token_palette", settings.token_palette);
+    let cgw = Arc::clone(game_world);
+    add_event_listener(&document().get_element_by_id("token_palette").unwrap(), "change", move |_: Event| {
+        let palette: TokenPalette = document().get_element_by_id("token_palette").unwrap()
+            .dyn_into::<HtmlSelectElement>().expect("Token palette dropdown isn't a select element")
+            .value().parse().unwrap_or_default();
+        Settings::update(|settings| settings.token_palette = palette);
+        cgw.lock().unwrap().set_token_palette(palette);
+    });
+
+    for id in KEY_INPUT_IDS {
+        input(id).set_value(settings.keybindings.key_mut(id));
+        let cgw = Arc::clone(game_world);
+        add_event_listener(&input(id), "keydown", move |e: KeyboardEvent| {
+            // The key gets bound instead of pressed
+            e.prevent_default();
+            e.stop_propagation();
+            input(id).set_value(&e.code());
+            let mut settings = Settings::load();
+            *settings.keybindings.key_mut(id) = e.code();
+            settings.save();
+            cgw.lock().unwrap().set_keybindings(&settings.keybindings);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    use super::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_settings_json() {
+        let mut settings = Settings::default();
+        settings.username = Some("alice".to_owned());
+        settings.token_palette = TokenPalette::Colorblind;
+        settings.keybindings.rotate_cw = "KeyT".to_owned();
+        assert_eq!(Settings::from_json(&settings.to_json()), settings);
+
+        // Settings added later get their default
+        let old = Settings::from_json("{\"username\": \"bob\"}");
+        assert_eq!(old.username.as_deref(), Some("bob"));
+        assert_eq!(old.keybindings, Keybindings::default());
+        assert!(!old.turn_alerts);
+
+        // Settings that can't be read are lost instead of breaking the page
+        assert_eq!(Settings::from_json("{\"username\": 3"), Settings::default());
+
+        assert_eq!(settings.keybindings.key(&ButtonAction::Rotation{ num_times: -1 }), "KeyE");
+        assert_eq!(settings.keybindings.key(&ButtonAction::Rotation{ num_times: 1 }), "KeyT");
+    }
+}
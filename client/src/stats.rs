@@ -0,0 +1,78 @@
+//! Statistics about how each player is doing, for showing alongside the game.
+//! They get updated from each turn's result as it comes in, so they only cover turns the client saw.
+
+use common::game_state::{BaseTurnEvent, BaseTurnEvents};
+use getset::CopyGetters;
+
+/// How a player has been doing so far
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct PlayerStats {
+    /// Number of tiles the player placed
+    tiles_placed: u32,
+    /// Number of tiles the player's token moved through
+    distance: u32,
+    /// Number of other players the player killed
+    kills: u32,
+}
+
+/// Statistics of every player in a game, indexed by player
+#[derive(Clone, Debug, Default)]
+pub struct GameStats(Vec<PlayerStats>);
+
+impl GameStats {
+    pub fn new(num_players: u32) -> Self {
+        Self(vec![PlayerStats::default(); num_players as usize])
+    }
+
+    /// Statistics of a player
+    pub fn player(&self, player: u32) -> PlayerStats {
+        self.0[player as usize]
+    }
+
+    /// Adds what happened during a turn
+    pub fn record_turn(&mut self, result: &BaseTurnEvents) {
+        for event in result.events() {
+            match event {
+                BaseTurnEvent::TilePlaced{ player, .. } => self.0[*player as usize].tiles_placed += 1,
+                BaseTurnEvent::PlayerMoved{ player, path } => self.0[*player as usize].distance += path.len() as u32,
+                BaseTurnEvent::PlayerDied{ kill } if !kill.self_elimination() => self.0[kill.killer() as usize].kills += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::{WrapBase, board::{Board, Port, RectangleBoard}, game::PathGame};
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    use super::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_record_turns() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::new(board, start_ports.clone(), [((), 3)]).wrap_base();
+        let mut state = game.new_state(2);
+        state.place_player(0, &start_ports[0].wrap_base());
+        state.place_player(1, &start_ports[10].wrap_base());
+
+        let mut stats = GameStats::new(2);
+        while !state.game_over() {
+            let player = state.turn_player();
+            let (kind, index, action, loc) = state.legal_moves(&game, player).into_iter().next().unwrap();
+            stats.record_turn(&state.take_turn_placing_tile(&game, &kind, index, &action, &loc));
+        }
+
+        for player in 0..2 {
+            // Tiles get placed in front of the placer, so they move through at least those
+            assert!(stats.player(player).tiles_placed() > 0);
+            assert!(stats.player(player).distance() >= stats.player(player).tiles_placed());
+            assert!(stats.player(player).kills() <= 1);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Named sets of choices in the game creation form, so favorite setups are one pick away.
+//! They get saved in the browser with the rest of the settings.
+
+use common::{clock::TimeControl, config::{MatchRounds, SpectatorDelay}, message::Request, options::{GamePreset, RejectionFeed}};
+use format_xml::xml;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsCast;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+
+use crate::{add_event_listener, document, settings::Settings, window};
+
+/// The clock dropdown's choice for games without clocks
+pub const NO_CLOCK: &str = "No clock";
+
+/// The spectator delay dropdown's choice for games whose spectators hear about moves right away
+pub const NO_DELAY: &str = "No delay";
+
+/// The choices in the game creation form
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameTemplate {
+    pub name: String,
+    pub preset: GamePreset,
+    pub open_hands: bool,
+    pub hand_exchange: bool,
+    pub scout: bool,
+    #[serde(default)]
+    pub rejection_feed: RejectionFeed,
+    #[serde(default)]
+    pub rounds: MatchRounds,
+    #[serde(default)]
+    pub simultaneous: bool,
+    #[serde(default)]
+    pub time_control: Option<TimeControl>,
+    #[serde(default)]
+    pub spectator_delay: Option<SpectatorDelay>,
+}
+
+impl GameTemplate {
+    /// The request that creates a game with these choices
+    pub fn request(&self) -> Request {
+        Request::CreateGame{
+            preset: self.preset, open_hands: self.open_hands, hand_exchange: self.hand_exchange, scout: self.scout,
+            rejection_feed: self.rejection_feed, rounds: self.rounds, simultaneous: self.simultaneous, time_control: self.time_control,
+            spectator_delay: self.spectator_delay,
+        }
+    }
+
+    /// Reads the choices in the form, naming them `name`
+    pub fn from_form(name: String) -> Self {
+        Self {
+            name,
+            preset: select("preset").value().parse().unwrap_or(GamePreset::Standard),
+            open_hands: checkbox("open_hands").checked(),
+            hand_exchange: checkbox("hand_exchange").checked(),
+            scout: checkbox("scout").checked(),
+            rejection_feed: select("rejection_feed").value().parse().unwrap_or_default(),
+            rounds: select("rounds").value().parse().ok().and_then(|rounds| MatchRounds::new(rounds).ok()).unwrap_or_default(),
+            simultaneous: checkbox("simultaneous").checked(),
+            // The first choice is no clock, which doesn't parse
+            time_control: select("time_control").value().parse().ok(),
+            // Delays are shown in whole seconds, like "30s"
+            spectator_delay: select("spectator_delay").value().strip_suffix('s').and_then(|secs| secs.parse().ok())
+                .and_then(|secs| SpectatorDelay::from_repr(secs).ok()),
+        }
+    }
+
+    /// Fills in the form with these choices
+    fn fill_form(&self) {
+        select("preset").set_value(&self.preset.to_string());
+        checkbox("open_hands").set_checked(self.open_hands);
+        checkbox("hand_exchange").set_checked(self.hand_exchange);
+        checkbox("scout").set_checked(self.scout);
+        select("rejection_feed").set_value(&self.rejection_feed.to_string());
+        select("rounds").set_value(&self.rounds.to_string());
+        checkbox("simultaneous").set_checked(self.simultaneous);
+        select("time_control").set_value(&self.time_control.map_or(NO_CLOCK.to_owned(), |control| control.to_string()));
+        select("spectator_delay").set_value(&self.spectator_delay.map_or(NO_DELAY.to_owned(), |delay| delay.to_string()));
+    }
+}
+
+/// Adds a template to `templates`, replacing the one with the same name if there is one.
+/// Templates stay sorted by name.
+pub fn add_template(templates: &mut Vec<GameTemplate>, template: GameTemplate) {
+    match templates.binary_search_by(|other| other.name.cmp(&template.name)) {
+        Ok(pos) => templates[pos] = template,
+        Err(pos) => templates.insert(pos, template),
+    }
+}
+
+fn select(id: &str) -> HtmlSelectElement {
+    document().get_element_by_id(id).expect("Missing dropdown")
+        .dyn_into().expect("Not a select element")
+}
+
+fn checkbox(id: &str) -> HtmlInputElement {
+    document().get_element_by_id(id).expect("Missing checkbox")
+        .dyn_into().expect("Not an input element")
+}
+
+/// Lists the saved templates in the dropdown, with `selected` selected
+fn show_templates(templates: &[GameTemplate], selected: &str) {
+    let options = std::iter::once(render_option("", "Saved templates"))
+        .chain(templates.iter().map(|template| render_option(&template.name, &template.name)))
+        .collect::<String>();
+    select("template").set_inner_html(&options);
+    select("template").set_value(selected);
+}
+
+fn render_option(value: &str, text: &str) -> String {
+    xml!(
+        <option value={html_escape::encode_double_quoted_attribute(value)}>{html_escape::encode_text(text)}</option>
+    ).to_string()
+}
+
+/// Shows the saved templates and adds the listeners for picking, saving, and deleting them
+pub fn init() {
+    show_templates(&Settings::load().templates, "");
+
+    add_event_listener(&select("template"), "change", |_: Event| {
+        let name = select("template").value();
+        if let Some(template) = Settings::load().templates.iter().find(|template| template.name == name) {
+            template.fill_form();
+        }
+    });
+
+    add_event_listener(&document().get_element_by_id("save_template").unwrap(), "click", |_: Event| {
+        let name = window().prompt_with_message_and_default("Name the template", &select("template").value())
+            .unwrap_or(None)
+            .map(|name| name.trim().to_owned())
+            .filter(|name| !name.is_empty());
+        if let Some(name) = name {
+            let mut settings = Settings::load();
+            add_template(&mut settings.templates, GameTemplate::from_form(name.clone()));
+            settings.save();
+            show_templates(&settings.templates, &name);
+        }
+    });
+
+    add_event_listener(&document().get_element_by_id("delete_template").unwrap(), "click", |_: Event| {
+        let name = select("template").value();
+        let mut settings = Settings::load();
+        settings.templates.retain(|template| template.name != name);
+        settings.save();
+        show_templates(&settings.templates, "");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+    use super::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn template(name: &str, preset: GamePreset) -> GameTemplate {
+        GameTemplate { name: name.to_owned(), preset, open_hands: false, hand_exchange: true, scout: false, rejection_feed: RejectionFeed::Host,
+            rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_add_template() {
+        let mut templates = vec![];
+        add_template(&mut templates, template("quick", GamePreset::Blitz));
+        add_template(&mut templates, template("long", GamePreset::Marathon));
+        assert_eq!(templates.iter().map(|template| template.name.as_str()).collect::<Vec<_>>(), ["long", "quick"]);
+
+        // Saving under the same name replaces the template
+        add_template(&mut templates, template("quick", GamePreset::Casual));
+        assert_eq!(templates.len(), 2);
+        assert_eq!(templates[1].preset, GamePreset::Casual);
+        assert!(matches!(templates[1].request(), Request::CreateGame{ preset: GamePreset::Casual, hand_exchange: true, .. }));
+    }
+}
@@ -0,0 +1,35 @@
+//! The tile count in the state panel: every tile of the game's tile set, marked by whether it's on the board,
+//! in a hand you can see, or still unaccounted for, so players can count which tiles are left to draw.
+
+use common::{game::BaseGame, game_state::{BaseGameState, TileWhereabouts}};
+use format_xml::xml;
+use web_sys::Element;
+
+use crate::{document, render::{self, BaseTileExt}};
+
+fn list() -> Element {
+    document().get_element_by_id("tile_count_list").expect("Missing tile count list")
+}
+
+/// Shows where each tile of `game`'s tile set is as far as whoever's looking at `state` can tell
+pub fn show(game: &BaseGame, state: &BaseGameState) {
+    let whereabouts = state.tile_whereabouts(game);
+    let unseen = whereabouts.iter().filter(|(_, whereabouts)| *whereabouts == TileWhereabouts::Unseen).count();
+    document().get_element_by_id("tile_count_unseen").expect("Missing unseen tile count")
+        .set_text_content(Some(&format!("{} of {}", unseen, whereabouts.len())));
+
+    list().set_inner_html(&whereabouts.into_iter().map(|(tile, whereabouts)| {
+        let class = match whereabouts {
+            TileWhereabouts::OnBoard => "tile-count-tile tile-count-on-board",
+            TileWhereabouts::InHand => "tile-count-tile tile-count-in-hand",
+            TileWhereabouts::Unseen => "tile-count-tile",
+        };
+        xml!(<div class={class}>{render::wrap_svg(&tile.render(), "tile-count-svg", tile.bounds())}</div>).to_string()
+    }).collect::<String>());
+}
+
+/// Empties the count, for leaving the game
+pub fn clear() {
+    list().set_inner_html("");
+    document().get_element_by_id("tile_count_unseen").expect("Missing unseen tile count").set_text_content(None);
+}
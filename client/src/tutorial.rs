@@ -0,0 +1,109 @@
+//! The script of the tutorial, and the hint bubble that walks the user through it.
+//! The tutorial is a local game against one AI opponent, split into steps. Each step shows a hint,
+//! maybe highlights part of the page, and waits for the user to do something before going on to the next.
+
+use wasm_bindgen::JsCast;
+use web_sys::Element;
+
+use crate::document;
+
+/// What a tutorial step waits for before going on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wait {
+    /// The user clicking the hint's Next button
+    Next,
+    /// The user placing their token
+    PlaceToken,
+    /// The user picking up a tile from their hand
+    SelectTile,
+    /// The user rotating the tile they picked up
+    RotateTile,
+    /// The user placing a tile on the board
+    PlaceTile,
+}
+
+/// A step of the tutorial
+#[derive(Clone, Copy, Debug)]
+pub struct Step {
+    pub hint: &'static str,
+    /// Id of the page element to highlight, if any
+    pub highlight: Option<&'static str>,
+    pub wait: Wait,
+}
+
+/// Every step of the tutorial, in order
+pub const STEPS: &[Step] = &[
+    Step {
+        hint: "Welcome! Everyone has a token that follows the paths on the tiles. \
+            Keep your token on the board longer than everyone else to win.",
+        highlight: None,
+        wait: Wait::Next,
+    },
+    Step {
+        hint: "Pick a spot on the edge of the board to start your token from.",
+        highlight: Some("svg_root"),
+        wait: Wait::PlaceToken,
+    },
+    Step {
+        hint: "These are the tiles in your hand. Once it's your turn, pick one up by clicking it.",
+        highlight: Some("bottom_panel"),
+        wait: Wait::SelectTile,
+    },
+    Step {
+        hint: "Tiles can be turned before they get placed. Rotate yours with these buttons, or with the keys shown on them.",
+        highlight: Some("rotate_cw"),
+        wait: Wait::RotateTile,
+    },
+    Step {
+        hint: "Now place the tile in front of your token. Red spots would send your token off the board.",
+        highlight: Some("svg_root"),
+        wait: Wait::PlaceTile,
+    },
+    Step {
+        hint: "Your token followed the path on the tile until it got to a spot with no tile. \
+            Every token touching a new tile moves along it, so your tiles can move other players too.",
+        highlight: None,
+        wait: Wait::Next,
+    },
+    Step {
+        hint: "Tokens that reach the edge of the board, or run into each other, are out. Try placing another tile.",
+        highlight: Some("svg_root"),
+        wait: Wait::PlaceTile,
+    },
+    Step {
+        hint: "That's all there is to it! Play against AI opponents with Play Offline, or join a game in the lobby.",
+        highlight: None,
+        wait: Wait::Next,
+    },
+];
+
+/// Shows the hint of a step, highlighting what it points at
+pub fn show_step(step: &Step) {
+    clear_highlight();
+    if let Some(id) = step.highlight {
+        document().get_element_by_id(id).expect("Missing highlighted element")
+            .set_attribute("tutorial-highlight", "").unwrap();
+    }
+    let hint = document().get_element_by_id("tutorial_hint").expect("Missing tutorial hint");
+    document().get_element_by_id("tutorial_text").expect("Missing tutorial hint text")
+        .set_text_content(Some(step.hint));
+    document().get_element_by_id("tutorial_next").expect("Missing tutorial Next button")
+        .toggle_attribute_with_force("hidden", step.wait != Wait::Next).unwrap();
+    hint.remove_attribute("hidden").unwrap();
+}
+
+/// Hides the hint and the highlight, for when the tutorial is over
+pub fn hide() {
+    clear_highlight();
+    document().get_element_by_id("tutorial_hint").expect("Missing tutorial hint")
+        .set_attribute("hidden", "").unwrap();
+}
+
+fn clear_highlight() {
+    let highlighted = document().query_selector_all("[tutorial-highlight]").expect("Invalid selector");
+    for i in 0..highlighted.length() {
+        if let Some(element) = highlighted.get(i).and_then(|node| node.dyn_into::<Element>().ok()) {
+            element.remove_attribute("tutorial-highlight").unwrap();
+        }
+    }
+}
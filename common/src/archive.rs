@@ -0,0 +1,154 @@
+//! Archives of finished games, for keeping them long term and passing them between programs.
+//! An archive is a single JSON document with everything needed to replay the game and say how it went:
+//!
+//! - `format`: always `"tsurust-archive"`, so other JSON files don't get mistaken for archives
+//! - `version`: version of the format. Readers reject versions newer than the ones they know.
+//! - `options`: the options the game was played with
+//! - `usernames`: the players' usernames, in turn order
+//! - `summary`: how the game ended, so it doesn't have to be replayed to find out
+//! - `replay`: the board, the tiles in the deck, the state when the game started with nothing hidden,
+//!   and every move made after that, encoded the same way as in replay links
+//!
+//! The board state keys things by coordinates, which JSON objects can't, so the replay stays encoded.
+//! The usernames and summary get worked out from the replay when the archive is made, and checked against it when it's read.
+
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+use crate::{options::GameOptions, replay::{Replay, ReplayMove}};
+
+/// What the `format` field of every archive says
+pub const ARCHIVE_FORMAT: &str = "tsurust-archive";
+/// Newest version of the archive format
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// How a game ended
+#[derive(Clone, Debug, PartialEq, Eq, Getters, CopyGetters, Serialize, Deserialize)]
+pub struct ArchiveSummary {
+    /// Usernames of the players who won
+    #[getset(get = "pub")]
+    winners: Vec<String>,
+    /// Number of tiles placed on the board
+    #[getset(get_copy = "pub")]
+    num_tiles_placed: u32,
+    /// Number of moves in the replay, skipped turns and players leaving included
+    #[getset(get_copy = "pub")]
+    num_moves: u32,
+}
+
+impl ArchiveSummary {
+    fn new(replay: &Replay) -> Self {
+        let state = replay.final_state();
+        Self {
+            winners: (0..state.num_players())
+                .filter(|player| state.won(*player))
+                .map(|player| replay.usernames()[player as usize].clone())
+                .collect(),
+            num_tiles_placed: replay.moves().iter().filter(|mv| matches!(mv, ReplayMove::PlaceTile{ .. })).count() as u32,
+            num_moves: replay.moves().len() as u32,
+        }
+    }
+}
+
+/// A finished game, archived
+#[derive(Clone, Debug, Getters, Serialize, Deserialize)]
+pub struct GameArchive {
+    format: String,
+    version: u32,
+    #[getset(get = "pub")]
+    options: GameOptions,
+    usernames: Vec<String>,
+    #[getset(get = "pub")]
+    summary: ArchiveSummary,
+    #[getset(get = "pub")]
+    #[serde(serialize_with = "encode_replay", deserialize_with = "decode_replay")]
+    replay: Replay,
+}
+
+fn encode_replay<S: Serializer>(replay: &Replay, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&replay.encode())
+}
+
+fn decode_replay<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Replay, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    Replay::decode(&encoded).ok_or_else(|| D::Error::custom("invalid replay"))
+}
+
+impl GameArchive {
+    /// Archives the replay of a game played with some options
+    pub fn new(options: GameOptions, replay: Replay) -> Self {
+        let usernames = replay.usernames().clone();
+        let summary = ArchiveSummary::new(&replay);
+        Self { format: ARCHIVE_FORMAT.to_owned(), version: ARCHIVE_VERSION, options, usernames, summary, replay }
+    }
+
+    /// Writes the archive as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Serialization went wrong")
+    }
+
+    /// Reads an archive written by `to_json`, checking that it's one this version can read
+    /// and that its usernames and summary match its replay
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let archive: Self = serde_json::from_str(json).map_err(|err| format!("Not a game archive: {}", err))?;
+        if archive.format != ARCHIVE_FORMAT {
+            return Err(format!("Not a game archive: format is {:?}", archive.format));
+        }
+        if archive.version > ARCHIVE_VERSION {
+            return Err(format!("Archive version {} is newer than the newest known version, {}", archive.version, ARCHIVE_VERSION));
+        }
+        if &archive.usernames != archive.replay.usernames() || archive.summary != ArchiveSummary::new(&archive.replay) {
+            return Err("Archive summary doesn't match its replay".to_owned());
+        }
+        Ok(archive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::{Board, Port, RectangleBoard}, game::PathGame, options::GamePreset, WrapBase};
+
+    use super::*;
+
+    /// A two player game where the first player walks off the board right away
+    fn test_archive() -> GameArchive {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::new(board, start_ports.clone(), [((), 3)]).wrap_base();
+        let mut state = game.new_state(2);
+        let mut replay = Replay::new(game.clone(), vec!["alice".to_owned(), "bob".to_owned()], state.clone());
+        for mv in [
+            ReplayMove::PlaceToken{ player: 0, port: start_ports[0].wrap_base() },
+            ReplayMove::PlaceToken{ player: 1, port: start_ports[10].wrap_base() },
+            ReplayMove::Leave{ player: 0 },
+        ] {
+            mv.apply(&game, &mut state);
+            replay.push(mv);
+        }
+        GameArchive::new(GamePreset::Blitz.options(), replay)
+    }
+
+    #[test]
+    fn test_archive_json() {
+        let archive = test_archive();
+        assert_eq!(archive.summary().winners(), &["bob"]);
+        assert_eq!(archive.summary().num_tiles_placed(), 0);
+        assert_eq!(archive.summary().num_moves(), 3);
+
+        let read = GameArchive::from_json(&archive.to_json()).unwrap();
+        assert_eq!(read.options(), &GamePreset::Blitz.options());
+        assert_eq!(read.summary(), archive.summary());
+        assert_eq!(
+            bincode::serialize(&read.replay().final_state()).unwrap(),
+            bincode::serialize(&archive.replay().final_state()).unwrap(),
+        );
+
+        assert!(GameArchive::from_json("{}").is_err());
+        let mut newer = archive.clone();
+        newer.version = ARCHIVE_VERSION + 1;
+        assert!(GameArchive::from_json(&newer.to_json()).is_err());
+        let mut tampered = archive;
+        tampered.summary.winners = vec!["alice".to_owned()];
+        assert!(GameArchive::from_json(&tampered.to_json()).is_err());
+    }
+}
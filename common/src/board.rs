@@ -1,12 +1,13 @@
-use crate::math::{Pt2i, Pt2u, Vec2u};
-use crate::tile::Kind;
+use crate::math::{Pt2i, Pt2u, Vec2i, Vec2u};
+use crate::tile::{Kind, SquareOctagonKind};
 use na::point;
 use nalgebra as na;
 use nalgebra::vector;
 use itertools::{Itertools, chain, iproduct};
 
 use serde::{Deserialize, Serialize};
-use getset::{CopyGetters};
+use getset::{CopyGetters, Getters};
+use fnv::FnvHashSet;
 use crate::{wrap_functions, impl_wrap_functions};
 
 use std::fmt::Debug;
@@ -46,6 +47,8 @@ macro_rules! for_each_board {
         }
         __mac! {
             ($crate::board::BaseBoard)::RectangleBoard: $crate::board::RectangleBoard,
+            ($crate::board::BaseBoard)::TorusBoard: $crate::board::TorusBoard,
+            ($crate::board::BaseBoard)::SquareOctagonBoard: $crate::board::SquareOctagonBoard,
         }
     };
 
@@ -70,9 +73,14 @@ for_each_board! {
                 <$t as Board>::Port::unwrap_base_ref(port)
             ).into_iter().map(|loc| loc.wrap_base()).collect()),* }
         }
+
+        /// Whether a tile location is blocked off
+        pub fn is_blocked(&self, loc: &BaseTLoc) -> bool {
+            match self { $($($p)*::$x(s) => s.is_blocked(<$t as Board>::TLoc::unwrap_base_ref(loc))),* }
+        }
     }
 
-    $($crate::impl_wrap_base!(BaseBoard::$x($t)))*;
+    $($crate::impl_wrap_base!(BaseBoard::$x($t));)*
 }
 
 /// A board in the path game, parameterized by player location (port) type, tile location type, and tile kind type
@@ -91,37 +99,61 @@ pub trait Board: Clone + Debug + Serialize + for<'a> Deserialize<'a> {
     /// All the kinds of tiles used by the board
     fn all_kinds(&self) -> Vec<Self::Kind>;
 
+    /// Whether a tile location is on the board
+    fn contains(&self, loc: &Self::TLoc) -> bool;
+
     /// The kind of tile that goes in a specific location
     fn kind_at(&self, loc: &Self::TLoc) -> Self::Kind;
 
     /// The player ports around a tile location, in order
     fn loc_ports(&self, loc: &Self::TLoc) -> Vec<Self::Port>;
 
-    /// The tile locations around a port, in no particular order
+    /// The tile locations around a port, in a fixed order.
+    /// A path that comes out onto a port bordering more than one other location goes on through the first one with a tile.
     fn port_locs(&self, port: &Self::Port) -> Vec<Self::TLoc>;
 
     /// Tile configuration for the board, used for generating tiles
     fn tile_config(&self) -> Self::TileConfig;
+
+    /// Whether players who end up on the same port kill each other.
+    /// On boards with an edge, walking off it is enough to end every path, so they don't need this.
+    fn collisions_kill(&self) -> bool {
+        false
+    }
+
+    /// Whether a tile location is blocked off, so no tile can go there and paths that run into it end
+    fn is_blocked(&self, _loc: &Self::TLoc) -> bool {
+        false
+    }
 }
 
 /// A tile config that just stores the number of ports per edge.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct PortsPerEdgeTileConfig(pub u32);
 
-/// A rectangular board with square tiles.
-#[derive(Clone, Debug, Serialize, Deserialize, CopyGetters)]
+/// A rectangular board with square tiles, some of which can be blocked off.
+#[derive(Clone, Debug, Serialize, Deserialize, CopyGetters, Getters)]
 pub struct RectangleBoard {
     #[getset(get_copy = "pub")]
     width: u32,
     #[getset(get_copy = "pub")]
     height: u32,
     #[getset(get_copy = "pub")]
-    ports_per_edge: u32
+    ports_per_edge: u32,
+    /// Tile locations that are blocked off, like holes in the board or obstacles on it
+    #[getset(get = "pub")]
+    blocked: FnvHashSet<Pt2u>,
 }
 
 impl RectangleBoard {
     pub fn new(width: u32, height: u32, ports_per_edge: u32) -> Self {
-        Self { width, height, ports_per_edge }
+        Self { width, height, ports_per_edge, blocked: FnvHashSet::default() }
+    }
+
+    /// Blocks off some tile locations. Locations off the board are ignored.
+    pub fn with_blocked(mut self, blocked: impl IntoIterator<Item = Pt2u>) -> Self {
+        self.blocked.extend(blocked.into_iter().filter(|loc| loc.x < self.width && loc.y < self.height));
+        self
     }
 }
 
@@ -140,17 +172,24 @@ impl Board for RectangleBoard {
         ).collect_vec()
     }
 
+    /// Ports next to blocked tile locations are left out, since tokens there could never move
     fn boundary_ports(&self) -> Vec<Self::Port> {
         chain!(
             iproduct!([0, self.height], 0..self.width, 1..=self.ports_per_edge).map(|(y, x, i)| (point![x, y], vector![i, 0])),
             iproduct!([0, self.width], 0..self.height, 1..=self.ports_per_edge).map(|(x, y, i)| (point![x, y], vector![0, i]))
-        ).collect_vec()
+        )
+            .filter(|port| !self.port_locs(port).iter().any(|loc| self.is_blocked(loc)))
+            .collect_vec()
     }
 
     fn all_kinds(&self) -> Vec<Self::Kind> {
         vec![()]
     }
 
+    fn contains(&self, loc: &Self::TLoc) -> bool {
+        loc.x < self.width && loc.y < self.height
+    }
+
     fn kind_at(&self, _: &Self::TLoc) -> Self::Kind {
     }
 
@@ -176,6 +215,211 @@ impl Board for RectangleBoard {
     fn tile_config(&self) -> Self::TileConfig {
         PortsPerEdgeTileConfig(self.ports_per_edge)
     }
+
+    fn is_blocked(&self, loc: &Self::TLoc) -> bool {
+        self.blocked.contains(loc)
+    }
+}
+
+/// A rectangular board with square tiles whose opposite edges are joined, like a torus.
+/// Paths that leave one edge come back in through the opposite one, so nobody can walk off the board.
+/// Ports on the bottom and right edges are the same as the ones on the top and left edges, and go by the latter.
+#[derive(Clone, Debug, Serialize, Deserialize, CopyGetters)]
+pub struct TorusBoard {
+    #[getset(get_copy = "pub")]
+    width: u32,
+    #[getset(get_copy = "pub")]
+    height: u32,
+    #[getset(get_copy = "pub")]
+    ports_per_edge: u32
+}
+
+impl TorusBoard {
+    pub fn new(width: u32, height: u32, ports_per_edge: u32) -> Self {
+        Self { width, height, ports_per_edge }
+    }
+
+    /// Wraps a point onto the board
+    fn wrap(&self, point: Pt2i) -> Pt2u {
+        point![point.x.rem_euclid(self.width as i32) as u32, point.y.rem_euclid(self.height as i32) as u32]
+    }
+}
+
+impl Board for TorusBoard {
+    /// Coordinates of a tile
+    type TLoc = Pt2u;
+    /// Floored coordinates of a port, wrapped onto the board, followed by fractional coordinates times `ports_per_edge + 1`
+    type Port = (Pt2u, Vec2u);
+    type Kind = ();
+    type TileConfig = PortsPerEdgeTileConfig;
+
+    fn all_ports(&self) -> Vec<Self::Port> {
+        chain!(
+            iproduct!(0..self.height, 0..self.width, 1..=self.ports_per_edge).map(|(y, x, i)| (point![x, y], vector![i, 0])),
+            iproduct!(0..self.width, 0..self.height, 1..=self.ports_per_edge).map(|(x, y, i)| (point![x, y], vector![0, i]))
+        ).collect_vec()
+    }
+
+    /// The board has no boundary, so these are the ports on the seams where its edges got joined
+    fn boundary_ports(&self) -> Vec<Self::Port> {
+        chain!(
+            iproduct!(0..self.width, 1..=self.ports_per_edge).map(|(x, i)| (point![x, 0], vector![i, 0])),
+            iproduct!(0..self.height, 1..=self.ports_per_edge).map(|(y, i)| (point![0, y], vector![0, i]))
+        ).collect_vec()
+    }
+
+    fn all_kinds(&self) -> Vec<Self::Kind> {
+        vec![()]
+    }
+
+    fn contains(&self, loc: &Self::TLoc) -> bool {
+        loc.x < self.width && loc.y < self.height
+    }
+
+    fn kind_at(&self, _: &Self::TLoc) -> Self::Kind {
+    }
+
+    fn loc_ports(&self, loc: &Self::TLoc) -> Vec<Self::Port> {
+        let loc_i = na::convert::<_, Pt2i>(*loc);
+        let right = self.wrap(loc_i + vector![1, 0]);
+        let below = self.wrap(loc_i + vector![0, 1]);
+        chain!(
+            (1..=self.ports_per_edge).map(|i| (*loc, vector![i, 0])),
+            (1..=self.ports_per_edge).map(|i| (right, vector![0, i])),
+            (1..=self.ports_per_edge).rev().map(|i| (below, vector![i, 0])),
+            (1..=self.ports_per_edge).rev().map(|i| (*loc, vector![0, i]))
+        ).collect_vec()
+    }
+
+    fn port_locs(&self, port: &Self::Port) -> Vec<Self::TLoc> {
+        let p0 = na::convert::<_, Pt2i>(port.0);
+        let p1 = p0 + if port.1[1] == 0 { vector![0, -1] } else { vector![-1, 0] };
+        vec![port.0, self.wrap(p1)]
+    }
+
+    fn tile_config(&self) -> Self::TileConfig {
+        PortsPerEdgeTileConfig(self.ports_per_edge)
+    }
+
+    fn collisions_kill(&self) -> bool {
+        true
+    }
+}
+
+/// Offsets to the tile locations across each edge of an octagon on a `SquareOctagonBoard`, clockwise from the top
+const OCTAGON_NEIGHBORS: [[i32; 2]; 8] = [[0, -2], [1, -1], [2, 0], [1, 1], [0, 2], [-1, 1], [-2, 0], [-1, -1]];
+/// Offsets to the tile locations across each edge of a square on a `SquareOctagonBoard`, clockwise from the top right
+const SQUARE_NEIGHBORS: [[i32; 2]; 4] = [[1, -1], [1, 1], [-1, 1], [-1, -1]];
+
+/// A board of octagons in a grid, with squares standing on their corners in the gaps between them.
+/// Octagons and squares take different kinds of tiles.
+/// Tile locations are on a grid with twice the resolution of the octagons': octagons are at even coordinates,
+/// and squares are at odd ones. `width` and `height` count octagons.
+#[derive(Clone, Debug, Serialize, Deserialize, CopyGetters)]
+pub struct SquareOctagonBoard {
+    #[getset(get_copy = "pub")]
+    width: u32,
+    #[getset(get_copy = "pub")]
+    height: u32,
+    #[getset(get_copy = "pub")]
+    ports_per_edge: u32
+}
+
+impl SquareOctagonBoard {
+    pub fn new(width: u32, height: u32, ports_per_edge: u32) -> Self {
+        Self { width, height, ports_per_edge }
+    }
+
+    /// Whether a tile location, which might be off the board, is on it
+    fn contains_signed(&self, loc: Pt2i) -> bool {
+        let (width, height) = (self.width as i32, self.height as i32);
+        loc.x >= 0 && loc.y >= 0 && match (loc.x % 2, loc.y % 2) {
+            (0, 0) => loc.x / 2 < width && loc.y / 2 < height,
+            (1, 1) => loc.x / 2 < width - 1 && loc.y / 2 < height - 1,
+            _ => false,
+        }
+    }
+
+    /// All the tile locations on the board, octagons first
+    pub fn all_locs(&self) -> Vec<Pt2u> {
+        chain!(
+            iproduct!(0..self.height, 0..self.width).map(|(y, x)| point![2 * x, 2 * y]),
+            iproduct!(0..self.height.saturating_sub(1), 0..self.width.saturating_sub(1)).map(|(y, x)| point![2 * x + 1, 2 * y + 1])
+        ).collect_vec()
+    }
+
+    /// The ports on the edge between a tile location and a neighbor, which might be off the board,
+    /// in clockwise order around the tile location
+    fn edge_ports(&self, loc: Pt2i, neighbor: Pt2i) -> impl Iterator<Item = (Pt2u, Vec2u)> {
+        let sum = na::try_convert::<_, Pt2u>(loc + neighbor.coords + vector![2, 2]).expect("Neighbor is too far off the board");
+        let first = (loc.y, loc.x) < (neighbor.y, neighbor.x);
+        let ports_per_edge = self.ports_per_edge;
+        (1..=ports_per_edge).map(move |i| (sum, vector![if first { i } else { ports_per_edge + 1 - i }, 0]))
+    }
+}
+
+impl Board for SquareOctagonBoard {
+    /// Coordinates of a tile, with octagons at even coordinates and squares at odd ones
+    type TLoc = Pt2u;
+    /// Sum of the coordinates of the tile locations on either side, plus 2 so it's never negative,
+    /// followed by which port along the edge it is, counting clockwise around the tile location that comes first
+    /// in reading order
+    type Port = (Pt2u, Vec2u);
+    type Kind = SquareOctagonKind;
+    type TileConfig = PortsPerEdgeTileConfig;
+
+    fn all_ports(&self) -> Vec<Self::Port> {
+        self.all_locs().iter().flat_map(|loc| self.loc_ports(loc)).unique().collect_vec()
+    }
+
+    fn boundary_ports(&self) -> Vec<Self::Port> {
+        self.all_ports().into_iter().filter(|port| self.port_locs(port).len() == 1).collect_vec()
+    }
+
+    fn all_kinds(&self) -> Vec<Self::Kind> {
+        vec![SquareOctagonKind::Octagon, SquareOctagonKind::Square]
+    }
+
+    fn contains(&self, loc: &Self::TLoc) -> bool {
+        self.contains_signed(na::convert(*loc))
+    }
+
+    fn kind_at(&self, loc: &Self::TLoc) -> Self::Kind {
+        if loc.x % 2 == 0 { SquareOctagonKind::Octagon } else { SquareOctagonKind::Square }
+    }
+
+    fn loc_ports(&self, loc: &Self::TLoc) -> Vec<Self::Port> {
+        let loc_i = na::convert::<_, Pt2i>(*loc);
+        let neighbors = match self.kind_at(loc) {
+            SquareOctagonKind::Octagon => &OCTAGON_NEIGHBORS[..],
+            SquareOctagonKind::Square => &SQUARE_NEIGHBORS[..],
+        };
+        neighbors.iter().flat_map(|offset| self.edge_ports(loc_i, loc_i + Vec2i::from(*offset))).collect_vec()
+    }
+
+    fn port_locs(&self, port: &Self::Port) -> Vec<Self::TLoc> {
+        let sum = na::convert::<_, Pt2i>(port.0) - vector![2, 2];
+        let (p0, p1) = if sum.x % 2 != 0 {
+            // Between an octagon and a square. The octagon's coordinates are the even ones, which are halves of multiples of 4.
+            let octagon = sum.map(|c| if (c + 1).rem_euclid(4) == 0 { (c + 1) / 2 } else { (c - 1) / 2 });
+            (octagon, Pt2i::from(sum.coords - octagon.coords))
+        } else if sum.x.rem_euclid(4) == 2 {
+            let left = point![(sum.x - 2) / 2, sum.y / 2];
+            (left, left + vector![2, 0])
+        } else {
+            let top = point![sum.x / 2, (sum.y - 2) / 2];
+            (top, top + vector![0, 2])
+        };
+
+        IntoIterator::into_iter([p0, p1])
+            .filter(|loc| self.contains_signed(*loc))
+            .flat_map(na::try_convert)
+            .collect_vec()
+    }
+
+    fn tile_config(&self) -> Self::TileConfig {
+        PortsPerEdgeTileConfig(self.ports_per_edge)
+    }
 }
 
 #[cfg(test)]
@@ -241,4 +485,64 @@ mod tests {
         expected.sort_by_key(|vec| *AsRef::<[u32; 2]>::as_ref(&vec.coords));
         assert_eq!(locs, expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_rectangle_board_blocked() {
+        let board = RectangleBoard::new(3, 2, 2).with_blocked([point![0, 0], point![1, 1], point![5, 5]]);
+        assert_eq!(board.blocked().len(), 2);
+        assert!(board.is_blocked(&point![1, 1]));
+        assert!(!board.is_blocked(&point![1, 0]));
+
+        // Tokens can't start on the top left tile's top and left edges, nor the bottom middle tile's bottom edge
+        let ports = board.boundary_ports();
+        assert_eq!(ports.len(), RectangleBoard::new(3, 2, 2).boundary_ports().len() - (2 + 2 + 2));
+        assert!(ports.iter().all(|port| board.port_locs(port).iter().all(|loc| !board.is_blocked(loc))));
+    }
+
+    #[test]
+    fn test_torus_board_wraps() {
+        let board = TorusBoard::new(3, 2, 2);
+        assert_eq!(board.all_ports().len(), 2 * 3 * 2 * 2);
+        assert_eq!(board.boundary_ports().len(), (3 + 2) * 2);
+
+        // The bottom right tile's right and bottom ports are on the left and top edges
+        let ports = board.loc_ports(&point![2, 1]);
+        assert_eq!(ports[2..4], [(point![0, 1], vector![0, 1]), (point![0, 1], vector![0, 2])]);
+        assert_eq!(ports[4..6], [(point![2, 0], vector![2, 0]), (point![2, 0], vector![1, 0])]);
+
+        // Every port has a tile on both sides, and both tiles have it
+        for port in board.all_ports() {
+            let locs = board.port_locs(&port);
+            assert_eq!(locs.len(), 2);
+            assert_ne!(locs[0], locs[1]);
+            assert!(locs.iter().all(|loc| board.loc_ports(loc).contains(&port)));
+        }
+    }
+
+    #[test]
+    fn test_square_octagon_board() {
+        let board = SquareOctagonBoard::new(2, 2, 1);
+        assert_eq!(board.all_locs(), vec![point![0, 0], point![2, 0], point![0, 2], point![2, 2], point![1, 1]]);
+        assert_eq!(board.kind_at(&point![1, 1]), SquareOctagonKind::Square);
+        assert!(!board.contains(&point![3, 3]));
+        assert!(!board.contains(&point![1, 0]));
+
+        // Each octagon shares 2 edges with octagons and 1 with the square
+        assert_eq!(board.all_ports().len(), 4 * 8 + 4 - (4 + 4));
+        assert_eq!(board.boundary_ports().len(), 4 * 5);
+        assert_eq!(board.loc_ports(&point![1, 1]).len(), 4);
+        assert_eq!(board.loc_ports(&point![0, 0])[3], board.loc_ports(&point![1, 1])[3]);
+        assert_eq!(board.loc_ports(&point![0, 0])[2], board.loc_ports(&point![2, 0])[6]);
+
+        // Ports are in opposite orders around the tiles on either side
+        let board = SquareOctagonBoard::new(3, 2, 2);
+        for port in board.all_ports() {
+            let locs = board.port_locs(&port);
+            assert!(!locs.is_empty() && locs.len() <= 2);
+            assert!(locs.iter().all(|loc| board.loc_ports(loc).contains(&port)));
+        }
+        let octagon = board.loc_ports(&point![2, 0]);
+        let square = board.loc_ports(&point![3, 1]);
+        assert_eq!(octagon[6..8], [square[7], square[6]]);
+    }
+}
@@ -1,4 +1,5 @@
 use fnv::FnvHashMap;
+use getset::CopyGetters;
 use itertools::Itertools;
 
 use std::hash::Hash;
@@ -6,7 +7,7 @@ use std::fmt::Debug;
 
 use serde::{Deserialize, Serialize};
 
-use crate::board::{BasePort, Board, Port, BaseTLoc, TLoc};
+use crate::board::{BaseBoard, BasePort, Board, Port, BaseTLoc, TLoc};
 use crate::game::Game;
 use crate::tile::{Tile, BaseTile};
 use crate::WrapBase;
@@ -21,6 +22,12 @@ macro_rules! for_each_board_state {
             ($crate::board_state::BaseBoardState)::Normal: $crate::board_state::BoardState<
                 $crate::board::RectangleBoard, $crate::tile::RegularTile<4>
             >,
+            ($crate::board_state::BaseBoardState)::Torus: $crate::board_state::BoardState<
+                $crate::board::TorusBoard, $crate::tile::RegularTile<4>
+            >,
+            ($crate::board_state::BaseBoardState)::SquareOctagon: $crate::board_state::BoardState<
+                $crate::board::SquareOctagonBoard, $crate::tile::SquareOctagonTile
+            >,
         }
     };
 
@@ -43,6 +50,11 @@ for_each_board_state! {
             match self { $($($p)*::$x(s) => s.player_port(player).map(|port| port.clone().wrap_base())),* }
         }
 
+        /// Every port a player has been on, in order, starting with where they placed their token
+        pub fn player_trail(&self, player: u32) -> Vec<BasePort> {
+            match self { $($($p)*::$x(s) => s.player_trail(player).iter().map(|port| port.clone().wrap_base()).collect()),* }
+        }
+
         /// All the tiles on the board
         pub fn tiles_vec(&self) -> Vec<(BaseTLoc, BaseTile)> {
             match self { $($($p)*::$x(s) => 
@@ -51,9 +63,43 @@ for_each_board_state! {
                     .collect()
             ),* }
         }
+
+        /// What would happen if a tile got placed at `loc`, without changing this state.
+        /// Returns the board state afterwards and who would die.
+        pub fn preview_tile(&self, board: &BaseBoard, tile: &BaseTile, loc: &BaseTLoc) -> (Self, Vec<Death>) {
+            match self { $($($p)*::$x(s) => {
+                let (state, deaths) = s.preview_tile(
+                    WrapBase::unwrap_base_ref(board),
+                    WrapBase::unwrap_base_ref(tile),
+                    TLoc::unwrap_base_ref(loc),
+                );
+                (state.wrap_base(), deaths)
+            }),* }
+        }
     }
 
-    $($crate::impl_wrap_base!(BaseBoardState::$x($t)))*;
+    $($crate::impl_wrap_base!(BaseBoardState::$x($t));)*
+}
+
+/// Why a player died
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DeathCause {
+    /// Their path led off the edge of the board
+    Edge,
+    /// They ended up on the same port as someone else, on a board where that kills
+    Collision,
+    /// Their path went around in a loop, so they'd never stop moving
+    Loop,
+    /// Their path ran into a blocked tile location
+    Blocked,
+}
+
+/// A player dying while moving along their path
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct Death {
+    player: u32,
+    cause: DeathCause,
 }
 
 /// The state of the board
@@ -61,6 +107,8 @@ for_each_board_state! {
 pub struct BoardState<B: Board, T> {
     tiles: FnvHashMap<B::TLoc, T>,
     players: Vec<Option<B::Port>>,
+    /// Every port each player has been on, in order
+    trails: Vec<Vec<B::Port>>,
 }
 
 impl<K, C, B, T> BoardState<B, T>
@@ -75,6 +123,7 @@ where
         Self {
             tiles: FnvHashMap::default(),
             players: vec![None; num_players as usize],
+            trails: vec![vec![]; num_players as usize],
         }
     }
 
@@ -98,25 +147,56 @@ where
         self.players[player as usize].as_ref()
     }
 
+    /// Every port a player has been on, in order, starting with where they placed their token.
+    /// Empty if the player hasn't placed their token yet.
+    pub fn player_trail(&self, player: u32) -> &[B::Port] {
+        &self.trails[player as usize]
+    }
+
     /// Player on port. None if there's no player there
     pub fn player_at(&self, port: &B::Port) -> Option<u32> {
         self.players.iter().position(|p| p.as_ref() == Some(port)).map(|n| n as u32)
     }
 
     /// Place a player token on some port.
+    /// The port gets added to the player's trail unless they're already on it.
     pub fn place_player(&mut self, player: u32, port: &B::Port) {
+        if self.players[player as usize].as_ref() != Some(port) {
+            self.trails[player as usize].push(port.clone());
+        }
         self.players[player as usize] = Some(port.clone());
     }
 
+    /// Remove a player token from the board. Does nothing if the player hasn't placed their token.
+    pub fn remove_player(&mut self, player: u32) {
+        self.players[player as usize] = None;
+    }
+
     /// Place a tile on some location. Assumes the location is empty and kinds match.
     pub fn place_tile(&mut self, tile: T, loc: &B::TLoc) {
         self.tiles.insert(loc.clone(), tile);
     }
 
+    /// Clones the state, places a tile on the clone, and advances players along it.
+    /// Returns the clone and the newly dead players.
+    pub fn preview_tile(&self, board: &B, tile: &T, loc: &B::TLoc) -> (Self, Vec<Death>) {
+        let mut state = self.clone();
+        state.place_tile(tile.clone(), loc);
+        let deaths = state.advance_players(board, loc);
+        (state, deaths)
+    }
+
     /// Move players that touch a tile along their respective paths until they face a dead end.
     /// Assumes the location has a tile on it.
-    /// Returns a list of newly dead players.
-    pub fn advance_players(&mut self, board: &B, loc: &B::TLoc) -> Vec<u32> {
+    /// Returns the newly dead players and why they died.
+    pub fn advance_players(&mut self, board: &B, loc: &B::TLoc) -> Vec<Death> {
+        self.advance_players_with_distances(board, loc).0
+    }
+
+    /// Like `advance_players`, but also returns how many tiles each player moved through, indexed by player.
+    pub fn advance_players_with_distances(&mut self, board: &B, loc: &B::TLoc) -> (Vec<Death>, Vec<u32>) {
+        // Where each player's trail was before moving, for spotting loops
+        let trail_starts = self.trails.iter().map(|trail| trail.len().saturating_sub(1)).collect_vec();
         // Contains tuples of player and tile location to move through.
         // If the tile location is None, the player is done moving.
         let mut to_advance = (0..self.players.len())
@@ -126,6 +206,7 @@ where
             .collect_vec();
 
         let mut dead = vec![];
+        let mut distances = vec![0; self.players.len()];
 
         // Making this change would introduce short-circuiting
         #[allow(clippy::unnecessary_fold)]
@@ -137,15 +218,24 @@ where
                     let tile = self.tile_at(loc).unwrap().clone();
                     let output = tile.output(input);
                     let port_out = board.loc_ports(loc)[output as usize].clone();
+                    let looped = self.trails[*player as usize][trail_starts[*player as usize]..].contains(&port_out);
                     self.players[*player as usize] = Some(port_out.clone());
+                    self.trails[*player as usize].push(port_out.clone());
+                    distances[*player as usize] += 1;
 
-                    // Figure out if they can move again
-                    // TODO: What if there's a choice?
-                    *maybe_loc = board.port_locs(&port_out).into_iter().find(|l| l != loc);
-                    if maybe_loc.is_none() {
-                        dead.push(*player);
+                    // Figure out if they can move again.
+                    // If the port borders more than one other location, the path goes on through the first one with a tile.
+                    let onward = board.port_locs(&port_out).into_iter().filter(|l| l != loc).collect_vec();
+                    *maybe_loc = onward.iter().find(|l| !board.is_blocked(l) && self.tile_at(l).is_some()).cloned();
+                    if looped {
+                        // Coming back to a port means going around the same loop forever
+                        dead.push(Death{ player: *player, cause: DeathCause::Loop });
+                        *maybe_loc = None;
+                    } else if onward.is_empty() {
+                        dead.push(Death{ player: *player, cause: DeathCause::Edge });
+                    } else if onward.iter().all(|l| board.is_blocked(l)) {
+                        dead.push(Death{ player: *player, cause: DeathCause::Blocked });
                     }
-                    *maybe_loc = maybe_loc.clone().filter(|l| self.tile_at(l).is_some());
                     maybe_loc.is_none()
                 } else {
                     true
@@ -154,6 +244,22 @@ where
             }).fold(true, |b1, b2| b1 && b2)
         {}
 
-        dead
+        if board.collisions_kill() {
+            let moved = |player: usize| distances[player] > 0;
+            let collided = (0..self.players.len())
+                .filter(|player| !dead.iter().any(|death| death.player == *player as u32))
+                .filter(|player| self.players[*player].is_some())
+                .filter(|player| (0..self.players.len()).any(|other| other != *player &&
+                    self.players[other] == self.players[*player] && (moved(*player) || moved(other))))
+                .collect_vec();
+            dead.extend(collided.into_iter().map(|player| Death{ player: player as u32, cause: DeathCause::Collision }));
+
+            // With no edge to leave them on, dead tokens come off the board so tiles placed next to them don't move them
+            for death in &dead {
+                self.players[death.player as usize] = None;
+            }
+        }
+
+        (dead, distances)
     }
 }
\ No newline at end of file
@@ -0,0 +1,147 @@
+//! Chess clocks: each player gets a bank of time for the whole round, which only runs down on their own turns.
+//! A player whose clock runs out is out of the game.
+
+use std::{fmt::{self, Display}, str::FromStr, time::Duration};
+
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ConfigError, TimeBank, TimeIncrement};
+
+/// How much time each player's clock starts with, and how much gets added back after each of their turns
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TimeControl {
+    bank: TimeBank,
+    increment: TimeIncrement,
+}
+
+impl TimeControl {
+    pub fn new(bank: TimeBank, increment: TimeIncrement) -> Self {
+        Self { bank, increment }
+    }
+
+    pub fn bank(&self) -> Duration {
+        self.bank.get()
+    }
+
+    pub fn increment(&self) -> Duration {
+        self.increment.get()
+    }
+
+    /// The time controls offered when creating a game, from fastest to slowest
+    pub fn all() -> impl Iterator<Item = Self> {
+        [(3, 2), (5, 3), (10, 5), (15, 10)].into_iter()
+            .map(|(mins, secs)| Self::new(
+                TimeBank::new(Duration::from_secs(mins * 60)).expect("Preset bank out of range"),
+                TimeIncrement::new(Duration::from_secs(secs)).expect("Preset increment out of range"),
+            ))
+    }
+}
+
+/// Written the way chess players do, minutes in the bank plus seconds of increment, like `5+3`
+impl Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{}", self.bank().as_secs() / 60, self.increment().as_secs())
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ConfigError::Invalid{ name: "time control", value: s.to_owned() };
+        let (mins, secs) = s.split_once('+').ok_or_else(invalid)?;
+        let mins = mins.trim().parse::<u64>().map_err(|_| invalid())?;
+        let secs = secs.trim().parse::<u64>().map_err(|_| invalid())?;
+        Ok(Self::new(TimeBank::new(Duration::from_secs(mins * 60))?, TimeIncrement::new(Duration::from_secs(secs))?))
+    }
+}
+
+/// How much time each player had left when the clocks were last looked at, and whose clock was running then
+#[derive(Clone, Debug, PartialEq, Eq, Getters, CopyGetters, Serialize, Deserialize)]
+pub struct Clocks {
+    #[getset(get = "pub")]
+    remaining: Vec<Duration>,
+    /// None when nobody's clock is running, like before everyone placed their token or after the round ended
+    #[getset(get_copy = "pub")]
+    running: Option<u32>,
+}
+
+impl Clocks {
+    /// Full clocks for `num_players` players, none of them running
+    pub fn new(control: TimeControl, num_players: u32) -> Self {
+        Self { remaining: vec![control.bank(); num_players as usize], running: None }
+    }
+
+    /// Time `player` has left once `elapsed` has passed
+    pub fn remaining_after(&self, player: u32, elapsed: Duration) -> Duration {
+        let remaining = self.remaining[player as usize];
+        if self.running == Some(player) { remaining.saturating_sub(elapsed) } else { remaining }
+    }
+
+    /// The clocks as they are once `elapsed` has passed
+    pub fn after(&self, elapsed: Duration) -> Self {
+        Self {
+            remaining: (0..self.remaining.len() as u32).map(|player| self.remaining_after(player, elapsed)).collect(),
+            running: self.running,
+        }
+    }
+
+    /// The player whose clock ran out once `elapsed` has passed, if any
+    pub fn ran_out(&self, elapsed: Duration) -> Option<u32> {
+        self.running.filter(|player| self.remaining_after(*player, elapsed).is_zero())
+    }
+
+    /// Stops the running clock once `elapsed` has passed, adding the increment unless it already ran out,
+    /// and starts `next`'s clock
+    pub fn switch(&mut self, control: TimeControl, elapsed: Duration, next: Option<u32>) {
+        if let Some(player) = self.running {
+            let remaining = self.remaining_after(player, elapsed);
+            self.remaining[player as usize] = if remaining.is_zero() { remaining } else { remaining + control.increment() };
+        }
+        self.running = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_control_notation() {
+        let control = "5+3".parse::<TimeControl>().unwrap();
+        assert_eq!(control.bank(), Duration::from_secs(300));
+        assert_eq!(control.increment(), Duration::from_secs(3));
+        assert_eq!(control.to_string(), "5+3");
+        for control in TimeControl::all() {
+            assert_eq!(control.to_string().parse::<TimeControl>(), Ok(control));
+        }
+
+        assert!("5".parse::<TimeControl>().is_err());
+        assert!("five+3".parse::<TimeControl>().is_err());
+        assert!("0+3".parse::<TimeControl>().is_err());
+    }
+
+    #[test]
+    fn test_clocks() {
+        let control = "1+2".parse::<TimeControl>().unwrap();
+        let mut clocks = Clocks::new(control, 2);
+        // Nothing runs down before anyone's clock starts
+        clocks.switch(control, Duration::from_secs(10), Some(0));
+        assert_eq!(clocks.remaining(), &[Duration::from_secs(60); 2]);
+
+        assert_eq!(clocks.after(Duration::from_secs(15)).remaining(), &[Duration::from_secs(45), Duration::from_secs(60)]);
+        assert_eq!(clocks.ran_out(Duration::from_secs(59)), None);
+        assert_eq!(clocks.ran_out(Duration::from_secs(60)), Some(0));
+
+        // Finishing a turn gets the increment back
+        clocks.switch(control, Duration::from_secs(15), Some(1));
+        assert_eq!(clocks.remaining(), &[Duration::from_secs(47), Duration::from_secs(60)]);
+        assert_eq!(clocks.running(), Some(1));
+
+        // Unless the clock already ran out
+        clocks.switch(control, Duration::from_secs(90), None);
+        assert_eq!(clocks.remaining(), &[Duration::from_secs(47), Duration::ZERO]);
+        assert_eq!(clocks.ran_out(Duration::from_secs(1000)), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! Validated values for everything that can be configured, shared by the server's config and the game creation form,
+//! so a value out of range gets rejected the same way wherever it comes from.
+//! Every limit is a newtype that can only hold a valid value, including after deserializing.
+
+use std::{fmt::{self, Display}, time::Duration};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Why a configuration got rejected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// A value is outside its range. Values are written the way they would be in a config.
+    OutOfRange{ name: &'static str, value: String, min: String, max: String },
+    /// An option only makes sense along with another one
+    Requires{ name: &'static str, requires: &'static str },
+    /// A value written as text couldn't be read
+    Invalid{ name: &'static str, value: String },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfRange{ name, value, min, max } =>
+                write!(f, "{} is {}, but it has to be between {} and {}", name, value, min, max),
+            Self::Requires{ name, requires } => write!(f, "{} requires {}", name, requires),
+            Self::Invalid{ name, value } => write!(f, "{} isn't a valid {}", value, name),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// How a limited value is written in configs: as itself for counts, in whole seconds for durations
+pub trait ConfigRepr: Copy + PartialOrd {
+    type Repr: Copy + Display + Serialize + for<'de> Deserialize<'de>;
+    /// Unit written after the value
+    const UNIT: &'static str;
+
+    fn to_repr(self) -> Self::Repr;
+    fn from_repr(repr: Self::Repr) -> Self;
+
+    fn describe(self) -> String {
+        format!("{}{}", self.to_repr(), Self::UNIT)
+    }
+}
+
+impl ConfigRepr for u32 {
+    type Repr = u32;
+    const UNIT: &'static str = "";
+
+    fn to_repr(self) -> u32 {
+        self
+    }
+
+    fn from_repr(repr: u32) -> Self {
+        repr
+    }
+}
+
+impl ConfigRepr for Duration {
+    type Repr = u64;
+    const UNIT: &'static str = "s";
+
+    fn to_repr(self) -> u64 {
+        self.as_secs()
+    }
+
+    fn from_repr(repr: u64) -> Self {
+        Duration::from_secs(repr)
+    }
+}
+
+/// Defines newtypes for values that have to stay within a range, with a default.
+/// They serialize as their config representation and get checked when deserialized.
+macro_rules! limits {
+    ($($(#[$attr:meta])* $name:ident($t:ty), $desc:literal, $min:expr, $max:expr, default $default:expr;)*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+            pub struct $name($t);
+
+            impl $name {
+                pub const MIN: Self = Self($min);
+                pub const MAX: Self = Self($max);
+                pub const DEFAULT: Self = Self($default);
+
+                /// Checks that a value is in range
+                pub fn new(value: $t) -> Result<Self, ConfigError> {
+                    if (Self::MIN.0..=Self::MAX.0).contains(&value) {
+                        Ok(Self(value))
+                    } else {
+                        Err(ConfigError::OutOfRange {
+                            name: $desc,
+                            value: value.describe(),
+                            min: Self::MIN.0.describe(),
+                            max: Self::MAX.0.describe(),
+                        })
+                    }
+                }
+
+                /// Checks that a value written the way it would be in a config is in range
+                pub fn from_repr(repr: <$t as ConfigRepr>::Repr) -> Result<Self, ConfigError> {
+                    Self::new(<$t>::from_repr(repr))
+                }
+
+                pub fn get(self) -> $t {
+                    self.0
+                }
+            }
+
+            impl Default for $name {
+                fn default() -> Self {
+                    Self::DEFAULT
+                }
+            }
+
+            impl Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    write!(f, "{}", self.0.describe())
+                }
+            }
+
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    self.0.to_repr().serialize(serializer)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    Self::from_repr(Deserialize::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+limits! {
+    /// Width or height of a board, in tiles
+    BoardSize(u32), "board size", 2, 16, default 6;
+    /// How long each turn can take
+    TurnTimeLimit(Duration), "turn time limit", Duration::from_secs(5), Duration::from_secs(60 * 60),
+        default Duration::from_secs(60);
+    /// How much time each player's clock starts with, in games played with clocks
+    TimeBank(Duration), "time bank", Duration::from_secs(60), Duration::from_secs(2 * 60 * 60),
+        default Duration::from_secs(5 * 60);
+    /// How much time gets added back to a player's clock after each of their turns
+    TimeIncrement(Duration), "time increment", Duration::ZERO, Duration::from_secs(60), default Duration::ZERO;
+    /// How long a disconnected player has to come back before their turns get skipped
+    DisconnectGracePeriod(Duration), "disconnect grace period", Duration::ZERO, Duration::from_secs(60 * 60),
+        default Duration::from_secs(60);
+    /// How long servers keep games that ended or sat empty without starting,
+    /// and connections that never picked a username
+    IdleTimeout(Duration), "idle timeout", Duration::from_secs(60), Duration::from_secs(24 * 60 * 60),
+        default Duration::from_secs(10 * 60);
+    /// How long spectators of games with a spectator delay wait to hear about what happens
+    SpectatorDelay(Duration), "spectator delay", Duration::from_secs(5), Duration::from_secs(5 * 60),
+        default Duration::from_secs(30);
+    /// Number of players in a game played on one device
+    NumPlayers(u32), "number of players", 2, 8, default 2;
+    /// Most rounds a match takes. Whoever wins more than half of them wins the match.
+    MatchRounds(u32), "rounds", 1, 9, default 1;
+}
+
+impl NumPlayers {
+    /// Every valid number of players, in order
+    pub fn all() -> impl Iterator<Item = Self> {
+        (Self::MIN.0..=Self::MAX.0).map(Self)
+    }
+}
+
+impl SpectatorDelay {
+    /// Delays offered when creating a game, shortest first
+    pub fn all() -> impl Iterator<Item = Self> {
+        [10, 30, 60, 120, 300].into_iter().map(|secs| Self(Duration::from_secs(secs)))
+    }
+}
+
+impl MatchRounds {
+    /// Every valid number of rounds, in order
+    pub fn all() -> impl Iterator<Item = Self> {
+        (Self::MIN.0..=Self::MAX.0).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        assert_eq!(BoardSize::new(6).map(BoardSize::get), Ok(6));
+        assert_eq!(BoardSize::new(1), Err(ConfigError::OutOfRange {
+            name: "board size", value: "1".to_owned(), min: "2".to_owned(), max: "16".to_owned(),
+        }));
+        assert!(TurnTimeLimit::new(Duration::from_secs(4)).is_err());
+        assert_eq!(DisconnectGracePeriod::from_repr(0).map(DisconnectGracePeriod::get), Ok(Duration::ZERO));
+        assert_eq!(NumPlayers::all().count(), 7);
+
+        // Durations are whole seconds when serialized, and get checked on the way in
+        let bytes = bincode::serialize(&TurnTimeLimit::DEFAULT).unwrap();
+        assert_eq!(bytes, bincode::serialize(&60u64).unwrap());
+        assert_eq!(bincode::deserialize::<TurnTimeLimit>(&bytes).unwrap(), TurnTimeLimit::DEFAULT);
+        assert!(bincode::deserialize::<TurnTimeLimit>(&bincode::serialize(&1u64).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+//! The daily challenge: a game set up the same way for everyone each day, played alone against bots.
+//! Everyone's results for the day get ranked on a leaderboard that starts over the next day.
+
+use std::{cmp::Reverse, fmt};
+
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Serialize};
+
+use crate::options::{BotDifficulty, GameOptions, GamePreset};
+
+/// Seconds in a day
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+/// Bots each daily challenge is played against
+pub const DAILY_BOTS: u32 = 3;
+/// How well the bots in the daily challenge play
+pub const DAILY_BOT_DIFFICULTY: BotDifficulty = BotDifficulty::Medium;
+
+/// A day, counted from the Unix epoch in UTC. Each one has its own challenge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Day(pub u32);
+
+impl Day {
+    /// The day it is `secs` seconds after the Unix epoch
+    pub fn from_unix_secs(secs: u64) -> Self {
+        Self((secs / SECS_PER_DAY) as u32)
+    }
+
+    /// The seed the day's draw piles get shuffled with
+    pub fn seed(self) -> u64 {
+        self.0 as u64
+    }
+
+    /// The options the day's challenge is played by. Every day is a standard game; only the tiles change.
+    pub fn options(self) -> GameOptions {
+        let mut options = GamePreset::Standard.options();
+        options.set_seed(Some(self.seed()));
+        options
+    }
+}
+
+/// Shown as the date, like `2024-03-09`
+impl fmt::Display for Day {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Counts from March so leap days come last in the year
+        let days = self.0 as i64 + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+        let year = year_of_era + era * 400 + (month <= 2) as i64;
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// How someone did in a daily challenge
+#[derive(Clone, Debug, PartialEq, Eq, Getters, CopyGetters, Serialize, Deserialize)]
+pub struct DailyResult {
+    #[getset(get = "pub")]
+    username: String,
+    /// Whether they won their game
+    #[getset(get_copy = "pub")]
+    won: bool,
+    /// Tiles their token moved through
+    #[getset(get_copy = "pub")]
+    distance: u32,
+}
+
+impl DailyResult {
+    pub fn new(username: String, won: bool, distance: u32) -> Self {
+        Self { username, won, distance }
+    }
+
+    /// Sorts results best first: winners before everyone else, then whoever went the farthest
+    pub fn rank_key(&self) -> (Reverse<bool>, Reverse<u32>) {
+        (Reverse(self.won), Reverse(self.distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_day() {
+        assert_eq!(Day::from_unix_secs(0), Day(0));
+        assert_eq!(Day::from_unix_secs(SECS_PER_DAY - 1), Day(0));
+        assert_eq!(Day::from_unix_secs(SECS_PER_DAY), Day(1));
+        assert_eq!(Day(0).to_string(), "1970-01-01");
+        assert_eq!(Day(59).to_string(), "1970-03-01");
+        assert_eq!(Day(19792).to_string(), "2024-03-10");
+        assert_eq!(Day(19783).to_string(), "2024-03-01");
+        assert_eq!(Day(19782).to_string(), "2024-02-29");
+
+        assert_eq!(Day(5).options().seed(), Some(5));
+        assert_ne!(Day(5).seed(), Day(6).seed());
+    }
+
+    #[test]
+    fn test_daily_result_rank() {
+        let mut results = [
+            DailyResult::new("alice".to_owned(), false, 30),
+            DailyResult::new("bob".to_owned(), true, 12),
+            DailyResult::new("carol".to_owned(), false, 41),
+        ];
+        results.sort_by_key(DailyResult::rank_key);
+        assert_eq!(results.iter().map(|result| result.username().as_str()).collect::<Vec<_>>(), ["bob", "carol", "alice"]);
+    }
+}
@@ -1,22 +1,102 @@
 use std::marker::PhantomData;
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Display};
 use std::hash::Hash;
 use enum_dispatch::enum_dispatch;
 use fnv::FnvHashMap;
+use getset::{CopyGetters, Getters};
 use serde::{Deserialize, Serialize};
 
-use crate::{board::{Board, Port, TLoc}, game_state::GameState, tile::{GAct, Kind, Tile}};
+use crate::{board::{Board, Port, TLoc}, game_state::{GameState, WinCondition}, tile::{GAct, Kind, Tile}};
 use crate::game_state::BaseGameState;
-use crate::board::BaseBoard;
+use crate::board::{BaseBoard, BasePort};
+use crate::tile::BaseTile;
 use crate::WrapBase;
 
+/// Start of a link fragment that invites someone to a game
+const JOIN_PREFIX: &str = "join=";
+
+/// A game's id. Ids are random instead of counted up,
+/// so games persisted by one server or moved over from another never get the same id as a game already there.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct GameId(pub u32);
+pub struct GameId(pub u64);
+
+impl GameId {
+    /// A random id. It's up to whoever keeps the games to check that it isn't taken already.
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+
+    /// The link fragment that invites someone to join this game
+    pub fn join_fragment(self) -> String {
+        format!("{}{}", JOIN_PREFIX, self)
+    }
+
+    /// Parses a link fragment made by `join_fragment`, with or without the `#` in front.
+    /// None if it isn't an invitation.
+    pub fn parse_join_fragment(fragment: &str) -> Option<Self> {
+        let fragment = fragment.strip_prefix('#').unwrap_or(fragment);
+        u64::from_str_radix(fragment.strip_prefix(JOIN_PREFIX)?, 16).ok().map(Self)
+    }
+}
+
+/// Shown in hex, always 16 digits long
+impl Display for GameId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// How a match of several rounds is going. A game of one round is a match decided by that round.
+#[derive(Clone, Debug, PartialEq, Eq, Getters, CopyGetters, Serialize, Deserialize)]
+pub struct MatchScore {
+    /// Most rounds the match takes
+    #[getset(get_copy = "pub")]
+    rounds: u32,
+    /// The round being played, starting at 1
+    #[getset(get_copy = "pub")]
+    round: u32,
+    /// Rounds each player won, indexed by player
+    #[getset(get = "pub")]
+    round_wins: Vec<u32>,
+}
+
+impl MatchScore {
+    /// A match that's best of `rounds`, with nobody having won a round yet
+    pub fn new(rounds: u32, num_players: u32) -> Self {
+        Self { rounds, round: 1, round_wins: vec![0; num_players as usize] }
+    }
+
+    /// Records who won the round being played. Players who won it together each get the round.
+    pub fn record_round(&mut self, winners: &[u32]) {
+        for winner in winners {
+            self.round_wins[*winner as usize] += 1;
+        }
+    }
+
+    /// Whether someone won more than half the rounds, or the last round was played.
+    /// Meant to be called once the round being played is recorded.
+    pub fn decided(&self) -> bool {
+        self.round >= self.rounds || self.round_wins.iter().any(|wins| wins * 2 > self.rounds)
+    }
+
+    /// Moves on to the next round
+    pub fn next_round(&mut self) {
+        self.round += 1;
+    }
+
+    /// The players who won the most rounds
+    pub fn leaders(&self) -> Vec<u32> {
+        let most = self.round_wins.iter().copied().max().unwrap_or(0);
+        (0..self.round_wins.len() as u32).filter(|player| self.round_wins[*player as usize] == most).collect()
+    }
+}
 
 #[enum_dispatch]
 pub trait GenericGame {
     fn new_state(&self, num_players: u32) -> BaseGameState;
 
+    fn new_state_seeded(&self, num_players: u32, seed: u64) -> BaseGameState;
+
     fn board(&self) -> BaseBoard;
 }
 
@@ -30,6 +110,10 @@ where
         GameState::new(self, num_players).into()
     }
 
+    fn new_state_seeded(&self, num_players: u32, seed: u64) -> BaseGameState {
+        GameState::new_seeded(self, num_players, seed).into()
+    }
+
     fn board(&self) -> BaseBoard {
         self.board().clone().into()
     }
@@ -43,6 +127,8 @@ macro_rules! for_each_game {
         }
         __mac! {
             ($crate::game::BaseGame)::Normal: $crate::game::PathGame<$crate::board::RectangleBoard, $crate::tile::RegularTile<4>>,
+            ($crate::game::BaseGame)::Torus: $crate::game::PathGame<$crate::board::TorusBoard, $crate::tile::RegularTile<4>>,
+            ($crate::game::BaseGame)::SquareOctagon: $crate::game::PathGame<$crate::board::SquareOctagonBoard, $crate::tile::SquareOctagonTile>,
         }
     };
 
@@ -65,12 +151,36 @@ for_each_game! {
             match self { $($($p)*::$x(s) => GameState::new(s, num_players).wrap_base()),* }
         }
 
+        /// A new state whose draw piles get shuffled the same way every time for the same seed
+        pub fn new_state_seeded(&self, num_players: u32, seed: u64) -> BaseGameState {
+            match self { $($($p)*::$x(s) => GameState::new_seeded(s, num_players, seed).wrap_base()),* }
+        }
+
         pub fn board(&self) -> BaseBoard {
             match self { $($($p)*::$x(s) => s.board().clone().wrap_base()),* }
         }
+
+        /// All the ports that players can start at
+        pub fn start_ports(&self) -> Vec<BasePort> {
+            match self { $($($p)*::$x(s) => s.start_ports().into_iter().map(|port| port.wrap_base()).collect()),* }
+        }
+
+        /// The game's tile set, one of each tile in its canonical orientation, in tile number order
+        pub fn all_tiles(&self) -> Vec<BaseTile> {
+            match self { $($($p)*::$x(s) => s.all_tiles().into_iter().map(|tile| tile.wrap_base()).collect()),* }
+        }
+
+        /// Number of a tile in the game's tile set, counting from 1, however it's turned.
+        /// None if it isn't in the set, like when it's hidden.
+        pub fn tile_number(&self, tile: &BaseTile) -> Option<u32> {
+            match self { $($($p)*::$x(s) => {
+                let tile = <$t as Game>::Tile::unwrap_base_ref(tile).clone().with_visible(true).canonical();
+                s.all_tiles().into_iter().position(|t| t.with_visible(true).canonical() == tile).map(|i| i as u32 + 1)
+            }),* }
+        }
     }
 
-    $($crate::impl_wrap_base!(BaseGame::$x($t)))*;
+    $($crate::impl_wrap_base!(BaseGame::$x($t));)*
 }
 
 pub trait Game: Clone + Debug + Serialize {
@@ -95,6 +205,12 @@ pub trait Game: Clone + Debug + Serialize {
 
     /// Tiles of some kind that a player starts with
     fn num_tiles_per_player(&self, kind: &Self::Kind) -> u32;
+
+    /// Tiles already on the board when the game starts, and where they are
+    fn preplaced_tiles(&self) -> Vec<(Self::TLoc, Self::Tile)>;
+
+    /// How the winners get decided
+    fn win_condition(&self) -> WinCondition;
 }
 
 /// A definition for a path game
@@ -106,6 +222,9 @@ pub struct PathGame<B: Board, T> {
     start_ports: Vec<<B as Board>::Port>,
     #[serde(bound = "")]
     tiles_per_player: FnvHashMap<<B as Board>::Kind, u32>,
+    #[serde(bound = "T: Tile")]
+    preplaced_tiles: Vec<(<B as Board>::TLoc, T)>,
+    win_condition: WinCondition,
     phantom: PhantomData<T>,
 }
 
@@ -122,9 +241,35 @@ where
             board,
             start_ports,
             tiles_per_player: tiles_per_player.into_iter().collect(),
+            preplaced_tiles: vec![],
+            win_condition: WinCondition::Survival,
             phantom: PhantomData,
         }
     }
+
+    /// Puts tiles on the board before the game starts, for scenarios and puzzles.
+    /// Tiles that are off the board, on a blocked location, the wrong kind for their location,
+    /// or on a location that already has a tile get left out.
+    /// Tokens can't start next to a tile that's already there, so those start ports get taken out.
+    pub fn with_preplaced_tiles(mut self, tiles: impl IntoIterator<Item = (B::TLoc, T)>) -> Self {
+        for (loc, tile) in tiles {
+            if self.board.contains(&loc) && !self.board.is_blocked(&loc) && tile.kind() == &self.board.kind_at(&loc) &&
+                self.preplaced_tiles.iter().all(|(l, _)| l != &loc)
+            {
+                self.preplaced_tiles.push((loc, tile.with_visible(true)));
+            }
+        }
+        let board = &self.board;
+        let preplaced_tiles = &self.preplaced_tiles;
+        self.start_ports.retain(|port| board.port_locs(port).iter().all(|loc| preplaced_tiles.iter().all(|(l, _)| l != loc)));
+        self
+    }
+
+    /// Decides the winners some other way than by who's left standing
+    pub fn with_win_condition(mut self, win_condition: WinCondition) -> Self {
+        self.win_condition = win_condition;
+        self
+    }
 }
 
 impl<K, C, B, T> Game for PathGame<B, T>
@@ -153,4 +298,54 @@ where
     fn num_tiles_per_player(&self, kind: &Self::Kind) -> u32 {
         self.tiles_per_player[kind]
     }
-}
\ No newline at end of file
+
+    fn preplaced_tiles(&self) -> Vec<(Self::TLoc, Self::Tile)> {
+        self.preplaced_tiles.clone()
+    }
+
+    fn win_condition(&self) -> WinCondition {
+        self.win_condition
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_fragment() {
+        let fragment = GameId(42).join_fragment();
+        assert_eq!(GameId::parse_join_fragment(&fragment), Some(GameId(42)));
+        assert_eq!(GameId::parse_join_fragment(&format!("#{}", fragment)), Some(GameId(42)));
+        let id = GameId::random();
+        assert_eq!(GameId::parse_join_fragment(&id.join_fragment()), Some(id));
+        assert_eq!(GameId(u64::MAX).to_string(), "ffffffffffffffff");
+        assert_eq!(GameId::parse_join_fragment("#join=xyz"), None);
+        assert_eq!(GameId::parse_join_fragment("#replay-id=42"), None);
+        assert_eq!(GameId::parse_join_fragment(""), None);
+    }
+
+    #[test]
+    fn test_match_score() {
+        let mut score = MatchScore::new(3, 3);
+        score.record_round(&[1]);
+        assert!(!score.decided());
+        score.next_round();
+        score.record_round(&[0, 1]);
+        assert!(score.decided());
+        assert_eq!(score.leaders(), vec![1]);
+
+        // Without a majority, the match goes on until the last round
+        let mut score = MatchScore::new(3, 3);
+        for winner in 0..3 {
+            score.record_round(&[winner]);
+            if winner < 2 {
+                assert!(!score.decided());
+                score.next_round();
+            }
+        }
+        assert!(score.decided());
+        assert_eq!(score.leaders(), vec![0, 1, 2]);
+        assert!(MatchScore::new(1, 2).decided());
+    }
+}
@@ -7,13 +7,14 @@ use rand::prelude::SliceRandom;
 use serde::{Deserialize, Serialize};
 
 
-use crate::{board::{BasePort, BaseTLoc, Board, TLoc}, board_state::BoardState, game::{Game}, pcg64, player_state::{Looker, PlayerState}, tile::{BaseKind, Tile, Kind}};
+use crate::{board::{BasePort, BaseTLoc, Board, TLoc}, board_state::BoardState, game::{Game}, pcg64_seeded, player_state::{Looker, PlayerState}, tile::{BaseKind, Tile, Kind}};
 use crate::tile::{BaseTile, GAct, BaseGAct};
-use crate::board_state::BaseBoardState;
+use crate::board_state::{BaseBoardState, Death, DeathCause};
 use crate::board::Port;
 use crate::player_state::{BasePlayerState, LookerTag};
 use crate::game::BaseGame;
 use crate::WrapBase;
+use crate::message::RejectReason;
 
 #[macro_export]
 macro_rules! for_each_game_state {
@@ -25,6 +26,12 @@ macro_rules! for_each_game_state {
             ($crate::game_state::BaseGameState)::Normal: $crate::game_state::GameState<
                 $crate::game::PathGame<$crate::board::RectangleBoard, $crate::tile::RegularTile<4>>
             >,
+            ($crate::game_state::BaseGameState)::Torus: $crate::game_state::GameState<
+                $crate::game::PathGame<$crate::board::TorusBoard, $crate::tile::RegularTile<4>>
+            >,
+            ($crate::game_state::BaseGameState)::SquareOctagon: $crate::game_state::GameState<
+                $crate::game::PathGame<$crate::board::SquareOctagonBoard, $crate::tile::SquareOctagonTile>
+            >,
         }
     };
 
@@ -35,6 +42,33 @@ macro_rules! for_each_game_state {
     };
 }
 
+/// How the winners of a game get decided once it's over
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WinCondition {
+    /// The players left standing win
+    #[default]
+    Survival,
+    /// Players score a point for each tile their token moves through,
+    /// and whoever has the highest score when the game's over wins, even if they died
+    Scoring,
+}
+
+/// Where a tile of a game's tile set is, as far as someone looking at the game can tell
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TileWhereabouts {
+    /// Placed on the board
+    OnBoard,
+    /// In a hand the looker can see, like their own
+    InHand,
+    /// Somewhere the looker can't see, like the draw pile or someone else's hand
+    Unseen,
+}
+
+/// A tile placement in (kind, index in hand, group action, location) format
+pub type TileMove<G> = (<G as Game>::Kind, u32, <G as Game>::GAct, <G as Game>::TLoc);
+/// A tile placement in (kind, index in hand, group action, location) format
+pub type BaseTileMove = (BaseKind, u32, BaseGAct, BaseTLoc);
+
 for_each_game_state! {
     p::x, t =>
     #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -47,6 +81,30 @@ for_each_game_state! {
             match self { $($($p)*::$x(s) => s.visible_state(looker).wrap_base()),* }
         }
 
+        /// This state with every hidden tile stripped down to its kind
+        pub fn redacted(&self) -> BaseGameState {
+            match self { $($($p)*::$x(s) => s.redacted().wrap_base()),* }
+        }
+
+        /// This state with every tile in players' hands visible, for games with open hands
+        pub fn with_hands_shown(&self) -> BaseGameState {
+            match self { $($($p)*::$x(s) => s.with_hands_shown().wrap_base()),* }
+        }
+
+        /// Fills in tiles in players' hands that were redacted, given in (player, index, tile) format.
+        pub fn reveal_tiles(&mut self, tiles: &[(u32, u32, BaseTile)]) {
+            match self { $($($p)*::$x(s) => s.reveal_tiles(&tiles.iter()
+                .map(|(p, i, t)| (*p, *i, <<$t as GameStateT>::Game as Game>::Tile::unwrap_base_ref(t).clone()))
+                .collect_vec())),* }
+        }
+
+        /// The tile in a player's hand by kind and index. None if the player is dead or there's no such tile.
+        pub fn hand_tile(&self, player: u32, kind: &BaseKind, index: u32) -> Option<BaseTile> {
+            match self { $($($p)*::$x(s) => s.player_state(player)
+                .and_then(|state| state.tile(Kind::unwrap_base_ref(kind), index))
+                .map(|tile| tile.clone().wrap_base())),* }
+        }
+
         /// Can someone place their token on the board on port `port`?
         pub fn can_place_player(&mut self, game: &BaseGame, port: &BasePort) -> bool {
             match self { $($($p)*::$x(s) => s.can_place_player(
@@ -56,7 +114,7 @@ for_each_game_state! {
         }
 
         /// Can `player` place a tile of kind `kind` from index `index` in their hand transformed by group action `action` to location `loc`?
-        pub fn can_place_tile(&mut self, game: &BaseGame, player: u32, kind: &BaseKind, index: u32, action: &BaseGAct, loc: &BaseTLoc) -> bool {
+        pub fn can_place_tile(&self, game: &BaseGame, player: u32, kind: &BaseKind, index: u32, action: &BaseGAct, loc: &BaseTLoc) -> bool {
             match self { $($($p)*::$x(s) => s.can_place_tile(
                 <$t as GameStateT>::Game::unwrap_base_ref(game),
                 player,
@@ -67,6 +125,15 @@ for_each_game_state! {
             )),* }
         }
 
+        /// All the tile placements `player` can make, in (kind, index, action, location) format.
+        /// Empty if the player is dead or hasn't placed their token.
+        pub fn legal_moves(&self, game: &BaseGame, player: u32) -> Vec<BaseTileMove> {
+            match self { $($($p)*::$x(s) => s.legal_moves(<$t as GameStateT>::Game::unwrap_base_ref(game), player)
+                .into_iter()
+                .map(|(kind, index, action, loc)| (kind.wrap_base(), index, action.wrap_base(), loc.wrap_base()))
+                .collect()),* }
+        }
+
         /// The player looking at this state, or None if no specific person
         pub fn looker(&self) -> Looker {
             match self { $($($p)*::$x(s) => s.looker()),* }
@@ -104,6 +171,20 @@ for_each_game_state! {
             match self { $($($p)*::$x(s) => s.winners().contains(&player)),* }
         }
 
+        pub fn win_condition(&self) -> WinCondition {
+            match self { $($($p)*::$x(s) => s.win_condition()),* }
+        }
+
+        /// The seed the draw piles got shuffled with. None unless the server is looking.
+        pub fn seed(&self) -> Option<u64> {
+            match self { $($($p)*::$x(s) => s.seed()),* }
+        }
+
+        /// Each player's score, indexed by player
+        pub fn scores(&self) -> Vec<u32> {
+            match self { $($($p)*::$x(s) => s.scores().clone()),* }
+        }
+
         /// Number of tiles left of each kind in the draw pile
         pub fn num_tiles_left_by_kind(&self) -> Vec<(BaseKind, u32)> {
             match self { $($($p)*::$x(s) => 
@@ -122,12 +203,36 @@ for_each_game_state! {
             ),* }
         }
 
+        /// Where each tile of the game's tile set is as far as the looker can tell, for counting which are left.
+        /// Tiles come in their canonical orientation, in tile number order.
+        pub fn tile_whereabouts(&self, game: &BaseGame) -> Vec<(BaseTile, TileWhereabouts)> {
+            let canonical = |tile: &BaseTile| tile.clone().with_visible(true).canonical();
+            let on_board = self.board_state().tiles_vec().iter().map(|(_, tile)| canonical(tile)).collect_vec();
+            let in_hand = (0..self.num_players())
+                .filter_map(|player| self.player_state(player))
+                .flat_map(|state| state.tiles_vec())
+                .flat_map(|(_, tiles)| tiles)
+                .filter(|tile| tile.visible())
+                .map(|tile| canonical(&tile))
+                .collect_vec();
+            game.all_tiles().iter().map(canonical).map(|tile| {
+                let whereabouts = if on_board.contains(&tile) {
+                    TileWhereabouts::OnBoard
+                } else if in_hand.contains(&tile) {
+                    TileWhereabouts::InHand
+                } else {
+                    TileWhereabouts::Unseen
+                };
+                (tile, whereabouts)
+            }).collect()
+        }
+
         /// Whose turn it is
         pub fn turn_player(&self) -> u32 {
             match self { $($($p)*::$x(s) => s.turn_player()),* }
         }
 
-        /// Whether all players placed their tokens
+        /// Whether all living players placed their tokens
         pub fn all_players_placed(&self) -> bool {
             match self { $($($p)*::$x(s) => s.all_players_placed()),* }
         }
@@ -136,33 +241,102 @@ for_each_game_state! {
             match self { $($($p)*::$x(s) => s.place_player(player, Port::unwrap_base_ref(port))),* }
         }
 
+        /// Whether `player` can still exchange their hand
+        pub fn can_exchange_hand(&self, player: u32) -> bool {
+            match self { $($($p)*::$x(s) => s.can_exchange_hand(player)),* }
+        }
+
+        /// Have the current player spend their turn exchanging their hand.
+        /// Returns the tiles drawn in (player, index, tile) format.
+        pub fn take_turn_exchanging_hand(&mut self) -> Vec<(u32, u32, BaseTile)> {
+            match self { $($($p)*::$x(s) => s.take_turn_exchanging_hand().into_iter()
+                .map(|(p, i, t)| (p, i, t.wrap_base()))
+                .collect()),* }
+        }
+
+        /// Whether `player` can still scout the draw piles
+        pub fn can_scout(&self, player: u32) -> bool {
+            match self { $($($p)*::$x(s) => s.can_scout(player)),* }
+        }
+
+        /// Has `player` use up their scouting.
+        /// Returns the top tile of each draw pile that isn't empty, visible.
+        pub fn scout(&mut self, player: u32) -> Vec<BaseTile> {
+            match self { $($($p)*::$x(s) => s.scout(player).into_iter().map(|t| t.wrap_base()).collect()),* }
+        }
+
+        /// Fills in the top tiles of draw piles that got scouted, replacing the redacted ones
+        pub fn reveal_top_tiles(&mut self, tiles: &[BaseTile]) {
+            match self { $($($p)*::$x(s) => s.reveal_top_tiles(
+                &tiles.iter().map(|t| <<$t as GameStateT>::Game as Game>::Tile::unwrap_base_ref(t).clone()).collect_vec()
+            )),* }
+        }
+
+        /// Whether the turn player has no legal move while someone else still has one,
+        /// so their turn has to be skipped for the game to go on.
+        pub fn turn_player_stuck(&self, game: &BaseGame) -> bool {
+            match self { $($($p)*::$x(s) => s.turn_player_stuck(<$t as GameStateT>::Game::unwrap_base_ref(game))),* }
+        }
+
+        /// Whether `player` has no legal move while someone else still has one.
+        pub fn player_stuck(&self, game: &BaseGame, player: u32) -> bool {
+            match self { $($($p)*::$x(s) => s.player_stuck(<$t as GameStateT>::Game::unwrap_base_ref(game), player)),* }
+        }
+
+        /// Skips the current player's turn without them placing a tile.
+        pub fn skip_turn(&mut self) {
+            match self { $($($p)*::$x(s) => s.skip_turn()),* }
+        }
+
+        /// Whether the last tile placement can be undone
+        pub fn can_undo(&self) -> bool {
+            match self { $($($p)*::$x(s) => s.can_undo()),* }
+        }
+
+        /// Puts the game back the way it was before the last tile placement.
+        /// Returns whether there was anything to undo.
+        pub fn undo_last_turn(&mut self) -> bool {
+            match self { $($($p)*::$x(s) => s.undo_last_turn()),* }
+        }
+
+        /// Removes a player from the game, probably because they left.
+        /// Returns a list of tiles added to player's hands in the form (player, index, tile)
+        pub fn remove_player(&mut self, game: &BaseGame, player: u32) -> Vec<(u32, u32, BaseTile)> {
+            match self { $($($p)*::$x(s) => s.remove_player(
+                <$t as GameStateT>::Game::unwrap_base_ref(game),
+                player,
+            ).into_iter().map(|(p, i, t)| (p, i, t.wrap_base())).collect()),* }
+        }
+
         /// Have the current player take a turn by placing a tile of kind `kind` from index `index` in their hand
         /// transformed by group action `action` to location `loc`.
         /// The turn is processed and then advances to the next player.
-        pub fn take_turn_placing_tile(&mut self, game: &BaseGame, kind: &BaseKind, index: u32, action: &BaseGAct, loc: &BaseTLoc) -> BaseTurnResult {
+        /// Returns what happened, in order.
+        pub fn take_turn_placing_tile(&mut self, game: &BaseGame, kind: &BaseKind, index: u32, action: &BaseGAct, loc: &BaseTLoc) -> BaseTurnEvents {
             match self { $($($p)*::$x(s) => {
-                let res = s.take_turn_placing_tile(
+                let events = s.take_turn_placing_tile(
                     <$t as GameStateT>::Game::unwrap_base_ref(game),
                     Kind::unwrap_base_ref(kind),
                     index,
                     GAct::unwrap_base_ref(action),
                     TLoc::unwrap_base_ref(loc),
                 );
-                BaseTurnResult {
-                    tile_placer: res.tile_placer,
-                    tile_placed: (res.tile_placed.0, res.tile_placed.1.wrap_base()),
-                    tile_loc: res.tile_loc.wrap_base(),
-                    player_ports: res.player_ports.into_iter().map(|p| p.wrap_base()).collect(),
-                    dead_players: res.dead_players,
-                    num_tiles_left: res.num_tiles_left.into_iter().map(|(k, n)| (k.wrap_base(), n)).collect(),
-                    drawn_tiles: res.drawn_tiles.into_iter().map(|(p, i, t)| (p, i, t.wrap_base())).collect(),
-                    game_over: res.game_over,
-                }
+                BaseTurnEvents(events.0.into_iter().map(|event| match event {
+                    TurnEvent::TilePlaced{ player, index, tile, loc } =>
+                        BaseTurnEvent::TilePlaced{ player, index, tile: tile.wrap_base(), loc: loc.wrap_base() },
+                    TurnEvent::PlayerMoved{ player, path } =>
+                        BaseTurnEvent::PlayerMoved{ player, path: path.into_iter().map(|p| p.wrap_base()).collect() },
+                    TurnEvent::PlayerDied{ kill } => BaseTurnEvent::PlayerDied{ kill },
+                    TurnEvent::TilesDrawn{ drawn } =>
+                        BaseTurnEvent::TilesDrawn{ drawn: drawn.into_iter().map(|(p, i, t)| (p, i, t.wrap_base())).collect() },
+                    TurnEvent::TurnChanged{ player } => BaseTurnEvent::TurnChanged{ player },
+                    TurnEvent::GameEnded{ winners } => BaseTurnEvent::GameEnded{ winners },
+                }).collect())
             }),* }
         }
     }
 
-    $($crate::impl_wrap_base!(BaseGameState::$x($t)))*;
+    $($crate::impl_wrap_base!(BaseGameState::$x($t));)*
 }
 
 /// This trait is just to make the macro work
@@ -170,6 +344,94 @@ pub trait GameStateT {
     type Game: Game;
 }
 
+impl BaseGameState {
+    /// Checks that `player` can place their token on port `port`, and says why not if they can't
+    pub fn check_place_player(&mut self, game: &BaseGame, player: u32, port: &BasePort) -> Result<(), RejectReason> {
+        if self.player_state(player).is_none() {
+            Err(RejectReason::Eliminated)
+        } else if self.board_state().player_port(player).is_some() {
+            Err(RejectReason::TokenAlreadyPlaced)
+        } else if !self.can_place_player(game, port) {
+            Err(RejectReason::IllegalMove)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that `player` can take their turn placing a tile of kind `kind` from index `index` in their hand
+    /// transformed by group action `action` to location `loc`, and says why not if they can't
+    pub fn check_place_tile(&self, game: &BaseGame, player: u32, kind: &BaseKind, index: u32, action: &BaseGAct, loc: &BaseTLoc)
+        -> Result<(), RejectReason>
+    {
+        if self.game_over() {
+            Err(RejectReason::GameOver)
+        } else if self.player_state(player).is_none() {
+            Err(RejectReason::Eliminated)
+        } else if !self.all_players_placed() {
+            Err(RejectReason::TokensNotPlaced)
+        } else if self.turn_player() != player {
+            Err(RejectReason::NotYourTurn{ turn_player: self.turn_player() })
+        } else if !self.can_place_tile(game, player, kind, index, action, loc) {
+            Err(RejectReason::IllegalMove)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that `player` can pick a tile of kind `kind` from index `index` in their hand
+    /// transformed by group action `action` to place at location `loc` once it's their turn,
+    /// in a game where everyone picks at once, and says why not if they can't
+    pub fn check_commit_tile(&self, game: &BaseGame, player: u32, kind: &BaseKind, index: u32, action: &BaseGAct, loc: &BaseTLoc)
+        -> Result<(), RejectReason>
+    {
+        if self.game_over() {
+            Err(RejectReason::GameOver)
+        } else if self.player_state(player).is_none() {
+            Err(RejectReason::Eliminated)
+        } else if !self.all_players_placed() {
+            Err(RejectReason::TokensNotPlaced)
+        } else if !self.can_place_tile(game, player, kind, index, action, loc) {
+            Err(RejectReason::IllegalMove)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that `player` can take their turn exchanging their hand, and says why not if they can't
+    pub fn check_exchange_hand(&self, player: u32) -> Result<(), RejectReason> {
+        if self.game_over() {
+            Err(RejectReason::GameOver)
+        } else if self.player_state(player).is_none() {
+            Err(RejectReason::Eliminated)
+        } else if !self.all_players_placed() {
+            Err(RejectReason::TokensNotPlaced)
+        } else if self.turn_player() != player {
+            Err(RejectReason::NotYourTurn{ turn_player: self.turn_player() })
+        } else if !self.can_exchange_hand(player) {
+            Err(RejectReason::IllegalMove)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that `player` can scout the draw piles, and says why not if they can't
+    pub fn check_scout(&self, player: u32) -> Result<(), RejectReason> {
+        if self.game_over() {
+            Err(RejectReason::GameOver)
+        } else if self.player_state(player).is_none() {
+            Err(RejectReason::Eliminated)
+        } else if !self.all_players_placed() {
+            Err(RejectReason::TokensNotPlaced)
+        } else if self.turn_player() != player {
+            Err(RejectReason::NotYourTurn{ turn_player: self.turn_player() })
+        } else if !self.can_scout(player) {
+            Err(RejectReason::IllegalMove)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 impl<G: Game> GameStateT for GameState<G> {
     type Game = G;
 }
@@ -186,20 +448,66 @@ pub struct GameState<G: Game> {
     tiles: FnvHashMap<G::Kind, VecDeque<G::Tile>>,
     #[getset(get = "pub")]
     winners: Vec<u32>,
+    #[getset(get_copy = "pub")]
+    win_condition: WinCondition,
+    /// Tiles each player's token moved through so far, indexed by player. Only decides the winners when scoring.
+    #[getset(get = "pub")]
+    scores: Vec<u32>,
+    /// Whether each player already exchanged their hand, which they can only do once per game
+    hands_exchanged: Vec<bool>,
+    /// Whether each player already scouted the draw piles, which they can only do once per game
+    scouted: Vec<bool>,
+    /// The seed the draw piles got shuffled with, so the game can be played again with the same tiles.
+    /// Hidden from everyone but the server, since it gives away the order of the draw piles.
+    #[getset(get_copy = "pub")]
+    seed: Option<u64>,
+    /// How the game was before the last tile placement, so it can be undone.
+    /// Never sent anywhere, since it holds hidden tiles. Moves that can't be taken back forget it.
+    // The default is spelled out so deserializing doesn't need the game to implement Default
+    #[serde(skip, default = "Option::default")]
+    last_turn: Option<Box<TurnUndo<G>>>,
+}
+
+/// Everything placing a tile can change, for undoing it
+#[derive(Clone, Debug)]
+struct TurnUndo<G: Game> {
+    board_state: BoardState<G::Board, G::Tile>,
+    player_states: Vec<Option<PlayerState<G::Tile>>>,
+    turn_player: u32,
+    tiles: FnvHashMap<G::Kind, VecDeque<G::Tile>>,
+    winners: Vec<u32>,
+    scores: Vec<u32>,
 }
 
 impl<G: Game> GameState<G> {
-    /// Construct a new state from a game
+    /// Construct a new state from a game, with the draw piles shuffled from a random seed
     pub fn new(game: &G, num_players: u32) -> Self {
+        let seed = rand::random();
+        log::debug!("Generating tiles for game with seed {}", seed);
+        Self::new_seeded(game, num_players, seed)
+    }
+
+    /// Construct a new state from a game, with the draw piles shuffled the same way every time for the same seed
+    pub fn new_seeded(game: &G, num_players: u32, seed: u64) -> Self {
         let mut tiles = game.all_tiles();
-        // TODO: Shuffle tiles first
-        tiles.sort_by_key(|tile| tile.kind().clone());
-        let groups = tiles.into_iter().group_by(|tile| tile.kind().clone());
-        let mut tiles = groups.into_iter().map(|(kind, tiles)|
-            (kind, tiles.map(|t| t.with_visible(false)).collect::<VecDeque<_>>())).collect::<FnvHashMap<_, _>>();
-        for tiles in tiles.values_mut() {
-            tiles.make_contiguous().shuffle(&mut pcg64!("Generating tiles for game"));
+        // Tiles already on the board don't get drawn again
+        let preplaced = game.preplaced_tiles();
+        for (_, placed) in &preplaced {
+            let placed = placed.clone().with_visible(false).canonical();
+            if let Some(i) = tiles.iter().position(|tile| tile.clone().with_visible(false).canonical() == placed) {
+                tiles.remove(i);
+            }
+        }
+        // The whole tile set gets shuffled before it's split into piles by kind,
+        // so the piles only depend on the seed and the tile set, not on what order the kinds come in
+        tiles.shuffle(&mut pcg64_seeded(seed));
+        let mut piles = game.board().all_kinds().into_iter()
+            .map(|kind| (kind, VecDeque::new()))
+            .collect::<FnvHashMap<_, _>>();
+        for tile in tiles {
+            piles.entry(tile.kind().clone()).or_insert_with(VecDeque::new).push_back(tile.with_visible(false));
         }
+        let tiles = piles;
 
         let mut state = Self {
             board_state: BoardState::new(game, num_players),
@@ -208,7 +516,16 @@ impl<G: Game> GameState<G> {
             turn_player: 0,
             tiles,
             winners: vec![],
+            win_condition: game.win_condition(),
+            scores: vec![0; num_players as usize],
+            hands_exchanged: vec![false; num_players as usize],
+            scouted: vec![false; num_players as usize],
+            seed: Some(seed),
+            last_turn: None,
         };
+        for (loc, tile) in preplaced {
+            state.board_state.place_tile(tile, &loc);
+        }
 
         // deal tiles
         for kind in game.board().all_kinds() {
@@ -218,6 +535,8 @@ impl<G: Game> GameState<G> {
             }).all(|b| b.is_some());
         }
 
+        #[cfg(debug_assertions)]
+        state.check_invariants(state.num_tiles());
         state
     }
 
@@ -240,6 +559,51 @@ impl<G: Game> GameState<G> {
                 (kind.clone(), tiles.iter().map(|t| t.clone().with_visible(false)).collect()))
                 .collect(),
             winners: self.winners.clone(),
+            win_condition: self.win_condition,
+            scores: self.scores.clone(),
+            hands_exchanged: self.hands_exchanged.clone(),
+            // Nobody gets told who else knows what's coming
+            scouted: self.scouted.iter().enumerate()
+                .map(|(player, scouted)| *scouted && (looker == Looker::Server || looker == Looker::Player(player as u32)))
+                .collect(),
+            seed: self.seed.filter(|_| looker == Looker::Server),
+            last_turn: None,
+        }
+    }
+
+    /// This state with every hidden tile, in hands and in the draw pile, stripped down to its kind.
+    /// Meant to be called on a visible state before sending it, so lookers can't dig
+    /// other players' tiles out of the serialized data.
+    pub fn redacted(&self) -> GameState<G> {
+        let mut result = self.clone();
+        for state in result.player_states.iter_mut().flatten() {
+            state.redact_hidden();
+        }
+        for tile in result.tiles.values_mut().flatten() {
+            if !tile.visible() {
+                *tile = tile.redacted();
+            }
+        }
+        result
+    }
+
+    /// This state with every tile in players' hands visible, for games where everyone sees everyone's tiles.
+    /// The draw pile stays hidden.
+    pub fn with_hands_shown(&self) -> GameState<G> {
+        let mut result = self.clone();
+        for state in result.player_states.iter_mut().flatten() {
+            state.show_all();
+        }
+        result
+    }
+
+    /// Fills in tiles in players' hands that were redacted, given in (player, index, tile) format.
+    /// Tiles of dead players are ignored.
+    pub fn reveal_tiles(&mut self, tiles: &[(u32, u32, G::Tile)]) {
+        for (player, index, tile) in tiles {
+            if let Some(state) = self.player_states[*player as usize].as_mut() {
+                state.set_tile(*index, tile.clone());
+            }
         }
     }
 
@@ -289,9 +653,11 @@ impl<G: Game> GameState<G> {
         tile
     }
 
-    /// Whether all players placed their tokens
+    /// Whether all living players placed their tokens
     pub fn all_players_placed(&self) -> bool {
-        self.board_state().all_players_placed()
+        (0..self.num_players())
+            .filter(|player| self.player_state(*player).is_some())
+            .all(|player| self.board_state().player_port(player).is_some())
     }
 
     /// Number of tiles left of each kind in the draw pile
@@ -315,8 +681,8 @@ impl<G: Game> GameState<G> {
 
     /// Move players that touch a tile along their respective paths until they face a dead end.
     /// Assumes the location has a tile on it.
-    /// Returns a list of dead players.
-    pub fn advance_players(&mut self, board: &G::Board, loc: &G::TLoc) -> Vec<u32> {
+    /// Returns the dead players and why they died.
+    pub fn advance_players(&mut self, board: &G::Board, loc: &G::TLoc) -> Vec<Death> {
         self.board_state.advance_players(board, loc)
     }
 
@@ -370,6 +736,91 @@ impl<G: Game> GameState<G> {
         }
     }
 
+    /// The first living player after `player` in turn order, wrapping around.
+    /// `player` itself is considered last. None if everyone is dead.
+    fn next_living_player(&self, player: u32) -> Option<u32> {
+        (0..self.num_players()).cycle().skip(player as usize + 1).take(self.num_players() as usize)
+            .find(|player| self.player_state(*player).is_some())
+    }
+
+    /// Ends the game if a unique player remains or if everyone's out of tiles.
+    /// Assumes at least one player is alive.
+    fn update_winners(&mut self) {
+        let mut remaining = (0..self.num_players())
+            .filter(|player| self.player_state(*player).is_some());
+        if let (Some(winner), None) = (remaining.next(), remaining.next()) {
+            // Unique player remaning, game is over
+            self.end_game(vec![winner]);
+        } else if self.player_states.iter()
+            .flat_map(|maybe| maybe.as_ref())
+            .all(|state| !state.has_tiles())
+        {
+            // If everyone's out of tiles, the game's over
+            self.end_game(self.living_players());
+        }
+    }
+
+    fn living_players(&self) -> Vec<u32> {
+        (0..self.num_players())
+            .filter(|player| self.player_state(*player).is_some())
+            .collect()
+    }
+
+    /// Ends the game. `survivors` win, unless the game is won by scoring,
+    /// in which case everyone tied for the highest score wins.
+    fn end_game(&mut self, survivors: Vec<u32>) {
+        self.winners = match self.win_condition {
+            WinCondition::Survival => survivors,
+            WinCondition::Scoring => {
+                let best = self.scores.iter().copied().max().unwrap_or(0);
+                (0..self.num_players()).filter(|player| self.scores[*player as usize] == best).collect()
+            }
+        };
+    }
+
+    /// Skips the current player's turn without them placing a tile, probably because they're disconnected.
+    /// The turn passes to the next living player.
+    pub fn skip_turn(&mut self) {
+        if let Some(next) = self.next_living_player(self.turn_player()) {
+            self.turn_player = next;
+        }
+        #[cfg(debug_assertions)]
+        self.check_invariants(self.num_tiles());
+    }
+
+    /// Removes a player from the game, probably because they left.
+    /// The player dies, their token is taken off the board, their tiles go back to the draw pile
+    /// and get redistributed, and the turn passes on if it was theirs.
+    /// Does nothing if the player is already dead or the game is over.
+    ///
+    /// Returns a list of tiles added to player's hands in the form (player, index, tile)
+    pub fn remove_player(&mut self, game: &G, player: u32) -> Vec<(u32, u32, G::Tile)> {
+        if self.game_over() || self.player_state(player).is_none() {
+            return vec![];
+        }
+
+        #[cfg(debug_assertions)]
+        let num_tiles = self.num_tiles();
+        self.last_turn = None;
+        self.handle_dead_players(game, &[player]);
+        self.board_state.remove_player(player);
+        let drawn_tiles = self.redistribute_tiles(game);
+
+        if let Some(next) = self.next_living_player(self.turn_player()) {
+            if self.turn_player == player {
+                self.turn_player = next;
+            }
+            self.update_winners();
+        } else {
+            // The last player left, so they get the win by default
+            self.end_game(vec![player]);
+        }
+
+        #[cfg(debug_assertions)]
+        self.check_invariants(num_tiles);
+        drawn_tiles
+    }
+
     /// Can someone place their token on the board on port `port`?
     pub fn can_place_player(&mut self, game: &G, port: &G::Port) -> bool {
         self.board_state.player_at(port).is_none() && game.start_ports().contains(port)
@@ -381,27 +832,143 @@ impl<G: Game> GameState<G> {
         self.place_player(self.turn_player(), port);
         // All players should still be alive
         self.turn_player = (self.turn_player + 1) % self.num_players();
+        #[cfg(debug_assertions)]
+        self.check_invariants(self.num_tiles());
     }
 
     /// Can `player` place a tile of kind `kind` from index `index` in their hand transformed by group action `action` to location `loc`?
-    pub fn can_place_tile(&mut self, game: &G, player: u32, kind: &G::Kind, index: u32, _action: &G::GAct, loc: &G::TLoc) -> bool {
+    pub fn can_place_tile(&self, game: &G, player: u32, kind: &G::Kind, index: u32, _action: &G::GAct, loc: &G::TLoc) -> bool {
         self.player_states[player as usize].as_ref().map_or(false, |state| index < state.num_tiles_by_kind(kind)) &&
             self.board_state.player_port(player).map_or(false, |port|
                 game.board().port_locs(port).contains(loc)) &&
             self.board_state.tile_at(loc).is_none() &&
+            !game.board().is_blocked(loc) &&
             kind == &game.board().kind_at(loc)
-            // TODO: In the original game, there's also the condition that a player can't kill themselves with a tile
-            // if they have a move that doesn't do that. Figure out if this should be checked here.
+    }
+
+    /// All the tile placements `player` can make, in (kind, index, action, location) format.
+    /// Empty if the player is dead or hasn't placed their token.
+    pub fn legal_moves(&self, game: &G, player: u32) -> Vec<TileMove<G>> {
+        let (state, port) = match (self.player_state(player), self.board_state.player_port(player)) {
+            (Some(state), Some(port)) => (state, port),
+            _ => return vec![],
+        };
+        let locs = game.board().port_locs(port);
+
+        state.tiles_vec().into_iter()
+            .flat_map(|(kind, tiles)| tiles.iter().enumerate().map(move |(index, tile)| (kind, index as u32, tile)))
+            .flat_map(|(kind, index, tile)| tile.all_actions().into_iter().map(move |action| (kind, index, action)))
+            .flat_map(|(kind, index, action)| locs.iter().map(move |loc| (kind.clone(), index, action.clone(), loc.clone())))
+            .filter(|(kind, index, action, loc)| self.can_place_tile(game, player, kind, *index, action, loc))
+            .collect()
+    }
+
+    /// Whether `player` can still exchange their hand.
+    /// They need tiles to exchange, and enough tiles of each kind have to be left to replace them.
+    pub fn can_exchange_hand(&self, player: u32) -> bool {
+        !self.hands_exchanged[player as usize] &&
+            self.player_state(player).is_some_and(|state| state.has_tiles() &&
+                state.tiles_vec().into_iter().all(|(kind, tiles)| self.tiles[kind].len() >= tiles.len()))
+    }
+
+    /// Have the current player spend their turn putting their hand at the bottom of the draw pile
+    /// and drawing as many tiles of each kind. Assumes they can.
+    /// Returns the tiles drawn in (player, index, tile) format. The turn then advances to the next player.
+    pub fn take_turn_exchanging_hand(&mut self) -> Vec<(u32, u32, G::Tile)> {
+        #[cfg(debug_assertions)]
+        let num_tiles = self.num_tiles();
+        let player = self.turn_player();
+        let old_tiles = self.player_states[player as usize].as_mut().expect("Player should be alive").remove_all_tiles();
+        let drawn = old_tiles.iter()
+            .flat_map(|tile| self.deal_tile(player, tile.kind()).map(|(index, tile)| (player, index, tile)))
+            .collect();
+        for tile in old_tiles {
+            self.tiles.get_mut(tile.kind()).unwrap().push_back(tile.with_visible(false));
+        }
+        self.hands_exchanged[player as usize] = true;
+        self.last_turn = None;
+
+        if let Some(next) = self.next_living_player(player) {
+            self.turn_player = next;
+        }
+        #[cfg(debug_assertions)]
+        self.check_invariants(num_tiles);
+        drawn
+    }
+
+    /// Whether `player` can still scout the draw piles. There has to be something left to see.
+    pub fn can_scout(&self, player: u32) -> bool {
+        !self.scouted[player as usize] && self.player_state(player).is_some() &&
+            self.tiles.values().any(|tiles| !tiles.is_empty())
+    }
+
+    /// Has `player` use up their scouting. The draw piles stay as they are.
+    /// Returns the top tile of each draw pile that isn't empty, visible.
+    pub fn scout(&mut self, player: u32) -> Vec<G::Tile> {
+        self.scouted[player as usize] = true;
+        // What the scout saw would outlast the undo
+        self.last_turn = None;
+        self.tiles.values()
+            .flat_map(|tiles| tiles.front().map(|tile| tile.clone().with_visible(true)))
+            .collect()
+    }
+
+    /// Fills in the top tiles of draw piles that got scouted, replacing the redacted ones
+    pub fn reveal_top_tiles(&mut self, tiles: &[G::Tile]) {
+        for tile in tiles {
+            if let Some(top) = self.tiles.get_mut(tile.kind()).and_then(VecDeque::front_mut) {
+                *top = tile.clone();
+            }
+        }
+    }
+
+    /// Whether the turn player has no legal move while someone else still has one,
+    /// so their turn has to be skipped for the game to go on.
+    pub fn turn_player_stuck(&self, game: &G) -> bool {
+        self.player_stuck(game, self.turn_player())
+    }
+
+    /// Whether `player` has no legal move while someone else still has one
+    pub fn player_stuck(&self, game: &G, player: u32) -> bool {
+        self.legal_moves(game, player).is_empty() &&
+            (0..self.num_players()).any(|other| other != player && !self.legal_moves(game, other).is_empty())
     }
 
     /// Have the current player take a turn by placing a tile of kind `kind` from index `index` in their hand
     /// transformed by group action `action` to location `loc`.
     /// The turn is processed and then advances to the next player.
-    pub fn take_turn_placing_tile(&mut self, game: &G, kind: &G::Kind, index: u32, action: &G::GAct, loc: &G::TLoc) -> TurnResult<G> {
+    /// Returns what happened, in order.
+    pub fn take_turn_placing_tile(&mut self, game: &G, kind: &G::Kind, index: u32, action: &G::GAct, loc: &G::TLoc) -> TurnEvents<G> {
+        #[cfg(debug_assertions)]
+        let num_tiles = self.num_tiles();
         let tile_placer = self.turn_player;
+        self.last_turn = Some(Box::new(TurnUndo {
+            board_state: self.board_state.clone(),
+            player_states: self.player_states.clone(),
+            turn_player: self.turn_player,
+            tiles: self.tiles.clone(),
+            winners: self.winners.clone(),
+            scores: self.scores.clone(),
+        }));
+
+        let tile = self.player_place_tile(self.turn_player(), kind, index, action, loc);
+        let mut events = vec![TurnEvent::TilePlaced{ player: tile_placer, index, tile, loc: loc.clone() }];
 
-        let tile_placed = self.player_place_tile(self.turn_player(), kind, index, action, loc);
-        let dead = self.advance_players(game.board(), loc);
+        // Each tile moved through adds a port to the trail
+        let trail_lengths = (0..self.num_players()).map(|player| self.board_state.player_trail(player).len()).collect_vec();
+        let (deaths, distances_moved) = self.board_state.advance_players_with_distances(game.board(), loc);
+        for (score, distance) in self.scores.iter_mut().zip(&distances_moved) {
+            *score += distance;
+        }
+        events.extend((0..self.num_players())
+            .filter(|player| distances_moved[*player as usize] > 0)
+            .map(|player| TurnEvent::PlayerMoved{
+                player, path: self.board_state.player_trail(player)[trail_lengths[player as usize]..].to_vec(),
+            }));
+        let dead = deaths.iter().map(|death| death.player()).collect_vec();
+        // Whoever placed the tile moved everyone who died, themselves included
+        events.extend(deaths.iter()
+            .map(|death| TurnEvent::PlayerDied{ kill: Kill{ killer: tile_placer, victim: death.player(), cause: death.cause() } }));
         let players_died = !dead.is_empty();
         self.handle_dead_players(game, &dead);
         let drawn_tiles = if players_died {
@@ -410,115 +977,294 @@ impl<G: Game> GameState<G> {
             self.deal_tile(self.turn_player, kind).map(|(index, tile)| (self.turn_player, index, tile)).into_iter().collect()
         };
 
-        let mut all_dead = false;
-        if let Some(next) = (0..self.num_players()).cycle().skip(self.turn_player() as usize + 1).take(self.num_players() as usize)
-            .find(|player| self.player_state(*player).is_some())
-        {
+        if let Some(next) = self.next_living_player(self.turn_player()) {
             self.turn_player = next;
+            self.update_winners();
+            // On boards with more than one kind of tile, everyone left can be next to only kinds they're out of
+            if self.winners.is_empty() && (0..self.num_players()).all(|player| self.legal_moves(game, player).is_empty()) {
+                self.end_game(self.living_players());
+            }
         } else {
             // Every player died, so the last ones that remained won
-            all_dead = true;
-            self.winners = dead.clone();
+            self.end_game(dead);
         }
 
-        let player_ports = (0..self.num_players())
-            .map(|player| self.board_state().player_port(player).expect("Players should have placed ports").clone())
-            .collect();
-        let num_tiles_left = self.tiles.iter()
-            .map(|(kind, tiles)| (kind.clone(), tiles.len() as u32))
-            .collect();
+        if !drawn_tiles.is_empty() {
+            events.push(TurnEvent::TilesDrawn{ drawn: drawn_tiles });
+        }
+        events.push(if self.winners.is_empty() {
+            TurnEvent::TurnChanged{ player: self.turn_player }
+        } else {
+            TurnEvent::GameEnded{ winners: self.winners.clone() }
+        });
+        #[cfg(debug_assertions)]
+        self.check_invariants(num_tiles);
+        TurnEvents(events)
+    }
+
+    /// Whether the last tile placement can be undone.
+    /// Skipped turns since then get undone along with it, but other moves can't be taken back.
+    pub fn can_undo(&self) -> bool {
+        self.last_turn.is_some()
+    }
+
+    /// Puts the game back the way it was before the last tile placement, with the turn back to whoever placed it.
+    /// Returns whether there was anything to undo.
+    pub fn undo_last_turn(&mut self) -> bool {
+        if let Some(last_turn) = self.last_turn.take() {
+            #[cfg(debug_assertions)]
+            let num_tiles = self.num_tiles();
+            let TurnUndo{ board_state, player_states, turn_player, tiles, winners, scores } = *last_turn;
+            self.board_state = board_state;
+            self.player_states = player_states;
+            self.turn_player = turn_player;
+            self.tiles = tiles;
+            self.winners = winners;
+            self.scores = scores;
+            #[cfg(debug_assertions)]
+            self.check_invariants(num_tiles);
+            true
+        } else { false }
+    }
+
+    /// Number of tiles in the game, counting hands, the board, and the draw piles
+    pub fn num_tiles(&self) -> usize {
+        self.player_states.iter().flatten()
+            .flat_map(|state| state.tiles_vec())
+            .map(|(_, tiles)| tiles.len())
+            .sum::<usize>() +
+            self.board_state.tiles_vec().len() +
+            self.tiles.values().map(VecDeque::len).sum::<usize>()
+    }
 
-        if !all_dead {
-            let mut remaining = (0..self.num_players())
-                .filter(|player| self.player_state(*player).is_some());
-            if let (Some(winner), None) = (remaining.next(), remaining.next()) {
-                // Unique player remaning, game is over
-                self.winners = vec![winner];
-            } else if self.player_states.iter()
-                .flat_map(|maybe| maybe.as_ref())
-                .all(|state| !state.has_tiles())
-            {
-                // If everyone's out of tiles, the game's over
-                self.winners = (0..self.num_players())
-                    .filter(|player| self.player_state(*player).is_some())
-                    .collect();
+    /// Panics if the state stopped making sense. Checked after every turn in debug builds.
+    ///
+    /// * The `num_tiles` tiles the game had are all still in a hand, on the board, or in a draw pile.
+    ///   Only living players have hands, so tiles dead players kept would go missing here.
+    /// * Winners are only picked once the game is over, and they're the living players or the top scorers
+    ///   depending on how the game is won.
+    /// * While the game goes on, it's a living player's turn.
+    pub fn check_invariants(&self, num_tiles: usize) {
+        assert_eq!(self.num_tiles(), num_tiles, "Tiles appeared or disappeared");
+        assert!(self.turn_player < self.num_players(), "Turn player {} isn't in the game", self.turn_player);
+
+        if self.game_over() {
+            assert!(self.winners.iter().all_unique() && self.winners.iter().all(|player| *player < self.num_players()),
+                "Winners {:?} aren't distinct players in the game", self.winners);
+            match self.win_condition {
+                WinCondition::Survival => {
+                    let living = self.living_players();
+                    // If nobody's left, the last ones standing won
+                    assert!(living.is_empty() || self.winners == living,
+                        "Winners {:?} aren't the survivors {:?}", self.winners, living);
+                }
+                WinCondition::Scoring => {
+                    let best = self.scores.iter().copied().max().unwrap_or(0);
+                    assert!((0..self.num_players()).all(|player| self.winners.contains(&player) == (self.scores[player as usize] == best)),
+                        "Winners {:?} aren't the top scorers with scores {:?}", self.winners, self.scores);
+                }
             }
+        } else {
+            assert!(self.player_state(self.turn_player).is_some(), "It's dead player {}'s turn", self.turn_player);
         }
+    }
+}
 
-        TurnResult {
-            tile_placer,
-            tile_placed: (index, tile_placed),
-            tile_loc: loc.clone(),
-            player_ports,
-            dead_players: dead,
-            num_tiles_left,
-            drawn_tiles,
-            game_over: !self.winners.is_empty()
-        }
+/// A player dying because of someone's tile placement, possibly their own
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, CopyGetters)]
+#[getset(get_copy = "pub")]
+pub struct Kill {
+    /// The player who placed the tile
+    killer: u32,
+    victim: u32,
+    cause: DeathCause,
+}
+
+impl Kill {
+    /// Whether the player killed themselves
+    pub fn self_elimination(&self) -> bool {
+        self.killer == self.victim
     }
 }
 
-/// The stuff that happened during a turn
-#[derive(Clone, Debug, Getters, CopyGetters)]
-pub struct TurnResult<G: Game> {
+/// Something that happened during a turn
+#[derive(Clone, Debug)]
+pub enum TurnEvent<G: Game> {
+    /// `player` placed the tile at `index` in their hand on `loc`, turned the way it went on the board
+    TilePlaced{ player: u32, index: u32, tile: G::Tile, loc: G::TLoc },
+    /// A player's token moved along the placed tile and any it led onto.
+    /// `path` has a port for each tile it moved through, ending where it stopped.
+    PlayerMoved{ player: u32, path: Vec<G::Port> },
+    /// A player died because of the tile placement
+    PlayerDied{ kill: Kill },
+    /// Players drew tiles, in (player, index, tile) format
+    TilesDrawn{ drawn: Vec<(u32, u32, G::Tile)> },
+    /// It's `player`'s turn now
+    TurnChanged{ player: u32 },
+    /// The game is over, and `winners` won
+    GameEnded{ winners: Vec<u32> },
+}
+
+/// Everything that happened during a turn, in the order it happened,
+/// so animating, logging, replaying, and sending the turn all go through the same events
+#[derive(Clone, Debug)]
+pub struct TurnEvents<G: Game>(Vec<TurnEvent<G>>);
+
+impl<G: Game> TurnEvents<G> {
+    pub fn events(&self) -> &[TurnEvent<G>] {
+        &self.0
+    }
+
     /// The player who placed the tile
-    #[getset(get_copy = "pub")]
-    tile_placer: u32,
-    /// index and tile placed
-    #[getset(get = "pub")]
-    tile_placed: (u32, G::Tile),
+    pub fn tile_placer(&self) -> u32 {
+        self.tile_placement().0
+    }
+
+    /// The index in the placer's hand of the tile placed, and the tile
+    pub fn tile_placed(&self) -> (u32, &G::Tile) {
+        let (_, index, tile, _) = self.tile_placement();
+        (index, tile)
+    }
+
     /// Where the tile was placed
-    #[getset(get = "pub")]
-    tile_loc: G::TLoc,
-    /// New locations of players, indexed by player
-    #[getset(get = "pub")]
-    player_ports: Vec<G::Port>,
+    pub fn tile_loc(&self) -> &G::TLoc {
+        self.tile_placement().3
+    }
+
+    fn tile_placement(&self) -> (u32, u32, &G::Tile, &G::TLoc) {
+        self.0.iter().find_map(|event| match event {
+            TurnEvent::TilePlaced{ player, index, tile, loc } => Some((*player, *index, tile, loc)),
+            _ => None,
+        }).expect("Every turn places a tile")
+    }
+
+    /// Who killed whom, one for each player that died
+    pub fn kills(&self) -> Vec<Kill> {
+        self.0.iter().filter_map(|event| match event {
+            TurnEvent::PlayerDied{ kill } => Some(*kill),
+            _ => None,
+        }).collect()
+    }
+
     /// Which players died
-    #[getset(get = "pub")]
-    dead_players: Vec<u32>,
-    /// New number of tiles per kind in the draw pile
-    #[getset(get = "pub")]
-    num_tiles_left: Vec<(G::Kind, u32)>,
-    /// New tiles drawn by players in (player, index, tile) format
-    #[getset(get = "pub")]
-    drawn_tiles: Vec<(u32, u32, G::Tile)>,
+    pub fn dead_players(&self) -> Vec<u32> {
+        self.kills().iter().map(|kill| kill.victim()).collect()
+    }
+
+    /// How many tiles a player moved through
+    pub fn distance_moved(&self, player: u32) -> u32 {
+        self.0.iter().map(|event| match event {
+            TurnEvent::PlayerMoved{ player: mover, path } if *mover == player => path.len() as u32,
+            _ => 0,
+        }).sum()
+    }
+
+    /// Tiles drawn by players in (player, index, tile) format
+    pub fn drawn_tiles(&self) -> Vec<(u32, u32, G::Tile)> {
+        self.0.iter().flat_map(|event| match event {
+            TurnEvent::TilesDrawn{ drawn } => drawn.clone(),
+            _ => vec![],
+        }).collect()
+    }
+
     /// Whether the game is over
-    #[getset(get = "pub")]
-    game_over: bool,
+    pub fn game_over(&self) -> bool {
+        self.0.iter().any(|event| matches!(event, TurnEvent::GameEnded{ .. }))
+    }
 }
 
-/// The stuff that happened during a turn
-#[derive(Clone, Debug, Getters, CopyGetters)]
-pub struct BaseTurnResult {
+/// Something that happened during a turn
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum BaseTurnEvent {
+    /// `player` placed the tile at `index` in their hand on `loc`, turned the way it went on the board
+    TilePlaced{ player: u32, index: u32, tile: BaseTile, loc: BaseTLoc },
+    /// A player's token moved along the placed tile and any it led onto.
+    /// `path` has a port for each tile it moved through, ending where it stopped.
+    PlayerMoved{ player: u32, path: Vec<BasePort> },
+    /// A player died because of the tile placement
+    PlayerDied{ kill: Kill },
+    /// Players drew tiles, in (player, index, tile) format
+    TilesDrawn{ drawn: Vec<(u32, u32, BaseTile)> },
+    /// It's `player`'s turn now
+    TurnChanged{ player: u32 },
+    /// The game is over, and `winners` won
+    GameEnded{ winners: Vec<u32> },
+}
+
+/// Everything that happened during a turn, in the order it happened,
+/// so animating, logging, replaying, and sending the turn all go through the same events
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BaseTurnEvents(Vec<BaseTurnEvent>);
+
+impl BaseTurnEvents {
+    pub fn events(&self) -> &[BaseTurnEvent] {
+        &self.0
+    }
+
     /// The player who placed the tile
-    #[getset(get_copy = "pub")]
-    tile_placer: u32,
-    /// index and tile placed
-    #[getset(get = "pub")]
-    tile_placed: (u32, BaseTile),
+    pub fn tile_placer(&self) -> u32 {
+        self.tile_placement().0
+    }
+
+    /// The index in the placer's hand of the tile placed, and the tile
+    pub fn tile_placed(&self) -> (u32, &BaseTile) {
+        let (_, index, tile, _) = self.tile_placement();
+        (index, tile)
+    }
+
     /// Where the tile was placed
-    #[getset(get = "pub")]
-    tile_loc: BaseTLoc,
-    /// New locations of players, indexed by player
-    #[getset(get = "pub")]
-    player_ports: Vec<BasePort>,
+    pub fn tile_loc(&self) -> &BaseTLoc {
+        self.tile_placement().3
+    }
+
+    fn tile_placement(&self) -> (u32, u32, &BaseTile, &BaseTLoc) {
+        self.0.iter().find_map(|event| match event {
+            BaseTurnEvent::TilePlaced{ player, index, tile, loc } => Some((*player, *index, tile, loc)),
+            _ => None,
+        }).expect("Every turn places a tile")
+    }
+
+    /// Who killed whom, one for each player that died
+    pub fn kills(&self) -> Vec<Kill> {
+        self.0.iter().filter_map(|event| match event {
+            BaseTurnEvent::PlayerDied{ kill } => Some(*kill),
+            _ => None,
+        }).collect()
+    }
+
     /// Which players died
-    #[getset(get = "pub")]
-    dead_players: Vec<u32>,
-    /// New number of tiles per kind in the draw pile
-    #[getset(get = "pub")]
-    num_tiles_left: Vec<(BaseKind, u32)>,
-    /// New tiles drawn by players in (player, index, tile) format
-    #[getset(get = "pub")]
-    drawn_tiles: Vec<(u32, u32, BaseTile)>,
+    pub fn dead_players(&self) -> Vec<u32> {
+        self.kills().iter().map(|kill| kill.victim()).collect()
+    }
+
+    /// How many tiles a player moved through
+    pub fn distance_moved(&self, player: u32) -> u32 {
+        self.0.iter().map(|event| match event {
+            BaseTurnEvent::PlayerMoved{ player: mover, path } if *mover == player => path.len() as u32,
+            _ => 0,
+        }).sum()
+    }
+
+    /// Tiles drawn by players in (player, index, tile) format
+    pub fn drawn_tiles(&self) -> Vec<(u32, u32, BaseTile)> {
+        self.0.iter().flat_map(|event| match event {
+            BaseTurnEvent::TilesDrawn{ drawn } => drawn.clone(),
+            _ => vec![],
+        }).collect()
+    }
+
     /// Whether the game is over
-    #[getset(get_copy = "pub")]
-    game_over: bool,
+    pub fn game_over(&self) -> bool {
+        self.0.iter().any(|event| matches!(event, BaseTurnEvent::GameEnded{ .. }))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{board::RectangleBoard, game::PathGame, tile::RegularTile};
+    use nalgebra::{point, vector};
+    use rand::Rng;
+
+    use crate::{board::{PortsPerEdgeTileConfig, RectangleBoard, SquareOctagonBoard, TorusBoard}, game::PathGame, math::{Pt2u, Vec2u}, tile::{RegularTile, SquareOctagonKind, SquareOctagonTile}};
 
     use super::*;
 
@@ -535,4 +1281,764 @@ mod tests {
             assert_eq!(state.board_state().player_port(player), None);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_game_state_new_seeded() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports, [((), 3)]);
+        let state = GameState::new_seeded(&game, 2, 7);
+        let again = GameState::new_seeded(&game, 2, 7);
+
+        // The same seed deals the same hands and stacks the same draw pile
+        assert_eq!(state.seed(), Some(7));
+        assert_eq!(state.tiles, again.tiles);
+        for player in 0..2 {
+            assert_eq!(state.player_state(player).unwrap().tiles(), again.player_state(player).unwrap().tiles());
+        }
+        assert_ne!(state.tiles, GameState::new_seeded(&game, 2, 8).tiles);
+
+        // Players can't work out what's coming from it
+        assert_eq!(state.visible_state(Looker::Player(0)).seed(), None);
+        assert_eq!(state.visible_state(Looker::Server).seed(), Some(7));
+    }
+
+    #[test]
+    fn test_game_state_new_seeded_kinds() {
+        let board = SquareOctagonBoard::new(3, 3, 1);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, SquareOctagonTile>::new(board, start_ports,
+            [(SquareOctagonKind::Octagon, 3), (SquareOctagonKind::Square, 1)]);
+        let hands = |state: &GameState<_>, kind| (0..state.num_players())
+            .map(|player| state.player_state(player).unwrap().tiles()[&kind].clone())
+            .collect_vec();
+
+        // A game can be dealt again from the seed it stored
+        let state = GameState::new(&game, 2);
+        let again = GameState::new_seeded(&game, 2, state.seed().unwrap());
+        assert_eq!(state.tiles, again.tiles);
+        for kind in [SquareOctagonKind::Octagon, SquareOctagonKind::Square] {
+            assert_eq!(hands(&state, kind), hands(&again, kind));
+
+            // Every tile of each kind is dealt or in that kind's pile, face down
+            let dealt = hands(&state, kind).into_iter().flatten().chain(state.tiles[&kind].iter().cloned())
+                .map(|tile| tile.with_visible(false).canonical())
+                .sorted().collect_vec();
+            let all = game.all_tiles().into_iter().filter(|tile| *tile.kind() == kind)
+                .map(|tile| tile.with_visible(false).canonical())
+                .sorted().collect_vec();
+            assert_eq!(dealt, all);
+            assert!(state.tiles[&kind].iter().all(|tile| !tile.visible()));
+        }
+
+        // Every kind gets shuffled, not just the first
+        let states = (0..10).map(|seed| GameState::new_seeded(&game, 2, seed)).collect_vec();
+        assert!(states.iter().map(|state| hands(state, SquareOctagonKind::Square)).unique().count() > 1);
+        assert!(states.iter().map(|state| state.tiles[&SquareOctagonKind::Octagon].clone()).unique().count() > 1);
+    }
+
+    #[test]
+    fn test_game_state_remove_player() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)]);
+        let mut state = GameState::new(&game, 3);
+        for player in 0..3 {
+            state.place_player(player, &start_ports[player as usize * 4]);
+        }
+        let num_tiles_left = state.num_tiles_left_by_kind()[0].1;
+
+        let drawn_tiles = state.remove_player(&game, 0);
+        assert!(drawn_tiles.is_empty());
+        assert!(state.player_state(0).is_none());
+        assert_eq!(state.board_state().player_port(0), None);
+        assert_eq!(state.num_tiles_left_by_kind()[0].1, num_tiles_left + 3);
+        assert_eq!(state.turn_player(), 1);
+        assert!(state.all_players_placed());
+        assert!(!state.game_over());
+
+        state.remove_player(&game, 2);
+        assert!(state.game_over());
+        assert_eq!(state.winners(), &vec![1]);
+    }
+
+    #[test]
+    fn test_game_state_scoring() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)])
+            .with_win_condition(WinCondition::Scoring);
+        let mut state = GameState::new(&game, 3);
+        for player in 0..3 {
+            state.place_player(player, &start_ports[player as usize * 4]);
+        }
+
+        let (kind, index, action, loc) = state.legal_moves(&game, 0).remove(0);
+        let result = state.take_turn_placing_tile(&game, &kind, index, &action, &loc);
+        assert_eq!(*state.scores(), (0..3).map(|player| result.distance_moved(player)).collect_vec());
+        assert!(state.scores()[0] >= 1);
+
+        // The player who went the farthest wins, even though they're out
+        state.scores = vec![5, 2, 7];
+        state.remove_player(&game, 2);
+        state.remove_player(&game, 0);
+        assert_eq!(state.winners(), &vec![2]);
+    }
+
+    #[test]
+    fn test_game_state_skip_turn() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports, [((), 3)]);
+        let mut state = GameState::new(&game, 3);
+
+        state.skip_turn();
+        assert_eq!(state.turn_player(), 1);
+        state.remove_player(&game, 2);
+        state.skip_turn();
+        assert_eq!(state.turn_player(), 0);
+        assert_eq!(state.player_state(1).unwrap().num_tiles_by_kind(&()), 3);
+    }
+
+    #[test]
+    fn test_game_state_turn_player_stuck() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)]);
+        let mut state = GameState::new(&game, 2);
+        // Nobody draws, and player 0 is out of tiles
+        state.tiles.values_mut().for_each(VecDeque::clear);
+        state.player_states[0].as_mut().unwrap().remove_all_tiles();
+        state.place_player(0, &start_ports[0]);
+        state.place_player(1, &start_ports[10]);
+
+        assert!(state.turn_player_stuck(&game));
+        assert!(!state.player_stuck(&game, 1));
+        state.skip_turn();
+        assert!(!state.turn_player_stuck(&game));
+
+        // If nobody can move, skipping doesn't help
+        state.player_states[1].as_mut().unwrap().remove_all_tiles();
+        assert!(!state.turn_player_stuck(&game));
+    }
+
+    #[test]
+    fn test_game_state_exchange_hand() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)]);
+        let mut state = GameState::new(&game, 2);
+        state.place_player(0, &start_ports[0]);
+        state.place_player(1, &start_ports[10]);
+        let old_hand = state.player_state(0).unwrap().tiles()[&()].clone();
+        let num_tiles_left = state.num_tiles_left_by_kind()[0].1;
+
+        assert!(state.can_exchange_hand(0));
+        let drawn = state.take_turn_exchanging_hand();
+        assert_eq!(drawn.iter().map(|(player, index, _)| (*player, *index)).collect_vec(), vec![(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(state.player_state(0).unwrap().num_tiles_by_kind(&()), 3);
+        // The old hand went to the bottom of the pile
+        assert_eq!(state.num_tiles_left_by_kind()[0].1, num_tiles_left);
+        assert_eq!(
+            state.tiles[&()].iter().rev().take(3).rev().map(|tile| tile.clone().with_visible(true)).collect_vec(),
+            old_hand.iter().map(|tile| tile.clone().with_visible(true)).collect_vec(),
+        );
+        assert_eq!(state.turn_player(), 1);
+
+        // Only once per game
+        assert!(!state.can_exchange_hand(0));
+        assert!(state.can_exchange_hand(1));
+        // and only if the pile can replace the hand
+        state.tiles.values_mut().for_each(VecDeque::clear);
+        assert!(!state.can_exchange_hand(1));
+    }
+
+    #[test]
+    fn test_game_state_scout() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)]);
+        let mut state = GameState::new(&game, 2);
+        state.place_player(0, &start_ports[0]);
+        state.place_player(1, &start_ports[10]);
+        let pile = state.tiles[&()].clone();
+
+        assert!(state.can_scout(0));
+        let seen = state.scout(0);
+        assert_eq!(seen, vec![pile[0].clone().with_visible(true)]);
+        assert_eq!(state.tiles[&()], pile, "Scouting doesn't change the pile");
+        assert!(!state.can_scout(0));
+        assert!(state.can_scout(1));
+
+        // Nobody else finds out what was seen, or that anything was
+        let other = state.visible_state(Looker::Player(1)).redacted();
+        assert!(other.tiles[&()].iter().all(|tile| *tile == tile.redacted()));
+        assert!(other.can_scout(0));
+        let mut own = state.visible_state(Looker::Player(0)).redacted();
+        assert!(!own.can_scout(0));
+
+        // The scout can fill in the top of their copy of the pile, and then draws what they saw
+        own.reveal_top_tiles(&seen);
+        assert_eq!(own.top_tile_left_of_kind(&()), Some(&seen[0]));
+        let (_, drawn) = own.deal_tile(0, &()).unwrap();
+        assert_eq!(drawn, seen[0]);
+
+        // There has to be something to see
+        state.tiles.values_mut().for_each(VecDeque::clear);
+        assert!(!state.can_scout(1));
+    }
+
+    #[test]
+    fn test_game_state_legal_moves() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)]);
+        let mut state = GameState::new(&game, 2);
+        assert!(state.legal_moves(&game, 0).is_empty());
+
+        state.place_player(0, &start_ports[0]);
+        let moves = state.legal_moves(&game, 0);
+        // 3 tiles in 4 rotations, all in the one spot in front of the token
+        assert_eq!(moves.len(), 12);
+        assert!(moves.iter().all(|(kind, index, action, loc)| state.can_place_tile(&game, 0, kind, *index, action, loc)));
+        assert!(moves.iter().map(|(_, _, _, loc)| loc).all_equal());
+    }
+
+    #[test]
+    fn test_turn_events() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)]);
+        let mut state = GameState::new(&game, 2);
+        state.place_player(0, &start_ports[0]);
+        state.place_player(1, &start_ports[10]);
+
+        while !state.game_over() {
+            let player = state.turn_player();
+            let (kind, index, action, loc) = state.legal_moves(&game, player).remove(0);
+            let tile = state.player_state(player).unwrap().tile(&kind, index).unwrap().apply_action(&action);
+            let result = state.take_turn_placing_tile(&game, &kind, index, &action, &loc);
+            let events = result.events();
+
+            // The tile goes down first, and the turn passes or the game ends last
+            assert!(matches!(&events[0], TurnEvent::TilePlaced{ player: placer, index: i, tile: t, loc: l }
+                if *placer == player && *i == index && *t == tile && *l == loc));
+            match events.last().unwrap() {
+                TurnEvent::TurnChanged{ player } => assert_eq!(*player, state.turn_player()),
+                TurnEvent::GameEnded{ winners } => assert_eq!(winners, state.winners()),
+                event => panic!("Turn ended with {:?}", event),
+            }
+
+            // Paths pick up where the trails were before
+            for event in events {
+                if let TurnEvent::PlayerMoved{ player, path } = event {
+                    assert!(state.board_state().player_trail(*player).ends_with(path));
+                }
+            }
+            for (player, index, tile) in result.drawn_tiles() {
+                assert_eq!(state.player_state(player).unwrap().tile(tile.kind(), index), Some(&tile));
+            }
+        }
+    }
+
+    #[test]
+    fn test_game_state_kills() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)]);
+        let mut state = GameState::new(&game, 2);
+        // Player 0 holds every tile so the moves don't depend on the deal
+        let hand = state.player_states[0].as_mut().unwrap();
+        hand.remove_all_tiles();
+        for tile in game.all_tiles() {
+            hand.add_tile(tile);
+        }
+        // Both tokens enter the same corner tile
+        state.place_player(0, &start_ports[0]);
+        state.place_player(1, &start_ports[1]);
+
+        let kills = state.legal_moves(&game, 0).into_iter()
+            .flat_map(|(kind, index, action, loc)| {
+                let result = state.clone().take_turn_placing_tile(&game, &kind, index, &action, &loc);
+                assert_eq!(result.kills().iter().map(|kill| kill.victim()).collect_vec(), result.dead_players());
+                result.kills()
+            })
+            .collect_vec();
+        assert!(kills.iter().all(|kill| kill.killer() == 0 && kill.cause() == DeathCause::Edge));
+        assert!(kills.iter().any(|kill| !kill.self_elimination()), "No move kills the other player");
+        assert!(kills.iter().any(Kill::self_elimination), "No move kills the placer");
+    }
+
+    #[test]
+    fn test_game_state_undo() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)]);
+        // Seeded, so there's a move that kills someone without ending the game
+        let mut state = GameState::new_seeded(&game, 3, 1);
+        state.place_player(0, &start_ports[0]);
+        state.place_player(1, &start_ports[1]);
+        state.place_player(2, &start_ports[start_ports.len() / 2]);
+        assert!(!state.can_undo());
+
+        // Undo a placement that kills someone, so as much as possible changes
+        let before = state.clone();
+        let (kind, index, action, loc) = state.legal_moves(&game, 0).into_iter()
+            .find(|(kind, index, action, loc)| {
+                let result = state.clone().take_turn_placing_tile(&game, kind, *index, action, loc);
+                !result.dead_players().is_empty() && !result.game_over()
+            })
+            .expect("No move kills anyone without ending the game");
+        state.take_turn_placing_tile(&game, &kind, index, &action, &loc);
+        state.skip_turn();
+        assert!(state.can_undo());
+        assert!(!state.visible_state(Looker::Player(0)).can_undo());
+
+        assert!(state.undo_last_turn());
+        assert!(!state.can_undo());
+        assert!(!state.undo_last_turn());
+        assert_eq!(state.turn_player(), 0);
+        assert_eq!(state.board_state().tile_at(&loc), None);
+        assert_eq!(state.tiles, before.tiles);
+        assert_eq!(state.scores(), before.scores());
+        assert!(state.winners().is_empty());
+        for player in 0..3 {
+            assert_eq!(state.player_state(player).unwrap().tiles(), before.player_state(player).unwrap().tiles());
+            assert_eq!(state.board_state().player_port(player), before.board_state().player_port(player));
+        }
+
+        // Exchanging a hand can't be taken back, since everyone saw the tiles go
+        state.take_turn_placing_tile(&game, &kind, index, &action, &loc);
+        state.take_turn_exchanging_hand();
+        assert!(!state.can_undo());
+    }
+
+    #[test]
+    fn test_board_state_preview_tile() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board.clone(), start_ports.clone(), [((), 3)]);
+        let mut state = GameState::new(&game, 2);
+        state.place_player(0, &start_ports[0]);
+        state.place_player(1, &start_ports[1]);
+
+        for (kind, index, action, loc) in state.legal_moves(&game, 0) {
+            let tile = state.player_state(0).unwrap().tile(&kind, index).unwrap().apply_action(&action);
+            let (preview, deaths) = state.board_state().preview_tile(&board, &tile, &loc);
+            let mut after = state.clone();
+            let result = after.take_turn_placing_tile(&game, &kind, index, &action, &loc);
+            assert_eq!(deaths.iter().map(|death| death.player()).collect_vec(), result.dead_players());
+            for player in 0..2 {
+                assert_eq!(preview.player_port(player), after.board_state().player_port(player));
+            }
+        }
+        // The state being previewed doesn't change
+        assert!(state.board_state().tiles_vec().is_empty());
+    }
+
+    #[test]
+    fn test_board_state_trails() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board.clone(), start_ports.clone(), [((), 3)]);
+        let mut state = GameState::new(&game, 2);
+        assert!(state.board_state().player_trail(0).is_empty());
+        state.place_player(0, &start_ports[0]);
+        state.place_player(1, &start_ports[10]);
+        // Placing a token where it already is doesn't count as moving
+        state.place_player(0, &start_ports[0]);
+        assert_eq!(state.board_state().player_trail(0), &start_ports[..1]);
+
+        while !state.game_over() {
+            let player = state.turn_player();
+            let (kind, index, action, loc) = state.legal_moves(&game, player).remove(0);
+            state.take_turn_placing_tile(&game, &kind, index, &action, &loc);
+
+            for player in 0..2 {
+                let trail = state.board_state().player_trail(player);
+                if let Some(port) = state.board_state().player_port(player) {
+                    assert_eq!(trail.last(), Some(port));
+                }
+                // Each step goes through a tile on the board
+                for (from, to) in trail.iter().tuple_windows() {
+                    assert!(board.port_locs(from).iter().any(|loc| board.port_locs(to).contains(loc) &&
+                        state.board_state().tile_at(loc).is_some()));
+                }
+            }
+        }
+    }
+
+    /// A row of three tile locations where the port between the first two also borders the third,
+    /// in place of the third's left port, so paths coming out onto it have a choice of where to go
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct ForkBoard(RectangleBoard);
+
+    impl ForkBoard {
+        /// The port with a choice
+        fn fork() -> (Pt2u, Vec2u) {
+            (point![1, 0], vector![0, 1])
+        }
+
+        /// The third location's left port, which only borders the second location now
+        fn replaced() -> (Pt2u, Vec2u) {
+            (point![2, 0], vector![0, 1])
+        }
+    }
+
+    impl Board for ForkBoard {
+        type TLoc = Pt2u;
+        type Port = (Pt2u, Vec2u);
+        type Kind = ();
+        type TileConfig = PortsPerEdgeTileConfig;
+
+        fn all_ports(&self) -> Vec<Self::Port> { self.0.all_ports() }
+        fn boundary_ports(&self) -> Vec<Self::Port> { self.0.boundary_ports() }
+        fn all_kinds(&self) -> Vec<Self::Kind> { self.0.all_kinds() }
+        fn contains(&self, loc: &Self::TLoc) -> bool { self.0.contains(loc) }
+        fn kind_at(&self, loc: &Self::TLoc) -> Self::Kind { self.0.kind_at(loc) }
+        fn tile_config(&self) -> Self::TileConfig { self.0.tile_config() }
+        fn is_blocked(&self, loc: &Self::TLoc) -> bool { self.0.is_blocked(loc) }
+
+        fn loc_ports(&self, loc: &Self::TLoc) -> Vec<Self::Port> {
+            self.0.loc_ports(loc).into_iter()
+                .map(|port| if *loc == point![2, 0] && port == Self::replaced() { Self::fork() } else { port })
+                .collect()
+        }
+
+        fn port_locs(&self, port: &Self::Port) -> Vec<Self::TLoc> {
+            if *port == Self::fork() {
+                vec![point![0, 0], point![1, 0], point![2, 0]]
+            } else if *port == Self::replaced() {
+                vec![point![1, 0]]
+            } else {
+                self.0.port_locs(port)
+            }
+        }
+    }
+
+    #[test]
+    fn test_board_state_branching_paths() {
+        let start = (point![0, 0], vector![0, 1]);
+        let straight = RegularTile::<4>::all_including_rotations(PortsPerEdgeTileConfig(1)).into_iter()
+            .find(|tile| tile.output(3) == 1).unwrap();
+        let advance = |board: RectangleBoard, tiles: &[Pt2u]| {
+            let board = ForkBoard(board);
+            let game = PathGame::<_, RegularTile<4>>::new(board.clone(), vec![start], [((), 1)]);
+            let mut state = BoardState::new(&game, 1);
+            state.place_player(0, &start);
+            for loc in tiles {
+                state.place_tile(straight.clone(), loc);
+            }
+            state.place_tile(straight.clone(), &point![0, 0]);
+            let deaths = state.advance_players(&board, &point![0, 0]);
+            (state, deaths)
+        };
+        let board = RectangleBoard::new(3, 1, 1);
+
+        // Nowhere to go on to, so the token waits at the fork
+        let (state, deaths) = advance(board.clone(), &[]);
+        assert!(deaths.is_empty());
+        assert_eq!(state.player_port(0), Some(&ForkBoard::fork()));
+
+        // The only tile past the fork is the way to go
+        let (state, deaths) = advance(board.clone(), &[point![2, 0]]);
+        assert_eq!(deaths.iter().map(|death| (death.player(), death.cause())).collect_vec(), vec![(0, DeathCause::Edge)]);
+        assert_eq!(state.player_trail(0), &[start, ForkBoard::fork(), (point![3, 0], vector![0, 1])]);
+
+        // With tiles on both, the first of the port's locations wins
+        let (state, deaths) = advance(board.clone(), &[point![1, 0], point![2, 0]]);
+        assert_eq!(deaths.iter().map(|death| (death.player(), death.cause())).collect_vec(), vec![(0, DeathCause::Edge)]);
+        assert_eq!(state.player_trail(0), &[start, ForkBoard::fork(), ForkBoard::replaced()]);
+
+        // A blocked way on only matters if every way on is blocked
+        let (state, deaths) = advance(board.clone().with_blocked([point![1, 0]]), &[]);
+        assert!(deaths.is_empty());
+        assert_eq!(state.player_port(0), Some(&ForkBoard::fork()));
+        let (_, deaths) = advance(board.with_blocked([point![1, 0], point![2, 0]]), &[]);
+        assert_eq!(deaths.iter().map(|death| (death.player(), death.cause())).collect_vec(), vec![(0, DeathCause::Blocked)]);
+    }
+
+    #[test]
+    fn test_torus_game() {
+        let board = TorusBoard::new(4, 4, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)]);
+        for seed in 0..20 {
+            let mut state = GameState::new(&game, 3);
+            for player in 0..3 {
+                state.place_player(player, &start_ports[(player as usize * 5 + seed) % start_ports.len()]);
+            }
+
+            while !state.game_over() {
+                let player = state.turn_player();
+                let moves = state.legal_moves(&game, player);
+                if moves.is_empty() {
+                    if !state.turn_player_stuck(&game) {
+                        break;
+                    }
+                    state.skip_turn();
+                    continue;
+                }
+                let (kind, index, action, loc) = &moves[seed % moves.len()];
+                let result = state.take_turn_placing_tile(&game, kind, *index, action, loc);
+
+                // Nobody walks off the board, and nobody is left sharing a port
+                assert!(result.kills().iter().all(|kill| kill.cause() != DeathCause::Edge));
+                let ports = (0..3).flat_map(|player| state.board_state().player_port(player)).collect_vec();
+                assert!(ports.iter().all_unique());
+                for player in result.dead_players() {
+                    assert_eq!(state.board_state().player_port(player), None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_blocked_game() {
+        let board = RectangleBoard::new(4, 4, 2).with_blocked([point![1, 1], point![2, 1], point![1, 2], point![2, 2]]);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board.clone(), start_ports.clone(), [((), 3)]);
+        let mut blocked_kills = 0;
+        for seed in 0..20 {
+            let mut state = GameState::new(&game, 3);
+            for player in 0..3 {
+                state.place_player(player, &start_ports[(player as usize * 5 + seed) % start_ports.len()]);
+            }
+
+            while !state.game_over() {
+                let player = state.turn_player();
+                let moves = state.legal_moves(&game, player);
+                if moves.is_empty() {
+                    break;
+                }
+                assert!(moves.iter().all(|(_, _, _, loc)| !board.is_blocked(loc)));
+                let (kind, index, action, loc) = &moves[seed % moves.len()];
+                let result = state.take_turn_placing_tile(&game, kind, *index, action, loc);
+
+                // Players that ran into the hole stop right next to it
+                for kill in result.kills().iter().filter(|kill| kill.cause() == DeathCause::Blocked) {
+                    let port = state.board_state().player_port(kill.victim()).unwrap();
+                    assert!(board.port_locs(port).iter().any(|loc| board.is_blocked(loc)));
+                    blocked_kills += 1;
+                }
+            }
+        }
+        assert!(blocked_kills > 0);
+    }
+
+    #[test]
+    fn test_preplaced_tiles() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let all_tiles = RegularTile::<4>::all(board.tile_config());
+        let (tile, other) = (all_tiles[5].rotate(1), all_tiles[6].clone());
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)])
+            .with_preplaced_tiles([(point![0, 0], tile.clone()), (point![0, 0], other.clone()), (point![6, 0], other), (point![2, 3], tile.clone())]);
+
+        // The second tile on the top left and the one off the board don't fit
+        assert_eq!(game.preplaced_tiles().len(), 2);
+        assert_eq!(game.start_ports().len(), start_ports.len() - 4);
+
+        let state = GameState::new(&game, 2);
+        assert_eq!(state.board_state().tile_at(&point![0, 0]), Some(&tile));
+        assert_eq!(state.board_state().tile_at(&point![2, 3]), Some(&tile));
+        assert_eq!(state.board_state().tile_at(&point![6, 0]), None);
+        // Only one copy of the tile was in the draw pile to take out
+        assert_eq!(state.tiles[&()].len() + 2 * 3, all_tiles.len() - 1);
+    }
+
+    #[test]
+    fn test_tile_whereabouts() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let all_tiles = RegularTile::<4>::all(board.tile_config());
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports, [((), 3)])
+            .with_preplaced_tiles([(point![2, 3], all_tiles[5].rotate(1))])
+            .wrap_base();
+        let state = game.new_state(2);
+        let count = |state: &BaseGameState, whereabouts| state.tile_whereabouts(&game).into_iter()
+            .filter(|(_, w)| *w == whereabouts)
+            .count();
+
+        // Players only see their own hand, and the tile on the board counts however it's turned
+        let visible = state.visible_state(Looker::Player(1)).redacted();
+        let whereabouts = visible.tile_whereabouts(&game);
+        assert_eq!(whereabouts.len(), 35);
+        assert_eq!(whereabouts[5], (all_tiles[5].clone().wrap_base(), TileWhereabouts::OnBoard));
+        assert_eq!(count(&visible, TileWhereabouts::InHand), 3);
+        assert_eq!(count(&visible, TileWhereabouts::Unseen), 31);
+
+        // Spectators see every hand
+        let spectated = state.visible_state(Looker::Spectator).redacted();
+        assert_eq!(count(&spectated, TileWhereabouts::InHand), 6);
+    }
+
+    #[test]
+    fn test_square_octagon_game() {
+        let board = SquareOctagonBoard::new(3, 3, 1);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, SquareOctagonTile>::new(board.clone(), start_ports.clone(),
+            [(SquareOctagonKind::Octagon, 3), (SquareOctagonKind::Square, 1)]);
+        for seed in 0..10 {
+            let mut state = GameState::new(&game, 2);
+            // Each kind has its own draw pile, and players get tiles of both
+            assert_eq!(state.num_tiles_left_by_kind().into_iter().sorted().collect_vec(),
+                vec![(&SquareOctagonKind::Octagon, 18 - 2 * 3), (&SquareOctagonKind::Square, 0)]);
+            for player in 0..2 {
+                assert_eq!(state.player_state(player).unwrap().num_tiles_by_kind(&SquareOctagonKind::Square), 1);
+                state.place_player(player, &start_ports[(player as usize * 7 + seed) % start_ports.len()]);
+            }
+
+            while !state.game_over() {
+                let player = state.turn_player();
+                let moves = state.legal_moves(&game, player);
+                if moves.is_empty() {
+                    assert!(state.turn_player_stuck(&game));
+                    state.skip_turn();
+                    continue;
+                }
+                // Tiles only go where their kind does
+                assert!(moves.iter().all(|(kind, _, _, loc)| kind == &board.kind_at(loc)));
+                let (kind, index, action, loc) = &moves[seed % moves.len()];
+                state.take_turn_placing_tile(&game, kind, *index, action, loc);
+            }
+        }
+    }
+
+    #[test]
+    fn test_game_state_check_moves() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports.clone(), [((), 3)]);
+        let mut state = GameState::new(&game, 2);
+        state.place_player(0, &start_ports[0]);
+        let (kind, index, action, loc) = state.legal_moves(&game, 0).remove(0);
+        let (kind, action, loc) = (kind.wrap_base(), action.wrap_base(), loc.wrap_base());
+        let game = game.wrap_base();
+        let mut state = state.wrap_base();
+
+        assert_eq!(state.check_place_player(&game, 0, &start_ports[1].wrap_base()), Err(RejectReason::TokenAlreadyPlaced));
+        assert_eq!(state.check_place_player(&game, 1, &start_ports[0].wrap_base()), Err(RejectReason::IllegalMove));
+        assert_eq!(state.check_place_tile(&game, 0, &kind, index, &action, &loc), Err(RejectReason::TokensNotPlaced));
+
+        state.place_player(1, &start_ports[1].wrap_base());
+        assert_eq!(state.check_place_tile(&game, 0, &kind, index, &action, &loc), Ok(()));
+        assert_eq!(state.check_place_tile(&game, 1, &kind, index, &action, &loc), Err(RejectReason::NotYourTurn{ turn_player: 0 }));
+        assert_eq!(state.check_place_tile(&game, 0, &kind, 3, &action, &loc), Err(RejectReason::IllegalMove));
+    }
+
+    #[test]
+    fn test_game_state_redacted() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports, [((), 3)]);
+        let state = GameState::new(&game, 3);
+        let visible = state.visible_state(Looker::Player(1)).redacted();
+
+        for player in 0..3 {
+            let tiles = &visible.player_state(player).unwrap().tiles()[&()];
+            if player == 1 {
+                assert_eq!(tiles, &state.player_state(player).unwrap().tiles()[&()]
+                    .iter().map(|tile| tile.clone().with_visible(true)).collect_vec());
+            } else {
+                assert!(tiles.iter().all(|tile| *tile == tile.redacted()));
+            }
+        }
+        assert!(visible.tiles[&()].iter().all(|tile| *tile == tile.redacted()));
+
+        // With open hands, everyone's tiles show, but the draw pile stays hidden
+        let open = state.visible_state(Looker::Player(1)).with_hands_shown().redacted();
+        for player in 0..3 {
+            assert_eq!(&open.player_state(player).unwrap().tiles()[&()], &state.player_state(player).unwrap().tiles()[&()]
+                .iter().map(|tile| tile.clone().with_visible(true)).collect_vec());
+        }
+        assert!(open.tiles[&()].iter().all(|tile| *tile == tile.redacted()));
+
+        // The real tiles can be filled back in
+        let mut revealed = visible;
+        let tile = state.player_state(0).unwrap().tiles()[&()][2].clone();
+        revealed.reveal_tiles(&[(0, 2, tile.clone())]);
+        assert_eq!(revealed.player_state(0).unwrap().tile(&(), 2), Some(&tile));
+    }
+
+    /// Plays a game out with random moves, the rare ones included, checking the invariants after each
+    fn fuzz_game<G: Game>(game: &G, num_players: u32, seed: u64) {
+        let mut rng = pcg64_seeded(seed);
+        let mut state = GameState::new_seeded(game, num_players, seed);
+        let num_tiles = game.all_tiles().len();
+        state.check_invariants(num_tiles);
+
+        for _ in 0..num_players {
+            let port = game.start_ports().into_iter().filter(|port| state.can_place_player(game, port)).collect_vec()
+                .choose(&mut rng).cloned().expect("Not enough start ports");
+            state.take_turn_placing_player(game, &port);
+            state.check_invariants(num_tiles);
+        }
+
+        for _ in 0..200 {
+            if state.game_over() {
+                break;
+            }
+            let player = state.turn_player();
+            match rng.gen_range(0..20) {
+                0 => {
+                    let living = (0..num_players).filter(|player| state.player_state(*player).is_some()).collect_vec();
+                    state.remove_player(game, *living.choose(&mut rng).unwrap());
+                }
+                1 if state.can_undo() => { state.undo_last_turn(); }
+                2 if state.can_exchange_hand(player) => { state.take_turn_exchanging_hand(); }
+                3 if state.can_scout(player) => { state.scout(player); }
+                4 => state.skip_turn(),
+                _ => if let Some((kind, index, action, loc)) = state.legal_moves(game, player).choose(&mut rng) {
+                    state.take_turn_placing_tile(game, kind, *index, action, loc);
+                } else if state.turn_player_stuck(game) {
+                    state.skip_turn();
+                } else {
+                    break;
+                },
+            }
+            state.check_invariants(num_tiles);
+            // What players see has all the same tiles, just some face down
+            state.visible_state(Looker::Player(player)).redacted().check_invariants(num_tiles);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_invariants() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board.clone(), start_ports.clone(), [((), 3)]);
+        let scoring = game.clone().with_win_condition(WinCondition::Scoring);
+        let all_tiles = RegularTile::<4>::all(board.tile_config());
+        let preplaced = game.clone().with_preplaced_tiles([(point![2, 2], all_tiles[0].clone()), (point![3, 4], all_tiles[7].clone())]);
+
+        let board = TorusBoard::new(4, 4, 2);
+        let start_ports = board.boundary_ports();
+        let torus = PathGame::<_, RegularTile<4>>::new(board, start_ports, [((), 3)]);
+
+        let board = SquareOctagonBoard::new(3, 3, 1);
+        let start_ports = board.boundary_ports();
+        let square_octagon = PathGame::<_, SquareOctagonTile>::new(board, start_ports,
+            [(SquareOctagonKind::Octagon, 3), (SquareOctagonKind::Square, 1)]);
+
+        for seed in 0..40 {
+            let num_players = seed as u32 % 4 + 1;
+            fuzz_game(&game, num_players, seed);
+            fuzz_game(&scoring, num_players, seed);
+            fuzz_game(&preplaced, num_players, seed);
+            fuzz_game(&torus, num_players.min(3), seed);
+            fuzz_game(&square_octagon, num_players.min(2), seed);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Tiles appeared or disappeared")]
+    fn test_check_invariants_lost_tile() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::<_, RegularTile<4>>::new(board, start_ports, [((), 3)]);
+        let mut state = GameState::new(&game, 2);
+        state.player_states[0].as_mut().unwrap().remove_tile(&(), 0);
+        state.check_invariants(game.all_tiles().len());
+    }
+}
@@ -0,0 +1,34 @@
+//! Summaries of finished games, so players can look back on how they've done.
+
+use std::time::Duration;
+
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Serialize};
+
+use crate::replay::ReplayId;
+
+/// How a finished game went
+#[derive(Clone, Debug, PartialEq, Eq, Getters, CopyGetters, Serialize, Deserialize)]
+pub struct GameSummary {
+    /// Usernames of everyone who played, in player order
+    #[getset(get = "pub")]
+    players: Vec<String>,
+    /// Usernames of the winners. Empty if nobody won.
+    #[getset(get = "pub")]
+    winners: Vec<String>,
+    /// Moves made, counting skipped turns and players leaving
+    #[getset(get_copy = "pub")]
+    num_moves: u32,
+    /// How long the game took from start to end
+    #[getset(get_copy = "pub")]
+    duration: Duration,
+    /// What the server keeps the replay under, for as long as it keeps it
+    #[getset(get_copy = "pub")]
+    replay_id: ReplayId,
+}
+
+impl GameSummary {
+    pub fn new(players: Vec<String>, winners: Vec<String>, num_moves: u32, duration: Duration, replay_id: ReplayId) -> Self {
+        Self { players, winners, num_moves, duration, replay_id }
+    }
+}
@@ -1,4 +1,5 @@
 pub mod board;
+pub mod config;
 pub mod math;
 pub mod tile;
 pub mod game;
@@ -6,21 +7,42 @@ pub mod player_state;
 pub mod board_state;
 pub mod game_state;
 pub mod message;
-
-use game::GameId;
+pub mod options;
+pub mod replay;
+pub mod archive;
+pub mod validation;
+pub mod history;
+pub mod daily;
+pub mod puzzle;
+pub mod notation;
+pub mod clock;
+pub mod sim;
+
+use game::{GameId, MatchScore};
 use game::BaseGame;
 use game_state::BaseGameState;
+use options::{Avatar, GameOptions, TokenColor};
 use getset::{Getters, CopyGetters};
 pub use nalgebra;
-use player_state::Looker;
 use rand::{distributions::{Uniform}, prelude::Distribution, thread_rng};
 use rand_pcg::Pcg64;
 use rand_core::SeedableRng;
 use serde::Deserialize;
 use serde::Serialize;
+use std::time::Duration;
 
+/// Address the server listens on.
+/// Clients connect to its port on the host that served them, unless told otherwise.
 pub const HOST_ADDRESS: &str = "127.0.0.1:7878";
 
+/// Version of the protocol, so server lists can tell which servers a client can talk to
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How often each side checks that the other is still there
+pub const HEARTBEAT_PERIOD: Duration = Duration::from_secs(10);
+/// How long a connection can go quiet before it's considered dead
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Constructs a PCG RNG from a seed
 pub fn pcg64_seeded(seed: u64) -> Pcg64 {
     Pcg64::seed_from_u64(seed)
@@ -126,20 +148,92 @@ pub struct GameInstance {
     /// stores username
     #[getset(get = "pub")]
     players: Vec<String>, 
+    /// stores usernames of everyone watching
+    #[getset(get = "pub")]
+    spectators: Vec<String>,
+    #[getset(get = "pub")]
+    options: GameOptions,
+    /// None if the game hasn't started
+    #[getset(get = "pub")]
+    match_score: Option<MatchScore>,
+    /// Every move written down so far this round, in move notation
+    #[getset(get = "pub")]
+    moves: Vec<String>,
+    /// The token color each player picked. None for players who didn't pick one.
+    #[getset(get = "pub")]
+    colors: Vec<Option<TokenColor>>,
+    /// Each player's avatar. None for players who didn't pick one.
+    #[getset(get = "pub")]
+    avatars: Vec<Option<Avatar>>,
 }
 
-impl GameInstance {
-    pub fn new(id: GameId, game: BaseGame, state: Option<BaseGameState>, players: Vec<String>) -> Self {
-        Self { id, game, state, players }
+/// How far along a game is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameStatus {
+    NotStarted,
+    Started,
+    Over,
+}
+
+/// A game as the lobby shows it. Much smaller than a `GameInstance`, since it leaves out the game's state,
+/// so it's what gets sent to everyone in the lobby whenever a game changes.
+/// The board is always a rectangle the size the options say, so the lobby can draw it from them.
+#[derive(Clone, Debug, PartialEq, Eq, Getters, CopyGetters, Serialize, Deserialize)]
+pub struct LobbyGame {
+    #[getset(get_copy = "pub")]
+    id: GameId,
+    /// stores username
+    #[getset(get = "pub")]
+    players: Vec<String>,
+    /// Each player's avatar. None for players who didn't pick one.
+    #[getset(get = "pub")]
+    avatars: Vec<Option<Avatar>>,
+    #[getset(get_copy = "pub")]
+    status: GameStatus,
+    #[getset(get = "pub")]
+    options: GameOptions,
+}
+
+impl LobbyGame {
+    pub fn new(id: GameId, players: Vec<String>, avatars: Vec<Option<Avatar>>, status: GameStatus, options: GameOptions) -> Self {
+        Self { id, players, avatars, status, options }
+    }
+}
+
+/// What a server says about itself, for server lists
+#[derive(Clone, Debug, PartialEq, Eq, Getters, CopyGetters, Serialize, Deserialize)]
+pub struct ServerInfo {
+    #[getset(get = "pub")]
+    name: String,
+    #[getset(get = "pub")]
+    region: String,
+    /// Number of connected peers that picked a username
+    #[getset(get_copy = "pub")]
+    num_players: u32,
+    #[getset(get = "pub")]
+    version: String,
+}
+
+impl ServerInfo {
+    pub fn new(name: String, region: String, num_players: u32) -> Self {
+        Self { name, region, num_players, version: VERSION.to_owned() }
     }
+}
 
-    /// Sets the looker of the game state. The game state must exist.
-    pub fn set_looker(&mut self, looker: Looker) {
-        self.state = Some(self.state.as_ref().unwrap().visible_state(looker));
+impl GameInstance {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(id: GameId, game: BaseGame, state: Option<BaseGameState>, players: Vec<String>, spectators: Vec<String>, options: GameOptions,
+        match_score: Option<MatchScore>, moves: Vec<String>, colors: Vec<Option<TokenColor>>, avatars: Vec<Option<Avatar>>) -> Self
+    {
+        Self { id, game, state, players, spectators, options, match_score, moves, colors, avatars }
     }
 
     /// Extracts all the fields for separate manipulation.
-    pub fn into_fields(self) -> (GameId, BaseGame, Option<BaseGameState>, Vec<String>) {
-        (self.id, self.game, self.state, self.players)
+    #[allow(clippy::type_complexity)]
+    pub fn into_fields(self) -> (
+        GameId, BaseGame, Option<BaseGameState>, Vec<String>, Vec<String>, GameOptions, Option<MatchScore>, Vec<String>,
+        Vec<Option<TokenColor>>, Vec<Option<Avatar>>,
+    ) {
+        (self.id, self.game, self.state, self.players, self.spectators, self.options, self.match_score, self.moves, self.colors, self.avatars)
     }
 }
\ No newline at end of file
@@ -1,60 +1,279 @@
 
 
 use serde::{Deserialize, Serialize};
+use strum_macros::{EnumDiscriminants, EnumIter};
 
-use crate::GameInstance;
-use crate::game::{GameId};
+use crate::{GameInstance, LobbyGame, ServerInfo};
+use crate::config::{MatchRounds, SpectatorDelay};
+use crate::clock::{Clocks, TimeControl};
+use crate::game::{GameId, MatchScore};
+use crate::options::{Avatar, BotDifficulty, Emote, GamePreset, RejectionFeed, TokenColor};
 use crate::game_state::BaseGameState;
 use crate::board::{BasePort, BaseTLoc};
-use crate::tile::{BaseKind, BaseGAct};
+use crate::tile::{BaseKind, BaseGAct, BaseTile};
+use crate::replay::{Replay, ReplayId};
+use crate::validation::UsernameProblem;
+use crate::history::GameSummary;
+use crate::daily::{Day, DailyResult};
 
 /// The request type used by the client to communicate to the server
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, EnumDiscriminants, Serialize, Deserialize)]
+#[strum_discriminants(name(RequestTag), derive(Hash, EnumIter))]
 pub enum Request {
     /// Set the username for a player
     SetUsername{ username: String },
     JoinLobby,
-    CreateGame,
+    /// Creates a game with the options a preset stands for,
+    /// optionally letting everyone see everyone's tiles, letting players exchange their hands,
+    /// letting them scout the draw pile, telling players about rejected moves, playing several rounds,
+    /// having everyone place their tiles at once, and holding back what happens from spectators
+    CreateGame{
+        preset: GamePreset, open_hands: bool, hand_exchange: bool, scout: bool, rejection_feed: RejectionFeed, rounds: MatchRounds,
+        simultaneous: bool, time_control: Option<TimeControl>, spectator_delay: Option<SpectatorDelay>,
+    },
     JoinGame{ id: GameId },
+    /// Leaves a game and goes back to the lobby. If the game has started, the player dies.
+    LeaveGame{ id: GameId },
+    /// Adds a bot played by the server to a game that hasn't started.
+    /// Only players of the game can add bots.
+    AddBot{ id: GameId, difficulty: BotDifficulty },
     /// Starts the game
     StartGame{ id: GameId },
     PlaceToken{ id: GameId, player: u32, port: BasePort },
     PlaceTile{ id: GameId, player: u32, kind: BaseKind, index: u32, action: BaseGAct, loc: BaseTLoc },
+    /// Spends the turn putting the player's hand back in the draw pile and drawing new tiles.
+    /// Only allowed once per game, in games with hand exchange on.
+    ExchangeHand{ id: GameId, player: u32 },
+    /// Looks at the top tile of each draw pile without drawing it. Doesn't end the turn.
+    /// Only allowed once per game, on the player's turn, in games with scouting on.
+    Scout{ id: GameId, player: u32 },
+    /// Asks for the game as it is now, for when the client lost track of it.
+    /// The server responds with `JoinedGame`, followed by `YourTurn` if it's the requester's turn.
+    Resync{ id: GameId },
     RemovePeer,
+    /// Checks that the server is still there. The server responds with `Pong`.
+    Ping,
+    /// Asks the server to describe itself. The server responds with `ServerInfo`.
+    GetServerInfo,
+    /// Asks for a replay the server keeps. The server responds with `Replay`.
+    GetReplay{ replay_id: ReplayId },
+    /// Asks for the results of the games the requester's username finished most recently.
+    /// The server responds with `History`.
+    GetHistory,
+    /// Secretly picks the tile to place this round, in games where everyone places at once.
+    /// Each player picks once a round. Once everyone has picked, the tiles get placed in turn order.
+    CommitTile{ id: GameId, player: u32, kind: BaseKind, index: u32, action: BaseGAct, loc: BaseTLoc },
+    /// Starts today's daily challenge, a game of the requester alone against bots, set up the same way for everyone.
+    /// Each username gets one go a day.
+    PlayDaily,
+    /// Asks for today's daily challenge leaderboard. The server responds with `Daily`.
+    GetDaily,
+    /// Asks everyone else still in the game to let the requester take back the last tile they placed.
+    /// Only the player who placed it can ask, before anyone makes another move, in games where players take turns.
+    RequestUndo{ id: GameId },
+    /// Answers the pending request to take back a tile placement. It gets undone once every other player accepts.
+    RespondUndo{ id: GameId, accept: bool },
+    /// Asks to pause a game, which freezes its turn timer and clocks and blocks moves until it's resumed.
+    /// It pauses right away if the host asks, and otherwise once most of the players still in the game have.
+    PauseGame{ id: GameId },
+    /// Asks to resume a paused game. It takes the same approval as pausing it.
+    ResumeGame{ id: GameId },
+    /// Asks for a rematch of a game that ended, with the same options and everyone moved one seat over.
+    /// The first player to ask creates it, and everyone who asks soon enough joins it.
+    Rematch{ id: GameId },
+    /// Changes the order the players of a game that hasn't started sit in.
+    /// `order` lists the players' current indexes in their new order.
+    ReorderPlayers{ id: GameId, order: Vec<u32> },
+    /// Picks the user's token color in a game that hasn't started
+    SetColor{ id: GameId, color: TokenColor },
+    /// Picks the picture shown next to the user's username, or takes it away if None
+    SetAvatar{ avatar: Option<Avatar> },
+    /// Sends a reaction to everyone in a game the user plays in.
+    /// Players who send too many too fast have the extra ones dropped.
+    Emote{ id: GameId, emote: Emote },
+}
+
+/// Why the server rejected a request.
+/// The server sends codes instead of text so the client can word them in the user's language.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectReason {
+    /// Someone else already has the username
+    UsernameTaken,
+    /// The username breaks the rules for usernames
+    InvalidUsername{ problem: UsernameProblem },
+    /// There's no game with the id
+    NoSuchGame,
+    /// Only players of the game can do that, and only for themselves
+    NotAPlayer,
+    /// The game has already started
+    AlreadyStarted,
+    /// The game hasn't started yet
+    NotStarted,
+    /// The player is out of the game
+    Eliminated,
+    /// The player already placed their token
+    TokenAlreadyPlaced,
+    /// Tiles can't be placed until everyone has placed their token
+    TokensNotPlaced,
+    /// It's player `turn_player`'s turn
+    NotYourTurn{ turn_player: u32 },
+    /// The game is over
+    GameOver,
+    /// The move breaks the rules
+    IllegalMove,
+    /// The server already hosts as many games as it allows
+    TooManyGames,
+    /// The username already played today's daily challenge
+    AlreadyPlayedDaily,
+    /// The game is paused
+    Paused,
+    /// It's too late to join the game's rematch
+    RematchClosed,
+    /// Another player already picked that token color
+    ColorTaken,
+}
+
+/// Why a player's turn got skipped. Sent as a code for the same reason as `RejectReason`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SkipReason {
+    /// The player took too long to come back after disconnecting
+    Disconnected,
+    /// The player ran out of time on their turn
+    OutOfTime,
+    /// The player has no tile they can place, but someone else does
+    NoLegalMove,
+    /// The tile the player picked in a game where everyone places at once couldn't go where they picked anymore
+    /// by the time it was their turn
+    MoveBlocked,
 }
 
 /// The response type used by the server to communicate to the client
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, EnumDiscriminants, Serialize, Deserialize)]
+#[strum_discriminants(name(ResponseTag), derive(Hash, EnumIter))]
 pub enum Response {
     /// Responds with the index of the player
     PlayerIndex{ id: GameId, index: u32 },
     /// List of players of the game have changed
-    ChangedPlayers{ id: GameId, names: Vec<String> },
+    ChangedPlayers{ id: GameId, names: Vec<String>, avatars: Vec<Option<Avatar>> },
+    /// List of spectators of the game have changed
+    ChangedSpectators{ id: GameId, names: Vec<String> },
     /// A game was created or edited in the lobby
-    ChangedGame{ game: GameInstance },
+    ChangedGame{ game: LobbyGame },
+    /// A game was removed from the lobby, because it ended or sat empty for too long
+    RemovedGame{ id: GameId },
     /// A game was joined
     JoinedGame{ game: GameInstance },
     /// The lobby was joined. The lobby has games.
-    JoinedLobby{ games: Vec<GameInstance> },
+    JoinedLobby{ games: Vec<LobbyGame> },
     /// Responds with the game's state
     StartedGame{ id: GameId, state: BaseGameState },
+    /// Player `player` left the game after it started, so their token is removed.
+    /// Their tiles got redistributed; `drawn` lists the tiles drawn in (player, index, tile) format,
+    /// redacted if the receiver shouldn't see them.
+    LeftGame{ id: GameId, player: u32, drawn: Vec<(u32, u32, BaseTile)> },
+    /// Player `player` disconnected from or reconnected to the game.
+    PlayerConnection{ id: GameId, player: u32, connected: bool },
+    /// Player `player`'s turn was skipped without them placing a tile.
+    SkippedTurn{ id: GameId, player: u32, reason: SkipReason },
     /// Player `player` has placed a token on port `port`.
     PlacedToken{ id: GameId, player: u32, port: BasePort },
     /// Invalid username
-    RejectedUsername,
+    RejectedUsername{ reason: RejectReason },
+    /// The game couldn't be created
+    RejectedGame{ reason: RejectReason },
     /// Invalid move, please undo
-    Rejected{ id: GameId },
+    Rejected{ id: GameId, reason: RejectReason },
+    /// Someone's move in the game got rejected. Only sent to the players the game's rejection feed covers.
+    /// `player` is None if whoever tried it isn't playing in the game.
+    RejectedAttempt{ id: GameId, player: Option<u32>, reason: RejectReason },
     /// Everyone placed their tokens; it's time to place some tiles
     AllPlacedTokens{ id: GameId },
     /// It's your turn, make a move
     YourTurn{ id: GameId },
-    /// Player `player` has placed a tile transformed by group action `action`
+    /// Player `player` has placed tile `tile` transformed by group action `action`
     /// from index `index` in their list of tiles of kind `kind` onto location `loc`.
-    PlacedTile{ id: GameId, player: u32, kind: BaseKind, index: u32, action: BaseGAct, loc: BaseTLoc },
+    /// `drawn` lists the tiles drawn afterward in (player, index, tile) format,
+    /// redacted if the receiver shouldn't see them.
+    PlacedTile{
+        id: GameId, player: u32, kind: BaseKind, index: u32, action: BaseGAct, loc: BaseTLoc,
+        tile: BaseTile, drawn: Vec<(u32, u32, BaseTile)>,
+    },
+    /// Player `player` put their hand back in the draw pile and drew new tiles.
+    /// `drawn` lists the tiles drawn in (player, index, tile) format, redacted if the receiver shouldn't see them.
+    ExchangedHand{ id: GameId, player: u32, drawn: Vec<(u32, u32, BaseTile)> },
+    /// The top tile of each draw pile that isn't empty, in answer to `Scout`.
+    /// Only the scouting player gets told, and only they find out they scouted.
+    Scouted{ id: GameId, tiles: Vec<BaseTile> },
+    /// The server is still there
+    Pong,
+    /// The server's name, region, player count, and version
+    ServerInfo{ info: ServerInfo },
+    /// The game is over, and its replay is kept under `replay_id`
+    ReplaySaved{ id: GameId, replay_id: ReplayId, replay: Replay },
+    /// A replay the server keeps. None if it isn't kept (anymore).
+    Replay{ replay_id: ReplayId, replay: Option<Replay> },
+    /// Results of the games the requester's username finished most recently, newest first
+    History{ games: Vec<GameSummary> },
+    /// A round ended without deciding the match, so the next one started on a fresh board.
+    /// Players who left the game stay out of it.
+    StartedRound{ id: GameId, state: BaseGameState, score: MatchScore },
+    /// Player `player` picked the tile to place this round, or got to sit it out, in a game where everyone places at once.
+    /// Which tile, and where, stays hidden until everyone has picked.
+    CommittedTile{ id: GameId, player: u32 },
+    /// The leaderboard of the daily challenge of `day`, best first
+    Daily{ day: Day, results: Vec<DailyResult> },
+    /// Player `player` asks to take back the last tile they placed, and everyone else has to accept
+    UndoRequested{ id: GameId, player: u32 },
+    /// Player `player` turned down taking back the last tile placement, so it stands
+    UndoDeclined{ id: GameId, player: u32 },
+    /// Everyone accepted, so the last tile placement got taken back, along with the skipped turns after it.
+    /// `game` is the game as it is now, with the turn back to whoever placed the tile.
+    UndidTurn{ game: GameInstance },
+    /// How much time each player has left, in games played with clocks.
+    /// Sent whenever a clock starts or stops, so the client can tick the running one down in between.
+    Clocks{ id: GameId, clocks: Clocks },
+    /// The game got paused, so nobody can move until it's resumed
+    GamePaused{ id: GameId },
+    /// The game got resumed, so the turn timer and clocks run again
+    GameResumed{ id: GameId },
+    /// The token colors players picked changed. None for players who didn't pick one.
+    ChangedColors{ id: GameId, colors: Vec<Option<TokenColor>> },
+    /// A player sent a reaction
+    Emoted{ id: GameId, player: u32, emote: Emote },
     ///// Players moved across tiles. Stores a port per player
     //CrossedTiles{ new_ports: Vec<G::Port> },
     ///// Players died. Stores players that died
     //Died{ dead: Vec<u32> },
     ///// Tiles have been dealt. Stores number of tiles dealt and new tiles per player.
     //DealtTiles{ num_tiles_dealt: u32,  }
-}
\ No newline at end of file
+}
+
+impl Request {
+    pub fn tag(&self) -> RequestTag {
+        self.into()
+    }
+}
+
+impl Response {
+    pub fn tag(&self) -> ResponseTag {
+        self.into()
+    }
+
+    /// The game whose players and spectators the response tells about something, if any.
+    /// Responses about the lobby, the server, and replays aren't about a game.
+    pub fn game_id(&self) -> Option<GameId> {
+        match self {
+            Self::PlayerIndex{ id, .. } | Self::ChangedPlayers{ id, .. } | Self::ChangedSpectators{ id, .. } | Self::StartedGame{ id, .. }
+            | Self::LeftGame{ id, .. } | Self::PlayerConnection{ id, .. } | Self::SkippedTurn{ id, .. } | Self::PlacedToken{ id, .. }
+            | Self::Rejected{ id, .. } | Self::RejectedAttempt{ id, .. } | Self::AllPlacedTokens{ id } | Self::YourTurn{ id }
+            | Self::PlacedTile{ id, .. } | Self::ExchangedHand{ id, .. } | Self::Scouted{ id, .. } | Self::ReplaySaved{ id, .. }
+            | Self::StartedRound{ id, .. } | Self::CommittedTile{ id, .. } | Self::UndoRequested{ id, .. } | Self::UndoDeclined{ id, .. }
+            | Self::Clocks{ id, .. } | Self::GamePaused{ id } | Self::GameResumed{ id } | Self::ChangedColors{ id, .. }
+            | Self::Emoted{ id, .. } => Some(*id),
+            Self::JoinedGame{ game } | Self::UndidTurn{ game } => Some(game.id()),
+            Self::ChangedGame{ .. } | Self::RemovedGame{ .. } | Self::JoinedLobby{ .. } | Self::RejectedUsername{ .. }
+            | Self::RejectedGame{ .. } | Self::Pong | Self::ServerInfo{ .. } | Self::Replay{ .. } | Self::History{ .. }
+            | Self::Daily{ .. } => None,
+        }
+    }
+}
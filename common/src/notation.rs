@@ -0,0 +1,103 @@
+//! Move notation: a short line of text for each move, for move lists.
+//!
+//! - `P3: T17 r2 @ (4,2)`: player 3 placed tile 17 of the game's tile set at (4, 2),
+//!   turned clockwise 2 times from how it was drawn. An `f` after the rotation means it got flipped first.
+//! - `P1: start (0,2) (0,1)`: player 1 put their token on the port of location (0, 2) on side (0, 1)
+//! - `P2: exchange`, `P2: skip`, `P2: leave`: player 2 exchanged their hand, had their turn skipped, or left
+//!
+//! Players are numbered from 1, in turn order. Scouting doesn't get written down, since nobody else finds out about it.
+
+use crate::{board::{BasePort, BaseTLoc}, game::BaseGame, replay::{Replay, ReplayMove}, tile::{BaseGAct, BaseTile}};
+
+fn port(port: &BasePort) -> String {
+    match port {
+        BasePort::Pt2uVec2u((loc, side)) => format!("({},{}) ({},{})", loc.x, loc.y, side.x, side.y),
+    }
+}
+
+fn loc(loc: &BaseTLoc) -> String {
+    match loc {
+        BaseTLoc::Pt2u(loc) => format!("({},{})", loc.x, loc.y),
+    }
+}
+
+fn action(action: &BaseGAct) -> String {
+    match action {
+        BaseGAct::Cycle(action) => format!("r{}", action.rotation()),
+        BaseGAct::Dihedral(action) => format!("r{}{}", action.rotation(), if action.reflected() { "f" } else { "" }),
+    }
+}
+
+/// Notation for a move. `tile` is the tile placed, for tile placements.
+/// None for scouting, which doesn't get written down.
+pub fn notate(game: &BaseGame, mv: &ReplayMove, tile: Option<&BaseTile>) -> Option<String> {
+    let (player, what) = match mv {
+        ReplayMove::PlaceToken{ player, port: p } => (player, format!("start {}", port(p))),
+        ReplayMove::PlaceTile{ player, action: a, loc: l, .. } => {
+            let number = tile.and_then(|tile| game.tile_number(tile)).map_or("?".to_owned(), |number| number.to_string());
+            (player, format!("T{} {} @ {}", number, action(a), loc(l)))
+        }
+        ReplayMove::ExchangeHand{ player } => (player, "exchange".to_owned()),
+        ReplayMove::Scout{ .. } => return None,
+        ReplayMove::SkipTurn{ player, .. } => (player, "skip".to_owned()),
+        ReplayMove::Leave{ player } => (player, "leave".to_owned()),
+    };
+    Some(format!("P{}: {}", player + 1, what))
+}
+
+/// Notation for every move in a replay that gets written down, paired with the index of the move in the replay
+pub fn notate_replay(replay: &Replay) -> Vec<(usize, String)> {
+    let game = replay.game();
+    let mut state = replay.initial_state().clone();
+    replay.moves().iter().enumerate().filter_map(|(i, mv)| {
+        let tile = match mv {
+            ReplayMove::PlaceTile{ player, kind, index, .. } => state.hand_tile(*player, kind, *index),
+            _ => None,
+        };
+        let notation = notate(game, mv, tile.as_ref());
+        mv.apply(game, &mut state);
+        notation.map(|notation| (i, notation))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::{Board, Port, RectangleBoard}, game::{Game, PathGame}, message::SkipReason, tile::Tile, WrapBase};
+
+    use super::*;
+
+    #[test]
+    fn test_notate_replay() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::new(board, start_ports.clone(), [((), 3)]).wrap_base();
+        let mut replay = Replay::new(game.clone(), vec!["alice".to_owned(), "bob".to_owned()], game.new_state_seeded(2, 1));
+        replay.push(ReplayMove::PlaceToken{ player: 0, port: start_ports[0].wrap_base() });
+        replay.push(ReplayMove::PlaceToken{ player: 1, port: start_ports[10].wrap_base() });
+
+        let state = replay.final_state();
+        let (kind, index, action, loc) = state.legal_moves(&game, 0).into_iter().next().unwrap();
+        let tile = state.hand_tile(0, &kind, index).unwrap();
+        replay.push(ReplayMove::PlaceTile{ player: 0, kind, index, action: action.clone(), loc: loc.clone() });
+        replay.push(ReplayMove::Scout{ player: 1 });
+        replay.push(ReplayMove::SkipTurn{ player: 1, reason: SkipReason::NoLegalMove });
+
+        let notated = notate_replay(&replay);
+        assert_eq!(notated.iter().map(|(i, _)| *i).collect::<Vec<_>>(), [0, 1, 2, 4]);
+        assert!(notated[0].1.starts_with("P1: start ("));
+        assert_eq!(notated[2].1, format!("P1: T{} {} @ {}", game.tile_number(&tile).unwrap(), super::action(&action), super::loc(&loc)));
+        assert_eq!(notated[3].1, "P2: skip");
+    }
+
+    #[test]
+    fn test_tile_number() {
+        let board = RectangleBoard::new(6, 6, 2);
+        let game = PathGame::new(board, vec![], [((), 3)]);
+        let tiles = game.all_tiles();
+        let game = game.wrap_base();
+        for (i, tile) in tiles.iter().enumerate() {
+            assert_eq!(game.tile_number(&tile.rotate(1).wrap_base()), Some(i as u32 + 1));
+            assert_eq!(game.tile_number(&tile.redacted().wrap_base()), None);
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use std::time::Duration;
+
+use getset::{CopyGetters, Getters, Setters};
+use itertools::iproduct;
+use nalgebra::point;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::{WrapBase, clock::TimeControl, board::{BaseTLoc, Board, RectangleBoard, SquareOctagonBoard, TorusBoard}, config::{BoardSize, ConfigError, MatchRounds, SpectatorDelay, TurnTimeLimit}, game::{BaseGame, PathGame}, game_state::WinCondition, tile::{BaseTile, SquareOctagonKind}};
+
+/// Ports on each edge of each square of the board
+pub const PORTS_PER_EDGE: u32 = 2;
+/// Tiles each player holds
+pub const TILES_PER_PLAYER: u32 = 3;
+/// Ports on each edge of each tile on a board of octagons. Any more and there'd be far too many octagon tiles to deal.
+pub const OCTAGON_PORTS_PER_EDGE: u32 = 1;
+/// Square tiles each player holds on a board of octagons, on top of `TILES_PER_PLAYER` octagon tiles
+pub const SQUARES_PER_PLAYER: u32 = 1;
+
+/// Everything that can be configured about a game when creating it
+#[derive(Clone, Debug, PartialEq, Eq, CopyGetters, Getters, Setters, Serialize, Deserialize)]
+pub struct GameOptions {
+    board_width: BoardSize,
+    board_height: BoardSize,
+    /// Whether opposite edges of the board are joined, so paths that leave one edge come back in the opposite one
+    #[getset(get_copy = "pub", set = "pub")]
+    wrap_around: bool,
+    /// Whether the middle of the board is blocked off, so no tiles can go there and paths that run into it end.
+    /// Boards that wrap around, and boards too small to have a middle, don't get blocked off.
+    #[getset(get_copy = "pub", set = "pub")]
+    hollow: bool,
+    /// Whether the board is made of octagons, with squares in the gaps between them, instead of squares.
+    /// Boards of octagons don't wrap around nor get their middle blocked off.
+    #[getset(get_copy = "pub", set = "pub")]
+    octagons: bool,
+    /// Tiles already on the board when the game starts, for scenarios and puzzles.
+    /// Ones that don't fit the board get left out.
+    #[getset(get = "pub", set = "pub")]
+    preplaced_tiles: Vec<(BaseTLoc, BaseTile)>,
+    /// Whether the last players standing win, or the players whose tokens went the farthest
+    #[getset(get_copy = "pub", set = "pub")]
+    win_condition: WinCondition,
+    /// Most rounds the game takes, each on a fresh board. Whoever wins the most rounds wins the game.
+    rounds: MatchRounds,
+    /// How long each turn can take. None if there's no limit.
+    turn_time_limit: Option<TurnTimeLimit>,
+    /// Whether a player who runs out of time gets a move made for them.
+    /// Otherwise their turn gets skipped.
+    #[getset(get_copy = "pub")]
+    auto_move: bool,
+    /// How much time each player's clock has for the whole round, on top of any turn time limit.
+    /// None if there are no clocks.
+    #[getset(get_copy = "pub", set = "pub")]
+    time_control: Option<TimeControl>,
+    /// Whether everyone gets to see everyone's tiles.
+    /// Otherwise, tiles players shouldn't see get redacted before they're sent.
+    #[getset(get_copy = "pub", set = "pub")]
+    open_hands: bool,
+    /// Whether each player can spend a turn once per game to trade their hand for new tiles
+    #[getset(get_copy = "pub", set = "pub")]
+    hand_exchange: bool,
+    /// Whether each player can privately look at the top of the draw pile once per game
+    #[getset(get_copy = "pub", set = "pub")]
+    scout: bool,
+    /// Who hears about moves that got rejected
+    #[getset(get_copy = "pub", set = "pub")]
+    rejection_feed: RejectionFeed,
+    /// Whether everyone secretly picks a tile to place each round instead of taking turns.
+    /// The tiles get placed in turn order once everyone has picked.
+    #[getset(get_copy = "pub", set = "pub")]
+    simultaneous: bool,
+    /// Seed to shuffle the draw piles with, so games played by these options get the same tiles.
+    /// None to shuffle them differently every game.
+    #[getset(get_copy = "pub", set = "pub")]
+    seed: Option<u64>,
+    /// How long spectators wait to hear about what happens, so they can't tip players off. None if they hear right away.
+    spectator_delay: Option<SpectatorDelay>,
+}
+
+impl GameOptions {
+    /// Checks every option, and that the options make sense together.
+    /// The board is squares, doesn't wrap around nor have its middle blocked off, and starts empty, the last players standing win
+    /// a single round, players take turns without clocks, tiles aren't open, hands can't be exchanged nor the draw pile scouted,
+    /// rejected moves stay private, the draw piles get shuffled differently every game, and spectators hear about moves right away
+    /// by default.
+    pub fn new(board_width: u32, board_height: u32, turn_time_limit: Option<Duration>, auto_move: bool)
+        -> Result<Self, ConfigError>
+    {
+        let turn_time_limit = turn_time_limit.map(TurnTimeLimit::new).transpose()?;
+        if auto_move && turn_time_limit.is_none() {
+            return Err(ConfigError::Requires{ name: "auto-move", requires: "a turn time limit" });
+        }
+        Ok(Self {
+            board_width: BoardSize::new(board_width)?,
+            board_height: BoardSize::new(board_height)?,
+            wrap_around: false,
+            hollow: false,
+            octagons: false,
+            preplaced_tiles: vec![],
+            win_condition: WinCondition::Survival,
+            rounds: MatchRounds::DEFAULT,
+            turn_time_limit,
+            auto_move,
+            time_control: None,
+            open_hands: false,
+            hand_exchange: false,
+            scout: false,
+            rejection_feed: RejectionFeed::Off,
+            simultaneous: false,
+            seed: None,
+            spectator_delay: None,
+        })
+    }
+
+    pub fn board_width(&self) -> u32 {
+        self.board_width.get()
+    }
+
+    pub fn board_height(&self) -> u32 {
+        self.board_height.get()
+    }
+
+    pub fn turn_time_limit(&self) -> Option<Duration> {
+        self.turn_time_limit.map(TurnTimeLimit::get)
+    }
+
+    pub fn rounds(&self) -> u32 {
+        self.rounds.get()
+    }
+
+    pub fn set_rounds(&mut self, rounds: MatchRounds) {
+        self.rounds = rounds;
+    }
+
+    /// Gives turns a time limit, replacing any they had
+    pub fn set_turn_time_limit(&mut self, limit: TurnTimeLimit) {
+        self.turn_time_limit = Some(limit);
+    }
+
+    pub fn spectator_delay(&self) -> Option<Duration> {
+        self.spectator_delay.map(SpectatorDelay::get)
+    }
+
+    pub fn set_spectator_delay(&mut self, delay: Option<SpectatorDelay>) {
+        self.spectator_delay = delay;
+    }
+
+    /// A game played by these options, with tokens starting on the edge of the board
+    pub fn game(&self) -> BaseGame {
+        let (width, height) = (self.board_width(), self.board_height());
+        if self.octagons {
+            let board = SquareOctagonBoard::new(width, height, OCTAGON_PORTS_PER_EDGE);
+            let start_ports = board.boundary_ports();
+            let preplaced_tiles = self.preplaced_tiles.iter().filter_map(|(loc, tile)| match (loc, tile) {
+                (BaseTLoc::Pt2u(loc), BaseTile::SquareOctagon(tile)) => Some((*loc, tile.clone())),
+                _ => None,
+            });
+            let tiles_per_player = [(SquareOctagonKind::Octagon, TILES_PER_PLAYER), (SquareOctagonKind::Square, SQUARES_PER_PLAYER)];
+            return PathGame::new(board, start_ports, tiles_per_player)
+                .with_preplaced_tiles(preplaced_tiles)
+                .with_win_condition(self.win_condition)
+                .wrap_base();
+        }
+
+        let preplaced_tiles = self.preplaced_tiles.iter().filter_map(|(loc, tile)| match (loc, tile) {
+            (BaseTLoc::Pt2u(loc), BaseTile::RegularTile4(tile)) => Some((*loc, tile.clone())),
+            _ => None,
+        });
+        if self.wrap_around {
+            let board = TorusBoard::new(width, height, PORTS_PER_EDGE);
+            let start_ports = board.boundary_ports();
+            PathGame::new(board, start_ports, [((), TILES_PER_PLAYER)])
+                .with_preplaced_tiles(preplaced_tiles)
+                .with_win_condition(self.win_condition)
+                .wrap_base()
+        } else {
+            let mut board = RectangleBoard::new(width, height, PORTS_PER_EDGE);
+            if self.hollow && width >= 4 && height >= 4 {
+                // The middle 2 tiles of an even side, or the middle tile of an odd one
+                let middle = |size: u32| (size - 1) / 2..=size / 2;
+                board = board.with_blocked(iproduct!(middle(width), middle(height)).map(|(x, y)| point![x, y]));
+            }
+            let start_ports = board.boundary_ports();
+            PathGame::new(board, start_ports, [((), TILES_PER_PLAYER)])
+                .with_preplaced_tiles(preplaced_tiles)
+                .with_win_condition(self.win_condition)
+                .wrap_base()
+        }
+    }
+}
+
+impl Default for GameOptions {
+    fn default() -> Self {
+        GamePreset::Standard.options()
+    }
+}
+
+/// Bundles of options to pick from when creating a game
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Display, EnumIter, EnumString, Serialize, Deserialize)]
+pub enum GamePreset {
+    /// No time pressure
+    Casual,
+    Standard,
+    /// Short turns, and moves get made for players who run out of time
+    Blitz,
+    /// A big board
+    Marathon,
+    /// The board game's setup: a 6x6 board with 2 ports on each edge of each square,
+    /// tokens starting on the edge of the board, no time limit, and none of the house rules.
+    /// Unlike the board game, players may place a tile that kills them even when another placement wouldn't.
+    Classic,
+    /// A board whose edges wrap around, where players only die by running into each other or going around in a loop
+    Torus,
+    /// A board with a hole in the middle, which kills players who run into it
+    Hollow,
+    /// A board of octagons with squares in the gaps between them, which take their own kind of tile
+    Octagons,
+    /// Players score a point for each tile their token moves through, and the highest score wins
+    Scoring,
+}
+
+impl GamePreset {
+    /// The full set of options this preset stands for
+    pub fn options(self) -> GameOptions {
+        let (board_size, turn_time_limit, auto_move) = match self {
+            Self::Casual => (6, None, false),
+            Self::Standard => (6, Some(Duration::from_secs(60)), false),
+            Self::Blitz => (6, Some(Duration::from_secs(15)), true),
+            Self::Marathon => (10, None, false),
+            Self::Classic | Self::Torus | Self::Hollow | Self::Scoring => (6, None, false),
+            Self::Octagons => (5, None, false),
+        };
+        let mut options = GameOptions::new(board_size, board_size, turn_time_limit, auto_move).expect("Presets should be valid");
+        options.set_wrap_around(self == Self::Torus);
+        options.set_hollow(self == Self::Hollow);
+        options.set_octagons(self == Self::Octagons);
+        if self == Self::Scoring {
+            options.set_win_condition(WinCondition::Scoring);
+        }
+        options
+    }
+
+    /// Whether games with this preset can turn on house rules like open hands, hand exchange, and scouting
+    pub fn allows_house_rules(self) -> bool {
+        self != Self::Classic
+    }
+}
+
+/// Who in a game hears about moves that got rejected, for spotting stuck players and cheating attempts.
+/// Whoever made the move always hears about it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Display, EnumIter, EnumString, Serialize, Deserialize)]
+pub enum RejectionFeed {
+    /// Nobody else
+    #[default]
+    Off,
+    /// The first player to join
+    Host,
+    /// Every player
+    Everyone,
+}
+
+/// How well a server-side bot plays
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Display, EnumIter, EnumString, Serialize, Deserialize)]
+pub enum BotDifficulty {
+    /// Plays the first move it finds, even if it's deadly
+    Easy,
+    /// Survives if it can
+    Medium,
+    /// Survives if it can, and takes the longest path it can
+    Hard,
+}
+
+/// A color a player can pick for their token before the game starts. No two players in a game can pick the same one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Display, EnumIter, EnumString, Serialize, Deserialize)]
+pub enum TokenColor {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Teal,
+    Blue,
+    Purple,
+    Pink,
+}
+
+/// A picture users can pick to show next to their username
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Display, EnumIter, EnumString, Serialize, Deserialize)]
+pub enum Avatar {
+    Cat,
+    Dog,
+    Fox,
+    Owl,
+    Frog,
+    Octopus,
+    Robot,
+    Dragon,
+}
+
+/// A reaction players can send to everyone in their game
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Display, EnumIter, EnumString, Serialize, Deserialize)]
+pub enum Emote {
+    ThumbsUp,
+    Laugh,
+    Wow,
+    Sad,
+    Angry,
+    GoodGame,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::{board::{PortsPerEdgeTileConfig, TLoc}, game::Game, tile::{RegularTile, Tile}};
+
+    #[test]
+    fn test_presets() {
+        let blitz = GamePreset::Blitz.options();
+        assert_eq!(blitz.turn_time_limit(), Some(Duration::from_secs(15)));
+        assert!(blitz.auto_move());
+        assert!(GamePreset::Marathon.options().board_width() > GamePreset::Standard.options().board_width());
+        let classic = GamePreset::Classic.options();
+        assert_eq!((classic.board_width(), classic.board_height()), (6, 6));
+        assert_eq!(classic.turn_time_limit(), None);
+        assert!(!GamePreset::Classic.allows_house_rules());
+        assert!(GamePreset::Casual.allows_house_rules());
+        assert!(GamePreset::Torus.options().wrap_around());
+        assert!(!GamePreset::Standard.options().wrap_around());
+        assert!(matches!(GamePreset::Torus.options().game(), BaseGame::Torus(_)));
+        assert!(matches!(GamePreset::Standard.options().game(), BaseGame::Normal(_)));
+        let mut scenario = GamePreset::Casual.options();
+        let tile = RegularTile::<4>::all(PortsPerEdgeTileConfig(PORTS_PER_EDGE)).remove(0).wrap_base();
+        scenario.set_preplaced_tiles(vec![(point![1, 1].wrap_base(), tile)]);
+        assert_eq!(scenario.game().new_state(2).board_state().tiles_vec().len(), 1);
+        match GamePreset::Hollow.options().game() {
+            BaseGame::Normal(game) => assert_eq!(game.board().blocked().len(), 4),
+            _ => panic!("A hollow board shouldn't wrap around"),
+        }
+        assert!(matches!(GamePreset::Octagons.options().game(), BaseGame::SquareOctagon(_)));
+        // Tiles for another kind of board get left out
+        scenario.set_octagons(true);
+        assert!(scenario.game().new_state(2).board_state().tiles_vec().is_empty());
+        assert_eq!(GamePreset::Scoring.options().game().new_state(2).win_condition(), WinCondition::Scoring);
+        assert_eq!(GamePreset::Standard.options().game().new_state(2).win_condition(), WinCondition::Survival);
+
+        for preset in GamePreset::iter() {
+            assert_eq!(preset.to_string().parse::<GamePreset>(), Ok(preset));
+            // Panics if the preset is invalid
+            preset.options();
+        }
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        assert!(GameOptions::new(6, 6, Some(Duration::from_secs(15)), true).is_ok());
+        assert!(matches!(GameOptions::new(6, 6, None, true), Err(ConfigError::Requires{ .. })));
+        assert!(matches!(GameOptions::new(6, 100, None, false), Err(ConfigError::OutOfRange{ .. })));
+        assert!(matches!(GameOptions::new(6, 6, Some(Duration::ZERO), false), Err(ConfigError::OutOfRange{ .. })));
+    }
+}
@@ -17,6 +17,7 @@ macro_rules! for_each_player_state {
         }
         __mac! {
             ($crate::player_state::BasePlayerState)::RegularTile4: $crate::player_state::PlayerState<$crate::tile::RegularTile<4>>,
+            ($crate::player_state::BasePlayerState)::SquareOctagon: $crate::player_state::PlayerState<$crate::tile::SquareOctagonTile>,
         }
     };
 
@@ -47,7 +48,7 @@ for_each_player_state! {
         }
     }
 
-    $($crate::impl_wrap_base!(BasePlayerState::$x($t)))*;
+    $($crate::impl_wrap_base!(BasePlayerState::$x($t));)*
 }
 
 /// Someone that looks at the game
@@ -113,6 +114,35 @@ impl<T: Tile> PlayerState<T> {
         self.tiles.values_mut().flat_map(|v| std::mem::take(v)).collect_vec()
     }
 
+    /// The tile in the player's hand by kind and index. None if there's no such tile.
+    pub fn tile(&self, kind: &T::Kind, index: u32) -> Option<&T> {
+        self.tiles.get(kind).and_then(|tiles| tiles.get(index as usize))
+    }
+
+    /// Replaces a tile in the player's hand by index, keeping its kind.
+    /// Does nothing if the index doesn't exist.
+    pub fn set_tile(&mut self, index: u32, tile: T) {
+        if let Some(slot) = self.tiles.get_mut(tile.kind()).and_then(|tiles| tiles.get_mut(index as usize)) {
+            *slot = tile;
+        }
+    }
+
+    /// Makes every tile visible
+    pub fn show_all(&mut self) {
+        for tile in self.tiles.values_mut().flatten() {
+            tile.set_visible(true);
+        }
+    }
+
+    /// Strips hidden tiles down to their kinds
+    pub fn redact_hidden(&mut self) {
+        for tile in self.tiles.values_mut().flatten() {
+            if !tile.visible() {
+                *tile = tile.redacted();
+            }
+        }
+    }
+
     /// Returns the state of `player` visible to `looker`
     pub fn visible_state(&self, player: u32, looker: Looker) -> PlayerState<T> {
         let mut result = self.clone();
@@ -0,0 +1,266 @@
+//! Puzzles: a game stopped partway through, and a goal for one of its players to reach from there.
+//! The other players move on their own, the same way every time, so a puzzle plays out the same wherever
+//! its solution gets checked. A puzzle is a single JSON document, like a game archive:
+//!
+//! - `format`: always `"tsurust-puzzle"`, so other JSON files don't get mistaken for puzzles
+//! - `version`: version of the format. Readers reject versions newer than the ones they know.
+//! - `name`: what the puzzle is called
+//! - `options`: the options the game is played with
+//! - `usernames`: the players' usernames, in turn order
+//! - `player`: the player whose moves solve the puzzle
+//! - `goal`: what that player has to do
+//! - `game` and `state`: the board and tiles in the deck, and the state of the game with nothing hidden,
+//!   encoded the same way as in replay links
+//!
+//! Every token has to be on the board already, and the game can't be over.
+
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{DeserializeOwned, Error}};
+
+use crate::{game::BaseGame, game_state::{BaseGameState, BaseTileMove}, options::GameOptions, replay::{decode_compressed, encode_compressed}};
+
+/// What the `format` field of every puzzle says
+pub const PUZZLE_FORMAT: &str = "tsurust-puzzle";
+/// Newest version of the puzzle format
+pub const PUZZLE_VERSION: u32 = 1;
+
+/// What the player solving a puzzle has to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PuzzleGoal {
+    /// Place `turns` tiles without dying. Getting to the end of the game alive works too.
+    Survive{ turns: u32 },
+    /// Get player `player` killed without dying
+    Eliminate{ player: u32 },
+}
+
+/// How an attempt at a puzzle is going
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PuzzleStatus {
+    Unsolved,
+    Solved,
+    Failed,
+}
+
+/// A puzzle
+#[derive(Clone, Debug, Getters, CopyGetters, Serialize, Deserialize)]
+pub struct Puzzle {
+    format: String,
+    version: u32,
+    #[getset(get = "pub")]
+    name: String,
+    #[getset(get = "pub")]
+    options: GameOptions,
+    #[getset(get = "pub")]
+    usernames: Vec<String>,
+    /// The player whose moves solve the puzzle
+    #[getset(get_copy = "pub")]
+    player: u32,
+    #[getset(get_copy = "pub")]
+    goal: PuzzleGoal,
+    #[getset(get = "pub")]
+    #[serde(serialize_with = "encode", deserialize_with = "decode")]
+    game: BaseGame,
+    /// The state the puzzle starts from, with nothing hidden
+    #[getset(get = "pub")]
+    #[serde(serialize_with = "encode", deserialize_with = "decode")]
+    state: BaseGameState,
+}
+
+fn encode<T: Serialize, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&encode_compressed(value))
+}
+
+fn decode<'de, T: DeserializeOwned, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    decode_compressed(&encoded).ok_or_else(|| D::Error::custom("invalid encoding"))
+}
+
+/// How the other players in a puzzle move: the first legal move that doesn't kill them, or the first legal move
+/// if they all do. None if they have no legal move.
+pub fn opponent_move(game: &BaseGame, state: &BaseGameState, player: u32) -> Option<BaseTileMove> {
+    let moves = state.legal_moves(game, player);
+    let survives = |(kind, index, action, loc): &&BaseTileMove| !state.clone()
+        .take_turn_placing_tile(game, kind, *index, action, loc)
+        .dead_players().contains(&player);
+    moves.iter().find(survives).or_else(|| moves.first()).cloned()
+}
+
+impl Puzzle {
+    /// Makes a puzzle out of a game in progress, checking that it can be solved from there
+    pub fn new(name: String, options: GameOptions, usernames: Vec<String>, game: BaseGame, state: BaseGameState,
+        player: u32, goal: PuzzleGoal) -> Result<Self, String>
+    {
+        let puzzle = Self { format: PUZZLE_FORMAT.to_owned(), version: PUZZLE_VERSION, name, options, usernames, player, goal, game, state };
+        puzzle.check()?;
+        Ok(puzzle)
+    }
+
+    /// Checks that the puzzle makes sense and isn't over before it starts
+    fn check(&self) -> Result<(), String> {
+        let num_players = self.state.num_players();
+        if self.usernames.len() != num_players as usize {
+            return Err(format!("Puzzle has {} usernames for {} players", self.usernames.len(), num_players));
+        }
+        if self.player >= num_players {
+            return Err(format!("Puzzle is solved by player {}, but only has {} players", self.player, num_players));
+        }
+        if let PuzzleGoal::Eliminate{ player } = self.goal {
+            if player >= num_players || player == self.player {
+                return Err(format!("Puzzle can't be solved by player {} eliminating player {}", self.player, player));
+            }
+        }
+        if !self.state.all_players_placed() {
+            return Err("Puzzle starts before every token is on the board".to_owned());
+        }
+        if self.status(&self.state, 0) != PuzzleStatus::Unsolved {
+            return Err("Puzzle is over before it starts".to_owned());
+        }
+        Ok(())
+    }
+
+    /// How an attempt at the puzzle is going, given the state of the game
+    /// and the number of tiles the player solving it placed so far
+    pub fn status(&self, state: &BaseGameState, turns_taken: u32) -> PuzzleStatus {
+        if state.player_state(self.player).is_none() {
+            return PuzzleStatus::Failed;
+        }
+        match self.goal {
+            PuzzleGoal::Survive{ turns } if turns_taken >= turns || state.game_over() => PuzzleStatus::Solved,
+            PuzzleGoal::Eliminate{ player } if state.player_state(player).is_none() => PuzzleStatus::Solved,
+            PuzzleGoal::Eliminate{ .. } if state.game_over() => PuzzleStatus::Failed,
+            _ => PuzzleStatus::Unsolved,
+        }
+    }
+
+    /// Plays out a solution, with the other players moving the way they always do in puzzles
+    /// and stuck players getting skipped like the server does.
+    /// Stops as soon as the puzzle is solved or failed, or with `Unsolved` once the moves run out.
+    /// An illegal move fails the puzzle.
+    pub fn check_solution(&self, moves: &[BaseTileMove]) -> PuzzleStatus {
+        let mut state = self.state.clone();
+        let mut moves = moves.iter();
+        let mut turns_taken = 0;
+        loop {
+            let status = self.status(&state, turns_taken);
+            if status != PuzzleStatus::Unsolved {
+                return status;
+            }
+
+            let player = state.turn_player();
+            if state.turn_player_stuck(&self.game) {
+                state.skip_turn();
+                continue;
+            }
+            let (kind, index, action, loc) = if player == self.player {
+                match moves.next() {
+                    Some(mv) if state.check_place_tile(&self.game, player, &mv.0, mv.1, &mv.2, &mv.3).is_ok() => mv.clone(),
+                    Some(_) => return PuzzleStatus::Failed,
+                    None => return PuzzleStatus::Unsolved,
+                }
+            } else {
+                opponent_move(&self.game, &state, player).expect("Players that aren't stuck have a legal move")
+            };
+            state.take_turn_placing_tile(&self.game, &kind, index, &action, &loc);
+            if player == self.player {
+                turns_taken += 1;
+            }
+        }
+    }
+
+    /// Writes the puzzle as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Serialization went wrong")
+    }
+
+    /// Reads a puzzle written by `to_json`, checking that it's one this version can read and that it makes sense
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let puzzle: Self = serde_json::from_str(json).map_err(|err| format!("Not a puzzle: {}", err))?;
+        if puzzle.format != PUZZLE_FORMAT {
+            return Err(format!("Not a puzzle: format is {:?}", puzzle.format));
+        }
+        if puzzle.version > PUZZLE_VERSION {
+            return Err(format!("Puzzle version {} is newer than the newest known version, {}", puzzle.version, PUZZLE_VERSION));
+        }
+        puzzle.check()?;
+        Ok(puzzle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::{Board, Port, RectangleBoard}, game::PathGame, options::GamePreset, WrapBase};
+
+    use super::*;
+
+    /// A two player game with both tokens placed, where the first player has to survive a turn
+    fn test_puzzle(goal: PuzzleGoal) -> Puzzle {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::new(board, start_ports.clone(), [((), 3)]).wrap_base();
+        let mut state = game.new_state_seeded(2, 1);
+        state.place_player(0, &start_ports[0].wrap_base());
+        state.place_player(1, &start_ports[10].wrap_base());
+        Puzzle::new("Test".to_owned(), GamePreset::Casual.options(), vec!["alice".to_owned(), "bob".to_owned()], game, state, 0, goal)
+            .unwrap()
+    }
+
+    /// The first player's moves, split by whether they survive them
+    fn first_moves(puzzle: &Puzzle) -> (Vec<BaseTileMove>, Vec<BaseTileMove>) {
+        let state = puzzle.state();
+        state.legal_moves(puzzle.game(), 0).into_iter().partition(|(kind, index, action, loc)|
+            !state.clone().take_turn_placing_tile(puzzle.game(), kind, *index, action, loc).dead_players().contains(&0))
+    }
+
+    #[test]
+    fn test_puzzle_survive() {
+        let puzzle = test_puzzle(PuzzleGoal::Survive{ turns: 1 });
+        let (safe, deadly) = first_moves(&puzzle);
+        assert_eq!(puzzle.check_solution(&[]), PuzzleStatus::Unsolved);
+        assert_eq!(puzzle.check_solution(&safe[..1]), PuzzleStatus::Solved);
+        if let Some(mv) = deadly.first() {
+            assert_eq!(puzzle.check_solution(std::slice::from_ref(mv)), PuzzleStatus::Failed);
+        }
+
+        // Moves that aren't legal fail it too
+        let (kind, _, action, loc) = safe[0].clone();
+        assert_eq!(puzzle.check_solution(&[(kind, 5, action, loc)]), PuzzleStatus::Failed);
+    }
+
+    #[test]
+    fn test_puzzle_eliminate() {
+        let puzzle = test_puzzle(PuzzleGoal::Eliminate{ player: 1 });
+        let mut state = puzzle.state().clone();
+        assert_eq!(puzzle.status(&state, 3), PuzzleStatus::Unsolved);
+        state.remove_player(puzzle.game(), 1);
+        assert_eq!(puzzle.status(&state, 0), PuzzleStatus::Solved);
+
+        let mut state = puzzle.state().clone();
+        state.remove_player(puzzle.game(), 0);
+        assert_eq!(puzzle.status(&state, 0), PuzzleStatus::Failed);
+    }
+
+    #[test]
+    fn test_puzzle_json() {
+        let puzzle = test_puzzle(PuzzleGoal::Survive{ turns: 2 });
+        let read = Puzzle::from_json(&puzzle.to_json()).unwrap();
+        assert_eq!(read.name(), "Test");
+        assert_eq!(read.options(), &GamePreset::Casual.options());
+        assert_eq!(read.usernames(), puzzle.usernames());
+        assert_eq!(read.goal(), PuzzleGoal::Survive{ turns: 2 });
+        assert_eq!(
+            bincode::serialize(read.state()).unwrap(),
+            bincode::serialize(puzzle.state()).unwrap(),
+        );
+
+        assert!(Puzzle::from_json("{}").is_err());
+        let mut newer = puzzle.clone();
+        newer.version = PUZZLE_VERSION + 1;
+        assert!(Puzzle::from_json(&newer.to_json()).is_err());
+        let mut nonsense = puzzle.clone();
+        nonsense.goal = PuzzleGoal::Eliminate{ player: 0 };
+        assert!(Puzzle::from_json(&nonsense.to_json()).is_err());
+        let mut over = puzzle;
+        over.state.remove_player(&over.game, 1);
+        assert!(Puzzle::from_json(&over.to_json()).is_err());
+    }
+}
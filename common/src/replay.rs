@@ -0,0 +1,249 @@
+//! Replays of finished games, and sharing them in links.
+//! A replay is the starting state of a game, with nothing hidden, and every move made after it.
+//! Small replays fit in a link's fragment themselves, so sharing them doesn't need the server to keep anything.
+//! Bigger ones get shared by the id the server keeps them under.
+
+use getset::Getters;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{board::{BasePort, BaseTLoc}, game::BaseGame, game_state::BaseGameState, message::SkipReason, tile::{BaseGAct, BaseKind, BaseTile}};
+
+/// Longest link fragment that holds a replay itself.
+/// Replays that don't fit get linked by id, so links stay short enough for browsers and chat apps.
+pub const MAX_INLINE_FRAGMENT_LEN: usize = 2000;
+/// Most bytes a replay in a link can decompress to, so a crafted link can't eat all the memory
+const MAX_DECOMPRESSED_LEN: usize = 1 << 20;
+/// Start of a fragment that holds a replay
+const INLINE_PREFIX: &str = "replay=";
+/// Start of a fragment that holds the id of a replay on the server
+const HOSTED_PREFIX: &str = "replay-id=";
+
+/// Id of a replay kept by the server
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ReplayId(pub u32);
+
+/// A move in a replay
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ReplayMove {
+    PlaceToken{ player: u32, port: BasePort },
+    PlaceTile{ player: u32, kind: BaseKind, index: u32, action: BaseGAct, loc: BaseTLoc },
+    /// Player `player` exchanged their hand
+    ExchangeHand{ player: u32 },
+    /// Player `player` looked at the top of the draw piles
+    Scout{ player: u32 },
+    /// Player `player` had their turn skipped
+    SkipTurn{ player: u32, reason: SkipReason },
+    /// Player `player` left the game
+    Leave{ player: u32 },
+}
+
+impl ReplayMove {
+    /// Makes the move on a state with nothing hidden.
+    /// Returns the tiles drawn because of it in (player, index, tile) format.
+    pub fn apply(&self, game: &BaseGame, state: &mut BaseGameState) -> Vec<(u32, u32, BaseTile)> {
+        match self {
+            Self::PlaceToken{ player, port } => {
+                state.place_player(*player, port);
+                vec![]
+            }
+            Self::PlaceTile{ kind, index, action, loc, .. } =>
+                state.take_turn_placing_tile(game, kind, *index, action, loc).drawn_tiles(),
+            Self::ExchangeHand{ .. } => state.take_turn_exchanging_hand(),
+            Self::Scout{ player } => {
+                state.scout(*player);
+                vec![]
+            }
+            Self::SkipTurn{ .. } => {
+                state.skip_turn();
+                vec![]
+            }
+            Self::Leave{ player } => state.remove_player(game, *player),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Getters, Serialize, Deserialize)]
+pub struct Replay {
+    #[getset(get = "pub")]
+    game: BaseGame,
+    #[getset(get = "pub")]
+    usernames: Vec<String>,
+    /// The state when the game started, with nothing hidden
+    #[getset(get = "pub")]
+    initial_state: BaseGameState,
+    #[getset(get = "pub")]
+    moves: Vec<ReplayMove>,
+}
+
+impl Replay {
+    /// Starts a replay of a game that just started
+    pub fn new(game: BaseGame, usernames: Vec<String>, initial_state: BaseGameState) -> Self {
+        Self { game, usernames, initial_state, moves: vec![] }
+    }
+
+    /// The seed the draw piles got shuffled with, so the game can be played again with the same tiles.
+    /// None if the starting state was saved with it hidden.
+    pub fn seed(&self) -> Option<u64> {
+        self.initial_state.seed()
+    }
+
+    /// Adds a move to the end of the replay
+    pub fn push(&mut self, mv: ReplayMove) {
+        self.moves.push(mv);
+    }
+
+    /// The player who placed the last tile in the replay. None if nobody placed one.
+    pub fn last_tile_placer(&self) -> Option<u32> {
+        self.moves.iter().rev().find_map(|mv| match mv {
+            ReplayMove::PlaceTile{ player, .. } => Some(*player),
+            _ => None,
+        })
+    }
+
+    /// Takes back the last tile placement, along with every move after it, for undoing it.
+    /// Returns whether there was a tile placement to take back.
+    pub fn undo_tile_placement(&mut self) -> bool {
+        if let Some(pos) = self.moves.iter().rposition(|mv| matches!(mv, ReplayMove::PlaceTile{ .. })) {
+            self.moves.truncate(pos);
+            true
+        } else { false }
+    }
+
+    /// The state after every move in the replay
+    pub fn final_state(&self) -> BaseGameState {
+        let mut state = self.initial_state.clone();
+        for mv in &self.moves {
+            mv.apply(&self.game, &mut state);
+        }
+        state
+    }
+
+    /// Encodes the replay as compressed, URL-safe base64
+    pub fn encode(&self) -> String {
+        encode_compressed(self)
+    }
+
+    /// Decodes a replay encoded by `encode`. None if it isn't one.
+    pub fn decode(encoded: &str) -> Option<Self> {
+        decode_compressed(encoded)
+    }
+}
+
+/// Encodes a value as compressed, URL-safe base64
+pub(crate) fn encode_compressed<T: Serialize>(value: &T) -> String {
+    let bytes = bincode::serialize(value).expect("Serialization went wrong");
+    base64::encode_config(miniz_oxide::deflate::compress_to_vec(&bytes, 9), base64::URL_SAFE_NO_PAD)
+}
+
+/// Decodes a value encoded by `encode_compressed`. None if it isn't one.
+pub(crate) fn decode_compressed<T: DeserializeOwned>(encoded: &str) -> Option<T> {
+    let compressed = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).ok()?;
+    let bytes = miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, MAX_DECOMPRESSED_LEN).ok()?;
+    bincode::deserialize(&bytes).ok()
+}
+
+/// What a link to a replay holds
+// Links get parsed once per page load, so their size doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum ReplayLink {
+    /// The replay itself
+    Inline(Replay),
+    /// The id the server keeps the replay under
+    Hosted(ReplayId),
+}
+
+impl ReplayLink {
+    /// The link fragment for a replay kept by the server under `id`.
+    /// Holds the replay itself if it fits, and its id otherwise.
+    pub fn fragment(replay: &Replay, id: ReplayId) -> String {
+        let inline = format!("{}{}", INLINE_PREFIX, replay.encode());
+        if inline.len() <= MAX_INLINE_FRAGMENT_LEN {
+            inline
+        } else {
+            Self::hosted_fragment(id)
+        }
+    }
+
+    /// The link fragment for a replay kept by the server under `id`, for when only the id is known
+    pub fn hosted_fragment(id: ReplayId) -> String {
+        format!("{}{}", HOSTED_PREFIX, id.0)
+    }
+
+    /// Parses a link fragment, with or without the `#` in front. None if it isn't a replay link.
+    pub fn parse(fragment: &str) -> Option<Self> {
+        let fragment = fragment.strip_prefix('#').unwrap_or(fragment);
+        if let Some(id) = fragment.strip_prefix(HOSTED_PREFIX) {
+            id.parse().ok().map(|id| Self::Hosted(ReplayId(id)))
+        } else {
+            fragment.strip_prefix(INLINE_PREFIX).and_then(Replay::decode).map(Self::Inline)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::{board::{Board, Port, RectangleBoard}, game::PathGame, pcg64_seeded, WrapBase};
+
+    use super::*;
+
+    /// A two player game played until someone dies
+    fn test_replay() -> Replay {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        let game = PathGame::new(board, start_ports.clone(), [((), 3)]).wrap_base();
+        let mut state = game.new_state(2);
+        let mut replay = Replay::new(game.clone(), vec!["alice".to_owned(), "bob".to_owned()], state.clone());
+
+        for (player, port) in [(0, &start_ports[0]), (1, &start_ports[10])] {
+            let mv = ReplayMove::PlaceToken{ player, port: port.wrap_base() };
+            mv.apply(&game, &mut state);
+            replay.push(mv);
+        }
+        while !state.game_over() {
+            let player = state.turn_player();
+            let mv = match state.legal_moves(&game, player).into_iter().next() {
+                Some((kind, index, action, loc)) => ReplayMove::PlaceTile{ player, kind, index, action, loc },
+                None => ReplayMove::Leave{ player },
+            };
+            mv.apply(&game, &mut state);
+            replay.push(mv);
+        }
+        replay
+    }
+
+    #[test]
+    fn test_replay_encoding() {
+        let replay = test_replay();
+        let decoded = Replay::decode(&replay.encode()).unwrap();
+        assert_eq!(decoded.usernames(), replay.usernames());
+        assert_eq!(decoded.moves().len(), replay.moves().len());
+        assert!(decoded.final_state().game_over());
+        assert_eq!(
+            bincode::serialize(&decoded.final_state()).unwrap(),
+            bincode::serialize(&replay.final_state()).unwrap()
+        );
+        assert!(Replay::decode("not a replay").is_none());
+    }
+
+    #[test]
+    fn test_replay_link() {
+        let replay = test_replay();
+        let fragment = ReplayLink::fragment(&replay, ReplayId(3));
+        assert!(fragment.starts_with(INLINE_PREFIX), "Replay of a short game should fit in a link");
+        assert!(matches!(ReplayLink::parse(&format!("#{}", fragment)), Some(ReplayLink::Inline(_))));
+
+        // Too long to fit even compressed, so it gets linked by id
+        let mut long_replay = replay;
+        let mut rng = pcg64_seeded(0);
+        long_replay.usernames.push((0..4000).map(|_| rng.gen_range('a'..='z')).collect());
+        let fragment = ReplayLink::fragment(&long_replay, ReplayId(3));
+        assert!(matches!(ReplayLink::parse(&fragment), Some(ReplayLink::Hosted(ReplayId(3)))));
+        assert_eq!(ReplayLink::hosted_fragment(ReplayId(3)), fragment);
+
+        assert!(ReplayLink::parse("#somewhere").is_none());
+        assert!(ReplayLink::parse("#replay-id=x").is_none());
+    }
+}
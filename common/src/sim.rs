@@ -0,0 +1,299 @@
+//! Simulated games: whole games played out with random legal moves from a seed,
+//! so the rules can be checked against lots of games that play out the same way every time.
+
+use getset::{CopyGetters, Getters};
+use itertools::Itertools;
+use rand::prelude::SliceRandom;
+use rand_pcg::Pcg64;
+
+use crate::{game::Game, game_state::{GameState, Kill, TurnEvents}, pcg64_seeded};
+
+/// Totals from a simulated game
+#[derive(Clone, Debug, Default, PartialEq, Eq, Getters, CopyGetters)]
+pub struct SimStats {
+    /// Tiles placed
+    #[getset(get_copy = "pub")]
+    turns: u32,
+    /// Turns skipped because the turn player had no legal move
+    #[getset(get_copy = "pub")]
+    skips: u32,
+    /// Tiles drawn after the initial deal
+    #[getset(get_copy = "pub")]
+    tiles_drawn: u32,
+    /// Everyone who died, in the order they died
+    #[getset(get = "pub")]
+    kills: Vec<Kill>,
+    /// Tiles each player's token moved through, indexed by player
+    #[getset(get = "pub")]
+    distances: Vec<u32>,
+    #[getset(get = "pub")]
+    winners: Vec<u32>,
+    /// Whether the game ended, instead of getting stuck with nobody able to move
+    #[getset(get_copy = "pub")]
+    finished: bool,
+}
+
+impl SimStats {
+    fn record<G: Game>(&mut self, events: &TurnEvents<G>) {
+        self.turns += 1;
+        self.tiles_drawn += events.drawn_tiles().len() as u32;
+        self.kills.extend(events.kills());
+        for (player, distance) in self.distances.iter_mut().enumerate() {
+            *distance += events.distance_moved(player as u32);
+        }
+    }
+}
+
+/// What a step of a simulated game did
+#[derive(Clone, Debug)]
+pub enum SimStep<G: Game> {
+    /// The turn player placed a tile
+    Placed(TurnEvents<G>),
+    /// The turn player had no legal move while someone else did
+    Skipped,
+}
+
+/// A game being played out with random legal moves
+#[derive(Getters)]
+pub struct Simulation<'a, G: Game> {
+    game: &'a G,
+    #[getset(get = "pub")]
+    state: GameState<G>,
+    rng: Pcg64,
+    #[getset(get = "pub")]
+    stats: SimStats,
+}
+
+impl<'a, G: Game> Simulation<'a, G> {
+    /// Starts a game with `num_players` players, each placed on a random start port.
+    /// The same seed always plays out the same way.
+    /// Panics if there aren't enough start ports to go around.
+    pub fn new(game: &'a G, num_players: u32, seed: u64) -> Self {
+        let mut state = GameState::new_seeded(game, num_players, seed);
+        let mut rng = pcg64_seeded(seed);
+        for _ in 0..num_players {
+            let ports = game.start_ports().into_iter().filter(|port| state.can_place_player(game, port)).collect_vec();
+            let port = ports.choose(&mut rng).expect("Not enough start ports for everyone");
+            state.take_turn_placing_player(game, port);
+        }
+
+        let stats = SimStats { distances: vec![0; num_players as usize], ..SimStats::default() };
+        Self { game, state, rng, stats }
+    }
+
+    /// Has the turn player place a random legal tile, or skips their turn if they're stuck.
+    /// None once the game is over or nobody can move.
+    pub fn step(&mut self) -> Option<SimStep<G>> {
+        if self.state.game_over() {
+            return None;
+        }
+
+        let player = self.state.turn_player();
+        let step = if let Some((kind, index, action, loc)) = self.state.legal_moves(self.game, player).choose(&mut self.rng) {
+            let events = self.state.take_turn_placing_tile(self.game, kind, *index, action, loc);
+            self.stats.record(&events);
+            SimStep::Placed(events)
+        } else if self.state.turn_player_stuck(self.game) {
+            self.state.skip_turn();
+            self.stats.skips += 1;
+            SimStep::Skipped
+        } else {
+            return None;
+        };
+
+        self.stats.winners = self.state.winners().clone();
+        self.stats.finished = self.state.game_over();
+        Some(step)
+    }
+
+    /// Plays the rest of the game out
+    pub fn run(mut self) -> SimStats {
+        while self.step().is_some() {}
+        self.stats
+    }
+}
+
+/// Plays a whole game with `num_players` players out with random legal moves from `seed`
+pub fn simulate<G: Game>(game: &G, num_players: u32, seed: u64) -> SimStats {
+    Simulation::new(game, num_players, seed).run()
+}
+
+/// Number of ways the next `depth` turns can go from `state`, counting each sequence of moves separately,
+/// like a chess engine's perft. A turn that has to be skipped goes one way, and a game that ends sooner counts once.
+pub fn perft<G: Game>(game: &G, state: &GameState<G>, depth: u32) -> u64 {
+    if depth == 0 || state.game_over() {
+        return 1;
+    }
+
+    let moves = state.legal_moves(game, state.turn_player());
+    if moves.is_empty() {
+        if !state.turn_player_stuck(game) {
+            return 1;
+        }
+        let mut next = state.clone();
+        next.skip_turn();
+        return perft(game, &next, depth - 1);
+    }
+
+    moves.iter().map(|(kind, index, action, loc)| {
+        let mut next = state.clone();
+        next.take_turn_placing_tile(game, kind, *index, action, loc);
+        perft(game, &next, depth - 1)
+    }).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::{Board, RectangleBoard, SquareOctagonBoard, TorusBoard}, game::PathGame, game_state::{TurnEvent, WinCondition},
+        tile::{RegularTile, SquareOctagonKind, SquareOctagonTile}};
+
+    use super::*;
+
+    fn rectangle_game() -> PathGame<RectangleBoard, RegularTile<4>> {
+        let board = RectangleBoard::new(6, 6, 2);
+        let start_ports = board.boundary_ports();
+        PathGame::new(board, start_ports, [((), 3)])
+    }
+
+    fn torus_game() -> PathGame<TorusBoard, RegularTile<4>> {
+        let board = TorusBoard::new(4, 4, 2);
+        let start_ports = board.boundary_ports();
+        PathGame::new(board, start_ports, [((), 3)])
+    }
+
+    fn square_octagon_game() -> PathGame<SquareOctagonBoard, SquareOctagonTile> {
+        let board = SquareOctagonBoard::new(3, 3, 1);
+        let start_ports = board.boundary_ports();
+        PathGame::new(board, start_ports, [(SquareOctagonKind::Octagon, 3), (SquareOctagonKind::Square, 1)])
+    }
+
+    /// Runs `check` on the state after each step of games with a range of seeds and player counts
+    fn check_steps<G: Game>(game: &G, max_players: u32, mut check: impl FnMut(&GameState<G>, &SimStep<G>)) {
+        for seed in 0..30 {
+            let mut sim = Simulation::new(game, seed as u32 % max_players + 1, seed);
+            while let Some(step) = sim.step() {
+                check(sim.state(), &step);
+            }
+        }
+    }
+
+    #[test]
+    fn test_simulate_deterministic() {
+        let game = rectangle_game();
+        for seed in 0..10 {
+            assert_eq!(simulate(&game, 4, seed), simulate(&game, 4, seed));
+        }
+        assert!((1..10).any(|seed| simulate(&game, 4, seed) != simulate(&game, 4, 0)));
+    }
+
+    #[test]
+    fn test_simulate_advance_players() {
+        fn check<G: Game>(game: &G, max_players: u32) {
+            check_steps(game, max_players, |state, step| {
+                if let SimStep::Placed(events) = step {
+                    for event in events.events() {
+                        match event {
+                            // Tokens that die come off boards where they could get run into
+                            TurnEvent::PlayerMoved{ player, path } => if let Some(port) = state.board_state().player_port(*player) {
+                                assert_eq!(path.last(), Some(port));
+                            },
+                            TurnEvent::PlayerDied{ kill } => assert!(state.player_state(kill.victim()).is_none()),
+                            _ => {}
+                        }
+                    }
+                }
+
+                // Tokens only stop in front of an empty space they could move into
+                for player in (0..state.num_players()).filter(|player| state.player_state(*player).is_some()) {
+                    let port = state.board_state().player_port(player).expect("Everyone should be placed");
+                    assert!(game.board().port_locs(port).iter()
+                        .any(|loc| state.board_state().tile_at(loc).is_none() && !game.board().is_blocked(loc)),
+                        "Player {} stopped at {:?} with nowhere to go", player, port);
+                }
+            });
+        }
+
+        check(&rectangle_game(), 4);
+        check(&torus_game(), 3);
+        check(&square_octagon_game(), 2);
+    }
+
+    #[test]
+    fn test_simulate_redistribution() {
+        fn check<G: Game>(game: &G, max_players: u32) {
+            check_steps(game, max_players, |state, _| {
+                let living = (0..state.num_players()).filter_map(|player| state.player_state(player)).collect_vec();
+                for (kind, num_left) in state.num_tiles_left_by_kind() {
+                    let counts = living.iter().map(|hand| hand.num_tiles_by_kind(kind)).collect_vec();
+                    let (min, max) = (counts.iter().min().copied().unwrap_or(0), counts.iter().max().copied().unwrap_or(0));
+                    assert!(max - min <= 1, "Hands of kind {:?} are uneven: {:?}", kind, counts);
+                    // Hands only run short once the pile runs out
+                    if num_left > 0 {
+                        assert!(counts.iter().all(|count| *count == game.num_tiles_per_player(kind)),
+                            "Hands of kind {:?} are short with {} left: {:?}", kind, num_left, counts);
+                    }
+                }
+            });
+        }
+
+        check(&rectangle_game(), 4);
+        check(&torus_game(), 3);
+        check(&square_octagon_game(), 2);
+    }
+
+    #[test]
+    fn test_simulate_end_of_game() {
+        fn check<G: Game>(game: &G, max_players: u32) {
+            for seed in 0..30 {
+                let num_players = seed as u32 % max_players + 1;
+                let mut sim = Simulation::new(game, num_players, seed);
+                while sim.step().is_some() {}
+                let (state, stats) = (sim.state(), sim.stats());
+
+                assert!(stats.finished(), "Game with seed {} got stuck", seed);
+                assert_eq!(stats.winners(), state.winners());
+                assert_eq!(stats.turns() as usize, state.board_state().tiles_vec().len());
+                assert_eq!(stats.distances(), state.scores());
+                let living = (0..num_players).filter(|player| state.player_state(*player).is_some()).collect_vec();
+                assert_eq!(stats.kills().len() + living.len(), num_players as usize);
+
+                // The game only ends once someone's the last one left or nobody can move
+                if living.len() > 1 {
+                    assert!(living.iter().all(|player| state.legal_moves(game, *player).is_empty()),
+                        "Game with seed {} ended while {:?} could still move", seed, living);
+                }
+            }
+        }
+
+        check(&rectangle_game(), 4);
+        check(&rectangle_game().with_win_condition(WinCondition::Scoring), 4);
+        check(&torus_game(), 3);
+        check(&square_octagon_game(), 2);
+    }
+
+    #[test]
+    fn test_perft() {
+        // Counts from the rules as they are, so changes to what can be placed where or whose turn comes next show up here.
+        // Update them only when the rules are meant to change.
+        let game = rectangle_game();
+        for (seed, steps, expected) in [(0, 0, [12, 144, 1728]), (1, 0, [12, 144, 1695]), (0, 6, [12, 45, 441])] {
+            let mut sim = Simulation::new(&game, 3, seed);
+            for _ in 0..steps {
+                sim.step();
+            }
+            assert_eq!((1..=3).map(|depth| perft(&game, sim.state(), depth)).collect_vec(), expected,
+                "Seed {} after {} steps", seed, steps);
+        }
+
+        let game = square_octagon_game();
+        for (seed, expected) in [(0, [24, 438, 1225]), (1, [24, 254, 1783])] {
+            let sim = Simulation::new(&game, 2, seed);
+            assert_eq!((1..=3).map(|depth| perft(&game, sim.state(), depth)).collect_vec(), expected, "Seed {}", seed);
+        }
+
+        // Nothing moves once the game is over
+        let mut sim = Simulation::new(&game, 2, 0);
+        while sim.step().is_some() {}
+        assert_eq!(perft(&game, sim.state(), 3), 1);
+    }
+}
@@ -17,9 +17,21 @@ impl Kind for () {
     impl_wrap_functions!(() BaseKind, Unit);
 }
 
+/// The kinds of tiles on a board of octagons with squares in the gaps between them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SquareOctagonKind {
+    Octagon,
+    Square,
+}
+
+impl Kind for SquareOctagonKind {
+    impl_wrap_functions!(() BaseKind, SquareOctagon);
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum BaseKind {
-    Unit(())
+    Unit(()),
+    SquareOctagon(SquareOctagonKind),
 }
 
 /// A group action on a tile.
@@ -119,6 +131,7 @@ macro_rules! for_each_tile {
         }
         __mac! {
             ($crate::tile::BaseTile)::RegularTile4: $crate::tile::RegularTile<4>,
+            ($crate::tile::BaseTile)::SquareOctagon: $crate::tile::SquareOctagonTile,
         }
     };
 
@@ -157,13 +170,39 @@ for_each_tile! {
             match self { $($($p)*::$x(s) => s.rotation_action(num_times).wrap_base()),* }
         }
 
+        /// Generate a group action that flips the tile over.
+        /// None if tiles of this type can't be flipped.
+        pub fn reflection_action(&self) -> Option<BaseGAct> {
+            match self { $($($p)*::$x(s) => s.reflection_action().map(|action| action.wrap_base())),* }
+        }
+
         /// Apply a group action to this tile.
         pub fn apply_action(&self, action: &BaseGAct) -> Self {
             match self { $($($p)*::$x(s) => s.apply_action(GAct::unwrap_base_ref(action)).wrap_base()),* }
         }
+
+        /// The canonical orientation of this tile, so tiles that only differ by how they're turned compare equal
+        pub fn canonical(&self) -> Self {
+            match self { $($($p)*::$x(s) => s.canonical().wrap_base()),* }
+        }
+
+        /// A hidden copy of this tile with everything but its kind stripped
+        pub fn redacted(&self) -> Self {
+            match self { $($($p)*::$x(s) => s.redacted().wrap_base()),* }
+        }
+
+        /// Whether the tile is visible to whoever has the reference
+        pub fn visible(&self) -> bool {
+            match self { $($($p)*::$x(s) => s.visible()),* }
+        }
+
+        /// This tile with its visibility set
+        pub fn with_visible(self, visible: bool) -> Self {
+            match self { $($($p)*::$x(s) => s.with_visible(visible).wrap_base()),* }
+        }
     }
 
-    $($crate::impl_wrap_base!(BaseTile::$x($t)))*;
+    $($crate::impl_wrap_base!(BaseTile::$x($t));)*
 }
 
 /// A tile in the path game, parameterized by kind
@@ -218,9 +257,18 @@ pub trait Tile: Clone + Debug + Eq + Ord + Hash + Serialize + for<'a> Deserializ
     /// Generate a rotation group action that rotates `num_times` times clockwise.
     fn rotation_action(&self, num_times: i32) -> Self::GAct;
 
+    /// Generate a group action that flips the tile over, across an axis through port 0.
+    /// None if tiles of this type can't be flipped, which is the default.
+    fn reflection_action(&self) -> Option<Self::GAct> {
+        None
+    }
+
     /// Apply a group action to this tile.
     fn apply_action(&self, action: &Self::GAct) -> Self;
 
+    /// Every group action that can be applied to this tile, with no two having the same effect on every tile
+    fn all_actions(&self) -> Vec<Self::GAct>;
+
     /// The output port of some input port on the tile
     fn output(&self, input: u32) -> u32;
 
@@ -232,6 +280,10 @@ pub trait Tile: Clone + Debug + Eq + Ord + Hash + Serialize + for<'a> Deserializ
 
     /// Set the visibility of this tile
     fn set_visible(&mut self, visible: bool);
+
+    /// A hidden copy of this tile with everything but its kind stripped,
+    /// for sending to someone who shouldn't know what it is
+    fn redacted(&self) -> Self;
 }
 
 /// A regular-polygon-shaped tile with `EDGES` edges.
@@ -326,8 +378,16 @@ impl<const EDGES: u32> Tile for RegularTile<EDGES> {
         self.rotate(action.rotation)
     }
 
+    fn all_actions(&self) -> Vec<Self::GAct> {
+        (0..EDGES as i32).map(|num_times| self.rotation_action(num_times)).collect()
+    }
+
     fn rotate(&self, num_times: i32) -> Self {
         let mut result = self.clone();
+        // Redacted tiles have nothing to rotate
+        if self.connections.is_empty() {
+            return result;
+        }
         let offset = (num_times * self.ports_per_edge() as i32).rem_euclid(self.num_ports() as i32);
         for i in 0..self.num_ports() as i32 {
             result.connections[i as usize] =
@@ -352,6 +412,121 @@ impl<const EDGES: u32> Tile for RegularTile<EDGES> {
     fn set_visible(&mut self, visible: bool) {
         self.visible = visible;
     }
+
+    fn redacted(&self) -> Self {
+        Self { connections: vec![], visible: false }
+    }
+}
+
+/// A tile on a board of octagons with squares in the gaps between them, which is either an octagon or a square.
+/// The two take different kinds of spots on the board.
+#[derive(Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum SquareOctagonTile {
+    Octagon(RegularTile<8>),
+    Square(RegularTile<4>),
+}
+
+impl Tile for SquareOctagonTile {
+    type Kind = SquareOctagonKind;
+    type GAct = CycleGAct;
+    type TileConfig = PortsPerEdgeTileConfig;
+
+    fn all_including_rotations(config: Self::TileConfig) -> Vec<Self> where Self: Sized {
+        RegularTile::<8>::all_including_rotations(config).into_iter().map(Self::Octagon)
+            .chain(RegularTile::<4>::all_including_rotations(config).into_iter().map(Self::Square))
+            .collect_vec()
+    }
+
+    fn all_rotations(&self) -> Vec<Self> where Self: Sized {
+        match self {
+            Self::Octagon(tile) => tile.all_rotations().into_iter().map(Self::Octagon).collect_vec(),
+            Self::Square(tile) => tile.all_rotations().into_iter().map(Self::Square).collect_vec(),
+        }
+    }
+
+    fn kind(&self) -> &Self::Kind {
+        match self {
+            Self::Octagon(_) => &SquareOctagonKind::Octagon,
+            Self::Square(_) => &SquareOctagonKind::Square,
+        }
+    }
+
+    fn num_ports(&self) -> u32 {
+        match self {
+            Self::Octagon(tile) => tile.num_ports(),
+            Self::Square(tile) => tile.num_ports(),
+        }
+    }
+
+    fn rotate(&self, num_times: i32) -> Self {
+        match self {
+            Self::Octagon(tile) => Self::Octagon(tile.rotate(num_times)),
+            Self::Square(tile) => Self::Square(tile.rotate(num_times)),
+        }
+    }
+
+    fn identity_action(&self) -> Self::GAct {
+        match self {
+            Self::Octagon(tile) => tile.identity_action(),
+            Self::Square(tile) => tile.identity_action(),
+        }
+    }
+
+    fn rotation_action(&self, num_times: i32) -> Self::GAct {
+        match self {
+            Self::Octagon(tile) => tile.rotation_action(num_times),
+            Self::Square(tile) => tile.rotation_action(num_times),
+        }
+    }
+
+    fn apply_action(&self, action: &Self::GAct) -> Self {
+        match self {
+            Self::Octagon(tile) => Self::Octagon(tile.apply_action(action)),
+            Self::Square(tile) => Self::Square(tile.apply_action(action)),
+        }
+    }
+
+    fn all_actions(&self) -> Vec<Self::GAct> {
+        match self {
+            Self::Octagon(tile) => tile.all_actions(),
+            Self::Square(tile) => tile.all_actions(),
+        }
+    }
+
+    fn output(&self, input: u32) -> u32 {
+        match self {
+            Self::Octagon(tile) => tile.output(input),
+            Self::Square(tile) => tile.output(input),
+        }
+    }
+
+    fn visible(&self) -> bool {
+        match self {
+            Self::Octagon(tile) => tile.visible(),
+            Self::Square(tile) => tile.visible(),
+        }
+    }
+
+    fn with_visible(self, visible: bool) -> Self {
+        match self {
+            Self::Octagon(tile) => Self::Octagon(tile.with_visible(visible)),
+            Self::Square(tile) => Self::Square(tile.with_visible(visible)),
+        }
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        match self {
+            Self::Octagon(tile) => tile.set_visible(visible),
+            Self::Square(tile) => tile.set_visible(visible),
+        }
+    }
+
+    fn redacted(&self) -> Self {
+        match self {
+            Self::Octagon(tile) => Self::Octagon(tile.redacted()),
+            Self::Square(tile) => Self::Square(tile.redacted()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -373,6 +548,21 @@ mod tests {
         assert_eq!(tile.rotate(-1), expected);
     }
 
+    #[test]
+    fn test_dihedral_reflection() {
+        let identity = DihedralGAct { rotation: 0, reflected: false, size: 4 };
+        let reflection = DihedralGAct { rotation: 0, reflected: true, size: 4 };
+        let rotation = DihedralGAct { rotation: 1, reflected: false, size: 4 };
+        assert_eq!(reflection.compose(&reflection), identity);
+        // Reflecting turns later rotations the other way
+        assert_eq!(rotation.compose(&reflection), DihedralGAct { rotation: 3, reflected: true, size: 4 });
+
+        // Square tiles only rotate
+        let tile = RegularTile::<4>::new(vec![2, 3, 0, 1, 7, 6, 5, 4]);
+        assert_eq!(tile.reflection_action(), None);
+        assert_eq!(tile.wrap_base().reflection_action(), None);
+    }
+
     #[test]
     fn test_triangle_tile_all() {
         let all = RegularTile::<3>::all(PortsPerEdgeTileConfig(2));
@@ -390,4 +580,15 @@ mod tests {
         let all = RegularTile::<4>::all(PortsPerEdgeTileConfig(2));
         assert_eq!(all.len(), 35);
     }
+
+    #[test]
+    fn test_square_octagon_tile_all() {
+        let all = SquareOctagonTile::all(PortsPerEdgeTileConfig(1));
+        let octagons = all.iter().filter(|tile| tile.kind() == &SquareOctagonKind::Octagon).count();
+        assert_eq!((octagons, all.len() - octagons), (18, 2));
+
+        // Redacting keeps the kind
+        assert!(all.iter().all(|tile| tile.redacted().kind() == tile.kind()));
+        assert_eq!(all[0].rotate(1).rotate(-1), all[0]);
+    }
 }
\ No newline at end of file
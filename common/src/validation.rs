@@ -0,0 +1,60 @@
+//! Rules for usernames, shared by the client and the server so a name the client lets through doesn't get rejected
+//! for a different reason on the server. Names get trimmed before they're checked, and the trimmed name is the one used.
+
+use serde::{Deserialize, Serialize};
+
+/// Most characters in a username
+pub const MAX_USERNAME_LEN: usize = 20;
+/// Punctuation allowed in usernames besides letters, digits, and spaces
+pub const USERNAME_PUNCTUATION: &[char] = &['-', '_', '.', '\''];
+/// Names nobody can take, since they'd look like they come from the server. Compared without case.
+pub const RESERVED_USERNAMES: &[&str] = &["admin", "administrator", "moderator", "server", "system"];
+
+/// What's wrong with a username. Sent as a code like the rest of the reasons for rejecting requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum UsernameProblem {
+    /// Nothing's left after trimming
+    Empty,
+    /// Longer than `MAX_USERNAME_LEN` characters
+    TooLong,
+    /// Has a character that isn't a letter, digit, space, or allowed punctuation
+    InvalidCharacter,
+    /// One of `RESERVED_USERNAMES`
+    Reserved,
+    /// Has a word the server filters out. Only the server knows which words those are.
+    Filtered,
+}
+
+/// Checks a username, returning it trimmed if it's allowed
+pub fn validate_username(username: &str) -> Result<String, UsernameProblem> {
+    let username = username.trim();
+    if username.is_empty() {
+        Err(UsernameProblem::Empty)
+    } else if username.chars().count() > MAX_USERNAME_LEN {
+        Err(UsernameProblem::TooLong)
+    } else if !username.chars().all(|c| c.is_alphanumeric() || c == ' ' || USERNAME_PUNCTUATION.contains(&c)) {
+        Err(UsernameProblem::InvalidCharacter)
+    } else if RESERVED_USERNAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(username)) {
+        Err(UsernameProblem::Reserved)
+    } else {
+        Ok(username.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_username() {
+        assert_eq!(validate_username("  alice "), Ok("alice".to_owned()));
+        assert_eq!(validate_username("Zoë O'Neil-Smith"), Ok("Zoë O'Neil-Smith".to_owned()));
+        assert_eq!(validate_username(" \t"), Err(UsernameProblem::Empty));
+        assert_eq!(validate_username(&"a".repeat(MAX_USERNAME_LEN)).map(|name| name.len()), Ok(MAX_USERNAME_LEN));
+        assert_eq!(validate_username(&"a".repeat(MAX_USERNAME_LEN + 1)), Err(UsernameProblem::TooLong));
+        assert_eq!(validate_username("a\nb"), Err(UsernameProblem::InvalidCharacter));
+        assert_eq!(validate_username("<script>"), Err(UsernameProblem::InvalidCharacter));
+        assert_eq!(validate_username("???"), Err(UsernameProblem::InvalidCharacter));
+        assert_eq!(validate_username("Server"), Err(UsernameProblem::Reserved));
+    }
+}
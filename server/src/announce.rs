@@ -0,0 +1,72 @@
+//! Registering the server with a directory so it shows up in server lists.
+//! Every so often, the server posts its info as JSON to the directory over plain HTTP.
+
+use std::{sync::Arc, time::Duration};
+
+use async_std::{io::{self, ReadExt, WriteExt}, net::TcpStream, sync::Mutex};
+use common::ServerInfo;
+use log::*;
+
+use crate::state::State;
+
+/// How often the server tells the directory it's still around
+pub const ANNOUNCE_PERIOD: Duration = Duration::from_secs(60);
+/// How long to wait on the directory before giving up on an announcement
+const ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the directory is, split into the parts needed to make a request
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirectoryUrl {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl DirectoryUrl {
+    /// Parses an `http://host[:port][/path]` URL. None if it isn't one; HTTPS isn't supported.
+    pub fn parse(url: &str) -> Option<Self> {
+        let rest = url.strip_prefix("http://")?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (authority, 80),
+        };
+
+        (!host.is_empty()).then(|| Self { host: host.to_owned(), port, path: path.to_owned() })
+    }
+
+    /// The HTTP request that announces `info` to the directory
+    pub fn announce_request(&self, info: &ServerInfo) -> String {
+        let body = serde_json::to_string(info).expect("Serialization went wrong");
+        format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path, self.host, body.len(), body
+        )
+    }
+}
+
+/// Sends the server's info to the directory once. Returns the status line of the response.
+async fn announce(url: &DirectoryUrl, info: &ServerInfo) -> io::Result<String> {
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port)).await?;
+    stream.write_all(url.announce_request(info).as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response.lines().next().unwrap_or_default().to_owned())
+}
+
+/// Announces the server to the directory until the server shuts down
+pub(crate) async fn run_announcer(url: DirectoryUrl, state: Arc<Mutex<State>>) {
+    loop {
+        let info = state.lock().await.server_info();
+        match io::timeout(ANNOUNCE_TIMEOUT, announce(&url, &info)).await {
+            Ok(status) if status.split(' ').nth(1).map_or(false, |code| code.starts_with('2')) =>
+                debug!("Announced to directory: {:?}", info),
+            Ok(status) => warn!("Directory rejected announcement: {}", status),
+            Err(err) => warn!("Failed to announce to directory: {}", err),
+        }
+        async_std::task::sleep(ANNOUNCE_PERIOD).await;
+    }
+}
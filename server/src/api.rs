@@ -0,0 +1,67 @@
+//! A read-only JSON API over HTTP, for tools, health checks and pages that don't speak the game's protocol.
+//! `/api/status` says how the server is doing, and `/api/games` lists the games it hosts.
+
+use common::{ServerInfo, options::GameOptions};
+use serde::Serialize;
+
+use crate::{game::GameInstance, state::State};
+
+/// Start of the paths the API answers
+pub const API_PATH: &str = "/api/";
+
+/// How the server is doing
+#[derive(Clone, Debug, Serialize)]
+pub struct ServerStatus {
+    #[serde(flatten)]
+    info: ServerInfo,
+    num_games: usize,
+    num_games_started: usize,
+}
+
+/// A game, as the lobby sees it
+#[derive(Clone, Debug, Serialize)]
+pub struct GameListing {
+    /// In hex, since JSON numbers can't hold every id exactly
+    id: String,
+    /// Usernames of the players, in turn order
+    players: Vec<String>,
+    num_spectators: usize,
+    started: bool,
+    game_over: bool,
+    options: GameOptions,
+}
+
+impl GameListing {
+    fn new(game: &GameInstance) -> Self {
+        Self {
+            id: game.id().to_string(),
+            players: game.players().iter().map(|player| player.username().clone()).collect(),
+            num_spectators: game.spectators().len(),
+            started: game.started(),
+            game_over: game.state().as_ref().map_or(false, |state| state.game_over()),
+            options: game.options().clone(),
+        }
+    }
+}
+
+pub fn status(state: &State) -> ServerStatus {
+    ServerStatus {
+        info: state.server_info(),
+        num_games: state.games().len(),
+        num_games_started: state.games().values().filter(|game| game.started()).count(),
+    }
+}
+
+pub fn games(state: &State) -> Vec<GameListing> {
+    state.games().values().map(GameListing::new).collect()
+}
+
+/// The JSON body of the response to a request for `path`. None if the API has nothing there.
+pub fn get(path: &str, state: &State) -> Option<String> {
+    let json = match path.strip_prefix(API_PATH)? {
+        "status" => serde_json::to_string(&status(state)),
+        "games" => serde_json::to_string(&games(state)),
+        _ => return None,
+    };
+    Some(json.expect("Serialization went wrong"))
+}
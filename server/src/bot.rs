@@ -0,0 +1,291 @@
+//! Bots that play games on the server.
+//! Demo mode bots talk to the server over an in-process channel instead of a web socket,
+//! but requests and responses still get serialized like they would over the wire.
+//! Bots added to a game with `AddBot` don't talk to the server at all; the processor moves for them.
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use async_std::sync::Mutex;
+use common::{GameStatus, LobbyGame, board::BasePort, config::MatchRounds, game::{BaseGame, GameId}, game_state::{BaseGameState, BaseTileMove, BaseTurnEvents}, message::{Request, Response}, options::{BotDifficulty, GamePreset, RejectionFeed}};
+use futures::{StreamExt, channel::mpsc::{self, UnboundedSender}};
+use itertools::Itertools;
+use log::*;
+
+use crate::{processor::respond_to_request, state::{State, Transport}};
+
+/// How long a bot waits before making each request, so humans can follow along
+const BOT_ACTION_DELAY: Duration = Duration::from_secs(1);
+
+/// What would happen if `player` made a tile placement.
+/// In games where everyone picks at once, it doesn't have to be their turn.
+fn simulate_move(game: &BaseGame, state: &BaseGameState, player: u32, (kind, index, action, loc): &BaseTileMove) -> BaseTurnEvents {
+    let mut state = state.clone();
+    for _ in 0..state.num_players() {
+        if state.turn_player() == player {
+            break;
+        }
+        state.skip_turn();
+    }
+    state.take_turn_placing_tile(game, kind, *index, action, loc)
+}
+
+/// Whether making a tile placement would kill `player`
+pub fn move_kills(game: &BaseGame, state: &BaseGameState, mv: &BaseTileMove, player: u32) -> bool {
+    simulate_move(game, state, player, mv).dead_players().contains(&player)
+}
+
+/// Picks a tile placement for `player`, preferring ones that don't kill them.
+/// None if they have no tiles to place.
+pub fn choose_move(game: &BaseGame, state: &BaseGameState, player: u32) -> Option<BaseTileMove> {
+    let mut moves = state.legal_moves(game, player);
+    let survivor = moves.iter().position(|mv| !move_kills(game, state, mv, player));
+    match survivor {
+        Some(index) => Some(moves.swap_remove(index)),
+        None => moves.into_iter().next(),
+    }
+}
+
+/// Picks a tile placement for `player` the way a bot of some difficulty would.
+/// None if they have no tiles to place.
+pub fn choose_bot_move(game: &BaseGame, state: &BaseGameState, player: u32, difficulty: BotDifficulty) -> Option<BaseTileMove> {
+    match difficulty {
+        BotDifficulty::Easy => state.legal_moves(game, player).into_iter().next(),
+        BotDifficulty::Medium => choose_move(game, state, player),
+        BotDifficulty::Hard => state.legal_moves(game, player).into_iter()
+            .max_by_key(|mv| {
+                let result = simulate_move(game, state, player, mv);
+                (!result.dead_players().contains(&player), result.distance_moved(player))
+            }),
+    }
+}
+
+/// Picks a free start port for `player`, spread out by player index
+/// so bots don't keep fighting over the same port
+pub fn choose_start_port(game: &BaseGame, state: &BaseGameState, player: u32) -> Option<BasePort> {
+    let free = game.start_ports().into_iter()
+        .filter(|port| state.clone().can_place_player(game, port))
+        .collect_vec();
+    free.get(player as usize * free.len() / state.num_players() as usize).cloned()
+}
+
+/// Stands in for the web socket of a bot the processor moves for.
+/// Those bots decide what to do from the server's state, so responses to them get dropped.
+#[derive(Debug)]
+pub struct NullTransport;
+
+impl Transport for NullTransport {
+    fn send(&self, _resp: Response) -> Result<(), Response> {
+        Ok(())
+    }
+}
+
+/// Sends responses to a bot, serialized like they would be over a web socket
+#[derive(Debug)]
+pub struct LoopbackTransport(UnboundedSender<Vec<u8>>);
+
+impl Transport for LoopbackTransport {
+    fn send(&self, resp: Response) -> Result<(), Response> {
+        let bytes = bincode::serialize(&resp).expect("Serialization went wrong");
+        self.0.unbounded_send(bytes).map_err(|_| resp)
+    }
+}
+
+/// A bot that joins games hosted by a specific bot and plays them to the end, over and over
+#[derive(Debug)]
+pub struct Bot {
+    username: String,
+    /// The bot that creates and starts games
+    host: String,
+    /// How many players the host waits for before starting
+    group_size: usize,
+    game: Option<(GameId, BaseGame)>,
+    /// None if the game hasn't started
+    state: Option<BaseGameState>,
+    /// Whether a join request is on its way
+    joining: bool,
+}
+
+impl Bot {
+    pub fn new(username: String, host: String, group_size: usize) -> Self {
+        Self { username, host, group_size, game: None, state: None, joining: false }
+    }
+
+    fn is_host(&self) -> bool {
+        self.username == self.host
+    }
+
+    /// The requests to make right after connecting
+    pub fn start(&self) -> Vec<Request> {
+        vec![Request::SetUsername{ username: self.username.clone() }]
+    }
+
+    /// Whether a game in the lobby is one this bot should join
+    fn should_join(&self, game: &LobbyGame) -> bool {
+        if self.game.is_some() || self.joining || game.status() != GameStatus::NotStarted {
+            return false;
+        }
+        if self.is_host() {
+            game.players().is_empty()
+        } else {
+            game.players().first() == Some(&self.host) && game.players().len() < self.group_size
+        }
+    }
+
+    fn join(&mut self, id: GameId) -> Vec<Request> {
+        self.joining = true;
+        vec![Request::JoinGame{ id }]
+    }
+
+    fn place_token(&self) -> Vec<Request> {
+        let (id, game) = self.game.as_ref().expect("Bot isn't in a game");
+        let state = self.state.as_ref().expect("Game hasn't started");
+        let player = state.player_expect();
+
+        choose_start_port(game, state, player)
+            .map(|port| Request::PlaceToken{ id: *id, player, port })
+            .into_iter().collect()
+    }
+
+    fn place_tile(&self) -> Vec<Request> {
+        let (id, game) = self.game.as_ref().expect("Bot isn't in a game");
+        let state = self.state.as_ref().expect("Game hasn't started");
+        let player = state.player_expect();
+
+        choose_move(game, state, player)
+            .map(|(kind, index, action, loc)| Request::PlaceTile{ id: *id, player, kind, index, action, loc })
+            .into_iter().collect()
+    }
+
+    /// Goes back to the lobby if the game is over
+    fn leave_if_over(&self) -> Vec<Request> {
+        if self.state.as_ref().map_or(false, |state| state.game_over()) {
+            vec![Request::JoinLobby]
+        } else { vec![] }
+    }
+
+    /// Updates the bot with a response and returns the requests it wants to make
+    pub fn handle_response(&mut self, resp: Response) -> Vec<Request> {
+        let in_game = |id: GameId| self.game.as_ref().map_or(false, |(gid, _)| *gid == id);
+
+        match resp {
+            Response::JoinedLobby{ games } => {
+                self.game = None;
+                self.state = None;
+                self.joining = false;
+                if self.is_host() {
+                    vec![Request::CreateGame{ preset: GamePreset::Standard, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None }]
+                } else if let Some(game) = games.iter().find(|game| self.should_join(game)) {
+                    self.join(game.id())
+                } else { vec![] }
+            }
+
+            Response::ChangedGame{ game } => if self.should_join(&game) {
+                self.join(game.id())
+            } else { vec![] }
+
+            Response::JoinedGame{ game } => {
+                self.joining = false;
+                self.game = Some((game.id(), game.game().clone()));
+                self.state = game.state().clone();
+                vec![]
+            }
+
+            Response::ChangedPlayers{ id, names, .. } => {
+                if self.is_host() && in_game(id) && self.state.is_none() && names.len() >= self.group_size {
+                    vec![Request::StartGame{ id }]
+                } else { vec![] }
+            }
+
+            Response::StartedGame{ id, state } | Response::StartedRound{ id, state, .. } if in_game(id) => {
+                self.state = Some(state);
+                if self.state.as_ref().unwrap().is_player() { self.place_token() } else { vec![] }
+            }
+
+            // Someone else got to the port first
+            Response::Rejected{ id, .. } if in_game(id) => {
+                let state = self.state.as_ref();
+                if state.map_or(false, |state| state.is_player() && state.board_state().player_port(state.player_expect()).is_none()) {
+                    self.place_token()
+                } else { vec![] }
+            }
+
+            Response::PlacedToken{ id, player, port } if in_game(id) => {
+                self.state.as_mut().expect("Token placed before the game started").place_player(player, &port);
+                vec![]
+            }
+
+            Response::PlacedTile{ id, player, kind, index, action, loc, tile, drawn } if in_game(id) => {
+                let (_, game) = self.game.as_ref().unwrap();
+                let state = self.state.as_mut().expect("Tile placed before the game started");
+                state.reveal_tiles(&[(player, index, tile)]);
+                state.take_turn_placing_tile(game, &kind, index, &action, &loc);
+                state.reveal_tiles(&drawn);
+                self.leave_if_over()
+            }
+
+            Response::ExchangedHand{ id, drawn, .. } if in_game(id) => {
+                let state = self.state.as_mut().expect("Hand exchanged before the game started");
+                state.take_turn_exchanging_hand();
+                state.reveal_tiles(&drawn);
+                vec![]
+            }
+
+            Response::LeftGame{ id, player, drawn } if in_game(id) => {
+                let (_, game) = self.game.as_ref().unwrap();
+                let state = self.state.as_mut().expect("Player left before the game started");
+                state.remove_player(game, player);
+                state.reveal_tiles(&drawn);
+                self.leave_if_over()
+            }
+
+            Response::SkippedTurn{ id, .. } if in_game(id) => {
+                self.state.as_mut().expect("Turn skipped before the game started").skip_turn();
+                vec![]
+            }
+
+            Response::YourTurn{ id } if in_game(id) => self.place_tile(),
+
+            _ => vec![],
+        }
+    }
+}
+
+/// Connects a bot to the server and has it play until the server shuts down
+async fn run_bot(addr: SocketAddr, mut bot: Bot, state: Arc<Mutex<State>>) {
+    let (tx, mut rx) = mpsc::unbounded();
+    state.lock().await.add_peer(addr, LoopbackTransport(tx));
+    info!("Starting bot {} at {}", bot.username, addr);
+
+    let mut requests = bot.start();
+    loop {
+        for req in requests {
+            async_std::task::sleep(BOT_ACTION_DELAY).await;
+            let bytes = bincode::serialize(&req).expect("Serialization went wrong");
+            let req = bincode::deserialize::<Request>(&bytes).expect("Deserialization went wrong");
+            respond_to_request(req, addr, &state).await;
+        }
+
+        requests = match rx.next().await {
+            Some(bytes) => match bincode::deserialize::<Response>(&bytes) {
+                Ok(resp) => bot.handle_response(resp),
+                Err(err) => {
+                    error!("Invalid response to bot {}: {:?}", bot.username, err);
+                    vec![]
+                }
+            },
+            None => break,
+        };
+    }
+}
+
+/// Spawns bots that keep playing games with each other.
+/// The first bot hosts, and starts each game once all the bots are in.
+pub(crate) fn spawn_bots(num_bots: usize, state: &Arc<Mutex<State>>) {
+    let host = "Bot 1".to_owned();
+    for i in 0..num_bots {
+        // No real peer connects from the unspecified address
+        let addr = SocketAddr::from(([0, 0, 0, 0], i as u16 + 1));
+        let bot = Bot::new(format!("Bot {}", i + 1), host.clone(), num_bots);
+        async_std::task::spawn(run_bot(addr, bot, Arc::clone(state)));
+    }
+}
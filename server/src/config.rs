@@ -0,0 +1,300 @@
+//! The server's configuration.
+//! It comes from an optional JSON config file, with environment variables overriding it and command line flags overriding both,
+//! and anything set nowhere keeps its default. Limits get checked the same way the client checks them.
+//! Every environment variable has a flag named after it, so `--tls-cert cert.pem` does what `TLS_CERT=cert.pem` does.
+
+use std::{env, fs, net::SocketAddr, path::{Path, PathBuf}};
+
+use common::config::{DisconnectGracePeriod, IdleTimeout, TurnTimeLimit};
+use log::LevelFilter;
+use getset::{CopyGetters, Getters};
+use serde::Deserialize;
+
+use crate::{announce::DirectoryUrl, filter::WordFilter, journal::FsyncPolicy, state::{DEFAULT_SERVER_NAME, DEFAULT_SERVER_REGION}};
+
+/// Environment variable for the path of the config file
+const CONFIG_FILE_VAR: &str = "CONFIG_FILE";
+/// Environment variable for overriding the disconnect grace period, in seconds
+const DISCONNECT_GRACE_PERIOD_VAR: &str = "DISCONNECT_GRACE_PERIOD";
+/// Environment variable for overriding how long idle games and peers get kept, in seconds
+const IDLE_TIMEOUT_VAR: &str = "IDLE_TIMEOUT";
+/// Environment variable for the server's name in server lists
+const SERVER_NAME_VAR: &str = "SERVER_NAME";
+/// Environment variable for the server's region in server lists
+const SERVER_REGION_VAR: &str = "SERVER_REGION";
+/// Environment variable for the URL of the directory to announce the server to
+const DIRECTORY_URL_VAR: &str = "DIRECTORY_URL";
+/// Environment variable for the directory to keep game journals in
+const JOURNAL_DIR_VAR: &str = "JOURNAL_DIR";
+/// Environment variable for when journal entries get synced to disk, `always` (the default) or `never`
+const JOURNAL_FSYNC_VAR: &str = "JOURNAL_FSYNC";
+/// Environment variable for the directory to write archives of finished games in
+const ARCHIVE_DIR_VAR: &str = "ARCHIVE_DIR";
+/// Environment variable for the directory of the client's files, to serve them on the same port as the game
+const STATIC_DIR_VAR: &str = "STATIC_DIR";
+/// Environment variable for the path of the TLS certificate chain, in PEM format
+const TLS_CERT_VAR: &str = "TLS_CERT";
+/// Environment variable for the path of the TLS private key, in PEM format
+const TLS_KEY_VAR: &str = "TLS_KEY";
+/// Environment variable for the address to listen on
+const BIND_ADDRESS_VAR: &str = "BIND_ADDRESS";
+/// Environment variable for overriding the port of the address to listen on
+const PORT_VAR: &str = "PORT";
+/// Environment variable for the most games the server hosts at once
+const MAX_GAMES_VAR: &str = "MAX_GAMES";
+/// Environment variable for a turn time limit every game gets, in seconds
+const TURN_TIME_LIMIT_VAR: &str = "TURN_TIME_LIMIT";
+/// Environment variable for how much gets logged, like `info` or `debug`
+const LOG_LEVEL_VAR: &str = "LOG_LEVEL";
+/// Environment variable for whether bots play each other so there's always something to watch
+const DEMO_VAR: &str = "DEMO";
+/// Environment variable for words players can't use, separated by commas
+const WORD_FILTER_VAR: &str = "WORD_FILTER";
+
+/// Every variable, for telling which flags exist
+const VARS: [&str; 19] = [
+    CONFIG_FILE_VAR, DISCONNECT_GRACE_PERIOD_VAR, IDLE_TIMEOUT_VAR, SERVER_NAME_VAR, SERVER_REGION_VAR, DIRECTORY_URL_VAR,
+    JOURNAL_DIR_VAR, JOURNAL_FSYNC_VAR, ARCHIVE_DIR_VAR, STATIC_DIR_VAR, TLS_CERT_VAR, TLS_KEY_VAR, BIND_ADDRESS_VAR,
+    PORT_VAR, MAX_GAMES_VAR, TURN_TIME_LIMIT_VAR, LOG_LEVEL_VAR, DEMO_VAR, WORD_FILTER_VAR,
+];
+
+#[derive(Clone, Debug, Getters, CopyGetters, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Name of the server in server lists
+    #[getset(get = "pub")]
+    name: String,
+    /// Where the server is, roughly, for server lists
+    #[getset(get = "pub")]
+    region: String,
+    /// How long a disconnected player has to come back before their turns get skipped, in seconds
+    #[getset(get_copy = "pub")]
+    disconnect_grace_period: DisconnectGracePeriod,
+    /// How long games that ended or sat empty without starting, and peers that never picked a username, get kept, in seconds
+    #[getset(get_copy = "pub")]
+    idle_timeout: IdleTimeout,
+    /// URL of the directory to announce the server to. The server doesn't announce itself if this isn't set.
+    directory_url: Option<String>,
+    /// Directory to keep game journals in. Games don't get journaled if this isn't set.
+    #[getset(get = "pub")]
+    journal_dir: Option<PathBuf>,
+    /// When journal entries get synced to disk
+    #[getset(get_copy = "pub")]
+    journal_fsync: FsyncPolicy,
+    /// Directory to write archives of finished games in. Games don't get archived if this isn't set.
+    #[getset(get = "pub")]
+    archive_dir: Option<PathBuf>,
+    /// Directory of the client's files, which get served over HTTP with games taken on `/ws`.
+    /// Every connection is a game if this isn't set.
+    #[getset(get = "pub")]
+    static_dir: Option<PathBuf>,
+    /// Certificate chain to accept `wss://` connections with, in PEM format.
+    /// Connections are plain `ws://` if this and the key aren't set.
+    tls_cert: Option<PathBuf>,
+    /// Private key of the certificate, in PEM format
+    tls_key: Option<PathBuf>,
+    /// Address to listen on
+    bind_address: String,
+    /// Port to listen on instead of the bind address's
+    port: Option<u16>,
+    /// The most games the server hosts at once. There's no limit if this isn't set.
+    #[getset(get_copy = "pub")]
+    max_games: Option<usize>,
+    /// Turn time limit every game gets, in seconds, instead of its preset's
+    #[getset(get_copy = "pub")]
+    turn_time_limit: Option<TurnTimeLimit>,
+    /// How much gets logged
+    log_level: String,
+    /// Whether bots play each other so there's always something to watch
+    #[getset(get_copy = "pub")]
+    demo: bool,
+    /// Words players can't use in usernames or anything else they write.
+    /// A word can start or end with `*` to also filter words it's part of.
+    #[getset(get = "pub")]
+    word_filter: WordFilter,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_SERVER_NAME.to_owned(),
+            region: DEFAULT_SERVER_REGION.to_owned(),
+            disconnect_grace_period: DisconnectGracePeriod::DEFAULT,
+            idle_timeout: IdleTimeout::DEFAULT,
+            directory_url: None,
+            journal_dir: None,
+            journal_fsync: FsyncPolicy::default(),
+            archive_dir: None,
+            static_dir: None,
+            tls_cert: None,
+            tls_key: None,
+            bind_address: common::HOST_ADDRESS.to_owned(),
+            port: None,
+            max_games: None,
+            turn_time_limit: None,
+            log_level: "debug".to_owned(),
+            demo: false,
+            word_filter: WordFilter::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Parses a config file's contents, checking every value
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let config: Self = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads the config file named by the flags or the environment, if any,
+    /// and applies the environment's overrides and then the flags'
+    pub fn from_env_and_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let flags = parse_flags(args)?;
+        let flag = |var: &str| flags.iter().rev().find(|(name, _)| *name == var).map(|(_, value)| value.clone());
+        let mut config = match flag(CONFIG_FILE_VAR).or_else(|| env::var(CONFIG_FILE_VAR).ok()) {
+            Some(path) => Self::load(Path::new(&path))?,
+            None => Self::default(),
+        };
+        config.override_with(|var| env::var(var).ok())?;
+        config.override_with(flag)?;
+        Ok(config)
+    }
+
+    fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|err| format!("Can't read {:?}: {}", path, err))?;
+        Self::parse(&json).map_err(|err| format!("Invalid config file {:?}: {}", path, err))
+    }
+
+    /// Overrides values with the variables `var` finds, checking every value
+    pub(crate) fn override_with(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        if let Some(secs) = var(DISCONNECT_GRACE_PERIOD_VAR) {
+            self.disconnect_grace_period = secs.parse::<u64>().map_err(|err| err.to_string())
+                .and_then(|secs| DisconnectGracePeriod::from_repr(secs).map_err(|err| err.to_string()))
+                .map_err(|err| format!("Invalid {} {:?}: {}", DISCONNECT_GRACE_PERIOD_VAR, secs, err))?;
+        }
+        if let Some(secs) = var(IDLE_TIMEOUT_VAR) {
+            self.idle_timeout = secs.parse::<u64>().map_err(|err| err.to_string())
+                .and_then(|secs| IdleTimeout::from_repr(secs).map_err(|err| err.to_string()))
+                .map_err(|err| format!("Invalid {} {:?}: {}", IDLE_TIMEOUT_VAR, secs, err))?;
+        }
+        if let Some(name) = var(SERVER_NAME_VAR) {
+            self.name = name;
+        }
+        if let Some(region) = var(SERVER_REGION_VAR) {
+            self.region = region;
+        }
+        if let Some(url) = var(DIRECTORY_URL_VAR) {
+            self.directory_url = Some(url);
+        }
+        if let Some(dir) = var(JOURNAL_DIR_VAR) {
+            self.journal_dir = Some(dir.into());
+        }
+        if let Some(policy) = var(JOURNAL_FSYNC_VAR) {
+            self.journal_fsync = policy.parse().map_err(|err| format!("Invalid {} {:?}: {}", JOURNAL_FSYNC_VAR, policy, err))?;
+        }
+        if let Some(dir) = var(ARCHIVE_DIR_VAR) {
+            self.archive_dir = Some(dir.into());
+        }
+        if let Some(dir) = var(STATIC_DIR_VAR) {
+            self.static_dir = Some(dir.into());
+        }
+        if let Some(path) = var(TLS_CERT_VAR) {
+            self.tls_cert = Some(path.into());
+        }
+        if let Some(path) = var(TLS_KEY_VAR) {
+            self.tls_key = Some(path.into());
+        }
+        if let Some(address) = var(BIND_ADDRESS_VAR) {
+            self.bind_address = address;
+        }
+        if let Some(port) = var(PORT_VAR) {
+            self.port = Some(port.parse().map_err(|err| format!("Invalid {} {:?}: {}", PORT_VAR, port, err))?);
+        }
+        if let Some(max) = var(MAX_GAMES_VAR) {
+            self.max_games = Some(max.parse().map_err(|err| format!("Invalid {} {:?}: {}", MAX_GAMES_VAR, max, err))?);
+        }
+        if let Some(secs) = var(TURN_TIME_LIMIT_VAR) {
+            self.turn_time_limit = Some(secs.parse::<u64>().map_err(|err| err.to_string())
+                .and_then(|secs| TurnTimeLimit::from_repr(secs).map_err(|err| err.to_string()))
+                .map_err(|err| format!("Invalid {} {:?}: {}", TURN_TIME_LIMIT_VAR, secs, err))?);
+        }
+        if let Some(level) = var(LOG_LEVEL_VAR) {
+            self.log_level = level;
+        }
+        if let Some(demo) = var(DEMO_VAR) {
+            self.demo = demo.parse().map_err(|err| format!("Invalid {} {:?}: {}", DEMO_VAR, demo, err))?;
+        }
+        if let Some(words) = var(WORD_FILTER_VAR) {
+            self.word_filter = WordFilter::new(words.split(','));
+        }
+        self.validate()
+    }
+
+    /// Checks what the types of the values don't
+    fn validate(&self) -> Result<(), String> {
+        match &self.directory_url {
+            Some(url) if DirectoryUrl::parse(url).is_none() =>
+                return Err(format!("Invalid directory URL {:?}: only http:// URLs are supported", url)),
+            _ => {}
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err("TLS needs both a certificate and a key".to_owned());
+        }
+        self.bind_address.parse::<SocketAddr>()
+            .map_err(|err| format!("Invalid bind address {:?}: {}", self.bind_address, err))?;
+        self.log_level.parse::<LevelFilter>()
+            .map_err(|err| format!("Invalid log level {:?}: {}", self.log_level, err))?;
+        Ok(())
+    }
+
+    /// The address to listen on
+    pub fn bind_address(&self) -> SocketAddr {
+        let mut address: SocketAddr = self.bind_address.parse().expect("Bind address should be checked");
+        if let Some(port) = self.port {
+            address.set_port(port);
+        }
+        address
+    }
+
+    /// How much gets logged
+    pub fn log_level(&self) -> LevelFilter {
+        self.log_level.parse().expect("Log level should be checked")
+    }
+
+    /// Paths of the TLS certificate chain and private key, if connections are over TLS
+    pub fn tls(&self) -> Option<(&Path, &Path)> {
+        self.tls_cert.as_deref().zip(self.tls_key.as_deref())
+    }
+
+    /// The directory to announce the server to, if any
+    pub fn directory_url(&self) -> Option<DirectoryUrl> {
+        self.directory_url.as_deref().and_then(DirectoryUrl::parse)
+    }
+}
+
+/// The flag for a variable, like `--tls-cert` for `TLS_CERT`
+fn flag_name(var: &str) -> String {
+    format!("--{}", var.to_lowercase().replace('_', "-"))
+}
+
+/// Parses flags given as `--flag value` or `--flag=value` into the variables they set.
+/// A flag without a value, like `--demo`, sets its variable to `true`.
+pub(crate) fn parse_flags(args: impl IntoIterator<Item = String>) -> Result<Vec<(&'static str, String)>, String> {
+    let mut args = args.into_iter().peekable();
+    let mut flags = vec![];
+    while let Some(arg) = args.next() {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_owned(), Some(value.to_owned())),
+            None => (arg, None),
+        };
+        let var = VARS.into_iter().find(|var| flag_name(var) == name).ok_or_else(|| format!(
+            "Unknown flag {:?}. The flags are {}.", name, VARS.map(flag_name).join(", "),
+        ))?;
+        let value = value
+            .or_else(|| args.next_if(|next| !next.starts_with("--")))
+            .unwrap_or_else(|| "true".to_owned());
+        flags.push((var, value));
+    }
+    Ok(flags)
+}
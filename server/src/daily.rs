@@ -0,0 +1,66 @@
+//! The leaderboard of the daily challenge. It starts over each day,
+//! and like the history, it's kept in memory only, so it's gone when the server restarts.
+
+use std::{collections::HashSet, time::{SystemTime, UNIX_EPOCH}};
+
+use common::daily::{DailyResult, Day};
+
+/// Most results the leaderboard keeps. Results that would rank below all of them get dropped.
+pub const MAX_DAILY_RESULTS: usize = 1000;
+/// Most results sent to players asking for the leaderboard
+pub const MAX_SENT_DAILY_RESULTS: usize = 50;
+
+/// The day it is now
+pub fn today() -> Day {
+    Day::from_unix_secs(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()))
+}
+
+/// Results of one day's daily challenge, best first
+#[derive(Debug)]
+pub struct DailyLeaderboard {
+    day: Day,
+    results: Vec<DailyResult>,
+    /// Usernames that got a result, including ones whose result got dropped, so nobody gets a second go
+    played: HashSet<String>,
+}
+
+impl DailyLeaderboard {
+    pub fn new(day: Day) -> Self {
+        Self { day, results: vec![], played: HashSet::new() }
+    }
+
+    pub fn day(&self) -> Day {
+        self.day
+    }
+
+    /// Moves the leaderboard on to `day`, dropping the results of any earlier day
+    pub fn roll_over(&mut self, day: Day) {
+        if day != self.day {
+            *self = Self::new(day);
+        }
+    }
+
+    /// Whether `username` already got a result today
+    pub fn played(&self, username: &str) -> bool {
+        self.played.contains(username)
+    }
+
+    /// Adds a result of the challenge of `day`, keeping the results ranked.
+    /// Results of other days, and of usernames that already got one, get ignored.
+    /// Returns whether the result was added.
+    pub fn record(&mut self, day: Day, result: DailyResult) -> bool {
+        if day != self.day || !self.played.insert(result.username().clone()) {
+            return false;
+        }
+        // Ties go to whoever finished first
+        let pos = self.results.partition_point(|other| other.rank_key() <= result.rank_key());
+        self.results.insert(pos, result);
+        self.results.truncate(MAX_DAILY_RESULTS);
+        true
+    }
+
+    /// The best results, as many as get sent to players
+    pub fn top(&self) -> Vec<DailyResult> {
+        self.results.iter().take(MAX_SENT_DAILY_RESULTS).cloned().collect()
+    }
+}
@@ -0,0 +1,112 @@
+//! Filtering words the server's operator doesn't want on their server out of usernames and any other text players write.
+//! Text gets split into words, and each word gets normalized before it's compared,
+//! so `B4D`, `b.a.d` and `bad` all count as `bad`.
+//! Patterns can start or end with `*` to match words starting with, ending with, or containing them.
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer};
+
+/// A pattern words get compared with, normalized
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Pattern {
+    word: String,
+    /// Whether the pattern started with `*`, so words can have anything before it
+    any_prefix: bool,
+    /// Whether the pattern ended with `*`, so words can have anything after it
+    any_suffix: bool,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Option<Self> {
+        let any_prefix = pattern.starts_with('*');
+        let any_suffix = pattern.len() > 1 && pattern.ends_with('*');
+        let word = normalize(pattern.trim_matches('*'));
+        (!word.is_empty()).then_some(Self { word, any_prefix, any_suffix })
+    }
+
+    /// Whether a normalized word matches
+    fn matches(&self, word: &str) -> bool {
+        match (self.any_prefix, self.any_suffix) {
+            (false, false) => word == self.word,
+            (false, true) => word.starts_with(&self.word),
+            (true, false) => word.ends_with(&self.word),
+            (true, true) => word.contains(&self.word),
+        }
+    }
+}
+
+/// Lowercases a word, reads digits and symbols that stand in for letters as those letters,
+/// and drops everything else that isn't a letter
+fn normalize(word: &str) -> String {
+    word.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | '!' | '|' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' | '+' => 't',
+            '8' => 'b',
+            '9' => 'g',
+            c => c,
+        })
+        .filter(|c| c.is_alphabetic())
+        .collect()
+}
+
+/// A list of patterns to filter out. Empty by default, so nothing gets filtered unless the config says so.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct WordFilter {
+    patterns: Vec<Pattern>,
+}
+
+impl fmt::Debug for WordFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The list itself is best kept out of logs
+        write!(f, "WordFilter({} patterns)", self.patterns.len())
+    }
+}
+
+impl WordFilter {
+    /// Makes a filter from patterns. Patterns with no letters in them get ignored.
+    pub fn new<S: AsRef<str>>(patterns: impl IntoIterator<Item = S>) -> Self {
+        Self { patterns: patterns.into_iter().filter_map(|pattern| Pattern::parse(pattern.as_ref())).collect() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    fn matches_word(&self, word: &str) -> bool {
+        let word = normalize(word);
+        !word.is_empty() && self.patterns.iter().any(|pattern| pattern.matches(&word))
+    }
+
+    /// Whether any word in some text gets filtered
+    pub fn matches(&self, text: &str) -> bool {
+        text.split_whitespace().any(|word| self.matches_word(word))
+    }
+
+    /// The text with every filtered word replaced with `*`s, keeping the whitespace between words
+    pub fn mask(&self, text: &str) -> String {
+        text.split_inclusive(char::is_whitespace)
+            .map(|piece| {
+                let word = piece.trim_end_matches(char::is_whitespace);
+                if self.matches_word(word) {
+                    "*".repeat(word.chars().count()) + &piece[word.len()..]
+                } else {
+                    piece.to_owned()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Config files list the patterns
+impl<'de> Deserialize<'de> for WordFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::new(Vec::<String>::deserialize(deserializer)?))
+    }
+}
@@ -1,7 +1,37 @@
+use std::collections::VecDeque;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
-use common::{game::{BaseGame, GameId}, game_state::BaseGameState};
-use getset::{Getters, CopyGetters};
+use common::{GameStatus, LobbyGame, clock::Clocks, daily::Day, game::{BaseGame, GameId, MatchScore}, game_state::{BaseGameState, BaseTileMove}, history::GameSummary, message::{RejectReason, Response, SkipReason}, notation, options::{Avatar, GameOptions, TokenColor}, player_state::{Looker, LookerTag}, replay::{Replay, ReplayId, ReplayMove}, tile::BaseTile};
+use getset::{Getters, CopyGetters, Setters};
+use serde::{Deserialize, Serialize};
+
+use crate::rate_limit::{EMOTE_BURST, EMOTES_PER_SECOND, RateLimiter, Verdict};
+
+/// How long after a game ends its players can ask for a rematch
+pub const REMATCH_WINDOW: Duration = Duration::from_secs(2 * 60);
+
+/// A tile drawn by a player, in (player, index, tile) format
+pub type DrawnTile = (u32, u32, BaseTile);
+
+/// What a player picked to do this round, in a game where everyone picks at once
+#[derive(Clone, Debug)]
+pub enum Commit {
+    /// Placing a tile, in (kind, index, action, location) format
+    PlaceTile(BaseTileMove),
+    /// Sitting the round out, and why
+    Skip(SkipReason),
+}
+
+/// A player's request to take back the last tile they placed, waiting on everyone else to accept
+#[derive(Clone, Debug)]
+struct UndoRequest {
+    player: u32,
+    /// Moves in the replay when it got asked for. Any move after that makes the request stale.
+    num_moves: usize,
+    /// Players who accepted so far
+    accepted: Vec<u32>,
+}
 
 #[derive(Clone, Debug, Getters, CopyGetters)]
 pub struct Player {
@@ -9,9 +39,60 @@ pub struct Player {
     addr: SocketAddr,
     #[getset(get = "pub")]
     username: String,
+    /// When the player lost their connection. None if they're connected.
+    #[getset(get_copy = "pub")]
+    disconnected_since: Option<Instant>,
+    /// Whether the player left after the game started. They stay out of later rounds.
+    #[getset(get_copy = "pub")]
+    left: bool,
+    /// The token color the player picked. None if they didn't pick one.
+    #[getset(get_copy = "pub")]
+    color: Option<TokenColor>,
+    /// None if the player didn't pick one
+    #[getset(get_copy = "pub")]
+    avatar: Option<Avatar>,
+    /// Keeps the player from flooding the game with emotes
+    emotes: RateLimiter,
+}
+
+impl Player {
+    fn new(addr: SocketAddr, username: String) -> Self {
+        Self {
+            addr, username, disconnected_since: None, left: false, color: None, avatar: None,
+            emotes: RateLimiter::new(EMOTE_BURST, EMOTES_PER_SECOND, Instant::now()),
+        }
+    }
+
+    /// Whether the player is connected
+    pub fn connected(&self) -> bool {
+        self.disconnected_since.is_none()
+    }
+}
+
+/// Everything about a game that outlives connections, for persisting it or moving it elsewhere.
+/// Players are kept by username, and spectators are left out.
+#[derive(Clone, Debug, CopyGetters, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    #[getset(get_copy = "pub")]
+    id: GameId,
+    game: BaseGame,
+    options: GameOptions,
+    players: Vec<String>,
+    /// None if the game hasn't started
+    state: Option<BaseGameState>,
+    /// None if the game hasn't started
+    replay: Option<Replay>,
+    /// None if the game hasn't started
+    match_score: Option<MatchScore>,
+    /// Indexes of players who left after the game started
+    left: Vec<u32>,
+    /// The day whose daily challenge the game is. None for other games.
+    daily: Option<Day>,
+    /// The token color each player picked
+    colors: Vec<Option<TokenColor>>,
 }
 
-#[derive(Debug, Getters, CopyGetters)]
+#[derive(Debug, Getters, CopyGetters, Setters)]
 pub struct GameInstance {
     #[getset(get_copy = "pub")]
     id: GameId,
@@ -25,33 +106,385 @@ pub struct GameInstance {
     players: Vec<Player>, 
     #[getset(get = "pub")]
     spectators: Vec<Player>,
+    #[getset(get = "pub")]
+    options: GameOptions,
+    /// When the current turn started. None if no turn is being timed.
+    turn_started: Option<Instant>,
+    /// Each player's clock as of when the current turn started. None if the game hasn't started or has no clocks.
+    clocks: Option<Clocks>,
+    /// Every move made since the round started. None if the game hasn't started.
+    #[getset(get = "pub")]
+    replay: Option<Replay>,
+    /// Rounds won so far. None if the game hasn't started.
+    #[getset(get = "pub")]
+    match_score: Option<MatchScore>,
+    /// What each player picked to do this round, in games where everyone picks at once.
+    /// None for players who haven't picked. Kept secret until everyone has.
+    commits: Vec<Option<Commit>>,
+    /// The pending request to take back the last tile placement, if any
+    undo_request: Option<UndoRequest>,
+    /// When the game got paused. None if it isn't paused.
+    paused_at: Option<Instant>,
+    /// Players who asked to pause the game, or to resume it if it's paused
+    pause_votes: Vec<u32>,
+    /// The game its rematch is played in, once someone asked for one
+    #[getset(get_copy = "pub", set = "pub")]
+    rematch: Option<GameId>,
+    /// Usernames in the order their players sit, for rematches and reordered players, so nobody's seat depends on when they joined.
+    /// Players who aren't in it sit after everyone who is.
+    #[getset(set = "pub")]
+    seats: Vec<String>,
+    /// When the game was created, or restored
+    #[getset(get_copy = "pub")]
+    created: Instant,
+    /// When the game started, or got restored after starting. None if it hasn't started.
+    started_at: Option<Instant>,
+    /// When the game ended. None if it hasn't.
+    #[getset(get_copy = "pub")]
+    ended: Option<Instant>,
+    /// The day whose daily challenge the game is. None for other games.
+    /// Daily challenges are played alone, so they stay out of the lobby.
+    #[getset(get_copy = "pub", set = "pub")]
+    daily: Option<Day>,
+    /// Responses held back from spectators until the spectator delay has passed, in (due, address, response) format, soonest first
+    delayed: VecDeque<(Instant, SocketAddr, Response)>,
 }
 
 impl GameInstance {
-    pub fn new(id: GameId, game: BaseGame) -> Self {
+    pub fn new(id: GameId, game: BaseGame, options: GameOptions) -> Self {
         Self {
             id,
             game,
             state: None,
             players: vec![],
-            spectators: vec![]
+            spectators: vec![],
+            options,
+            turn_started: None,
+            clocks: None,
+            replay: None,
+            match_score: None,
+            commits: vec![],
+            undo_request: None,
+            paused_at: None,
+            pause_votes: vec![],
+            rematch: None,
+            seats: vec![],
+            created: Instant::now(),
+            started_at: None,
+            ended: None,
+            daily: None,
+            delayed: VecDeque::new(),
         }
     }
 
-    pub fn to_common(&self) -> common::GameInstance {
+    /// Takes a snapshot of the game
+    pub fn snapshot(&self) -> GameSnapshot {
+        GameSnapshot {
+            id: self.id,
+            game: self.game.clone(),
+            options: self.options.clone(),
+            players: self.players.iter().map(|player| player.username.clone()).collect(),
+            state: self.state.clone(),
+            replay: self.replay.clone(),
+            match_score: self.match_score.clone(),
+            left: self.players.iter().enumerate().filter(|(_, player)| player.left).map(|(index, _)| index as u32).collect(),
+            daily: self.daily,
+            colors: self.colors(),
+        }
+    }
+
+    /// Restores a game from a snapshot, giving each player an address from `new_addr`.
+    /// The players count as disconnected until they join again.
+    pub fn restore(snapshot: GameSnapshot, mut new_addr: impl FnMut() -> SocketAddr, now: Instant) -> Self {
+        let GameSnapshot{ id, game, options, players, state, replay, match_score, left, daily, colors } = snapshot;
+        let players: Vec<_> = players.into_iter().enumerate()
+            .map(|(index, username)| Player {
+                addr: new_addr(), username, disconnected_since: Some(now), left: left.contains(&(index as u32)),
+                color: colors.get(index).copied().flatten(),
+                // Avatars come back as the players join again
+                avatar: None,
+                emotes: RateLimiter::new(EMOTE_BURST, EMOTES_PER_SECOND, now),
+            })
+            .collect();
+        let turn_started = state.is_some().then_some(now);
+        // The clocks start over, since the journal gets replayed much faster than it got played
+        let clocks = options.time_control().filter(|_| state.is_some()).map(|control| Clocks::new(control, players.len() as u32));
+        let ended = state.as_ref().filter(|state| state.game_over()).map(|_| now);
+        // Picks come back as the journal gets replayed
+        let commits = vec![None; players.len()];
+        Self {
+            id, game, state, players, spectators: vec![], options, turn_started, clocks, replay, match_score, commits, undo_request: None,
+            // Pauses come back as the journal gets replayed
+            paused_at: None, pause_votes: vec![], rematch: None, seats: vec![], created: now, started_at: turn_started, ended, daily,
+            delayed: VecDeque::new(),
+        }
+    }
+
+    /// The game as the lobby shows it
+    pub fn to_lobby(&self) -> LobbyGame {
+        let status = match &self.state {
+            None => GameStatus::NotStarted,
+            Some(state) if state.game_over() => GameStatus::Over,
+            Some(_) => GameStatus::Started,
+        };
+        LobbyGame::new(
+            self.id,
+            self.players.iter().map(|player| player.username().clone()).collect(),
+            self.avatars(),
+            status,
+            self.options.clone(),
+        )
+    }
+
+    /// The game as seen by `looker`
+    pub fn to_common_for(&self, looker: Looker) -> common::GameInstance {
         common::GameInstance::new(
             self.id,
             self.game.clone(),
-            self.state.clone(),
+            self.visible_state(looker),
             self.players.iter().map(|player| player.username().clone()).collect(),
+            self.spectators.iter().map(|spectator| spectator.username().clone()).collect(),
+            self.options.clone(),
+            self.match_score.clone(),
+            self.replay.as_ref().map_or(vec![], |replay| notation::notate_replay(replay).into_iter().map(|(_, mv)| mv).collect()),
+            self.colors(),
+            self.avatars(),
         )
     }
 
+    /// The state as seen by `looker`, with tiles they shouldn't see redacted.
+    /// In games with open hands, everyone sees everyone's hands, but not the draw pile. None if the game hasn't started.
+    pub fn visible_state(&self, looker: Looker) -> Option<BaseGameState> {
+        self.state.as_ref().map(|state| {
+            let state = state.visible_state(looker);
+            if self.options.open_hands() { state.with_hands_shown() } else { state }.redacted()
+        })
+    }
+
+    /// Tiles drawn by players in (player, index, tile) format as `looker` gets to see them
+    pub fn visible_drawn_tiles(&self, drawn: &[DrawnTile], looker: Looker) -> Vec<DrawnTile> {
+        drawn.iter().map(|(player, index, tile)| {
+            let visible = looker.tag() != LookerTag::Player || looker == Looker::Player(*player);
+            let tile = if visible || self.options.open_hands() {
+                tile.clone().with_visible(true)
+            } else {
+                tile.redacted()
+            };
+            (*player, *index, tile)
+        }).collect()
+    }
+
+    /// Iterates over all players and spectators along with who they look at the game as.
+    /// Players come first.
+    pub fn users_with_lookers(&self) -> impl Iterator<Item = (&Player, Looker)> {
+        self.players.iter().enumerate().map(|(index, player)| (player, Looker::Player(index as u32)))
+            .chain(self.spectators.iter().map(|spectator| (spectator, Looker::Spectator)))
+    }
+
+    /// Whether it's up to `player` to place a tile: on their turn, or in games where everyone picks at once,
+    /// until they've picked what to do this round
+    pub fn awaits_move(&self, player: u32) -> bool {
+        match &self.state {
+            Some(state) if self.options.simultaneous() => state.all_players_placed() && !state.game_over() &&
+                state.player_state(player).is_some() && !self.committed(player),
+            Some(state) => !state.game_over() && state.turn_player() == player,
+            None => false,
+        }
+    }
+
+    /// Addresses of the players it's up to to place a tile
+    pub fn awaited_players(&self) -> Vec<SocketAddr> {
+        self.players.iter().enumerate()
+            .filter(|(index, _)| self.awaits_move(*index as u32))
+            .map(|(_, player)| player.addr)
+            .collect()
+    }
+
+    /// Whether `player` picked what to do this round
+    pub fn committed(&self, player: u32) -> bool {
+        self.commits.get(player as usize).map_or(false, Option::is_some)
+    }
+
+    /// Has `player` pick what to do this round, replacing what they picked before
+    pub fn commit(&mut self, player: u32, commit: Commit) {
+        self.commits[player as usize] = Some(commit);
+    }
+
+    /// Takes what `player` picked to do this round. None if they haven't picked.
+    pub fn take_commit(&mut self, player: u32) -> Option<Commit> {
+        self.commits.get_mut(player as usize).and_then(Option::take)
+    }
+
+    /// Whether every living player picked what to do this round, so the round can be played out
+    pub fn all_committed(&self) -> bool {
+        self.state.as_ref().map_or(false, |state| {
+            state.all_players_placed() && !state.game_over() &&
+                (0..self.players.len() as u32).all(|player| state.player_state(player).is_none() || self.committed(player))
+        })
+    }
+
+    /// Forgets what everyone picked, for starting the next round
+    pub fn clear_commits(&mut self) {
+        self.commits.iter_mut().for_each(|commit| *commit = None);
+    }
+
+    /// The pending request to take back the last tile placement, unless someone moved since it got asked for
+    fn pending_undo(&self) -> Option<&UndoRequest> {
+        let num_moves = self.replay.as_ref().map_or(0, |replay| replay.moves().len());
+        self.undo_request.as_ref().filter(|request| request.num_moves == num_moves)
+    }
+
+    /// Players who have to accept `player` taking back a tile placement: everyone else who hasn't left
+    fn undo_voters(&self, player: u32) -> impl Iterator<Item = u32> + '_ {
+        (0..self.num_players()).filter(move |voter| *voter != player && !self.players[*voter as usize].left)
+    }
+
+    /// Checks that `player` can ask to take back the last tile placement, and says why not if they can't.
+    /// They have to have placed it, and nobody can have moved since, other than getting their turn skipped.
+    pub fn check_request_undo(&self, player: u32) -> Result<(), RejectReason> {
+        let state = self.state.as_ref().ok_or(RejectReason::NotStarted)?;
+        if state.game_over() {
+            Err(RejectReason::GameOver)
+        } else if self.paused() {
+            Err(RejectReason::Paused)
+        } else if self.options.simultaneous() || !state.can_undo() || self.pending_undo().is_some() ||
+            self.replay.as_ref().and_then(Replay::last_tile_placer) != Some(player)
+        {
+            Err(RejectReason::IllegalMove)
+        } else { Ok(()) }
+    }
+
+    /// Has `player` ask to take back the last tile placement. Assumes they can.
+    pub fn request_undo(&mut self, player: u32) {
+        let num_moves = self.replay.as_ref().map_or(0, |replay| replay.moves().len());
+        self.undo_request = Some(UndoRequest{ player, num_moves, accepted: vec![] });
+    }
+
+    /// Checks that `player` can answer the pending request to take back a tile placement, and says why not if they can't
+    pub fn check_respond_undo(&self, player: u32) -> Result<(), RejectReason> {
+        match self.pending_undo() {
+            _ if self.paused() => Err(RejectReason::Paused),
+            Some(request) if self.undo_voters(request.player).any(|voter| voter == player) => Ok(()),
+            _ => Err(RejectReason::IllegalMove),
+        }
+    }
+
+    /// Has `player` accept the pending request to take back a tile placement. Assumes they can answer it.
+    /// Returns whether everyone who has to accept it has.
+    pub fn accept_undo(&mut self, player: u32) -> bool {
+        if let Some(request) = &mut self.undo_request {
+            request.accepted.push(player);
+        }
+        self.undo_request.as_ref().map_or(false, |request|
+            self.undo_voters(request.player).all(|voter| request.accepted.contains(&voter)))
+    }
+
+    /// Drops the pending request to take back a tile placement, because someone turned it down
+    pub fn decline_undo(&mut self) {
+        self.undo_request = None;
+    }
+
+    /// Takes back the last tile placement, along with the skipped turns after it, once everyone accepted
+    pub fn undo(&mut self) {
+        self.undo_request = None;
+        if let (Some(state), Some(replay)) = (&mut self.state, &mut self.replay) {
+            if state.undo_last_turn() {
+                replay.undo_tile_placement();
+            }
+        }
+    }
+
+    /// Whether the game is paused
+    pub fn paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Checks that `player` can ask to pause the game, or to resume it if `pause` is false, and says why not if they can't
+    pub fn check_vote_pause(&self, player: u32, pause: bool) -> Result<(), RejectReason> {
+        let state = self.state.as_ref().ok_or(RejectReason::NotStarted)?;
+        if state.game_over() {
+            Err(RejectReason::GameOver)
+        } else if self.players[player as usize].left {
+            Err(RejectReason::Eliminated)
+        } else if self.paused() == pause || self.pause_votes.contains(&player) {
+            Err(RejectReason::IllegalMove)
+        } else { Ok(()) }
+    }
+
+    /// Counts `player` asking to pause the game, or to resume it if it's paused. Assumes they can.
+    /// Returns whether that's enough: the host's word is, and otherwise it takes most of the players still in the game.
+    pub fn vote_pause(&mut self, player: u32) -> bool {
+        self.pause_votes.push(player);
+        let staying = self.players.iter().filter(|player| !player.left).count();
+        let votes = self.pause_votes.iter().filter(|voter| !self.players[**voter as usize].left).count();
+        player == 0 || votes * 2 > staying
+    }
+
+    /// Pauses the game at `now`, freezing the turn timer and clocks
+    pub fn pause(&mut self, now: Instant) {
+        self.pause_votes.clear();
+        self.paused_at = Some(now);
+    }
+
+    /// Resumes the game at `now`. The time it spent paused doesn't count towards the turn.
+    pub fn resume(&mut self, now: Instant) {
+        self.pause_votes.clear();
+        if let (Some(paused_at), Some(started)) = (self.paused_at.take(), &mut self.turn_started) {
+            *started += now.duration_since(paused_at);
+        }
+    }
+
     /// Whether the game has started
     pub fn started(&self) -> bool {
         self.state.is_some()
     }
 
+    /// Index of the player at an address. None if they aren't a player in this game.
+    pub fn player_index(&self, addr: SocketAddr) -> Option<u32> {
+        self.players.iter().position(|player| player.addr == addr).map(|index| index as u32)
+    }
+
+    /// Starts timing a new turn, switching the clocks over to whoever's turn it is.
+    /// Nobody's clock runs once the game is over.
+    pub fn restart_turn_timer(&mut self, now: Instant) {
+        let elapsed = self.turn_elapsed(now);
+        let next = self.state.as_ref()
+            .filter(|state| state.all_players_placed() && !state.game_over() && !self.options.simultaneous())
+            .map(|state| state.turn_player());
+        if let (Some(clocks), Some(control)) = (&mut self.clocks, self.options.time_control()) {
+            clocks.switch(control, elapsed, next);
+        }
+        // Time only starts counting once the game is resumed
+        self.turn_started = Some(self.paused_at.unwrap_or(now));
+    }
+
+    /// How much time each player has left at `now`. None if the game hasn't started or has no clocks.
+    pub fn clocks(&self, now: Instant) -> Option<Clocks> {
+        self.clocks.as_ref().map(|clocks| clocks.after(self.turn_elapsed(now)))
+    }
+
+    /// The player whose clock ran out by `now`, if any
+    pub fn clock_ran_out(&self, now: Instant) -> Option<u32> {
+        self.clocks.as_ref()
+            .filter(|_| self.state.as_ref().map_or(false, |state| !state.game_over()))
+            .and_then(|clocks| clocks.ran_out(self.turn_elapsed(now)))
+    }
+
+    /// How long the current turn has taken by `now`, not counting time spent paused
+    fn turn_elapsed(&self, now: Instant) -> Duration {
+        let now = self.paused_at.unwrap_or(now);
+        self.turn_started.map_or(Duration::ZERO, |started| now.duration_since(started))
+    }
+
+    /// Whether the current turn took longer than the game's turn time limit
+    pub fn turn_timed_out(&self, now: Instant) -> bool {
+        match (self.options.turn_time_limit(), &self.state) {
+            (Some(limit), Some(state)) if self.turn_started.is_some() =>
+                state.all_players_placed() && !state.game_over() && self.turn_elapsed(now) >= limit,
+            _ => false,
+        }
+    }
+
     /// Adds a player to the game by address and username, replacing the address
     /// if the username is already in the game. Does not add new players if the game has started.
     /// Returns the player's index if they got added or their address got replaced.
@@ -62,20 +495,103 @@ impl GameInstance {
             player.addr = addr;
             Some(index as u32)
         } else if !self.started() {
-            self.players.push(Player { addr, username });
-            Some(self.players.len() as u32 - 1)
+            let seat = |username: &str| self.seats.iter().position(|seat| seat == username).unwrap_or(self.seats.len());
+            let index = self.players.iter()
+                .position(|player| seat(&player.username) > seat(&username))
+                .unwrap_or(self.players.len());
+            self.players.insert(index, Player::new(addr, username));
+            Some(index as u32)
         } else { None }
     }
 
-    /// Removes a player from the game. Returns whether the player was in the game.
-    /// TODO: If the game has started, kill the player token.
-    pub fn remove_player(&mut self, addr: SocketAddr) -> bool {
-        if !self.started() {
-            if let Some(pos) = self.players.iter().position(|player| player.addr == addr) {
-                self.players.remove(pos);
-                true
-            } else { false }
-        } else { false }
+    /// Checks that `order` lists each player's index exactly once, and says why not if it doesn't
+    pub fn check_reorder_players(&self, order: &[u32]) -> Result<(), RejectReason> {
+        let mut sorted = order.to_vec();
+        sorted.sort_unstable();
+        if sorted.into_iter().eq(0..self.players.len() as u32) {
+            Ok(())
+        } else { Err(RejectReason::IllegalMove) }
+    }
+
+    /// The token color each player picked, in player order
+    pub fn colors(&self) -> Vec<Option<TokenColor>> {
+        self.players.iter().map(Player::color).collect()
+    }
+
+    /// Each player's avatar, in player order
+    pub fn avatars(&self) -> Vec<Option<Avatar>> {
+        self.players.iter().map(Player::avatar).collect()
+    }
+
+    /// Gives `player` avatar `avatar`
+    pub fn set_avatar(&mut self, player: u32, avatar: Option<Avatar>) {
+        self.players[player as usize].avatar = avatar;
+    }
+
+    /// Counts `player` sending an emote at `now`, and returns whether it goes through.
+    /// Emotes sent too fast get dropped.
+    pub fn emote(&mut self, player: u32, now: Instant) -> bool {
+        self.players[player as usize].emotes.check(now) == Verdict::Allow
+    }
+
+    /// Checks that `player` can pick token color `color`, and says why not if they can't.
+    /// Players can pick a color they already have, but not one someone else has.
+    pub fn check_set_color(&self, player: u32, color: TokenColor) -> Result<(), RejectReason> {
+        if self.players.iter().enumerate().any(|(index, other)| index as u32 != player && other.color == Some(color)) {
+            Err(RejectReason::ColorTaken)
+        } else { Ok(()) }
+    }
+
+    /// Gives `player` token color `color`
+    pub fn set_color(&mut self, player: u32, color: TokenColor) {
+        self.players[player as usize].color = Some(color);
+    }
+
+    /// Seats the players in `order`, which lists their indexes before the change.
+    /// Players who join later sit after them.
+    pub fn reorder_players(&mut self, order: &[u32]) {
+        self.players = order.iter().map(|index| self.players[*index as usize].clone()).collect();
+        self.seats = self.players.iter().map(|player| player.username.clone()).collect();
+    }
+
+    /// Removes a player from the game. If the game has started, the player stays in the list
+    /// so indexes don't shift, but their token gets killed instead.
+    /// Returns the player's index and the tiles that got redistributed in (player, index, tile) format
+    /// if they were in the game and something changed.
+    pub fn remove_player(&mut self, addr: SocketAddr) -> Option<(u32, Vec<DrawnTile>)> {
+        let pos = self.players.iter().position(|player| player.addr == addr)?;
+
+        let drawn = if let Some(state) = &mut self.state {
+            if state.game_over() || state.player_state(pos as u32).is_none() {
+                return None;
+            }
+            self.players[pos].left = true;
+            self.commits[pos] = None;
+            state.remove_player(&self.game, pos as u32)
+        } else {
+            self.players.remove(pos);
+            vec![]
+        };
+        Some((pos as u32, drawn))
+    }
+
+    /// Marks a player as disconnected if the game is in progress and they're still alive,
+    /// so they get a chance to come back instead of dying right away.
+    /// Returns the player's index if their status changed.
+    pub fn disconnect_player(&mut self, addr: SocketAddr, now: Instant) -> Option<u32> {
+        let pos = self.players.iter().position(|player| player.addr == addr && player.connected())?;
+
+        let state = self.state.as_ref()?;
+        if state.game_over() || state.player_state(pos as u32).is_none() {
+            return None;
+        }
+        self.players[pos].disconnected_since = Some(now);
+        Some(pos as u32)
+    }
+
+    /// Marks a player as connected. Returns whether they were disconnected before.
+    pub fn reconnect_player(&mut self, player: u32) -> bool {
+        self.players[player as usize].disconnected_since.take().is_some()
     }
 
     /// Adds a spectator to the game by address and username, replacing the address if the
@@ -86,24 +602,155 @@ impl GameInstance {
         {
             spectator.addr = addr;
         } else {
-            self.spectators.push(Player { addr, username })
+            self.spectators.push(Player::new(addr, username))
         }
     }
 
-    /// Removes a spectator from the game. Does nothing if they weren't in the game.
-    pub fn remove_spectator(&mut self, addr: SocketAddr) {
+    /// Removes a spectator from the game, and returns whether they were in the game.
+    pub fn remove_spectator(&mut self, addr: SocketAddr) -> bool {
         if let Some(pos) = self.spectators.iter().position(|player| player.addr == addr) {
             self.spectators.remove(pos);
+            self.delayed.retain(|(_, to, _)| *to != addr);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Holds back a response to a spectator if the game has a spectator delay.
+    /// Returns the response if it should be sent right away instead.
+    pub fn delay_for_spectator(&mut self, addr: SocketAddr, resp: Response, now: Instant) -> Option<(SocketAddr, Response)> {
+        match self.options.spectator_delay() {
+            Some(delay) if self.spectators.iter().any(|spectator| spectator.addr == addr) => {
+                self.delayed.push_back((now + delay, addr, resp));
+                None
+            }
+            _ => Some((addr, resp)),
+        }
+    }
+
+    /// Takes the responses held back from spectators whose delay has passed by `now`.
+    /// Ones to peers that stopped spectating since get dropped.
+    pub fn release_delayed(&mut self, now: Instant) -> Vec<(SocketAddr, Response)> {
+        let mut released = vec![];
+        while matches!(self.delayed.front(), Some((due, _, _)) if *due <= now) {
+            let (_, addr, resp) = self.delayed.pop_front().unwrap(); // just checked
+            if self.spectators.iter().any(|spectator| spectator.addr == addr) {
+                released.push((addr, resp));
+            }
         }
+        released
+    }
+
+    /// Whether some responses are still being held back from spectators
+    pub fn has_delayed(&self) -> bool {
+        !self.delayed.is_empty()
     }
 
     pub fn num_players(&self) -> u32 {
         self.players.len() as u32
     }
 
+    /// A fresh state for round `round` of the game. Games with a seed in their options shuffle each round's draw piles
+    /// with a seed following from it, so the whole match can be played again while rounds still differ.
+    fn new_state(&self, round: u32) -> BaseGameState {
+        let num_players = self.players.len() as u32;
+        match self.options.seed() {
+            Some(seed) => self.game.new_state_seeded(num_players, seed.wrapping_add(round as u64 - 1)),
+            None => self.game.new_state(num_players),
+        }
+    }
+
     /// Start the game. Adding players is not allowed afterward.
-    pub fn start(&mut self) {
-        self.state = Some(self.game.new_state(self.players.len() as u32));
+    pub fn start(&mut self, now: Instant) {
+        let state = self.new_state(1);
+        let usernames = self.players.iter().map(|player| player.username.clone()).collect();
+        self.replay = Some(Replay::new(self.game.clone(), usernames, state.clone()));
+        self.match_score = Some(MatchScore::new(self.options.rounds(), self.players.len() as u32));
+        self.commits = vec![None; self.players.len()];
+        self.clocks = self.options.time_control().map(|control| Clocks::new(control, self.players.len() as u32));
+        self.state = Some(state);
+        self.started_at = Some(now);
+    }
+
+    /// Counts the round that just ended towards the match, and returns whether the match is decided.
+    /// It's also decided if fewer than 2 players are still in it.
+    pub fn end_round(&mut self) -> bool {
+        if let (Some(state), Some(score)) = (&self.state, &mut self.match_score) {
+            let winners = (0..self.players.len() as u32).filter(|player| state.won(*player)).collect::<Vec<_>>();
+            score.record_round(&winners);
+            score.decided() || self.players.iter().filter(|player| !player.left).count() < 2
+        } else { true }
+    }
+
+    /// Starts the next round of the match on a fresh board, with a replay of its own.
+    /// Players who left stay out of it.
+    pub fn start_next_round(&mut self) {
+        let round = self.match_score.as_ref().map_or(1, |score| score.round() + 1);
+        let mut state = self.new_state(round);
+        for (index, player) in self.players.iter().enumerate() {
+            if player.left {
+                state.remove_player(&self.game, index as u32);
+            }
+        }
+        let usernames = self.players.iter().map(|player| player.username.clone()).collect();
+        self.replay = Some(Replay::new(self.game.clone(), usernames, state.clone()));
+        if let Some(score) = &mut self.match_score {
+            score.next_round();
+        }
+        self.clear_commits();
+        self.undo_request = None;
+        // Clients start the round fresh, so it starts unpaused
+        self.paused_at = None;
+        self.pause_votes.clear();
+        self.state = Some(state);
+        self.turn_started = None;
+        self.clocks = self.options.time_control().map(|control| Clocks::new(control, self.players.len() as u32));
+    }
+
+    /// Checks that the game's players can ask for a rematch at `now`, and says why not if they can't.
+    /// The game has to have ended less than the rematch window ago, and daily challenges can only be played once.
+    pub fn check_rematch(&self, now: Instant) -> Result<(), RejectReason> {
+        match self.ended {
+            _ if self.daily.is_some() => Err(RejectReason::IllegalMove),
+            None => Err(RejectReason::IllegalMove),
+            Some(ended) if now.saturating_duration_since(ended) > REMATCH_WINDOW => Err(RejectReason::RematchClosed),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Usernames of the players in the order they sit in the rematch.
+    /// Everyone moves one seat over, so someone else goes first.
+    pub fn rematch_seats(&self) -> Vec<String> {
+        let mut seats = self.players.iter().map(|player| player.username.clone()).collect::<Vec<_>>();
+        seats.rotate_left(1);
+        seats
+    }
+
+    /// Records that the game ended at `now`
+    pub fn end(&mut self, now: Instant) {
+        self.ended = Some(now);
+    }
+
+    /// Sums up how the game went, with its replay kept under `replay_id`. None if it hasn't ended.
+    pub fn summary(&self, replay_id: ReplayId) -> Option<GameSummary> {
+        let (score, replay, started, ended) = (self.match_score.as_ref()?, self.replay.as_ref()?, self.started_at?, self.ended?);
+        // Players who left are still in the replay, at the same index as in the state
+        let players = replay.usernames().clone();
+        // Whoever won the most rounds won the match
+        let leaders = score.leaders();
+        let winners = players.iter().enumerate()
+            .filter(|(player, _)| leaders.contains(&(*player as u32)))
+            .map(|(_, username)| username.clone())
+            .collect();
+        Some(GameSummary::new(players, winners, replay.moves().len() as u32, ended.saturating_duration_since(started), replay_id))
+    }
+
+    /// Adds a move to the game's replay. Does nothing if the game hasn't started.
+    pub fn record(&mut self, mv: ReplayMove) {
+        if let Some(replay) = &mut self.replay {
+            replay.push(mv);
+        }
     }
 
     /// Gets the state mutably
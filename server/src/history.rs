@@ -0,0 +1,62 @@
+//! Results of the games each player finished, kept by username.
+//! Like replays, they're kept in memory only, so they're gone when the server restarts.
+
+use std::collections::{HashMap, VecDeque};
+
+use common::history::GameSummary;
+
+/// Most results kept for each username. The oldest ones get dropped to make room for new ones.
+pub const MAX_RESULTS_PER_USER: usize = 20;
+/// Most usernames results get kept for.
+/// The username that finished a game longest ago gets dropped to make room for a new one.
+pub const MAX_USERS: usize = 4096;
+
+#[derive(Debug, Default)]
+struct UserHistory {
+    /// Newest first
+    results: VecDeque<GameSummary>,
+    /// When the username last got a result, counted in results recorded
+    last_recorded: u64,
+}
+
+/// Results of finished games by username
+#[derive(Debug, Default)]
+pub struct History {
+    users: HashMap<String, UserHistory>,
+    num_recorded: u64,
+}
+
+impl History {
+    /// Adds the result of a finished game to the history of each of `usernames`
+    pub fn record<'a>(&mut self, summary: &GameSummary, usernames: impl IntoIterator<Item = &'a String>) {
+        self.num_recorded += 1;
+        for username in usernames {
+            if !self.users.contains_key(username) && self.users.len() >= MAX_USERS {
+                self.drop_stalest_user();
+            }
+            let user = self.users.entry(username.clone()).or_default();
+            user.results.push_front(summary.clone());
+            user.results.truncate(MAX_RESULTS_PER_USER);
+            user.last_recorded = self.num_recorded;
+        }
+    }
+
+    fn drop_stalest_user(&mut self) {
+        let stalest = self.users.iter()
+            .min_by_key(|(_, user)| user.last_recorded)
+            .map(|(username, _)| username.clone());
+        if let Some(username) = stalest {
+            self.users.remove(&username);
+        }
+    }
+
+    /// Results of the games a username finished most recently, newest first
+    pub fn recent(&self, username: &str) -> Vec<GameSummary> {
+        self.users.get(username).map_or(vec![], |user| user.results.iter().cloned().collect())
+    }
+
+    /// Number of usernames with results kept
+    pub fn num_users(&self) -> usize {
+        self.users.len()
+    }
+}
@@ -0,0 +1,193 @@
+//! Serving HTTP on the same port as the game, so deploying takes a single binary.
+//! Requests to upgrade `/ws` to a WebSocket get handed over to the game, requests under `/api/` go to the JSON API,
+//! `/metrics` gets scraped by Prometheus, and other requests get files from the static directory if there is one.
+//! Each connection gets one response, so there's no keep-alive to keep track of.
+
+use std::{io, path::{Component, Path, PathBuf}, pin::Pin, task::{Context, Poll}};
+
+use async_std::sync::Mutex;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{api, metrics, state::State};
+
+/// Path the client connects to for playing
+pub const WS_PATH: &str = "/ws";
+/// Longest request head read before giving up on the request
+const MAX_HEAD_LEN: usize = 8192;
+/// File served for paths that name a directory
+const INDEX_FILE: &str = "index.html";
+
+/// The parts of an HTTP request that matter for routing it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpRequest {
+    method: String,
+    /// Path without the query
+    path: String,
+    /// Headers, with lowercase names
+    headers: Vec<(String, String)>,
+}
+
+impl HttpRequest {
+    /// Parses a request head, up to but not including the empty line. None if it isn't one.
+    pub fn parse(head: &str) -> Option<Self> {
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next()?.split(' ');
+        let method = request_line.next()?.to_owned();
+        let target = request_line.next()?;
+        request_line.next().filter(|version| version.starts_with("HTTP/"))?;
+        let path = target.split('?').next().unwrap_or_default().to_owned();
+        let headers = lines
+            .map(|line| line.split_once(':').map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_owned())))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { method, path, headers })
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The value of a header, if the request has it. `name` is lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the request asks to become a WebSocket connection
+    pub fn is_websocket_upgrade(&self) -> bool {
+        self.header("upgrade").map_or(false, |upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+    }
+}
+
+/// A stream that some bytes were already read from, which get read again before the rest of the stream
+#[derive(Debug)]
+pub struct Rewound<S> {
+    read: Vec<u8>,
+    pos: usize,
+    inner: S,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Rewound<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if self.pos < self.read.len() {
+            let len = buf.len().min(self.read.len() - self.pos);
+            buf[..len].copy_from_slice(&self.read[self.pos..self.pos + len]);
+            self.pos += len;
+            Poll::Ready(Ok(len))
+        } else {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Rewound<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Reads the head of the request on a stream.
+/// Returns the request along with the stream rewound to its start, so the head can be read again.
+pub async fn read_request<S: AsyncRead + Unpin>(mut stream: S) -> io::Result<(HttpRequest, Rewound<S>)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+    let mut read = vec![];
+    let mut buf = [0; 1024];
+    let head_len = loop {
+        if let Some(i) = read.windows(4).position(|window| window == b"\r\n\r\n") {
+            break i;
+        }
+        if read.len() > MAX_HEAD_LEN {
+            return Err(invalid("Request head is too long"));
+        }
+        let len = stream.read(&mut buf).await?;
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        read.extend_from_slice(&buf[..len]);
+    };
+    let request = std::str::from_utf8(&read[..head_len]).ok()
+        .and_then(HttpRequest::parse)
+        .ok_or_else(|| invalid("Invalid request head"))?;
+    Ok((request, Rewound { read, pos: 0, inner: stream }))
+}
+
+/// The file in `static_dir` that a request path names. None if the path tries to leave the directory.
+pub fn resolve_path(static_dir: &Path, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) || path.contains('\\') {
+        return None;
+    }
+    let path = static_dir.join(relative);
+    Some(if path.is_dir() { path.join(INDEX_FILE) } else { path })
+}
+
+/// The content type to serve a file with, going by its extension
+pub fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("wasm") => "application/wasm",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Writes a whole response and closes the stream
+pub async fn respond<S: AsyncWrite + Unpin>(stream: &mut S, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.close().await
+}
+
+/// Responds to a request that isn't for a game, from the API, with metrics, or with a file in `static_dir`
+pub async fn serve<S: AsyncWrite + Unpin>(stream: &mut S, request: &HttpRequest, state: &Mutex<State>, static_dir: Option<&Path>)
+    -> io::Result<()>
+{
+    if request.method() != "GET" {
+        return respond(stream, "405 Method Not Allowed", "text/plain", b"Method not allowed").await;
+    }
+    if request.path().starts_with(api::API_PATH) {
+        let json = api::get(request.path(), &*state.lock().await);
+        match json {
+            Some(json) => respond(stream, "200 OK", "application/json", json.as_bytes()).await,
+            None => respond(stream, "404 Not Found", "text/plain", b"Not found").await,
+        }
+    } else if request.path() == metrics::METRICS_PATH {
+        let text = metrics::render(&*state.lock().await);
+        respond(stream, "200 OK", metrics::METRICS_CONTENT_TYPE, text.as_bytes()).await
+    } else if let Some(static_dir) = static_dir {
+        serve_file(stream, request, static_dir).await
+    } else {
+        respond(stream, "404 Not Found", "text/plain", b"Not found").await
+    }
+}
+
+/// Responds to a request for a file in `static_dir`. The caller checks that it's a GET request.
+pub async fn serve_file<S: AsyncWrite + Unpin>(stream: &mut S, request: &HttpRequest, static_dir: &Path) -> io::Result<()> {
+    let contents = match resolve_path(static_dir, request.path()) {
+        Some(path) => async_std::fs::read(&path).await.ok().map(|contents| (path, contents)),
+        None => None,
+    };
+    match contents {
+        Some((path, contents)) => respond(stream, "200 OK", content_type(&path), &contents).await,
+        None => respond(stream, "404 Not Found", "text/plain", b"Not found").await,
+    }
+}
@@ -0,0 +1,271 @@
+//! Crash-safe journaling of games.
+//! Each game gets a journal file that starts with a snapshot of the game and goes on with every request
+//! that changes the game in progress, written before the request gets applied.
+//! Taking a snapshot replaces the whole file, so the journal only ever holds requests newer than its snapshot.
+//! On startup, games get restored by loading their snapshots and replaying the requests after them.
+//!
+//! Entries are bincode, which stores enum variants by index, and journals outlive the server that wrote them.
+//! New variants of `ElementaryRequest` and of anything in a `GameSnapshot` go at the end of their enums.
+//! Any other change to what gets journaled needs `JOURNAL_VERSION` bumped, so older journals get skipped instead of misread.
+
+use std::{fs::{self, File, OpenOptions}, io::{self, Write}, net::SocketAddr, path::{Path, PathBuf}, str::FromStr, time::Instant};
+
+use common::game::GameId;
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{game::{GameInstance, GameSnapshot}, processor::{process_elementary_requests, ElementaryRequest}, state::State};
+
+/// Extension of journal files
+const JOURNAL_EXTENSION: &str = "journal";
+
+/// Start of every journal file, before the format version
+const JOURNAL_MAGIC: &[u8; 8] = b"TSJOURNL";
+
+/// Version of the journal format, written after the magic
+const JOURNAL_VERSION: u32 = 1;
+
+/// When journal entries get synced to disk
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsyncPolicy {
+    /// Sync after every entry. Nothing gets lost, but every move waits on the disk.
+    #[default]
+    Always,
+    /// Leave syncing to the OS. A crash of the machine can lose the last few entries.
+    Never,
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err("expected always or never".to_owned()),
+        }
+    }
+}
+
+// There's only one snapshot per journal, so its size doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum JournalEntry {
+    /// The whole game. Always the first entry.
+    Snapshot(GameSnapshot),
+    /// A request from a player, by username
+    Request{ username: String, req: ElementaryRequest },
+}
+
+/// Where journals get written, and how
+#[derive(Debug)]
+pub struct Journal {
+    dir: PathBuf,
+    fsync: FsyncPolicy,
+}
+
+impl Journal {
+    /// Creates a journal in a directory, creating the directory if it doesn't exist
+    pub fn new(dir: PathBuf, fsync: FsyncPolicy) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, fsync })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, id: GameId) -> PathBuf {
+        self.dir.join(format!("game-{}.{}", id, JOURNAL_EXTENSION))
+    }
+
+    /// Replaces a game's journal with a snapshot of the game
+    pub fn snapshot(&self, game: &GameInstance) -> io::Result<()> {
+        let entry = JournalEntry::Snapshot(game.snapshot());
+
+        // Write to the side first so a crash can't leave the game without a snapshot
+        let path = self.path(game.id());
+        let tmp_path = path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(JOURNAL_MAGIC)?;
+        file.write_all(&JOURNAL_VERSION.to_le_bytes())?;
+        write_entry(&mut file, &entry)?;
+        if self.fsync == FsyncPolicy::Always {
+            file.sync_all()?;
+        }
+        fs::rename(tmp_path, path)
+    }
+
+    /// Deletes a game's journal, so the game doesn't get restored
+    pub fn remove(&self, id: GameId) -> io::Result<()> {
+        match fs::remove_file(self.path(id)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Appends a request to a game's journal
+    pub fn append(&self, id: GameId, username: String, req: ElementaryRequest) -> io::Result<()> {
+        let mut file = OpenOptions::new().append(true).open(self.path(id))?;
+        write_entry(&mut file, &JournalEntry::Request{ username, req })?;
+        if self.fsync == FsyncPolicy::Always {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes an entry with its length in front, so an entry cut off by a crash can be told apart
+fn write_entry(file: &mut File, entry: &JournalEntry) -> io::Result<()> {
+    let bytes = bincode::serialize(entry).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let mut buf = (bytes.len() as u64).to_le_bytes().to_vec();
+    buf.extend(bytes);
+    file.write_all(&buf)
+}
+
+/// Reads the entries of a journal up to the end, or up to the first entry that didn't get fully written.
+/// None if the journal isn't in this version's format.
+fn read_entries(path: &Path) -> io::Result<Option<Vec<JournalEntry>>> {
+    let bytes = fs::read(path)?;
+    let header_len = JOURNAL_MAGIC.len() + 4;
+    if bytes.len() < header_len || bytes[..JOURNAL_MAGIC.len()] != JOURNAL_MAGIC[..] ||
+        bytes[JOURNAL_MAGIC.len()..header_len] != JOURNAL_VERSION.to_le_bytes()
+    {
+        return Ok(None);
+    }
+    let mut rest = &bytes[header_len..];
+    let mut entries = vec![];
+
+    while rest.len() >= 8 {
+        let (len, after_len) = rest.split_at(8);
+        let len = u64::from_le_bytes(len.try_into().unwrap()) as usize;
+        if after_len.len() < len {
+            warn!("Journal {:?} ends in a partial entry", path);
+            break;
+        }
+        match bincode::deserialize(&after_len[..len]) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => {
+                warn!("Journal {:?} has a corrupt entry: {}", path, err);
+                break;
+            }
+        }
+        rest = &after_len[len..];
+    }
+    Ok(Some(entries))
+}
+
+/// The game a request changes, if the request should be journaled when the game is in progress.
+/// Requests that only expand into other requests don't count, since those get journaled themselves.
+fn journaled_game(req: &ElementaryRequest) -> Option<GameId> {
+    match req {
+        ElementaryRequest::PlaceToken{ id, .. } |
+        ElementaryRequest::PlaceTile{ id, .. } |
+        ElementaryRequest::CommitTile{ id, .. } |
+        ElementaryRequest::ExchangeHand{ id, .. } |
+        ElementaryRequest::Scout{ id, .. } |
+        ElementaryRequest::SkipTurn{ id, .. } |
+        ElementaryRequest::RequestUndo{ id } |
+        ElementaryRequest::RespondUndo{ id, .. } |
+        ElementaryRequest::PauseGame{ id } |
+        ElementaryRequest::ResumeGame{ id } |
+        ElementaryRequest::LeaveGame{ id } => Some(*id),
+        _ => None,
+    }
+}
+
+/// Appends a request to the journal of the game it changes if journaling is on
+/// and the requester is playing that game. Failures only get logged, since the game can go on without its journal.
+pub(crate) fn journal_request(state: &State, req: &ElementaryRequest, requester: SocketAddr) {
+    if let (Some(journal), Some(id)) = (state.journal(), journaled_game(req)) {
+        let username = state.game(id)
+            .filter(|game| game.started())
+            .and_then(|game| game.player_index(requester).map(|index| game.players()[index as usize].username().clone()));
+        if let Some(username) = username {
+            if let Err(err) = journal.append(id, username, req.clone()) {
+                error!("Failed to journal {:?} for game {:?}: {}", req, id, err);
+            }
+        }
+    }
+}
+
+/// Replaces the journal of a game with a snapshot of it if journaling is on
+pub(crate) fn journal_snapshot(state: &State, id: GameId) {
+    if let (Some(journal), Some(game)) = (state.journal(), state.game(id)) {
+        if let Err(err) = journal.snapshot(game) {
+            error!("Failed to snapshot game {:?}: {}", id, err);
+        }
+    }
+}
+
+/// Deletes the journal of a game that's going away if journaling is on
+pub(crate) fn journal_removal(state: &State, id: GameId) {
+    if let Some(journal) = state.journal() {
+        if let Err(err) = journal.remove(id) {
+            error!("Failed to delete the journal of game {:?}: {}", id, err);
+        }
+    }
+}
+
+/// Restores every game journaled in a directory into the state by replaying its journal.
+/// Players of restored games count as disconnected until they join again under the same username,
+/// and bots come back as players that never reconnect.
+/// Journaling should be off while restoring, so the replayed requests don't get journaled again.
+/// Each restored game then gets a fresh snapshot, so new entries don't end up after a partial one and get lost.
+/// Returns the number of games restored.
+pub(crate) fn restore(journal: &Journal, state: &mut State) -> io::Result<usize> {
+    let mut paths = fs::read_dir(journal.dir())?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().map_or(false, |ext| ext == JOURNAL_EXTENSION));
+    paths.sort();
+
+    let now = Instant::now();
+    let mut num_games = 0;
+    for path in paths {
+        let mut entries = match read_entries(&path)? {
+            Some(entries) => entries.into_iter(),
+            None => {
+                warn!("Journal {:?} isn't in journal format version {}, skipping it", path, JOURNAL_VERSION);
+                continue;
+            }
+        };
+        let id = match entries.next() {
+            Some(JournalEntry::Snapshot(snapshot)) => match state.restore_game(snapshot, now) {
+                Some(id) => id,
+                None => {
+                    warn!("Journal {:?} is of a game that's already here, skipping it", path);
+                    continue;
+                }
+            },
+            _ => {
+                warn!("Journal {:?} doesn't start with a snapshot, skipping it", path);
+                continue;
+            }
+        };
+
+        for entry in entries {
+            if let JournalEntry::Request{ username, req } = entry {
+                let addr = state.game(id)
+                    .and_then(|game| game.players().iter().find(|player| *player.username() == username))
+                    .map(|player| player.addr());
+                match addr {
+                    Some(addr) => { process_elementary_requests(vec![req], addr, state); }
+                    None => warn!("Journal {:?} has a request from {}, who isn't in the game", path, username),
+                }
+            }
+        }
+
+        let result = match state.game(id) {
+            Some(game) => journal.snapshot(game),
+            // The game ended during the replay
+            None => journal.remove(id),
+        };
+        if let Err(err) = result {
+            error!("Failed to snapshot restored game {:?}: {}", id, err);
+        }
+        num_games += 1;
+    }
+    Ok(num_games)
+}
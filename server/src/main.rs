@@ -1,91 +1,201 @@
+pub mod announce;
+pub mod api;
+pub mod bot;
+pub mod config;
+pub mod daily;
+pub mod filter;
+pub mod history;
 pub mod processor;
+pub mod queue;
+pub mod rate_limit;
 pub mod game;
+pub mod http;
+pub mod journal;
+pub mod metrics;
+pub mod peer;
 pub mod state;
+pub mod tls;
+#[cfg(test)]
+mod tests;
 
-use std::{sync::Arc};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
-use async_std::{net::{SocketAddr, TcpListener, TcpStream}, sync::Mutex};
-use async_tungstenite::{accept_async, tungstenite::{Error, Message, Result}};
-use common::{message::{Request}};
+use async_std::{net::{SocketAddr, TcpListener}, sync::Mutex};
+use async_tungstenite::{accept_async_with_config, tungstenite::{Error, protocol::WebSocketConfig}};
 
-use futures::{StreamExt, future::{self, Either}, pin_mut, prelude::*};
-use futures::channel::mpsc::{self};
+use futures::{AsyncRead, AsyncWrite};
 use log::*;
 
-use crate::{processor::{respond_to_request}, state::State};
+use crate::{config::ServerConfig, journal::Journal, processor::{respond_to_cleanup, respond_to_timeouts}, state::State};
+
+/// Number of bots to run in demo mode
+const DEMO_NUM_BOTS: usize = 3;
+/// How often to check for players that took too long to reconnect
+const TIMEOUT_CHECK_PERIOD: Duration = Duration::from_secs(1);
+/// How often to clean up games and peers that went idle
+const CLEANUP_PERIOD: Duration = Duration::from_secs(60);
+
+/// Handles a connection over a TCP stream, or a TLS stream on top of one.
+/// The connection is a game if it asks for a WebSocket, and an HTTP request otherwise.
+/// If there's a static directory, games only get taken on `/ws`.
+async fn accept_connection<S>(peer: SocketAddr, stream: S, state: Arc<Mutex<State>>, static_dir: Option<Arc<PathBuf>>)
+    where S: AsyncRead + AsyncWrite + Unpin
+{
+    let result = match http::read_request(stream).await {
+        Ok((request, stream))
+            if request.is_websocket_upgrade() && (static_dir.is_none() || request.path() == http::WS_PATH) =>
+            return play(peer, stream, state).await,
+        Ok((request, mut stream)) => http::serve(&mut stream, &request, &state, static_dir.as_deref().map(PathBuf::as_path)).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        info!("Failed to serve {}: {}", peer, err);
+    }
+}
 
-async fn accept_connection(peer: SocketAddr, stream: TcpStream, state: Arc<Mutex<State>>) {
-    if let Err(e) = handle_connection(peer, stream, Arc::clone(&state)).await {
+/// Plays games over a connection until it closes
+async fn play<S>(peer: SocketAddr, stream: S, state: Arc<Mutex<State>>)
+    where S: AsyncRead + AsyncWrite + Unpin
+{
+    // Messages too big to be requests close the connection before they get buffered
+    let config = WebSocketConfig {
+        max_message_size: Some(rate_limit::MAX_MESSAGE_SIZE),
+        max_frame_size: Some(rate_limit::MAX_MESSAGE_SIZE),
+        ..WebSocketConfig::default()
+    };
+    let ws_stream = match accept_async_with_config(stream, Some(config)).await {
+        Ok(ws_stream) => ws_stream,
+        Err(err) => {
+            warn!("Failed to accept {}: {}", peer, err);
+            return;
+        }
+    };
+    info!("New web socket connection: {}", peer);
+    if let Err(e) = peer::serve_peer(peer, ws_stream, state).await {
         match e {
             Error::ConnectionClosed | Error::Protocol(_) | Error::Utf8 => {}
+            Error::Capacity(err) => warn!("{} sent too much at once, so it got disconnected: {}", peer, err),
             error => error!("Error processing connection: {}", error),
         }
     }
 }
 
-async fn handle_connection(peer: SocketAddr, stream: TcpStream, state: Arc<Mutex<State>>) -> Result<()> {
-    let ws_stream = accept_async(stream).await.unwrap_or_else(|_| panic!("Failed to accept {}", peer));
-    info!("New web socket connection: {}", peer);
-    let (mut sink, mut stream) = ws_stream.split();
-
-    let (tx, mut rx) = mpsc::unbounded();
-    {
-        let mut state = state.lock().await;
-        state.add_peer(peer, tx);
+async fn run() {
+    // The logger's level comes from the config, so config errors can't be logged
+    let config = match ServerConfig::from_env_and_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    env_logger::builder().filter_level(config.log_level()).parse_default_env().init();
+
+    let mut state = State::new();
+    state.set_disconnect_grace_period(config.disconnect_grace_period().get());
+    info!("Disconnect grace period is {}", config.disconnect_grace_period());
+    state.set_idle_timeout(config.idle_timeout().get());
+    info!("Idle games and peers get cleaned up after {}", config.idle_timeout());
+    state.set_name(config.name().clone());
+    state.set_region(config.region().clone());
+    state.set_max_games(config.max_games());
+    state.set_turn_time_limit(config.turn_time_limit());
+    if !config.word_filter().is_empty() {
+        info!("Filtering {:?} out of usernames", config.word_filter());
     }
-    info!("Starting game with {}", peer);
-
-    let stream_loop = async {
-        while let Some(msg) = stream.next().await {
-            let msg = msg?;
-            if let Message::Binary(msg) = msg {
-                match bincode::deserialize::<Request>(&msg) {
-                    Ok(req) => respond_to_request(req, peer, &state).await,
-                    Err(err) => error!("Invalid request from {}: {:?}", peer, err),
+    state.set_word_filter(config.word_filter().clone());
+    if let Some(limit) = config.turn_time_limit() {
+        info!("Every game has a turn time limit of {}", limit);
+    }
+    if let Some(dir) = config.journal_dir() {
+        let fsync = config.journal_fsync();
+        match Journal::new(dir.clone(), fsync) {
+            Ok(journal) => {
+                match journal::restore(&journal, &mut state) {
+                    Ok(num_games) => info!("Restored {} games from journals in {:?}", num_games, dir),
+                    Err(err) => error!("Failed to restore games from journals in {:?}: {}", dir, err),
                 }
+                info!("Journaling games in {:?} with fsync policy {:?}", dir, fsync);
+                state.set_journal(Some(journal));
             }
+            Err(err) => error!("Can't journal games in {:?}: {}", dir, err),
         }
+    }
+    if let Some(dir) = config.archive_dir() {
+        info!("Archiving finished games in {:?}", dir);
+        state.set_archive_dir(Some(dir.clone()));
+    }
+    let state = Arc::new(Mutex::new(state));
 
-        Ok(())
+    let tls = match config.tls() {
+        Some((cert, key)) => match tls::acceptor(cert, key) {
+            Ok(acceptor) => {
+                info!("Accepting wss:// connections with the certificate in {:?}", cert);
+                Some(acceptor)
+            }
+            Err(err) => {
+                error!("{}", err);
+                return;
+            }
+        },
+        None => None,
     };
 
-    // Actually sends the responses
-    let receive_loop = async {
-        while let Some(resp) = rx.next().await {
-            match sink.send(bincode::serialize(&resp).unwrap().into()).await {
-                Ok(_) => info!("Sent response to {}: {:?}", peer, resp),
-                Err(err) => error!("Error sending response to {}: {:?}, error: {}", peer, resp, err),
-            }
+    if let Some(url) = config.directory_url() {
+        info!("Announcing to directory at {:?}", url);
+        async_std::task::spawn(announce::run_announcer(url, Arc::clone(&state)));
+    }
+
+    let timeout_state = Arc::clone(&state);
+    async_std::task::spawn(async move {
+        loop {
+            async_std::task::sleep(TIMEOUT_CHECK_PERIOD).await;
+            respond_to_timeouts(&timeout_state).await;
         }
-        Ok(())
-    };
+    });
 
-    pin_mut!(stream_loop, receive_loop);
-    let result = match future::select(stream_loop, receive_loop).await {
-        Either::Left(result) => result.0,
-        Either::Right(result) => result.0,
-    };
-    info!("{} disconnected", peer);
-    state.lock().await.remove_peer(peer);
-    respond_to_request(Request::RemovePeer, peer, &state).await;
-    result
-}
+    let cleanup_state = Arc::clone(&state);
+    async_std::task::spawn(async move {
+        loop {
+            async_std::task::sleep(CLEANUP_PERIOD).await;
+            respond_to_cleanup(&cleanup_state).await;
+        }
+    });
 
-async fn run() {
-    env_logger::builder().filter_level(log::LevelFilter::Debug).parse_default_env().init();
+    if config.demo() {
+        info!("Running in demo mode with {} bots", DEMO_NUM_BOTS);
+        bot::spawn_bots(DEMO_NUM_BOTS, &state);
+    }
 
-    let state = Arc::new(Mutex::new(State::new()));
+    let static_dir = config.static_dir().clone().map(Arc::new);
+    if let Some(dir) = &static_dir {
+        info!("Serving the client from {:?}, with games on {}", dir, http::WS_PATH);
+    }
 
-    info!("Attempting to listen to {}", common::HOST_ADDRESS);
-    let listener = TcpListener::bind(common::HOST_ADDRESS).await
-        .unwrap_or_else(|_| panic!("Can't listen to {}", common::HOST_ADDRESS));
-    info!("Listening on {}", common::HOST_ADDRESS);
+    let address = config.bind_address();
+    info!("Attempting to listen to {}", address);
+    let listener = TcpListener::bind(address).await
+        .unwrap_or_else(|_| panic!("Can't listen to {}", address));
+    info!("Listening on {}", address);
 
     while let Ok((stream, _)) = listener.accept().await {
         let peer = stream.peer_addr().expect("Connected streams should have a peer address");
         info!("Peer address {}", peer);
 
-        async_std::task::spawn(accept_connection(peer, stream, Arc::clone(&state)));
+        let state = Arc::clone(&state);
+        let static_dir = static_dir.clone();
+        match &tls {
+            Some(acceptor) => {
+                let handshake = acceptor.accept(stream);
+                async_std::task::spawn(async move {
+                    match handshake.await {
+                        Ok(stream) => accept_connection(peer, stream, state, static_dir).await,
+                        Err(err) => info!("TLS handshake with {} failed: {}", peer, err),
+                    }
+                });
+            }
+            None => { async_std::task::spawn(accept_connection(peer, stream, state, static_dir)); }
+        }
     }
 }
 
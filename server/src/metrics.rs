@@ -0,0 +1,123 @@
+//! Metrics about the server for Prometheus, which scrapes them from `/metrics` in its text format.
+//! Counters get kept as things happen, and gauges get read off the state when scraped.
+
+use std::{collections::BTreeMap, fmt::Write, time::Duration};
+
+use common::message::{RejectReason, RequestTag, Response};
+
+use crate::state::State;
+
+/// Path Prometheus scrapes
+pub const METRICS_PATH: &str = "/metrics";
+/// Content type of the text format
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+/// Upper bounds of the turn latency histogram's buckets, in seconds
+const TURN_LATENCY_BUCKETS: [f64; 8] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.1, 0.5];
+
+/// Counts of how long something took, bucketed
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// Number of observations at most each bucket's bound, not counting smaller buckets
+    bucket_counts: [u64; TURN_LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, secs: f64) {
+        if let Some(i) = TURN_LATENCY_BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.bucket_counts[i] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+/// Counters kept since the server started
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    /// Game connections accepted
+    connections: u64,
+    /// Requests processed, by type
+    requests: BTreeMap<String, u64>,
+    /// Requests rejected, by reason
+    rejections: BTreeMap<String, u64>,
+    /// How long requests that make a move took to process
+    turn_latency: Histogram,
+}
+
+/// Whether a request is someone making a move
+fn is_turn(tag: RequestTag) -> bool {
+    matches!(tag, RequestTag::PlaceToken | RequestTag::PlaceTile | RequestTag::ExchangeHand | RequestTag::Scout)
+}
+
+/// The name of a reject reason, without its fields
+fn reason_label(reason: RejectReason) -> String {
+    let debug = format!("{:?}", reason);
+    debug.split(|c: char| !c.is_alphanumeric()).next().unwrap_or_default().to_owned()
+}
+
+impl Metrics {
+    /// Counts a game connection
+    pub fn connection_opened(&mut self) {
+        self.connections += 1;
+    }
+
+    /// Counts a processed request, the rejections it got, and how long it took if it made a move
+    pub fn request_processed<'a>(&mut self, tag: RequestTag, responses: impl IntoIterator<Item = &'a Response>, elapsed: Duration) {
+        *self.requests.entry(format!("{:?}", tag)).or_default() += 1;
+        for response in responses {
+            if let Response::Rejected{ reason, .. } | Response::RejectedUsername{ reason } | Response::RejectedGame{ reason } = response {
+                *self.rejections.entry(reason_label(*reason)).or_default() += 1;
+            }
+        }
+        if is_turn(tag) {
+            self.turn_latency.observe(elapsed.as_secs_f64());
+        }
+    }
+}
+
+/// Writes the header of a metric
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Every metric, in Prometheus's text format
+pub fn render(state: &State) -> String {
+    let metrics = state.metrics();
+    let mut out = String::new();
+
+    header(&mut out, "tsurust_peers", "gauge", "Connected peers that picked a username.");
+    writeln!(out, "tsurust_peers {}", state.server_info().num_players()).unwrap();
+
+    header(&mut out, "tsurust_games", "gauge", "Games hosted, by whether they started.");
+    let num_started = state.games().values().filter(|game| game.started()).count();
+    writeln!(out, "tsurust_games{{started=\"true\"}} {}", num_started).unwrap();
+    writeln!(out, "tsurust_games{{started=\"false\"}} {}", state.games().len() - num_started).unwrap();
+
+    header(&mut out, "tsurust_connections_total", "counter", "Game connections accepted.");
+    writeln!(out, "tsurust_connections_total {}", metrics.connections).unwrap();
+
+    header(&mut out, "tsurust_requests_total", "counter", "Requests processed, by type.");
+    for (tag, count) in &metrics.requests {
+        writeln!(out, "tsurust_requests_total{{type=\"{}\"}} {}", tag, count).unwrap();
+    }
+
+    header(&mut out, "tsurust_rejections_total", "counter", "Requests rejected, by reason.");
+    for (reason, count) in &metrics.rejections {
+        writeln!(out, "tsurust_rejections_total{{reason=\"{}\"}} {}", reason, count).unwrap();
+    }
+
+    header(&mut out, "tsurust_turn_latency_seconds", "histogram", "Time taken to process moves.");
+    let mut cumulative = 0;
+    for (bound, count) in TURN_LATENCY_BUCKETS.iter().zip(metrics.turn_latency.bucket_counts) {
+        cumulative += count;
+        writeln!(out, "tsurust_turn_latency_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative).unwrap();
+    }
+    writeln!(out, "tsurust_turn_latency_seconds_bucket{{le=\"+Inf\"}} {}", metrics.turn_latency.count).unwrap();
+    writeln!(out, "tsurust_turn_latency_seconds_sum {}", metrics.turn_latency.sum).unwrap();
+    writeln!(out, "tsurust_turn_latency_seconds_count {}", metrics.turn_latency.count).unwrap();
+
+    out
+}
@@ -0,0 +1,169 @@
+//! Talking to a peer, whatever carries its messages.
+//! Requests come in and responses go out through a `PeerTransport`, so the same loop serves web sockets and,
+//! in tests, in-memory channels.
+
+use std::{fmt::Display, net::SocketAddr, sync::Arc, time::Instant};
+
+use async_std::sync::Mutex;
+use async_tungstenite::{WebSocketStream, tungstenite::{Error, Message}};
+use common::{CONNECTION_TIMEOUT, HEARTBEAT_PERIOD, message::{Request, Response}};
+use futures::{AsyncRead, AsyncWrite, Sink, SinkExt, Stream, StreamExt, future::{self, Either, Ready}, pin_mut, sink::With, stream::{self, Map, SplitSink, SplitStream}};
+use log::*;
+
+use crate::{processor::respond_to_request, queue, rate_limit::{RateLimiter, Verdict}, state::State};
+
+/// Something that came from a peer
+#[derive(Clone, Debug)]
+pub enum FromPeer {
+    Request(Request),
+    /// A message that should have been a request but couldn't be read, with why
+    Invalid(String),
+    /// Anything else, like a pong. It still shows the peer is alive.
+    Other,
+}
+
+/// Something to send to a peer
+// Only one gets sent at a time, so its size doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum ToPeer {
+    Response(Response),
+    /// Checks that the peer is still there
+    Ping,
+}
+
+/// A connection to a peer, which splits into an end to receive from and an end to send to
+pub trait PeerTransport {
+    type Error: Display;
+    type Receiver: Stream<Item = Result<FromPeer, Self::Error>> + Unpin;
+    type Sender: Sink<ToPeer, Error = Self::Error> + Unpin;
+
+    fn split(self) -> (Self::Sender, Self::Receiver);
+}
+
+// The error comes straight from the web socket, which decides its size
+#[allow(clippy::result_large_err)]
+fn from_message(msg: Result<Message, Error>) -> Result<FromPeer, Error> {
+    Ok(match msg? {
+        Message::Binary(msg) => match bincode::deserialize::<Request>(&msg) {
+            Ok(req) => FromPeer::Request(req),
+            Err(err) => FromPeer::Invalid(format!("{:?}", err)),
+        },
+        _ => FromPeer::Other,
+    })
+}
+
+fn to_message(out: ToPeer) -> Ready<Result<Message, Error>> {
+    future::ok(match out {
+        ToPeer::Response(resp) => bincode::serialize(&resp).unwrap().into(),
+        ToPeer::Ping => Message::Ping(vec![]),
+    })
+}
+
+/// Requests and responses go over the web socket as bincode
+impl<S: AsyncRead + AsyncWrite + Unpin> PeerTransport for WebSocketStream<S> {
+    type Error = Error;
+    type Receiver = Map<SplitStream<Self>, fn(Result<Message, Error>) -> Result<FromPeer, Error>>;
+    type Sender = With<SplitSink<Self, Message>, Message, ToPeer, Ready<Result<Message, Error>>, fn(ToPeer) -> Ready<Result<Message, Error>>>;
+
+    fn split(self) -> (Self::Sender, Self::Receiver) {
+        let (sink, stream) = StreamExt::split(self);
+        (sink.with(to_message as fn(_) -> _), stream.map(from_message as fn(_) -> _))
+    }
+}
+
+/// Serves a peer until it disconnects, stops responding, falls too far behind on responses, or keeps sending too fast.
+/// The peer gets added to the state for the duration, and removed along with everything it was in afterwards.
+pub async fn serve_peer<T: PeerTransport>(peer: SocketAddr, transport: T, state: Arc<Mutex<State>>) -> Result<(), T::Error> {
+    let (mut sink, mut stream) = transport.split();
+
+    let (tx, rx) = queue::bounded(queue::MAX_QUEUED_RESPONSES);
+    {
+        let mut state = state.lock().await;
+        state.add_peer(peer, tx);
+        state.metrics_mut().connection_opened();
+    }
+    info!("Starting game with {}", peer);
+
+    // Anything from the peer counts as a sign of life, including pongs
+    let last_heard = Mutex::new(Instant::now());
+
+    let stream_loop = async {
+        let mut limiter = RateLimiter::default();
+        while let Some(msg) = stream.next().await {
+            let msg = msg?;
+            let now = Instant::now();
+            *last_heard.lock().await = now;
+            if matches!(msg, FromPeer::Other) {
+                continue;
+            }
+            match limiter.check(now) {
+                Verdict::Allow => {}
+                Verdict::Drop => {
+                    warn!("{} is sending too fast, dropping {:?}", peer, msg);
+                    continue;
+                }
+                Verdict::Disconnect => {
+                    warn!("{} kept sending too fast, so it's getting disconnected", peer);
+                    break;
+                }
+            }
+            match msg {
+                FromPeer::Request(req) => respond_to_request(req, peer, &state).await,
+                FromPeer::Invalid(err) => error!("Invalid request from {}: {}", peer, err),
+                FromPeer::Other => {}
+            }
+        }
+
+        Ok(())
+    };
+
+    // Actually sends the responses, and pings the peer every so often
+    let receive_loop = async {
+        let heartbeats = stream::unfold((), |()| async {
+            async_std::task::sleep(HEARTBEAT_PERIOD).await;
+            Some(((), ()))
+        });
+        // The queue only ends when the peer falls too far behind or the state drops it
+        let responses = rx.map(Some).chain(stream::once(future::ready(None)));
+        let outgoing = stream::select(responses.map(Either::Left), heartbeats.map(Either::Right));
+        pin_mut!(outgoing);
+
+        while let Some(out) = outgoing.next().await {
+            match out {
+                Either::Left(None) => {
+                    warn!("{} fell too far behind on responses or went idle, so it's getting disconnected", peer);
+                    break;
+                }
+
+                Either::Left(Some(resp)) => {
+                    info!("Sending response to {}: {:?}", peer, resp);
+                    if let Err(err) = sink.send(ToPeer::Response(resp)).await {
+                        error!("Error sending response to {}: {}", peer, err);
+                    }
+                }
+
+                Either::Right(()) => {
+                    if last_heard.lock().await.elapsed() > CONNECTION_TIMEOUT {
+                        info!("{} stopped responding", peer);
+                        break;
+                    }
+                    if let Err(err) = sink.send(ToPeer::Ping).await {
+                        error!("Error pinging {}: {}", peer, err);
+                    }
+                }
+            }
+        }
+        Ok(())
+    };
+
+    pin_mut!(stream_loop, receive_loop);
+    let result = match future::select(stream_loop, receive_loop).await {
+        Either::Left(result) => result.0,
+        Either::Right(result) => result.0,
+    };
+    info!("{} disconnected", peer);
+    state.lock().await.remove_peer(peer);
+    respond_to_request(Request::RemovePeer, peer, &state).await;
+    result
+}
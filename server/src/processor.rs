@@ -1,28 +1,51 @@
-use std::{net::SocketAddr, collections::VecDeque};
+use std::{fs, net::SocketAddr, collections::VecDeque, time::{Instant, SystemTime, UNIX_EPOCH}};
 
 
 use async_std::sync::{Mutex};
-use common::{message::{Request, Response}, player_state::Looker, board::{RectangleBoard, Board, BasePort, BaseTLoc}, game::{PathGame, GameId}, WrapBase, tile::{BaseKind, BaseGAct}};
+use common::{archive::GameArchive, clock::TimeControl, config::{MatchRounds, SpectatorDelay}, daily::{DAILY_BOTS, DAILY_BOT_DIFFICULTY, DailyResult}, message::{RejectReason, Request, Response, SkipReason}, player_state::Looker, board::{BasePort, BaseTLoc}, game::GameId, options::{Avatar, BotDifficulty, Emote, GamePreset, RejectionFeed, TokenColor}, replay::{Replay, ReplayId, ReplayMove}, tile::{BaseKind, BaseGAct}};
 
 use itertools::{Itertools};
 use log::*;
 
-use crate::state::State;
+use serde::{Deserialize, Serialize};
+
+use crate::{bot::{choose_bot_move, choose_start_port}, daily::today, game::{Commit, GameInstance, Player}, journal::{journal_removal, journal_request, journal_snapshot}, state::State};
 
 /// A request for which a simple action is done.
 /// This can generate more `ElementaryRequest`s as well as responses.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ElementaryRequest {
     SetUsername{ username: String },
     JoinLobby,
     /// Elementary only. Does not send a response.
     LeaveLobby,
-    CreateGame,
+    CreateGame{
+        preset: GamePreset, open_hands: bool, hand_exchange: bool, scout: bool, rejection_feed: RejectionFeed, rounds: MatchRounds,
+        simultaneous: bool, time_control: Option<TimeControl>, spectator_delay: Option<SpectatorDelay>,
+    },
     JoinGame{ id: GameId },
-    /// Elementary only. Does not send a response.
+    /// Notifies the players of the game if someone left after it started.
     LeaveGame{ id: GameId },
     /// Elementary only. Does not send a response.
     LeaveGames,
+    /// Elementary only. Marks the requester as disconnected from a game in progress, or has them leave otherwise.
+    DisconnectGame{ id: GameId },
+    /// Elementary only. Does not send a response.
+    DisconnectGames,
+    /// Elementary only. Notifies the players of the game that a player's connection changed.
+    NotifyPlayerConnection{ id: GameId, player: u32, connected: bool },
+    /// Elementary only. Skips the requester's turn if it's theirs,
+    /// or in games where everyone picks at once, has them sit the round out if they haven't picked yet.
+    SkipTurn{ id: GameId, reason: SkipReason },
+    /// Elementary only. Skips the turn player's turn if they have no legal move but someone else does,
+    /// or in games where everyone picks at once, has everyone who hasn't picked and has no legal move sit the round out.
+    SkipStuckTurn{ id: GameId },
+    /// Elementary only. Makes a move for the requester the way a bot of some difficulty would if it's their turn,
+    /// or skips it if they can't move.
+    AutoMove{ id: GameId, difficulty: BotDifficulty },
+    /// Elementary only. Places the requester's token on a free start port.
+    AutoPlaceToken{ id: GameId },
+    AddBot{ id: GameId, difficulty: BotDifficulty },
     /// Elementary only. Notifies the players of the game that the list of players changed.
     NotifyChangePlayers{ id: GameId },
     /// Elementary only. Notifies the lobby that a game changed.
@@ -30,6 +53,34 @@ pub enum ElementaryRequest {
     StartGame{ id: GameId },
     PlaceToken{ id: GameId, player: u32, port: BasePort },
     PlaceTile{ id: GameId, player: u32, kind: BaseKind, index: u32, action: BaseGAct, loc: BaseTLoc },
+    ExchangeHand{ id: GameId, player: u32 },
+    Scout{ id: GameId, player: u32 },
+    Resync{ id: GameId },
+    /// Elementary only. Keeps the replay of a game that just ended and tells its players and spectators about it.
+    SaveReplay{ id: GameId },
+    Ping,
+    GetServerInfo,
+    GetReplay{ replay_id: ReplayId },
+    GetHistory,
+    /// Elementary only. Counts the round of a game that just ended towards its match,
+    /// then starts the next round, or keeps the replay if the match is decided.
+    EndRound{ id: GameId },
+    CommitTile{ id: GameId, player: u32, kind: BaseKind, index: u32, action: BaseGAct, loc: BaseTLoc },
+    /// Elementary only. Plays out what everyone picked this round in turn order, once everyone has picked.
+    ResolveCommits{ id: GameId },
+    PlayDaily,
+    GetDaily,
+    RequestUndo{ id: GameId },
+    RespondUndo{ id: GameId, accept: bool },
+    PauseGame{ id: GameId },
+    ResumeGame{ id: GameId },
+    Rematch{ id: GameId },
+    ReorderPlayers{ id: GameId, order: Vec<u32> },
+    SetColor{ id: GameId, color: TokenColor },
+    SetAvatar{ avatar: Option<Avatar> },
+    Emote{ id: GameId, emote: Emote },
+    /// Elementary only. Notifies the players and spectators of the game that the list of spectators changed.
+    NotifyChangeSpectators{ id: GameId },
 }
 
 impl ElementaryRequest {
@@ -37,105 +88,743 @@ impl ElementaryRequest {
         match req {
             Request::SetUsername{ username } => vec![Self::SetUsername{ username }],
             Request::JoinLobby => vec![Self::LeaveGames, Self::JoinLobby],
-            Request::CreateGame => vec![Self::CreateGame],
+            Request::CreateGame{ preset, open_hands, hand_exchange, scout, rejection_feed, rounds, simultaneous, time_control, spectator_delay } =>
+                vec![Self::CreateGame{ preset, open_hands, hand_exchange, scout, rejection_feed, rounds, simultaneous, time_control, spectator_delay }],
             Request::JoinGame{ id } => vec![Self::LeaveLobby, Self::JoinGame{ id }],
+            Request::LeaveGame{ id } => vec![Self::LeaveGame{ id }, Self::JoinLobby],
+            Request::AddBot{ id, difficulty } => vec![Self::AddBot{ id, difficulty }],
             Request::StartGame{ id } => vec![Self::StartGame{ id }],
             Request::PlaceToken{ id, player, port } => vec![Self::PlaceToken{ id, player, port }],
             Request::PlaceTile{ id, player, kind, index, action, loc } =>
                 vec![Self::PlaceTile{ id, player, kind, index, action, loc }],
-            Request::RemovePeer => vec![Self::LeaveGames, Self::LeaveLobby],
+            Request::ExchangeHand{ id, player } => vec![Self::ExchangeHand{ id, player }],
+            Request::Scout{ id, player } => vec![Self::Scout{ id, player }],
+            Request::Resync{ id } => vec![Self::Resync{ id }],
+            Request::RemovePeer => vec![Self::DisconnectGames, Self::LeaveLobby],
+            Request::Ping => vec![Self::Ping],
+            Request::GetServerInfo => vec![Self::GetServerInfo],
+            Request::GetReplay{ replay_id } => vec![Self::GetReplay{ replay_id }],
+            Request::GetHistory => vec![Self::GetHistory],
+            Request::CommitTile{ id, player, kind, index, action, loc } =>
+                vec![Self::CommitTile{ id, player, kind, index, action, loc }],
+            Request::PlayDaily => vec![Self::PlayDaily],
+            Request::GetDaily => vec![Self::GetDaily],
+            Request::RequestUndo{ id } => vec![Self::RequestUndo{ id }],
+            Request::RespondUndo{ id, accept } => vec![Self::RespondUndo{ id, accept }],
+            Request::PauseGame{ id } => vec![Self::PauseGame{ id }],
+            Request::ResumeGame{ id } => vec![Self::ResumeGame{ id }],
+            Request::Rematch{ id } => vec![Self::Rematch{ id }],
+            Request::ReorderPlayers{ id, order } => vec![Self::ReorderPlayers{ id, order }],
+            Request::SetColor{ id, color } => vec![Self::SetColor{ id, color }],
+            Request::SetAvatar{ avatar } => vec![Self::SetAvatar{ avatar }],
+            Request::Emote{ id, emote } => vec![Self::Emote{ id, emote }],
         }
     }
 }
 
 /// Processes a request, and returns a list of responses to send to peers.
 pub(crate) fn process_request(req: Request, requester: SocketAddr, state: &mut State) -> Vec<(SocketAddr, Response)> {
-    let elem_req = ElementaryRequest::vec_from_request(req);
+    let tag = req.tag();
+    let start = Instant::now();
+    let responses = process_elementary_requests(ElementaryRequest::vec_from_request(req), requester, state);
+    let responses = process_bot_actions(responses, state);
+    let responses = delay_spectator_responses(responses, state, Instant::now());
+    state.metrics_mut().request_processed(tag, responses.iter().map(|(_, resp)| resp), start.elapsed());
+    responses
+}
+
+/// Holds back responses to spectators of games with a spectator delay, so they can't tip players off.
+/// Spectators still hear right away when their own requests get rejected.
+/// Returns the responses to send right away.
+fn delay_spectator_responses(responses: Vec<(SocketAddr, Response)>, state: &mut State, now: Instant) -> Vec<(SocketAddr, Response)> {
+    responses.into_iter()
+        .filter_map(|(addr, resp)| match resp.game_id().filter(|_| !matches!(resp, Response::Rejected{ .. })) {
+            Some(id) => match state.game_mut(id) {
+                Some(game) => game.delay_for_spectator(addr, resp, now),
+                None => Some((addr, resp)),
+            },
+            None => Some((addr, resp)),
+        })
+        .collect()
+}
+
+/// Has bots the processor moves for act on the responses sent to them,
+/// including responses caused by other bots' actions.
+/// Returns the responses along with the ones the bots' actions caused.
+fn process_bot_actions(mut responses: Vec<(SocketAddr, Response)>, state: &mut State) -> Vec<(SocketAddr, Response)> {
+    let mut checked = 0;
+    while checked < responses.len() {
+        let (addr, resp) = &responses[checked];
+        checked += 1;
+
+        if let Some(difficulty) = state.bot_difficulty(*addr) {
+            let addr = *addr;
+            let req = match resp {
+                Response::StartedGame{ id, .. } | Response::StartedRound{ id, .. } => Some(ElementaryRequest::AutoPlaceToken{ id: *id }),
+                Response::YourTurn{ id } => Some(ElementaryRequest::AutoMove{ id: *id, difficulty }),
+                // Bots don't mind
+                Response::UndoRequested{ id, .. } => Some(ElementaryRequest::RespondUndo{ id: *id, accept: true }),
+                // Turns that came up while the game was paused still need playing
+                Response::GameResumed{ id } if state.game(*id).map_or(false, |game|
+                    game.player_index(addr).map_or(false, |player| game.awaits_move(player))) =>
+                    Some(ElementaryRequest::AutoMove{ id: *id, difficulty }),
+                _ => None,
+            };
+            if let Some(req) = req {
+                let new_responses = process_elementary_requests(vec![req], addr, state);
+                responses.extend(new_responses);
+            }
+        }
+    }
+    responses
+}
+
+/// Rejects a move in a game, and tells whoever the game's rejection feed covers that it was tried
+fn reject_move(inst: &GameInstance, requester: SocketAddr, reason: RejectReason) -> Vec<(SocketAddr, Response)> {
+    let id = inst.id();
+    let player = inst.player_index(requester);
+    let watchers = match inst.options().rejection_feed() {
+        RejectionFeed::Off => &[][..],
+        RejectionFeed::Host => &inst.players()[..inst.players().len().min(1)],
+        RejectionFeed::Everyone => &inst.players()[..],
+    };
+    let feed = watchers.iter()
+        .filter(|watcher| watcher.addr() != requester)
+        .map(|watcher| (watcher.addr(), Response::RejectedAttempt{ id, player, reason }));
+    [(requester, Response::Rejected{ id, reason })].into_iter().chain(feed).collect()
+}
+
+/// Places a tile for a player in a game in progress whose move was already checked,
+/// and tells everyone in the game about it. Whoever moves next gets told separately.
+fn place_tile(
+    inst: &mut GameInstance, player: u32, kind: BaseKind, index: u32, action: BaseGAct, loc: BaseTLoc,
+    to_process: &mut VecDeque<ElementaryRequest>,
+) -> Vec<(SocketAddr, Response)> {
+    let id = inst.id();
+    let (game, game_state) = inst.game_and_state_mut();
+    let game_state = game_state.expect("Tile placed in a game that didn't start");
+    let tile = game_state.hand_tile(player, &kind, index).expect("Tile was checked to exist");
+    let result = game_state.take_turn_placing_tile(game, &kind, index, &action, &loc);
+    let game_over = result.game_over();
+
+    let drawn = result.drawn_tiles();
+    inst.record(ReplayMove::PlaceTile{
+        player, kind: kind.clone(), index, action: action.clone(), loc: loc.clone(),
+    });
+    // The clocks stop once the game is over
+    inst.restart_turn_timer(Instant::now());
+    if game_over {
+        to_process.extend([
+            ElementaryRequest::EndRound{ id },
+            ElementaryRequest::NotifyChangeGame{ id },
+        ]);
+    } else {
+        to_process.push_back(ElementaryRequest::SkipStuckTurn{ id });
+    }
+
+    inst.users_with_lookers()
+        .map(|(user, looker)| {
+            (user.addr(), Response::PlacedTile {
+                id, player, kind: kind.clone(), index: index as u32, action: action.clone(), loc: loc.clone(),
+                tile: tile.clone(), drawn: inst.visible_drawn_tiles(&drawn, looker),
+            })
+        })
+        .chain(clock_updates(inst))
+        .collect()
+}
+
+/// Tells everyone in a game played with clocks how much time each player has left.
+/// Nothing for games without clocks.
+fn clock_updates(inst: &GameInstance) -> Vec<(SocketAddr, Response)> {
+    inst.clocks(Instant::now()).map_or(vec![], |clocks| inst.players_and_spectators()
+        .map(|user| (user.addr(), Response::Clocks{ id: inst.id(), clocks: clocks.clone() }))
+        .collect())
+}
+
+/// Takes back the last tile placement in a game everyone accepted undoing it in,
+/// and tells everyone in the game how it is now. Whoever placed the tile gets their turn back.
+fn undo_turn(inst: &mut GameInstance) -> Vec<(SocketAddr, Response)> {
+    let id = inst.id();
+    inst.undo();
+    inst.restart_turn_timer(Instant::now());
+    inst.users_with_lookers()
+        .map(|(user, looker)| (user.addr(), Response::UndidTurn{ game: inst.to_common_for(looker) }))
+        .chain(clock_updates(inst))
+        .chain(inst.awaited_players().into_iter().map(|addr| (addr, Response::YourTurn{ id })))
+        .collect()
+}
+
+/// Creates the rematch of game `id`, which ended, with the same options and everyone moved one seat over.
+/// Its bots join right away, since they can't ask for it themselves.
+/// Returns the rematch's id and the responses to the bots joining.
+fn create_rematch(state: &mut State, id: GameId) -> (GameId, Vec<(SocketAddr, Response)>) {
+    // This can be proven to work without relying on the user input being good
+    let game = state.game(id).expect("Rematch requested on nonexistent game");
+    let (options, seats) = (game.options().clone(), game.rematch_seats());
+    let bots = game.players().iter().map(Player::addr).filter(|addr| state.bot_difficulty(*addr).is_some()).collect_vec();
+
+    let rematch = state.add_game(options.game(), options).id();
+    state.game_mut(rematch).unwrap().set_seats(seats); // just added
+    state.game_mut(id).unwrap().set_rematch(Some(rematch));
+    journal_snapshot(state, rematch);
+    let responses = bots.into_iter()
+        .flat_map(|bot| process_elementary_requests(vec![ElementaryRequest::JoinGame{ id: rematch }], bot, state))
+        .collect();
+    (rematch, responses)
+}
+
+/// Counts a player asking to pause a game, or to resume it if `pause` is false,
+/// and tells everyone in the game once that's enough to do it
+fn vote_pause(state: &mut State, id: GameId, requester: SocketAddr, pause: bool) -> Vec<(SocketAddr, Response)> {
+    if let Some(inst) = state.game_mut(id) {
+        if let Some(player) = inst.player_index(requester) {
+            if let Err(reason) = inst.check_vote_pause(player, pause) {
+                reject_move(inst, requester, reason)
+            } else if !inst.vote_pause(player) {
+                vec![]
+            } else if pause {
+                inst.pause(Instant::now());
+                state.broadcast_to_game(id, Response::GamePaused{ id })
+            } else {
+                inst.resume(Instant::now());
+                state.broadcast_to_game(id, Response::GameResumed{ id })
+            }
+        } else { reject_move(inst, requester, RejectReason::NotAPlayer) }
+    } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
+}
+
+/// Skips a player's turn in a game in progress, and tells everyone in the game about it.
+/// Whoever moves next gets told separately.
+fn skip_turn(
+    state: &mut State, id: GameId, player: u32, reason: SkipReason, to_process: &mut VecDeque<ElementaryRequest>,
+) -> Vec<(SocketAddr, Response)> {
+    let inst = state.game_mut(id).expect("Turn skipped in a nonexistent game");
+    inst.state_mut().expect("Turn skipped in a game that didn't start").skip_turn();
+    inst.restart_turn_timer(Instant::now());
+    inst.record(ReplayMove::SkipTurn{ player, reason });
+    to_process.push_back(ElementaryRequest::SkipStuckTurn{ id });
+    let clocks = clock_updates(inst);
+    let mut responses = state.broadcast_to_game(id, Response::SkippedTurn{ id, player, reason });
+    responses.extend(clocks);
+    responses
+}
+
+/// Writes the archive of a game that just ended into the archive directory if archiving is on.
+/// Failures only get logged, since the replay still gets kept.
+fn archive_game(state: &State, id: GameId, replay_id: ReplayId, replay: &Replay) {
+    if let (Some(dir), Some(game)) = (state.archive_dir(), state.game(id)) {
+        // Replay ids start over when the server restarts, so the time keeps names unique
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        let path = dir.join(format!("game-{}-{}.json", secs, replay_id.0));
+        let archive = GameArchive::new(game.options().clone(), replay.clone());
+        if let Err(err) = fs::create_dir_all(dir).and_then(|_| fs::write(&path, archive.to_json())) {
+            error!("Failed to archive game {:?} to {:?}: {}", id, path, err);
+        }
+    }
+}
+
+/// Adds the result of game `id`, which just ended, to the history of each player that isn't a bot
+fn record_history(state: &mut State, id: GameId, replay_id: ReplayId) {
+    if let Some(summary) = state.game(id).and_then(|game| game.summary(replay_id)) {
+        let humans = summary.players().iter().filter(|username| !state.is_bot(username)).cloned().collect_vec();
+        state.history_mut().record(&summary, &humans);
+    }
+}
+
+/// Adds how the players of daily challenge `id`, which just ended, did to the leaderboard,
+/// and sends them the leaderboard. Bots don't get ranked. Does nothing if the game isn't a daily challenge.
+fn record_daily(state: &mut State, id: GameId) -> Vec<(SocketAddr, Response)> {
+    let results = state.game(id)
+        .and_then(|game| game.daily().zip(game.state().as_ref()).map(|(day, game_state)| (game, day, game_state)))
+        .map(|(game, day, game_state)| {
+            let results = game.players().iter().enumerate()
+                .filter(|(_, player)| !state.is_bot(player.username()))
+                .map(|(index, player)| (player.addr(), DailyResult::new(
+                    player.username().clone(), game_state.won(index as u32), game_state.scores()[index],
+                )))
+                .collect_vec();
+            (day, results)
+        });
+    if let Some((day, results)) = results {
+        let addrs = results.into_iter()
+            .map(|(addr, result)| {
+                state.daily_mut().record(day, result);
+                addr
+            })
+            .collect_vec();
+        let (day, results) = (state.daily().day(), state.daily().top());
+        addrs.into_iter().flat_map(|addr| state.send_to(addr, Response::Daily{ day, results: results.clone() })).collect()
+    } else { vec![] }
+}
+
+/// Tells everyone in the lobby about the current state of game `id`, unless it's a daily challenge
+fn lobby_update(state: &State, id: GameId) -> Vec<(SocketAddr, Response)> {
+    // This can be proven to work without relying on the user input being good
+    let game = state.game(id).expect("NotifyChangeGame requested on nonexistent game");
+    if game.daily().is_some() {
+        return vec![];
+    }
+    state.broadcast_to_lobby(Response::ChangedGame{ game: game.to_lobby() })
+}
+
+/// Sends the lobby the changes to games that were held back because they changed too soon after the last one
+fn process_lobby_updates(state: &mut State, now: Instant) -> Vec<(SocketAddr, Response)> {
+    state.take_due_lobby_updates(now).into_iter()
+        .flat_map(|id| lobby_update(state, id))
+        .collect()
+}
+
+/// Skips turns of players who have been disconnected for longer than the grace period,
+/// and removes those who haven't even placed their token yet.
+/// Also moves for or skips players who ran out of time on their turn, removes players whose clock ran out,
+/// and sends the lobby and spectators the changes that were held back.
+/// Returns a list of responses to send to peers.
+pub(crate) fn process_timeouts(state: &mut State, now: Instant) -> Vec<(SocketAddr, Response)> {
+    let lobby_responses = process_lobby_updates(state, now);
+
+    let grace_period = state.disconnect_grace_period();
+    let timed_out = |player: &&Player| player.disconnected_since()
+        .map_or(false, |since| now.duration_since(since) >= grace_period);
+
+    // Nothing times out while a game is paused
+    let to_process = state.games().values()
+        .filter(|game| !game.paused())
+        .flat_map(|game| game.state().as_ref()
+            .filter(|game_state| !game_state.game_over())
+            .map(|game_state| (game, game_state)))
+        .flat_map(|(game, game_state)| {
+            if game_state.all_players_placed() {
+                // Don't bother skipping turns if nobody's around to play
+                let anyone_connected = game.players().iter().enumerate()
+                    .any(|(i, player)| player.connected() && game_state.player_state(i as u32).is_some());
+                // When everyone picks at once, it's up to everyone who hasn't picked yet
+                let waiting_on = if !anyone_connected {
+                    vec![]
+                } else if game.options().simultaneous() {
+                    (0..game.num_players()).filter(|player| game.awaits_move(*player)).collect_vec()
+                } else {
+                    vec![game_state.turn_player()]
+                };
+                // Running out of time on the clock is the same as resigning
+                if let Some(player) = game.clock_ran_out(now).filter(|_| anyone_connected) {
+                    return vec![(game.players()[player as usize].addr(), ElementaryRequest::LeaveGame{ id: game.id() })];
+                }
+                waiting_on.into_iter()
+                    .flat_map(|player| {
+                        let player = &game.players()[player as usize];
+                        let req = if timed_out(&player) {
+                            Some(ElementaryRequest::SkipTurn{ id: game.id(), reason: SkipReason::Disconnected })
+                        } else if game.turn_timed_out(now) {
+                            Some(if game.options().auto_move() {
+                                ElementaryRequest::AutoMove{ id: game.id(), difficulty: BotDifficulty::Medium }
+                            } else {
+                                ElementaryRequest::SkipTurn{ id: game.id(), reason: SkipReason::OutOfTime }
+                            })
+                        } else { None };
+                        req.map(|req| (player.addr(), req))
+                    })
+                    .collect_vec()
+            } else {
+                game.players().iter().enumerate()
+                    .filter(|(i, player)| timed_out(player) && game_state.board_state().player_port(*i as u32).is_none())
+                    .map(|(_, player)| (player.addr(), ElementaryRequest::LeaveGame{ id: game.id() }))
+                    .collect_vec()
+            }
+        })
+        .collect_vec();
+
+    let responses = to_process.into_iter()
+        .flat_map(|(addr, req)| process_elementary_requests(vec![req], addr, state))
+        .collect();
+    let responses = process_bot_actions(responses, state);
+    let delayed_responses = state.peers_and_games_mut().1.values_mut()
+        .flat_map(|game| game.release_delayed(now))
+        .collect_vec();
+    lobby_responses.into_iter()
+        .chain(delayed_responses)
+        .chain(delay_spectator_responses(responses, state, now))
+        .collect()
+}
+
+/// Removes games that ended or sat empty without starting for longer than the idle timeout,
+/// bots that aren't playing any game left, and peers that connected that long ago without picking a username.
+/// Removing a peer closes its connection.
+/// Returns a list of responses to send to peers.
+pub(crate) fn process_cleanup(state: &mut State, now: Instant) -> Vec<(SocketAddr, Response)> {
+    let idle_timeout = state.idle_timeout();
+    let idle_since = |since: Instant| now.saturating_duration_since(since) >= idle_timeout;
+
+    let idle_games = state.games().values()
+        .filter(|game| match game.ended() {
+            // Spectators still get to see how it ended
+            Some(ended) => idle_since(ended) && !game.has_delayed(),
+            None => !game.started() && game.players().is_empty() && idle_since(game.created()),
+        })
+        .map(|game| game.id())
+        .collect_vec();
+    for id in &idle_games {
+        info!("Removing idle game {:?}", id);
+        journal_removal(state, *id);
+        state.remove_game(*id);
+    }
+
+    let idle_peers = state.peers().iter()
+        .filter(|(addr, peer)| match state.bot_difficulty(**addr) {
+            Some(_) => !state.games().values().any(|game| game.player_index(**addr).is_some()),
+            None => !state.has_username(**addr) && idle_since(peer.connected_at()),
+        })
+        .map(|(addr, _)| *addr)
+        .collect_vec();
+    for addr in idle_peers {
+        info!("Removing idle peer {}", addr);
+        state.remove_peer(addr);
+    }
 
+    idle_games.into_iter()
+        .flat_map(|id| state.broadcast_to_lobby(Response::RemovedGame{ id }))
+        .collect()
+}
+
+/// Processes a list of elementary requests in order, and returns a list of responses to send to peers.
+/// Requests that change games in progress get journaled before they're applied.
+pub(crate) fn process_elementary_requests(elem_req: Vec<ElementaryRequest>, requester: SocketAddr, state: &mut State) -> Vec<(SocketAddr, Response)> {
     let mut to_process = elem_req.into_iter().collect::<VecDeque<_>>();
     let mut responses = vec![];
     while let Some(req) = to_process.pop_front() {
+        journal_request(state, &req, requester);
         responses.extend(match req {
             ElementaryRequest::SetUsername{ username: name } => {
-                if state.set_username(requester, name.clone()) {
-                    to_process.push_back(ElementaryRequest::JoinLobby);
-                    vec![]
-                } else {
-                    vec![(requester, Response::RejectedUsername)]
+                match state.set_username(requester, &name) {
+                    Ok(()) => {
+                        to_process.push_back(ElementaryRequest::JoinLobby);
+                        vec![]
+                    }
+                    Err(reason) => state.send_to(requester, Response::RejectedUsername{ reason }),
                 }
             },
 
-            ElementaryRequest::CreateGame => {
-                let board = RectangleBoard::new(6, 6, 2);
-                let start_ports = board.boundary_ports();
-                let game = PathGame::new(
-                    RectangleBoard::new(6, 6, 2),
-                    start_ports,
-                    [((), 3)],
-                ).wrap_base();
-                
-                let game = state.add_game(game).to_common();
-                to_process.push_back(ElementaryRequest::NotifyChangeGame{ id: game.id() });
-                vec![]
+            ElementaryRequest::Ping => state.send_to(requester, Response::Pong),
+
+            ElementaryRequest::GetServerInfo => state.send_to(requester, Response::ServerInfo{ info: state.server_info() }),
+
+            ElementaryRequest::GetReplay{ replay_id } =>
+                state.send_to(requester, Response::Replay{ replay_id, replay: state.replay(replay_id).cloned() }),
+
+            ElementaryRequest::GetHistory => {
+                let username = state.peer(requester).expect("Peer doesn't exist").username();
+                state.send_to(requester, Response::History{ games: state.history().recent(username) })
+            }
+
+            ElementaryRequest::SaveReplay{ id } => {
+                // This can be proven to work without relying on the user input being good
+                let replay = state.game(id).and_then(|game| game.replay().clone())
+                    .expect("SaveReplay requested on a game that didn't start");
+                let replay_id = state.add_replay(replay.clone());
+                archive_game(state, id, replay_id, &replay);
+                state.game_mut(id).unwrap().end(Instant::now());
+                record_history(state, id, replay_id);
+                let mut responses = state.broadcast_to_game(id, Response::ReplaySaved{ id, replay_id, replay });
+                responses.extend(record_daily(state, id));
+                responses
+            }
+
+            ElementaryRequest::CreateGame{
+                preset, open_hands, hand_exchange, scout, rejection_feed, rounds, simultaneous, time_control, spectator_delay,
+            } => {
+                if state.max_games().map_or(false, |max| state.games().len() >= max) {
+                    state.send_to(requester, Response::RejectedGame{ reason: RejectReason::TooManyGames })
+                } else {
+                    let mut options = preset.options();
+                    if let Some(limit) = state.turn_time_limit() {
+                        options.set_turn_time_limit(limit);
+                    }
+                    // Presets that play by the book stay that way
+                    if preset.allows_house_rules() {
+                        options.set_open_hands(open_hands);
+                        // Both happen on a player's own turn, which games where everyone picks at once don't have
+                        options.set_hand_exchange(hand_exchange && !simultaneous);
+                        options.set_scout(scout && !simultaneous);
+                        options.set_simultaneous(simultaneous);
+                    }
+                    options.set_rejection_feed(rejection_feed);
+                    options.set_rounds(rounds);
+                    // Clocks run on a player's own turn too
+                    options.set_time_control(time_control.filter(|_| !options.simultaneous()));
+                    options.set_spectator_delay(spectator_delay);
+                    let game = options.game();
+                    let id = state.add_game(game, options).id();
+                    journal_snapshot(state, id);
+                    to_process.push_back(ElementaryRequest::NotifyChangeGame{ id });
+                    vec![]
+                }
+            }
+
+            ElementaryRequest::EndRound{ id } => {
+                // This can be proven to work without relying on the user input being good
+                let game = state.game_mut(id).expect("EndRound requested on nonexistent game");
+                if game.end_round() {
+                    to_process.push_back(ElementaryRequest::SaveReplay{ id });
+                    vec![]
+                } else {
+                    game.start_next_round();
+                    journal_snapshot(state, id);
+                    let game = state.game(id).unwrap(); // no more need for the mutable borrow
+                    let score = game.match_score().clone().expect("Game started, there should be a score");
+
+                    game.users_with_lookers().map(|(user, looker)| {
+                        let this_state = game.visible_state(looker)
+                            .expect("Game started, there should be a state");
+                        (user.addr(), Response::StartedRound{ id, state: this_state, score: score.clone() })
+                    })
+                    .collect()
+                }
             }
 
             ElementaryRequest::JoinGame{ id } => {
-                let username = state.peer(requester).expect("Peer doesn't exist").username().clone();
+                let peer = state.peer(requester).expect("Peer doesn't exist");
+                let (username, avatar) = (peer.username().clone(), peer.avatar());
 
-                if let Some(game) = state.game_mut(id) {
+                // Daily challenges are played alone, so nobody else gets in, not even to watch
+                let shut_out = state.game(id).map_or(false, |game| game.daily().is_some() &&
+                    !state.is_bot(&username) && !game.players().iter().any(|player| *player.username() == username));
+
+                if let Some(game) = state.game_mut(id).filter(|_| !shut_out) {
                     let index = game.add_player(requester, username.clone());
                     if index.is_none() {
                         game.add_spectator(requester, username);
+                        to_process.push_back(ElementaryRequest::NotifyChangeSpectators{ id });
                     }
 
-                    if index.is_some() {
+                    if let Some(index) = index {
+                        game.set_avatar(index, avatar);
                         to_process.extend([
                             ElementaryRequest::NotifyChangePlayers{ id },
                             ElementaryRequest::NotifyChangeGame{ id },
-                        ])
+                        ]);
+                        if game.reconnect_player(index) {
+                            to_process.push_back(ElementaryRequest::NotifyPlayerConnection{ id, player: index, connected: true });
+                        }
                     }
 
-                    let mut game_inst = game.to_common();
-                    if game.started() {
-                        game_inst.set_looker(if let Some(index) = index {
-                            Looker::Player(index)
-                        } else {
-                            Looker::Spectator
-                        })
-                    };
+                    let game_inst = game.to_common_for(index.map_or(Looker::Spectator, Looker::Player));
+                    let game = state.game(id).unwrap(); // no more need for the mutable borrow
+                    if index.is_some() && !game.started() {
+                        journal_snapshot(state, id);
+                    }
                     [
                         Some((requester, Response::JoinedGame{ game: game_inst } )),
-                        game.state().as_ref().map_or(false, |state| index == Some(state.turn_player()))
+                        game.clocks(Instant::now()).map(|clocks| (requester, Response::Clocks{ id, clocks })),
+                        game.paused().then_some((requester, Response::GamePaused{ id })),
+                        index.map_or(false, |index| game.awaits_move(index))
                             .then(|| (requester, Response::YourTurn{ id }))
                     ].into_iter().flatten().collect()
-                } else { vec![(requester, Response::Rejected{ id })] }
+                } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
+            }
+
+            ElementaryRequest::Resync{ id } => {
+                if let Some(game) = state.game(id) {
+                    let looker = game.users_with_lookers()
+                        .find(|(user, _)| user.addr() == requester)
+                        .map(|(_, looker)| looker);
+                    if let Some(looker) = looker {
+                        [
+                            Some((requester, Response::JoinedGame{ game: game.to_common_for(looker) })),
+                            game.clocks(Instant::now()).map(|clocks| (requester, Response::Clocks{ id, clocks })),
+                            game.paused().then_some((requester, Response::GamePaused{ id })),
+                            matches!(looker, Looker::Player(player) if game.awaits_move(player))
+                                .then_some((requester, Response::YourTurn{ id }))
+                        ].into_iter().flatten().collect()
+                    } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NotAPlayer }) }
+                } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
             }
 
             ElementaryRequest::LeaveGame{ id } => {
                 if let Some(game) = state.game_mut(id) {
-                    if game.remove_player(requester) {
-                        to_process.extend([
-                            ElementaryRequest::NotifyChangePlayers{ id },
-                            ElementaryRequest::NotifyChangeGame{ id },
-                        ]);
-                        vec![]
+                    let (was_all_placed, prev_turn_player) = game.state().as_ref()
+                        .map_or((false, 0), |state| (state.all_players_placed(), state.turn_player()));
+
+                    if let Some((player, drawn)) = game.remove_player(requester) {
+                        if game.started() {
+                            game.record(ReplayMove::Leave{ player });
+                        }
+                        if let Some(game_state) = game.state() {
+                            let all_placed = game_state.all_players_placed();
+                            let newly_all_placed = all_placed && !was_all_placed;
+                            let turn_player = game_state.turn_player();
+                            let game_over = game_state.game_over();
+                            // When everyone picks at once, the turn only matters once the round gets played out
+                            let simultaneous = game.options().simultaneous();
+                            let new_turn = all_placed && !game_over &&
+                                (newly_all_placed || (!simultaneous && turn_player != prev_turn_player));
+
+                            if game_over {
+                                to_process.extend([
+                                    ElementaryRequest::EndRound{ id },
+                                    ElementaryRequest::NotifyChangeGame{ id },
+                                ]);
+                            }
+                            if new_turn {
+                                to_process.push_back(ElementaryRequest::SkipStuckTurn{ id });
+                            }
+                            // The clocks stop once the game is over
+                            let clocks_changed = new_turn || game_over;
+                            if clocks_changed {
+                                game.restart_turn_timer(Instant::now());
+                            }
+                            // The player who left might have been the last one left to pick
+                            if simultaneous && game.all_committed() {
+                                to_process.push_back(ElementaryRequest::ResolveCommits{ id });
+                            }
+                            let to_move = if new_turn { game.awaited_players() } else { vec![] };
+
+                            game.users_with_lookers()
+                                .flat_map(|(user, looker)| { vec![
+                                    Some((user.addr(), Response::LeftGame{ id, player, drawn: game.visible_drawn_tiles(&drawn, looker) })),
+                                    newly_all_placed.then(|| (user.addr(), Response::AllPlacedTokens{ id })),
+                                ].into_iter().flatten()})
+                                .chain(if clocks_changed { clock_updates(game) } else { vec![] })
+                                .chain(to_move.into_iter().map(|addr| (addr, Response::YourTurn{ id })))
+                                .collect()
+                        } else {
+                            journal_snapshot(state, id);
+                            to_process.extend([
+                                ElementaryRequest::NotifyChangePlayers{ id },
+                                ElementaryRequest::NotifyChangeGame{ id },
+                            ]);
+                            vec![]
+                        }
                     } else {
-                        game.remove_spectator(requester);
+                        if game.remove_spectator(requester) {
+                            to_process.push_back(ElementaryRequest::NotifyChangeSpectators{ id });
+                        }
                         vec![]
                     }
                 } else { vec![] }
             }
 
             ElementaryRequest::LeaveGames => {
-                to_process.extend(state.games().iter().map(|game| ElementaryRequest::LeaveGame{ id: game.id() }));
+                to_process.extend(state.games().values().map(|game| ElementaryRequest::LeaveGame{ id: game.id() }));
                 vec![]
             }
 
+            ElementaryRequest::DisconnectGame{ id } => {
+                if let Some(game) = state.game_mut(id) {
+                    if let Some(player) = game.disconnect_player(requester, Instant::now()) {
+                        to_process.push_back(ElementaryRequest::NotifyPlayerConnection{ id, player, connected: false });
+                    } else {
+                        to_process.push_back(ElementaryRequest::LeaveGame{ id });
+                    }
+                }
+                vec![]
+            }
+
+            ElementaryRequest::DisconnectGames => {
+                to_process.extend(state.games().values().map(|game| ElementaryRequest::DisconnectGame{ id: game.id() }));
+                vec![]
+            }
+
+            ElementaryRequest::NotifyPlayerConnection{ id, player, connected } => {
+                // This can be proven to work without relying on the user input being good
+                let game = state.game(id).expect("NotifyPlayerConnection requested on nonexistent game");
+
+                game.players_and_spectators()
+                    .filter(|user| user.connected())
+                    .map(|user| (user.addr(), Response::PlayerConnection{ id, player, connected }))
+                    .collect()
+            }
+
+            ElementaryRequest::SkipTurn{ id, reason } => {
+                if let Some(inst) = state.game_mut(id) {
+                    let player = inst.player_index(requester);
+                    if let (Some(player), Some(game_state)) = (player, inst.state()) {
+                        if inst.options().simultaneous() {
+                            // Sitting the round out counts as a pick, and everyone finds out why once the round gets played out
+                            if inst.awaits_move(player) {
+                                inst.commit(player, Commit::Skip(reason));
+                                if inst.all_committed() {
+                                    to_process.push_back(ElementaryRequest::ResolveCommits{ id });
+                                }
+                                state.broadcast_to_game(id, Response::CommittedTile{ id, player })
+                            } else { vec![] }
+                        } else if player == game_state.turn_player() && game_state.all_players_placed() && !game_state.game_over() {
+                            let mut responses = skip_turn(state, id, player, reason, &mut to_process);
+                            let inst = state.game(id).unwrap(); // no more need for the mutable borrow
+                            responses.extend(inst.awaited_players().into_iter().map(|addr| (addr, Response::YourTurn{ id })));
+                            responses
+                        } else { vec![] }
+                    } else { vec![] }
+                } else { vec![] }
+            }
+
+            ElementaryRequest::AutoMove{ id, difficulty } => {
+                if let Some(inst) = state.game(id) {
+                    if let (Some(player), Some(game_state)) = (inst.player_index(requester), inst.state()) {
+                        to_process.push_back(match choose_bot_move(inst.game(), game_state, player, difficulty) {
+                            Some((kind, index, action, loc)) if inst.options().simultaneous() =>
+                                ElementaryRequest::CommitTile{ id, player, kind, index, action, loc },
+                            Some((kind, index, action, loc)) => ElementaryRequest::PlaceTile{ id, player, kind, index, action, loc },
+                            None => ElementaryRequest::SkipTurn{ id, reason: SkipReason::NoLegalMove },
+                        });
+                    }
+                }
+                vec![]
+            }
+
+            ElementaryRequest::SkipStuckTurn{ id } => {
+                let stuck_players = state.game(id).and_then(|inst| inst.state().as_ref()
+                    .filter(|game_state| game_state.all_players_placed() && !game_state.game_over())
+                    .map(|game_state| if inst.options().simultaneous() {
+                        (0..inst.num_players())
+                            .filter(|player| inst.awaits_move(*player) && game_state.player_stuck(inst.game(), *player))
+                            .collect_vec()
+                    } else {
+                        game_state.turn_player_stuck(inst.game()).then(|| game_state.turn_player()).into_iter().collect_vec()
+                    })
+                    .map(|players| players.into_iter().map(|player| inst.players()[player as usize].addr()).collect_vec()))
+                    .unwrap_or_default();
+                // Each skip is the stuck player's, so it gets journaled as theirs
+                stuck_players.into_iter()
+                    .flat_map(|addr| process_elementary_requests(
+                        vec![ElementaryRequest::SkipTurn{ id, reason: SkipReason::NoLegalMove }], addr, state
+                    ))
+                    .collect()
+            }
+
+            ElementaryRequest::AutoPlaceToken{ id } => {
+                if let Some(inst) = state.game(id) {
+                    if let (Some(player), Some(game_state)) = (inst.player_index(requester), inst.state()) {
+                        if let Some(port) = choose_start_port(inst.game(), game_state, player) {
+                            to_process.push_back(ElementaryRequest::PlaceToken{ id, player, port });
+                        }
+                    }
+                }
+                vec![]
+            }
+
+            ElementaryRequest::AddBot{ id, difficulty } => {
+                let check = match state.game(id) {
+                    None => Err(RejectReason::NoSuchGame),
+                    Some(game) if game.started() => Err(RejectReason::AlreadyStarted),
+                    Some(game) if game.player_index(requester).is_none() => Err(RejectReason::NotAPlayer),
+                    Some(_) => Ok(()),
+                };
+                if let Err(reason) = check {
+                    state.send_to(requester, Response::Rejected{ id, reason })
+                } else {
+                    let bot = state.add_bot(difficulty);
+                    // The bot joins like anyone else would, so everyone hears about it
+                    process_elementary_requests(vec![ElementaryRequest::JoinGame{ id }], bot, state)
+                }
+            }
+
             ElementaryRequest::JoinLobby => {
                 let username = state.peer(requester).expect("Peer doesn't exist").username().clone();
                 state.add_to_lobby(username, requester);
-                let games = state.games().iter().map(|game| game.to_common()).collect();
-                vec![(requester, Response::JoinedLobby{ games })]
+                let games = state.games().values()
+                    .filter(|game| game.daily().is_none())
+                    .map(GameInstance::to_lobby)
+                    .collect();
+                state.send_to(requester, Response::JoinedLobby{ games })
             }
 
             ElementaryRequest::LeaveLobby => {
@@ -144,105 +833,416 @@ pub(crate) fn process_request(req: Request, requester: SocketAddr, state: &mut S
             }
 
             ElementaryRequest::NotifyChangeGame{ id } => {
-                // This can be proven to work without relying on the user input being good
-                let game = state.game(id).expect("NotifyChangeGame requested on nonexistent game");
-
-                state.lobby().iter().map(|(_, addr)|
-                    (*addr, Response::ChangedGame{ game: game.to_common() })
-                ).collect()
+                if state.lobby_update_due(id, Instant::now()) {
+                    lobby_update(state, id)
+                } else { vec![] }
             }
 
             ElementaryRequest::NotifyChangePlayers{ id } => {
                 // This can be proven to work without relying on the user input being good
                 let game = state.game(id).expect("NotifyChangePlayers requested on nonexistent game");
 
-                let usernames = game.players().iter().map(|player| player.username().clone())
+                let names = game.players().iter().map(|player| player.username().clone())
+                    .collect_vec();
+                let avatars = game.avatars();
+                // Picked colors stay with their players as seats change
+                let colors = game.colors();
+                let mut responses = state.broadcast_to_game(id, Response::ChangedPlayers{ id, names, avatars });
+                if colors.iter().any(Option::is_some) {
+                    responses.extend(state.broadcast_to_game(id, Response::ChangedColors{ id, colors }));
+                }
+                responses
+            }
+
+            ElementaryRequest::NotifyChangeSpectators{ id } => {
+                // This can be proven to work without relying on the user input being good
+                let game = state.game(id).expect("NotifyChangeSpectators requested on nonexistent game");
+
+                let names = game.spectators().iter().map(|spectator| spectator.username().clone())
                     .collect_vec();
-                game.players_and_spectators().map(|player|
-                    (player.addr(), Response::ChangedPlayers{ id, names: usernames.clone() })
-                ).collect()
+                state.broadcast_to_game(id, Response::ChangedSpectators{ id, names })
             }
 
             ElementaryRequest::StartGame{ id } => {
                 if let Some(game) = state.game_mut(id) {
-                    let players_spectators = game.players_and_spectators().cloned().collect_vec();
                     if !game.started() {
-                        game.start();
+                        game.start(Instant::now());
+                        journal_snapshot(state, id);
                         let game = state.game(id).unwrap(); // no more need for the mutable borrow
 
                         to_process.push_back(ElementaryRequest::NotifyChangeGame{ id });
 
-                        let game_state = game.state().as_ref()
-                            .expect("Game started, there should be a state");
-                        players_spectators.into_iter().enumerate().map(|(index, user)| {
-                            let this_state = game_state.visible_state(if (index as u32) < game.num_players() {
-                                    Looker::Player(index as u32)
-                                } else {
-                                    Looker::Spectator
-                                });
+                        game.users_with_lookers().map(|(user, looker)| {
+                            let this_state = game.visible_state(looker)
+                                .expect("Game started, there should be a state");
                             (user.addr(), Response::StartedGame { id, state: this_state })
                         })
-                        .chain(state.lobby().values().map(|addr| (
-                            *addr, Response::ChangedGame{ game: game.to_common() }
-                        )))
                         .collect()
-                    } else { vec![(requester, Response::Rejected{ id })] }
-                } else { vec![(requester, Response::Rejected{ id })] }
+                    } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::AlreadyStarted }) }
+                } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
             }
 
             ElementaryRequest::PlaceToken{ id, player, port } => {
                 if let Some(inst) = state.game_mut(id) {
+                    // Players can only place their own token, once, and not while the game is paused
+                    let is_requester = inst.player_index(requester) == Some(player);
+                    let paused = inst.paused();
                     if let (game, Some(game_state)) = inst.game_and_state_mut() {
-                        if game_state.can_place_player(game, &port) {
+                        let check = if !is_requester {
+                            Err(RejectReason::NotAPlayer)
+                        } else if paused {
+                            Err(RejectReason::Paused)
+                        } else {
+                            game_state.check_place_player(game, player, &port)
+                        };
+                        if let Err(reason) = check {
+                            reject_move(inst, requester, reason)
+                        } else {
                             game_state.place_player(player, &port);
                             let all_placed = game_state.all_players_placed();
-                            let turn_player = game_state.turn_player();
+                            if all_placed {
+                                inst.restart_turn_timer(Instant::now());
+                                to_process.push_back(ElementaryRequest::SkipStuckTurn{ id });
+                            }
+                            inst.record(ReplayMove::PlaceToken{ player, port: port.clone() });
+                            let to_move = inst.awaited_players();
 
-                            inst.players_and_spectators().into_iter()
-                                .flat_map(|user| { vec![
-                                    Some((user.addr(), Response::PlacedToken { id, player, port: port.clone() })),
-                                    all_placed.then(|| (user.addr(), Response::AllPlacedTokens{ id })),
-                                ].into_iter().flatten()})
-                                .chain(all_placed.then(|| (inst.players()[turn_player as usize].addr(), Response::YourTurn{ id })))
-                                .collect()
-                        } else {
-                            vec![(requester, Response::Rejected{ id })]
+                            let mut responses = state.broadcast_to_game(id, Response::PlacedToken{ id, player, port });
+                            if all_placed {
+                                responses.extend(state.broadcast_to_game(id, Response::AllPlacedTokens{ id }));
+                                responses.extend(state.game(id).map_or(vec![], clock_updates));
+                                responses.extend(to_move.into_iter().map(|addr| (addr, Response::YourTurn{ id })));
+                            }
+                            responses
                         }
                     } else {
-                        warn!("Game state is missing");
-                        vec![(requester, Response::Rejected{ id })]
+                        state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NotStarted })
                     }
-                } else { vec![(requester, Response::Rejected{ id })] }
+                } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
             }
 
             ElementaryRequest::PlaceTile{ id, player, kind, index, action, loc } => {
                 if let Some(inst) = state.game_mut(id) {
+                    // Players can only place tiles for themselves, on their turn, in games where they take turns that aren't paused
+                    let is_requester = inst.player_index(requester) == Some(player);
+                    let simultaneous = inst.options().simultaneous();
+                    let paused = inst.paused();
                     if let (game, Some(game_state)) = inst.game_and_state_mut() {
-                        if game_state.can_place_tile(game, player, &kind, index, &action, &loc) {
-                            let result = game_state.take_turn_placing_tile(game, &kind, index, &action, &loc);
-                            let turn_player = game_state.turn_player();
-                            let game_over = result.game_over();
-                            
-                            if game_over {
-                                to_process.push_back(ElementaryRequest::NotifyChangeGame{ id });
+                        let check = if !is_requester {
+                            Err(RejectReason::NotAPlayer)
+                        } else if paused {
+                            Err(RejectReason::Paused)
+                        } else if simultaneous {
+                            Err(RejectReason::IllegalMove)
+                        } else {
+                            game_state.check_place_tile(game, player, &kind, index, &action, &loc)
+                        };
+                        if let Err(reason) = check {
+                            reject_move(inst, requester, reason)
+                        } else {
+                            let mut responses = place_tile(inst, player, kind, index, action, loc, &mut to_process);
+                            responses.extend(inst.awaited_players().into_iter().map(|addr| (addr, Response::YourTurn{ id })));
+                            responses
+                        }
+                    } else {
+                        state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NotStarted })
+                    }
+                } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
+            }
+
+            ElementaryRequest::CommitTile{ id, player, kind, index, action, loc } => {
+                if let Some(inst) = state.game_mut(id) {
+                    // Players can only pick tiles for themselves, once a round, in games where everyone picks at once that aren't paused
+                    let is_requester = inst.player_index(requester) == Some(player);
+                    let simultaneous = inst.options().simultaneous();
+                    if let Some(game_state) = inst.state() {
+                        let check = if !is_requester {
+                            Err(RejectReason::NotAPlayer)
+                        } else if inst.paused() {
+                            Err(RejectReason::Paused)
+                        } else if !simultaneous || inst.committed(player) {
+                            Err(RejectReason::IllegalMove)
+                        } else {
+                            game_state.check_commit_tile(inst.game(), player, &kind, index, &action, &loc)
+                        };
+                        if let Err(reason) = check {
+                            reject_move(inst, requester, reason)
+                        } else {
+                            // What got picked stays secret until everyone has picked
+                            inst.commit(player, Commit::PlaceTile((kind, index, action, loc)));
+                            if inst.all_committed() {
+                                to_process.push_back(ElementaryRequest::ResolveCommits{ id });
                             }
+                            state.broadcast_to_game(id, Response::CommittedTile{ id, player })
+                        }
+                    } else {
+                        state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NotStarted })
+                    }
+                } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
+            }
+
+            ElementaryRequest::ResolveCommits{ id } => {
+                let mut responses = vec![];
+                if state.game(id).map_or(false, |inst| inst.all_committed()) {
+                    // Picks get played out in turn order, so earlier placements can block later ones
+                    loop {
+                        let inst = state.game_mut(id).unwrap(); // checked above, and resolving doesn't remove games
+                        let player = match inst.state() {
+                            Some(game_state) if !game_state.game_over() => game_state.turn_player(),
+                            _ => break,
+                        };
+                        match inst.take_commit(player) {
+                            Some(Commit::PlaceTile((kind, index, action, loc))) => {
+                                let still_legal = inst.state().as_ref().map_or(false, |game_state|
+                                    game_state.check_commit_tile(inst.game(), player, &kind, index, &action, &loc).is_ok());
+                                responses.extend(if still_legal {
+                                    place_tile(inst, player, kind, index, action, loc, &mut to_process)
+                                } else {
+                                    skip_turn(state, id, player, SkipReason::MoveBlocked, &mut to_process)
+                                });
+                            }
+                            Some(Commit::Skip(reason)) => responses.extend(skip_turn(state, id, player, reason, &mut to_process)),
+                            None => break,
+                        }
+                    }
+                    let inst = state.game_mut(id).unwrap();
+                    inst.clear_commits();
+                    responses.extend(inst.awaited_players().into_iter().map(|addr| (addr, Response::YourTurn{ id })));
+                }
+                responses
+            }
+
+            ElementaryRequest::PlayDaily => {
+                let username = state.peer(requester).expect("Peer doesn't exist").username().clone();
+                let day = today();
+                state.daily_mut().roll_over(day);
+
+                // Whoever's still in the middle of today's challenge doesn't get to start it over either
+                let playing = state.games().values().any(|game| game.daily() == Some(day) &&
+                    game.players().iter().any(|player| *player.username() == username));
+
+                if playing || state.daily().played(&username) {
+                    state.send_to(requester, Response::RejectedGame{ reason: RejectReason::AlreadyPlayedDaily })
+                } else if state.max_games().map_or(false, |max| state.games().len() >= max) {
+                    state.send_to(requester, Response::RejectedGame{ reason: RejectReason::TooManyGames })
+                } else {
+                    let mut options = day.options();
+                    if let Some(limit) = state.turn_time_limit() {
+                        options.set_turn_time_limit(limit);
+                    }
+                    let game = options.game();
+                    let id = state.add_game(game, options).id();
+                    let inst = state.game_mut(id).unwrap(); // just added
+                    inst.set_daily(Some(day));
+                    // Added here, since joining keeps out anyone who isn't already playing a daily challenge
+                    inst.add_player(requester, username);
+                    to_process.extend([ElementaryRequest::LeaveLobby, ElementaryRequest::JoinGame{ id }]);
+                    to_process.extend((0..DAILY_BOTS).map(|_| ElementaryRequest::AddBot{ id, difficulty: DAILY_BOT_DIFFICULTY }));
+                    to_process.push_back(ElementaryRequest::StartGame{ id });
+                    vec![]
+                }
+            }
+
+            ElementaryRequest::GetDaily => {
+                state.daily_mut().roll_over(today());
+                state.send_to(requester, Response::Daily{ day: state.daily().day(), results: state.daily().top() })
+            }
+
+            ElementaryRequest::RequestUndo{ id } => {
+                if let Some(inst) = state.game_mut(id) {
+                    if let Some(player) = inst.player_index(requester) {
+                        if let Err(reason) = inst.check_request_undo(player) {
+                            reject_move(inst, requester, reason)
+                        } else {
+                            inst.request_undo(player);
+                            state.broadcast_to_game(id, Response::UndoRequested{ id, player })
+                        }
+                    } else { reject_move(inst, requester, RejectReason::NotAPlayer) }
+                } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
+            }
+
+            ElementaryRequest::RespondUndo{ id, accept } => {
+                if let Some(inst) = state.game_mut(id) {
+                    if let Some(player) = inst.player_index(requester) {
+                        if let Err(reason) = inst.check_respond_undo(player) {
+                            reject_move(inst, requester, reason)
+                        } else if !accept {
+                            inst.decline_undo();
+                            state.broadcast_to_game(id, Response::UndoDeclined{ id, player })
+                        } else if inst.accept_undo(player) {
+                            undo_turn(inst)
+                        } else { vec![] }
+                    } else { reject_move(inst, requester, RejectReason::NotAPlayer) }
+                } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
+            }
+
+            ElementaryRequest::PauseGame{ id } => vote_pause(state, id, requester, true),
+
+            ElementaryRequest::ResumeGame{ id } => vote_pause(state, id, requester, false),
+
+            ElementaryRequest::Rematch{ id } => {
+                let check = match state.game(id) {
+                    None => Err(RejectReason::NoSuchGame),
+                    Some(game) if game.player_index(requester).is_none() => Err(RejectReason::NotAPlayer),
+                    Some(game) => game.check_rematch(Instant::now()).and_then(|()| match game.rematch() {
+                        // Whoever's late to a rematch that already started has to find another game
+                        Some(rematch) => match state.game(rematch) {
+                            Some(rematch_game) if !rematch_game.started() => Ok(Some(rematch)),
+                            _ => Err(RejectReason::RematchClosed),
+                        },
+                        None if state.max_games().map_or(false, |max| state.games().len() >= max) => Err(RejectReason::TooManyGames),
+                        None => Ok(None),
+                    }),
+                };
+                match check {
+                    Err(reason) => state.send_to(requester, Response::Rejected{ id, reason }),
+                    Ok(Some(rematch)) => {
+                        to_process.push_back(ElementaryRequest::JoinGame{ id: rematch });
+                        vec![]
+                    }
+                    Ok(None) => {
+                        let (rematch, responses) = create_rematch(state, id);
+                        to_process.extend([ElementaryRequest::NotifyChangeGame{ id: rematch }, ElementaryRequest::JoinGame{ id: rematch }]);
+                        responses
+                    }
+                }
+            }
+
+            ElementaryRequest::ReorderPlayers{ id, order } => {
+                let check = match state.game(id) {
+                    None => Err(RejectReason::NoSuchGame),
+                    Some(game) if game.started() => Err(RejectReason::AlreadyStarted),
+                    Some(game) if game.player_index(requester).is_none() => Err(RejectReason::NotAPlayer),
+                    Some(game) => game.check_reorder_players(&order),
+                };
+                if let Err(reason) = check {
+                    state.send_to(requester, Response::Rejected{ id, reason })
+                } else {
+                    state.game_mut(id).unwrap().reorder_players(&order); // just checked
+                    journal_snapshot(state, id);
+                    to_process.extend([
+                        ElementaryRequest::NotifyChangePlayers{ id },
+                        ElementaryRequest::NotifyChangeGame{ id },
+                    ]);
+                    vec![]
+                }
+            }
+
+            ElementaryRequest::SetAvatar{ avatar } => {
+                state.set_avatar(requester, avatar);
+                // Games the user plays in show the new avatar right away
+                let ids = state.games().values()
+                    .filter(|game| game.player_index(requester).is_some())
+                    .map(GameInstance::id)
+                    .collect_vec();
+                for id in ids {
+                    let game = state.game_mut(id).unwrap(); // just found
+                    let player = game.player_index(requester).unwrap();
+                    game.set_avatar(player, avatar);
+                    to_process.extend([ElementaryRequest::NotifyChangePlayers{ id }, ElementaryRequest::NotifyChangeGame{ id }]);
+                }
+                vec![]
+            }
+
+            ElementaryRequest::Emote{ id, emote } => {
+                match state.game_mut(id) {
+                    None => state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }),
+                    Some(game) => match game.player_index(requester) {
+                        None => state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NotAPlayer }),
+                        Some(player) => if game.emote(player, Instant::now()) {
+                            state.broadcast_to_game(id, Response::Emoted{ id, player, emote })
+                        } else { vec![] },
+                    },
+                }
+            }
+
+            ElementaryRequest::SetColor{ id, color } => {
+                let check = match state.game(id) {
+                    None => Err(RejectReason::NoSuchGame),
+                    Some(game) if game.started() => Err(RejectReason::AlreadyStarted),
+                    Some(game) => match game.player_index(requester) {
+                        Some(player) => game.check_set_color(player, color).map(|()| player),
+                        None => Err(RejectReason::NotAPlayer),
+                    },
+                };
+                match check {
+                    Err(reason) => state.send_to(requester, Response::Rejected{ id, reason }),
+                    Ok(player) => {
+                        let game = state.game_mut(id).unwrap(); // just checked
+                        game.set_color(player, color);
+                        let colors = game.colors();
+                        journal_snapshot(state, id);
+                        state.broadcast_to_game(id, Response::ChangedColors{ id, colors })
+                    }
+                }
+            }
+
+            ElementaryRequest::ExchangeHand{ id, player } => {
+                if let Some(inst) = state.game_mut(id) {
+                    // Players can only exchange their own hand, on their turn, in games that allow it and aren't paused
+                    let is_requester = inst.player_index(requester) == Some(player);
+                    let allowed = inst.options().hand_exchange();
+                    let paused = inst.paused();
+                    if let Some(game_state) = inst.state_mut() {
+                        let check = if !is_requester {
+                            Err(RejectReason::NotAPlayer)
+                        } else if paused {
+                            Err(RejectReason::Paused)
+                        } else if !allowed {
+                            Err(RejectReason::IllegalMove)
+                        } else {
+                            game_state.check_exchange_hand(player)
+                        };
+                        if let Err(reason) = check {
+                            reject_move(inst, requester, reason)
+                        } else {
+                            let drawn = game_state.take_turn_exchanging_hand();
+                            inst.record(ReplayMove::ExchangeHand{ player });
+                            inst.restart_turn_timer(Instant::now());
+                            to_process.push_back(ElementaryRequest::SkipStuckTurn{ id });
 
-                            inst.players_and_spectators().into_iter()
-                                .map(|user| { 
-                                    (user.addr(), Response::PlacedTile {
-                                        id, player, kind: kind.clone(), index: index as u32, action: action.clone(), loc: loc.clone()
-                                    })
-                                })
-                                .chain((!game_over).then(|| (inst.players()[turn_player as usize].addr(), Response::YourTurn{ id })))
+                            inst.users_with_lookers()
+                                .map(|(user, looker)| (user.addr(), Response::ExchangedHand {
+                                    id, player, drawn: inst.visible_drawn_tiles(&drawn, looker),
+                                }))
+                                .chain(clock_updates(inst))
+                                .chain(inst.awaited_players().into_iter().map(|addr| (addr, Response::YourTurn{ id })))
                                 .collect()
+                        }
+                    } else {
+                        state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NotStarted })
+                    }
+                } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
+            }
+
+            ElementaryRequest::Scout{ id, player } => {
+                if let Some(inst) = state.game_mut(id) {
+                    // Players can only scout for themselves, on their turn, in games that allow it and aren't paused
+                    let is_requester = inst.player_index(requester) == Some(player);
+                    let allowed = inst.options().scout();
+                    let paused = inst.paused();
+                    if let Some(game_state) = inst.state_mut() {
+                        let check = if !is_requester {
+                            Err(RejectReason::NotAPlayer)
+                        } else if paused {
+                            Err(RejectReason::Paused)
+                        } else if !allowed {
+                            Err(RejectReason::IllegalMove)
+                        } else {
+                            game_state.check_scout(player)
+                        };
+                        if let Err(reason) = check {
+                            reject_move(inst, requester, reason)
                         } else {
-                            vec![(requester, Response::Rejected{ id })]
+                            // The piles stay hidden in the state, so the tiles only go to the scout
+                            let tiles = game_state.scout(player);
+                            inst.record(ReplayMove::Scout{ player });
+                            state.send_to(requester, Response::Scouted{ id, tiles })
                         }
                     } else {
-                        warn!("Game state is missing");
-                        vec![(requester, Response::Rejected{ id })]
+                        state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NotStarted })
                     }
-                } else { vec![(requester, Response::Rejected{ id })] }
+                } else { state.send_to(requester, Response::Rejected{ id, reason: RejectReason::NoSuchGame }) }
             }
         })
     }
@@ -256,9 +1256,30 @@ pub(crate) async fn respond_to_request(req: Request, requester: SocketAddr, stat
     let mut state = state.lock().await;
     
     let responses = process_request(req, requester, &mut state);
+    send_responses(responses, &state);
+}
+
+/// Processes and responds to players timing out.
+pub(crate) async fn respond_to_timeouts(state: &Mutex<State>) {
+    let mut state = state.lock().await;
+
+    let responses = process_timeouts(&mut state, Instant::now());
+    send_responses(responses, &state);
+}
+
+/// Processes and responds to games and peers going idle.
+pub(crate) async fn respond_to_cleanup(state: &Mutex<State>) {
+    let mut state = state.lock().await;
+
+    let responses = process_cleanup(&mut state, Instant::now());
+    send_responses(responses, &state);
+}
+
+/// Sends responses to their respective peers.
+pub(crate) fn send_responses(responses: Vec<(SocketAddr, Response)>, state: &State) {
     for (addr, resp) in responses {
         if let Some(peer) = state.peer(addr) {
-            if let Err(resp) = peer.tx().unbounded_send(resp) {
+            if let Err(resp) = peer.tx().send(resp) {
                 warn!("Failed to send response to {}: {:?}", addr, resp);
             }
         } else {
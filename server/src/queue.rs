@@ -0,0 +1,102 @@
+//! Bounded queues of responses waiting to be sent to a peer, so a peer that stops reading can't grow the server's memory forever.
+//! Updates to a game in the lobby replace the ones still waiting, since only the latest one matters.
+//! A peer whose queue overflows anyway gets disconnected; the receiving end ends and the connection closes.
+
+use std::{collections::VecDeque, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll, Waker}};
+
+use common::message::Response;
+use futures::Stream;
+
+use crate::state::Transport;
+
+/// Most responses waiting to be sent to a peer before it gets disconnected
+pub const MAX_QUEUED_RESPONSES: usize = 256;
+
+#[derive(Debug, Default)]
+struct Queue {
+    responses: VecDeque<Response>,
+    /// Most responses the queue holds
+    capacity: usize,
+    /// Whether the queue overflowed, which ends it
+    overflowed: bool,
+    /// Whether the sending end is gone
+    closed: bool,
+    /// Task waiting for a response
+    waker: Option<Waker>,
+}
+
+impl Queue {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The sending end of a peer's queue
+#[derive(Debug)]
+pub struct QueueSender(Arc<Mutex<Queue>>);
+
+/// The receiving end of a peer's queue. Ends if the queue overflows or the sending end is dropped.
+#[derive(Debug)]
+pub struct QueueReceiver(Arc<Mutex<Queue>>);
+
+/// Makes a queue that holds up to `capacity` responses
+pub fn bounded(capacity: usize) -> (QueueSender, QueueReceiver) {
+    let queue = Arc::new(Mutex::new(Queue { capacity, ..Queue::default() }));
+    (QueueSender(Arc::clone(&queue)), QueueReceiver(queue))
+}
+
+impl Transport for QueueSender {
+    fn send(&self, resp: Response) -> Result<(), Response> {
+        let mut queue = self.0.lock().unwrap();
+        if queue.overflowed {
+            return Err(resp);
+        }
+
+        let waiting = match &resp {
+            Response::ChangedGame{ game } => queue.responses.iter_mut()
+                .find(|waiting| matches!(waiting, Response::ChangedGame{ game: other } if other.id() == game.id())),
+            _ => None,
+        };
+        if let Some(waiting) = waiting {
+            *waiting = resp;
+        } else if queue.responses.len() >= queue.capacity {
+            queue.overflowed = true;
+            queue.responses.clear();
+            queue.wake();
+            return Err(resp);
+        } else {
+            queue.responses.push_back(resp);
+        }
+        queue.wake();
+        Ok(())
+    }
+}
+
+impl Drop for QueueSender {
+    fn drop(&mut self) {
+        let mut queue = self.0.lock().unwrap();
+        queue.closed = true;
+        queue.wake();
+    }
+}
+
+impl Stream for QueueReceiver {
+    type Item = Response;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Response>> {
+        let mut queue = self.0.lock().unwrap();
+        if queue.overflowed {
+            return Poll::Ready(None);
+        }
+        match queue.responses.pop_front() {
+            Some(resp) => Poll::Ready(Some(resp)),
+            None if queue.closed => Poll::Ready(None),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Limits on how much a peer can send, so a malicious or buggy client can't flood the processor.
+//! Each peer gets a token bucket for its requests, and requests that come when the bucket is empty get dropped.
+//! A peer that keeps sending anyway gets disconnected.
+
+use std::time::Instant;
+
+/// Requests a peer can make per second, on average
+pub const REQUESTS_PER_SECOND: f64 = 20.0;
+/// Requests a peer can make at once after being quiet for a while
+pub const REQUEST_BURST: u32 = 40;
+/// Requests in a row that can be dropped before the peer gets disconnected
+pub const MAX_DROPPED_IN_A_ROW: u32 = 40;
+/// Emotes a player can send per second in a game, on average
+pub const EMOTES_PER_SECOND: f64 = 0.5;
+/// Emotes a player can send at once after being quiet for a while
+pub const EMOTE_BURST: u32 = 3;
+/// Largest message a peer can send, in bytes. Requests are much smaller than this.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// What to do with a request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// Drop the request, since the peer is sending too fast
+    Drop,
+    /// Disconnect the peer, since it kept sending too fast
+    Disconnect,
+}
+
+/// A token bucket. Each request takes a token, and tokens come back at a steady rate up to the bucket's capacity.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    per_second: f64,
+    tokens: f64,
+    /// When tokens were last added
+    refilled: Instant,
+    /// Requests dropped since one was last allowed
+    dropped_in_a_row: u32,
+}
+
+impl RateLimiter {
+    /// A full bucket that holds `burst` tokens and gets `per_second` tokens back every second
+    pub fn new(burst: u32, per_second: f64, now: Instant) -> Self {
+        Self { capacity: burst as f64, per_second, tokens: burst as f64, refilled: now, dropped_in_a_row: 0 }
+    }
+
+    /// Takes a token for a request made at `now`, and says what to do with the request
+    pub fn check(&mut self, now: Instant) -> Verdict {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.refilled = self.refilled.max(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.dropped_in_a_row = 0;
+            Verdict::Allow
+        } else {
+            self.dropped_in_a_row += 1;
+            if self.dropped_in_a_row > MAX_DROPPED_IN_A_ROW {
+                Verdict::Disconnect
+            } else {
+                Verdict::Drop
+            }
+        }
+    }
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(REQUEST_BURST, REQUESTS_PER_SECOND, Instant::now())
+    }
+}
@@ -1,39 +1,146 @@
-use std::{net::SocketAddr, collections::{HashMap, hash_map}};
+use std::{net::SocketAddr, collections::{BTreeSet, HashMap, VecDeque, hash_map}, fmt::Debug, path::PathBuf, time::{Duration, Instant}};
 
-use common::{message::Response};
+use common::{ServerInfo, config::{DisconnectGracePeriod, IdleTimeout, TurnTimeLimit}, message::{RejectReason, Response}, validation::{UsernameProblem, validate_username}, options::{Avatar, BotDifficulty}, replay::{Replay, ReplayId}};
 use common::game::{GameId, BaseGame};
+use common::options::GameOptions;
 
 use fnv::FnvHashMap;
 use futures::channel::mpsc::UnboundedSender;
-use getset::{Getters, MutGetters};
+use getset::{CopyGetters, Getters, MutGetters, Setters};
+use log::*;
+use serde::{Deserialize, Serialize};
 
-use crate::game::{GameInstance};
+use crate::{bot::NullTransport, daily::{DailyLeaderboard, today}, filter::WordFilter, history::History, game::{GameInstance, GameSnapshot}, journal::Journal, metrics::Metrics};
 
 type PeerMap = FnvHashMap<SocketAddr, Peer>;
 
-#[derive(Debug, Getters, MutGetters)]
+/// Name of the server in server lists if none is configured
+pub const DEFAULT_SERVER_NAME: &str = "Tsurust Server";
+/// Region of the server in server lists if none is configured
+pub const DEFAULT_SERVER_REGION: &str = "Unknown";
+/// Most replays kept at once. The oldest ones get dropped to make room for new ones.
+pub const MAX_STORED_REPLAYS: usize = 256;
+/// Least time between updates of a game sent to the lobby.
+/// Changes that come sooner get sent together once it's up, so busy games don't flood the lobby.
+pub const LOBBY_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A way to send responses to a peer
+pub trait Transport: Debug + Send + Sync {
+    /// Sends a response to the peer. Gives the response back if the peer can't receive it.
+    // The response is only given back to be logged, so its size doesn't matter
+    #[allow(clippy::result_large_err)]
+    fn send(&self, resp: Response) -> Result<(), Response>;
+}
+
+/// Responses go to the task that owns the peer's web socket
+impl Transport for UnboundedSender<Response> {
+    fn send(&self, resp: Response) -> Result<(), Response> {
+        self.unbounded_send(resp).map_err(|err| err.into_inner())
+    }
+}
+
+/// Pairs a response with each address, cloning it for all but the last one
+fn to_each(addrs: impl IntoIterator<Item = SocketAddr>, resp: Response) -> Vec<(SocketAddr, Response)> {
+    let mut addrs = addrs.into_iter().peekable();
+    let mut responses = Vec::with_capacity(addrs.size_hint().0);
+    while let Some(addr) = addrs.next() {
+        if addrs.peek().is_none() {
+            responses.push((addr, resp));
+            break;
+        }
+        responses.push((addr, resp.clone()));
+    }
+    responses
+}
+
+#[derive(Debug, Getters, CopyGetters, MutGetters)]
 pub struct Peer {
     #[getset(get = "pub")]
     username: String,
-    #[getset(get = "pub")]
-    tx: UnboundedSender<Response>,
+    /// None if the peer didn't pick one
+    #[getset(get_copy = "pub")]
+    avatar: Option<Avatar>,
+    tx: Box<dyn Transport>,
+    /// When the peer connected
+    #[getset(get_copy = "pub")]
+    connected_at: Instant,
 }
 
 impl Peer {
+    pub fn tx(&self) -> &dyn Transport {
+        self.tx.as_ref()
+    }
+}
+
+/// Everything about the server's state that outlives connections, for persisting it or moving it elsewhere.
+/// Peers, the lobby, spectators, and bots are left out, and players are kept by username.
+#[derive(Clone, Debug, Getters, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    #[getset(get = "pub")]
+    games: Vec<GameSnapshot>,
+    disconnect_grace_period: Duration,
+    name: String,
+    region: String,
 }
 
-#[derive(Debug, Getters, MutGetters)]
+#[derive(Debug, Getters, CopyGetters, MutGetters, Setters)]
 pub struct State {
     #[getset(get = "pub")]
     peers: PeerMap,
     /// Maps usernames to addresses
     inv_peers: HashMap<String, SocketAddr>,
     #[getset(get = "pub", get_mut = "pub")]
-    games: Vec<GameInstance>,
+    games: FnvHashMap<GameId, GameInstance>,
     /// Map of players outside any game to their addresses
     #[getset(get = "pub")]
     lobby: HashMap<String, SocketAddr>,
-    id_counter: u32,
+    /// How long a disconnected player has to come back before their turns get skipped
+    #[getset(get_copy = "pub", set = "pub")]
+    disconnect_grace_period: Duration,
+    /// How long games that ended or sat empty without starting, and peers without a username, get kept
+    #[getset(get_copy = "pub", set = "pub")]
+    idle_timeout: Duration,
+    /// Name of the server in server lists
+    #[getset(get = "pub", set = "pub")]
+    name: String,
+    /// Where the server is, roughly, for server lists
+    #[getset(get = "pub", set = "pub")]
+    region: String,
+    /// The most games hosted at once. None if there's no limit.
+    #[getset(get_copy = "pub", set = "pub")]
+    max_games: Option<usize>,
+    /// Turn time limit every new game gets instead of its preset's. None if games keep their preset's.
+    #[getset(get_copy = "pub", set = "pub")]
+    turn_time_limit: Option<TurnTimeLimit>,
+    /// Words players can't use in what they write
+    #[getset(get = "pub", set = "pub")]
+    word_filter: WordFilter,
+    /// Bots the processor moves for, by address
+    bots: FnvHashMap<SocketAddr, BotDifficulty>,
+    bot_counter: u32,
+    /// For giving players of restored games placeholder addresses
+    placeholder_counter: u32,
+    /// Where games get journaled. None if journaling is off.
+    #[getset(get = "pub", set = "pub")]
+    journal: Option<Journal>,
+    /// Where archives of finished games get written. None if games don't get archived.
+    #[getset(get = "pub", set = "pub")]
+    archive_dir: Option<PathBuf>,
+    #[getset(get = "pub", get_mut = "pub")]
+    metrics: Metrics,
+    /// Replays of finished games, oldest first
+    replays: VecDeque<(ReplayId, Replay)>,
+    replay_counter: u32,
+    /// Results of finished games by username
+    #[getset(get = "pub", get_mut = "pub")]
+    history: History,
+    /// Results of today's daily challenge
+    #[getset(get = "pub", get_mut = "pub")]
+    daily: DailyLeaderboard,
+    /// When each game was last sent to the lobby
+    lobby_updated: FnvHashMap<GameId, Instant>,
+    /// Games that changed too soon after they were last sent to the lobby, and still have to be sent
+    lobby_pending: BTreeSet<GameId>,
 }
 
 impl State {
@@ -41,10 +148,58 @@ impl State {
         Self {
             peers: FnvHashMap::default(),
             inv_peers: HashMap::default(),
-            games: vec![],
+            games: FnvHashMap::default(),
             lobby: HashMap::default(),
-            id_counter: 0,
+            disconnect_grace_period: DisconnectGracePeriod::DEFAULT.get(),
+            idle_timeout: IdleTimeout::DEFAULT.get(),
+            name: DEFAULT_SERVER_NAME.to_owned(),
+            region: DEFAULT_SERVER_REGION.to_owned(),
+            max_games: None,
+            turn_time_limit: None,
+            word_filter: WordFilter::default(),
+            bots: FnvHashMap::default(),
+            bot_counter: 0,
+            placeholder_counter: 0,
+            journal: None,
+            archive_dir: None,
+            metrics: Metrics::default(),
+            replays: VecDeque::new(),
+            replay_counter: 0,
+            history: History::default(),
+            daily: DailyLeaderboard::new(today()),
+            lobby_updated: FnvHashMap::default(),
+            lobby_pending: BTreeSet::new(),
+        }
+    }
+
+    /// Adds a peer for a bot the processor moves for, with a unique username.
+    /// Returns the bot's address.
+    pub fn add_bot(&mut self, difficulty: BotDifficulty) -> SocketAddr {
+        self.bot_counter += 1;
+        // No real peer connects from 0.0.0.0/8, and demo bots use 0.0.0.0
+        let addr = SocketAddr::from(([0, 0, 0, 1], self.bot_counter as u16));
+        self.add_peer(addr, NullTransport);
+        let mut suffix = self.bot_counter;
+        while !self.claim_username(addr, format!("{} Bot {}", difficulty, suffix)) {
+            suffix += 1;
         }
+        self.bots.insert(addr, difficulty);
+        addr
+    }
+
+    /// How well the bot at an address plays. None if there's no bot there.
+    pub fn bot_difficulty(&self, addr: SocketAddr) -> Option<BotDifficulty> {
+        self.bots.get(&addr).copied()
+    }
+
+    /// Whether a username belongs to a bot the processor moves for
+    pub fn is_bot(&self, username: &str) -> bool {
+        self.inv_peers.get(username).map_or(false, |addr| self.bots.contains_key(addr))
+    }
+
+    /// What this server says about itself in server lists
+    pub fn server_info(&self) -> ServerInfo {
+        ServerInfo::new(self.name.clone(), self.region.clone(), self.inv_peers.len() as u32)
     }
 
     pub fn add_to_lobby(&mut self, username: String, addr: SocketAddr) {
@@ -61,22 +216,75 @@ impl State {
         }
     }
 
+    /// Records that game `id` changed at `now`, and returns whether to send it to the lobby right away.
+    /// If not, `take_due_lobby_updates` returns it once `LOBBY_UPDATE_INTERVAL` is up.
+    pub fn lobby_update_due(&mut self, id: GameId, now: Instant) -> bool {
+        match self.lobby_updated.get(&id) {
+            Some(last) if now.saturating_duration_since(*last) < LOBBY_UPDATE_INTERVAL => {
+                self.lobby_pending.insert(id);
+                false
+            }
+            _ => {
+                self.lobby_updated.insert(id, now);
+                self.lobby_pending.remove(&id);
+                true
+            }
+        }
+    }
+
+    /// Takes the games whose held back changes can be sent to the lobby at `now`
+    pub fn take_due_lobby_updates(&mut self, now: Instant) -> Vec<GameId> {
+        let lobby_updated = &self.lobby_updated;
+        let due = self.lobby_pending.iter()
+            .copied()
+            .filter(|id| lobby_updated.get(id)
+                .map_or(true, |last| now.saturating_duration_since(*last) >= LOBBY_UPDATE_INTERVAL))
+            .collect::<Vec<_>>();
+        for id in &due {
+            self.lobby_pending.remove(id);
+            self.lobby_updated.insert(*id, now);
+        }
+        due
+    }
+
     /// Add a peer with a placeholder username
-    pub fn add_peer(&mut self, addr: SocketAddr, tx: UnboundedSender<Response>) {
-        self.peers.insert(addr, Peer { username: "???".to_owned(), tx });
+    pub fn add_peer(&mut self, addr: SocketAddr, tx: impl Transport + 'static) {
+        self.peers.insert(addr, Peer { username: "???".to_owned(), avatar: None, tx: Box::new(tx), connected_at: Instant::now() });
     }
     
-    /// Removes a peer
+    /// Removes a peer. Its connection closes if it's still open.
     pub fn remove_peer(&mut self, addr: SocketAddr) {
         if let Some(username) = self.peers.get(&addr).map(|peer| peer.username()) {
-            self.inv_peers.remove(username);
+            if self.inv_peers.get(username) == Some(&addr) {
+                self.inv_peers.remove(username);
+            }
         }
         self.peers.remove(&addr);
+        self.bots.remove(&addr);
     }
     
-    /// Set the username of a peer, assuming it exists.
+    /// Set the username of a peer, assuming it exists, trimmed the way the rules for usernames say.
+    /// Returns why not instead if the username breaks the rules, has a filtered word, or is not unique.
+    pub fn set_username(&mut self, addr: SocketAddr, username: &str) -> Result<(), RejectReason> {
+        let username = validate_username(username).map_err(|problem| RejectReason::InvalidUsername{ problem })?;
+        if self.word_filter.matches(&username) {
+            return Err(RejectReason::InvalidUsername{ problem: UsernameProblem::Filtered });
+        }
+        if self.claim_username(addr, username) {
+            Ok(())
+        } else {
+            Err(RejectReason::UsernameTaken)
+        }
+    }
+
+    /// Sets the avatar of a peer, assuming the peer exists
+    pub fn set_avatar(&mut self, addr: SocketAddr, avatar: Option<Avatar>) {
+        self.peers.get_mut(&addr).expect("Expected peer to exist").avatar = avatar;
+    }
+
+    /// Set the username of a peer without checking it against the rules, assuming the peer exists.
     /// Returns false instead if the username is not unique.
-    pub fn set_username(&mut self, addr: SocketAddr, username: String) -> bool {
+    fn claim_username(&mut self, addr: SocketAddr, username: String) -> bool {
         if let hash_map::Entry::Vacant(e) = self.inv_peers.entry(username.clone()) {
             self.peers.get_mut(&addr)
                 .expect("Expected peer to exist")
@@ -88,34 +296,123 @@ impl State {
         }
     }
 
+    /// Whether the peer at an address picked a username
+    pub fn has_username(&self, addr: SocketAddr) -> bool {
+        self.peers.get(&addr).map_or(false, |peer| self.inv_peers.get(peer.username()) == Some(&addr))
+    }
+
     /// Get the peer, if it exists.
     pub fn peer(&self, addr: SocketAddr) -> Option<&Peer> {
         self.peers.get(&addr)
     }
 
-    pub fn peers_and_games_mut(&mut self) -> (&PeerMap, &mut [GameInstance]) {
+    /// A response to send to one peer, in the form the processor returns responses in
+    pub fn send_to(&self, addr: SocketAddr, resp: Response) -> Vec<(SocketAddr, Response)> {
+        vec![(addr, resp)]
+    }
+
+    /// A response to send to every player and spectator of game `id`, players first.
+    /// Nobody gets it if the game doesn't exist.
+    pub fn broadcast_to_game(&self, id: GameId, resp: Response) -> Vec<(SocketAddr, Response)> {
+        match self.game(id) {
+            Some(game) => to_each(game.players_and_spectators().map(|user| user.addr()), resp),
+            None => vec![],
+        }
+    }
+
+    /// A response to send to everyone in the lobby
+    pub fn broadcast_to_lobby(&self, resp: Response) -> Vec<(SocketAddr, Response)> {
+        to_each(self.lobby.values().copied(), resp)
+    }
+
+    pub fn peers_and_games_mut(&mut self) -> (&PeerMap, &mut FnvHashMap<GameId, GameInstance>) {
         (&self.peers, &mut self.games)
     }
 
-    /// Adds a game to the list and returns a reference to it.
-    pub fn add_game(&mut self, game: BaseGame) -> &GameInstance {
-        let id = GameId(self.id_counter);
-        self.id_counter += 1;
-        self.games.push(GameInstance::new(id, game));
-        self.games.last().unwrap()
+    /// Adds a game under a random id that isn't taken and returns a reference to it.
+    pub fn add_game(&mut self, game: BaseGame, options: GameOptions) -> &GameInstance {
+        let mut id = GameId::random();
+        while self.games.contains_key(&id) {
+            id = GameId::random();
+        }
+        self.games.insert(id, GameInstance::new(id, game, options));
+        &self.games[&id]
+    }
+
+    /// Removes a game, along with any lobby update of it still waiting to be sent
+    pub fn remove_game(&mut self, id: GameId) -> Option<GameInstance> {
+        self.lobby_updated.remove(&id);
+        self.lobby_pending.remove(&id);
+        self.games.remove(&id)
+    }
+
+    /// Keeps the replay of a finished game, dropping the oldest one if there are too many.
+    /// Returns the id it's kept under.
+    pub fn add_replay(&mut self, replay: Replay) -> ReplayId {
+        let id = ReplayId(self.replay_counter);
+        self.replay_counter += 1;
+        if self.replays.len() >= MAX_STORED_REPLAYS {
+            self.replays.pop_front();
+        }
+        self.replays.push_back((id, replay));
+        id
+    }
+
+    /// Gets a replay by id, if it's still kept
+    pub fn replay(&self, id: ReplayId) -> Option<&Replay> {
+        self.replays.binary_search_by_key(&id, |(id, _)| *id).ok().map(|i| &self.replays[i].1)
     }
 
-    fn game_index(&self, id: GameId) -> Option<usize> {
-        self.games.binary_search_by_key(&id, |game| game.id()).ok()
+    /// Takes a snapshot of everything that outlives connections
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            games: self.games.values().map(GameInstance::snapshot).collect(),
+            disconnect_grace_period: self.disconnect_grace_period,
+            name: self.name.clone(),
+            region: self.region.clone(),
+        }
+    }
+
+    /// Restores a state from a snapshot, with nobody connected.
+    /// Players of its games count as disconnected until they join again under the same username.
+    pub fn restore(snapshot: StateSnapshot) -> Self {
+        let StateSnapshot{ games, disconnect_grace_period, name, region } = snapshot;
+        let mut state = Self { disconnect_grace_period, name, region, ..Self::new() };
+        let now = Instant::now();
+        for game in games {
+            if state.restore_game(game, now).is_none() {
+                warn!("Snapshot has two games with the same id, keeping the first one");
+            }
+        }
+        state
+    }
+
+    /// Adds a game from a snapshot, keeping its id.
+    /// Its players get placeholder addresses and count as disconnected until they join again.
+    /// Returns the game's id, or None if a game with that id is already here, in which case nothing gets added.
+    pub fn restore_game(&mut self, snapshot: GameSnapshot, now: Instant) -> Option<GameId> {
+        if self.games.contains_key(&snapshot.id()) {
+            return None;
+        }
+        let counter = &mut self.placeholder_counter;
+        let game = GameInstance::restore(snapshot, || {
+            *counter += 1;
+            // No real peer connects from 0.0.0.0/8, and bots use 0.0.0.0 and 0.0.0.1
+            SocketAddr::from(([0, 0, 0, 2], *counter as u16))
+        }, now);
+
+        let id = game.id();
+        self.games.insert(id, game);
+        Some(id)
     }
 
     /// Gets a game by id, if it exists
     pub fn game(&self, id: GameId) -> Option<&GameInstance> {
-        self.game_index(id).map(|i| &self.games[i])
+        self.games.get(&id)
     }
 
     /// Gets a game mutably by id, if it exists
     pub fn game_mut(&mut self, id: GameId) -> Option<&mut GameInstance> {
-        self.game_index(id).map(|i| &mut self.games[i])
+        self.games.get_mut(&id)
     }
 }
\ No newline at end of file
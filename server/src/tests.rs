@@ -0,0 +1,1731 @@
+//! Protocol tests that run a whole game through the request processor.
+//! The server runs in-process, and test clients mirror the game state from the responses they receive,
+//! the same way the real client does.
+
+use std::{collections::HashSet, net::SocketAddr, path::Path, sync::Arc, time::{Duration, Instant}};
+
+use async_std::{sync::Mutex, task::JoinHandle};
+
+use common::{GameStatus, archive::GameArchive, board::{BasePort, Board, RectangleBoard}, clock::{Clocks, TimeControl}, config::{MatchRounds, SpectatorDelay, TurnTimeLimit}, daily::{DAILY_BOTS, DailyResult}, game::{BaseGame, GameId}, game_state::{BaseGameState, BaseTileMove}, history::GameSummary, message::{RejectReason, Request, RequestTag, Response, ResponseTag, SkipReason}, options::{Avatar, BotDifficulty, Emote, GamePreset, RejectionFeed, TokenColor}, player_state::Looker, replay::ReplayId, validation::UsernameProblem};
+use futures::{FutureExt, StreamExt, channel::mpsc::{self, UnboundedReceiver, UnboundedSender}, stream};
+use strum::IntoEnumIterator;
+
+use crate::{announce::DirectoryUrl, api, bot::move_kills, config::{ServerConfig, parse_flags}, daily::today, game::REMATCH_WINDOW, filter::WordFilter, history::{self, History}, http::{self, HttpRequest}, journal::{self, FsyncPolicy, Journal}, metrics, peer::{FromPeer, PeerTransport, ToPeer, serve_peer}, processor::{process_cleanup, process_request, process_timeouts, send_responses}, queue, rate_limit::{self, EMOTE_BURST, RateLimiter, Verdict}, state::{LOBBY_UPDATE_INTERVAL, State, Transport}};
+
+/// A client that remembers everything it received and mirrors the state of the game it's in
+struct TestClient {
+    addr: SocketAddr,
+    rx: UnboundedReceiver<Response>,
+    /// Every response received, in order
+    received: Vec<Response>,
+    game: Option<(GameId, BaseGame)>,
+    state: Option<BaseGameState>,
+    your_turn: bool,
+    /// Players that died from a tile placement
+    deaths: Vec<u32>,
+    /// Whether the client tries to kill itself when choosing a move
+    prefer_death: bool,
+}
+
+impl TestClient {
+    fn new(addr: SocketAddr, rx: UnboundedReceiver<Response>) -> Self {
+        Self {
+            addr,
+            rx,
+            received: vec![],
+            game: None,
+            state: None,
+            your_turn: false,
+            deaths: vec![],
+            prefer_death: false,
+        }
+    }
+
+    /// Updates the mirrored state like the real client would
+    fn handle_response(&mut self, resp: Response) {
+        let in_game = |id: &GameId, game: &Option<(GameId, BaseGame)>| game.as_ref().map_or(false, |(gid, _)| gid == id);
+
+        match &resp {
+            Response::JoinedLobby{ .. } => {
+                self.game = None;
+                self.state = None;
+                self.your_turn = false;
+            }
+
+            Response::JoinedGame{ game } => {
+                self.game = Some((game.id(), game.game().clone()));
+                self.state = game.state().clone();
+            }
+
+            Response::StartedGame{ id, state } if in_game(id, &self.game) => {
+                self.state = Some(state.clone());
+            }
+
+            Response::StartedRound{ id, state, .. } if in_game(id, &self.game) => {
+                self.state = Some(state.clone());
+                self.your_turn = false;
+            }
+
+            Response::PlacedToken{ id, player, port } if in_game(id, &self.game) => {
+                self.state.as_mut().expect("Token placed before the game started").place_player(*player, port);
+            }
+
+            Response::PlacedTile{ id, player, kind, index, action, loc, tile, drawn } if in_game(id, &self.game) => {
+                let (_, game) = self.game.as_ref().unwrap();
+                let state = self.state.as_mut().expect("Tile placed before the game started");
+                assert_eq!(state.turn_player(), *player, "Tile placed out of turn");
+                state.reveal_tiles(&[(*player, *index, tile.clone())]);
+                let result = state.take_turn_placing_tile(game, kind, *index, action, loc);
+                state.reveal_tiles(drawn);
+                self.deaths.extend(result.dead_players());
+                if state.is_player() && state.player_expect() == *player {
+                    self.your_turn = false;
+                }
+            }
+
+            Response::LeftGame{ id, player, drawn } if in_game(id, &self.game) => {
+                let (_, game) = self.game.as_ref().unwrap();
+                let state = self.state.as_mut().expect("Player left before the game started");
+                state.remove_player(game, *player);
+                state.reveal_tiles(drawn);
+            }
+
+            Response::ExchangedHand{ id, player, drawn } if in_game(id, &self.game) => {
+                let state = self.state.as_mut().expect("Hand exchanged before the game started");
+                assert_eq!(state.turn_player(), *player, "Hand exchanged out of turn");
+                state.take_turn_exchanging_hand();
+                state.reveal_tiles(drawn);
+                if state.is_player() && state.player_expect() == *player {
+                    self.your_turn = false;
+                }
+            }
+
+            Response::Scouted{ id, tiles } if in_game(id, &self.game) => {
+                let state = self.state.as_mut().expect("Scouted before the game started");
+                state.scout(state.player_expect());
+                state.reveal_top_tiles(tiles);
+            }
+
+            Response::SkippedTurn{ id, player, .. } if in_game(id, &self.game) => {
+                let state = self.state.as_mut().expect("Turn skipped before the game started");
+                assert_eq!(state.turn_player(), *player, "Skipped someone else's turn");
+                state.skip_turn();
+                if state.is_player() && state.player_expect() == *player {
+                    self.your_turn = false;
+                }
+            }
+
+            Response::YourTurn{ id } if in_game(id, &self.game) => {
+                self.your_turn = true;
+            }
+
+            Response::UndidTurn{ game } if in_game(&game.id(), &self.game) => {
+                self.state = game.state().clone();
+                self.your_turn = false;
+            }
+
+            Response::CommittedTile{ id, player } if in_game(id, &self.game) => {
+                let state = self.state.as_ref().expect("Tile picked before the game started");
+                if state.is_player() && state.player_expect() == *player {
+                    self.your_turn = false;
+                }
+            }
+
+            _ => {}
+        }
+
+        self.received.push(resp);
+    }
+
+    fn state(&self) -> &BaseGameState {
+        self.state.as_ref().expect("Client isn't in a started game")
+    }
+
+    /// Picks any legal tile placement, even if it isn't this player's turn
+    fn any_move(&self) -> BaseTileMove {
+        let (_, game) = self.game.as_ref().expect("Client isn't in a game");
+        let state = self.state();
+        state.legal_moves(game, state.player_expect()).into_iter().next().expect("No legal moves")
+    }
+
+    /// Picks a legal tile placement, preferring ones that kill this player if `prefer_death` is set
+    /// and ones that don't otherwise.
+    fn choose_move(&self) -> BaseTileMove {
+        let (_, game) = self.game.as_ref().expect("Client isn't in a game");
+        let state = self.state();
+        let moves = state.legal_moves(game, state.player_expect());
+        moves.iter().find(|mv| move_kills(game, state, mv, state.player_expect()) == self.prefer_death)
+            .or_else(|| moves.first())
+            .cloned()
+            .expect("No legal moves")
+    }
+}
+
+/// A server that hands responses straight to test clients.
+/// Requests and responses go through bincode to make sure they survive the wire.
+struct TestServer {
+    state: State,
+    clients: Vec<TestClient>,
+    next_port: u16,
+    requests_sent: HashSet<RequestTag>,
+}
+
+impl TestServer {
+    fn new() -> Self {
+        Self {
+            state: State::new(),
+            clients: vec![],
+            next_port: 10000,
+            requests_sent: HashSet::new(),
+        }
+    }
+
+    fn new_addr(&mut self) -> SocketAddr {
+        self.next_port += 1;
+        SocketAddr::from(([127, 0, 0, 1], self.next_port))
+    }
+
+    /// Connects a new client and returns its index
+    fn connect(&mut self) -> usize {
+        let addr = self.new_addr();
+        let (tx, rx) = mpsc::unbounded();
+        self.state.add_peer(addr, tx);
+        self.clients.push(TestClient::new(addr, rx));
+        self.clients.len() - 1
+    }
+
+    /// Drops a client's connection, like `serve_peer` does
+    fn disconnect(&mut self, client: usize) {
+        let addr = self.clients[client].addr;
+        self.state.remove_peer(addr);
+        self.requests_sent.insert(RequestTag::RemovePeer);
+        let responses = process_request(Request::RemovePeer, addr, &mut self.state);
+        self.deliver(responses);
+    }
+
+    /// Gives a disconnected client a fresh connection with a new address
+    fn reconnect(&mut self, client: usize) {
+        let addr = self.new_addr();
+        let (tx, rx) = mpsc::unbounded();
+        self.state.add_peer(addr, tx);
+        self.clients[client].addr = addr;
+        self.clients[client].rx = rx;
+    }
+
+    /// Sends a request from a client and returns the responses that client got back
+    fn request(&mut self, client: usize, req: Request) -> Vec<Response> {
+        let req = bincode::deserialize::<Request>(&bincode::serialize(&req).unwrap()).unwrap();
+        self.requests_sent.insert(req.tag());
+        let num_received = self.clients[client].received.len();
+
+        let responses = process_request(req, self.clients[client].addr, &mut self.state);
+        self.deliver(responses);
+        self.clients[client].received[num_received..].to_vec()
+    }
+
+    /// Sends a request that should go through
+    fn accept(&mut self, client: usize, req: Request) -> Vec<Response> {
+        let responses = self.request(client, req.clone());
+        assert!(
+            !responses.iter().any(|resp| matches!(resp, Response::Rejected{ .. } | Response::RejectedUsername{ .. } | Response::RejectedGame{ .. })),
+            "Request {:?} was rejected", req
+        );
+        responses
+    }
+
+    /// Sends a request to create a game that should go through, and returns the new game's id
+    fn create_game(&mut self, client: usize, req: Request) -> GameId {
+        let old_ids = self.state.games().keys().copied().collect::<HashSet<_>>();
+        self.accept(client, req);
+        *self.state.games().keys().find(|id| !old_ids.contains(id)).expect("No game got created")
+    }
+
+    /// Sends a request that should get rejected for a reason
+    fn reject(&mut self, client: usize, req: Request, reason: RejectReason) {
+        let responses = self.request(client, req.clone());
+        assert!(
+            responses.iter().any(|resp| matches!(resp,
+                Response::Rejected{ reason: r, .. } | Response::RejectedUsername{ reason: r } | Response::RejectedGame{ reason: r }
+                    if *r == reason)),
+            "Request {:?} wasn't rejected with {:?}, got {:?}", req, reason, responses
+        );
+    }
+
+    /// Lets the server handle players that took too long to reconnect
+    fn time_out(&mut self, now: Instant) {
+        let responses = process_timeouts(&mut self.state, now);
+        self.deliver(responses);
+    }
+
+    fn deliver(&mut self, responses: Vec<(SocketAddr, Response)>) {
+        send_responses(responses, &self.state);
+        for client in &mut self.clients {
+            while let Some(Some(resp)) = client.rx.next().now_or_never() {
+                let resp = bincode::deserialize::<Response>(&bincode::serialize(&resp).unwrap()).unwrap();
+                client.handle_response(resp);
+            }
+        }
+    }
+
+    /// The client whose turn it is, if any
+    fn turn_client(&self) -> Option<usize> {
+        self.clients.iter().position(|client| client.your_turn)
+    }
+
+    /// Has whoever's turn it is make a move
+    fn play_turn(&mut self, id: GameId) {
+        let client = self.turn_client().expect("Nobody got told it's their turn");
+        let player = self.clients[client].state().player_expect();
+        let (kind, index, action, loc) = self.clients[client].choose_move();
+        self.accept(client, Request::PlaceTile{ id, player, kind, index, action, loc });
+    }
+
+    /// Has Alice and Bob start a game and place their tokens.
+    /// Returns the game's id and the clients in player order.
+    fn start_two_player_game(&mut self, preset: GamePreset) -> (GameId, [usize; 2]) {
+        let clients @ [alice, bob] = [(); 2].map(|_| self.connect());
+        for (client, name) in [(alice, "alice"), (bob, "bob")] {
+            self.accept(client, Request::SetUsername{ username: name.to_owned() });
+        }
+
+        let id = self.create_game(alice, Request::CreateGame{ preset, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+        for client in clients {
+            self.accept(client, Request::JoinGame{ id });
+        }
+        self.accept(alice, Request::StartGame{ id });
+
+        let start_ports: Vec<BasePort> = self.state.game(id).unwrap().game().start_ports();
+        for (player, client) in clients.into_iter().enumerate() {
+            let port = start_ports[player * 10].clone();
+            self.accept(client, Request::PlaceToken{ id, player: player as u32, port });
+        }
+        (id, clients)
+    }
+
+    fn game_state(&self, id: GameId) -> &BaseGameState {
+        self.state.game(id).unwrap().state().as_ref().expect("Game hasn't started")
+    }
+}
+
+fn received_tag(client: &TestClient, tag: ResponseTag) -> bool {
+    client.received.iter().any(|resp| resp.tag() == tag)
+}
+
+#[test]
+fn test_full_game_protocol() {
+    let mut server = TestServer::new();
+    let clients @ [alice, bob, carol, dave] = [(); 4].map(|_| server.connect());
+
+    // Usernames and lobby
+    server.accept(alice, Request::SetUsername{ username: "alice".to_owned() });
+    server.reject(bob, Request::SetUsername{ username: "alice".to_owned() }, RejectReason::UsernameTaken);
+    server.reject(bob, Request::SetUsername{ username: " ".to_owned() }, RejectReason::InvalidUsername{ problem: UsernameProblem::Empty });
+    server.reject(bob, Request::SetUsername{ username: "server".to_owned() }, RejectReason::InvalidUsername{ problem: UsernameProblem::Reserved });
+    for (client, name) in [(bob, "bob"), (carol, "carol"), (dave, "dave")] {
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+    }
+    assert!(clients.iter().all(|client| received_tag(&server.clients[*client], ResponseTag::JoinedLobby)));
+
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Standard, open_hands: false, hand_exchange: true, scout: true, rejection_feed: RejectionFeed::Host, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    assert!(clients.iter().all(|client| received_tag(&server.clients[*client], ResponseTag::ChangedGame)));
+    server.state.set_max_games(Some(1));
+    server.reject(bob, Request::CreateGame{ preset: GamePreset::Blitz, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None }, RejectReason::TooManyGames);
+    assert_eq!(server.state.games().len(), 1);
+    server.state.set_max_games(None);
+
+    // Joining and starting
+    for client in [alice, bob, carol] {
+        server.accept(client, Request::JoinGame{ id });
+    }
+    assert!([alice, bob, carol].iter().all(|client| received_tag(&server.clients[*client], ResponseTag::ChangedPlayers)));
+
+    // Avatars show up next to their players
+    server.accept(bob, Request::SetAvatar{ avatar: Some(Avatar::Fox) });
+    assert!(server.clients[carol].received.iter()
+        .any(|resp| matches!(resp, Response::ChangedPlayers{ avatars, .. } if avatars == &[None, Some(Avatar::Fox), None])));
+    assert_eq!(server.state.game(id).unwrap().to_lobby().avatars(), &[None, Some(Avatar::Fox), None]);
+    server.reject(dave, Request::AddBot{ id, difficulty: BotDifficulty::Easy }, RejectReason::NotAPlayer);
+
+    // Seats can change until the game starts
+    server.reject(dave, Request::ReorderPlayers{ id, order: vec![1, 0, 2] }, RejectReason::NotAPlayer);
+    server.reject(bob, Request::ReorderPlayers{ id, order: vec![1, 1, 2] }, RejectReason::IllegalMove);
+    server.reject(bob, Request::ReorderPlayers{ id, order: vec![1, 0] }, RejectReason::IllegalMove);
+    server.accept(bob, Request::ReorderPlayers{ id, order: vec![1, 0, 2] });
+    assert!(server.clients[carol].received.iter()
+        .any(|resp| matches!(resp, Response::ChangedPlayers{ names, .. } if names == &["bob", "alice", "carol"])));
+    server.accept(alice, Request::ReorderPlayers{ id, order: vec![1, 0, 2] });
+    assert_eq!(server.state.game(id).unwrap().players().iter().map(|player| player.username().as_str()).collect::<Vec<_>>(), ["alice", "bob", "carol"]);
+
+    // So can token colors, as long as nobody else has the same one
+    server.reject(dave, Request::SetColor{ id, color: TokenColor::Blue }, RejectReason::NotAPlayer);
+    server.accept(bob, Request::SetColor{ id, color: TokenColor::Blue });
+    server.accept(bob, Request::SetColor{ id, color: TokenColor::Blue });
+    server.reject(carol, Request::SetColor{ id, color: TokenColor::Blue }, RejectReason::ColorTaken);
+    server.accept(carol, Request::SetColor{ id, color: TokenColor::Green });
+    assert!(server.clients[alice].received.iter().any(|resp| matches!(resp, Response::ChangedColors{ colors, .. }
+        if colors == &[None, Some(TokenColor::Blue), Some(TokenColor::Green)])));
+
+    server.accept(alice, Request::StartGame{ id });
+    server.reject(alice, Request::ReorderPlayers{ id, order: vec![1, 0, 2] }, RejectReason::AlreadyStarted);
+    server.reject(alice, Request::SetColor{ id, color: TokenColor::Red }, RejectReason::AlreadyStarted);
+    server.reject(alice, Request::StartGame{ id }, RejectReason::AlreadyStarted);
+    server.reject(alice, Request::AddBot{ id, difficulty: BotDifficulty::Easy }, RejectReason::AlreadyStarted);
+    assert!([alice, bob, carol].iter().all(|client| received_tag(&server.clients[*client], ResponseTag::StartedGame)));
+
+    // Late joiners spectate
+    server.accept(dave, Request::JoinGame{ id });
+    assert!(!server.clients[dave].state().is_player());
+    assert!(clients.iter().all(|client| server.clients[*client].received.iter()
+        .any(|resp| matches!(resp, Response::ChangedSpectators{ names, .. } if names == &["dave"]))));
+
+    // Nobody gets sent other players' tiles
+    let hand = |client: usize, player: u32| server.clients[client].state().player_state(player).unwrap()
+        .tiles_vec().into_iter().flat_map(|(_, tiles)| tiles).collect::<Vec<_>>();
+    assert!(hand(bob, 0).iter().all(|tile| *tile == tile.redacted()));
+    assert!(hand(bob, 1).iter().all(|tile| *tile != tile.redacted()));
+
+    // Token placement
+    let start_ports: Vec<BasePort> = server.state.game(id).unwrap().game().start_ports();
+    server.reject(bob, Request::PlaceToken{ id, player: 0, port: start_ports[0].clone() }, RejectReason::NotAPlayer);
+    server.reject(dave, Request::PlaceToken{ id, player: 0, port: start_ports[0].clone() }, RejectReason::NotAPlayer);
+    server.reject(bob, Request::PlaceToken{ id, player: 7, port: start_ports[0].clone() }, RejectReason::NotAPlayer);
+    // Only the host hears about rejected moves, and only ones by others
+    assert!(server.clients[alice].received.iter()
+        .any(|resp| matches!(resp, Response::RejectedAttempt{ player: Some(1), reason: RejectReason::NotAPlayer, .. })));
+    assert!(server.clients[alice].received.iter()
+        .any(|resp| matches!(resp, Response::RejectedAttempt{ player: None, .. })));
+    assert!(!received_tag(&server.clients[carol], ResponseTag::RejectedAttempt));
+    for (player, client) in [alice, bob, carol].into_iter().enumerate() {
+        let port = start_ports[player * 10].clone();
+        server.accept(client, Request::PlaceToken{ id, player: player as u32, port });
+        if player == 0 {
+            // Taken port, and moving a placed token
+            server.reject(bob, Request::PlaceToken{ id, player: 1, port: start_ports[0].clone() }, RejectReason::IllegalMove);
+            server.reject(alice, Request::PlaceToken{ id, player: 0, port: start_ports[5].clone() }, RejectReason::TokenAlreadyPlaced);
+        }
+    }
+    assert!(clients.iter().all(|client| received_tag(&server.clients[*client], ResponseTag::AllPlacedTokens)));
+    assert_eq!(server.turn_client(), Some(alice));
+
+    // Nobody can place tiles for someone else or out of turn
+    let (kind, index, action, loc) = server.clients[alice].choose_move();
+    server.reject(bob, Request::PlaceTile{ id, player: 0, kind, index, action, loc }, RejectReason::NotAPlayer);
+    let (kind, index, action, loc) = server.clients[bob].any_move();
+    server.reject(bob, Request::PlaceTile{ id, player: 1, kind: kind.clone(), index, action: action.clone(), loc: loc.clone() }, RejectReason::NotYourTurn{ turn_player: 0 });
+    server.reject(bob, Request::PlaceTile{ id, player: 7, kind, index, action, loc }, RejectReason::NotAPlayer);
+
+    // A tile has to go in front of the token
+    let (kind, index, action, _) = server.clients[alice].choose_move();
+    let far_loc = server.state.game(id).unwrap().game().board().port_locs(&start_ports[10])[0].clone();
+    server.reject(alice, Request::PlaceTile{ id, player: 0, kind, index, action, loc: far_loc }, RejectReason::IllegalMove);
+
+    // Clients that lost track of the game get it again, redacted like before
+    let resynced = server.accept(alice, Request::Resync{ id });
+    assert!(matches!(&resynced[..], [Response::JoinedGame{ .. }, Response::YourTurn{ .. }]));
+    // along with the moves so far, which are only tokens getting placed
+    if let Response::JoinedGame{ game } = &resynced[0] {
+        assert_eq!(game.moves().len(), game.players().len());
+        assert!(game.moves().iter().all(|mv| mv.contains(": start (")));
+    }
+    assert!(server.clients[alice].state().player_state(1).unwrap().tiles_vec().into_iter()
+        .flat_map(|(_, tiles)| tiles).all(|tile| tile == tile.redacted()));
+    assert!(matches!(&server.accept(dave, Request::Resync{ id })[..], [Response::JoinedGame{ .. }]));
+    server.play_turn(id);
+
+    // Bob drops out on his turn, takes too long to come back, and gets skipped
+    assert_eq!(server.turn_client(), Some(bob));
+    server.disconnect(bob);
+    assert!(received_tag(&server.clients[alice], ResponseTag::PlayerConnection));
+    server.state.set_disconnect_grace_period(Duration::ZERO);
+    server.time_out(Instant::now());
+    assert!(server.clients[alice].received.iter()
+        .any(|resp| matches!(resp, Response::SkippedTurn{ player: 1, reason: SkipReason::Disconnected, .. })));
+    server.clients[bob].your_turn = false;
+    server.reconnect(bob);
+    server.accept(bob, Request::SetUsername{ username: "bob".to_owned() });
+    server.accept(bob, Request::JoinGame{ id });
+    assert!(server.clients[bob].state().is_player());
+
+    // Carol exchanges her hand, which she can only do once
+    assert_eq!(server.turn_client(), Some(carol));
+    server.reject(alice, Request::ExchangeHand{ id, player: 2 }, RejectReason::NotAPlayer);
+    server.accept(carol, Request::ExchangeHand{ id, player: 2 });
+    assert!([alice, bob, dave].iter().all(|client| received_tag(&server.clients[*client], ResponseTag::ExchangedHand)));
+    assert_eq!(server.turn_client(), Some(alice));
+
+    // Alice scouts the draw pile without anyone else finding out, and it's still her turn
+    server.reject(bob, Request::Scout{ id, player: 1 }, RejectReason::NotYourTurn{ turn_player: 0 });
+    server.accept(alice, Request::Scout{ id, player: 0 });
+    assert!([bob, carol, dave].iter().all(|client| !received_tag(&server.clients[*client], ResponseTag::Scouted)));
+    let kind = server.game_state(id).num_tiles_left_by_kind()[0].0.clone();
+    assert_eq!(
+        server.clients[alice].state().top_tile_left_of_kind(&kind),
+        server.game_state(id).top_tile_left_of_kind(&kind).map(|tile| tile.with_visible(true)),
+    );
+    server.reject(alice, Request::Scout{ id, player: 0 }, RejectReason::IllegalMove);
+    assert_eq!(server.turn_client(), Some(alice));
+
+    server.play_turn(id);
+    server.play_turn(id);
+    server.reject(carol, Request::ExchangeHand{ id, player: 2 }, RejectReason::IllegalMove);
+
+    // Carol leaves on her turn, so it passes to Alice
+    assert_eq!(server.turn_client(), Some(carol));
+    server.accept(carol, Request::LeaveGame{ id });
+    assert!(received_tag(&server.clients[carol], ResponseTag::JoinedLobby));
+    assert!(received_tag(&server.clients[dave], ResponseTag::LeftGame));
+    assert_eq!(server.turn_client(), Some(alice));
+
+    // Play it out
+    for client in &mut server.clients {
+        client.prefer_death = true;
+    }
+    for _ in 0..100 {
+        if server.game_state(id).game_over() {
+            break;
+        }
+        server.play_turn(id);
+    }
+    assert!(server.game_state(id).game_over());
+    assert!(!server.clients[dave].deaths.is_empty());
+
+    // Everyone still watching agrees with the server
+    let server_state = server.game_state(id).clone();
+    for client in [alice, bob, dave] {
+        let state = server.clients[client].state();
+        assert!(state.game_over());
+        if state.is_player() {
+            let player = state.player_expect();
+            assert_eq!(
+                state.player_state(player).map(|state| state.tiles_vec()),
+                server_state.player_state(player).map(|state| state.tiles_vec()),
+            );
+        }
+        assert_eq!(state.turn_player(), server_state.turn_player());
+        for player in 0..server_state.num_players() {
+            assert_eq!(state.won(player), server_state.won(player));
+            assert_eq!(state.player_state(player).is_some(), server_state.player_state(player).is_some());
+            assert_eq!(state.board_state().player_port(player), server_state.board_state().player_port(player));
+        }
+        assert_eq!(
+            state.board_state().tiles_vec().into_iter().collect::<HashSet<_>>(),
+            server_state.board_state().tiles_vec().into_iter().collect::<HashSet<_>>(),
+        );
+    }
+
+    // The replay got kept, plays out the same way, and can be asked for later
+    let replay_id = match server.clients[dave].received.iter().find(|resp| matches!(resp, Response::ReplaySaved{ .. })) {
+        Some(Response::ReplaySaved{ id: replay_game, replay_id, replay }) => {
+            assert_eq!(*replay_game, id);
+            assert_eq!(replay.usernames(), &["alice", "bob", "carol"]);
+            assert_eq!(
+                bincode::serialize(&replay.final_state()).unwrap(),
+                bincode::serialize(&server_state).unwrap(),
+            );
+            *replay_id
+        }
+        _ => panic!("Spectator never got the replay"),
+    };
+    let num_moves = server.state.game(id).unwrap().replay().as_ref().unwrap().moves().len();
+    match &server.accept(carol, Request::GetReplay{ replay_id })[..] {
+        [Response::Replay{ replay: Some(replay), .. }] => assert_eq!(replay.moves().len(), num_moves),
+        resps => panic!("Unexpected responses to GetReplay: {:?}", resps),
+    }
+    assert!(matches!(
+        server.accept(carol, Request::GetReplay{ replay_id: ReplayId(replay_id.0 + 1) })[..],
+        [Response::Replay{ replay: None, .. }]
+    ));
+
+    server.accept(alice, Request::JoinLobby);
+    assert!(matches!(server.accept(dave, Request::Ping)[..], [Response::Pong]));
+
+    // The finished game gets cleaned up once it's been over for long enough
+    let later = Instant::now() + server.state.idle_timeout();
+    let responses = process_cleanup(&mut server.state, later);
+    server.deliver(responses);
+    assert!(server.state.game(id).is_none());
+    assert!(server.clients[alice].received.iter().any(|resp| matches!(resp, Response::RemovedGame{ id: removed } if *removed == id)));
+    match &server.accept(dave, Request::GetServerInfo)[..] {
+        [Response::ServerInfo{ info }] => assert_eq!(info.num_players(), 4),
+        resps => panic!("Unexpected responses to GetServerInfo: {:?}", resps),
+    }
+
+    // Players get the game in their history, and spectators don't
+    match &server.accept(carol, Request::GetHistory)[..] {
+        [Response::History{ games }] => {
+            assert_eq!(games.len(), 1);
+            assert_eq!(games[0].players(), &["alice", "bob", "carol"]);
+            assert_eq!(games[0].replay_id(), replay_id);
+            assert_eq!(games[0].num_moves() as usize, num_moves);
+            let winners = (0..3).filter(|player| server_state.won(*player)).map(|player| games[0].players()[player as usize].clone()).collect::<Vec<_>>();
+            assert_eq!(games[0].winners(), &winners);
+        }
+        resps => panic!("Unexpected responses to GetHistory: {:?}", resps),
+    }
+    assert!(matches!(&server.accept(dave, Request::GetHistory)[..], [Response::History{ games }] if games.is_empty()));
+    assert!(matches!(&server.accept(dave, Request::GetDaily)[..], [Response::Daily{ results, .. }] if results.is_empty()));
+
+    // The whole protocol got exercised
+    for tag in RequestTag::iter() {
+        // Only games where everyone picks at once have tiles picked ahead of time,
+        // and daily challenges, undos, pauses, rematches, and emotes get their own tests
+        if [
+            RequestTag::CommitTile, RequestTag::PlayDaily, RequestTag::RequestUndo, RequestTag::RespondUndo,
+            RequestTag::PauseGame, RequestTag::ResumeGame, RequestTag::Rematch, RequestTag::Emote,
+        ].contains(&tag) {
+            continue;
+        }
+        assert!(server.requests_sent.contains(&tag), "Request {:?} was never sent", tag);
+    }
+    for tag in ResponseTag::iter() {
+        // The server never sends player indexes on their own, only matches have more than one round,
+        // only games where everyone picks at once have tiles picked ahead of time, and undos, clocks, pauses, and emotes get their own tests
+        if [
+            ResponseTag::PlayerIndex, ResponseTag::StartedRound, ResponseTag::CommittedTile,
+            ResponseTag::UndoRequested, ResponseTag::UndoDeclined, ResponseTag::UndidTurn, ResponseTag::Clocks,
+            ResponseTag::GamePaused, ResponseTag::GameResumed, ResponseTag::Emoted,
+        ].contains(&tag) {
+            continue;
+        }
+        assert!(server.clients.iter().any(|client| received_tag(client, tag)), "Response {:?} was never received", tag);
+    }
+}
+
+#[test]
+fn test_blitz_auto_move() {
+    let mut server = TestServer::new();
+    let (id, [alice, bob]) = server.start_two_player_game(GamePreset::Blitz);
+    assert_eq!(server.turn_client(), Some(alice));
+
+    // Still within the time limit
+    server.time_out(Instant::now());
+    assert!(!received_tag(&server.clients[bob], ResponseTag::PlacedTile));
+
+    // Alice runs out of time, so a move gets made for her
+    server.time_out(Instant::now() + Duration::from_secs(16));
+    assert!(received_tag(&server.clients[bob], ResponseTag::PlacedTile));
+    assert!(!received_tag(&server.clients[bob], ResponseTag::SkippedTurn));
+    assert_ne!(server.game_state(id).turn_player(), 0);
+}
+
+#[test]
+fn test_lobby_updates_coalesced() {
+    let mut server = TestServer::new();
+    let [alice, bob, carol] = [(); 3].map(|_| server.connect());
+    for (client, name) in [(alice, "alice"), (bob, "bob"), (carol, "carol")] {
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+    }
+    let changed_games = |server: &TestServer| server.clients[carol].received.iter()
+        .filter(|resp| resp.tag() == ResponseTag::ChangedGame)
+        .count();
+
+    // The first change goes out right away, and the ones right after it get held back
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Standard, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    server.accept(alice, Request::JoinGame{ id });
+    server.accept(bob, Request::JoinGame{ id });
+    assert_eq!(changed_games(&server), 1);
+
+    server.time_out(Instant::now());
+    assert_eq!(changed_games(&server), 1);
+
+    // Then they get sent together
+    server.time_out(Instant::now() + LOBBY_UPDATE_INTERVAL);
+    assert_eq!(changed_games(&server), 2);
+    server.time_out(Instant::now() + LOBBY_UPDATE_INTERVAL * 2);
+    assert_eq!(changed_games(&server), 2);
+
+    // The lobby only gets what it shows, not the whole state of the game
+    server.accept(alice, Request::StartGame{ id });
+    let game = server.state.game(id).unwrap();
+    let lobby_size = bincode::serialize(&game.to_lobby()).unwrap().len();
+    let full_size = bincode::serialize(&game.to_common_for(Looker::Spectator)).unwrap().len();
+    assert_eq!(game.to_lobby().status(), GameStatus::Started);
+    assert!(lobby_size * 10 < full_size, "Lobby update is {} bytes, the full game {}", lobby_size, full_size);
+}
+
+#[test]
+fn test_cleanup() {
+    let mut server = TestServer::new();
+    let [alice, bob] = ["alice", "bob"].map(|name| {
+        let client = server.connect();
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+        client
+    });
+    let lurker = server.connect();
+    let create = Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None };
+    let empty = server.create_game(alice, create.clone());
+    let joined = server.create_game(alice, create);
+    server.accept(alice, Request::JoinGame{ id: joined });
+    server.accept(alice, Request::AddBot{ id: joined, difficulty: BotDifficulty::Easy });
+
+    // Nothing is idle for long enough yet
+    assert!(process_cleanup(&mut server.state, Instant::now()).is_empty());
+    assert_eq!(server.state.games().len(), 2);
+
+    let later = Instant::now() + server.state.idle_timeout();
+    let responses = process_cleanup(&mut server.state, later);
+    server.deliver(responses);
+    assert!(server.state.game(empty).is_none());
+    assert!(server.state.game(joined).is_some());
+    assert!(matches!(server.clients[bob].received.last(), Some(Response::RemovedGame{ id }) if *id == empty));
+    assert!(server.state.peer(server.clients[lurker].addr).is_none());
+    assert!(server.state.peer(server.clients[alice].addr).is_some());
+    assert_eq!(server.state.server_info().num_players(), 3);
+
+    // Bots go away with the last game they play in
+    let bot = server.state.game(joined).unwrap().players()[1].addr();
+    assert!(server.state.bot_difficulty(bot).is_some());
+    server.state.remove_game(joined);
+    process_cleanup(&mut server.state, Instant::now());
+    assert!(server.state.peer(bot).is_none());
+    assert!(server.state.bot_difficulty(bot).is_none());
+}
+
+#[test]
+fn test_directory_url() {
+    assert_eq!(DirectoryUrl::parse("https://example.com/servers"), None);
+    assert_eq!(DirectoryUrl::parse("http://:80/servers"), None);
+    assert_eq!(DirectoryUrl::parse("http://example.com:port/servers"), None);
+
+    let mut state = State::new();
+    state.set_name("Test".to_owned());
+    let url = DirectoryUrl::parse("http://example.com:8080/api/servers").unwrap();
+    let req = url.announce_request(&state.server_info());
+    assert!(req.starts_with("POST /api/servers HTTP/1.1\r\nHost: example.com\r\n"));
+    assert!(req.ends_with(&serde_json::to_string(&state.server_info()).unwrap()));
+    assert!(DirectoryUrl::parse("http://example.com").unwrap().announce_request(&state.server_info()).starts_with("POST / "));
+}
+
+#[test]
+fn test_bots() {
+    let mut server = TestServer::new();
+    let alice = server.connect();
+    server.accept(alice, Request::SetUsername{ username: "alice".to_owned() });
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    server.accept(alice, Request::JoinGame{ id });
+
+    for difficulty in BotDifficulty::iter() {
+        server.accept(alice, Request::AddBot{ id, difficulty });
+    }
+    let names = server.state.game(id).unwrap().players().iter().map(|player| player.username().clone()).collect::<Vec<_>>();
+    assert_eq!(names, ["alice", "Easy Bot 1", "Medium Bot 2", "Hard Bot 3"]);
+
+    // The bots place their tokens as soon as the game starts
+    server.accept(alice, Request::StartGame{ id });
+    let start_ports: Vec<BasePort> = server.state.game(id).unwrap().game().start_ports();
+    let port = start_ports.into_iter()
+        .find(|port| server.game_state(id).clone().can_place_player(server.state.game(id).unwrap().game(), port))
+        .unwrap();
+    server.accept(alice, Request::PlaceToken{ id, player: 0, port });
+    assert!(server.game_state(id).all_players_placed());
+
+    // Hands can't be exchanged nor piles scouted unless the game was created with those options
+    server.reject(alice, Request::ExchangeHand{ id, player: 0 }, RejectReason::IllegalMove);
+    server.reject(alice, Request::Scout{ id, player: 0 }, RejectReason::IllegalMove);
+
+    // And take their turns right after Alice
+    for _ in 0..100 {
+        if server.game_state(id).game_over() {
+            break;
+        }
+        assert_eq!(server.turn_client(), Some(alice));
+        server.play_turn(id);
+    }
+    assert!(server.game_state(id).game_over());
+    assert!(server.clients[alice].received.iter().any(|resp| matches!(resp, Response::PlacedTile{ player, .. } if *player != 0)));
+}
+
+#[test]
+fn test_journal_replay() {
+    let dir = std::env::temp_dir().join(format!("tsurust-journal-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut server = TestServer::new();
+    server.state.set_journal(Some(Journal::new(dir.clone(), FsyncPolicy::Never).unwrap()));
+    let (id, _) = server.start_two_player_game(GamePreset::Casual);
+    for _ in 0..2 {
+        server.play_turn(id);
+    }
+
+    // A crash in the middle of writing an entry leaves part of it behind
+    let path = dir.join(format!("game-{}.journal", id));
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes.extend([100, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3]);
+    std::fs::write(&path, bytes).unwrap();
+
+    let restore = || {
+        let mut restored = State::new();
+        assert_eq!(journal::restore(&Journal::new(dir.clone(), FsyncPolicy::Never).unwrap(), &mut restored).unwrap(), 1);
+        restored
+    };
+    let serialized = |state: &State| bincode::serialize(state.game(id).unwrap().state().as_ref().unwrap()).unwrap();
+    let mut restored = restore();
+    assert_eq!(serialized(&restored), serialized(&server.state));
+    let game = restored.game(id).unwrap();
+    assert!(game.players().iter().all(|player| !player.connected()));
+    assert_eq!(game.players().iter().map(|player| player.username().clone()).collect::<Vec<_>>(), ["alice", "bob"]);
+
+    // New games don't reuse the restored game's id
+    let game = server.state.game(id).unwrap().game().clone();
+    assert_ne!(restored.add_game(game, GamePreset::Casual.options()).id(), id);
+
+    // Restoring leaves a fresh snapshot behind, so moves made afterwards don't end up after the partial entry
+    server.play_turn(id);
+    assert_eq!(serialized(&restore()), serialized(&server.state));
+
+    // Journals in another format get skipped instead of misread
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[8] += 1;
+    std::fs::write(&path, bytes).unwrap();
+    let mut restored = State::new();
+    assert_eq!(journal::restore(&Journal::new(dir.clone(), FsyncPolicy::Never).unwrap(), &mut restored).unwrap(), 0);
+    assert!(restored.game(id).is_none());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_game_archive() {
+    let dir = std::env::temp_dir().join(format!("tsurust-archive-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut server = TestServer::new();
+    server.state.set_archive_dir(Some(dir.clone()));
+    let (id, _) = server.start_two_player_game(GamePreset::Casual);
+    while !server.game_state(id).game_over() {
+        server.play_turn(id);
+    }
+
+    let paths = std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).collect::<Vec<_>>();
+    assert_eq!(paths.len(), 1);
+    let archive = GameArchive::from_json(&std::fs::read_to_string(&paths[0]).unwrap()).unwrap();
+    assert_eq!(archive.options(), &GamePreset::Casual.options());
+    assert_eq!(archive.replay().usernames(), &["alice", "bob"]);
+    let winners = (0..2).filter(|player| server.game_state(id).won(*player)).count();
+    assert_eq!(archive.summary().winners().len(), winners);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_state_snapshot() {
+    let mut server = TestServer::new();
+    server.state.set_name("Snapshot".to_owned());
+    let (id, [alice, _]) = server.start_two_player_game(GamePreset::Casual);
+    server.play_turn(id);
+    server.accept(alice, Request::LeaveGame{ id });
+    server.accept(alice, Request::CreateGame{ preset: GamePreset::Marathon, open_hands: true, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+
+    let snapshot = server.state.snapshot();
+    let snapshot = bincode::deserialize(&bincode::serialize(&snapshot).unwrap()).unwrap();
+    let mut restored = State::restore(snapshot);
+    assert_eq!(restored.name(), "Snapshot");
+    assert!(restored.peers().is_empty());
+    assert_eq!(restored.games().len(), 2);
+    for game in server.state.games().values() {
+        let restored_game = restored.game(game.id()).unwrap();
+        assert_eq!(game.options(), restored_game.options());
+        assert_eq!(bincode::serialize(game.state()).unwrap(), bincode::serialize(restored_game.state()).unwrap());
+        assert!(restored_game.players().iter().all(|player| !player.connected()));
+    }
+
+    // Games already here don't get restored again, and new games get ids that aren't taken
+    assert_eq!(restored.restore_game(server.state.game(id).unwrap().snapshot(), Instant::now()), None);
+    let game = restored.game(id).unwrap().game().clone();
+    let new_id = restored.add_game(game, GamePreset::Casual.options()).id();
+    assert!(server.state.game(new_id).is_none());
+    assert_eq!(restored.games().len(), 3);
+}
+
+#[test]
+fn test_server_config() {
+    let config = ServerConfig::parse(r#"{ "name": "Configured", "disconnect_grace_period": 30, "journal_fsync": "never" }"#).unwrap();
+    assert_eq!(config.name(), "Configured");
+    assert_eq!(config.region(), "Unknown");
+    assert_eq!(config.disconnect_grace_period().get(), Duration::from_secs(30));
+    assert_eq!(config.journal_fsync(), FsyncPolicy::Never);
+
+    // Limits get checked in the file and in the environment alike
+    assert!(ServerConfig::parse(r#"{ "disconnect_grace_period": 100000 }"#).is_err());
+    assert!(ServerConfig::parse(r#"{ "directory_url": "https://example.com" }"#).is_err());
+    assert!(ServerConfig::parse(r#"{ "max_players": 4 }"#).is_err());
+    assert!(ServerConfig::parse(r#"{ "tls_cert": "cert.pem" }"#).is_err());
+    let tls = ServerConfig::parse(r#"{ "tls_cert": "cert.pem", "tls_key": "key.pem" }"#).unwrap();
+    assert_eq!(tls.tls(), Some((Path::new("cert.pem"), Path::new("key.pem"))));
+    assert_eq!(config.tls(), None);
+
+    let mut overridden = config.clone();
+    overridden.override_with(|var| (var == "DISCONNECT_GRACE_PERIOD").then(|| "0".to_owned())).unwrap();
+    assert_eq!(overridden.disconnect_grace_period().get(), Duration::ZERO);
+    assert_eq!(overridden.name(), "Configured");
+    let mut overridden = config;
+    assert!(overridden.override_with(|var| (var == "DISCONNECT_GRACE_PERIOD").then(|| "100000".to_owned())).is_err());
+
+    // Flags set the same variables as the environment
+    let args = ["--port", "9000", "--max-games=2", "--demo", "--turn-time-limit", "20", "--log-level", "info"];
+    let flags = parse_flags(args.map(str::to_owned)).unwrap();
+    let mut flagged = ServerConfig::default();
+    flagged.override_with(|var| flags.iter().find(|(name, _)| *name == var).map(|(_, value)| value.clone())).unwrap();
+    assert_eq!(flagged.bind_address(), "127.0.0.1:9000".parse().unwrap());
+    assert_eq!(flagged.max_games(), Some(2));
+    assert!(flagged.demo());
+    assert_eq!(flagged.turn_time_limit().map(|limit| limit.get()), Some(Duration::from_secs(20)));
+    assert_eq!(flagged.log_level(), log::LevelFilter::Info);
+    assert!(parse_flags(["--max-players".to_owned()]).is_err());
+    assert!(ServerConfig::parse(r#"{ "bind_address": "localhost" }"#).is_err());
+    assert!(ServerConfig::parse(r#"{ "log_level": "loud" }"#).is_err());
+    assert!(ServerConfig::parse(r#"{ "turn_time_limit": 1 }"#).is_err());
+
+    let filtered = ServerConfig::parse(r#"{ "word_filter": ["darn", "heck*"] }"#).unwrap();
+    assert!(filtered.word_filter().matches("Heckin"));
+    let mut overridden = ServerConfig::default();
+    overridden.override_with(|var| (var == "WORD_FILTER").then(|| "darn,heck*".to_owned())).unwrap();
+    assert_eq!(overridden.word_filter(), filtered.word_filter());
+}
+
+#[test]
+fn test_word_filter() {
+    let filter = WordFilter::new(["darn", "heck*", "*dang", "*gosh*", "*", "--"]);
+    // The last two have no letters
+    assert_eq!(format!("{:?}", filter), "WordFilter(4 patterns)");
+    assert!(filter.matches("well DARN it"));
+    assert!(filter.matches("d4rn"));
+    assert!(filter.matches("d.a.r.n"));
+    assert!(!filter.matches("darning"));
+    assert!(filter.matches("heckin"));
+    assert!(!filter.matches("oheck"));
+    assert!(filter.matches("dagnabdang"));
+    assert!(!filter.matches("dangit"));
+    assert!(filter.matches("ohmyg0shgolly"));
+    assert!(!filter.matches("-- ..."));
+    assert!(!WordFilter::default().matches("darn"));
+
+    assert_eq!(filter.mask("oh  darn, it's\theckin cold"), "oh  ***** it's\t****** cold");
+
+    let mut state = State::new();
+    state.set_word_filter(filter);
+    let addr = SocketAddr::from(([127, 0, 0, 1], 10001));
+    state.add_peer(addr, mpsc::unbounded().0);
+    assert_eq!(state.set_username(addr, " Darn Dude "), Err(RejectReason::InvalidUsername{ problem: UsernameProblem::Filtered }));
+    assert_eq!(state.set_username(addr, "Darnell"), Ok(()));
+}
+
+#[test]
+fn test_server_turn_time_limit() {
+    let mut server = TestServer::new();
+    let alice = server.connect();
+    server.accept(alice, Request::SetUsername{ username: "alice".to_owned() });
+    server.state.set_turn_time_limit(Some(TurnTimeLimit::new(Duration::from_secs(30)).unwrap()));
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    assert_eq!(server.state.game(id).unwrap().options().turn_time_limit(), Some(Duration::from_secs(30)));
+}
+
+#[test]
+fn test_classic_preset() {
+    let mut server = TestServer::new();
+    let carol = server.connect();
+    server.accept(carol, Request::SetUsername{ username: "carol".to_owned() });
+    let id = server.create_game(carol, Request::CreateGame{ preset: GamePreset::Classic, open_hands: true, hand_exchange: true, scout: true, rejection_feed: RejectionFeed::Host, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    let options = server.state.game(id).unwrap().options().clone();
+    assert!(!options.open_hands() && !options.hand_exchange() && !options.scout());
+    assert_eq!(options.rejection_feed(), RejectionFeed::Host);
+
+    // Tokens start on the edge of the board, and the game ends once one player is left or tiles run out
+    let (id, _) = server.start_two_player_game(GamePreset::Classic);
+    let boundary_ports = RectangleBoard::new(6, 6, 2).boundary_ports();
+    assert_eq!(server.state.game(id).unwrap().game().start_ports().len(), boundary_ports.len());
+    while !server.game_state(id).game_over() {
+        server.play_turn(id);
+    }
+    let state = server.game_state(id);
+    let num_alive = (0..2).filter(|player| state.player_state(*player).is_some()).count();
+    assert!(num_alive <= 1 || (0..2).all(|player| state.won(player)));
+}
+
+#[test]
+fn test_octagons_preset() {
+    let mut server = TestServer::new();
+    let (id, _) = server.start_two_player_game(GamePreset::Octagons);
+    assert!(matches!(server.state.game(id).unwrap().game(), BaseGame::SquareOctagon(_)));
+
+    // Players who are out of the kind of tile they're next to get skipped, and the game still ends
+    while !server.game_state(id).game_over() {
+        server.play_turn(id);
+    }
+}
+
+#[test]
+fn test_match_rounds() {
+    let mut server = TestServer::new();
+    let clients @ [alice, bob] = [(); 2].map(|_| server.connect());
+    for (client, name) in [(alice, "alice"), (bob, "bob")] {
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+    }
+    let rounds = MatchRounds::new(3).unwrap();
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds, simultaneous: false, time_control: None, spectator_delay: None });
+    for client in clients {
+        server.accept(client, Request::JoinGame{ id });
+    }
+    server.accept(alice, Request::StartGame{ id });
+
+    // Each round starts on a fresh board, until someone won 2 rounds or all 3 were played
+    let start_ports: Vec<BasePort> = server.state.game(id).unwrap().game().start_ports();
+    let mut rounds_played = 0;
+    while !server.game_state(id).game_over() {
+        rounds_played += 1;
+        let round = server.state.game(id).unwrap().match_score().as_ref().unwrap().round();
+        assert_eq!(round, rounds_played);
+        assert!(server.game_state(id).board_state().tiles_vec().is_empty());
+        for (player, client) in clients.into_iter().enumerate() {
+            let port = start_ports[player * 10].clone();
+            server.accept(client, Request::PlaceToken{ id, player: player as u32, port });
+        }
+        while server.state.game(id).unwrap().match_score().as_ref().unwrap().round() == round && !server.game_state(id).game_over() {
+            server.play_turn(id);
+        }
+    }
+    assert!((2..=3).contains(&rounds_played));
+    let received_rounds = server.clients[bob].received.iter()
+        .filter(|resp| matches!(resp, Response::StartedRound{ .. }))
+        .count();
+    assert_eq!(received_rounds, rounds_played as usize - 1);
+    assert!(received_tag(&server.clients[alice], ResponseTag::ReplaySaved));
+
+    // The history has whoever won the most rounds
+    let score = server.state.game(id).unwrap().match_score().clone().unwrap();
+    assert!(score.decided());
+    match &server.accept(alice, Request::GetHistory)[..] {
+        [Response::History{ games }] => {
+            let winners = score.leaders().into_iter().map(|player| ["alice", "bob"][player as usize].to_owned()).collect::<Vec<_>>();
+            assert_eq!(games[0].winners(), &winners);
+        }
+        resps => panic!("Unexpected responses to GetHistory: {:?}", resps),
+    }
+}
+
+
+#[test]
+fn test_simultaneous_placement() {
+    let mut server = TestServer::new();
+    let clients @ [alice, bob] = [(); 2].map(|_| server.connect());
+    for (client, name) in [(alice, "alice"), (bob, "bob")] {
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+    }
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: true, time_control: None, spectator_delay: None });
+    for client in clients {
+        server.accept(client, Request::JoinGame{ id });
+    }
+    server.accept(alice, Request::StartGame{ id });
+    let start_ports: Vec<BasePort> = server.state.game(id).unwrap().game().start_ports();
+    for (player, client) in clients.into_iter().enumerate() {
+        let port = start_ports[player * 10].clone();
+        server.accept(client, Request::PlaceToken{ id, player: player as u32, port });
+    }
+    assert!(clients.iter().all(|client| server.clients[*client].your_turn));
+
+    // Tiles only get picked, not placed straight away
+    let (kind, index, action, loc) = server.clients[alice].choose_move();
+    server.reject(alice, Request::PlaceTile{ id, player: 0, kind: kind.clone(), index, action: action.clone(), loc: loc.clone() }, RejectReason::IllegalMove);
+    server.accept(alice, Request::CommitTile{ id, player: 0, kind: kind.clone(), index, action: action.clone(), loc: loc.clone() });
+    assert!(received_tag(&server.clients[bob], ResponseTag::CommittedTile));
+    assert!(!received_tag(&server.clients[bob], ResponseTag::PlacedTile));
+    assert!(!server.clients[alice].your_turn);
+    server.reject(alice, Request::CommitTile{ id, player: 0, kind, index, action, loc }, RejectReason::IllegalMove);
+
+    // Once everyone picked, the picks get played out in turn order, and everyone picks again
+    let (kind, index, action, loc) = server.clients[bob].choose_move();
+    server.accept(bob, Request::CommitTile{ id, player: 1, kind, index, action, loc });
+    let placed = server.clients[bob].received.iter().filter(|resp| matches!(resp, Response::PlacedTile{ .. })).count();
+    let skipped = server.clients[bob].received.iter().filter(|resp| matches!(resp, Response::SkippedTurn{ .. })).count();
+    assert_eq!(placed + skipped, 2);
+    assert_eq!(server.clients[bob].state().board_state().tiles_vec().len(), server.game_state(id).board_state().tiles_vec().len());
+    assert_eq!(server.clients[bob].state().turn_player(), server.game_state(id).turn_player());
+    if !server.game_state(id).game_over() {
+        assert!(clients.iter().all(|client| server.clients[*client].your_turn));
+    }
+}
+#[test]
+fn test_undo() {
+    let mut server = TestServer::new();
+    let clients @ [alice, bob] = [(); 2].map(|_| server.connect());
+    for (client, name) in [(alice, "alice"), (bob, "bob")] {
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+    }
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    for client in clients {
+        server.accept(client, Request::JoinGame{ id });
+    }
+    server.accept(alice, Request::AddBot{ id, difficulty: BotDifficulty::Easy });
+    server.accept(alice, Request::StartGame{ id });
+    let start_ports: Vec<BasePort> = server.state.game(id).unwrap().game().start_ports();
+    for (player, client) in clients.into_iter().enumerate() {
+        let port = start_ports[player * 10].clone();
+        server.accept(client, Request::PlaceToken{ id, player: player as u32, port });
+    }
+    server.reject(alice, Request::RequestUndo{ id }, RejectReason::IllegalMove);
+    server.play_turn(id);
+
+    // Only whoever placed the tile can ask, once at a time, and the bot accepts right away
+    assert_eq!(server.turn_client(), Some(bob));
+    server.reject(bob, Request::RequestUndo{ id }, RejectReason::IllegalMove);
+    server.accept(alice, Request::RequestUndo{ id });
+    assert!(received_tag(&server.clients[bob], ResponseTag::UndoRequested));
+    server.reject(alice, Request::RequestUndo{ id }, RejectReason::IllegalMove);
+    server.reject(alice, Request::RespondUndo{ id, accept: true }, RejectReason::IllegalMove);
+    assert_eq!(server.game_state(id).board_state().tiles_vec().len(), 1);
+
+    // Bob turns it down, so the tile stays
+    server.accept(bob, Request::RespondUndo{ id, accept: false });
+    assert!(received_tag(&server.clients[alice], ResponseTag::UndoDeclined));
+    server.reject(bob, Request::RespondUndo{ id, accept: true }, RejectReason::IllegalMove);
+    assert_eq!(server.turn_client(), Some(bob));
+
+    // Once everyone accepts, the tile comes off and it's Alice's turn again
+    server.accept(alice, Request::RequestUndo{ id });
+    server.accept(bob, Request::RespondUndo{ id, accept: true });
+    assert!(clients.iter().all(|client| received_tag(&server.clients[*client], ResponseTag::UndidTurn)));
+    assert!(server.clients[bob].received.iter()
+        .any(|resp| matches!(resp, Response::UndidTurn{ game } if game.moves().len() == 3)));
+    assert_eq!(server.turn_client(), Some(alice));
+    assert!(server.game_state(id).board_state().tiles_vec().is_empty());
+    assert_eq!(server.state.game(id).unwrap().replay().as_ref().unwrap().moves().len(), 3);
+    assert_eq!(
+        server.clients[alice].state().player_state(0).map(|state| state.tiles_vec()),
+        server.game_state(id).player_state(0).map(|state| state.tiles_vec()),
+    );
+    assert!(server.clients[bob].state().board_state().tiles_vec().is_empty());
+
+    // Requests go stale once someone moves
+    server.play_turn(id);
+    server.accept(alice, Request::RequestUndo{ id });
+    server.play_turn(id);
+    server.reject(bob, Request::RespondUndo{ id, accept: true }, RejectReason::IllegalMove);
+}
+
+#[test]
+fn test_daily_challenge() {
+    let mut server = TestServer::new();
+    let [alice, bob] = [(); 2].map(|_| server.connect());
+    for (client, name) in [(alice, "alice"), (bob, "bob")] {
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+        server.accept(client, Request::JoinLobby);
+    }
+    assert!(matches!(&server.accept(bob, Request::GetDaily)[..], [Response::Daily{ results, .. }] if results.is_empty()));
+
+    // Alice gets put straight into a game against bots, with the day's tiles
+    server.accept(alice, Request::PlayDaily);
+    let id = server.clients[alice].game.as_ref().map(|(id, _)| *id).expect("Alice didn't join the daily challenge");
+    let game = server.state.game(id).unwrap();
+    assert_eq!(game.daily(), Some(today()));
+    assert_eq!(game.players().len(), 1 + DAILY_BOTS as usize);
+    assert_eq!(game.options().seed(), Some(today().seed()));
+    assert!(game.started());
+
+    // Nobody else sees it or gets in
+    assert!(!server.clients[bob].received.iter().any(|resp| matches!(resp, Response::ChangedGame{ game } if game.id() == id)));
+    assert!(matches!(&server.accept(bob, Request::JoinLobby)[..], [Response::JoinedLobby{ games }] if games.is_empty()));
+    server.reject(bob, Request::JoinGame{ id }, RejectReason::NoSuchGame);
+
+    let start_ports: Vec<BasePort> = server.state.game(id).unwrap().game().start_ports();
+    let port = start_ports.into_iter()
+        .find(|port| server.game_state(id).clone().can_place_player(server.state.game(id).unwrap().game(), port))
+        .unwrap();
+    server.accept(alice, Request::PlaceToken{ id, player: 0, port });
+    for _ in 0..100 {
+        if server.game_state(id).game_over() {
+            break;
+        }
+        server.play_turn(id);
+    }
+    assert!(server.game_state(id).game_over());
+
+    // Her result goes on the day's leaderboard, and she only gets one try
+    let result = DailyResult::new("alice".to_owned(), server.game_state(id).won(0), server.game_state(id).scores()[0]);
+    assert!(matches!(server.clients[alice].received.last(), Some(Response::Daily{ results, .. }) if results.len() == 1 && results[0] == result));
+    match &server.accept(bob, Request::GetDaily)[..] {
+        [Response::Daily{ day, results }] => {
+            assert_eq!(*day, today());
+            assert_eq!(results, &[result]);
+        }
+        resps => panic!("Unexpected responses to GetDaily: {:?}", resps),
+    }
+    server.accept(alice, Request::LeaveGame{ id });
+    server.reject(alice, Request::PlayDaily, RejectReason::AlreadyPlayedDaily);
+}
+
+#[test]
+fn test_http() {
+    let head = "GET /ws?x=1 HTTP/1.1\r\nHost: localhost\r\nUpgrade: WebSocket\r\nConnection: Upgrade";
+    let request = HttpRequest::parse(head).unwrap();
+    assert_eq!(request.path(), http::WS_PATH);
+    assert_eq!(request.header("host"), Some("localhost"));
+    assert!(request.is_websocket_upgrade());
+    assert!(HttpRequest::parse("nonsense").is_none());
+
+    // The game gets to read the request head again
+    let bytes = format!("{}\r\n\r\nframe", head).into_bytes();
+    let (request, mut stream) = futures::executor::block_on(http::read_request(futures::io::Cursor::new(bytes.clone()))).unwrap();
+    assert_eq!(request.method(), "GET");
+    let mut reread = vec![];
+    futures::executor::block_on(futures::AsyncReadExt::read_to_end(&mut stream, &mut reread)).unwrap();
+    assert_eq!(reread, bytes);
+
+    let dir = std::env::temp_dir().join(format!("tsurust-static-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("pkg")).unwrap();
+    std::fs::write(dir.join("index.html"), "<html></html>").unwrap();
+    assert_eq!(http::resolve_path(&dir, "/"), Some(dir.join("index.html")));
+    assert_eq!(http::resolve_path(&dir, "/pkg/client.js"), Some(dir.join("pkg/client.js")));
+    assert_eq!(http::resolve_path(&dir, "/../secret"), None);
+    assert_eq!(http::content_type(Path::new("client_bg.wasm")), "application/wasm");
+
+    let serve = |path: &str| {
+        let request = HttpRequest::parse(&format!("GET {} HTTP/1.1", path)).unwrap();
+        let mut response = vec![];
+        futures::executor::block_on(http::serve_file(&mut response, &request, &dir)).unwrap();
+        String::from_utf8(response).unwrap()
+    };
+    let index = serve("/");
+    assert!(index.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(index.contains("Content-Type: text/html"));
+    assert!(index.ends_with("\r\n\r\n<html></html>"));
+    assert!(serve("/missing.js").starts_with("HTTP/1.1 404"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_api() {
+    let mut server = TestServer::new();
+    let (id, _) = server.start_two_player_game(GamePreset::Blitz);
+    let carol = server.connect();
+    server.accept(carol, Request::SetUsername{ username: "carol".to_owned() });
+    server.accept(carol, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+
+    let status: serde_json::Value = serde_json::from_str(&api::get("/api/status", &server.state).unwrap()).unwrap();
+    assert_eq!(status["num_players"], 3);
+    assert_eq!(status["num_games"], 2);
+    assert_eq!(status["num_games_started"], 1);
+
+    let games: Vec<serde_json::Value> = serde_json::from_str(&api::get("/api/games", &server.state).unwrap()).unwrap();
+    let started = games.iter().find(|game| game["started"] == true).unwrap();
+    assert_eq!(started["players"], serde_json::json!(["alice", "bob"]));
+    assert_eq!(started["game_over"], false);
+    assert_eq!(started["id"], serde_json::json!(id.to_string()));
+    let not_started = games.iter().find(|game| game["started"] == false).unwrap();
+    assert_eq!(not_started["players"], serde_json::json!([]));
+    assert!(api::get("/api/nothing", &server.state).is_none());
+
+    // Without a static directory, only the API gets served
+    let state = async_std::sync::Mutex::new(server.state);
+    let serve = |path: &str| {
+        let request = HttpRequest::parse(&format!("GET {} HTTP/1.1", path)).unwrap();
+        let mut response = vec![];
+        futures::executor::block_on(http::serve(&mut response, &request, &state, None)).unwrap();
+        String::from_utf8(response).unwrap()
+    };
+    assert!(serve("/api/status").contains("Content-Type: application/json"));
+    assert!(serve("/index.html").starts_with("HTTP/1.1 404"));
+}
+
+#[test]
+fn test_metrics() {
+    let mut server = TestServer::new();
+    let (id, [alice, _]) = server.start_two_player_game(GamePreset::Casual);
+    server.reject(alice, Request::StartGame{ id }, RejectReason::AlreadyStarted);
+    server.state.metrics_mut().connection_opened();
+    server.play_turn(id);
+
+    let text = metrics::render(&server.state);
+    assert!(text.contains("# TYPE tsurust_peers gauge\ntsurust_peers 2\n"));
+    assert!(text.contains("tsurust_games{started=\"true\"} 1\n"));
+    assert!(text.contains("tsurust_connections_total 1\n"));
+    assert!(text.contains("tsurust_requests_total{type=\"StartGame\"} 2\n"));
+    assert!(text.contains("tsurust_rejections_total{reason=\"AlreadyStarted\"} 1\n"));
+    // Both tokens and a tile got placed
+    assert!(text.contains("tsurust_turn_latency_seconds_bucket{le=\"+Inf\"} 3\n"));
+    assert!(text.contains("tsurust_turn_latency_seconds_count 3\n"));
+}
+
+#[test]
+fn test_peer_queue() {
+    let mut server = TestServer::new();
+    let alice = server.connect();
+    server.accept(alice, Request::SetUsername{ username: "alice".to_owned() });
+    let ids = [(); 2].map(|_| {
+        server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None })
+    });
+    let empty_game = server.state.game(ids[0]).unwrap().to_lobby();
+    server.accept(alice, Request::JoinGame{ id: ids[0] });
+    let game = |i: usize| server.state.game(ids[i]).unwrap().to_lobby();
+
+    let (tx, mut rx) = queue::bounded(3);
+    tx.send(Response::ChangedGame{ game: empty_game }).unwrap();
+    tx.send(Response::Pong).unwrap();
+    // Lobby updates of the same game replace the one still waiting
+    tx.send(Response::ChangedGame{ game: game(0) }).unwrap();
+    tx.send(Response::ChangedGame{ game: game(1) }).unwrap();
+    match futures::executor::block_on(rx.next()) {
+        Some(Response::ChangedGame{ game }) => assert_eq!(game.players(), &["alice"]),
+        resp => panic!("Unexpected response {:?}", resp),
+    }
+
+    // A peer that falls too far behind gets nothing more
+    tx.send(Response::Pong).unwrap();
+    assert!(tx.send(Response::Pong).is_err());
+    assert!(futures::executor::block_on(rx.next()).is_none());
+}
+
+#[test]
+fn test_broadcast() {
+    let mut server = TestServer::new();
+    let clients = ["alice", "bob", "carol"].map(|name| {
+        let client = server.connect();
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+        client
+    });
+    let id = server.create_game(clients[0], Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    server.accept(clients[1], Request::JoinGame{ id });
+    let addr = |client: usize| server.clients[client].addr;
+
+    let recipients = |responses: Vec<(SocketAddr, Response)>| responses.into_iter().map(|(addr, _)| addr).collect::<HashSet<_>>();
+    assert_eq!(recipients(server.state.broadcast_to_game(id, Response::Pong)), HashSet::from([addr(clients[1])]));
+    assert_eq!(recipients(server.state.broadcast_to_lobby(Response::Pong)), HashSet::from([addr(clients[0]), addr(clients[2])]));
+    assert!(server.state.broadcast_to_game(GameId(id.0.wrapping_add(1)), Response::Pong).is_empty());
+    assert_eq!(server.state.send_to(addr(clients[2]), Response::Pong).len(), 1);
+}
+
+#[test]
+fn test_spectator_list() {
+    let mut server = TestServer::new();
+    let (id, [alice, bob]) = server.start_two_player_game(GamePreset::Casual);
+    let [carol, dave] = ["carol", "dave"].map(|name| {
+        let client = server.connect();
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+        client
+    });
+    let spectators = |responses: &[Response]| responses.iter().rev().find_map(|resp| match resp {
+        Response::ChangedSpectators{ names, .. } => Some(names.clone()),
+        _ => None,
+    });
+
+    server.accept(carol, Request::JoinGame{ id });
+    let responses = server.accept(dave, Request::JoinGame{ id });
+    match &responses[..] {
+        [Response::JoinedGame{ game }, ..] => assert_eq!(game.spectators(), &["carol", "dave"]),
+        responses => panic!("Unexpected responses {:?}", responses),
+    }
+    assert_eq!(spectators(&responses), Some(vec!["carol".to_owned(), "dave".to_owned()]));
+
+    // Players get told when spectators leave, but not when someone leaves a game they weren't watching
+    let responses = server.accept(carol, Request::LeaveGame{ id });
+    assert_eq!(spectators(&server.clients[alice].received), Some(vec!["dave".to_owned()]));
+    assert_eq!(spectators(&responses), None);
+    server.accept(carol, Request::JoinLobby);
+    assert_eq!(spectators(&server.clients[bob].received), Some(vec!["dave".to_owned()]));
+    assert_eq!(server.state.game(id).unwrap().to_common_for(Looker::Spectator).spectators(), &["dave"]);
+}
+
+#[test]
+fn test_history() {
+    let summary = |moves: u32| GameSummary::new(vec!["alice".to_owned(), "bob".to_owned()], vec![], moves, Duration::ZERO, ReplayId(moves));
+    let mut history = History::default();
+    for moves in 0..history::MAX_RESULTS_PER_USER as u32 + 5 {
+        history.record(&summary(moves), &["alice".to_owned()]);
+    }
+    let recent = history.recent("alice");
+    assert_eq!(recent.len(), history::MAX_RESULTS_PER_USER);
+    assert_eq!(recent[0].num_moves(), history::MAX_RESULTS_PER_USER as u32 + 4);
+    assert!(history.recent("bob").is_empty());
+
+    // Usernames that haven't finished a game in the longest time make room for new ones
+    for i in 0..history::MAX_USERS {
+        history.record(&summary(0), &[format!("player {}", i)]);
+    }
+    assert_eq!(history.num_users(), history::MAX_USERS);
+    assert!(history.recent("alice").is_empty());
+    assert_eq!(history.recent("player 0").len(), 1);
+
+    // Bots don't get a history
+    let mut server = TestServer::new();
+    let alice = server.connect();
+    server.accept(alice, Request::SetUsername{ username: "alice".to_owned() });
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    server.accept(alice, Request::JoinGame{ id });
+    server.accept(alice, Request::AddBot{ id, difficulty: BotDifficulty::Easy });
+    server.accept(alice, Request::StartGame{ id });
+    let bot = server.state.game(id).unwrap().players()[1].username().clone();
+    assert!(server.state.is_bot(&bot));
+    assert!(!server.state.is_bot("alice"));
+
+    let bot_port = server.game_state(id).board_state().player_port(1).expect("Bot didn't place its token");
+    let port = server.state.game(id).unwrap().game().start_ports().into_iter().find(|port| *port != bot_port).unwrap();
+    server.accept(alice, Request::PlaceToken{ id, player: 0, port });
+    while !server.game_state(id).game_over() {
+        server.play_turn(id);
+    }
+    assert_eq!(server.state.history().num_users(), 1);
+    assert_eq!(server.state.history().recent("alice")[0].players(), &["alice".to_owned(), bot]);
+}
+
+/// Carries a peer's messages over in-memory channels instead of a web socket
+struct ChannelTransport {
+    requests: UnboundedReceiver<FromPeer>,
+    responses: UnboundedSender<ToPeer>,
+}
+
+impl PeerTransport for ChannelTransport {
+    type Error = mpsc::SendError;
+    type Receiver = stream::Map<UnboundedReceiver<FromPeer>, fn(FromPeer) -> Result<FromPeer, mpsc::SendError>>;
+    type Sender = UnboundedSender<ToPeer>;
+
+    fn split(self) -> (Self::Sender, Self::Receiver) {
+        (self.responses, self.requests.map(Ok as fn(_) -> _))
+    }
+}
+
+/// The other end of a `ChannelTransport`, served by `serve_peer` like a real connection
+struct ChannelPeer {
+    tx: UnboundedSender<FromPeer>,
+    rx: UnboundedReceiver<ToPeer>,
+}
+
+impl ChannelPeer {
+    fn connect(addr: SocketAddr, state: &Arc<Mutex<State>>) -> (Self, JoinHandle<Result<(), mpsc::SendError>>) {
+        let (tx, requests) = mpsc::unbounded();
+        let (responses, rx) = mpsc::unbounded();
+        let task = async_std::task::spawn(serve_peer(addr, ChannelTransport { requests, responses }, Arc::clone(state)));
+        (Self { tx, rx }, task)
+    }
+
+    fn send(&self, req: Request) {
+        self.tx.unbounded_send(FromPeer::Request(req)).unwrap();
+    }
+
+    /// Waits for the first response `f` picks something out of, skipping the ones before it
+    async fn expect<T>(&mut self, mut f: impl FnMut(Response) -> Option<T>) -> T {
+        loop {
+            match async_std::future::timeout(Duration::from_secs(5), self.rx.next()).await {
+                Ok(Some(ToPeer::Response(resp))) => if let Some(found) = f(resp) { return found; },
+                Ok(Some(ToPeer::Ping)) => {}
+                Ok(None) => panic!("Peer got disconnected"),
+                Err(_) => panic!("Timed out waiting for a response"),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_channel_peers() {
+    async_std::task::block_on(async {
+        let state = Arc::new(Mutex::new(State::new()));
+        let addrs = [10001, 10002].map(|port| SocketAddr::from(([127, 0, 0, 1], port)));
+        let (mut alice, alice_task) = ChannelPeer::connect(addrs[0], &state);
+        let (mut bob, _bob_task) = ChannelPeer::connect(addrs[1], &state);
+
+        for (peer, name) in [(&mut alice, "alice"), (&mut bob, "bob")] {
+            peer.send(Request::SetUsername{ username: name.to_owned() });
+            peer.expect(|resp| matches!(resp, Response::JoinedLobby{ .. }).then_some(())).await;
+        }
+
+        alice.send(Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+        let id = bob.expect(|resp| match resp {
+            Response::ChangedGame{ game } => Some(game.id()),
+            _ => None,
+        }).await;
+        for peer in [&mut alice, &mut bob] {
+            peer.send(Request::JoinGame{ id });
+            peer.expect(|resp| matches!(resp, Response::JoinedGame{ .. }).then_some(())).await;
+        }
+
+        alice.send(Request::StartGame{ id });
+        let start_ports = state.lock().await.game(id).unwrap().game().start_ports();
+        for (player, peer) in [&mut alice, &mut bob].into_iter().enumerate() {
+            peer.expect(|resp| matches!(resp, Response::StartedGame{ .. }).then_some(())).await;
+            peer.send(Request::PlaceToken{ id, player: player as u32, port: start_ports[player * 10].clone() });
+        }
+        alice.expect(|resp| matches!(resp, Response::AllPlacedTokens{ .. }).then_some(())).await;
+
+        // Bob leaving leaves Alice the winner
+        bob.send(Request::LeaveGame{ id });
+        let replay = alice.expect(|resp| match resp {
+            Response::ReplaySaved{ replay, .. } => Some(replay),
+            _ => None,
+        }).await;
+        assert!(replay.final_state().won(0));
+
+        // Closing the connection removes the peer
+        drop(alice.tx);
+        alice_task.await.unwrap();
+        assert!(state.lock().await.peer(addrs[0]).is_none());
+    });
+}
+
+#[test]
+fn test_rate_limit() {
+    let start = Instant::now();
+    let mut limiter = RateLimiter::new(2, 1.0, start);
+    assert_eq!(limiter.check(start), Verdict::Allow);
+    assert_eq!(limiter.check(start), Verdict::Allow);
+    assert_eq!(limiter.check(start), Verdict::Drop);
+    // Tokens come back over time, but no more than the bucket holds
+    assert_eq!(limiter.check(start + Duration::from_secs(1)), Verdict::Allow);
+    let later = start + Duration::from_secs(60);
+    assert_eq!(limiter.check(later), Verdict::Allow);
+    assert_eq!(limiter.check(later), Verdict::Allow);
+    for _ in 0..rate_limit::MAX_DROPPED_IN_A_ROW {
+        assert_eq!(limiter.check(later), Verdict::Drop);
+    }
+    assert_eq!(limiter.check(later), Verdict::Disconnect);
+
+    // A peer that floods the server gets disconnected
+    async_std::task::block_on(async {
+        let state = Arc::new(Mutex::new(State::new()));
+        let addr = SocketAddr::from(([127, 0, 0, 1], 10001));
+        let (peer, task) = ChannelPeer::connect(addr, &state);
+        // The server can hang up partway through the flood, after which sending fails
+        for _ in 0..rate_limit::REQUEST_BURST + rate_limit::MAX_DROPPED_IN_A_ROW + 10 {
+            if peer.tx.unbounded_send(FromPeer::Request(Request::Ping)).is_err() {
+                break;
+            }
+        }
+        async_std::future::timeout(Duration::from_secs(5), task).await.expect("Flooding peer stayed connected").unwrap();
+        assert!(state.lock().await.peer(addr).is_none());
+    });
+}
+
+/// The last clocks a client was told about
+fn last_clocks(client: &TestClient) -> Option<&Clocks> {
+    client.received.iter().rev().find_map(|resp| match resp {
+        Response::Clocks{ clocks, .. } => Some(clocks),
+        _ => None,
+    })
+}
+
+#[test]
+fn test_clocks() {
+    let mut server = TestServer::new();
+    let clients @ [alice, bob] = [(); 2].map(|_| server.connect());
+    for (client, name) in [(alice, "alice"), (bob, "bob")] {
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+    }
+    let time_control = Some("1+5".parse::<TimeControl>().unwrap());
+
+    // Clocks run on a player's own turn, which games where everyone picks at once don't have
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: true, time_control, spectator_delay: None });
+    assert_eq!(server.state.game(id).unwrap().options().time_control(), None);
+    server.accept(alice, Request::LeaveGame{ id });
+
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control, spectator_delay: None });
+    for client in clients {
+        server.accept(client, Request::JoinGame{ id });
+    }
+    server.accept(alice, Request::StartGame{ id });
+    let start_ports: Vec<BasePort> = server.state.game(id).unwrap().game().start_ports();
+    for (player, client) in clients.into_iter().enumerate() {
+        assert_eq!(last_clocks(&server.clients[client]), None);
+        let port = start_ports[player * 10].clone();
+        server.accept(client, Request::PlaceToken{ id, player: player as u32, port });
+    }
+
+    // Alice's clock starts once everyone's placed their token, and she gets the increment for moving
+    let clocks = last_clocks(&server.clients[bob]).expect("Clocks didn't start").clone();
+    assert_eq!(clocks.running(), Some(0));
+    assert!(clocks.remaining()[0] <= Duration::from_secs(60));
+    assert_eq!(clocks.remaining()[1], Duration::from_secs(60));
+    server.play_turn(id);
+    let clocks = last_clocks(&server.clients[bob]).unwrap().clone();
+    assert_eq!(clocks.running(), Some(1));
+    assert!(clocks.remaining()[0] > Duration::from_secs(60));
+    assert!((Duration::from_secs(59)..=Duration::from_secs(60)).contains(&clocks.remaining()[1]));
+
+    // Running out of time on the clock loses Bob the game, where a turn time limit would only skip his turn
+    server.time_out(Instant::now() + Duration::from_secs(59));
+    assert!(server.game_state(id).player_state(1).is_some());
+    server.time_out(Instant::now() + Duration::from_secs(61));
+    assert!(server.game_state(id).player_state(1).is_none());
+    assert!(server.game_state(id).won(0));
+    assert!(received_tag(&server.clients[alice], ResponseTag::LeftGame));
+}
+
+#[test]
+fn test_pause() {
+    let mut server = TestServer::new();
+    let clients @ [alice, bob, carol] = [(); 3].map(|_| server.connect());
+    for (client, name) in [(alice, "alice"), (bob, "bob"), (carol, "carol")] {
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+    }
+    server.state.set_turn_time_limit(Some(TurnTimeLimit::new(Duration::from_secs(30)).unwrap()));
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    for client in clients {
+        server.accept(client, Request::JoinGame{ id });
+    }
+    server.reject(bob, Request::PauseGame{ id }, RejectReason::NotStarted);
+    server.accept(alice, Request::StartGame{ id });
+    let start_ports: Vec<BasePort> = server.state.game(id).unwrap().game().start_ports();
+    for (player, client) in clients.into_iter().enumerate() {
+        let port = start_ports[player * 10].clone();
+        server.accept(client, Request::PlaceToken{ id, player: player as u32, port });
+    }
+
+    // Bob alone isn't enough, but most of the players are
+    server.accept(bob, Request::PauseGame{ id });
+    assert!(!server.state.game(id).unwrap().paused());
+    server.reject(bob, Request::PauseGame{ id }, RejectReason::IllegalMove);
+    server.accept(carol, Request::PauseGame{ id });
+    assert!(server.state.game(id).unwrap().paused());
+    assert!(clients.iter().all(|client| received_tag(&server.clients[*client], ResponseTag::GamePaused)));
+    server.reject(carol, Request::PauseGame{ id }, RejectReason::IllegalMove);
+
+    // Nobody can move, and nobody runs out of time
+    assert_eq!(server.turn_client(), Some(alice));
+    let (kind, index, action, loc) = server.clients[alice].choose_move();
+    server.reject(alice, Request::PlaceTile{ id, player: 0, kind, index, action, loc }, RejectReason::Paused);
+    server.time_out(Instant::now() + Duration::from_secs(60 * 60));
+    assert!(!received_tag(&server.clients[alice], ResponseTag::SkippedTurn));
+
+    // The host's word is enough, and the turn picks up where it left off
+    server.accept(alice, Request::ResumeGame{ id });
+    assert!(!server.state.game(id).unwrap().paused());
+    assert!(clients.iter().all(|client| received_tag(&server.clients[*client], ResponseTag::GameResumed)));
+    server.time_out(Instant::now() + Duration::from_secs(20));
+    assert!(!received_tag(&server.clients[alice], ResponseTag::SkippedTurn));
+    server.play_turn(id);
+    assert_eq!(server.turn_client(), Some(bob));
+}
+
+#[test]
+fn test_rematch() {
+    let mut server = TestServer::new();
+    let (id, [alice, bob]) = server.start_two_player_game(GamePreset::Casual);
+    let carol = server.connect();
+    server.accept(carol, Request::SetUsername{ username: "carol".to_owned() });
+    server.reject(alice, Request::Rematch{ id }, RejectReason::IllegalMove);
+    while !server.game_state(id).game_over() {
+        server.play_turn(id);
+    }
+    server.reject(carol, Request::Rematch{ id }, RejectReason::NotAPlayer);
+
+    // Whoever asks first creates the rematch, and whoever asks next joins it, one seat over
+    let rematch = server.create_game(bob, Request::Rematch{ id });
+    assert!(server.clients[bob].received.iter().any(|resp| matches!(resp, Response::JoinedGame{ game } if game.id() == rematch)));
+    assert_eq!(server.state.game(rematch).unwrap().options(), server.state.game(id).unwrap().options());
+    server.accept(alice, Request::Rematch{ id });
+    assert!(server.clients[alice].received.iter().any(|resp| matches!(resp, Response::JoinedGame{ game } if game.id() == rematch)));
+    let usernames = |server: &TestServer, id| server.state.game(id).unwrap().players().iter().map(|player| player.username().clone()).collect::<Vec<_>>();
+    assert_eq!(usernames(&server, rematch), ["bob", "alice"]);
+    assert_eq!(server.state.game(id).unwrap().check_rematch(Instant::now() + REMATCH_WINDOW + Duration::from_secs(1)), Err(RejectReason::RematchClosed));
+
+    // Nobody gets into a rematch that already started
+    let mut server = TestServer::new();
+    let (id, [alice, bob]) = server.start_two_player_game(GamePreset::Casual);
+    while !server.game_state(id).game_over() {
+        server.play_turn(id);
+    }
+    let rematch = server.create_game(bob, Request::Rematch{ id });
+    server.accept(bob, Request::AddBot{ id: rematch, difficulty: BotDifficulty::Easy });
+    server.accept(bob, Request::StartGame{ id: rematch });
+    server.reject(alice, Request::Rematch{ id }, RejectReason::RematchClosed);
+
+    // Bots can't ask, so they're in it from the start
+    let dave = server.connect();
+    server.accept(dave, Request::SetUsername{ username: "dave".to_owned() });
+    let id = server.create_game(dave, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    server.accept(dave, Request::JoinGame{ id });
+    server.accept(dave, Request::AddBot{ id, difficulty: BotDifficulty::Easy });
+    server.accept(dave, Request::StartGame{ id });
+    let bot = server.state.game(id).unwrap().players()[1].username().clone();
+    let bot_port = server.game_state(id).board_state().player_port(1).expect("Bot didn't place its token");
+    let port = server.state.game(id).unwrap().game().start_ports().into_iter().find(|port| *port != bot_port).unwrap();
+    server.accept(dave, Request::PlaceToken{ id, player: 0, port });
+    while !server.game_state(id).game_over() {
+        server.play_turn(id);
+    }
+    let rematch = server.create_game(dave, Request::Rematch{ id });
+    assert_eq!(usernames(&server, rematch), [bot, "dave".to_owned()]);
+}
+
+#[test]
+fn test_emote() {
+    let mut server = TestServer::new();
+    let (id, [alice, bob]) = server.start_two_player_game(GamePreset::Casual);
+    let carol = server.connect();
+    server.accept(carol, Request::SetUsername{ username: "carol".to_owned() });
+    server.accept(carol, Request::JoinGame{ id });
+
+    // Everyone in the game sees it, spectators included, but only players can send one
+    server.accept(bob, Request::Emote{ id, emote: Emote::Wow });
+    let emoted = |client: &TestClient| client.received.iter()
+        .filter(|resp| matches!(resp, Response::Emoted{ player: 1, emote: Emote::Wow, .. }))
+        .count();
+    assert!([alice, bob, carol].iter().all(|client| emoted(&server.clients[*client]) == 1));
+    server.reject(carol, Request::Emote{ id, emote: Emote::Laugh }, RejectReason::NotAPlayer);
+
+    // Emotes sent too fast get dropped
+    for _ in 0..EMOTE_BURST * 2 {
+        server.accept(bob, Request::Emote{ id, emote: Emote::Wow });
+    }
+    assert_eq!(emoted(&server.clients[alice]), EMOTE_BURST as usize);
+}
+
+#[test]
+fn test_spectator_delay() {
+    let mut server = TestServer::new();
+    let [alice, bob, carol, dave] = ["alice", "bob", "carol", "dave"].map(|name| {
+        let client = server.connect();
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+        client
+    });
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: false, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: Some(SpectatorDelay::DEFAULT) });
+    for client in [alice, bob] {
+        server.accept(client, Request::JoinGame{ id });
+    }
+    server.accept(alice, Request::StartGame{ id });
+
+    // Spectators don't even get the game right away, but they hear about their own requests getting rejected
+    assert!(server.accept(carol, Request::JoinGame{ id }).is_empty());
+    server.accept(dave, Request::JoinGame{ id });
+    server.accept(bob, Request::Emote{ id, emote: Emote::Wow });
+    assert!(server.clients[alice].received.iter().any(|resp| matches!(resp, Response::Emoted{ .. })));
+    server.reject(carol, Request::Emote{ id, emote: Emote::Laugh }, RejectReason::NotAPlayer);
+
+    // Nothing gets through before the delay is up, and what was held back from spectators who left gets dropped
+    server.accept(dave, Request::JoinLobby);
+    let num_received = server.clients[carol].received.len();
+    server.time_out(Instant::now() + SpectatorDelay::DEFAULT.get() / 2);
+    assert_eq!(server.clients[carol].received.len(), num_received);
+    server.time_out(Instant::now() + SpectatorDelay::DEFAULT.get());
+    match &server.clients[carol].received[num_received..] {
+        [Response::JoinedGame{ .. }, rest @ ..] =>
+            assert!(rest.iter().any(|resp| matches!(resp, Response::Emoted{ player: 1, emote: Emote::Wow, .. }))),
+        responses => panic!("Unexpected responses {:?}", responses),
+    }
+    assert!(!server.clients[dave].received.iter().any(|resp| matches!(resp, Response::JoinedGame{ .. } | Response::Emoted{ .. })));
+}
+
+#[test]
+fn test_open_hands() {
+    let mut server = TestServer::new();
+    let [alice, bob, carol] = ["alice", "bob", "carol"].map(|name| {
+        let client = server.connect();
+        server.accept(client, Request::SetUsername{ username: name.to_owned() });
+        client
+    });
+    let id = server.create_game(alice, Request::CreateGame{ preset: GamePreset::Casual, open_hands: true, hand_exchange: false, scout: false, rejection_feed: RejectionFeed::Off, rounds: MatchRounds::DEFAULT, simultaneous: false, time_control: None, spectator_delay: None });
+    for client in [alice, bob] {
+        server.accept(client, Request::JoinGame{ id });
+    }
+    server.accept(alice, Request::StartGame{ id });
+    server.accept(carol, Request::JoinGame{ id });
+
+    // Everyone sees everyone's tiles face up, but the draw pile stays hidden
+    for client in [alice, bob, carol] {
+        let state = server.clients[client].state();
+        for player in 0..2 {
+            assert!(state.player_state(player).unwrap().tiles_vec().into_iter().flat_map(|(_, tiles)| tiles)
+                .all(|tile| tile.visible() && tile != tile.redacted()));
+        }
+    }
+    let state = server.state.game(id).unwrap().visible_state(Looker::Player(1)).unwrap();
+    assert!(state.num_tiles_left_by_kind().iter()
+        .all(|(kind, _)| state.top_tile_left_of_kind(kind).into_iter().all(|tile| tile == tile.redacted())));
+}
@@ -0,0 +1,37 @@
+//! TLS for connections, so the game can be played from HTTPS pages over `wss://` without a proxy in front.
+//! The certificate chain and private key come from PEM files named in the config.
+
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use async_tls::TlsAcceptor;
+use rustls::{Certificate, NoClientAuth, PrivateKey, ServerConfig, internal::pemfile};
+
+fn open(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path).map(BufReader::new).map_err(|err| format!("Can't read {:?}: {}", path, err))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<Certificate>, String> {
+    let certs = pemfile::certs(&mut open(path)?).map_err(|_| format!("Invalid certificates in {:?}", path))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates in {:?}", path));
+    }
+    Ok(certs)
+}
+
+/// Loads the first private key in a file, in PKCS #8 or RSA format
+fn load_key(path: &Path) -> Result<PrivateKey, String> {
+    let invalid = |_| format!("Invalid private key in {:?}", path);
+    let mut keys = pemfile::pkcs8_private_keys(&mut open(path)?).map_err(invalid)?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut open(path)?).map_err(invalid)?;
+    }
+    keys.into_iter().next().ok_or_else(|| format!("No private key in {:?}", path))
+}
+
+/// Makes an acceptor that does the TLS handshake for incoming connections
+pub fn acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor, String> {
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.set_single_cert(load_certs(cert)?, load_key(key)?)
+        .map_err(|err| format!("Can't use the certificate in {:?}: {}", cert, err))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}